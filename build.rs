@@ -1,6 +1,16 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Set protoc to use bundled version if not found
     std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+
+    // Reported by every node so mixed-version fleets can be spotted
+    let commit = std::process::Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=DISTBUILD_GIT_COMMIT={}", commit);
     
     tonic_build::configure()
         .build_server(true)
@@ -0,0 +1,133 @@
+use super::{is_valid_hash, Cas, INCOMING_COUNTER};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+
+/// Directory under a CAS view's root mapping actions to their results
+const ACTIONS_DIR: &str = ".actions";
+/// Reverse of `ACTIONS_DIR`: an empty file per action under its result
+const ACTION_OUTPUTS_DIR: &str = ".action-outputs";
+
+/// The action cache: which output blob a compile of a given input (the
+/// source tarball, rustc arguments included) produced, so the same compile
+/// elsewhere can reuse it instead of running again
+impl Cas {
+    /// Output recorded for the action with input `input_hash`, here or in
+    /// the mirror, as long as the output blob is still around
+    pub fn action_result(&self, input_hash: &str) -> Option<String> {
+        self.action_path(input_hash)
+            .and_then(|path| Ok(fs::read_to_string(path)?))
+            .ok()
+            .map(|output| output.trim().to_string())
+            .filter(|output| self.exists(output))
+            .or_else(|| self.mirror()?.action_result(input_hash))
+    }
+
+    /// Record that the action with input `input_hash` produced `output_hash`
+    pub fn record_action(&self, input_hash: &str, output_hash: &str) -> Result<()> {
+        self.check_writable()?;
+        if !is_valid_hash(output_hash) {
+            anyhow::bail!("Invalid output hash: {:?}", output_hash);
+        }
+        let path = self.action_path(input_hash)?;
+
+        // Indexed by result first: an entry there without the action is
+        // skipped by `action_refs`, the other way round it'd be missed
+        let by_output = self.action_outputs_dir(output_hash);
+        fs::create_dir_all(&by_output).with_context(|| format!("Failed to create directory {:?}", by_output))?;
+        fs::write(by_output.join(input_hash), b"").with_context(|| format!("Failed to index action {}", input_hash))?;
+
+        let dir = path.parent().context("Action path has no parent")?;
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create directory {:?}", dir))?;
+
+        let temp = dir.join(format!(
+            ".incoming-{}-{}",
+            std::process::id(),
+            INCOMING_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp, output_hash).with_context(|| format!("Failed to write {:?}", temp))?;
+        fs::rename(&temp, &path).with_context(|| format!("Failed to move {:?} to {:?}", temp, path))
+    }
+
+    /// Action-cache entries using blob `hash`, as (input, output) pairs:
+    /// the action it's the input of, and those it's the recorded result of
+    pub fn action_refs(&self, hash: &str) -> Result<Vec<(String, String)>> {
+        let recorded = |input: &str| fs::read_to_string(self.action_path(input).ok()?).ok().map(|output| output.trim().to_string());
+
+        let mut refs: Vec<(String, String)> = recorded(hash).map(|output| (hash.to_string(), output)).into_iter().collect();
+        let by_output = self.action_outputs_dir(hash);
+        let entries = match fs::read_dir(&by_output) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(refs),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", by_output)),
+        };
+        for entry in entries {
+            let input = entry?.file_name().to_string_lossy().into_owned();
+            // Left behind when the action was recorded again with another result
+            if recorded(&input).as_deref() == Some(hash) {
+                refs.push((input, hash.to_string()));
+            }
+        }
+        refs.sort();
+        Ok(refs)
+    }
+
+    /// Layout: <root>/.action-outputs/<first2>/<output hash>/<input hash>
+    fn action_outputs_dir(&self, output_hash: &str) -> PathBuf {
+        self.root.join(ACTION_OUTPUTS_DIR).join(&output_hash[..2]).join(output_hash)
+    }
+
+    /// Layout: <root>/.actions/<first2>/<input hash>
+    fn action_path(&self, input_hash: &str) -> Result<PathBuf> {
+        if !is_valid_hash(input_hash) {
+            anyhow::bail!("Invalid input hash: {:?}", input_hash);
+        }
+        Ok(self.root.join(ACTIONS_DIR).join(&input_hash[..2]).join(input_hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_action_results() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+        let input = cas.put(b"fn main() {}").unwrap();
+        let output = cas.put(b"compiled").unwrap();
+        assert_eq!(cas.action_result(&input), None);
+
+        cas.record_action(&input, &output).unwrap();
+        assert_eq!(cas.action_result(&input), Some(output.clone()));
+
+        // A result whose blob was collected is a miss
+        cas.remove(&output).unwrap();
+        assert_eq!(cas.action_result(&input), None);
+
+        let read_only = Cas::new(temp_dir.path()).unwrap().with_read_only(true);
+        assert!(read_only.record_action(&input, &input).is_err());
+    }
+
+    #[test]
+    fn test_action_refs() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+        let [debug, release, output, rebuilt] = [b"debug".as_slice(), b"release", b"output", b"rebuilt"].map(|data| cas.put(data).unwrap());
+        assert!(cas.action_refs(&output).unwrap().is_empty());
+
+        cas.record_action(&debug, &output).unwrap();
+        cas.record_action(&release, &output).unwrap();
+        let mut expected = vec![(debug.clone(), output.clone()), (release.clone(), output.clone())];
+        expected.sort();
+        assert_eq!(cas.action_refs(&output).unwrap(), expected);
+        assert_eq!(cas.action_refs(&debug).unwrap(), [(debug.clone(), output.clone())]);
+
+        // Recorded again with another result: only that one uses it now
+        cas.record_action(&debug, &rebuilt).unwrap();
+        assert_eq!(cas.action_refs(&output).unwrap(), [(release.clone(), output.clone())]);
+        assert_eq!(cas.action_refs(&rebuilt).unwrap(), [(debug.clone(), rebuilt.clone())]);
+    }
+}
@@ -0,0 +1,127 @@
+use super::Cas;
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::time::{Duration, SystemTime};
+
+/// Which blobs to include in an exported seed archive
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// Only blobs written within this long ago
+    pub max_age: Option<Duration>,
+    /// Only these hashes (e.g. from a manifest file)
+    pub hashes: Option<HashSet<String>>,
+}
+
+/// Result of importing a seed archive
+#[derive(Debug, Clone, Default)]
+pub struct ImportStats {
+    pub imported: usize,
+    pub already_present: usize,
+    /// Entries whose content didn't match their name
+    pub corrupt: Vec<String>,
+}
+
+impl Cas {
+    /// Write selected blobs into a gzipped tar archive (one entry per hash),
+    /// returning the number of blobs exported
+    pub fn export_archive<W: Write>(&self, writer: W, filter: &ExportFilter) -> Result<usize> {
+        let mut tar = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+        let now = SystemTime::now();
+        let mut exported = 0;
+
+        for hash in self.list_all()? {
+            if let Some(hashes) = &filter.hashes {
+                if !hashes.contains(&hash) {
+                    continue;
+                }
+            }
+
+            if let Some(max_age) = filter.max_age {
+                let modified = self.stored_meta(&hash)?.1;
+                if now.duration_since(modified).unwrap_or(Duration::ZERO) > max_age {
+                    continue;
+                }
+            }
+
+            let data = self.get(&hash)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, &hash, &data[..])
+                .with_context(|| format!("Failed to add {} to archive", hash))?;
+            exported += 1;
+        }
+
+        tar.into_inner()?.finish()?;
+        Ok(exported)
+    }
+
+    /// Load every blob from a seed archive produced by `export_archive`
+    pub fn import_archive<R: Read>(&self, reader: R) -> Result<ImportStats> {
+        let mut archive = tar::Archive::new(GzDecoder::new(reader));
+        let mut stats = ImportStats::default();
+
+        for entry in archive.entries().context("Failed to read archive")? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().to_string();
+
+            if self.exists(&name) {
+                stats.already_present += 1;
+                continue;
+            }
+
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+
+            // Never trust the archive: the name must match the content
+            if self.compute_hash(&data) != name {
+                stats.corrupt.push(name);
+                continue;
+            }
+
+            self.put(&data)?;
+            stats.imported += 1;
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cas_export_import_roundtrip() {
+        let source_dir = TempDir::new().unwrap();
+        let source = Cas::new(source_dir.path()).unwrap();
+        let wanted = source.put(b"common dependency").unwrap();
+        source.put(b"not in manifest").unwrap();
+
+        let filter = ExportFilter {
+            hashes: Some(HashSet::from([wanted.clone()])),
+            ..Default::default()
+        };
+        let mut archive = Vec::new();
+        assert_eq!(source.export_archive(&mut archive, &filter).unwrap(), 1);
+
+        let target_dir = TempDir::new().unwrap();
+        let target = Cas::new(target_dir.path()).unwrap();
+        let stats = target.import_archive(&archive[..]).unwrap();
+
+        assert_eq!(stats.imported, 1);
+        assert!(stats.corrupt.is_empty());
+        assert_eq!(target.get(&wanted).unwrap(), b"common dependency");
+        assert_eq!(target.list_all().unwrap().len(), 1);
+
+        // Importing again is a no-op
+        let stats = target.import_archive(&archive[..]).unwrap();
+        assert_eq!(stats.already_present, 1);
+    }
+}
@@ -0,0 +1,169 @@
+use super::{is_valid_hash, Cas, INCOMING_COUNTER};
+use crate::common::clock;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+
+/// Directory under a CAS view's root holding metadata sidecars
+const META_DIR: &str = ".meta";
+
+/// A blob's last access is only rewritten once it's this stale, so most
+/// reads don't turn into writes
+const ACCESS_RESOLUTION_SECS: i64 = 3600;
+
+/// Where a blob came from and when it was last used, kept next to the blob
+/// when `Cas::with_blob_metadata` is enabled
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlobMeta {
+    pub created_at: i64,
+    pub last_access: i64,
+    /// Logical size in bytes
+    pub size: u64,
+    /// Job whose output (or input) this blob is, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crate_name: Option<String>,
+}
+
+impl Cas {
+    /// Metadata recorded for `hash` here or in the mirror, if any
+    pub fn blob_meta(&self, hash: &str) -> Option<BlobMeta> {
+        if let Some(mirror) = self.mirror_for(hash) {
+            return mirror.blob_meta(hash);
+        }
+        let data = fs::read(self.meta_path(hash).ok()?).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// Note which job and crate blob `hash` belongs to. The first origin
+    /// recorded sticks; later puts of the same content don't overwrite it.
+    pub fn record_origin(&self, hash: &str, job_id: Option<&str>, crate_name: Option<&str>) -> Result<()> {
+        if !self.records_metadata() || !self.is_stored(hash) {
+            return Ok(());
+        }
+
+        let now = clock::now();
+        let mut meta = self.blob_meta(hash).unwrap_or(BlobMeta {
+            created_at: now,
+            last_access: now,
+            size: self.logical_size(hash),
+            ..Default::default()
+        });
+        if meta.job_id.is_none() {
+            meta.job_id = job_id.filter(|id| !id.is_empty()).map(str::to_string);
+        }
+        if meta.crate_name.is_none() {
+            meta.crate_name = crate_name.filter(|name| !name.is_empty()).map(str::to_string);
+        }
+        self.write_meta(hash, &meta)
+    }
+
+    /// Start the record of a blob that was just stored
+    pub(crate) fn meta_created(&self, hash: &str, size: u64) {
+        if !self.records_metadata() {
+            return;
+        }
+        let now = clock::now();
+        let meta = BlobMeta {
+            created_at: now,
+            last_access: now,
+            size,
+            ..Default::default()
+        };
+        if let Err(e) = self.write_meta(hash, &meta) {
+            log::warn!("⚠️  Failed to record metadata for {}: {}", hash, e);
+        }
+    }
+
+    /// Bump the last access time of a blob that was just read
+    pub(crate) fn meta_accessed(&self, hash: &str) {
+        if !self.records_metadata() || self.mirror_for(hash).is_some() {
+            return;
+        }
+        let Some(mut meta) = self.blob_meta(hash) else {
+            return;
+        };
+        let now = clock::now();
+        if now - meta.last_access < ACCESS_RESOLUTION_SECS {
+            return;
+        }
+        meta.last_access = now;
+        let _ = self.write_meta(hash, &meta);
+    }
+
+    /// Drop the record of a removed blob
+    pub(crate) fn meta_removed(&self, hash: &str) {
+        if let Ok(path) = self.meta_path(hash) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    fn records_metadata(&self) -> bool {
+        self.blob_metadata && !self.read_only
+    }
+
+    fn logical_size(&self, hash: &str) -> u64 {
+        self.read_range(hash, 0, 0).map(|(_, size)| size).unwrap_or(0)
+    }
+
+    /// Layout: <root>/.meta/<first2>/<hash>.json
+    fn meta_path(&self, hash: &str) -> Result<PathBuf> {
+        if !is_valid_hash(hash) {
+            anyhow::bail!("Invalid blob hash: {:?}", hash);
+        }
+        Ok(self.root.join(META_DIR).join(&hash[..2]).join(format!("{}.json", hash)))
+    }
+
+    /// Write via a temp file so concurrent readers never see half a record
+    fn write_meta(&self, hash: &str, meta: &BlobMeta) -> Result<()> {
+        let path = self.meta_path(hash)?;
+        let dir = path.parent().context("Metadata path has no parent")?;
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create directory {:?}", dir))?;
+
+        let temp = dir.join(format!(
+            ".incoming-{}-{}",
+            std::process::id(),
+            INCOMING_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp, serde_json::to_vec(meta)?).with_context(|| format!("Failed to write {:?}", temp))?;
+        fs::rename(&temp, &path).with_context(|| format!("Failed to move {:?} to {:?}", temp, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_blob_metadata_lifecycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap().with_blob_metadata(true);
+
+        let hash = cas.put(b"libfoo.rlib").unwrap();
+        let meta = cas.blob_meta(&hash).unwrap();
+        assert_eq!(meta.size, 11);
+        assert_eq!(meta.created_at, meta.last_access);
+        assert_eq!(meta.job_id, None);
+
+        cas.record_origin(&hash, Some("job-1"), Some("foo")).unwrap();
+        cas.record_origin(&hash, Some("job-2"), Some("bar")).unwrap();
+        let meta = cas.blob_meta(&hash).unwrap();
+        assert_eq!(meta.job_id.as_deref(), Some("job-1"));
+        assert_eq!(meta.crate_name.as_deref(), Some("foo"));
+
+        // Sidecars aren't blobs, and go away with theirs
+        assert_eq!(cas.list_all().unwrap(), vec![hash.clone()]);
+        cas.remove(&hash).unwrap();
+        assert!(cas.blob_meta(&hash).is_none());
+
+        // Off by default
+        let plain = Cas::new(temp_dir.path().join("plain")).unwrap();
+        let hash = plain.put(b"data").unwrap();
+        plain.record_origin(&hash, Some("job-3"), None).unwrap();
+        assert!(plain.blob_meta(&hash).is_none());
+    }
+}
@@ -0,0 +1,204 @@
+/// First line of a chunk manifest, followed by the blob's hash and length.
+/// Including the blob's own hash means no real blob can be mistaken for a
+/// manifest (its content would have to contain its own SHA-256).
+pub const MANIFEST_MAGIC: &[u8] = b"distbuild-chunks-v1 ";
+
+/// Content-defined chunk sizes for large blobs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkParams {
+    pub min_size: usize,
+    pub avg_size: usize,
+    pub max_size: usize,
+    /// Blobs smaller than this are stored whole
+    pub min_blob_size: usize,
+}
+
+impl ChunkParams {
+    /// Chunks between a quarter and four times `avg_size`; only blobs of at
+    /// least `min_blob_size` (and always bigger than a chunk) are split
+    pub fn new(avg_size: usize, min_blob_size: usize) -> Self {
+        let avg_size = avg_size.next_power_of_two().max(256);
+        ChunkParams {
+            min_size: avg_size / 4,
+            avg_size,
+            max_size: avg_size * 4,
+            min_blob_size: min_blob_size.max(avg_size * 4 + 1),
+        }
+    }
+}
+
+/// Gear hash table: 256 pseudo-random words (splitmix64 with a fixed seed,
+/// so chunk boundaries are stable across builds and machines)
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6469_7374_6275_696c; // "distbuil"
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Split `data` FastCDC-style, returning the end offset of each chunk.
+/// Boundaries depend only on nearby content, so an edit early in a blob
+/// leaves the chunks after it unchanged.
+pub fn chunk_boundaries(data: &[u8], params: &ChunkParams) -> Vec<usize> {
+    let mut boundaries = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        start += first_chunk_len(&data[start..], params);
+        boundaries.push(start);
+    }
+    boundaries
+}
+
+/// Length of the first chunk `data` splits into. Only the first `max_size`
+/// bytes are looked at, so a stream can be cut as soon as it has that many
+/// buffered, giving the same chunks as splitting the whole blob.
+pub fn first_chunk_len(data: &[u8], params: &ChunkParams) -> usize {
+    let bits = params.avg_size.trailing_zeros();
+    // Normalized chunking: a stricter mask before the average size and a
+    // looser one after pulls chunk sizes towards the average. The gear hash
+    // shifts left, so its top bits depend on the most bytes.
+    let strict_mask = !0u64 << (64 - (bits + 1));
+    let loose_mask = !0u64 << (64 - (bits - 1));
+
+    if data.len() <= params.min_size {
+        return data.len();
+    }
+
+    let end = data.len().min(params.max_size);
+    let normal = data.len().min(params.avg_size);
+    let mut hash = 0u64;
+    for i in params.min_size..end {
+        hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+        let mask = if i < normal { strict_mask } else { loose_mask };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// What a chunked blob is stored as: its length and chunk hashes in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub len: u64,
+    pub chunks: Vec<String>,
+}
+
+impl Manifest {
+    pub fn encode(&self, hash: &str) -> Vec<u8> {
+        let mut out = MANIFEST_MAGIC.to_vec();
+        out.extend_from_slice(format!("{} {}\n", hash, self.len).as_bytes());
+        for chunk in &self.chunks {
+            out.extend_from_slice(chunk.as_bytes());
+            out.push(b'\n');
+        }
+        out
+    }
+
+    /// Parse `data` if it is the manifest of blob `hash`
+    pub fn decode(hash: &str, data: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(data.strip_prefix(MANIFEST_MAGIC)?).ok()?;
+        let mut lines = text.lines();
+        let (manifest_hash, len) = lines.next()?.split_once(' ')?;
+        if manifest_hash != hash {
+            return None;
+        }
+
+        let chunks: Vec<String> = lines.map(str::to_string).collect();
+        let valid = chunks
+            .iter()
+            .all(|chunk| chunk.len() == 64 && chunk.chars().all(|c| c.is_ascii_hexdigit()));
+        valid.then_some(Manifest {
+            len: len.parse().ok()?,
+            chunks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random bytes
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunk_sizes_within_bounds() {
+        let params = ChunkParams::new(4096, 0);
+        let data = noise(1 << 20, 1);
+        let boundaries = chunk_boundaries(&data, &params);
+
+        assert_eq!(*boundaries.last().unwrap(), data.len());
+        let mut start = 0;
+        for (i, &end) in boundaries.iter().enumerate() {
+            let size = end - start;
+            assert!(size <= params.max_size);
+            assert!(size >= params.min_size || i == boundaries.len() - 1);
+            start = end;
+        }
+
+        // Roughly the requested average
+        let avg = data.len() / boundaries.len();
+        assert!(avg > params.avg_size / 2 && avg < params.avg_size * 2, "avg {}", avg);
+    }
+
+    #[test]
+    fn test_insert_only_changes_nearby_chunks() {
+        let params = ChunkParams::new(4096, 0);
+        let original = noise(256 * 1024, 7);
+        let mut edited = original.clone();
+        edited.splice(1000..1000, b"a few inserted bytes".iter().copied());
+
+        let chunks = |data: &[u8]| -> Vec<Vec<u8>> {
+            let mut start = 0;
+            chunk_boundaries(data, &params)
+                .into_iter()
+                .map(|end| {
+                    let chunk = data[start..end].to_vec();
+                    start = end;
+                    chunk
+                })
+                .collect()
+        };
+        let before = chunks(&original);
+        let after = chunks(&edited);
+
+        let shared = after.iter().filter(|chunk| before.contains(chunk)).count();
+        assert!(shared >= before.len() - 2, "{} of {} chunks shared", shared, before.len());
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let hash = "ab".repeat(32);
+        let manifest = Manifest {
+            len: 12345,
+            chunks: vec!["cd".repeat(32), "ef".repeat(32)],
+        };
+        let encoded = manifest.encode(&hash);
+
+        assert_eq!(Manifest::decode(&hash, &encoded), Some(manifest));
+        // Only valid for the blob it describes
+        assert_eq!(Manifest::decode(&"00".repeat(32), &encoded), None);
+        assert_eq!(Manifest::decode(&hash, b"plain data"), None);
+    }
+}
@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs;
+use std::path::Path;
+
+/// AES-256-GCM encryption of blob contents at rest
+///
+/// Stored layout: `<12-byte nonce><ciphertext><16-byte tag>`. The blob hash
+/// (computed over the plaintext) is bound as associated data, so a ciphertext
+/// can't be moved to a different hash undetected.
+pub struct BlobCipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl BlobCipher {
+    /// Bytes added to every blob on disk (nonce + tag)
+    pub const OVERHEAD: usize = NONCE_LEN + 16;
+
+    /// Create a cipher from a raw 32-byte key
+    pub fn new(key: &[u8]) -> Result<Self> {
+        let key = UnboundKey::new(&AES_256_GCM, key)
+            .map_err(|_| anyhow::anyhow!("CAS encryption key must be 32 bytes"))?;
+        Ok(BlobCipher {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Load a key file containing 64 hex characters (32 bytes)
+    pub fn from_key_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read CAS key file {:?}", path.as_ref()))?;
+        let key = hex::decode(content.trim())
+            .with_context(|| format!("CAS key file {:?} is not valid hex", path.as_ref()))?;
+        Self::new(&key)
+    }
+
+    pub fn encrypt(&self, hash: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("Failed to generate nonce"))?;
+
+        let mut in_out = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(hash.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt blob {}", hash))?;
+
+        let mut stored = Vec::with_capacity(NONCE_LEN + in_out.len());
+        stored.extend_from_slice(&nonce);
+        stored.extend_from_slice(&in_out);
+        Ok(stored)
+    }
+
+    pub fn decrypt(&self, hash: &str, stored: &[u8]) -> Result<Vec<u8>> {
+        if stored.len() < Self::OVERHEAD {
+            anyhow::bail!("Encrypted blob {} is truncated", hash);
+        }
+
+        let (nonce, ciphertext) = stored.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| anyhow::anyhow!("Invalid nonce in blob {}", hash))?;
+
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(hash.as_bytes()), &mut in_out)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt blob {} (wrong key or corrupt)", hash))?;
+        Ok(plaintext.to_vec())
+    }
+}
+
+impl std::fmt::Debug for BlobCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BlobCipher(AES-256-GCM)")
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Bounded in-memory LRU of small stored blobs (plaintext, manifests
+/// included), so repeatedly read metadata doesn't go back to disk
+#[derive(Debug)]
+pub struct HotCache {
+    max_bytes: u64,
+    max_blob_bytes: u64,
+    inner: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    blobs: HashMap<String, (Arc<[u8]>, u64)>, // key -> (data, last use)
+    by_use: BTreeMap<u64, String>,            // last use -> key, oldest first
+    bytes: u64,
+    clock: u64,
+}
+
+impl HotCache {
+    /// Hold up to `max_bytes` in total, of blobs no larger than `max_blob_bytes`
+    pub fn new(max_bytes: u64, max_blob_bytes: u64) -> Self {
+        HotCache {
+            max_bytes,
+            max_blob_bytes: max_blob_bytes.min(max_bytes),
+            inner: Mutex::default(),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let mut entries = self.inner.lock().unwrap();
+        entries.clock += 1;
+        let now = entries.clock;
+
+        let (data, last_use) = entries.blobs.get_mut(key)?;
+        let previous = std::mem::replace(last_use, now);
+        let data = data.to_vec();
+        entries.by_use.remove(&previous);
+        entries.by_use.insert(now, key.to_string());
+        Some(data)
+    }
+
+    /// Cache `data` if it's small enough, evicting least recently used blobs
+    pub fn insert(&self, key: &str, data: &[u8]) {
+        let len = data.len() as u64;
+        if len > self.max_blob_bytes {
+            return;
+        }
+
+        let mut entries = self.inner.lock().unwrap();
+        entries.remove(key);
+        while entries.bytes + len > self.max_bytes {
+            let Some((_, oldest)) = entries.by_use.pop_first() else {
+                break;
+            };
+            entries.remove(&oldest);
+        }
+
+        entries.clock += 1;
+        let now = entries.clock;
+        entries.blobs.insert(key.to_string(), (Arc::from(data), now));
+        entries.by_use.insert(now, key.to_string());
+        entries.bytes += len;
+    }
+
+    pub fn remove(&self, key: &str) {
+        self.inner.lock().unwrap().remove(key);
+    }
+}
+
+impl Entries {
+    fn remove(&mut self, key: &str) {
+        if let Some((data, last_use)) = self.blobs.remove(key) {
+            self.by_use.remove(&last_use);
+            self.bytes -= data.len() as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = HotCache::new(10, 4);
+        cache.insert("a", b"aaaa");
+        cache.insert("b", b"bbbb");
+        assert!(cache.get("a").is_some());
+
+        // Needs room: "b" is the least recently used
+        cache.insert("c", b"cccc");
+        assert_eq!(cache.get("a").as_deref(), Some(&b"aaaa"[..]));
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("c").as_deref(), Some(&b"cccc"[..]));
+
+        // Too big to cache at all
+        cache.insert("d", b"ddddd");
+        assert!(cache.get("d").is_none());
+
+        cache.remove("a");
+        assert!(cache.get("a").is_none());
+    }
+}
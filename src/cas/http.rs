@@ -0,0 +1,475 @@
+use super::{is_valid_hash, Cas};
+use crate::common::auth::Authenticator;
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::io::Write;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Max size of a request line plus headers
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+
+/// Serve blobs from `cas` over HTTP on `addr` (read-only, for CDNs/caches):
+/// `GET|HEAD /blobs/<hash>` and `/namespaces/<name>/blobs/<hash>`.
+/// Blobs are immutable, so the hash is a strong ETag and responses are
+/// cacheable forever; single byte ranges are supported. `GET /metrics`
+/// exports the CAS's traffic counters for Prometheus. With auth on, every
+/// request needs `Authorization: Bearer <token>` (see `Authenticator`).
+pub async fn serve(cas: Arc<Cas>, addr: &str, auth: Authenticator) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind blob HTTP server to {}", addr))?;
+    info!("🌐 Serving CAS blobs over HTTP on {}", addr);
+    serve_listener(cas, listener, auth).await
+}
+
+async fn serve_listener(cas: Arc<Cas>, listener: TcpListener, auth: Authenticator) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let cas = cas.clone();
+        let auth = auth.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(&cas, &auth, stream).await {
+                warn!("⚠️  Blob HTTP request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    range: Option<String>,
+    if_none_match: Option<String>,
+    authorization: Option<String>,
+}
+
+async fn handle_connection(cas: &Cas, auth: &Authenticator, stream: TcpStream) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    let request = match read_request(&mut stream).await? {
+        Some(request) => request,
+        None => return respond(&mut stream, "400 Bad Request", &[], b"bad request\n").await,
+    };
+
+    if request.method != "GET" && request.method != "HEAD" {
+        return respond(&mut stream, "405 Method Not Allowed", &[("Allow", "GET, HEAD")], b"").await;
+    }
+    let head_only = request.method == "HEAD";
+
+    if !auth.accepts(request.authorization.as_deref()) {
+        return respond(&mut stream, "401 Unauthorized", &[("WWW-Authenticate", "Bearer")], b"missing or invalid auth token\n").await;
+    }
+
+    if request.path == "/metrics" {
+        let body = cas.metrics().to_prometheus();
+        let content_length = body.len().to_string();
+        let headers = [
+            ("Content-Type", "text/plain; version=0.0.4"),
+            ("Content-Length", content_length.as_str()),
+            ("Cache-Control", "no-store"),
+        ];
+        write_head(&mut stream, "200 OK", &headers).await?;
+        if !head_only {
+            stream.get_mut().write_all(body.as_bytes()).await?;
+        }
+        stream.get_mut().shutdown().await?;
+        return Ok(());
+    }
+
+    let Some((namespace, hash)) = parse_blob_path(&request.path) else {
+        return respond(&mut stream, "404 Not Found", &[], b"not found\n").await;
+    };
+    // Only existing namespaces: a read-only endpoint must not create them
+    let cas = match namespace {
+        Some(name) if cas.list_namespaces()?.iter().any(|n| n == name) => cas.namespace(name)?,
+        Some(_) => return respond(&mut stream, "404 Not Found", &[], b"not found\n").await,
+        None => cas.clone(),
+    };
+    if !cas.exists(hash) {
+        return respond(&mut stream, "404 Not Found", &[], b"not found\n").await;
+    }
+
+    let etag = format!("\"{}\"", hash);
+    if request
+        .if_none_match
+        .as_deref()
+        .is_some_and(|tags| tags.trim() == "*" || tags.split(',').any(|tag| tag.trim() == etag))
+    {
+        return respond(&mut stream, "304 Not Modified", &[("ETag", &etag)], b"").await;
+    }
+
+    let (mut body, size) = open_blob(&cas, hash).await?;
+
+    let (status, start, len, content_range) = match request.range.as_deref().map(|r| parse_range(r, size)) {
+        None | Some(RangeSpec::Ignore) => ("200 OK", 0, size, None),
+        Some(RangeSpec::Unsatisfiable) => {
+            let content_range = format!("bytes */{}", size);
+            return respond(
+                &mut stream,
+                "416 Range Not Satisfiable",
+                &[("Content-Range", &content_range)],
+                b"",
+            )
+            .await;
+        }
+        Some(RangeSpec::Bytes(start, end)) => (
+            "206 Partial Content",
+            start,
+            end - start + 1,
+            Some(format!("bytes {}-{}/{}", start, end, size)),
+        ),
+    };
+
+    let content_length = len.to_string();
+    let mut headers = vec![
+        ("Content-Type", "application/octet-stream"),
+        ("Content-Length", content_length.as_str()),
+        ("ETag", etag.as_str()),
+        ("Accept-Ranges", "bytes"),
+        ("Cache-Control", "public, max-age=31536000, immutable"),
+    ];
+    if let Some(content_range) = &content_range {
+        headers.push(("Content-Range", content_range));
+    }
+
+    write_head(&mut stream, status, &headers).await?;
+    if !head_only {
+        body.seek_to(start).await?;
+        tokio::io::copy(&mut body.reader().take(len), stream.get_mut()).await?;
+    }
+    stream.get_mut().shutdown().await?;
+
+    Ok(())
+}
+
+/// Read the request line and the headers we care about
+async fn read_request(stream: &mut BufReader<TcpStream>) -> Result<Option<HttpRequest>> {
+    let mut line = String::new();
+    let mut total = stream.read_line(&mut line).await?;
+
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    let mut request = HttpRequest {
+        method: method.to_string(),
+        path: path.split('?').next().unwrap_or(path).to_string(),
+        range: None,
+        if_none_match: None,
+        authorization: None,
+    };
+
+    loop {
+        let mut header = String::new();
+        let read = stream.read_line(&mut header).await?;
+        total += read;
+        if read == 0 || total > MAX_HEADER_BYTES {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "range" => request.range = Some(value.trim().to_string()),
+                "if-none-match" => request.if_none_match = Some(value.trim().to_string()),
+                "authorization" => request.authorization = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(Some(request))
+}
+
+/// Split `/blobs/<hash>` or `/namespaces/<name>/blobs/<hash>`
+fn parse_blob_path(path: &str) -> Option<(Option<&str>, &str)> {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let (namespace, hash) = match segments.as_slice() {
+        ["blobs", hash] => (None, *hash),
+        ["namespaces", name, "blobs", hash] => (Some(*name), *hash),
+        _ => return None,
+    };
+
+    is_valid_hash(hash).then_some((namespace, hash))
+}
+
+#[derive(Debug, PartialEq)]
+enum RangeSpec {
+    /// Inclusive byte range
+    Bytes(u64, u64),
+    Unsatisfiable,
+    /// Malformed or multi-range: serve the whole blob
+    Ignore,
+}
+
+fn parse_range(header: &str, size: u64) -> RangeSpec {
+    let Some(spec) = header.strip_prefix("bytes=") else {
+        return RangeSpec::Ignore;
+    };
+    if spec.contains(',') {
+        return RangeSpec::Ignore;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeSpec::Ignore;
+    };
+
+    let (start, end) = match (start.parse::<u64>().ok(), end.parse::<u64>().ok()) {
+        // bytes=-N: the last N bytes
+        (None, Some(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return RangeSpec::Unsatisfiable;
+            }
+            (size.saturating_sub(suffix), size.saturating_sub(1))
+        }
+        (Some(start), None) if end.is_empty() => (start, size.saturating_sub(1)),
+        (Some(start), Some(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+        _ => return RangeSpec::Ignore,
+    };
+
+    if size == 0 || start >= size {
+        return RangeSpec::Unsatisfiable;
+    }
+    RangeSpec::Bytes(start, end)
+}
+
+/// Blob contents: straight from disk, or decrypted into memory when the
+/// CAS is encrypted at rest
+enum BlobBody {
+    File(tokio::fs::File),
+    Memory(std::io::Cursor<Vec<u8>>),
+}
+
+impl BlobBody {
+    async fn seek_to(&mut self, offset: u64) -> Result<()> {
+        match self {
+            BlobBody::File(file) => {
+                file.seek(std::io::SeekFrom::Start(offset)).await?;
+            }
+            BlobBody::Memory(cursor) => cursor.set_position(offset),
+        }
+        Ok(())
+    }
+
+    fn reader(&mut self) -> &mut (dyn AsyncRead + Unpin + Send) {
+        match self {
+            BlobBody::File(file) => file,
+            BlobBody::Memory(cursor) => cursor,
+        }
+    }
+}
+
+async fn open_blob(cas: &Cas, hash: &str) -> Result<(BlobBody, u64)> {
+    // Anything not stored as a plain file of its own is assembled in memory
+    let path = cas.get_path(hash)?;
+    if cas.is_encrypted() || cas.is_chunked(hash) || !path.exists() {
+        let cas = cas.clone();
+        let hash = hash.to_string();
+        let data = tokio::task::spawn_blocking(move || cas.get(&hash)).await??;
+        let size = data.len() as u64;
+        return Ok((BlobBody::Memory(std::io::Cursor::new(data)), size));
+    }
+
+    let file = tokio::fs::File::open(&path)
+        .await
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    let size = file.metadata().await?.len();
+    Ok((BlobBody::File(file), size))
+}
+
+/// Fetch blob `hash` into `cas` from the blob HTTP endpoint at `base_url`,
+/// resuming whatever an interrupted earlier download left behind with a
+/// range request. The result is checked against `hash` before it's
+/// stored. Returns false if the server doesn't have it.
+pub async fn fetch(
+    client: &reqwest::Client,
+    base_url: &str,
+    token: Option<&str>,
+    cas: &Cas,
+    hash: &str,
+) -> Result<bool> {
+    let base_url = base_url.trim_end_matches('/');
+    let url = match cas.namespace_name() {
+        Some(namespace) => format!("{}/namespaces/{}/blobs/{}", base_url, namespace, hash),
+        None => format!("{}/blobs/{}", base_url, hash),
+    };
+
+    let path = cas.download_path(hash)?;
+    let mut file = std::fs::File::options()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    let offset = file.metadata()?.len();
+
+    let mut request = client.get(&url);
+    if offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+    }
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let mut response = request.send().await.with_context(|| format!("Failed to fetch {}", url))?;
+    match response.status() {
+        reqwest::StatusCode::NOT_FOUND => return Ok(false),
+        reqwest::StatusCode::PARTIAL_CONTENT => {}
+        // The range was ignored: start over
+        reqwest::StatusCode::OK => file.set_len(0)?,
+        // The earlier attempt got all of it
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE => {}
+        status => anyhow::bail!("{} answered {}", url, status),
+    }
+
+    while let Some(data) = response.chunk().await.with_context(|| format!("Failed to fetch {}", url))? {
+        file.write_all(&data)
+            .with_context(|| format!("Failed to write to {:?}", path))?;
+    }
+    drop(file);
+    cas.finish_download(hash)?;
+    Ok(true)
+}
+
+async fn write_head(stream: &mut BufReader<TcpStream>, status: &str, headers: &[(&str, &str)]) -> Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.get_mut().write_all(head.as_bytes()).await?;
+    Ok(())
+}
+
+async fn respond(
+    stream: &mut BufReader<TcpStream>,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<()> {
+    let content_length = body.len().to_string();
+    let mut all_headers = headers.to_vec();
+    all_headers.push(("Content-Length", &content_length));
+
+    write_head(stream, status, &all_headers).await?;
+    stream.get_mut().write_all(body).await?;
+    stream.get_mut().shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::SchedulerConfig;
+    use tempfile::TempDir;
+
+    async fn request(addr: std::net::SocketAddr, raw: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(raw.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-3", 10), RangeSpec::Bytes(0, 3));
+        assert_eq!(parse_range("bytes=4-", 10), RangeSpec::Bytes(4, 9));
+        assert_eq!(parse_range("bytes=-3", 10), RangeSpec::Bytes(7, 9));
+        assert_eq!(parse_range("bytes=5-100", 10), RangeSpec::Bytes(5, 9));
+        assert_eq!(parse_range("bytes=10-", 10), RangeSpec::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), RangeSpec::Ignore);
+        assert_eq!(parse_range("items=0-1", 10), RangeSpec::Ignore);
+    }
+
+    #[tokio::test]
+    async fn test_serve_blobs() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Arc::new(Cas::new(temp_dir.path()).unwrap());
+        let hash = cas.put(b"0123456789").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_listener(cas, listener, Authenticator::from_config(&Default::default())));
+
+        let full = request(addr, &format!("GET /blobs/{} HTTP/1.1\r\n\r\n", hash)).await;
+        assert!(full.starts_with("HTTP/1.1 200 OK"));
+        assert!(full.contains(&format!("ETag: \"{}\"", hash)));
+        assert!(full.ends_with("\r\n\r\n0123456789"));
+
+        let partial = request(addr, &format!("GET /blobs/{} HTTP/1.1\r\nRange: bytes=2-4\r\n\r\n", hash)).await;
+        assert!(partial.starts_with("HTTP/1.1 206 Partial Content"));
+        assert!(partial.contains("Content-Range: bytes 2-4/10"));
+        assert!(partial.ends_with("\r\n\r\n234"));
+
+        let cached = request(addr, &format!("GET /blobs/{} HTTP/1.1\r\nIf-None-Match: \"{}\"\r\n\r\n", hash, hash)).await;
+        assert!(cached.starts_with("HTTP/1.1 304 Not Modified"));
+
+        let missing = request(addr, &format!("GET /blobs/{} HTTP/1.1\r\n\r\n", "0".repeat(64))).await;
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found"));
+
+        let metrics = request(addr, "GET /metrics HTTP/1.1\r\n\r\n").await;
+        assert!(metrics.starts_with("HTTP/1.1 200 OK"));
+        assert!(metrics.contains("distbuild_cas_puts_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_serve_with_auth() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Arc::new(Cas::new(temp_dir.path()).unwrap());
+        let hash = cas.namespace("project-a").unwrap().put(b"secret").unwrap();
+
+        let config = SchedulerConfig {
+            auth_token: Some("shared-secret".to_string()),
+            ..Default::default()
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_listener(cas, listener, Authenticator::from_config(&config)));
+
+        let path = format!("/namespaces/project-a/blobs/{}", hash);
+        let anonymous = request(addr, &format!("GET {} HTTP/1.1\r\n\r\n", path)).await;
+        assert!(anonymous.starts_with("HTTP/1.1 401 Unauthorized"));
+        let bad = request(addr, &format!("GET {} HTTP/1.1\r\nAuthorization: Bearer guess\r\n\r\n", path)).await;
+        assert!(bad.starts_with("HTTP/1.1 401 Unauthorized"));
+        let metrics = request(addr, "GET /metrics HTTP/1.1\r\n\r\n").await;
+        assert!(metrics.starts_with("HTTP/1.1 401 Unauthorized"));
+
+        let authed = request(addr, &format!("GET {} HTTP/1.1\r\nAuthorization: Bearer shared-secret\r\n\r\n", path)).await;
+        assert!(authed.starts_with("HTTP/1.1 200 OK"));
+        assert!(authed.ends_with("\r\n\r\nsecret"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let temp_dir = TempDir::new().unwrap();
+        let served = Arc::new(Cas::new(temp_dir.path().join("served")).unwrap());
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let hash = served.put(&data).unwrap();
+        let other = served.namespace("project-a").unwrap().put(b"namespaced").unwrap();
+
+        let config = SchedulerConfig {
+            auth_token: Some("shared-secret".to_string()),
+            ..Default::default()
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(serve_listener(served, listener, Authenticator::from_config(&config)));
+
+        let cas = Cas::new(temp_dir.path().join("local")).unwrap();
+        let client = reqwest::Client::new();
+        assert!(fetch(&client, &base_url, None, &cas, &hash).await.is_err());
+        assert!(!fetch(&client, &base_url, Some("shared-secret"), &cas, &"0".repeat(64)).await.unwrap());
+
+        // An interrupted download is resumed where it stopped
+        std::fs::write(cas.download_path(&hash).unwrap(), &data[..40_000]).unwrap();
+        assert!(fetch(&client, &base_url, Some("shared-secret"), &cas, &hash).await.unwrap());
+        assert_eq!(cas.get(&hash).unwrap(), data);
+
+        let namespace = cas.namespace("project-a").unwrap();
+        assert!(fetch(&client, &base_url, Some("shared-secret"), &namespace, &other).await.unwrap());
+        assert_eq!(namespace.get(&other).unwrap(), b"namespaced");
+    }
+}
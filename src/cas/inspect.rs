@@ -0,0 +1,141 @@
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use std::io::{Cursor, Read};
+
+/// Bytes of a binary blob shown as a hex preview
+pub const HEX_PREVIEW_BYTES: usize = 256;
+
+/// A blob decoded into whatever form reads best on a terminal
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlobView {
+    /// Tar archive (possibly gzipped): entries as (path, size)
+    Tar { gzipped: bool, entries: Vec<(String, u64)> },
+    /// Pretty-printed JSON document
+    Json(String),
+    Text(String),
+    /// Hex dump of the first bytes
+    Binary { size: usize, preview: String },
+}
+
+impl BlobView {
+    /// Short name of the detected kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BlobView::Tar { gzipped: true, .. } => "tar.gz",
+            BlobView::Tar { gzipped: false, .. } => "tar",
+            BlobView::Json(_) => "json",
+            BlobView::Text(_) => "text",
+            BlobView::Binary { .. } => "binary",
+        }
+    }
+}
+
+/// Detect what a blob holds: tarball, JSON manifest, text or raw bytes
+pub fn inspect(data: &[u8]) -> BlobView {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        if let Ok(entries) = tar_entries(GzDecoder::new(data)) {
+            return BlobView::Tar { gzipped: true, entries };
+        }
+    }
+
+    // POSIX and GNU tar headers carry "ustar" at offset 257
+    if data.get(257..262) == Some(b"ustar") {
+        if let Ok(entries) = tar_entries(Cursor::new(data)) {
+            return BlobView::Tar { gzipped: false, entries };
+        }
+    }
+
+    if let Ok(text) = std::str::from_utf8(data) {
+        let trimmed = text.trim_start();
+        if trimmed.starts_with('{') || trimmed.starts_with('[') {
+            if let Ok(value) = serde_json::from_str::<serde_json::Value>(text) {
+                if let Ok(pretty) = serde_json::to_string_pretty(&value) {
+                    return BlobView::Json(pretty);
+                }
+            }
+        }
+        if !text.contains('\0') {
+            return BlobView::Text(text.to_string());
+        }
+    }
+
+    BlobView::Binary {
+        size: data.len(),
+        preview: hex_dump(&data[..data.len().min(HEX_PREVIEW_BYTES)]),
+    }
+}
+
+fn tar_entries<R: Read>(reader: R) -> Result<Vec<(String, u64)>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        entries.push((entry.path()?.display().to_string(), entry.size()));
+    }
+    Ok(entries)
+}
+
+/// `xxd`-style lines of 16 bytes: offset, hex, printable ASCII
+fn hex_dump(data: &[u8]) -> String {
+    data.chunks(16)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            format!("{:08x}  {:<47}  {}", line * 16, hex.join(" "), ascii)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn tarball() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(13);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "src/lib.rs", &b"pub fn a() {}"[..]).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_inspect_detects_kinds() {
+        let tar = tarball();
+        assert_eq!(
+            inspect(&tar),
+            BlobView::Tar {
+                gzipped: false,
+                entries: vec![("src/lib.rs".to_string(), 13)]
+            }
+        );
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        std::io::Write::write_all(&mut gz, &tar).unwrap();
+        assert_eq!(inspect(&gz.finish().unwrap()).kind(), "tar.gz");
+
+        match inspect(br#"{"outputs":["a.rlib"]}"#) {
+            BlobView::Json(pretty) => assert!(pretty.contains("\n  \"outputs\"")),
+            other => panic!("expected JSON, got {:?}", other),
+        }
+
+        assert_eq!(inspect(b"fn main() {}"), BlobView::Text("fn main() {}".to_string()));
+        assert_eq!(inspect(b"{ not json").kind(), "text");
+
+        match inspect(&[0u8, 159, 146, 150]) {
+            BlobView::Binary { size, preview } => {
+                assert_eq!(size, 4);
+                assert!(preview.starts_with("00000000  00 9f 92 96"));
+            }
+            other => panic!("expected binary, got {:?}", other),
+        }
+    }
+}
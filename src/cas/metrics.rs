@@ -0,0 +1,173 @@
+use super::Cas;
+use crate::proto::distbuild::GetCasMetricsResponse;
+use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Running totals of CAS traffic in this process, shared by every view
+/// (clone or namespace) of the same CAS
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    puts: AtomicU64,
+    deduplicated_puts: AtomicU64,
+    bytes_in: AtomicU64,
+    gets: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl Counters {
+    /// A put of `bytes`; `stored` is false when the blob was already there
+    pub(crate) fn record_put(&self, bytes: u64, stored: bool) {
+        self.puts.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(bytes, Ordering::Relaxed);
+        if !stored {
+            self.deduplicated_puts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A get that found the blob (sending `bytes`), or missed
+    pub(crate) fn record_get(&self, bytes: Option<u64>) {
+        self.gets.fetch_add(1, Ordering::Relaxed);
+        match bytes {
+            Some(bytes) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                self.bytes_out.fetch_add(bytes, Ordering::Relaxed);
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Snapshot of a CAS's traffic since the process started
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CasMetrics {
+    pub puts: u64,
+    /// Puts of blobs that were already stored
+    pub deduplicated_puts: u64,
+    pub bytes_in: u64,
+    pub gets: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub bytes_out: u64,
+}
+
+impl CasMetrics {
+    /// Fraction of gets that found their blob (1.0 before any gets)
+    pub fn hit_rate(&self) -> f64 {
+        if self.gets == 0 {
+            return 1.0;
+        }
+        self.hits as f64 / self.gets as f64
+    }
+
+    /// Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let counters = [
+            ("puts", "Blobs put", self.puts),
+            ("deduplicated_puts", "Puts of blobs already stored", self.deduplicated_puts),
+            ("bytes_in", "Bytes put", self.bytes_in),
+            ("gets", "Blob reads", self.gets),
+            ("hits", "Blob reads that found the blob", self.hits),
+            ("misses", "Blob reads that didn't find the blob", self.misses),
+            ("bytes_out", "Bytes read", self.bytes_out),
+        ];
+
+        let mut text = String::new();
+        for (name, help, value) in counters {
+            text.push_str(&format!(
+                "# HELP distbuild_cas_{name}_total {help}\n# TYPE distbuild_cas_{name}_total counter\ndistbuild_cas_{name}_total {value}\n"
+            ));
+        }
+        text
+    }
+}
+
+impl From<CasMetrics> for GetCasMetricsResponse {
+    fn from(m: CasMetrics) -> Self {
+        GetCasMetricsResponse {
+            puts: m.puts,
+            deduplicated_puts: m.deduplicated_puts,
+            bytes_in: m.bytes_in,
+            gets: m.gets,
+            hits: m.hits,
+            misses: m.misses,
+            bytes_out: m.bytes_out,
+        }
+    }
+}
+
+impl From<GetCasMetricsResponse> for CasMetrics {
+    fn from(m: GetCasMetricsResponse) -> Self {
+        CasMetrics {
+            puts: m.puts,
+            deduplicated_puts: m.deduplicated_puts,
+            bytes_in: m.bytes_in,
+            gets: m.gets,
+            hits: m.hits,
+            misses: m.misses,
+            bytes_out: m.bytes_out,
+        }
+    }
+}
+
+impl Cas {
+    /// Puts, gets and bytes moved through this CAS (all namespaces) so far
+    pub fn metrics(&self) -> CasMetrics {
+        let c = &self.counters;
+        CasMetrics {
+            puts: c.puts.load(Ordering::Relaxed),
+            deduplicated_puts: c.deduplicated_puts.load(Ordering::Relaxed),
+            bytes_in: c.bytes_in.load(Ordering::Relaxed),
+            gets: c.gets.load(Ordering::Relaxed),
+            hits: c.hits.load(Ordering::Relaxed),
+            misses: c.misses.load(Ordering::Relaxed),
+            bytes_out: c.bytes_out.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counts the bytes of a streaming get as they're read
+pub(crate) struct CountingReader<R> {
+    pub(crate) inner: R,
+    pub(crate) counters: Arc<Counters>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.counters.bytes_out.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cas_metrics() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+        let hash = cas.put(b"hello").unwrap();
+        cas.namespace("ci").unwrap().put(b"hello").unwrap();
+        cas.put(b"hello").unwrap();
+
+        cas.get(&hash).unwrap();
+        let mut streamed = Vec::new();
+        cas.get_reader(&hash).unwrap().read_to_end(&mut streamed).unwrap();
+        assert!(cas.get(&"0".repeat(64)).is_err());
+
+        let metrics = cas.metrics();
+        assert_eq!(metrics.puts, 3);
+        assert_eq!(metrics.deduplicated_puts, 1);
+        assert_eq!(metrics.bytes_in, 15);
+        assert_eq!((metrics.gets, metrics.hits, metrics.misses), (3, 2, 1));
+        assert_eq!(metrics.bytes_out, 10);
+        assert!(metrics.to_prometheus().contains("distbuild_cas_hits_total 2\n"));
+    }
+}
@@ -1,14 +1,79 @@
+use crate::common::config::CasConfig;
+use crate::common::DistbuildError;
 use anyhow::{Context, Result};
 use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+pub mod action_cache;
+pub mod archive;
+pub mod blob_meta;
+pub mod chunking;
+pub mod crypto;
+pub mod hot_cache;
+pub mod http;
+pub mod inspect;
+pub mod metrics;
+pub mod pack;
+pub mod remote;
+pub mod replication;
+pub mod retention;
+pub mod service;
+pub mod shards;
+pub mod stats;
+pub mod transfer;
+pub mod verify;
+
+use chunking::{chunk_boundaries, first_chunk_len, ChunkParams, Manifest, MANIFEST_MAGIC};
+use crypto::BlobCipher;
+use hot_cache::HotCache;
+use metrics::{Counters, CountingReader};
+use pack::PackIndex;
+use shards::Shard;
+use transfer::PARTIAL_EXPIRY;
+
+/// Directory under the CAS root that holds per-namespace stores
+const NAMESPACES_DIR: &str = "namespaces";
+
+/// Stored files larger than this are never read looking for a chunk manifest
+const MAX_MANIFEST_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Plain blobs at least this big are memory-mapped for streaming reads;
+/// smaller ones are cheaper to read through a buffer
+const MMAP_MIN_BYTES: u64 = 1024 * 1024;
+
+/// Average chunk size a streaming put into an encrypted, non-chunking store
+/// is cut into, so no more than a few chunks' worth of plaintext is held
+const STREAM_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Distinguishes concurrent streaming puts' temp files within a process
+static INCOMING_COUNTER: AtomicU64 = AtomicU64::new(0);
 
 /// Content-Addressable Storage (CAS)
 /// Layout: <cas_root>/<first2>/<next2>/<full_sha256>
+/// Namespaced: <cas_root>/namespaces/<name>/<first2>/<next2>/<full_sha256>
 #[derive(Debug, Clone)]
 pub struct Cas {
     root: PathBuf,
+    base: PathBuf,
+    namespace: Option<String>,
+    cipher: Option<Arc<BlobCipher>>,
+    index: Option<Arc<RwLock<HashSet<String>>>>, // known blobs, shared by all namespaces
+    quotas: Arc<HashMap<String, u64>>,           // max stored bytes per namespace
+    usage: Arc<Mutex<HashMap<String, u64>>>,     // stored bytes per quota'd namespace
+    chunking: Option<ChunkParams>,
+    read_only: bool,
+    mirror: Option<Arc<Cas>>, // read-only fallback for blobs not stored here
+    packs: Arc<Mutex<PackIndex>>, // packfiles under this view's root
+    hot_cache: Option<Arc<HotCache>>, // shared by all namespaces, keyed like the index
+    counters: Arc<Counters>,          // traffic, shared by all namespaces
+    blob_metadata: bool,              // keep a BlobMeta sidecar per blob
+    shards: Arc<Vec<Shard>>,          // other roots for loose blobs, by hash prefix
 }
 
 impl Cas {
@@ -17,13 +82,459 @@ impl Cas {
         let root = root.as_ref().to_path_buf();
         fs::create_dir_all(&root)
             .with_context(|| format!("Failed to create CAS root at {:?}", root))?;
-        Ok(Cas { root })
+        Ok(Cas {
+            base: root.clone(),
+            root,
+            namespace: None,
+            cipher: None,
+            index: None,
+            quotas: Arc::new(HashMap::new()),
+            usage: Arc::new(Mutex::new(HashMap::new())),
+            chunking: None,
+            read_only: false,
+            mirror: None,
+            packs: Arc::default(),
+            hot_cache: None,
+            counters: Arc::default(),
+            blob_metadata: false,
+            shards: Arc::default(),
+        })
+    }
+
+    /// Create a CAS instance with the options from the `[cas]` config section
+    pub fn from_config(config: &CasConfig) -> Result<Self> {
+        let mut cas = Self::new(&config.root)?;
+
+        if let Some(key_file) = &config.encryption_key_file {
+            cas.cipher = Some(Arc::new(BlobCipher::from_key_file(key_file)?));
+        }
+        let shards = config.shards.iter()
+            .map(|shard| Shard::new(&shard.prefix_range, &shard.path))
+            .collect::<Result<Vec<_>>>()?;
+        cas = cas.with_quotas(config.quotas.clone()).with_shards(shards)?;
+        if config.chunking.enabled {
+            cas = cas.with_chunking(ChunkParams::new(
+                config.chunking.avg_chunk_kib * 1024,
+                config.chunking.min_blob_kib * 1024,
+            ));
+        }
+
+        if config.hot_cache.max_mib > 0 {
+            cas = cas.with_hot_cache(
+                config.hot_cache.max_mib * 1024 * 1024,
+                config.hot_cache.max_blob_kib * 1024,
+            );
+        }
+
+        cas = cas
+            .with_blob_metadata(config.blob_metadata)
+            .with_read_only(config.read_only);
+        if let Some(mirror) = &config.mirror {
+            if !Path::new(&mirror.root).is_dir() {
+                anyhow::bail!("CAS mirror not found at {}", mirror.root);
+            }
+            let mut golden = Self::new(&mirror.root)?;
+            if let Some(key_file) = &mirror.encryption_key_file {
+                golden.cipher = Some(Arc::new(BlobCipher::from_key_file(key_file)?));
+            }
+            cas = cas.with_mirror(golden);
+        }
+
+        match &config.namespace {
+            Some(name) => cas.namespace(name),
+            None => Ok(cas),
+        }
+    }
+
+    /// Like `from_config`, plus the in-memory existence index (with periodic
+    /// reconciliation) when enabled; for long-running scheduler/worker processes
+    pub fn for_service(config: &CasConfig) -> Result<Self> {
+        let cas = Self::from_config(config)?;
+        if config.packing.enabled && !config.read_only {
+            cas.spawn_compactor(
+                Duration::from_secs(config.packing.interval_secs.max(1)),
+                config.packing.max_blob_kib * 1024,
+                Duration::from_secs(config.packing.min_age_secs),
+            );
+        }
+        if !config.index {
+            return Ok(cas);
+        }
+
+        let cas = cas.with_index()?;
+        cas.spawn_index_reconciler(Duration::from_secs(config.index_reconcile_secs.max(1)));
+        Ok(cas)
+    }
+
+    /// Keep an in-memory set of known blobs (in every namespace) so `exists`
+    /// doesn't hit the filesystem for blobs already seen. Populated from disk
+    /// now; misses still check disk, since other processes share the CAS.
+    pub fn with_index(mut self) -> Result<Self> {
+        let keys = self.scan_index_keys()?;
+        self.index = Some(Arc::new(RwLock::new(keys)));
+        Ok(self)
+    }
+
+    /// Rebuild the index from disk, dropping blobs removed by other
+    /// processes. Returns the number of indexed blobs.
+    pub fn reconcile_index(&self) -> Result<usize> {
+        let Some(index) = &self.index else {
+            return Ok(0);
+        };
+
+        let keys = self.scan_index_keys()?;
+        let count = keys.len();
+        if let Ok(mut index) = index.write() {
+            *index = keys;
+        }
+        Ok(count)
+    }
+
+    /// This CAS outside any namespace
+    fn base_view(&self) -> Cas {
+        let mut base = self.clone();
+        base.root = self.base.clone();
+        base.namespace = None;
+        base.packs = Arc::default();
+        base
+    }
+
+    fn scan_index_keys(&self) -> Result<HashSet<String>> {
+        let base = self.base_view();
+
+        let mut keys: HashSet<String> = base.list_all()?.into_iter().collect();
+        for name in self.list_namespaces()? {
+            let view = base.namespace(&name)?;
+            keys.extend(view.list_all()?.iter().map(|hash| view.index_key(hash)));
+        }
+        Ok(keys)
+    }
+
+    /// Index entries are `<namespace>/<hash>`, or just the hash by default
+    fn index_key(&self, hash: &str) -> String {
+        match &self.namespace {
+            Some(name) => format!("{}/{}", name, hash),
+            None => hash.to_string(),
+        }
+    }
+
+    /// Reconcile the index in a background thread every `every`
+    pub fn spawn_index_reconciler(&self, every: Duration) {
+        let cas = self.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(every);
+            if let Err(e) = cas.reconcile_index() {
+                log::warn!("⚠️  CAS index reconciliation failed: {}", e);
+            }
+        });
+    }
+
+    /// Compact the default space and every namespace in a background
+    /// thread every `every` (see `compact`)
+    pub fn spawn_compactor(&self, every: Duration, max_blob_size: u64, min_age: Duration) {
+        let base = self.base_view();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(every);
+            let views = base.list_namespaces().map(|names| {
+                std::iter::once(Ok(base.clone()))
+                    .chain(names.iter().map(|name| base.namespace(name)))
+                    .collect::<Vec<_>>()
+            });
+            for view in views.into_iter().flatten() {
+                match view.and_then(|view| view.compact(max_blob_size, min_age)) {
+                    Ok(stats) if stats.packed > 0 => {
+                        log::info!("📦 Packed {} small blobs into {} pack(s)", stats.packed, stats.packs_written);
+                    }
+                    Ok(_) => {}
+                    Err(e) => log::warn!("⚠️  CAS compaction failed: {}", e),
+                }
+            }
+        });
+    }
+
+    fn index_insert(&self, hash: &str) {
+        if let Some(Ok(mut index)) = self.index.as_ref().map(|index| index.write()) {
+            index.insert(self.index_key(hash));
+        }
+    }
+
+    /// Update the index and quota usage after a blob is deleted
+    pub(crate) fn blob_removed(&self, hash: &str) {
+        if let Some(Ok(mut index)) = self.index.as_ref().map(|index| index.write()) {
+            index.remove(&self.index_key(hash));
+        }
+        if let Some(cache) = &self.hot_cache {
+            cache.remove(&self.index_key(hash));
+        }
+        self.meta_removed(hash);
+        // Recounted from disk on the next put
+        if let Some(name) = &self.namespace {
+            self.usage.lock().unwrap().remove(name);
+        }
+    }
+
+    /// Limit the bytes each listed namespace may store; puts that would go
+    /// over fail with `DistbuildError::QuotaExceeded`
+    pub fn with_quotas(mut self, quotas: HashMap<String, u64>) -> Self {
+        self.quotas = Arc::new(quotas);
+        self
+    }
+
+    /// Reserve `bytes` against this namespace's quota. Usage is counted from
+    /// disk on first use, then tracked in-process.
+    fn charge_quota(&self, bytes: u64) -> Result<()> {
+        let Some(name) = &self.namespace else {
+            return Ok(());
+        };
+        let Some(&limit) = self.quotas.get(name) else {
+            return Ok(());
+        };
+
+        let mut usage = self.usage.lock().unwrap();
+        let used = match usage.get(name) {
+            Some(&used) => used,
+            None => self.stored_bytes()?,
+        };
+        if used + bytes > limit {
+            usage.insert(name.clone(), used);
+            return Err(DistbuildError::QuotaExceeded {
+                namespace: name.clone(),
+                used,
+                limit,
+                needed: bytes,
+            }
+            .into());
+        }
+        usage.insert(name.clone(), used + bytes);
+        Ok(())
+    }
+
+    /// Bytes occupied on disk by this view's blobs
+    fn stored_bytes(&self) -> Result<u64> {
+        let mut total = 0;
+        for hash in self.list_all()? {
+            total += self.stored_meta(&hash)?.0;
+        }
+        Ok(total)
+    }
+
+    /// Get a view of the same CAS scoped to namespace `name`; blobs, GC and
+    /// stats of one namespace don't see another's
+    pub fn namespace(&self, name: &str) -> Result<Self> {
+        validate_namespace(name)?;
+
+        let root = self.base.join(NAMESPACES_DIR).join(name);
+        if !self.read_only {
+            fs::create_dir_all(&root)
+                .with_context(|| format!("Failed to create CAS namespace at {:?}", root))?;
+        }
+        let mirror = match &self.mirror {
+            Some(mirror) => Some(Arc::new(mirror.namespace(name)?)),
+            None => None,
+        };
+
+        Ok(Cas {
+            root,
+            base: self.base.clone(),
+            namespace: Some(name.to_string()),
+            cipher: self.cipher.clone(),
+            index: self.index.clone(),
+            quotas: self.quotas.clone(),
+            usage: self.usage.clone(),
+            chunking: self.chunking,
+            read_only: self.read_only,
+            mirror,
+            packs: Arc::default(),
+            hot_cache: self.hot_cache.clone(),
+            counters: self.counters.clone(),
+            blob_metadata: self.blob_metadata,
+            shards: self.shards.clone(),
+        })
+    }
+
+    /// Namespace this view is scoped to (`None` for the shared default space)
+    pub fn namespace_name(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// List all namespaces that exist under the CAS root
+    pub fn list_namespaces(&self) -> Result<Vec<String>> {
+        let dir = self.base.join(NAMESPACES_DIR);
+        let mut names = Vec::new();
+
+        if !dir.exists() {
+            return Ok(names);
+        }
+
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.path().is_dir() {
+                if let Some(name) = entry.file_name().to_str() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        names.sort();
+        Ok(names)
+    }
+
+    /// Encrypt blob contents at rest with the given 32-byte AES-256-GCM key
+    pub fn with_encryption_key(mut self, key: &[u8]) -> Result<Self> {
+        self.cipher = Some(Arc::new(BlobCipher::new(key)?));
+        Ok(self)
+    }
+
+    /// Store large blobs as content-defined chunks plus a manifest, so
+    /// similar blobs (e.g. successive builds of an rlib) share most storage
+    pub fn with_chunking(mut self, params: ChunkParams) -> Self {
+        self.chunking = Some(params);
+        self
+    }
+
+    /// Keep recently read blobs up to `max_blob_bytes` in memory, at most
+    /// `max_bytes` of them, so hot metadata and manifests skip the disk
+    pub fn with_hot_cache(mut self, max_bytes: u64, max_blob_bytes: u64) -> Self {
+        self.hot_cache = Some(Arc::new(HotCache::new(max_bytes, max_blob_bytes)));
+        self
+    }
+
+    /// Record when each blob was stored and last read, and which job it
+    /// came from, in a sidecar under `.meta` (see `blob_meta`)
+    pub fn with_blob_metadata(mut self, enabled: bool) -> Self {
+        self.blob_metadata = enabled;
+        self
+    }
+
+    /// Refuse puts, removes and GC
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Whether writes are refused
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Read blobs missing here from `mirror` (made read-only), so this CAS
+    /// acts as a writable overlay over it. Listing, stats and GC only cover
+    /// the overlay.
+    pub fn with_mirror(mut self, mirror: Cas) -> Self {
+        self.mirror = Some(Arc::new(mirror.with_read_only(true)));
+        self
+    }
+
+    /// The read-only CAS behind this overlay, if any
+    pub fn mirror(&self) -> Option<&Cas> {
+        self.mirror.as_deref()
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(DistbuildError::ReadOnly(self.root.display().to_string()).into());
+        }
+        Ok(())
+    }
+
+    /// The mirror, if `hash` isn't stored here but may be there
+    fn mirror_for(&self, hash: &str) -> Option<&Cas> {
+        self.mirror.as_deref().filter(|_| !self.is_stored(hash))
+    }
+
+    /// Whether `hash` is stored here, as its own file or in a pack
+    fn is_stored(&self, hash: &str) -> bool {
+        self.hash_to_path(hash).is_ok_and(|path| path.exists()) || self.packed_entry(hash).is_some()
+    }
+
+    /// Stored size and modification time of `hash`, loose or packed
+    fn stored_meta(&self, hash: &str) -> Result<(u64, SystemTime)> {
+        if let Ok(metadata) = fs::metadata(self.hash_to_path(hash)?) {
+            return Ok((metadata.len(), metadata.modified()?));
+        }
+        match self.packed_entry(hash) {
+            Some(entry) => Ok((entry.len, entry.modified)),
+            None => anyhow::bail!("Hash {} not found in CAS", hash),
+        }
     }
 
     /// Put bytes into CAS and return the hash
     pub fn put(&self, data: &[u8]) -> Result<String> {
+        self.check_writable()?;
         let hash = self.compute_hash(data);
-        let path = self.hash_to_path(&hash);
+        if self.mirror_for(&hash).is_some_and(|mirror| mirror.exists(&hash)) {
+            self.counters.record_put(data.len() as u64, false);
+            return Ok(hash);
+        }
+
+        let stored = match self.chunking {
+            Some(params) if data.len() >= params.min_blob_size => {
+                self.put_chunked(&hash, data, &params)?
+            }
+            _ => self.write_blob(&hash, data)?,
+        };
+
+        self.counters.record_put(data.len() as u64, stored);
+        self.index_insert(&hash);
+        if stored {
+            self.meta_created(&hash, data.len() as u64);
+        }
+        Ok(hash)
+    }
+
+    /// Put everything `reader` yields and return the hash, without holding
+    /// the whole blob in memory (see `writer`)
+    pub fn put_stream<R: Read>(&self, mut reader: R) -> Result<String> {
+        let mut writer = self.writer()?;
+        std::io::copy(&mut reader, &mut writer).context("Failed to stream blob into CAS")?;
+        writer.finish()
+    }
+
+    /// Start writing a blob whose hash isn't known yet. Bytes are hashed and
+    /// written to a temp file as they arrive, then moved into place by
+    /// `BlobWriter::finish`. Chunking and encrypted stores instead cut the
+    /// stream into chunks as it arrives and store (and encrypt) each one on
+    /// its own; an encrypted store that doesn't chunk uses
+    /// `STREAM_CHUNK_BYTES` chunks for blobs too big to hold at once.
+    pub fn writer(&self) -> Result<BlobWriter> {
+        self.check_writable()?;
+        let params = match (self.chunking, &self.cipher) {
+            (Some(params), _) => Some(params),
+            (None, Some(_)) => Some(ChunkParams::new(STREAM_CHUNK_BYTES, 0)),
+            (None, None) => None,
+        };
+        let sink = if let Some(params) = params {
+            Sink::Chunked {
+                params,
+                pending: Vec::new(),
+                chunks: Vec::new(),
+                size: 0,
+            }
+        } else {
+            let path = self.root.join(format!(
+                ".incoming-{}-{}",
+                std::process::id(),
+                INCOMING_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let file = fs::File::create(&path)
+                .with_context(|| format!("Failed to create {:?}", path))?;
+            Sink::File {
+                path,
+                file: BufWriter::new(file),
+                size: 0,
+            }
+        };
+
+        Ok(BlobWriter {
+            cas: self.clone(),
+            hasher: Sha256::new(),
+            sink: Some(sink),
+        })
+    }
+
+    /// Store `data` under `hash` as-is; returns false if it was already there
+    fn write_blob(&self, hash: &str, data: &[u8]) -> Result<bool> {
+        let path = self.hash_to_path(hash)?;
         
         // Create parent directories
         if let Some(parent) = path.parent() {
@@ -32,77 +543,383 @@ impl Cas {
         }
 
         // Write the blob (skip if already exists)
+        if self.is_stored(hash) {
+            return Ok(false);
+        }
+
+        let stored = match &self.cipher {
+            Some(cipher) => cipher.encrypt(hash, data)?,
+            None => data.to_vec(),
+        };
+        self.charge_quota(stored.len() as u64)?;
+        let mut file = fs::File::create(&path)
+            .with_context(|| format!("Failed to create file {:?}", path))?;
+        file.write_all(&stored)
+            .with_context(|| format!("Failed to write to {:?}", path))?;
+        Ok(true)
+    }
+
+    fn put_chunked(&self, hash: &str, data: &[u8], params: &ChunkParams) -> Result<bool> {
+        if self.is_stored(hash) {
+            return Ok(false);
+        }
+
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        for end in chunk_boundaries(data, params) {
+            chunks.push(self.put_chunk(&data[start..end])?);
+            start = end;
+        }
+
+        let manifest = Manifest {
+            len: data.len() as u64,
+            chunks,
+        };
+        self.write_blob(hash, &manifest.encode(hash))
+    }
+
+    /// Store one chunk of a blob being chunked and return its hash
+    fn put_chunk(&self, chunk: &[u8]) -> Result<String> {
+        let chunk_hash = Self::hash_bytes(chunk);
+        if !self.write_blob(&chunk_hash, chunk)? {
+            // A reused chunk must not look older to GC than its new manifest
+            self.touch(&chunk_hash)?;
+        }
+        self.index_insert(&chunk_hash);
+        Ok(chunk_hash)
+    }
+
+    /// Store blob `hash` as a manifest over chunks already in this CAS
+    /// (as sent by a replication peer), after checking they add up to `hash`
+    pub fn put_from_chunks(&self, hash: &str, chunks: &[String]) -> Result<()> {
+        self.check_writable()?;
+        let mut hasher = Sha256::new();
+        let mut len = 0;
+        for chunk in chunks {
+            let data = self.assemble(chunk)
+                .with_context(|| format!("Missing chunk {} of {}", chunk, hash))?;
+            hasher.update(&data);
+            len += data.len() as u64;
+        }
+        if hex::encode(hasher.finalize()) != hash {
+            anyhow::bail!("Chunks don't add up to {}", hash);
+        }
+
+        for chunk in chunks {
+            self.touch(chunk)?;
+        }
+        let manifest = Manifest {
+            len,
+            chunks: chunks.to_vec(),
+        };
+        if self.write_blob(hash, &manifest.encode(hash))? {
+            self.meta_created(hash, len);
+        }
+        self.index_insert(hash);
+        Ok(())
+    }
+
+    fn touch(&self, hash: &str) -> Result<()> {
+        let path = self.hash_to_path(hash)?;
+        // A packed blob's age can't be changed in place; give it a fresh
+        // loose copy instead (the next compaction packs it again)
         if !path.exists() {
-            let mut file = fs::File::create(&path)
-                .with_context(|| format!("Failed to create file {:?}", path))?;
-            file.write_all(data)
-                .with_context(|| format!("Failed to write to {:?}", path))?;
+            if let Some(stored) = self.read_packed(hash)? {
+                return fs::write(&path, stored)
+                    .with_context(|| format!("Failed to unpack {:?}", path));
+            }
         }
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+            .with_context(|| format!("Failed to touch {:?}", path))
+    }
 
-        Ok(hash)
+    /// The chunks blob `hash` is stored as, or `None` if it's stored whole
+    pub fn chunk_list(&self, hash: &str) -> Result<Option<Vec<String>>> {
+        if let Some(mirror) = self.mirror_for(hash) {
+            return mirror.chunk_list(hash);
+        }
+        let path = self.hash_to_path(hash)?;
+        let size = self.stored_meta(hash)?.0;
+        if size > MAX_MANIFEST_BYTES {
+            return Ok(None);
+        }
+
+        // Unencrypted blobs can be ruled out from their first bytes
+        if self.cipher.is_none() && path.exists() {
+            let mut prefix = vec![0; MANIFEST_MAGIC.len()];
+            let mut file = fs::File::open(&path)
+                .with_context(|| format!("Failed to open {:?}", path))?;
+            if file.read_exact(&mut prefix).is_err() || prefix != MANIFEST_MAGIC {
+                return Ok(None);
+            }
+        }
+
+        let stored = self.read_stored(hash)?;
+        Ok(Manifest::decode(hash, &stored).map(|manifest| manifest.chunks))
+    }
+
+    /// Whether blob `hash` is stored as chunks rather than a single file
+    pub fn is_chunked(&self, hash: &str) -> bool {
+        matches!(self.chunk_list(hash), Ok(Some(_)))
     }
 
     /// Get bytes from CAS by hash
     pub fn get(&self, hash: &str) -> Result<Vec<u8>> {
-        let path = self.hash_to_path(hash);
+        let data = self.assemble(hash);
+        self.counters.record_get(data.as_ref().ok().map(|data| data.len() as u64));
+        if data.is_ok() {
+            self.meta_accessed(hash);
+        }
+        data
+    }
+
+    /// `get` without counting it in the metrics, for internal reads
+    fn assemble(&self, hash: &str) -> Result<Vec<u8>> {
+        let stored = self.read_stored(hash)?;
+
+        match Manifest::decode(hash, &stored) {
+            Some(manifest) => {
+                let mut data = Vec::with_capacity(manifest.len as usize);
+                for chunk in &manifest.chunks {
+                    data.extend_from_slice(&self.read_stored(chunk)?);
+                }
+                Ok(data)
+            }
+            None => Ok(stored),
+        }
+    }
+
+    /// Read and decrypt the file stored under `hash` (a manifest for chunked blobs)
+    fn read_stored(&self, hash: &str) -> Result<Vec<u8>> {
+        let Some(cache) = &self.hot_cache else {
+            return self.read_stored_uncached(hash);
+        };
+
+        let key = self.index_key(hash);
+        if let Some(data) = cache.get(&key) {
+            return Ok(data);
+        }
+        let data = self.read_stored_uncached(hash)?;
+        cache.insert(&key, &data);
+        Ok(data)
+    }
+
+    fn read_stored_uncached(&self, hash: &str) -> Result<Vec<u8>> {
+        if let Some(mirror) = self.mirror_for(hash) {
+            return mirror.read_stored(hash);
+        }
+
+        let path = self.hash_to_path(hash)?;
         
-        if !path.exists() {
-            anyhow::bail!("Hash {} not found in CAS", hash);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            // Possibly compacted into a pack (even since we last looked)
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => match self.read_packed(hash)? {
+                Some(data) => data,
+                None => anyhow::bail!("Hash {} not found in CAS", hash),
+            },
+            Err(e) => return Err(e).with_context(|| format!("Failed to read from {:?}", path)),
+        };
+
+        match &self.cipher {
+            Some(cipher) => cipher.decrypt(hash, &data),
+            None => Ok(data),
+        }
+    }
+
+    /// Open a blob for streaming reads instead of buffering it whole.
+    /// Large plain blobs are memory-mapped. Encrypted blobs are
+    /// authenticated as a unit, so those are still decrypted into memory
+    /// before reading; chunked blobs are read one chunk at a time.
+    pub fn get_reader(&self, hash: &str) -> Result<Box<dyn Read + Send>> {
+        let reader = self.open_reader(hash);
+        self.counters.record_get(reader.as_ref().ok().map(|_| 0));
+        if reader.is_ok() {
+            self.meta_accessed(hash);
+        }
+        Ok(Box::new(CountingReader {
+            inner: reader?,
+            counters: self.counters.clone(),
+        }))
+    }
+
+    fn open_reader(&self, hash: &str) -> Result<Box<dyn Read + Send>> {
+        if let Some(chunks) = self.chunk_list(hash)? {
+            let cas = self.clone();
+            let readers = chunks.into_iter().map(move |chunk| cas.open_reader(&chunk));
+            return Ok(Box::new(ChunkReader {
+                readers: Box::new(readers),
+                current: None,
+            }));
         }
 
-        let mut file = fs::File::open(&path)
+        if let Some(mirror) = self.mirror_for(hash) {
+            return mirror.open_reader(hash);
+        }
+
+        let path = self.hash_to_path(hash)?;
+        let cached = self.hot_cache.as_ref().and_then(|cache| cache.get(&self.index_key(hash)));
+        if let Some(data) = cached {
+            return Ok(Box::new(Cursor::new(data)));
+        }
+        if self.cipher.is_some() || !path.exists() {
+            return Ok(Box::new(Cursor::new(self.assemble(hash)?)));
+        }
+
+        let file = fs::File::open(&path)
             .with_context(|| format!("Failed to open {:?}", path))?;
-        
-        let mut data = Vec::new();
-        file.read_to_end(&mut data)
-            .with_context(|| format!("Failed to read from {:?}", path))?;
+        if file.metadata()?.len() >= MMAP_MIN_BYTES {
+            // SAFETY: stored blobs are never written in place. They're only
+            // ever replaced by a rename or unlinked, which leaves this
+            // mapping of the old file intact.
+            let map = unsafe { memmap2::Mmap::map(&file) }
+                .with_context(|| format!("Failed to map {:?}", path))?;
+            return Ok(Box::new(Cursor::new(map)));
+        }
+        Ok(Box::new(BufReader::new(file)))
+    }
 
-        Ok(data)
+    /// Fetch several blobs concurrently with at most `parallelism` reads in
+    /// flight; results arrive on the returned channel as they complete
+    pub fn get_many(
+        &self,
+        hashes: &[String],
+        parallelism: usize,
+    ) -> mpsc::Receiver<(String, Result<Vec<u8>>)> {
+        let (tx, rx) = mpsc::channel();
+        let queue = Arc::new(Mutex::new(hashes.to_vec()));
+
+        for _ in 0..parallelism.clamp(1, hashes.len().max(1)) {
+            let cas = self.clone();
+            let queue = queue.clone();
+            let tx = tx.clone();
+
+            std::thread::spawn(move || loop {
+                let next = queue.lock().ok().and_then(|mut queue| queue.pop());
+                let Some(hash) = next else {
+                    break;
+                };
+                let result = cas.get(&hash);
+                if tx.send((hash, result)).is_err() {
+                    break; // receiver gone
+                }
+            });
+        }
+
+        rx
     }
 
     /// Check if a hash exists in CAS
     pub fn exists(&self, hash: &str) -> bool {
-        self.hash_to_path(hash).exists()
+        if let Some(Ok(index)) = self.index.as_ref().map(|index| index.read()) {
+            if index.contains(&self.index_key(hash)) {
+                return true;
+            }
+        }
+
+        let exists = self.is_stored(hash);
+        if exists {
+            self.index_insert(hash);
+        }
+        exists || self.mirror.as_ref().is_some_and(|mirror| mirror.exists(hash))
+    }
+
+    /// Remove a blob, returning whether it existed
+    pub fn remove(&self, hash: &str) -> Result<bool> {
+        self.check_writable()?;
+        let path = self.hash_to_path(hash)?;
+        let loose = path.exists();
+        let packed = self.packed_entry(hash).is_some();
+
+        if !loose && !packed {
+            return Ok(false);
+        }
+
+        if loose {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {:?}", path))?;
+        }
+        if packed {
+            self.drop_packed(&HashSet::from([hash.to_string()]))?;
+        }
+        self.blob_removed(hash);
+        Ok(true)
+    }
+
+    /// Whether blob contents are encrypted at rest
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
     }
 
     /// Get the file path for a hash (without checking existence)
-    pub fn get_path(&self, hash: &str) -> PathBuf {
+    /// Note: with encryption enabled the file holds ciphertext
+    pub fn get_path(&self, hash: &str) -> Result<PathBuf> {
         self.hash_to_path(hash)
     }
 
     /// Compute SHA-256 hash of data
     fn compute_hash(&self, data: &[u8]) -> String {
+        Self::hash_bytes(data)
+    }
+
+    /// The CAS address of `data`
+    pub fn hash_bytes(data: &[u8]) -> String {
         let mut hasher = Sha256::new();
         hasher.update(data);
         hex::encode(hasher.finalize())
     }
 
-    /// Convert hash to filesystem path
-    /// Layout: <root>/<first2>/<next2>/<full_hash>
-    fn hash_to_path(&self, hash: &str) -> PathBuf {
-        if hash.len() < 4 {
-            return self.root.join(hash);
+    /// Convert hash to filesystem path, refusing anything that isn't a
+    /// hash (so it can't name a file outside the CAS)
+    /// Layout: <root>/<first2>/<next2>/<full_hash>, with <root> the shard
+    /// covering <first2> if any
+    fn hash_to_path(&self, hash: &str) -> Result<PathBuf> {
+        if !is_valid_hash(hash) {
+            anyhow::bail!("Invalid blob hash: {:?}", hash);
         }
-        
-        let first2 = &hash[0..2];
-        let next2 = &hash[2..4];
-        
-        self.root.join(first2).join(next2).join(hash)
+        let prefix = u8::from_str_radix(&hash[0..2], 16)?;
+        Ok(self.loose_root(prefix).join(&hash[0..2]).join(&hash[2..4]).join(hash))
     }
 
     /// List all hashes in CAS (for debugging/testing)
     pub fn list_all(&self) -> Result<Vec<String>> {
+        let mut hashes = self.list_loose()?;
+        let loose: HashSet<&String> = hashes.iter().collect();
+        let packed: Vec<String> = self
+            .list_packed()?
+            .into_iter()
+            .filter(|hash| !loose.contains(hash))
+            .collect();
+        hashes.extend(packed);
+        Ok(hashes)
+    }
+
+    /// Hashes stored as their own file
+    fn list_loose(&self) -> Result<Vec<String>> {
         let mut hashes = Vec::new();
-        
-        if !self.root.exists() {
-            return Ok(hashes);
+
+        for root in self.loose_roots() {
+            if root.exists() {
+                self.list_loose_under(&root, &mut hashes)?;
+            }
         }
+        Ok(hashes)
+    }
 
-        for entry in fs::read_dir(&self.root)? {
+    fn list_loose_under(&self, root: &Path, hashes: &mut Vec<String>) -> Result<()> {
+        for entry in fs::read_dir(root)? {
             let entry = entry?;
             let first2_path = entry.path();
             
-            if !first2_path.is_dir() {
+            // Skip non-shard directories (namespaces, packs, quarantine),
+            // and prefixes now routed to another root
+            let Some(prefix) = hash_prefix(&entry.file_name()) else {
+                continue;
+            };
+            if !first2_path.is_dir() || self.loose_root(prefix) != root {
                 continue;
             }
 
@@ -117,7 +934,7 @@ impl Cas {
                 for entry in fs::read_dir(&next2_path)? {
                     let entry = entry?;
                     if entry.path().is_file() {
-                        if let Some(hash) = entry.file_name().to_str() {
+                        if let Some(hash) = entry.file_name().to_str().filter(|hash| is_valid_hash(hash)) {
                             hashes.push(hash.to_string());
                         }
                     }
@@ -125,13 +942,323 @@ impl Cas {
             }
         }
 
-        Ok(hashes)
+        Ok(())
     }
 
     /// Get CAS root directory
     pub fn root(&self) -> &Path {
         &self.root
     }
+
+    /// Delete blobs older than `max_age`, skipping any hash in `pinned`
+    /// (blobs still needed by in-flight jobs)
+    pub fn gc(&self, max_age: Duration, pinned: &HashSet<String>) -> Result<GcStats> {
+        self.gc_with_ages(max_age, &HashMap::new(), pinned)
+    }
+
+    /// Like `gc`, but blobs listed in `ages` (e.g. by retention rules) use
+    /// their own max age instead of `default_max_age`
+    pub fn gc_with_ages(
+        &self,
+        default_max_age: Duration,
+        ages: &HashMap<String, Duration>,
+        pinned: &HashSet<String>,
+    ) -> Result<GcStats> {
+        self.check_writable()?;
+        self.expire_partials(PARTIAL_EXPIRY)?;
+        let mut stats = GcStats::default();
+        let now = SystemTime::now();
+        let mut stale = Vec::new();
+        let mut kept = Vec::new();
+
+        for hash in self.list_all()? {
+            stats.scanned += 1;
+
+            if pinned.contains(&hash) {
+                stats.pinned += 1;
+                kept.push(hash);
+                continue;
+            }
+
+            let (size, modified) = self.stored_meta(&hash)?;
+            let age = now.duration_since(modified).unwrap_or(Duration::ZERO);
+            let max_age = ages.get(&hash).copied().unwrap_or(default_max_age);
+
+            if age >= max_age {
+                stale.push((hash, size));
+            } else {
+                kept.push(hash);
+            }
+        }
+
+        // Chunks of blobs that stay must stay too, however old they are
+        let mut live_chunks = HashSet::new();
+        for hash in &kept {
+            if let Some(chunks) = self.chunk_list(hash)? {
+                live_chunks.extend(chunks);
+            }
+        }
+
+        let mut packed = HashSet::new();
+        for (hash, size) in stale {
+            if live_chunks.contains(&hash) {
+                continue;
+            }
+            let path = self.hash_to_path(&hash)?;
+            if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {:?}", path))?;
+            }
+            if self.packed_entry(&hash).is_some() {
+                packed.insert(hash.clone());
+            }
+            self.blob_removed(&hash);
+            stats.deleted += 1;
+            stats.bytes_freed += size;
+        }
+        if !packed.is_empty() {
+            self.drop_packed(&packed)?;
+        }
+
+        Ok(stats)
+    }
+}
+
+/// A blob being written incrementally; call `finish` to store it. Dropping
+/// an unfinished writer discards what was written.
+pub struct BlobWriter {
+    cas: Cas,
+    hasher: Sha256,
+    sink: Option<Sink>,
+}
+
+enum Sink {
+    File {
+        path: PathBuf,
+        file: BufWriter<fs::File>,
+        size: u64,
+    },
+    /// Chunks are stored once they can't change; `pending` holds the bytes
+    /// not yet cut, at most `max(min_blob_size, max_size)` of them
+    Chunked {
+        params: ChunkParams,
+        pending: Vec<u8>,
+        chunks: Vec<String>,
+        size: u64,
+    },
+}
+
+impl Write for BlobWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = match self.sink.as_mut() {
+            Some(Sink::File { file, size, .. }) => {
+                let n = file.write(buf)?;
+                *size += n as u64;
+                n
+            }
+            Some(Sink::Chunked {
+                params,
+                pending,
+                chunks,
+                size,
+            }) => {
+                pending.extend_from_slice(buf);
+                *size += buf.len() as u64;
+                // Small blobs are stored whole, so nothing is cut until the
+                // blob is known to be chunked
+                if *size >= params.min_blob_size as u64 {
+                    let mut start = 0;
+                    while pending.len() - start >= params.max_size {
+                        let end = start + first_chunk_len(&pending[start..], params);
+                        let hash = self.cas.put_chunk(&pending[start..end])
+                            .map_err(std::io::Error::other)?;
+                        chunks.push(hash);
+                        start = end;
+                    }
+                    pending.drain(..start);
+                }
+                buf.len()
+            }
+            None => return Err(std::io::Error::other("blob writer already finished")),
+        };
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.sink.as_mut() {
+            Some(Sink::File { file, .. }) => file.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl BlobWriter {
+    /// Hash of the bytes written so far
+    pub fn hash(&self) -> String {
+        hex::encode(self.hasher.clone().finalize())
+    }
+
+    /// Number of bytes written so far
+    pub fn size(&self) -> u64 {
+        match &self.sink {
+            Some(Sink::File { size, .. }) => *size,
+            Some(Sink::Chunked { size, .. }) => *size,
+            None => 0,
+        }
+    }
+
+    /// Store the written bytes and return their hash
+    pub fn finish(mut self) -> Result<String> {
+        let (path, mut file, size) = match self.sink.take() {
+            Some(Sink::File { path, file, size }) => (path, file, size),
+            Some(Sink::Chunked {
+                params,
+                pending,
+                chunks,
+                size,
+            }) => return self.finish_chunked(params, pending, chunks, size),
+            None => anyhow::bail!("Blob writer already finished"),
+        };
+
+        let result = (|| {
+            file.flush().with_context(|| format!("Failed to write to {:?}", path))?;
+            drop(file);
+
+            let hash = hex::encode(std::mem::take(&mut self.hasher).finalize());
+            let dest = self.cas.hash_to_path(&hash)?;
+            let in_mirror = self.cas.mirror_for(&hash).is_some_and(|mirror| mirror.exists(&hash));
+            let already_stored = self.cas.is_stored(&hash) || in_mirror;
+            self.cas.counters.record_put(size, !already_stored);
+            if already_stored {
+                fs::remove_file(&path)?;
+            } else {
+                self.cas.charge_quota(size)?;
+                if let Some(parent) = dest.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("Failed to create directory {:?}", parent))?;
+                }
+                shards::move_file(&path, &dest)?;
+                self.cas.meta_created(&hash, size);
+            }
+
+            self.cas.index_insert(&hash);
+            Ok(hash)
+        })();
+
+        if result.is_err() {
+            let _ = fs::remove_file(&path);
+        }
+        result
+    }
+
+    fn finish_chunked(
+        mut self,
+        params: ChunkParams,
+        pending: Vec<u8>,
+        mut chunks: Vec<String>,
+        size: u64,
+    ) -> Result<String> {
+        if size < params.min_blob_size as u64 {
+            return self.cas.put(&pending);
+        }
+
+        let cas = &self.cas;
+        let hash = hex::encode(std::mem::take(&mut self.hasher).finalize());
+        if cas.mirror_for(&hash).is_some_and(|mirror| mirror.exists(&hash)) {
+            cas.counters.record_put(size, false);
+            return Ok(hash);
+        }
+
+        let mut start = 0;
+        for end in chunk_boundaries(&pending, &params) {
+            chunks.push(cas.put_chunk(&pending[start..end])?);
+            start = end;
+        }
+        let manifest = Manifest { len: size, chunks };
+        let stored = cas.write_blob(&hash, &manifest.encode(&hash))?;
+
+        cas.counters.record_put(size, stored);
+        cas.index_insert(&hash);
+        if stored {
+            cas.meta_created(&hash, size);
+        }
+        Ok(hash)
+    }
+}
+
+impl Drop for BlobWriter {
+    fn drop(&mut self) {
+        if let Some(Sink::File { path, .. }) = self.sink.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Concatenation of a chunked blob's chunks, opened lazily
+struct ChunkReader {
+    readers: Box<dyn Iterator<Item = Result<Box<dyn Read + Send>>> + Send>,
+    current: Option<Box<dyn Read + Send>>,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some(reader) = &mut self.current {
+                let n = reader.read(buf)?;
+                if n > 0 || buf.is_empty() {
+                    return Ok(n);
+                }
+            }
+            match self.readers.next() {
+                Some(next) => {
+                    self.current = Some(next.map_err(std::io::Error::other)?);
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Namespace names become directory names, so keep them to a safe charset
+fn validate_namespace(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !name.starts_with('.');
+
+    if !valid {
+        anyhow::bail!(
+            "Invalid CAS namespace {:?} (use letters, digits, '-', '_' or '.')",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Whether `hash` is a blob hash: 64 lowercase hex digits, as SHA-256
+/// hashes are written here. Anything else is refused before it can become
+/// a path.
+pub fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// The byte a top-level `<first2>` hash directory stands for
+fn hash_prefix(name: &std::ffi::OsStr) -> Option<u8> {
+    name.to_str()
+        .filter(|s| s.len() == 2)
+        .and_then(|s| u8::from_str_radix(s, 16).ok())
+}
+
+/// Result of a garbage collection pass
+#[derive(Debug, Clone, Default)]
+pub struct GcStats {
+    pub scanned: usize,
+    pub deleted: usize,
+    pub pinned: usize,
+    pub bytes_freed: u64,
 }
 
 #[cfg(test)]
@@ -164,6 +1291,22 @@ mod tests {
         assert!(cas.get(&fake_hash).is_err());
     }
 
+    #[test]
+    fn test_paths_outside_cas_refused() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("cas");
+        let cas = Cas::new(&root).unwrap();
+        fs::write(temp_dir.path().join("secret"), b"not a blob").unwrap();
+
+        for hash in ["../secret", "ab/../../secret", &"A".repeat(64), ""] {
+            assert!(!cas.exists(hash), "{:?}", hash);
+            assert!(cas.get(hash).is_err(), "{:?}", hash);
+            assert!(cas.read_range(hash, 0, 100).is_err(), "{:?}", hash);
+            assert!(cas.remove(hash).is_err(), "{:?}", hash);
+        }
+        assert!(temp_dir.path().join("secret").exists());
+    }
+
     #[test]
     fn test_cas_deduplication() {
         let temp_dir = TempDir::new().unwrap();
@@ -189,5 +1332,361 @@ mod tests {
         assert!(all_hashes.contains(&hash1));
         assert!(all_hashes.contains(&hash2));
     }
-}
 
+    #[test]
+    fn test_cas_get_reader() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = vec![7u8; 256 * 1024];
+
+        for cas in [
+            Cas::new(temp_dir.path()).unwrap(),
+            Cas::new(temp_dir.path().join("enc")).unwrap().with_encryption_key(&[1u8; 32]).unwrap(),
+        ] {
+            let hash = cas.put(&data).unwrap();
+            let mut streamed = Vec::new();
+            cas.get_reader(&hash).unwrap().read_to_end(&mut streamed).unwrap();
+            assert_eq!(streamed, data);
+            assert!(cas.get_reader(&"0".repeat(64)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_cas_get_many() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+
+        let mut hashes: Vec<String> = (0..10)
+            .map(|i| cas.put(format!("rlib {}", i).as_bytes()).unwrap())
+            .collect();
+        hashes.push("0".repeat(64));
+
+        let results: Vec<_> = cas.get_many(&hashes, 4).into_iter().collect();
+        assert_eq!(results.len(), 11);
+        for (hash, result) in results {
+            match result {
+                Ok(data) => assert_eq!(cas.compute_hash(&data), hash),
+                Err(_) => assert_eq!(hash, "0".repeat(64)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_cas_remove() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+
+        let hash = cas.put(b"remove me").unwrap();
+        assert!(cas.remove(&hash).unwrap());
+        assert!(!cas.exists(&hash));
+        assert!(!cas.remove(&hash).unwrap());
+    }
+
+    #[test]
+    fn test_cas_encryption_at_rest() {
+        let temp_dir = TempDir::new().unwrap();
+        let key = [42u8; 32];
+        let cas = Cas::new(temp_dir.path()).unwrap().with_encryption_key(&key).unwrap();
+
+        let data = b"proprietary source";
+        let hash = cas.put(data).unwrap();
+
+        // Addressing is by plaintext, contents on disk are not
+        assert_eq!(hash, Cas::new(temp_dir.path()).unwrap().compute_hash(data));
+        let on_disk = fs::read(cas.get_path(&hash).unwrap()).unwrap();
+        assert!(!on_disk.windows(data.len()).any(|w| w == data));
+        assert_eq!(cas.get(&hash).unwrap(), data);
+
+        // The wrong key can't read it
+        let other = Cas::new(temp_dir.path()).unwrap().with_encryption_key(&[7u8; 32]).unwrap();
+        assert!(other.get(&hash).is_err());
+    }
+
+    #[test]
+    fn test_cas_namespaces_are_isolated() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+        let project_a = cas.namespace("project-a").unwrap();
+        let project_b = cas.namespace("project-b").unwrap();
+
+        let shared = cas.put(b"default space").unwrap();
+        let hash_a = project_a.put(b"only in a").unwrap();
+
+        assert!(project_a.exists(&hash_a));
+        assert!(!project_b.exists(&hash_a));
+        assert!(!cas.exists(&hash_a));
+        assert_eq!(cas.list_all().unwrap(), vec![shared]);
+        assert_eq!(project_a.list_all().unwrap(), vec![hash_a.clone()]);
+
+        // GC in one namespace leaves the others alone
+        project_b.gc(Duration::ZERO, &HashSet::new()).unwrap();
+        assert!(project_a.exists(&hash_a));
+
+        assert_eq!(cas.list_namespaces().unwrap(), vec!["project-a", "project-b"]);
+        assert!(cas.namespace("../escape").is_err());
+        assert!(cas.namespace("").is_err());
+    }
+
+    #[test]
+    fn test_cas_existence_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let existing = Cas::new(temp_dir.path()).unwrap().put(b"already there").unwrap();
+
+        let cas = Cas::new(temp_dir.path()).unwrap().with_index().unwrap();
+        let other = Cas::new(temp_dir.path()).unwrap();
+        assert!(cas.exists(&existing));
+
+        // Writes by other processes are found on disk and then indexed
+        let external = other.put(b"written elsewhere").unwrap();
+        assert!(cas.exists(&external));
+
+        // Deletes by other processes are picked up on reconciliation
+        other.remove(&existing).unwrap();
+        assert!(cas.exists(&existing));
+        assert_eq!(cas.reconcile_index().unwrap(), 1);
+        assert!(!cas.exists(&existing));
+
+        let own = cas.put(b"mine").unwrap();
+        cas.remove(&own).unwrap();
+        assert!(!cas.exists(&own));
+
+        // Namespaces share the index without seeing each other's blobs
+        let in_ns = cas.namespace("project").unwrap().put(b"namespaced").unwrap();
+        assert!(!cas.exists(&in_ns));
+        assert!(cas.namespace("project").unwrap().exists(&in_ns));
+        assert_eq!(cas.reconcile_index().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_cas_gc_skips_pinned() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+
+        let pinned_hash = cas.put(b"in-flight input").unwrap();
+        let stale_hash = cas.put(b"stale output").unwrap();
+
+        let pinned = HashSet::from([pinned_hash.clone()]);
+        let stats = cas.gc(Duration::ZERO, &pinned).unwrap();
+
+        assert_eq!(stats.scanned, 2);
+        assert_eq!(stats.pinned, 1);
+        assert_eq!(stats.deleted, 1);
+        assert!(cas.exists(&pinned_hash));
+        assert!(!cas.exists(&stale_hash));
+    }
+
+    #[test]
+    fn test_namespace_quota() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path())
+            .unwrap()
+            .with_quotas(HashMap::from([("small".to_string(), 10)]));
+        let small = cas.namespace("small").unwrap();
+
+        let hash = small.put(b"12345678").unwrap();
+        // Re-putting an existing blob costs nothing
+        small.put(b"12345678").unwrap();
+
+        let err = small.put(b"abcdef").unwrap_err();
+        match err.downcast_ref::<DistbuildError>() {
+            Some(DistbuildError::QuotaExceeded { namespace, used, limit, needed }) => {
+                assert_eq!((namespace.as_str(), *used, *limit, *needed), ("small", 8, 10, 6));
+            }
+            other => panic!("expected QuotaExceeded, got {:?}", other),
+        }
+
+        // Other namespaces and the default space are unaffected
+        cas.put(b"abcdef").unwrap();
+        cas.namespace("big").unwrap().put(b"abcdef").unwrap();
+
+        // Deleting frees space
+        small.remove(&hash).unwrap();
+        small.put(b"abcdef").unwrap();
+    }
+
+    #[test]
+    fn test_chunked_blobs_share_chunks() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path())
+            .unwrap()
+            .with_chunking(ChunkParams::new(1024, 16 * 1024));
+
+        let mut state = 42u64;
+        let original: Vec<u8> = (0..64 * 1024)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect();
+        let mut rebuilt = original.clone();
+        rebuilt[30_000] ^= 0xff;
+
+        let hash = cas.put(&original).unwrap();
+        let blobs_after_first = cas.list_all().unwrap().len();
+        let rebuilt_hash = cas.put(&rebuilt).unwrap();
+
+        assert!(cas.is_chunked(&hash));
+        assert_eq!(cas.get(&hash).unwrap(), original);
+        let mut streamed = Vec::new();
+        cas.get_reader(&rebuilt_hash).unwrap().read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, rebuilt);
+
+        // A one-byte change adds a manifest and a couple of chunks, not a copy
+        let added = cas.list_all().unwrap().len() - blobs_after_first;
+        assert!(added <= 4, "{} new blobs", added);
+
+        // Small blobs are still stored whole
+        let small = cas.put(b"small").unwrap();
+        assert!(!cas.is_chunked(&small));
+
+        // GC keeps the chunks of a pinned manifest
+        let pinned = HashSet::from([rebuilt_hash.clone()]);
+        cas.gc(Duration::ZERO, &pinned).unwrap();
+        assert_eq!(cas.get(&rebuilt_hash).unwrap(), rebuilt);
+        assert!(!cas.exists(&hash));
+    }
+
+    #[test]
+    fn test_put_stream() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+        let data = b"streamed into the store".repeat(1000);
+
+        let hash = cas.put_stream(&data[..]).unwrap();
+        assert_eq!(hash, Cas::hash_bytes(&data));
+        assert_eq!(cas.get(&hash).unwrap(), data);
+
+        // Same content again, and an abandoned writer, leave no temp files
+        assert_eq!(cas.put_stream(&data[..]).unwrap(), hash);
+        let mut abandoned = cas.writer().unwrap();
+        abandoned.write_all(b"partial").unwrap();
+        drop(abandoned);
+
+        let leftovers: Vec<_> = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.path().is_file())
+            .collect();
+        assert!(leftovers.is_empty());
+        assert_eq!(cas.list_all().unwrap(), vec![hash]);
+    }
+
+    #[test]
+    fn test_get_reader_large_blob() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+        let data: Vec<u8> = (0..MMAP_MIN_BYTES as usize * 2).map(|i| (i % 251) as u8).collect();
+        let hash = cas.put(&data).unwrap();
+
+        let mut reader = cas.get_reader(&hash).unwrap();
+        // Removing the blob mid-read doesn't pull it from under the reader
+        cas.remove(&hash).unwrap();
+        let mut streamed = Vec::new();
+        reader.read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, data);
+        assert_eq!(cas.metrics().bytes_out, data.len() as u64);
+    }
+
+    #[test]
+    fn test_put_stream_chunked() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunked = Cas::new(temp_dir.path().join("chunked"))
+            .unwrap()
+            .with_chunking(ChunkParams::new(1024, 16 * 1024));
+        let twin = Cas::new(temp_dir.path().join("twin"))
+            .unwrap()
+            .with_chunking(ChunkParams::new(1024, 16 * 1024));
+        let encrypted = Cas::new(temp_dir.path().join("enc"))
+            .unwrap()
+            .with_encryption_key(&[3u8; 32])
+            .unwrap();
+
+        let mut state = 7u64;
+        let data: Vec<u8> = (0..64 * 1024)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect();
+
+        // Written in odd-sized pieces, the stream is cut into the same
+        // chunks as putting the blob whole
+        let mut writer = chunked.writer().unwrap();
+        for piece in data.chunks(999) {
+            writer.write_all(piece).unwrap();
+        }
+        assert_eq!(writer.size(), data.len() as u64);
+        let hash = writer.finish().unwrap();
+        assert_eq!(hash, twin.put(&data).unwrap());
+        assert_eq!(chunked.chunk_list(&hash).unwrap(), twin.chunk_list(&hash).unwrap());
+        assert_eq!(chunked.get(&hash).unwrap(), data);
+
+        // Blobs under the chunking threshold are stored whole
+        let small = chunked.put_stream(&data[..1000]).unwrap();
+        assert!(!chunked.is_chunked(&small));
+        assert_eq!(chunked.get(&small).unwrap(), &data[..1000]);
+
+        // An encrypted store splits big streams and encrypts each chunk
+        let big = data.repeat(80);
+        let hash = encrypted.put_stream(&big[..]).unwrap();
+        assert_eq!(hash, Cas::hash_bytes(&big));
+        let chunks = encrypted.chunk_list(&hash).unwrap().unwrap();
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            let stored = fs::read(encrypted.hash_to_path(chunk).unwrap()).unwrap();
+            assert_ne!(stored, encrypted.get(chunk).unwrap());
+        }
+        assert_eq!(encrypted.get(&hash).unwrap(), big);
+        let small = encrypted.put_stream(&data[..]).unwrap();
+        assert!(!encrypted.is_chunked(&small));
+        assert_eq!(encrypted.get(&small).unwrap(), data);
+    }
+
+    #[test]
+    fn test_read_only_mirror_overlay() {
+        let golden_dir = TempDir::new().unwrap();
+        let overlay_dir = TempDir::new().unwrap();
+        let golden = Cas::new(golden_dir.path()).unwrap();
+        let shared = golden.put(b"std rlib").unwrap();
+        let namespaced = golden.namespace("ci").unwrap().put(b"ci only").unwrap();
+
+        let cas = Cas::new(overlay_dir.path()).unwrap().with_mirror(golden.clone());
+        assert_eq!(cas.get(&shared).unwrap(), b"std rlib");
+        assert!(cas.exists(&shared));
+        assert_eq!(cas.namespace("ci").unwrap().get(&namespaced).unwrap(), b"ci only");
+
+        // Writes land in the overlay only; blobs the mirror has aren't copied
+        let local = cas.put(b"fresh output").unwrap();
+        assert_eq!(cas.put(b"std rlib").unwrap(), shared);
+        assert_eq!(cas.list_all().unwrap(), vec![local.clone()]);
+        assert!(!golden.exists(&local));
+        assert!(!cas.remove(&shared).unwrap());
+        assert!(golden.exists(&shared));
+
+        let read_only = golden.with_read_only(true);
+        let err = read_only.put(b"nope").unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(DistbuildError::ReadOnly(_))));
+        assert!(read_only.remove(&shared).is_err());
+        assert!(read_only.gc(Duration::ZERO, &HashSet::new()).is_err());
+    }
+
+    #[test]
+    fn test_hot_cache_serves_small_blobs() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap().with_hot_cache(1024, 16);
+        let small = cas.put(b"metadata").unwrap();
+        let large = cas.put(&[7u8; 100]).unwrap();
+        assert_eq!(cas.get(&small).unwrap(), b"metadata");
+        cas.get(&large).unwrap();
+
+        // Read again without touching disk; large blobs aren't cached
+        fs::remove_file(cas.get_path(&small).unwrap()).unwrap();
+        fs::remove_file(cas.get_path(&large).unwrap()).unwrap();
+        assert_eq!(cas.get(&small).unwrap(), b"metadata");
+        assert!(cas.get(&large).is_err());
+
+        // Namespaces don't share entries, and removal invalidates
+        assert!(cas.namespace("ci").unwrap().get(&small).is_err());
+        cas.blob_removed(&small);
+        assert!(cas.get(&small).is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use super::Cas;
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Directory under a CAS root (or namespace root) holding packfiles
+pub const PACKS_DIR: &str = "packs";
+
+/// First line of every pack index
+const INDEX_MAGIC: &str = "distbuild-pack-v1";
+
+/// A pack is closed once it holds this many bytes
+const MAX_PACK_BYTES: u64 = 64 * 1024 * 1024;
+
+/// A compaction lock older than this is assumed to be left by a crashed process
+const STALE_LOCK: Duration = Duration::from_secs(60 * 60);
+
+/// Where a packed blob's stored bytes live
+#[derive(Debug, Clone)]
+pub(crate) struct PackEntry {
+    pub pack: PathBuf,
+    pub offset: u64,
+    pub len: u64,
+    /// Modification time of the loose file it was packed from (for GC)
+    pub modified: SystemTime,
+}
+
+/// In-memory view of the `.idx` files in one packs directory
+#[derive(Debug, Default)]
+pub(crate) struct PackIndex {
+    entries: HashMap<String, PackEntry>,
+    loaded: HashSet<OsString>,
+    dir_modified: Option<SystemTime>,
+}
+
+impl PackIndex {
+    /// Pick up packs written or removed (by any process) since the last
+    /// look; cheap when the directory hasn't changed
+    fn refresh(&mut self, dir: &Path) -> Result<()> {
+        let modified = fs::metadata(dir).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified == self.dir_modified {
+            return Ok(());
+        }
+
+        let mut present = HashSet::new();
+        if modified.is_some() {
+            for entry in fs::read_dir(dir)? {
+                let name = entry?.file_name();
+                if Path::new(&name).extension().is_some_and(|ext| ext == "idx") {
+                    present.insert(name);
+                }
+            }
+        }
+
+        // A removed pack invalidates entries pointing into it
+        if !self.loaded.is_subset(&present) {
+            *self = PackIndex::default();
+        }
+        for name in present.difference(&self.loaded.clone()) {
+            let idx = dir.join(name);
+            let pack = idx.with_extension("pack");
+            for (hash, entry) in read_index(&idx, &pack)? {
+                self.entries.insert(hash, entry);
+            }
+            self.loaded.insert(name.clone());
+        }
+        self.dir_modified = modified;
+        Ok(())
+    }
+}
+
+fn read_index(idx: &Path, pack: &Path) -> Result<Vec<(String, PackEntry)>> {
+    let text = fs::read_to_string(idx).with_context(|| format!("Failed to read {:?}", idx))?;
+    let mut lines = text.lines();
+    if lines.next() != Some(INDEX_MAGIC) {
+        anyhow::bail!("{:?} is not a pack index", idx);
+    }
+
+    lines
+        .map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            let [hash, offset, len, modified] = fields[..] else {
+                anyhow::bail!("Malformed line in {:?}: {}", idx, line);
+            };
+            Ok((
+                hash.to_string(),
+                PackEntry {
+                    pack: pack.to_path_buf(),
+                    offset: offset.parse()?,
+                    len: len.parse()?,
+                    modified: UNIX_EPOCH + Duration::from_secs(modified.parse()?),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Result of a compaction pass
+#[derive(Debug, Clone, Default)]
+pub struct CompactStats {
+    /// Loose blobs moved into packs
+    pub packed: usize,
+    pub packs_written: usize,
+    pub bytes_packed: u64,
+}
+
+/// Holds the compaction lock of a packs directory until dropped
+struct CompactLock(PathBuf);
+
+impl CompactLock {
+    fn acquire(dir: &Path) -> Result<Self> {
+        let path = dir.join(".compact.lock");
+        let stale = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| modified.elapsed().unwrap_or_default() > STALE_LOCK);
+        if stale {
+            let _ = fs::remove_file(&path);
+        }
+
+        fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(|| format!("Another compaction is running (lock {:?})", path))?;
+        Ok(CompactLock(path))
+    }
+}
+
+impl Drop for CompactLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// A pack being written, with its entries so far
+struct PackWriter {
+    name: String,
+    file: BufWriter<fs::File>,
+    entries: Vec<(String, u64, u64, SystemTime)>,
+    len: u64,
+}
+
+impl Cas {
+    fn packs_dir(&self) -> PathBuf {
+        self.root.join(PACKS_DIR)
+    }
+
+    /// Where `hash` is packed, if it is
+    pub(crate) fn packed_entry(&self, hash: &str) -> Option<PackEntry> {
+        let mut index = self.packs.lock().ok()?;
+        if let Some(entry) = index.entries.get(hash) {
+            return Some(entry.clone());
+        }
+        index.refresh(&self.packs_dir()).ok()?;
+        index.entries.get(hash).cloned()
+    }
+
+    /// Hashes stored in packs
+    pub(crate) fn list_packed(&self) -> Result<Vec<String>> {
+        let mut index = self.packs.lock().unwrap();
+        index.refresh(&self.packs_dir())?;
+        Ok(index.entries.keys().cloned().collect())
+    }
+
+    /// Whether `hash` is only stored in a pack (not as its own file)
+    pub fn is_packed(&self, hash: &str) -> bool {
+        self.hash_to_path(hash).is_ok_and(|path| !path.exists()) && self.packed_entry(hash).is_some()
+    }
+
+    /// Stored (possibly encrypted) bytes of a packed blob, if it is packed
+    pub(crate) fn read_packed(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        let Some(entry) = self.packed_entry(hash) else {
+            return Ok(None);
+        };
+
+        let read = |entry: &PackEntry| -> std::io::Result<Vec<u8>> {
+            let mut file = fs::File::open(&entry.pack)?;
+            file.seek(SeekFrom::Start(entry.offset))?;
+            let mut data = vec![0; entry.len as usize];
+            file.read_exact(&mut data)?;
+            Ok(data)
+        };
+
+        match read(&entry) {
+            Ok(data) => Ok(Some(data)),
+            // Rewritten by another process since we indexed it
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                *self.packs.lock().unwrap() = PackIndex::default();
+                match self.packed_entry(hash) {
+                    Some(entry) => Ok(Some(read(&entry).with_context(|| {
+                        format!("Failed to read {} from {:?}", hash, entry.pack)
+                    })?)),
+                    None => Ok(None),
+                }
+            }
+            Err(e) => Err(e).with_context(|| format!("Failed to read {} from {:?}", hash, entry.pack)),
+        }
+    }
+
+    /// Move loose blobs of at most `max_blob_size` bytes, untouched for at
+    /// least `min_age`, into packfiles. Reads find them through the pack
+    /// index; only one process compacts a directory at a time.
+    pub fn compact(&self, max_blob_size: u64, min_age: Duration) -> Result<CompactStats> {
+        self.check_writable()?;
+        let dir = self.packs_dir();
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        let _lock = CompactLock::acquire(&dir)?;
+
+        let now = SystemTime::now();
+        let mut candidates = Vec::new();
+        for hash in self.list_loose()? {
+            let metadata = match fs::metadata(self.hash_to_path(&hash)?) {
+                Ok(metadata) => metadata,
+                Err(_) => continue, // removed meanwhile
+            };
+            let modified = metadata.modified()?;
+            let age = now.duration_since(modified).unwrap_or(Duration::ZERO);
+            if metadata.len() <= max_blob_size && age >= min_age {
+                candidates.push((hash, modified));
+            }
+        }
+
+        let mut stats = CompactStats::default();
+        let mut writer: Option<PackWriter> = None;
+        for (hash, modified) in candidates {
+            let data = match fs::read(self.hash_to_path(&hash)?) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+
+            let pack = match &mut writer {
+                Some(pack) => pack,
+                None => writer.insert(self.start_pack(&dir)?),
+            };
+            pack.file.write_all(&data)?;
+            pack.entries.push((hash, pack.len, data.len() as u64, modified));
+            pack.len += data.len() as u64;
+
+            if pack.len >= MAX_PACK_BYTES {
+                self.finish_pack(&dir, writer.take().unwrap(), &mut stats)?;
+            }
+        }
+        if let Some(pack) = writer {
+            self.finish_pack(&dir, pack, &mut stats)?;
+        }
+
+        Ok(stats)
+    }
+
+    fn start_pack(&self, dir: &Path) -> Result<PackWriter> {
+        let name = format!("pack-{}", uuid::Uuid::new_v4().simple());
+        let path = dir.join(format!("{}.pack.tmp", name));
+        let file = fs::File::create(&path).with_context(|| format!("Failed to create {:?}", path))?;
+        Ok(PackWriter {
+            name,
+            file: BufWriter::new(file),
+            entries: Vec::new(),
+            len: 0,
+        })
+    }
+
+    /// Publish a pack and its index, then drop the loose copies
+    fn finish_pack(&self, dir: &Path, pack: PackWriter, stats: &mut CompactStats) -> Result<()> {
+        let PackWriter { name, file, entries, len } = pack;
+        file.into_inner()
+            .map_err(|e| e.into_error())
+            .and_then(|file| file.sync_all())
+            .with_context(|| format!("Failed to write pack {}", name))?;
+
+        let lines: Vec<_> = entries
+            .iter()
+            .map(|(hash, offset, len, modified)| (hash.as_str(), *offset, *len, *modified))
+            .collect();
+        self.publish_pack(dir, &name, &lines)?;
+
+        // The pack now serves these; a loose file touched meanwhile stays,
+        // so its newer age isn't lost
+        for (hash, _, _, modified) in &entries {
+            let Ok(path) = self.hash_to_path(hash) else {
+                continue;
+            };
+            let unchanged = fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .is_ok_and(|m| m == *modified);
+            if unchanged {
+                let _ = fs::remove_file(&path);
+            }
+        }
+
+        stats.packed += entries.len();
+        stats.packs_written += 1;
+        stats.bytes_packed += len;
+        Ok(())
+    }
+
+    /// Rename `<name>.pack.tmp` into place and write its index
+    fn publish_pack(&self, dir: &Path, name: &str, entries: &[(&str, u64, u64, SystemTime)]) -> Result<()> {
+        let mut index = format!("{}\n", INDEX_MAGIC);
+        for (hash, offset, len, modified) in entries {
+            let secs = modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            index.push_str(&format!("{} {} {} {}\n", hash, offset, len, secs));
+        }
+
+        let tmp = dir.join(format!("{}.pack.tmp", name));
+        fs::rename(&tmp, dir.join(format!("{}.pack", name)))
+            .with_context(|| format!("Failed to publish pack {}", name))?;
+        let idx_tmp = dir.join(format!("{}.idx.tmp", name));
+        fs::write(&idx_tmp, index).with_context(|| format!("Failed to write {:?}", idx_tmp))?;
+        fs::rename(&idx_tmp, dir.join(format!("{}.idx", name)))
+            .with_context(|| format!("Failed to publish index of pack {}", name))?;
+        Ok(())
+    }
+
+    /// Rewrite every pack holding one of `hashes` without them (deleting
+    /// packs left empty). Returns the stored bytes dropped.
+    pub(crate) fn drop_packed(&self, hashes: &HashSet<String>) -> Result<u64> {
+        let dir = self.packs_dir();
+        let mut by_pack: HashMap<PathBuf, Vec<(String, PackEntry)>> = HashMap::new();
+        {
+            let mut index = self.packs.lock().unwrap();
+            index.refresh(&dir)?;
+            for (hash, entry) in &index.entries {
+                by_pack
+                    .entry(entry.pack.clone())
+                    .or_default()
+                    .push((hash.clone(), entry.clone()));
+            }
+        }
+
+        let mut freed = 0;
+        for (pack, mut entries) in by_pack {
+            if !entries.iter().any(|(hash, _)| hashes.contains(hash)) {
+                continue;
+            }
+            entries.sort_by_key(|(_, entry)| entry.offset);
+            let (dropped, kept): (Vec<_>, Vec<_>) =
+                entries.into_iter().partition(|(hash, _)| hashes.contains(hash));
+            freed += dropped.iter().map(|(_, entry)| entry.len).sum::<u64>();
+
+            if !kept.is_empty() {
+                let mut writer = self.start_pack(&dir)?;
+                let mut source = fs::File::open(&pack).with_context(|| format!("Failed to open {:?}", pack))?;
+                let mut lines = Vec::new();
+                for (hash, entry) in &kept {
+                    let mut data = vec![0; entry.len as usize];
+                    source.seek(SeekFrom::Start(entry.offset))?;
+                    source.read_exact(&mut data)?;
+                    writer.file.write_all(&data)?;
+                    lines.push((hash.as_str(), writer.len, entry.len, entry.modified));
+                    writer.len += entry.len;
+                }
+                writer.file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+                self.publish_pack(&dir, &writer.name, &lines)?;
+            }
+
+            // Index first, so readers never look up offsets in a missing pack
+            let _ = fs::remove_file(pack.with_extension("idx"));
+            fs::remove_file(&pack).with_context(|| format!("Failed to remove {:?}", pack))?;
+        }
+
+        *self.packs.lock().unwrap() = PackIndex::default();
+        Ok(freed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_compact_and_read_packed() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+        let small: Vec<String> = (0..50).map(|i| cas.put(format!("blob {}", i).as_bytes()).unwrap()).collect();
+        let large = cas.put(&vec![7u8; 4096]).unwrap();
+
+        let stats = cas.compact(1024, Duration::ZERO).unwrap();
+        assert_eq!(stats.packed, 50);
+        assert_eq!(stats.packs_written, 1);
+
+        // Small blobs are gone from disk but still readable, listed and found
+        assert!(cas.is_packed(&small[3]));
+        assert!(!cas.get_path(&small[3]).unwrap().exists());
+        assert_eq!(cas.get(&small[3]).unwrap(), b"blob 3");
+        assert!(cas.exists(&small[49]));
+        assert!(!cas.is_packed(&large));
+        assert_eq!(cas.list_all().unwrap().len(), 51);
+
+        // Another process sees the pack too
+        let other = Cas::new(temp_dir.path()).unwrap();
+        assert_eq!(other.get(&small[0]).unwrap(), b"blob 0");
+
+        // Removing a packed blob rewrites the pack without it
+        assert!(cas.remove(&small[0]).unwrap());
+        assert!(!cas.exists(&small[0]));
+        assert!(other.get(&small[0]).is_err());
+        assert_eq!(other.get(&small[1]).unwrap(), b"blob 1");
+        assert_eq!(cas.list_all().unwrap().len(), 50);
+    }
+
+    #[test]
+    fn test_gc_drops_stale_packed_blobs() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+        let pinned = cas.put(b"still needed").unwrap();
+        let stale = cas.put(b"old output").unwrap();
+        cas.compact(1024, Duration::ZERO).unwrap();
+
+        let stats = cas.gc(Duration::ZERO, &HashSet::from([pinned.clone()])).unwrap();
+        assert_eq!(stats.deleted, 1);
+        assert!(!cas.exists(&stale));
+        assert_eq!(cas.get(&pinned).unwrap(), b"still needed");
+    }
+}
@@ -0,0 +1,251 @@
+use super::service::MAX_BLOB_MESSAGE_BYTES;
+use crate::common::auth::{AuthedBlobStoreClient, ClientAuth};
+use crate::common::config::RemoteConfig;
+use crate::proto::distbuild::blob_store_client::BlobStoreClient;
+use crate::proto::distbuild::*;
+use anyhow::{Context, Result};
+use log::warn;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::{Code, Response, Status};
+
+/// Connects to remote CAS peers with the timeouts, retries and circuit
+/// breaking from `RemoteConfig`. Clones share the breakers, so a peer
+/// that keeps failing is skipped by every transfer, not just one.
+#[derive(Debug, Clone, Default)]
+pub struct RemoteCas {
+    config: RemoteConfig,
+    breakers: Arc<Mutex<HashMap<String, Breaker>>>,
+    auth: ClientAuth,
+}
+
+/// Consecutive failed calls to one peer, and until when it's skipped
+#[derive(Debug, Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// A connection to one peer's blob store
+#[derive(Debug, Clone)]
+pub struct PeerClient {
+    peer: String,
+    client: AuthedBlobStoreClient,
+    remote: RemoteCas,
+}
+
+impl RemoteCas {
+    pub fn new(config: &RemoteConfig) -> Self {
+        RemoteCas {
+            config: config.clone(),
+            breakers: Arc::default(),
+            auth: ClientAuth::default(),
+        }
+    }
+
+    /// Connect with this node's token and certificate (see `ClientAuth`)
+    pub fn with_auth(mut self, auth: ClientAuth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Connect to `peer` (host:port), unless its breaker is open
+    pub async fn connect(&self, peer: &str) -> Result<PeerClient> {
+        self.check_breaker(peer)?;
+
+        let connected = async {
+            self.auth
+                .endpoint(format!("http://{}", peer))?
+                .connect_timeout(Duration::from_millis(self.config.connect_timeout_ms))
+                .timeout(Duration::from_secs(self.config.request_timeout_secs.max(1)))
+                .connect()
+                .await
+        }
+        .await;
+        let channel = match connected {
+            Ok(channel) => channel,
+            Err(e) => {
+                self.record_failure(peer);
+                return Err(e).with_context(|| format!("Failed to connect to CAS peer {}", peer));
+            }
+        };
+
+        let client = BlobStoreClient::with_interceptor(channel, self.auth.clone())
+            .max_decoding_message_size(MAX_BLOB_MESSAGE_BYTES)
+            .max_encoding_message_size(MAX_BLOB_MESSAGE_BYTES);
+        Ok(PeerClient {
+            peer: peer.to_string(),
+            client,
+            remote: self.clone(),
+        })
+    }
+
+    /// Whether `peer` is currently skipped after repeated failures
+    pub fn is_open(&self, peer: &str) -> bool {
+        self.check_breaker(peer).is_err()
+    }
+
+    /// Fail fast while the breaker is open. Once the cooldown is over one
+    /// call is let through; if that fails too it opens again right away.
+    fn check_breaker(&self, peer: &str) -> Result<()> {
+        let breakers = self.breakers.lock().unwrap();
+        match breakers.get(peer).and_then(|breaker| breaker.open_until) {
+            Some(until) if until > Instant::now() => anyhow::bail!(
+                "CAS peer {} is failing; skipped for another {}s",
+                peer,
+                until.saturating_duration_since(Instant::now()).as_secs() + 1
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_success(&self, peer: &str) {
+        self.breakers.lock().unwrap().remove(peer);
+    }
+
+    fn record_failure(&self, peer: &str) {
+        let threshold = self.config.breaker_failures;
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(peer.to_string()).or_default();
+        breaker.failures += 1;
+        if threshold > 0 && breaker.failures >= threshold {
+            let cooldown = Duration::from_secs(self.config.breaker_cooldown_secs);
+            if breaker.failures == threshold {
+                warn!("⚠️  CAS peer {} failed {} times in a row; skipping it for {:?}", peer, threshold, cooldown);
+            }
+            breaker.open_until = Some(Instant::now() + cooldown);
+        }
+    }
+
+    /// Wait before retry number `attempt` (1-based)
+    fn backoff(&self, attempt: u32) -> Duration {
+        let initial = Duration::from_millis(self.config.initial_backoff_ms);
+        let max = Duration::from_millis(self.config.max_backoff_ms);
+        initial.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1))).min(max)
+    }
+}
+
+impl PeerClient {
+    pub async fn has_blob(&self, request: HasBlobRequest) -> Result<HasBlobResponse> {
+        self.call(|mut client| {
+            let request = request.clone();
+            async move { client.has_blob(request).await }
+        })
+        .await
+    }
+
+    pub async fn read_blob(&self, request: ReadBlobRequest) -> Result<ReadBlobResponse> {
+        self.call(|mut client| {
+            let request = request.clone();
+            async move { client.read_blob(request).await }
+        })
+        .await
+    }
+
+    /// Retrying is safe: the peer only appends at the offset it reports
+    pub async fn write_blob(&self, request: WriteBlobRequest) -> Result<WriteBlobResponse> {
+        self.call(|mut client| {
+            let request = request.clone();
+            async move { client.write_blob(request).await }
+        })
+        .await
+    }
+
+    pub async fn put_blob(&self, request: PutBlobRequest) -> Result<PutBlobResponse> {
+        self.call(|mut client| {
+            let request = request.clone();
+            async move { client.put_blob(request).await }
+        })
+        .await
+    }
+
+    pub async fn record_action(&self, request: RecordActionRequest) -> Result<RecordActionResponse> {
+        self.call(|mut client| {
+            let request = request.clone();
+            async move { client.record_action(request).await }
+        })
+        .await
+    }
+
+    /// Run `call`, retrying transient failures with backoff. Errors the
+    /// peer answered deliberately (not found, quota, ...) are returned as
+    /// is and don't count against its breaker.
+    async fn call<T, F, Fut>(&self, mut call: F) -> Result<T>
+    where
+        F: FnMut(AuthedBlobStoreClient) -> Fut,
+        Fut: Future<Output = Result<Response<T>, Status>>,
+    {
+        let remote = &self.remote;
+        let mut attempt = 0;
+        loop {
+            remote.check_breaker(&self.peer)?;
+            let status = match call(self.client.clone()).await {
+                Ok(resp) => {
+                    remote.record_success(&self.peer);
+                    return Ok(resp.into_inner());
+                }
+                Err(status) => status,
+            };
+            if !is_transient(&status) {
+                return Err(status.into());
+            }
+
+            attempt += 1;
+            if attempt >= remote.config.max_attempts.max(1) {
+                remote.record_failure(&self.peer);
+                return Err(anyhow::Error::from(status))
+                    .with_context(|| format!("CAS peer {} still failing after {} attempts", self.peer, attempt));
+            }
+            let delay = remote.backoff(attempt);
+            warn!("⚠️  CAS peer {} call failed ({}), retrying in {:?}", self.peer, status.message(), delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Failures worth retrying: the peer or the network hiccuped
+fn is_transient(status: &Status) -> bool {
+    matches!(
+        status.code(),
+        Code::Unavailable | Code::DeadlineExceeded | Code::Aborted | Code::Unknown | Code::Cancelled
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let remote = RemoteCas::new(&RemoteConfig {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 500,
+            ..Default::default()
+        });
+        assert_eq!(remote.backoff(1), Duration::from_millis(100));
+        assert_eq!(remote.backoff(3), Duration::from_millis(400));
+        assert_eq!(remote.backoff(10), Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_breaker_opens_after_repeated_failures() {
+        let remote = RemoteCas::new(&RemoteConfig {
+            connect_timeout_ms: 200,
+            breaker_failures: 2,
+            ..Default::default()
+        });
+        // Nothing listens on port 1
+        let peer = "127.0.0.1:1";
+        assert!(remote.connect(peer).await.is_err());
+        assert!(!remote.is_open(peer));
+        assert!(remote.connect(peer).await.is_err());
+        assert!(remote.is_open(peer));
+
+        // Shared by clones; other peers are unaffected
+        let err = remote.clone().connect(peer).await.unwrap_err();
+        assert!(err.to_string().contains("skipped"));
+        assert!(!remote.is_open("127.0.0.1:2"));
+    }
+}
@@ -0,0 +1,371 @@
+use super::http;
+use super::remote::{PeerClient, RemoteCas};
+use super::transfer::TRANSFER_PIECE_BYTES;
+use super::Cas;
+use crate::common::auth::ClientAuth;
+use crate::common::config::{RemoteConfig, ReplicationConfig, ReplicationMode};
+use crate::proto::distbuild::*;
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::fs;
+use std::io::Write;
+use std::time::Duration;
+
+/// Copies blobs between this node's CAS and its peers' (see `ReplicationConfig`)
+#[derive(Debug, Clone)]
+pub struct Replicator {
+    mode: ReplicationMode,
+    peers: Vec<String>,
+    discover_workers: bool,
+    scheduler_addr: String,
+    self_addr: String,
+    remote: RemoteCas,
+    auth: ClientAuth,
+    /// Blob HTTP endpoint tried before any peer (see `RemoteConfig::http_url`)
+    http_url: Option<String>,
+    http: reqwest::Client,
+}
+
+impl Replicator {
+    /// `self_addr` is this node's own blob store address, never used as a peer
+    pub fn new(config: &ReplicationConfig, scheduler_addr: &str, self_addr: &str) -> Self {
+        Replicator {
+            mode: config.mode,
+            peers: config.peers.clone(),
+            discover_workers: config.discover_workers,
+            scheduler_addr: scheduler_addr.to_string(),
+            self_addr: self_addr.to_string(),
+            remote: RemoteCas::default(),
+            auth: ClientAuth::default(),
+            http_url: None,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Time out, retry and circuit-break peer calls as `config` says
+    /// (see `RemoteCas`); the defaults otherwise
+    pub fn with_remote(mut self, config: &RemoteConfig) -> Self {
+        self.remote = RemoteCas::new(config).with_auth(self.auth.clone());
+        self.http_url = config.http_url.clone().filter(|url| !url.is_empty());
+        self.http = reqwest::Client::builder()
+            .connect_timeout(Duration::from_millis(config.connect_timeout_ms))
+            .build()
+            .unwrap_or_default();
+        self
+    }
+
+    /// Token to list workers with, for a scheduler that requires one, and
+    /// certificate to connect to peers with
+    pub fn with_auth(mut self, auth: ClientAuth) -> Self {
+        self.remote = self.remote.with_auth(auth.clone());
+        self.auth = auth;
+        self
+    }
+
+    /// Configured peers plus, if enabled, the workers the scheduler knows of
+    /// (the scheduler itself is always a candidate when discovering)
+    async fn peers(&self) -> Vec<String> {
+        let mut peers = self.peers.clone();
+
+        if self.discover_workers {
+            peers.push(self.scheduler_addr.clone());
+            match self.registered_workers().await {
+                Ok(workers) => peers.extend(workers),
+                Err(e) => warn!("⚠️  Couldn't list workers for replication: {}", e),
+            }
+        }
+
+        // Peers whose breaker is open would only fail fast; skip them quietly
+        peers.retain(|peer| *peer != self.self_addr && !self.remote.is_open(peer));
+        peers.sort();
+        peers.dedup();
+        peers
+    }
+
+    async fn registered_workers(&self) -> Result<Vec<String>> {
+        let mut client = self.auth.connect_scheduler(format!("http://{}", self.scheduler_addr))
+            .await
+            .context("Failed to connect to scheduler")?;
+        let resp = client.list_workers(ListWorkersRequest {}).await?.into_inner();
+        Ok(resp.workers.into_iter().map(|w| w.address).collect())
+    }
+
+    /// Push a blob to every peer that doesn't already have it. Chunked blobs
+    /// only send the chunks a peer is missing. Best effort: failures are
+    /// logged, and the number of peers that received it is returned.
+    pub async fn push(&self, cas: &Cas, hash: &str) -> usize {
+        if !self.mode.pushes() {
+            return 0;
+        }
+
+        let payload = match cas.chunk_list(hash) {
+            Ok(Some(chunks)) => Ok(Payload::Chunked(chunks)),
+            Ok(None) => cas.get(hash).map(Payload::Whole),
+            Err(e) => Err(e),
+        };
+        let payload = match payload {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("⚠️  Can't replicate {}: {}", hash, e);
+                return 0;
+            }
+        };
+
+        let mut pushed = 0;
+        for peer in self.peers().await {
+            let pushed_to = match self.remote.connect(&peer).await {
+                Ok(client) => push_to_peer(&client, cas, hash, &payload).await,
+                Err(e) => Err(e),
+            };
+            match pushed_to {
+                Ok(true) => pushed += 1,
+                Ok(false) => {}
+                Err(e) => warn!("⚠️  Failed to replicate {} to {}: {}", hash, peer, e),
+            }
+        }
+
+        if pushed > 0 {
+            info!("📡 Replicated {} to {} peer(s)", hash, pushed);
+        }
+        pushed
+    }
+
+    /// Push an action's input and output, then its action-cache entry, to
+    /// every peer. Best effort like `push`; returns the number of peers
+    /// that recorded the entry.
+    pub async fn push_action(&self, cas: &Cas, input_hash: &str, output_hash: &str) -> usize {
+        if !self.mode.pushes() {
+            return 0;
+        }
+        self.push(cas, input_hash).await;
+        self.push(cas, output_hash).await;
+
+        let request = RecordActionRequest {
+            input_hash: input_hash.to_string(),
+            output_hash: output_hash.to_string(),
+            namespace: cas.namespace_name().unwrap_or_default().to_string(),
+        };
+        let mut recorded = 0;
+        for peer in self.peers().await {
+            let result = match self.remote.connect(&peer).await {
+                Ok(client) => client.record_action(request.clone()).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(_) => recorded += 1,
+                Err(e) => warn!("⚠️  Failed to record action {} on {}: {}", input_hash, peer, e),
+            }
+        }
+
+        if recorded > 0 {
+            info!("📡 Recorded action {} on {} peer(s)", input_hash, recorded);
+        }
+        recorded
+    }
+
+    /// Fetch a blob missing locally from the blob HTTP endpoint, if one is
+    /// configured, or else the first peer that has it, and store it in
+    /// `cas`. Returns whether it was found.
+    pub async fn pull(&self, cas: &Cas, hash: &str) -> Result<bool> {
+        if let Some(base_url) = &self.http_url {
+            match http::fetch(&self.http, base_url, self.auth.token(), cas, hash).await {
+                Ok(true) => {
+                    info!("🌐 Fetched {} from {}", hash, base_url);
+                    return Ok(true);
+                }
+                Ok(false) => {}
+                Err(e) => warn!("⚠️  Failed to fetch {} from {}: {}", hash, base_url, e),
+            }
+        }
+        if !self.mode.pulls() {
+            return Ok(false);
+        }
+
+        for peer in self.peers().await {
+            let client = match self.remote.connect(&peer).await {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("⚠️  Can't reach CAS peer {}: {}", peer, e);
+                    continue;
+                }
+            };
+
+            match pull_from_peer(&client, cas, hash).await {
+                Ok(true) => {
+                    info!("📡 Pulled {} from {}", hash, peer);
+                    return Ok(true);
+                }
+                Ok(false) => {}
+                Err(e) => warn!("⚠️  Failed to fetch {} from {}: {}", hash, peer, e),
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// What to send for a blob: its bytes, or the chunks it's stored as
+enum Payload {
+    Whole(Vec<u8>),
+    Chunked(Vec<String>),
+}
+
+/// Returns false if the peer already had the blob
+async fn push_to_peer(client: &PeerClient, cas: &Cas, hash: &str, payload: &Payload) -> Result<bool> {
+    let namespace = cas.namespace_name().unwrap_or_default().to_string();
+
+    if has_blob(client, hash, &namespace).await? {
+        return Ok(false);
+    }
+
+    let chunks = match payload {
+        Payload::Whole(data) => {
+            upload(client, hash, &namespace, data).await?;
+            return Ok(true);
+        }
+        Payload::Chunked(chunks) => chunks,
+    };
+
+    for chunk in chunks {
+        if !has_blob(client, chunk, &namespace).await? {
+            upload(client, chunk, &namespace, &cas.get(chunk)?).await?;
+        }
+    }
+    let request = PutBlobRequest {
+        namespace: namespace.clone(),
+        hash: hash.to_string(),
+        chunks: chunks.clone(),
+        ..Default::default()
+    };
+    let resp = client.put_blob(request).await?;
+    if resp.hash != hash {
+        anyhow::bail!("peer stored {} instead of {}", resp.hash, hash);
+    }
+    Ok(!resp.already_present)
+}
+
+/// Returns false if the peer doesn't have the blob
+async fn pull_from_peer(client: &PeerClient, cas: &Cas, hash: &str) -> Result<bool> {
+    let namespace = cas.namespace_name().unwrap_or_default().to_string();
+    let probe = client
+        .read_blob(ReadBlobRequest {
+            hash: hash.to_string(),
+            namespace: namespace.clone(),
+            accept_chunks: true,
+            ..Default::default()
+        })
+        .await?;
+    if !probe.found {
+        return Ok(false);
+    }
+
+    if probe.chunks.is_empty() {
+        return download(client, cas, hash, &namespace).await;
+    }
+
+    // Only fetch the chunks this CAS doesn't already share with the peer
+    for chunk in &probe.chunks {
+        if !cas.exists(chunk) && !download(client, cas, chunk, &namespace).await? {
+            anyhow::bail!("peer is missing chunk {}", chunk);
+        }
+    }
+    cas.put_from_chunks(hash, &probe.chunks)?;
+    Ok(true)
+}
+
+/// Fetch blob `hash` piece by piece into this CAS, continuing from whatever
+/// an interrupted earlier download left behind. The result is checked
+/// against `hash` before it's stored. Returns false if the peer lacks it.
+async fn download(
+    client: &PeerClient,
+    cas: &Cas,
+    hash: &str,
+    namespace: &str,
+) -> Result<bool> {
+    let path = cas.download_path(hash)?;
+    let mut file = fs::File::options()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    let mut offset = file.metadata()?.len();
+
+    loop {
+        let resp = client
+            .read_blob(ReadBlobRequest {
+                hash: hash.to_string(),
+                namespace: namespace.to_string(),
+                offset,
+                length: TRANSFER_PIECE_BYTES as u64,
+                accept_chunks: false,
+            })
+            .await?;
+        if !resp.found {
+            return Ok(false);
+        }
+        if offset > resp.total_size {
+            drop(file);
+            let _ = fs::remove_file(&path);
+            anyhow::bail!("partial download of {} is longer than the blob", hash);
+        }
+
+        file.write_all(&resp.data)
+            .with_context(|| format!("Failed to write to {:?}", path))?;
+        offset += resp.data.len() as u64;
+        if offset >= resp.total_size {
+            break;
+        }
+        if resp.data.is_empty() {
+            anyhow::bail!("peer sent nothing for {} at offset {}", hash, offset);
+        }
+    }
+
+    drop(file);
+    cas.finish_download(hash)?;
+    Ok(true)
+}
+
+/// Send `data` (blob `hash`) piece by piece. The peer reports how much it
+/// already holds, so a retried or restarted upload skips what it has.
+async fn upload(
+    client: &PeerClient,
+    hash: &str,
+    namespace: &str,
+    data: &[u8],
+) -> Result<()> {
+    let total = data.len() as u64;
+    let mut offset = 0u64;
+
+    loop {
+        if offset > total {
+            anyhow::bail!("peer holds more of {} than the blob's {} bytes", hash, total);
+        }
+        let end = (offset as usize + TRANSFER_PIECE_BYTES).min(data.len());
+        let resp = client
+            .write_blob(WriteBlobRequest {
+                hash: hash.to_string(),
+                namespace: namespace.to_string(),
+                offset,
+                data: data[offset as usize..end].to_vec(),
+                finish_write: end == data.len(),
+            })
+            .await?;
+        if resp.complete {
+            return Ok(());
+        }
+        if resp.committed_offset == offset && end > offset as usize {
+            anyhow::bail!("peer made no progress on {} at offset {}", hash, offset);
+        }
+        offset = resp.committed_offset;
+    }
+}
+
+async fn has_blob(client: &PeerClient, hash: &str, namespace: &str) -> Result<bool> {
+    let resp = client
+        .has_blob(HasBlobRequest {
+            hash: hash.to_string(),
+            namespace: namespace.to_string(),
+        })
+        .await?;
+    Ok(resp.present)
+}
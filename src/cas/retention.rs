@@ -0,0 +1,75 @@
+use crate::common::config::RetentionRule;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Per-output GC ages from `[[cas.retention]]` rules
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    rules: Vec<RetentionRule>,
+}
+
+impl RetentionPolicy {
+    pub fn new(rules: Vec<RetentionRule>) -> Self {
+        RetentionPolicy { rules }
+    }
+
+    /// Age after which an output of this job type and profile may be
+    /// collected, or `None` if no rule matches
+    pub fn max_age(&self, job_type: &str, profile: &str) -> Option<Duration> {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.job_type.as_deref().is_none_or(|t| t == job_type)
+                    && rule.profile.as_deref().is_none_or(|p| p == profile)
+            })
+            .map(|rule| Duration::from_secs(rule.keep_days * 24 * 60 * 60))
+    }
+
+    /// Max age per output hash for outputs given as (hash, job type, profile).
+    /// A blob produced by several jobs gets the longest matching age.
+    pub fn ages<'a, I>(&self, outputs: I) -> HashMap<String, Duration>
+    where
+        I: IntoIterator<Item = (&'a str, &'a str, &'a str)>,
+    {
+        let mut ages: HashMap<String, Duration> = HashMap::new();
+        for (hash, job_type, profile) in outputs {
+            if let Some(age) = self.max_age(job_type, profile) {
+                let entry = ages.entry(hash.to_string()).or_insert(age);
+                *entry = (*entry).max(age);
+            }
+        }
+        ages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(job_type: Option<&str>, profile: Option<&str>, keep_days: u64) -> RetentionRule {
+        RetentionRule {
+            job_type: job_type.map(str::to_string),
+            profile: profile.map(str::to_string),
+            keep_days,
+        }
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let policy = RetentionPolicy::new(vec![
+            rule(Some("doc"), None, 2),
+            rule(Some("rust-compile"), Some("release"), 90),
+            rule(None, Some("debug"), 7),
+        ]);
+        let days = |d: u64| Some(Duration::from_secs(d * 86400));
+
+        assert_eq!(policy.max_age("rust-compile", "release"), days(90));
+        assert_eq!(policy.max_age("rust-compile", "debug"), days(7));
+        assert_eq!(policy.max_age("doc", "release"), days(2));
+        assert_eq!(policy.max_age("rust-compile", "bench"), None);
+
+        // Same blob from a debug and a release job is kept for the longer
+        let ages = policy.ages([("h", "rust-compile", "debug"), ("h", "rust-compile", "release")]);
+        assert_eq!(ages.get("h").copied(), days(90));
+    }
+}
@@ -0,0 +1,306 @@
+use super::{is_valid_hash, Cas};
+use crate::common::DistbuildError;
+use crate::proto::distbuild::blob_store_server::{BlobStore, BlobStoreServer};
+use crate::proto::distbuild::*;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+/// Largest blob that fits in a single transfer message
+pub const MAX_BLOB_MESSAGE_BYTES: usize = 512 * 1024 * 1024;
+
+/// gRPC access to a local CAS for peers
+pub struct BlobStoreService {
+    cas: Arc<Cas>,
+}
+
+impl BlobStoreService {
+    /// Build the tonic server for `cas`, with message limits raised for blobs
+    pub fn server(cas: Arc<Cas>) -> BlobStoreServer<Self> {
+        BlobStoreServer::new(BlobStoreService { cas })
+            .max_decoding_message_size(MAX_BLOB_MESSAGE_BYTES)
+            .max_encoding_message_size(MAX_BLOB_MESSAGE_BYTES)
+    }
+
+    fn cas_for(&self, namespace: &str) -> anyhow::Result<Cas> {
+        if namespace.is_empty() {
+            return Ok(self.cas.as_ref().clone());
+        }
+        self.cas.namespace(namespace)
+    }
+}
+
+/// Refuse anything but a blob hash before it gets near the filesystem
+#[allow(clippy::result_large_err)] // handlers return `Status` as is
+fn check_hash(hash: &str) -> Result<(), Status> {
+    if !is_valid_hash(hash) {
+        return Err(Status::invalid_argument(format!("Invalid blob hash: {:?}", hash)));
+    }
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl BlobStore for BlobStoreService {
+    async fn get_blob(
+        &self,
+        request: Request<GetBlobRequest>,
+    ) -> Result<Response<GetBlobResponse>, Status> {
+        let req = request.into_inner();
+        check_hash(&req.hash)?;
+        let cas = self
+            .cas_for(&req.namespace)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        if !cas.exists(&req.hash) {
+            return Ok(Response::new(GetBlobResponse::default()));
+        }
+
+        let response = tokio::task::spawn_blocking(move || {
+            if req.accept_chunks {
+                if let Some(chunks) = cas.chunk_list(&req.hash)? {
+                    return Ok(GetBlobResponse {
+                        found: true,
+                        data: Vec::new(),
+                        chunks,
+                    });
+                }
+            }
+            cas.get(&req.hash).map(|data| GetBlobResponse {
+                found: true,
+                data,
+                chunks: Vec::new(),
+            })
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e: anyhow::Error| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(response))
+    }
+
+    async fn put_blob(
+        &self,
+        request: Request<PutBlobRequest>,
+    ) -> Result<Response<PutBlobResponse>, Status> {
+        let req = request.into_inner();
+        if !req.chunks.is_empty() {
+            check_hash(&req.hash)?;
+        }
+        let cas = self
+            .cas_for(&req.namespace)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let (hash, already_present) = tokio::task::spawn_blocking(move || {
+            if !req.chunks.is_empty() {
+                let already_present = cas.exists(&req.hash);
+                if !already_present {
+                    cas.put_from_chunks(&req.hash, &req.chunks)?;
+                }
+                return Ok((req.hash, already_present));
+            }
+
+            let hash = Cas::hash_bytes(&req.data);
+            let already_present = cas.exists(&hash);
+            cas.put(&req.data).map(|hash| (hash, already_present))
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(store_error)?;
+
+        Ok(Response::new(PutBlobResponse { hash, already_present }))
+    }
+
+    async fn has_blob(
+        &self,
+        request: Request<HasBlobRequest>,
+    ) -> Result<Response<HasBlobResponse>, Status> {
+        let req = request.into_inner();
+        check_hash(&req.hash)?;
+        let cas = self
+            .cas_for(&req.namespace)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        Ok(Response::new(HasBlobResponse {
+            present: cas.exists(&req.hash),
+        }))
+    }
+
+    async fn read_blob(
+        &self,
+        request: Request<ReadBlobRequest>,
+    ) -> Result<Response<ReadBlobResponse>, Status> {
+        let req = request.into_inner();
+        check_hash(&req.hash)?;
+        let cas = self
+            .cas_for(&req.namespace)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        if !cas.exists(&req.hash) {
+            return Ok(Response::new(ReadBlobResponse::default()));
+        }
+
+        let response = tokio::task::spawn_blocking(move || {
+            if req.accept_chunks {
+                if let Some(chunks) = cas.chunk_list(&req.hash)? {
+                    return Ok(ReadBlobResponse {
+                        found: true,
+                        total_size: cas.read_range(&req.hash, 0, 0)?.1,
+                        chunks,
+                        ..Default::default()
+                    });
+                }
+            }
+            if req.length == 0 {
+                return Ok(ReadBlobResponse {
+                    found: true,
+                    total_size: cas.read_range(&req.hash, 0, 0)?.1,
+                    ..Default::default()
+                });
+            }
+
+            let length = (req.length as usize).min(MAX_BLOB_MESSAGE_BYTES);
+            cas.read_range(&req.hash, req.offset, length)
+                .map(|(data, total_size)| ReadBlobResponse {
+                    found: true,
+                    data,
+                    total_size,
+                    chunks: Vec::new(),
+                })
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e: anyhow::Error| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(response))
+    }
+
+    async fn write_blob(
+        &self,
+        request: Request<WriteBlobRequest>,
+    ) -> Result<Response<WriteBlobResponse>, Status> {
+        let req = request.into_inner();
+        check_hash(&req.hash)?;
+        let cas = self
+            .cas_for(&req.namespace)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let response = tokio::task::spawn_blocking(move || {
+            if cas.exists(&req.hash) {
+                return Ok(WriteBlobResponse {
+                    committed_offset: req.offset + req.data.len() as u64,
+                    complete: true,
+                });
+            }
+
+            let committed_offset = cas.append_upload(&req.hash, req.offset, &req.data)?;
+            let appended = committed_offset == req.offset + req.data.len() as u64;
+            if req.finish_write && appended {
+                cas.finish_upload(&req.hash)?;
+                return Ok(WriteBlobResponse { committed_offset, complete: true });
+            }
+            Ok(WriteBlobResponse { committed_offset, complete: false })
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(store_error)?;
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_cas_metrics(
+        &self,
+        _request: Request<GetCasMetricsRequest>,
+    ) -> Result<Response<GetCasMetricsResponse>, Status> {
+        Ok(Response::new(self.cas.metrics().into()))
+    }
+
+    async fn record_action(
+        &self,
+        request: Request<RecordActionRequest>,
+    ) -> Result<Response<RecordActionResponse>, Status> {
+        let req = request.into_inner();
+        check_hash(&req.input_hash)?;
+        check_hash(&req.output_hash)?;
+        let cas = self
+            .cas_for(&req.namespace)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        // An entry whose blobs aren't here would point at nothing
+        for hash in [&req.input_hash, &req.output_hash] {
+            if !cas.exists(hash) {
+                return Err(Status::failed_precondition(format!("Blob {} not found", hash)));
+            }
+        }
+        cas.record_action(&req.input_hash, &req.output_hash).map_err(store_error)?;
+
+        Ok(Response::new(RecordActionResponse {}))
+    }
+}
+
+/// Status for a failed write into the CAS
+fn store_error(e: anyhow::Error) -> Status {
+    match e.downcast_ref::<DistbuildError>() {
+        Some(quota @ DistbuildError::QuotaExceeded { .. }) => {
+            Status::resource_exhausted(quota.to_string())
+        }
+        Some(read_only @ DistbuildError::ReadOnly(_)) => {
+            Status::failed_precondition(read_only.to_string())
+        }
+        Some(mismatch @ DistbuildError::HashMismatch { .. }) => {
+            Status::data_loss(mismatch.to_string())
+        }
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_hashes_outside_cas_refused() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path().join("cas")).unwrap();
+        let service = BlobStoreService { cas: Arc::new(cas) };
+        let hash = "../../../../../../etc/hostname".to_string();
+
+        let get = GetBlobRequest { hash: hash.clone(), ..Default::default() };
+        let status = service.get_blob(Request::new(get)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let has = HasBlobRequest { hash: hash.clone(), ..Default::default() };
+        let status = service.has_blob(Request::new(has)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let read = ReadBlobRequest { hash: hash.clone(), length: 100, ..Default::default() };
+        let status = service.read_blob(Request::new(read)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let write = WriteBlobRequest { hash: hash.clone(), data: b"x".to_vec(), ..Default::default() };
+        let status = service.write_blob(Request::new(write)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let action = RecordActionRequest { input_hash: hash, output_hash: "0".repeat(64), ..Default::default() };
+        let status = service.record_action(Request::new(action)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_record_action() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Arc::new(Cas::new(temp_dir.path().join("cas")).unwrap());
+        let service = BlobStoreService { cas: cas.clone() };
+        let input = cas.put(b"input").unwrap();
+        let output = Cas::hash_bytes(b"output");
+
+        // Not until the result itself has been uploaded
+        let request = RecordActionRequest {
+            input_hash: input.clone(),
+            output_hash: output.clone(),
+            ..Default::default()
+        };
+        let status = service.record_action(Request::new(request.clone())).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        assert_eq!(cas.action_result(&input), None);
+
+        cas.put(b"output").unwrap();
+        service.record_action(Request::new(request)).await.unwrap();
+        assert_eq!(cas.action_result(&input), Some(output));
+    }
+}
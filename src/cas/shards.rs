@@ -0,0 +1,160 @@
+use super::{Cas, NAMESPACES_DIR};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A storage root holding the loose blobs whose hashes start with a byte
+/// in `first..=last`, e.g. a second disk for `prefix_range = "80-ff"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shard {
+    first: u8,
+    last: u8,
+    root: PathBuf,
+}
+
+impl Shard {
+    /// Parse a `"00-7f"` style range (a single `"ab"` is one prefix)
+    pub fn new(prefix_range: &str, root: impl Into<PathBuf>) -> Result<Self> {
+        let parse = |prefix: &str| {
+            let prefix = prefix.trim();
+            if prefix.len() != 2 {
+                anyhow::bail!("Invalid shard prefix {:?} (expected two hex digits)", prefix);
+            }
+            u8::from_str_radix(prefix, 16)
+                .with_context(|| format!("Invalid shard prefix {:?} (expected two hex digits)", prefix))
+        };
+
+        let (first, last) = match prefix_range.split_once('-') {
+            Some((first, last)) => (parse(first)?, parse(last)?),
+            None => (parse(prefix_range)?, parse(prefix_range)?),
+        };
+        if first > last {
+            anyhow::bail!("Invalid shard prefix range {:?} (start is after end)", prefix_range);
+        }
+        Ok(Shard {
+            first,
+            last,
+            root: root.into(),
+        })
+    }
+
+    fn covers(&self, prefix: u8) -> bool {
+        (self.first..=self.last).contains(&prefix)
+    }
+}
+
+impl Cas {
+    /// Keep loose blobs whose hash prefix falls in a shard's range under
+    /// that shard's root instead of this CAS's root (e.g. to spread IO over
+    /// several disks). Prefixes no shard covers stay under the root, as do
+    /// packs and other bookkeeping. Changing the ranges doesn't move
+    /// existing blobs.
+    pub fn with_shards(mut self, shards: Vec<Shard>) -> Result<Self> {
+        for (i, shard) in shards.iter().enumerate() {
+            if let Some(other) = shards[..i].iter().find(|other| other.first <= shard.last && shard.first <= other.last) {
+                anyhow::bail!(
+                    "CAS shards {:?} and {:?} have overlapping prefix ranges",
+                    other.root,
+                    shard.root
+                );
+            }
+            if !self.read_only {
+                fs::create_dir_all(&shard.root)
+                    .with_context(|| format!("Failed to create CAS shard at {:?}", shard.root))?;
+            }
+        }
+        self.shards = Arc::new(shards);
+        Ok(self)
+    }
+
+    /// This view's directory in each shard
+    pub fn shard_roots(&self) -> Vec<PathBuf> {
+        self.shards.iter().map(|shard| self.shard_view_root(shard)).collect()
+    }
+
+    /// Directory holding the `<first2>` directory for hashes starting with `prefix`
+    pub(crate) fn loose_root(&self, prefix: u8) -> PathBuf {
+        match self.shards.iter().find(|shard| shard.covers(prefix)) {
+            Some(shard) => self.shard_view_root(shard),
+            None => self.root.clone(),
+        }
+    }
+
+    /// Every directory loose blobs of this view may live under
+    pub(crate) fn loose_roots(&self) -> Vec<PathBuf> {
+        let mut roots = vec![self.root.clone()];
+        for root in self.shard_roots() {
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+        roots
+    }
+
+    fn shard_view_root(&self, shard: &Shard) -> PathBuf {
+        match &self.namespace {
+            Some(name) => shard.root.join(NAMESPACES_DIR).join(name),
+            None => shard.root.clone(),
+        }
+    }
+}
+
+/// Rename `from` to `to`, copying instead when they're on different disks
+pub(crate) fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to).with_context(|| format!("Failed to move {:?} to {:?}", from, to))?;
+    fs::remove_file(from).with_context(|| format!("Failed to remove {:?}", from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_shard_ranges() {
+        assert!(Shard::new("00-7f", "/a").unwrap().covers(0x7f));
+        assert!(!Shard::new("00-7f", "/a").unwrap().covers(0x80));
+        assert!(Shard::new("ab", "/a").unwrap().covers(0xab));
+        assert!(Shard::new("80-7f", "/a").is_err());
+        assert!(Shard::new("0-ff", "/a").is_err());
+        assert!(Shard::new("zz", "/a").is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        let overlapping = vec![
+            Shard::new("00-80", temp_dir.path().join("a")).unwrap(),
+            Shard::new("80-ff", temp_dir.path().join("b")).unwrap(),
+        ];
+        assert!(Cas::new(temp_dir.path().join("root")).unwrap().with_shards(overlapping).is_err());
+    }
+
+    #[test]
+    fn test_sharded_storage() {
+        let temp_dir = TempDir::new().unwrap();
+        let disk = temp_dir.path().join("disk2");
+        let cas = Cas::new(temp_dir.path().join("root"))
+            .unwrap()
+            .with_shards(vec![Shard::new("00-ff", &disk).unwrap()])
+            .unwrap();
+
+        let hash = cas.put(b"sharded").unwrap();
+        let streamed = cas.put_stream(&b"streamed"[..]).unwrap();
+        assert!(disk.join(&hash[..2]).join(&hash[2..4]).join(&hash).is_file());
+        assert_eq!(cas.get(&streamed).unwrap(), b"streamed");
+
+        let mut listed = cas.list_all().unwrap();
+        listed.sort();
+        let mut expected = vec![hash.clone(), streamed];
+        expected.sort();
+        assert_eq!(listed, expected);
+
+        // Namespaces get their own directory in each shard
+        let ns = cas.namespace("team").unwrap();
+        let hash = ns.put(b"namespaced").unwrap();
+        assert!(disk.join(NAMESPACES_DIR).join("team").join(&hash[..2]).is_dir());
+        assert_eq!(ns.list_all().unwrap(), vec![hash]);
+    }
+}
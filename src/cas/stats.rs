@@ -0,0 +1,106 @@
+use super::crypto::BlobCipher;
+use super::Cas;
+use anyhow::Result;
+
+/// Upper bounds (exclusive) of the size histogram buckets, in bytes
+const SIZE_BUCKETS: [u64; 5] = [
+    1024,
+    64 * 1024,
+    1024 * 1024,
+    16 * 1024 * 1024,
+    256 * 1024 * 1024,
+];
+
+/// Number of largest blobs reported by `Cas::stats`
+const LARGEST_BLOBS: usize = 10;
+
+/// Summary of what is stored in a CAS
+#[derive(Debug, Clone, Default)]
+pub struct CasStats {
+    pub blob_count: usize,
+    /// Bytes callers put into the CAS
+    pub logical_bytes: u64,
+    /// Bytes actually occupied on disk
+    pub stored_bytes: u64,
+    /// (bucket upper bound, blob count); the last bucket has no upper bound
+    pub histogram: Vec<(Option<u64>, usize)>,
+    /// Largest blobs as (hash, size), biggest first
+    pub largest: Vec<(String, u64)>,
+}
+
+impl CasStats {
+    /// Logical bytes per stored byte (1.0 when blobs are stored as-is,
+    /// slightly below 1.0 with encryption overhead)
+    pub fn compression_ratio(&self) -> f64 {
+        if self.stored_bytes == 0 {
+            return 1.0;
+        }
+        self.logical_bytes as f64 / self.stored_bytes as f64
+    }
+}
+
+impl Cas {
+    /// Walk the store and summarize blob count, sizes and the largest blobs
+    pub fn stats(&self) -> Result<CasStats> {
+        let mut stats = CasStats {
+            histogram: SIZE_BUCKETS
+                .iter()
+                .map(|&bound| (Some(bound), 0))
+                .chain(std::iter::once((None, 0)))
+                .collect(),
+            ..Default::default()
+        };
+        let mut sizes = Vec::new();
+
+        for hash in self.list_all()? {
+            let size = self.stored_meta(&hash)?.0;
+
+            let logical_size = if self.is_encrypted() {
+                size.saturating_sub(BlobCipher::OVERHEAD as u64)
+            } else {
+                size
+            };
+
+            stats.blob_count += 1;
+            stats.logical_bytes += logical_size;
+            stats.stored_bytes += size;
+
+            let bucket = SIZE_BUCKETS
+                .iter()
+                .position(|&bound| size < bound)
+                .unwrap_or(SIZE_BUCKETS.len());
+            stats.histogram[bucket].1 += 1;
+
+            sizes.push((hash, logical_size));
+        }
+
+        sizes.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+        sizes.truncate(LARGEST_BLOBS);
+        stats.largest = sizes;
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cas_stats() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+
+        cas.put(b"small").unwrap();
+        let big_hash = cas.put(&vec![7u8; 2048]).unwrap();
+
+        let stats = cas.stats().unwrap();
+        assert_eq!(stats.blob_count, 2);
+        assert_eq!(stats.logical_bytes, 2048 + 5);
+        assert_eq!(stats.histogram[0], (Some(1024), 1));
+        assert_eq!(stats.histogram[1], (Some(64 * 1024), 1));
+        assert_eq!(stats.largest[0], (big_hash, 2048));
+        assert_eq!(stats.compression_ratio(), 1.0);
+    }
+}
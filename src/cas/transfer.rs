@@ -0,0 +1,172 @@
+use super::{is_valid_hash, Cas};
+use crate::common::DistbuildError;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Most bytes sent in one ReadBlob/WriteBlob message
+pub const TRANSFER_PIECE_BYTES: usize = 1024 * 1024;
+
+/// Partial uploads and downloads untouched this long are removed by GC
+pub const PARTIAL_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+const UPLOADS_DIR: &str = ".uploads";
+const DOWNLOADS_DIR: &str = ".downloads";
+
+impl Cas {
+    /// Up to `len` bytes of blob `hash` starting at `offset`, and the blob's
+    /// total size. Plain blobs are read in place; encrypted, chunked and
+    /// packed ones are assembled in memory first (chunked blobs are better
+    /// transferred chunk by chunk).
+    pub fn read_range(&self, hash: &str, offset: u64, len: usize) -> Result<(Vec<u8>, u64)> {
+        let path = self.hash_to_path(hash)?;
+        if self.cipher.is_none() && path.exists() && !self.is_chunked(hash) {
+            let mut file = fs::File::open(&path)
+                .with_context(|| format!("Failed to open {:?}", path))?;
+            let total = file.metadata()?.len();
+            file.seek(SeekFrom::Start(offset.min(total)))?;
+            let mut data = Vec::new();
+            file.take(len as u64).read_to_end(&mut data)
+                .with_context(|| format!("Failed to read from {:?}", path))?;
+            return Ok((data, total));
+        }
+
+        let data = self.get(hash)?;
+        let total = data.len() as u64;
+        let start = offset.min(total) as usize;
+        let end = start.saturating_add(len).min(data.len());
+        Ok((data[start..end].to_vec(), total))
+    }
+
+    /// Append `data` at `offset` to the partial upload of `hash` and return
+    /// how many bytes are committed. If `offset` isn't that, nothing is
+    /// written and the sender should resume from the returned offset.
+    pub fn append_upload(&self, hash: &str, offset: u64, data: &[u8]) -> Result<u64> {
+        self.check_writable()?;
+        let path = self.partial_path(UPLOADS_DIR, hash)?;
+        let committed = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if offset != committed {
+            return Ok(committed);
+        }
+
+        let mut file = fs::File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {:?}", path))?;
+        file.write_all(data)
+            .with_context(|| format!("Failed to write to {:?}", path))?;
+        Ok(committed + data.len() as u64)
+    }
+
+    /// Store a finished upload, after checking it really is blob `hash`
+    pub fn finish_upload(&self, hash: &str) -> Result<()> {
+        let path = self.partial_path(UPLOADS_DIR, hash)?;
+        self.store_partial(path, hash)
+    }
+
+    /// File a resumable download of `hash` is written to; whatever it
+    /// already holds was received by an earlier attempt
+    pub fn download_path(&self, hash: &str) -> Result<PathBuf> {
+        self.partial_path(DOWNLOADS_DIR, hash)
+    }
+
+    /// Store a finished download, after checking it really is blob `hash`
+    pub fn finish_download(&self, hash: &str) -> Result<()> {
+        let path = self.download_path(hash)?;
+        self.store_partial(path, hash)
+    }
+
+    fn partial_path(&self, dir: &str, hash: &str) -> Result<PathBuf> {
+        if !is_valid_hash(hash) {
+            anyhow::bail!("Invalid blob hash: {}", hash);
+        }
+        let dir = self.root.join(dir);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create directory {:?}", dir))?;
+        Ok(dir.join(hash))
+    }
+
+    /// Move a completed partial transfer into the CAS. The partial file is
+    /// removed either way; a corrupt one has to be sent again from scratch.
+    fn store_partial(&self, path: PathBuf, hash: &str) -> Result<()> {
+        let mut file = fs::File::open(&path)
+            .with_context(|| format!("No transfer of {} in progress", hash))?;
+        let mut writer = self.writer()?;
+        let copied = std::io::copy(&mut file, &mut writer);
+        let _ = fs::remove_file(&path);
+        copied.with_context(|| format!("Failed to read {:?}", path))?;
+
+        let received = writer.hash();
+        if received != hash {
+            return Err(DistbuildError::HashMismatch {
+                expected: hash.to_string(),
+                actual: received,
+            }
+            .into());
+        }
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Remove partial transfers abandoned for longer than `max_age`
+    pub(crate) fn expire_partials(&self, max_age: Duration) -> Result<()> {
+        for dir in [UPLOADS_DIR, DOWNLOADS_DIR] {
+            let Ok(entries) = fs::read_dir(self.root.join(dir)) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let stale = entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .is_ok_and(|modified| modified.elapsed().unwrap_or_default() > max_age);
+                if stale {
+                    let _ = fs::remove_file(entry.path());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resumed_upload() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+        let data = b"a large rlib, sent in pieces".repeat(100);
+        let hash = Cas::hash_bytes(&data);
+
+        assert_eq!(cas.append_upload(&hash, 0, &data[..1000]).unwrap(), 1000);
+        // A retried piece after a lost response, and a sender that restarted
+        // from zero, are both told where to resume
+        assert_eq!(cas.append_upload(&hash, 0, &data[..1000]).unwrap(), 1000);
+        assert_eq!(cas.append_upload(&hash, 1000, &data[1000..]).unwrap(), data.len() as u64);
+        cas.finish_upload(&hash).unwrap();
+        assert_eq!(cas.get(&hash).unwrap(), data);
+
+        let (piece, total) = cas.read_range(&hash, 1000, 10).unwrap();
+        assert_eq!(piece, &data[1000..1010]);
+        assert_eq!(total, data.len() as u64);
+    }
+
+    #[test]
+    fn test_corrupt_upload_is_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+        let hash = Cas::hash_bytes(b"expected");
+
+        cas.append_upload(&hash, 0, b"something else").unwrap();
+        assert!(cas.finish_upload(&hash).is_err());
+        assert!(!cas.exists(&hash));
+        assert!(cas.list_all().unwrap().is_empty());
+        // Starts over from scratch
+        assert_eq!(cas.append_upload(&hash, 0, b"expected").unwrap(), 8);
+    }
+}
@@ -0,0 +1,137 @@
+use super::shards::move_file;
+use super::Cas;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// What to do with blobs whose content no longer matches their hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CorruptAction {
+    /// Only report corrupt blobs
+    #[default]
+    Report,
+    /// Delete corrupt blobs
+    Delete,
+    /// Move corrupt blobs to `<root>/.quarantine/`
+    Quarantine,
+}
+
+/// Options for a scrub pass
+#[derive(Debug, Clone, Default)]
+pub struct VerifyOptions {
+    /// Limit hashing throughput to this many bytes per second (None = unlimited)
+    pub max_bytes_per_sec: Option<u64>,
+    pub action: CorruptAction,
+}
+
+/// Result of a scrub pass
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub bytes_checked: u64,
+    /// Hashes whose content didn't match (or couldn't be read)
+    pub corrupt: Vec<String>,
+}
+
+impl Cas {
+    /// Re-hash every blob and report those whose content doesn't match their hash
+    pub fn verify(&self, options: &VerifyOptions) -> Result<VerifyReport> {
+        if options.action != CorruptAction::Report {
+            self.check_writable()?;
+        }
+        let mut report = VerifyReport::default();
+        let started = Instant::now();
+
+        for hash in self.list_all()? {
+            let (intact, size) = match self.assemble(&hash) {
+                Ok(data) => (self.compute_hash(&data) == hash, data.len() as u64),
+                Err(_) => (false, 0),
+            };
+
+            report.checked += 1;
+            report.bytes_checked += size;
+
+            if !intact {
+                self.handle_corrupt(&hash, options.action)?;
+                report.corrupt.push(hash);
+            }
+
+            // Throttle so a scrub doesn't starve builds of disk bandwidth
+            if let Some(rate) = options.max_bytes_per_sec.filter(|&rate| rate > 0) {
+                let target = Duration::from_secs_f64(report.bytes_checked as f64 / rate as f64);
+                let elapsed = started.elapsed();
+                if target > elapsed {
+                    std::thread::sleep(target - elapsed);
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Directory where quarantined blobs are moved
+    pub fn quarantine_dir(&self) -> PathBuf {
+        self.root.join(".quarantine")
+    }
+
+    fn handle_corrupt(&self, hash: &str, action: CorruptAction) -> Result<()> {
+        let path = self.hash_to_path(hash)?;
+
+        match action {
+            CorruptAction::Report => {}
+            CorruptAction::Delete => {
+                self.remove(hash)?;
+            }
+            CorruptAction::Quarantine => {
+                let quarantine = self.quarantine_dir();
+                fs::create_dir_all(&quarantine)
+                    .with_context(|| format!("Failed to create directory {:?}", quarantine))?;
+                if path.exists() {
+                    move_file(&path, &quarantine.join(hash))
+                        .with_context(|| format!("Failed to quarantine {:?}", path))?;
+                } else if let Some(stored) = self.read_packed(hash)? {
+                    fs::write(quarantine.join(hash), stored)
+                        .with_context(|| format!("Failed to quarantine {}", hash))?;
+                    self.remove(hash)?;
+                }
+            }
+        }
+
+        if action != CorruptAction::Report {
+            self.blob_removed(hash);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cas_verify_quarantines_corrupt_blobs() {
+        let temp_dir = TempDir::new().unwrap();
+        let cas = Cas::new(temp_dir.path()).unwrap();
+
+        let good = cas.put(b"intact blob").unwrap();
+        let bad = cas.put(b"soon to rot").unwrap();
+        fs::write(cas.get_path(&bad).unwrap(), b"bit rot").unwrap();
+
+        let options = VerifyOptions {
+            action: CorruptAction::Quarantine,
+            ..Default::default()
+        };
+        let report = cas.verify(&options).unwrap();
+
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.corrupt, vec![bad.clone()]);
+        assert!(cas.exists(&good));
+        assert!(!cas.exists(&bad));
+        assert!(cas.quarantine_dir().join(&bad).exists());
+
+        // Quarantined blobs are no longer part of the store
+        assert_eq!(cas.verify(&options).unwrap().checked, 1);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::common::config::{SchedulerConfig, TlsConfig};
+use crate::proto::distbuild::blob_store_client::BlobStoreClient;
+use crate::proto::distbuild::scheduler_client::SchedulerClient;
+use crate::proto::distbuild::worker_client::WorkerClient;
+use anyhow::{Context, Result};
+use log::warn;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::metadata::MetadataValue;
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity, ServerTlsConfig};
+use tonic::{Request, Status};
+
+const AUTHORIZATION: &str = "authorization";
+/// How long to wait on each scheduler before trying the next
+const FAILOVER_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// A scheduler that couldn't be reached is passed over this long
+const FAILOVER_SKIP: Duration = Duration::from_secs(30);
+
+/// Scheduler client sending the configured token with every request
+pub type AuthedSchedulerClient = SchedulerClient<InterceptedService<Channel, ClientAuth>>;
+/// Worker and blob store clients, likewise: a worker's server and the
+/// scheduler's blob store check the same token
+pub type AuthedWorkerClient = WorkerClient<InterceptedService<Channel, ClientAuth>>;
+pub type AuthedBlobStoreClient = BlobStoreClient<InterceptedService<Channel, ClientAuth>>;
+
+/// How this node proves itself when calling others: `[scheduler]
+/// auth_token` on scheduler requests, and its certificate on every
+/// connection when `[scheduler.tls]` is set
+#[derive(Clone, Default)]
+pub struct ClientAuth {
+    token: Option<Arc<str>>,
+    tls: Option<ClientTlsConfig>,
+    unreachable: Arc<Mutex<HashMap<String, Instant>>>, // scheduler URL -> when it last failed
+}
+
+impl ClientAuth {
+    pub fn new(token: Option<&str>) -> Self {
+        ClientAuth {
+            token: token.filter(|t| !t.is_empty()).map(Arc::from),
+            tls: None,
+            unreachable: Arc::default(),
+        }
+    }
+
+    /// Fails if the TLS certificates or key can't be read
+    pub fn from_config(config: &SchedulerConfig) -> Result<Self> {
+        let auth = Self::new(config.auth_token.as_deref());
+        match &config.tls {
+            Some(tls) => auth.with_tls(tls),
+            None => Ok(auth),
+        }
+    }
+
+    /// Connect over TLS, presenting this node's certificate
+    pub fn with_tls(mut self, config: &TlsConfig) -> Result<Self> {
+        let (ca, identity) = load_tls(config)?;
+        let mut tls = ClientTlsConfig::new().ca_certificate(ca).identity(identity);
+        if let Some(domain) = &config.domain {
+            tls = tls.domain_name(domain);
+        }
+        self.tls = Some(tls);
+        Ok(self)
+    }
+
+    /// Endpoint for `url` (e.g. `http://host:5000`), switched to https
+    /// when TLS is configured
+    pub fn endpoint(&self, url: String) -> Result<Endpoint, tonic::transport::Error> {
+        let Some(tls) = &self.tls else {
+            return Endpoint::from_shared(url);
+        };
+        let url = match url.strip_prefix("http://") {
+            Some(rest) => format!("https://{}", rest),
+            None => url,
+        };
+        Endpoint::from_shared(url)?.tls_config(tls.clone())
+    }
+
+    /// Token sent to schedulers and peers, for callers outside gRPC
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    pub async fn channel(&self, url: String) -> Result<Channel, tonic::transport::Error> {
+        self.endpoint(url)?.connect().await
+    }
+
+    /// Connect to the scheduler at `addr` (a URL, e.g. `http://host:5000`)
+    pub async fn connect_scheduler(&self, addr: String) -> Result<AuthedSchedulerClient, tonic::transport::Error> {
+        let channel = self.channel(addr).await?;
+        Ok(SchedulerClient::with_interceptor(channel, self.clone()))
+    }
+
+    /// Connect to the first of `urls` (at least one; see
+    /// `SchedulerConfig::urls`) that answers, so a standby takes over
+    /// from a primary that went away. Ones that recently failed are tried
+    /// last, sparing every call a wait on a scheduler that's down.
+    pub async fn connect_any(&self, urls: &[String]) -> Result<AuthedSchedulerClient, tonic::transport::Error> {
+        let mut urls: Vec<&String> = urls.iter().collect();
+        if urls.len() == 1 {
+            return self.connect_scheduler(urls[0].clone()).await;
+        }
+        {
+            let mut unreachable = self.unreachable.lock().unwrap();
+            unreachable.retain(|_, failed| failed.elapsed() < FAILOVER_SKIP);
+            urls.sort_by_key(|url| unreachable.contains_key(*url));
+        }
+
+        let mut last_error = None;
+        for url in urls {
+            match self.endpoint(url.clone())?.connect_timeout(FAILOVER_CONNECT_TIMEOUT).connect().await {
+                Ok(channel) => {
+                    self.unreachable.lock().unwrap().remove(url);
+                    return Ok(SchedulerClient::with_interceptor(channel, self.clone()));
+                }
+                Err(e) => {
+                    warn!("⚠️  Scheduler {} unreachable: {}", url, e);
+                    self.unreachable.lock().unwrap().insert(url.clone(), Instant::now());
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.expect("tried at least one scheduler"))
+    }
+}
+
+impl std::fmt::Debug for ClientAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let token = self.token.as_ref().map(|_| "<redacted>");
+        f.debug_struct("ClientAuth")
+            .field("token", &token)
+            .field("tls", &self.tls.is_some())
+            .finish()
+    }
+}
+
+impl Interceptor for ClientAuth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.token {
+            let value = MetadataValue::try_from(format!("Bearer {}", token))
+                .map_err(|_| Status::invalid_argument("auth_token must be printable ASCII"))?;
+            request.metadata_mut().insert(AUTHORIZATION, value);
+        }
+        Ok(request)
+    }
+}
+
+/// Who a scheduler request came from, attached to it by the
+/// `Authenticator`
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    /// Whose token it carried (`shared`, an `auth_tokens` identity or a
+    /// tenant's name); `None` when auth is off
+    pub identity: Option<String>,
+    /// Tenant whose token it carried, confining it to that tenant's jobs
+    /// and workers; `None` for operators (and everyone when auth is off)
+    pub tenant: Option<String>,
+}
+
+impl Caller {
+    /// The caller of `request` (an operator if none was attached)
+    pub fn of<T>(request: &Request<T>) -> Self {
+        request.extensions().get::<Caller>().cloned().unwrap_or_default()
+    }
+}
+
+/// Rejects scheduler (and blob store, worker and blob HTTP) requests
+/// without an accepted token: `auth_token`,
+/// any of `auth_tokens` or a tenant's. With none configured every request
+/// is let in.
+#[derive(Clone)]
+pub struct Authenticator {
+    /// (identity, token, tenant it acts as)
+    tokens: Arc<Vec<(String, String, Option<String>)>>,
+}
+
+impl Authenticator {
+    pub fn from_config(config: &SchedulerConfig) -> Self {
+        let shared = config.auth_token.iter().map(|token| ("shared".to_string(), token.clone(), None));
+        let operators = config.auth_tokens.iter().map(|(identity, token)| (identity.clone(), token.clone(), None));
+        let tenants = config
+            .tenants
+            .iter()
+            .filter_map(|(name, tenant)| Some((name.clone(), tenant.token.clone()?, Some(name.clone()))));
+        let tokens = shared
+            .chain(operators)
+            .chain(tenants)
+            .filter(|(_, token, _)| !token.is_empty())
+            .collect();
+        Authenticator { tokens: Arc::new(tokens) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Whether an HTTP `Authorization` header carries an accepted token
+    /// (any request does when auth is off)
+    pub fn accepts(&self, authorization: Option<&str>) -> bool {
+        !self.enabled()
+            || authorization
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .and_then(|token| self.identify(token))
+                .is_some()
+    }
+
+    /// Who `token` belongs to, if anyone, and the tenant it acts as
+    fn identify(&self, token: &str) -> Option<(&str, Option<&String>)> {
+        self.tokens
+            .iter()
+            .find(|(_, accepted, _)| constant_time_eq(accepted.as_bytes(), token.as_bytes()))
+            .map(|(identity, _, tenant)| (identity.as_str(), tenant.as_ref()))
+    }
+}
+
+impl Interceptor for Authenticator {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if !self.enabled() {
+            return Ok(request);
+        }
+        let token = request
+            .metadata()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match token.and_then(|token| self.identify(token)) {
+            Some((identity, tenant)) => {
+                let caller = Caller {
+                    identity: Some(identity.to_string()),
+                    tenant: tenant.cloned(),
+                };
+                request.extensions_mut().insert(caller);
+                Ok(request)
+            }
+            None => {
+                let peer = request.remote_addr().map(|a| a.to_string()).unwrap_or_else(|| "unknown".to_string());
+                warn!("🔒 Rejected request from {}: {}", peer, if token.is_some() { "bad token" } else { "no token" });
+                Err(Status::unauthenticated("Missing or invalid auth token (set [scheduler] auth_token)"))
+            }
+        }
+    }
+}
+
+/// Compare without leaking how long a matching prefix is
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Server side of `[scheduler.tls]`: present this node's certificate and
+/// only accept clients with one signed by the CA
+pub fn server_tls(config: &TlsConfig) -> Result<ServerTlsConfig> {
+    let (ca, identity) = load_tls(config)?;
+    Ok(ServerTlsConfig::new().identity(identity).client_ca_root(ca))
+}
+
+fn load_tls(config: &TlsConfig) -> Result<(Certificate, Identity)> {
+    let read = |path: &str, what: &str| fs::read(path).with_context(|| format!("Failed to read TLS {} {:?}", what, path));
+    let ca = Certificate::from_pem(read(&config.ca_cert, "CA certificate")?);
+    let identity = Identity::from_pem(read(&config.cert, "certificate")?, read(&config.key, "key")?);
+    Ok((ca, identity))
+}
@@ -0,0 +1,61 @@
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Clock differences above this are reported by `doctor`
+pub const MAX_CLOCK_SKEW_MS: i64 = 2000;
+
+/// Wall-clock anchor taken once per process
+struct Anchor {
+    unix_ms: i64,
+    instant: Instant,
+}
+
+static ANCHOR: OnceLock<Anchor> = OnceLock::new();
+
+/// Current unix time in milliseconds. Read from the system clock once, then
+/// advanced by the monotonic clock, so timestamps from one process never go
+/// backwards (and durations between them are never negative) even if NTP
+/// steps the system clock.
+pub fn now_ms() -> i64 {
+    let anchor = ANCHOR.get_or_init(|| Anchor {
+        unix_ms: chrono::Utc::now().timestamp_millis(),
+        instant: Instant::now(),
+    });
+    anchor.unix_ms + anchor.instant.elapsed().as_millis() as i64
+}
+
+/// Current unix time in seconds (see `now_ms`)
+pub fn now() -> i64 {
+    now_ms() / 1000
+}
+
+/// Estimate how far a remote clock is ahead of ours from a request sent at
+/// `sent_ms` and answered at `received_ms` (both local) carrying the remote
+/// time `remote_ms`. Returns (skew, uncertainty) in milliseconds; the remote
+/// read its clock somewhere within the round trip.
+pub fn estimate_skew(sent_ms: i64, remote_ms: i64, received_ms: i64) -> (i64, i64) {
+    let round_trip = (received_ms - sent_ms).max(0);
+    let midpoint = sent_ms + round_trip / 2;
+    (remote_ms - midpoint, round_trip / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_is_monotonic() {
+        let first = now_ms();
+        let second = now_ms();
+        assert!(second >= first);
+        assert!((first - chrono::Utc::now().timestamp_millis()).abs() < 1000);
+    }
+
+    #[test]
+    fn test_estimate_skew() {
+        // Remote 5s ahead, 100ms round trip
+        assert_eq!(estimate_skew(1_000, 6_050, 1_100), (5_000, 50));
+        // Remote behind
+        assert_eq!(estimate_skew(10_000, 7_000, 10_000), (-3_000, 0));
+    }
+}
@@ -1,63 +1,1126 @@
+use crate::common::logging::LoggingConfig;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Sections not needed by the loading role fall back to their defaults
+/// (see `Config::load_for`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub scheduler: SchedulerConfig,
+    #[serde(default)]
     pub cas: CasConfig,
+    #[serde(default)]
     pub worker: WorkerConfig,
+    #[serde(default)]
+    pub wrapper: WrapperConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+}
+
+/// Which binary/command is loading the config, deciding the sections it must have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Scheduler,
+    Worker,
+    Wrapper,
+    /// CLI and REPL commands talking to a running cluster
+    Client,
+}
+
+impl Role {
+    /// Sections that must be present in a config file for this role
+    pub fn required_sections(&self) -> &'static [&'static str] {
+        match self {
+            Role::Scheduler => &["scheduler", "cas"],
+            Role::Worker => &["scheduler", "cas", "worker"],
+            Role::Wrapper => &["scheduler"],
+            Role::Client => &["scheduler"],
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Role::Scheduler => "scheduler",
+            Role::Worker => "worker",
+            Role::Wrapper => "wrapper",
+            Role::Client => "client",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SchedulerConfig {
     pub addr: String,
+    /// More schedulers workers, wrappers and the CLI fall back to, in
+    /// order, when `addr` doesn't answer (e.g. a hot standby)
+    #[serde(default)]
+    pub fallback_addrs: Vec<String>,
+    /// Heartbeat interval pushed to all workers, overriding their own config
+    #[serde(default)]
+    pub heartbeat_interval_secs: Option<u64>,
+    /// Serve CAS blobs over plain HTTP on this address (for CDNs/caches);
+    /// requests need a token like the gRPC services when auth is on
+    #[serde(default)]
+    pub http_addr: Option<String>,
+    /// Serve a web dashboard of workers, jobs and the queue on this address
+    #[serde(default)]
+    pub dashboard_addr: Option<String>,
+    /// Persist jobs and workers here so they survive scheduler restarts
+    /// (in memory only when unset)
+    #[serde(default)]
+    pub state_dir: Option<String>,
+    /// Run as a read-only mirror of the primary scheduler whose `state_dir`
+    /// this is (shared storage), serving read RPCs for reporting and UIs
+    #[serde(default)]
+    pub mirror_of: Option<String>,
+    /// Run as a hot standby for the primary scheduler sharing `state_dir`,
+    /// following its journal and taking over when it stops answering
+    #[serde(default)]
+    pub standby: Option<StandbyConfig>,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub quarantine: QuarantineConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub validation: ValidationConfig,
+    #[serde(default)]
+    pub weights: WeightConfig,
+    /// Endpoints told about finished jobs and build sessions
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    /// A build session counts as finished once none of its jobs has been
+    /// queued or running for this long
+    #[serde(default = "default_session_idle_secs")]
+    pub session_idle_secs: u64,
+    /// How queued jobs are spread over workers with spare capacity
+    #[serde(default)]
+    pub strategy: StrategyKind,
+    /// Dispatched jobs running longer are reaped (failed or retried) unless
+    /// they set their own timeout; 0 = no limit
+    #[serde(default = "default_job_timeout_secs")]
+    pub job_timeout_secs: u64,
+    /// Workers silent this long get no new jobs and are shown offline
+    #[serde(default = "default_worker_timeout_secs")]
+    pub worker_timeout_secs: u64,
+    /// Further silence tolerated before `offline_action` is taken, e.g. to
+    /// ride out a short network blip without losing running jobs
+    #[serde(default)]
+    pub offline_grace_secs: u64,
+    /// What becomes of a worker once it's been offline past the grace period
+    #[serde(default)]
+    pub offline_action: OfflineAction,
+    /// Offline workers stay listed this long before `offline_action =
+    /// "remove"` forgets them; 0 = forgotten straight away
+    #[serde(default = "default_offline_retention_secs")]
+    pub offline_retention_secs: u64,
+    /// Submissions that would queue beyond this many pending jobs are
+    /// rejected, telling the client when to retry; 0 = no limit
+    #[serde(default)]
+    pub max_pending_jobs: usize,
+    /// Pending jobs gain a priority level for every this many seconds they
+    /// wait, so low-priority or hard-to-place ones eventually go first; 0 =
+    /// strict priority order
+    #[serde(default = "default_aging_secs")]
+    pub aging_secs: u64,
+    /// Finished jobs are forgotten this long after finishing; 0 = kept
+    /// until `max_finished_jobs` pushes them out
+    #[serde(default)]
+    pub job_retention_secs: u64,
+    /// Most finished jobs kept, the longest finished forgotten first; 0 =
+    /// no limit
+    #[serde(default)]
+    pub max_finished_jobs: usize,
+    /// Append forgotten jobs to `jobs-archive.jsonl` in `state_dir` first
+    #[serde(default)]
+    pub archive_jobs: bool,
+    /// Finished jobs stay searchable in the job history this long after
+    /// finishing, even once the jobs themselves are forgotten; 0 = for good
+    #[serde(default = "default_history_retention_secs")]
+    pub history_retention_secs: u64,
+    /// On SIGTERM/SIGINT, wait up to this long for running jobs to finish
+    /// before saving state and exiting; 0 = don't wait
+    #[serde(default = "default_shutdown_drain_secs")]
+    pub shutdown_drain_secs: u64,
+    /// Token workers and clients send with every scheduler request; the
+    /// scheduler rejects requests without an accepted one when it's set
+    #[serde(default)]
+    pub auth_token: Option<String>,
+    /// More tokens the scheduler accepts, by identity (e.g. one per team
+    /// or CI system, so each can be revoked on its own)
+    #[serde(default)]
+    pub auth_tokens: HashMap<String, String>,
+    /// Teams sharing this scheduler, by name, each confined to its own
+    /// jobs and workers
+    #[serde(default)]
+    pub tenants: HashMap<String, TenantConfig>,
+    /// Encrypt and mutually authenticate every gRPC connection
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// `[scheduler.tenants.<name>]`: a team sharing the scheduler. Requests
+/// with its token act as the tenant: its jobs are only listed, inspected
+/// or changed by it (and operators), and run on its own workers or shared
+/// ones. Its workers only run its jobs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TenantConfig {
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Most of its jobs waiting to be dispatched at once; 0 = no limit
+    #[serde(default)]
+    pub max_pending_jobs: usize,
+    /// Most of its jobs dispatched or running at once; 0 = no limit
+    #[serde(default)]
+    pub max_running_jobs: usize,
+}
+
+/// `[scheduler.standby]`: this scheduler mirrors the primary writing to
+/// the same `state_dir` (on shared storage) and takes its place once it
+/// has been unreachable for `takeover_after_secs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StandbyConfig {
+    /// The primary's address, pinged to tell whether it's still up
+    pub primary: String,
+    #[serde(default = "default_takeover_after_secs")]
+    pub takeover_after_secs: u64,
+}
+
+/// `[scheduler.tls]`: TLS on every gRPC connection in the cluster, with
+/// both ends presenting certificates signed by `ca_cert`. All nodes,
+/// wrappers and CLI users need one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// CA certificate (PEM) every node's certificate is signed by
+    pub ca_cert: String,
+    /// This node's certificate chain (PEM), presented as server and client
+    pub cert: String,
+    /// Private key (PEM) for `cert`
+    pub key: String,
+    /// Name servers' certificates are checked against, instead of the host
+    /// being dialed
+    #[serde(default)]
+    pub domain: Option<String>,
+}
+
+/// `[scheduler] strategy`: which worker a queued job goes to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrategyKind {
+    /// The worker using the smallest share of its capacity
+    LeastLoaded,
+    /// Each worker in turn
+    #[default]
+    RoundRobin,
+    Random,
+}
+
+/// `[scheduler] offline_action`: what happens to a worker that stopped
+/// heartbeating
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OfflineAction {
+    /// Keep it, listed as offline, and leave its jobs to the job timeout;
+    /// it carries on if it comes back
+    MarkOffline,
+    /// Queue its jobs again, and forget it once it's been offline for
+    /// `offline_retention_secs`; it re-registers if it comes back
+    #[default]
+    Remove,
+    /// Keep it, listed as offline, but queue its jobs again elsewhere
+    RequeueJobs,
+}
+
+/// `[scheduler.retry] max_attempts = 5`. A failed job whose error is in
+/// `retry_on` is queued again after a backoff, until it has been tried
+/// `max_attempts` times.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Including the first; 1 = never retry
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled on each further one up to `max_backoff_secs`
+    #[serde(default = "default_retry_initial_backoff_secs")]
+    pub initial_backoff_secs: u64,
+    #[serde(default = "default_retry_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<RetryClass>,
+    /// Failed dispatches after which a job is no longer simply reassigned
+    /// to another worker, but retried (or failed) as above; 1 = at once
+    #[serde(default = "default_retry_dispatch_attempts")]
+    pub dispatch_attempts: u32,
+}
+
+/// `[scheduler.quarantine]`: a worker failing job after job (say, with a
+/// broken toolchain) gets no work for `duration_secs`. Then it's on
+/// probation, one job at a time: a success puts it back in rotation, a
+/// failure quarantines it again for twice as long, up to
+/// `max_duration_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineConfig {
+    /// Counted failures in a row that quarantine a worker; 0 = never
+    #[serde(default = "default_quarantine_consecutive_failures")]
+    pub consecutive_failures: u32,
+    /// Counted failures within `window_secs` that quarantine a worker,
+    /// successes in between or not; 0 = never
+    #[serde(default = "default_quarantine_window_failures")]
+    pub window_failures: u32,
+    #[serde(default = "default_quarantine_window_secs")]
+    pub window_secs: u64,
+    #[serde(default = "default_quarantine_duration_secs")]
+    pub duration_secs: u64,
+    #[serde(default = "default_quarantine_max_duration_secs")]
+    pub max_duration_secs: u64,
+    /// Kinds of failure held against the worker
+    #[serde(default = "default_quarantine_count_on")]
+    pub count_on: Vec<RetryClass>,
+}
+
+impl QuarantineConfig {
+    /// How long quarantine number `strike` (1-based) lasts
+    pub fn duration_secs(&self, strike: u32) -> u64 {
+        self.duration_secs
+            .saturating_mul(2u64.saturating_pow(strike.saturating_sub(1)))
+            .min(self.max_duration_secs)
+    }
+}
+
+impl Default for QuarantineConfig {
+    fn default() -> Self {
+        QuarantineConfig {
+            consecutive_failures: default_quarantine_consecutive_failures(),
+            window_failures: default_quarantine_window_failures(),
+            window_secs: default_quarantine_window_secs(),
+            duration_secs: default_quarantine_duration_secs(),
+            max_duration_secs: default_quarantine_max_duration_secs(),
+            count_on: default_quarantine_count_on(),
+        }
+    }
+}
+
+fn default_quarantine_consecutive_failures() -> u32 {
+    5
+}
+
+fn default_quarantine_window_failures() -> u32 {
+    10
+}
+
+fn default_quarantine_window_secs() -> u64 {
+    600
+}
+
+fn default_quarantine_duration_secs() -> u64 {
+    300
+}
+
+fn default_quarantine_max_duration_secs() -> u64 {
+    3600
+}
+
+fn default_quarantine_count_on() -> Vec<RetryClass> {
+    vec![RetryClass::Dispatch, RetryClass::Worker]
+}
+
+/// `[scheduler.health]`: each worker is scored from 0 to 1 by how many of
+/// its recent jobs failed (of the kinds quarantine counts), how long
+/// dispatches to it take and how regularly its heartbeats arrive. Workers
+/// scoring below `unhealthy_below` only get jobs no healthier worker can
+/// take, so a worker going bad is eased off before it's quarantined.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthConfig {
+    /// Job outcomes older than this no longer count
+    #[serde(default = "default_health_window_secs")]
+    pub window_secs: u64,
+    /// Dispatches taking longer than this lower the score in proportion
+    #[serde(default = "default_health_slow_dispatch_ms")]
+    pub slow_dispatch_ms: u64,
+    /// 0 = scores are only reported, not acted on
+    #[serde(default = "default_health_unhealthy_below")]
+    pub unhealthy_below: f64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            window_secs: default_health_window_secs(),
+            slow_dispatch_ms: default_health_slow_dispatch_ms(),
+            unhealthy_below: default_health_unhealthy_below(),
+        }
+    }
+}
+
+fn default_health_window_secs() -> u64 {
+    600
+}
+
+fn default_health_slow_dispatch_ms() -> u64 {
+    500
+}
+
+fn default_health_unhealthy_below() -> f64 {
+    0.5
+}
+
+/// `[scheduler.validation]`: what a submitted job must look like to be
+/// queued. Its input hash must be a well-formed hash either way; jobs
+/// failing a check are rejected with the reason, so the client can tell
+/// whether anything would get them accepted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidationConfig {
+    /// Job types accepted (e.g. `rust-compile`); empty = any
+    #[serde(default)]
+    pub job_types: Vec<String>,
+    /// Largest input accepted, as the client declares it; 0 = no limit
+    #[serde(default)]
+    pub max_input_bytes: u64,
+    /// Reject jobs whose input isn't in the CAS the scheduler serves
+    #[serde(default)]
+    pub require_input: bool,
+}
+
+/// `[scheduler.weights]`: a job takes up a worker slot for every
+/// `slot_secs` its crate's recent runs took (rounded up), or for a crate
+/// not built yet, for every `slot_bytes` of input, up to `max_weight`
+/// slots. Workers only get jobs that fit in their free slots, so a heavy
+/// crate doesn't land on a worker that's nearly full.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeightConfig {
+    /// 0 = don't weigh by past run times
+    #[serde(default = "default_weight_slot_secs")]
+    pub slot_secs: u64,
+    /// 0 = don't weigh by input size
+    #[serde(default = "default_weight_slot_bytes")]
+    pub slot_bytes: u64,
+    /// 1 = every job takes one slot
+    #[serde(default = "default_weight_max_weight")]
+    pub max_weight: u32,
+}
+
+impl Default for WeightConfig {
+    fn default() -> Self {
+        WeightConfig {
+            slot_secs: default_weight_slot_secs(),
+            slot_bytes: default_weight_slot_bytes(),
+            max_weight: default_weight_max_weight(),
+        }
+    }
+}
+
+fn default_weight_slot_secs() -> u64 {
+    60
+}
+
+fn default_weight_slot_bytes() -> u64 {
+    4 * 1024 * 1024
+}
+
+fn default_weight_max_weight() -> u32 {
+    4
+}
+
+/// `[[scheduler.webhooks]]`: a URL POSTed a JSON payload when a job
+/// completes or fails, or a build session finishes. Deliveries that fail
+/// or get a non-2xx answer are retried with backoff.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Sign each payload with this key, sending the hex HMAC-SHA256 of the
+    /// body as `X-Distbuild-Signature: sha256=<hex>`
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Events sent; empty = all
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    /// Tries per delivery before it's given up on
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: u32,
+}
+
+impl WebhookConfig {
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    JobCompleted,
+    JobFailed,
+    SessionFinished,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::JobCompleted => "job_completed",
+            WebhookEvent::JobFailed => "job_failed",
+            WebhookEvent::SessionFinished => "session_finished",
+        }
+    }
+}
+
+fn default_webhook_max_attempts() -> u32 {
+    5
+}
+
+fn default_offline_retention_secs() -> u64 {
+    3600
+}
+
+fn default_history_retention_secs() -> u64 {
+    30 * 24 * 3600
+}
+
+fn default_session_idle_secs() -> u64 {
+    30
+}
+
+/// Kinds of job failure a retry policy can choose to retry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryClass {
+    /// The job couldn't be handed to its worker
+    Dispatch,
+    /// The worker failed the job for a reason other than those below
+    Worker,
+    /// The job's CAS namespace is full
+    Quota,
+    /// The build itself failed; the same inputs will fail again
+    Compile,
+    /// The job ran past its deadline or stopped showing signs of life
+    Timeout,
+}
+
+impl RetryConfig {
+    /// Wait before retry number `attempt` (1-based)
+    pub fn backoff_secs(&self, attempt: u32) -> u64 {
+        self.initial_backoff_secs
+            .saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff_secs)
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: default_retry_max_attempts(),
+            initial_backoff_secs: default_retry_initial_backoff_secs(),
+            max_backoff_secs: default_retry_max_backoff_secs(),
+            retry_on: default_retry_on(),
+            dispatch_attempts: default_retry_dispatch_attempts(),
+        }
+    }
+}
+
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_initial_backoff_secs() -> u64 {
+    2
+}
+
+fn default_retry_max_backoff_secs() -> u64 {
+    60
+}
+
+fn default_retry_on() -> Vec<RetryClass> {
+    vec![RetryClass::Dispatch, RetryClass::Worker, RetryClass::Timeout]
+}
+
+fn default_retry_dispatch_attempts() -> u32 {
+    3
+}
+
+fn default_job_timeout_secs() -> u64 {
+    3600
+}
+
+fn default_worker_timeout_secs() -> u64 {
+    10
+}
+
+fn default_shutdown_drain_secs() -> u64 {
+    30
+}
+
+fn default_aging_secs() -> u64 {
+    300
+}
+
+fn default_takeover_after_secs() -> u64 {
+    15
+}
+
+impl Default for CasConfig {
+    fn default() -> Self {
+        CasConfig {
+            root: "./cas-root".to_string(),
+            encryption_key_file: None,
+            prefetch_parallelism: default_prefetch_parallelism(),
+            namespace: None,
+            index: false,
+            index_reconcile_secs: default_index_reconcile_secs(),
+            replication: ReplicationConfig::default(),
+            remote: RemoteConfig::default(),
+            quotas: HashMap::new(),
+            shards: Vec::new(),
+            chunking: ChunkingConfig::default(),
+            packing: PackingConfig::default(),
+            hot_cache: HotCacheConfig::default(),
+            blob_metadata: false,
+            retention: Vec::new(),
+            read_only: false,
+            mirror: None,
+        }
+    }
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        WorkerConfig {
+            heartbeat_interval_secs: 10,
+            capacity: 4,
+            sandbox_root: None,
+            sandbox_pool_size: default_sandbox_pool_size(),
+            sandbox_paranoid_wipe: false,
+            labels: HashMap::new(),
+            tenant: None,
+            job_types: Vec::new(),
+            pool: None,
+        }
+    }
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig {
+            addr: "127.0.0.1:5000".to_string(),
+            fallback_addrs: Vec::new(),
+            heartbeat_interval_secs: None,
+            http_addr: None,
+            dashboard_addr: None,
+            state_dir: None,
+            mirror_of: None,
+            standby: None,
+            retry: RetryConfig::default(),
+            quarantine: QuarantineConfig::default(),
+            health: HealthConfig::default(),
+            validation: ValidationConfig::default(),
+            weights: WeightConfig::default(),
+            webhooks: Vec::new(),
+            session_idle_secs: default_session_idle_secs(),
+            strategy: StrategyKind::default(),
+            job_timeout_secs: default_job_timeout_secs(),
+            worker_timeout_secs: default_worker_timeout_secs(),
+            offline_grace_secs: 0,
+            offline_action: OfflineAction::default(),
+            offline_retention_secs: default_offline_retention_secs(),
+            max_pending_jobs: 0,
+            aging_secs: default_aging_secs(),
+            job_retention_secs: 0,
+            max_finished_jobs: 0,
+            archive_jobs: false,
+            history_retention_secs: default_history_retention_secs(),
+            shutdown_drain_secs: default_shutdown_drain_secs(),
+            auth_token: None,
+            auth_tokens: HashMap::new(),
+            tenants: HashMap::new(),
+            tls: None,
+        }
+    }
+}
+
+impl SchedulerConfig {
+    /// URLs of every scheduler to try, `addr` first
+    pub fn urls(&self) -> Vec<String> {
+        std::iter::once(&self.addr)
+            .chain(&self.fallback_addrs)
+            .map(|addr| format!("http://{}", addr))
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CasConfig {
     pub root: String,
+    /// File holding a hex-encoded 32-byte AES-256-GCM key; when set, blob
+    /// contents are encrypted at rest (hashes are still over plaintext)
+    #[serde(default)]
+    pub encryption_key_file: Option<String>,
+    /// Max concurrent blob reads when prefetching job inputs
+    #[serde(default = "default_prefetch_parallelism")]
+    pub prefetch_parallelism: usize,
+    /// Project namespace for blobs written by this client; unset uses the
+    /// shared default space
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// Keep an in-memory index of known hashes in the scheduler and workers
+    /// so existence checks skip the (possibly NFS) filesystem
+    #[serde(default)]
+    pub index: bool,
+    /// How often the index is reconciled against disk
+    #[serde(default = "default_index_reconcile_secs")]
+    pub index_reconcile_secs: u64,
+    #[serde(default)]
+    pub replication: ReplicationConfig,
+    #[serde(default)]
+    pub remote: RemoteConfig,
+    /// Max bytes each namespace may store, e.g. `[cas.quotas] project-a = 10737418240`
+    #[serde(default)]
+    pub quotas: HashMap<String, u64>,
+    /// Extra roots for loose blobs by hash prefix, e.g. one per disk
+    #[serde(default)]
+    pub shards: Vec<ShardConfig>,
+    #[serde(default)]
+    pub chunking: ChunkingConfig,
+    #[serde(default)]
+    pub packing: PackingConfig,
+    #[serde(default)]
+    pub hot_cache: HotCacheConfig,
+    /// Keep a small sidecar per blob with its creation/last-access times
+    /// and originating job, shown by `cas inspect`
+    #[serde(default)]
+    pub blob_metadata: bool,
+    /// How long job outputs are kept by GC; the first matching rule wins
+    #[serde(default)]
+    pub retention: Vec<RetentionRule>,
+    /// Refuse puts, removes and GC (e.g. when `root` is a shared golden CAS)
+    #[serde(default)]
+    pub read_only: bool,
+    /// Read-only CAS consulted for blobs missing under `root`, which then
+    /// acts as a writable overlay
+    #[serde(default)]
+    pub mirror: Option<MirrorConfig>,
+}
+
+/// e.g. `[cas.mirror] root = "/mnt/golden-cas"`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorConfig {
+    pub root: String,
+    /// Key the mirror's blobs are encrypted with, if any
+    #[serde(default)]
+    pub encryption_key_file: Option<String>,
+}
+
+/// e.g. `[[cas.retention]] profile = "release"  keep_days = 90`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionRule {
+    /// Job type to match (any if unset)
+    #[serde(default)]
+    pub job_type: Option<String>,
+    /// Cargo profile to match (any if unset)
+    #[serde(default)]
+    pub profile: Option<String>,
+    pub keep_days: u64,
+}
+
+/// Storing large blobs as content-defined chunks, so similar artifacts
+/// (e.g. successive builds of an rlib) share storage and transfers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Target chunk size (rounded up to a power of two)
+    #[serde(default = "default_avg_chunk_kib")]
+    pub avg_chunk_kib: usize,
+    /// Blobs smaller than this are stored whole
+    #[serde(default = "default_min_blob_kib")]
+    pub min_blob_kib: usize,
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        ChunkingConfig {
+            enabled: false,
+            avg_chunk_kib: default_avg_chunk_kib(),
+            min_blob_kib: default_min_blob_kib(),
+        }
+    }
+}
+
+fn default_avg_chunk_kib() -> usize {
+    64
+}
+
+fn default_min_blob_kib() -> usize {
+    1024
+}
+
+/// `[[cas.shards]] prefix_range = "80-ff", path = "/mnt/disk2/cas"`: loose
+/// blobs whose hash starts with a byte in the (inclusive, hex) range are
+/// stored under `path` instead of the CAS root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardConfig {
+    pub prefix_range: String,
+    pub path: String,
+}
+
+/// Periodic compaction of small blobs into packfiles by the scheduler and
+/// workers, so the CAS isn't millions of tiny files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Blobs up to this size are packed
+    #[serde(default = "default_pack_max_blob_kib")]
+    pub max_blob_kib: u64,
+    /// Only blobs untouched for this long are packed
+    #[serde(default = "default_pack_min_age_secs")]
+    pub min_age_secs: u64,
+    #[serde(default = "default_pack_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Default for PackingConfig {
+    fn default() -> Self {
+        PackingConfig {
+            enabled: false,
+            max_blob_kib: default_pack_max_blob_kib(),
+            min_age_secs: default_pack_min_age_secs(),
+            interval_secs: default_pack_interval_secs(),
+        }
+    }
+}
+
+/// In-memory LRU of small blobs in front of the disk CAS, e.g.
+/// `[cas.hot_cache] max_mib = 256`; disabled when `max_mib` is 0
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HotCacheConfig {
+    #[serde(default)]
+    pub max_mib: u64,
+    /// Only blobs up to this size are cached
+    #[serde(default = "default_hot_cache_max_blob_kib")]
+    pub max_blob_kib: u64,
+}
+
+impl Default for HotCacheConfig {
+    fn default() -> Self {
+        HotCacheConfig {
+            max_mib: 0,
+            max_blob_kib: default_hot_cache_max_blob_kib(),
+        }
+    }
+}
+
+fn default_hot_cache_max_blob_kib() -> u64 {
+    64
+}
+
+fn default_pack_max_blob_kib() -> u64 {
+    16
+}
+
+fn default_pack_min_age_secs() -> u64 {
+    600
+}
+
+fn default_pack_interval_secs() -> u64 {
+    3600
+}
+
+/// Copying blobs between the CAS instances of the scheduler and workers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplicationConfig {
+    #[serde(default)]
+    pub mode: ReplicationMode,
+    /// Extra peers to replicate with (host:port of a scheduler or worker)
+    #[serde(default)]
+    pub peers: Vec<String>,
+    /// Also treat workers registered with the scheduler as peers
+    #[serde(default)]
+    pub discover_workers: bool,
+}
+
+/// How calls to remote CAS peers are timed out and retried, e.g.
+/// `[cas.remote] max_attempts = 3`. After `breaker_failures` failed calls
+/// in a row a peer is skipped for `breaker_cooldown_secs` (0 = never).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    #[serde(default = "default_remote_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+    /// Per request, including each retry
+    #[serde(default = "default_remote_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    #[serde(default = "default_remote_max_attempts")]
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled on each further one up to `max_backoff_ms`
+    #[serde(default = "default_remote_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_remote_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    #[serde(default = "default_remote_breaker_failures")]
+    pub breaker_failures: u32,
+    #[serde(default = "default_remote_breaker_cooldown_secs")]
+    pub breaker_cooldown_secs: u64,
+    /// Blob HTTP endpoint (a scheduler's `http_addr`, or a CDN in front of
+    /// it, e.g. `http://cdn.example.com`) to fetch missing blobs from
+    /// before asking peers
+    #[serde(default)]
+    pub http_url: Option<String>,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        RemoteConfig {
+            connect_timeout_ms: default_remote_connect_timeout_ms(),
+            request_timeout_secs: default_remote_request_timeout_secs(),
+            max_attempts: default_remote_max_attempts(),
+            initial_backoff_ms: default_remote_initial_backoff_ms(),
+            max_backoff_ms: default_remote_max_backoff_ms(),
+            breaker_failures: default_remote_breaker_failures(),
+            breaker_cooldown_secs: default_remote_breaker_cooldown_secs(),
+            http_url: None,
+        }
+    }
+}
+
+fn default_remote_connect_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_remote_request_timeout_secs() -> u64 {
+    30
+}
+
+fn default_remote_max_attempts() -> u32 {
+    5
+}
+
+fn default_remote_initial_backoff_ms() -> u64 {
+    200
+}
+
+fn default_remote_max_backoff_ms() -> u64 {
+    5000
+}
+
+fn default_remote_breaker_failures() -> u32 {
+    5
+}
+
+fn default_remote_breaker_cooldown_secs() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplicationMode {
+    /// Every node relies on the shared CAS root
+    #[default]
+    Off,
+    /// Push new job outputs to peers as soon as they're written
+    Push,
+    /// Fetch missing inputs from peers on demand
+    Pull,
+    Both,
+}
+
+impl ReplicationMode {
+    pub fn pushes(&self) -> bool {
+        matches!(self, ReplicationMode::Push | ReplicationMode::Both)
+    }
+
+    pub fn pulls(&self) -> bool {
+        matches!(self, ReplicationMode::Pull | ReplicationMode::Both)
+    }
+}
+
+fn default_index_reconcile_secs() -> u64 {
+    300
+}
+
+fn default_prefetch_parallelism() -> usize {
+    8
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkerConfig {
     pub heartbeat_interval_secs: u64,
     pub capacity: u32,
+    /// Where job sandboxes live (default: a per-worker dir under the system temp dir)
+    #[serde(default)]
+    pub sandbox_root: Option<String>,
+    /// Clean sandboxes kept ready for reuse
+    #[serde(default = "default_sandbox_pool_size")]
+    pub sandbox_pool_size: usize,
+    /// Delete and recreate sandboxes after every job instead of emptying them
+    #[serde(default)]
+    pub sandbox_paranoid_wipe: bool,
+    /// Advertised to the scheduler for jobs' constraints to match, on top
+    /// of the detected `os` and `arch` (e.g. `target`, `rustc`)
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Only run this tenant's jobs (implied by a tenant's auth token)
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Job types this worker runs (e.g. `rust-compile`); empty = any
+    #[serde(default)]
+    pub job_types: Vec<String>,
+    /// Pool this worker is in (e.g. `linux-x64`, `big-mem`): jobs sent to
+    /// the pool only run on its workers, which also take jobs sent nowhere
+    #[serde(default)]
+    pub pool: Option<String>,
+}
+
+fn default_sandbox_pool_size() -> usize {
+    4
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrapperConfig {
+    /// Crates that compile in less than this (locally, or failing that as
+    /// a remote round trip) are built locally
+    #[serde(default = "default_local_threshold_ms")]
+    pub local_threshold_ms: u64,
+    /// A crate built locally as tiny goes remote again after this long, to
+    /// be re-measured
+    #[serde(default = "default_reevaluate_secs")]
+    pub reevaluate_secs: u64,
+    /// Which crates compiled on this machine are pushed to the shared
+    /// action cache (results from workers always are)
+    #[serde(default)]
+    pub push: PushPolicy,
+    /// Added to the priority of every job this client submits (the
+    /// `DISTBUILD_PRIORITY` environment variable overrides it)
+    #[serde(default)]
+    pub priority: i32,
+    /// Worker labels every job submitted from here requires
+    #[serde(default)]
+    pub constraints: HashMap<String, String>,
+    /// Tenant jobs submitted from here belong to (implied by a tenant's
+    /// auth token)
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Worker pool jobs submitted from here run in (the `DISTBUILD_POOL`
+    /// environment variable overrides it); unset = any worker
+    #[serde(default)]
+    pub pool: Option<String>,
+}
+
+/// Whether a client may populate the shared cache with its own compiles
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PushPolicy {
+    All,
+    /// Only from CI (the `CI` environment variable is set)
+    #[default]
+    CiOnly,
+    None,
+}
+
+impl PushPolicy {
+    /// Whether this process may push its local compiles
+    pub fn allows_push(&self) -> bool {
+        match self {
+            PushPolicy::All => true,
+            PushPolicy::CiOnly => std::env::var("CI").is_ok_and(|ci| !ci.is_empty() && ci != "false"),
+            PushPolicy::None => false,
+        }
+    }
+}
+
+fn default_local_threshold_ms() -> u64 {
+    100
+}
+
+fn default_reevaluate_secs() -> u64 {
+    24 * 60 * 60
+}
+
+impl Default for WrapperConfig {
+    fn default() -> Self {
+        WrapperConfig {
+            local_threshold_ms: default_local_threshold_ms(),
+            reevaluate_secs: default_reevaluate_secs(),
+            push: PushPolicy::default(),
+            priority: 0,
+            constraints: HashMap::new(),
+            tenant: None,
+            pool: None,
+        }
+    }
 }
 
 impl Config {
-    /// Load config from a TOML file
+    /// Load config from a TOML file, with every section optional
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
         let content = fs::read_to_string(path.as_ref())
             .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
         
         let config: Config = toml::from_str(&content)
-            .with_context(|| "Failed to parse config file")?;
+            .with_context(|| format!("Failed to parse config file {:?}", path.as_ref()))?;
         
         Ok(config)
     }
 
+    /// Load config from a TOML file for `role`, failing if a section it
+    /// needs is missing. Each section is parsed separately so errors name it.
+    pub fn load_for<P: AsRef<Path>>(path: P, role: Role) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {} config file {:?}", role, path))?;
+        Self::parse_for(&content, role).with_context(|| format!("Invalid config file {:?}", path))
+    }
+
+    fn parse_for(content: &str, role: Role) -> Result<Self> {
+        let table: toml::Table = toml::from_str(content)
+            .with_context(|| format!("Failed to parse {} config as TOML", role))?;
+
+        for section in role.required_sections() {
+            if !table.contains_key(*section) {
+                anyhow::bail!("The {} needs a [{}] section, but it is missing", role, section);
+            }
+        }
+
+        Ok(Config {
+            scheduler: section(&table, "scheduler", role)?,
+            cas: section(&table, "cas", role)?,
+            worker: section(&table, "worker", role)?,
+            wrapper: section(&table, "wrapper", role)?,
+            logging: section(&table, "logging", role)?,
+        })
+    }
+
     /// Load config from default locations
     pub fn load_default() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) => Self::load(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Load config for `role` from default locations
+    pub fn load_default_for(role: Role) -> Result<Self> {
+        match Self::default_path() {
+            Some(path) => Self::load_for(path, role),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn default_path() -> Option<std::path::PathBuf> {
         // Try current directory first
         if Path::new("config.toml").exists() {
-            return Self::load("config.toml");
+            return Some("config.toml".into());
         }
 
         // Try ~/.config/cargo-distbuild/config.toml
-        if let Some(home) = std::env::var_os("HOME") {
-            let config_path = Path::new(&home)
-                .join(".config")
-                .join("cargo-distbuild")
-                .join("config.toml");
-            if config_path.exists() {
-                return Self::load(config_path);
-            }
-        }
-
-        // Return default config
-        Ok(Self::default())
+        let config_path = Path::new(&std::env::var_os("HOME")?)
+            .join(".config")
+            .join("cargo-distbuild")
+            .join("config.toml");
+        config_path.exists().then_some(config_path)
     }
 
     /// Save config to file
@@ -72,20 +1135,119 @@ impl Config {
     }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            scheduler: SchedulerConfig {
-                addr: "127.0.0.1:5000".to_string(),
-            },
-            cas: CasConfig {
-                root: "./cas-root".to_string(),
-            },
-            worker: WorkerConfig {
-                heartbeat_interval_secs: 10,
-                capacity: 4,
-            },
-        }
+/// Deserialize one top-level section, defaulting it when absent
+fn section<T>(table: &toml::Table, name: &str, role: Role) -> Result<T>
+where
+    T: serde::de::DeserializeOwned + Default,
+{
+    match table.get(name) {
+        Some(value) => value
+            .clone()
+            .try_into()
+            .with_context(|| format!("Invalid [{}] section (loading {} config)", name, role)),
+        None => Ok(T::default()),
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_sections() {
+        let wrapper_only = r#"
+            [scheduler]
+            addr = "10.0.0.1:5000"
+        "#;
+
+        // The wrapper doesn't care that [worker] and [cas] are absent
+        let config = Config::parse_for(wrapper_only, Role::Wrapper).unwrap();
+        assert_eq!(config.scheduler.addr, "10.0.0.1:5000");
+        assert_eq!(config.worker.capacity, 4);
+
+        let err = Config::parse_for(wrapper_only, Role::Worker).unwrap_err();
+        assert!(err.to_string().contains("worker needs a [cas] section"));
+
+        let bad_worker = r#"
+            [scheduler]
+            addr = "10.0.0.1:5000"
+            [cas]
+            root = "/cas"
+            [worker]
+            capacity = "lots"
+        "#;
+        let err = Config::parse_for(bad_worker, Role::Worker).unwrap_err();
+        assert!(err.to_string().contains("Invalid [worker] section"));
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let config = Config::parse_for(
+            r#"
+            [scheduler]
+            addr = "10.0.0.1:5000"
+            strategy = "least-loaded"
+            [scheduler.retry]
+            max_backoff_secs = 5
+            retry_on = ["dispatch", "quota"]
+        "#,
+            Role::Wrapper,
+        )
+        .unwrap();
+        assert_eq!(config.scheduler.strategy, StrategyKind::LeastLoaded);
+        assert_eq!(config.scheduler.job_timeout_secs, 3600);
+        assert_eq!(config.scheduler.worker_timeout_secs, 10);
+        assert_eq!(config.scheduler.offline_action, OfflineAction::Remove);
+        assert_eq!(config.scheduler.shutdown_drain_secs, 30);
+        assert_eq!(config.scheduler.aging_secs, 300);
+        let retry = config.scheduler.retry;
+        assert_eq!(retry.max_attempts, 3);
+        assert_eq!(retry.retry_on, vec![RetryClass::Dispatch, RetryClass::Quota]);
+        assert_eq!(retry.backoff_secs(1), 2);
+        assert_eq!(retry.backoff_secs(2), 4);
+        assert_eq!(retry.backoff_secs(3), 5);
+    }
+
+    #[test]
+    fn test_quarantine_policy() {
+        let config = Config::parse_for(
+            r#"
+            [scheduler]
+            addr = "10.0.0.1:5000"
+            [scheduler.quarantine]
+            duration_secs = 60
+            max_duration_secs = 200
+            count_on = ["worker", "compile"]
+        "#,
+            Role::Wrapper,
+        )
+        .unwrap();
+        let quarantine = config.scheduler.quarantine;
+        assert_eq!(quarantine.consecutive_failures, 5);
+        assert_eq!(quarantine.count_on, vec![RetryClass::Worker, RetryClass::Compile]);
+        assert_eq!(quarantine.duration_secs(1), 60);
+        assert_eq!(quarantine.duration_secs(2), 120);
+        assert_eq!(quarantine.duration_secs(3), 200);
+    }
+
+    #[test]
+    fn test_tenants() {
+        let config = Config::parse_for(
+            r#"
+            [scheduler]
+            addr = "10.0.0.1:5000"
+            [scheduler.tenants.team-a]
+            token = "a-token"
+            max_pending_jobs = 100
+            [scheduler.tenants.team-b]
+        "#,
+            Role::Wrapper,
+        )
+        .unwrap();
+        let tenants = config.scheduler.tenants;
+        assert_eq!(tenants["team-a"].token.as_deref(), Some("a-token"));
+        assert_eq!(tenants["team-a"].max_pending_jobs, 100);
+        assert_eq!(tenants["team-a"].max_running_jobs, 0);
+        assert!(tenants["team-b"].token.is_none());
+    }
+}
@@ -1,4 +1,7 @@
+use std::time::Duration;
 use thiserror::Error;
+use tonic::metadata::MetadataValue;
+use tonic::Status;
 
 #[derive(Error, Debug)]
 pub enum DistbuildError {
@@ -14,18 +17,118 @@ pub enum DistbuildError {
     #[error("CAS error: {0}")]
     Cas(String),
 
+    #[error("CAS quota exceeded for namespace {namespace}: {used} of {limit} bytes used, {needed} more needed")]
+    QuotaExceeded {
+        namespace: String,
+        used: u64,
+        limit: u64,
+        needed: u64,
+    },
+
+    #[error("Compilation failed: {0}")]
+    CompileFailed(String),
+
+    #[error("CAS at {0} is read-only")]
+    ReadOnly(String),
+
     #[error("Job not found: {0}")]
     JobNotFound(String),
 
     #[error("Worker not found: {0}")]
     WorkerNotFound(String),
 
+    #[error("Blob doesn't match its hash: expected {expected}, got {actual}")]
+    HashMismatch { expected: String, actual: String },
+
     #[error("Invalid hash: {0}")]
     InvalidHash(String),
 
+    #[error("Scheduler queue is full ({pending} jobs pending); retry in {retry_after_secs}s")]
+    QueueFull { pending: usize, retry_after_secs: u64 },
+
+    #[error("Job rejected: {message}")]
+    Rejected { reason: Rejection, message: String },
+
     #[error("Other error: {0}")]
     Other(#[from] anyhow::Error),
 }
 
 pub type Result<T> = std::result::Result<T, DistbuildError>;
 
+/// Why the scheduler refused a job outright, so the client can tell what
+/// (if anything) would get it accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// The input hash isn't a hash at all
+    InvalidHash,
+    /// The input isn't in the scheduler's CAS; pushing it there may help
+    MissingInput,
+    /// The input is larger than the scheduler takes
+    InputTooLarge,
+    /// The scheduler doesn't run jobs of that type
+    UnknownJobType,
+}
+
+impl Rejection {
+    const ALL: [Rejection; 4] = [
+        Rejection::InvalidHash,
+        Rejection::MissingInput,
+        Rejection::InputTooLarge,
+        Rejection::UnknownJobType,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Rejection::InvalidHash => "invalid-hash",
+            Rejection::MissingInput => "missing-input",
+            Rejection::InputTooLarge => "input-too-large",
+            Rejection::UnknownJobType => "unknown-job-type",
+        }
+    }
+}
+
+/// Metadata on a `QueueFull` rejection: seconds to wait before resubmitting
+const RETRY_AFTER: &str = "retry-after";
+/// Metadata on a `Rejected` job: which `Rejection` it was
+const REJECTION: &str = "rejection";
+
+impl DistbuildError {
+    /// As a gRPC status for the client. `QueueFull` is `ResourceExhausted`
+    /// carrying its backoff, which `retry_after` reads back; `Rejected`
+    /// carries its reason, which `rejection` reads back.
+    pub fn into_status(self) -> Status {
+        match self {
+            DistbuildError::QueueFull { retry_after_secs, .. } => {
+                let mut status = Status::resource_exhausted(self.to_string());
+                status.metadata_mut().insert(RETRY_AFTER, retry_after_secs.into());
+                status
+            }
+            DistbuildError::Rejected { reason, .. } => {
+                let mut status = match reason {
+                    Rejection::MissingInput => Status::failed_precondition(self.to_string()),
+                    _ => Status::invalid_argument(self.to_string()),
+                };
+                status.metadata_mut().insert(REJECTION, MetadataValue::from_static(reason.as_str()));
+                status
+            }
+            other => Status::internal(other.to_string()),
+        }
+    }
+}
+
+/// How long to back off before resubmitting, if `status` rejected a job
+/// because the scheduler's queue is full
+pub fn retry_after(status: &Status) -> Option<Duration> {
+    if status.code() != tonic::Code::ResourceExhausted {
+        return None;
+    }
+    let secs = status.metadata().get(RETRY_AFTER)?.to_str().ok()?.parse().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+
+/// Why `status` rejected a job outright, if it did
+pub fn rejection(status: &Status) -> Option<Rejection> {
+    let reason = status.metadata().get(REJECTION)?.to_str().ok()?;
+    Rejection::ALL.into_iter().find(|rejection| rejection.as_str() == reason)
+}
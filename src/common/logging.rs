@@ -0,0 +1,395 @@
+use anyhow::{Context, Result};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Log destinations for the long-running services (`[logging.scheduler]`,
+/// `[logging.worker]`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub scheduler: ServiceLogConfig,
+    #[serde(default)]
+    pub worker: ServiceLogConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceLogConfig {
+    /// Minimum level: error, warn, info, debug or trace
+    #[serde(default = "default_level")]
+    pub level: String,
+    #[serde(default = "default_sinks")]
+    pub sinks: Vec<LogSinkConfig>,
+}
+
+fn default_level() -> String {
+    "info".to_string()
+}
+
+fn default_sinks() -> Vec<LogSinkConfig> {
+    vec![LogSinkConfig::Stdout]
+}
+
+impl Default for ServiceLogConfig {
+    fn default() -> Self {
+        ServiceLogConfig {
+            level: default_level(),
+            sinks: default_sinks(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum LogSinkConfig {
+    Stdout,
+    Stderr,
+    /// Append to a file, rotating by size and/or time
+    File {
+        path: String,
+        #[serde(default)]
+        format: LogFormat,
+        /// Rotate once the file exceeds this many megabytes
+        #[serde(default)]
+        max_size_mb: Option<u64>,
+        /// Rotate at the start of every hour or day
+        #[serde(default)]
+        rotate: Option<RotatePeriod>,
+        /// Number of rotated files to keep
+        #[serde(default = "default_keep")]
+        keep: usize,
+    },
+    /// Send to the systemd journal via its native socket
+    Journald {
+        #[serde(default)]
+        identifier: Option<String>,
+    },
+}
+
+fn default_keep() -> usize {
+    5
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RotatePeriod {
+    Hourly,
+    Daily,
+}
+
+impl RotatePeriod {
+    fn secs(&self) -> i64 {
+        match self {
+            RotatePeriod::Hourly => 60 * 60,
+            RotatePeriod::Daily => 24 * 60 * 60,
+        }
+    }
+}
+
+/// Install the global logger for `service` with the configured sinks
+pub fn init(service: &'static str, config: &ServiceLogConfig) -> Result<()> {
+    let level: LevelFilter = config
+        .level
+        .parse()
+        .with_context(|| format!("Invalid [logging.{}] level: {}", service, config.level))?;
+
+    let sinks = config
+        .sinks
+        .iter()
+        .map(|sink| Sink::open(service, sink).map(Mutex::new))
+        .collect::<Result<Vec<_>>>()?;
+
+    log::set_boxed_logger(Box::new(Logger { service, sinks }))
+        .context("Logger already initialized")?;
+    log::set_max_level(level);
+
+    Ok(())
+}
+
+struct Logger {
+    service: &'static str,
+    sinks: Vec<Mutex<Sink>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = record.args().to_string();
+        for sink in &self.sinks {
+            if let Ok(mut sink) = sink.lock() {
+                // Logging must never take a service down
+                let _ = sink.write(self.service, record.level(), &message);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        for sink in &self.sinks {
+            if let Ok(mut sink) = sink.lock() {
+                sink.flush();
+            }
+        }
+    }
+}
+
+enum Sink {
+    Stdout,
+    Stderr,
+    File(RotatingFile),
+    #[cfg(unix)]
+    Journald {
+        socket: std::os::unix::net::UnixDatagram,
+        identifier: String,
+    },
+}
+
+impl Sink {
+    fn open(service: &str, config: &LogSinkConfig) -> Result<Self> {
+        Ok(match config {
+            LogSinkConfig::Stdout => Sink::Stdout,
+            LogSinkConfig::Stderr => Sink::Stderr,
+            LogSinkConfig::File {
+                path,
+                format,
+                max_size_mb,
+                rotate,
+                keep,
+            } => Sink::File(RotatingFile::open(
+                PathBuf::from(path),
+                *format,
+                max_size_mb.map(|mb| mb * 1024 * 1024),
+                *rotate,
+                *keep,
+            )?),
+            #[cfg(unix)]
+            LogSinkConfig::Journald { identifier } => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket
+                    .connect("/run/systemd/journal/socket")
+                    .context("Failed to connect to journald")?;
+                Sink::Journald {
+                    socket,
+                    identifier: identifier
+                        .clone()
+                        .unwrap_or_else(|| format!("cargo-distbuild-{}", service)),
+                }
+            }
+            #[cfg(not(unix))]
+            LogSinkConfig::Journald { .. } => {
+                anyhow::bail!("journald logging is only supported on unix")
+            }
+        })
+    }
+
+    fn write(&mut self, service: &str, level: Level, message: &str) -> Result<()> {
+        match self {
+            Sink::Stdout => println!("{}", message),
+            Sink::Stderr => eprintln!("{}", message),
+            Sink::File(file) => file.write(service, level, message)?,
+            #[cfg(unix)]
+            Sink::Journald { socket, identifier } => {
+                // Native protocol: newline-separated KEY=value fields
+                let priority = match level {
+                    Level::Error => 3,
+                    Level::Warn => 4,
+                    Level::Info => 6,
+                    Level::Debug | Level::Trace => 7,
+                };
+                let entry = format!(
+                    "MESSAGE={}\nPRIORITY={}\nSYSLOG_IDENTIFIER={}\n",
+                    message.replace('\n', " "),
+                    priority,
+                    identifier
+                );
+                socket.send(entry.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        if let Sink::File(file) = self {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    format: LogFormat,
+    size: u64,
+    max_size: Option<u64>,
+    rotate: Option<RotatePeriod>,
+    period_start: i64,
+    keep: usize,
+}
+
+impl RotatingFile {
+    fn open(
+        path: PathBuf,
+        format: LogFormat,
+        max_size: Option<u64>,
+        rotate: Option<RotatePeriod>,
+        keep: usize,
+    ) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create log directory {:?}", parent))?;
+        }
+        let file = Self::open_append(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path,
+            file,
+            format,
+            size,
+            max_size,
+            rotate,
+            period_start: Self::period_start(rotate, chrono::Utc::now().timestamp()),
+            keep,
+        })
+    }
+
+    fn open_append(path: &PathBuf) -> Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file {:?}", path))
+    }
+
+    fn period_start(rotate: Option<RotatePeriod>, now: i64) -> i64 {
+        match rotate {
+            Some(period) => now - now.rem_euclid(period.secs()),
+            None => 0,
+        }
+    }
+
+    fn write(&mut self, service: &str, level: Level, message: &str) -> Result<()> {
+        let now = chrono::Utc::now();
+
+        let line = match self.format {
+            LogFormat::Text => format!(
+                "{} {:<5} {}: {}\n",
+                now.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                level,
+                service,
+                message
+            ),
+            LogFormat::Json => format!(
+                "{}\n",
+                serde_json::json!({
+                    "timestamp": now.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                    "level": level.to_string(),
+                    "service": service,
+                    "message": message,
+                })
+            ),
+        };
+
+        let period_start = Self::period_start(self.rotate, now.timestamp());
+        let size_exceeded = self
+            .max_size
+            .is_some_and(|max| self.size + line.len() as u64 > max && self.size > 0);
+        if size_exceeded || period_start != self.period_start {
+            self.rotate_files()?;
+            self.period_start = period_start;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Shift `log` -> `log.1` -> `log.2` ..., dropping anything beyond `keep`
+    fn rotate_files(&mut self) -> Result<()> {
+        self.file.flush()?;
+
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated(self.keep));
+            for n in (1..self.keep).rev() {
+                let _ = fs::rename(rotated(n), rotated(n + 1));
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+
+        self.file = Self::open_append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rotating_file_rotates_by_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("scheduler.log");
+
+        let mut file = RotatingFile::open(path.clone(), LogFormat::Json, Some(200), None, 2).unwrap();
+        for i in 0..10 {
+            file.write("scheduler", Level::Info, &format!("message {}", i)).unwrap();
+        }
+
+        // Only `keep` rotated files survive, each under the size limit
+        assert!(path.exists());
+        assert!(temp_dir.path().join("scheduler.log.1").exists());
+        assert!(temp_dir.path().join("scheduler.log.2").exists());
+        assert!(!temp_dir.path().join("scheduler.log.3").exists());
+
+        let last = fs::read_to_string(&path).unwrap();
+        let entry: serde_json::Value = serde_json::from_str(last.lines().last().unwrap()).unwrap();
+        assert_eq!(entry["message"], "message 9");
+        assert_eq!(entry["service"], "scheduler");
+    }
+
+    #[test]
+    fn test_sink_config_parsing() {
+        let config: LoggingConfig = toml::from_str(
+            r#"
+            [scheduler]
+            level = "debug"
+            sinks = [
+                { kind = "stderr" },
+                { kind = "file", path = "/tmp/s.log", format = "json", max_size_mb = 100, rotate = "daily" },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.scheduler.sinks.len(), 2);
+        assert!(matches!(
+            config.scheduler.sinks[1],
+            LogSinkConfig::File { format: LogFormat::Json, rotate: Some(RotatePeriod::Daily), keep: 5, .. }
+        ));
+        // Unconfigured services keep logging to stdout
+        assert!(matches!(config.worker.sinks[..], [LogSinkConfig::Stdout]));
+    }
+}
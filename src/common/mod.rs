@@ -1,5 +1,11 @@
+pub mod auth;
+pub mod clock;
 pub mod config;
+pub mod logging;
+pub mod platform;
+pub mod session;
 pub mod types;
+pub mod version;
 pub mod error;
 
 pub use config::Config;
@@ -0,0 +1,310 @@
+use crate::proto::distbuild::{PlatformFingerprint, WorkerCapabilities};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+/// What a compiled artifact depends on from the machine that built it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Platform {
+    pub os: String,
+    pub arch: String,
+    /// e.g. "glibc 2.35", "musl", or "unknown"
+    pub libc: String,
+}
+
+impl Platform {
+    /// Fingerprint of the current machine
+    pub fn detect() -> Self {
+        Platform {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            libc: detect_libc(),
+        }
+    }
+
+    /// Labels every worker advertises, which jobs can be constrained on
+    pub fn labels(&self) -> HashMap<String, String> {
+        HashMap::from([
+            ("os".to_string(), self.os.clone()),
+            ("arch".to_string(), self.arch.clone()),
+        ])
+    }
+
+    /// Explain why artifacts built on `remote` may fail to link here,
+    /// or `None` if the platforms are compatible
+    pub fn mismatch(&self, remote: &Platform) -> Option<String> {
+        if self.os != remote.os || self.arch != remote.arch {
+            return Some(format!(
+                "built on {}-{} but this machine is {}-{}",
+                remote.os, remote.arch, self.os, self.arch
+            ));
+        }
+
+        match (glibc_version(&self.libc), glibc_version(&remote.libc)) {
+            // Newer glibc symbols aren't available on an older local glibc
+            (Some(local), Some(built)) if built > local => Some(format!(
+                "built against {} but this machine has {}",
+                remote.libc, self.libc
+            )),
+            (Some(_), Some(_)) => None,
+            _ if self.libc != remote.libc && self.libc != "unknown" && remote.libc != "unknown" => {
+                Some(format!("built against {} but this machine uses {}", remote.libc, self.libc))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{} ({})", self.os, self.arch, self.libc)
+    }
+}
+
+impl From<PlatformFingerprint> for Platform {
+    fn from(fingerprint: PlatformFingerprint) -> Self {
+        Platform {
+            os: fingerprint.os,
+            arch: fingerprint.arch,
+            libc: fingerprint.libc,
+        }
+    }
+}
+
+impl From<Platform> for PlatformFingerprint {
+    fn from(platform: Platform) -> Self {
+        PlatformFingerprint {
+            os: platform.os,
+            arch: platform.arch,
+            libc: platform.libc,
+        }
+    }
+}
+
+/// What a worker can build with, as it tells the scheduler on registering
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// rustc versions installed (e.g. "1.78.0"), the default first
+    pub rustc_versions: Vec<String>,
+    /// e.g. "x86_64-unknown-linux-gnu"
+    pub host_triple: String,
+    /// Targets it can compile for, the host's included
+    pub targets: Vec<String>,
+    /// Free space where jobs are staged; 0 = unknown
+    pub disk_free_bytes: u64,
+    /// Job types it runs; empty = any
+    pub job_types: Vec<String>,
+}
+
+impl Capabilities {
+    /// Probe this machine's toolchains (through rustup when it's there)
+    /// and the disk holding `dir`
+    pub fn detect(dir: &Path, job_types: Vec<String>) -> Self {
+        let (default_version, host_triple) = rustc_release(&mut Command::new("rustc")).unwrap_or_default();
+        let mut rustc_versions: Vec<String> = Some(default_version).filter(|v| !v.is_empty()).into_iter().collect();
+        for toolchain in command_lines(Command::new("rustup").args(["toolchain", "list"])) {
+            let Some(name) = toolchain.split_whitespace().next() else {
+                continue;
+            };
+            if let Some((version, _)) = rustc_release(Command::new("rustup").args(["run", name, "rustc"])) {
+                if !rustc_versions.contains(&version) {
+                    rustc_versions.push(version);
+                }
+            }
+        }
+
+        let mut targets = command_lines(Command::new("rustup").args(["target", "list", "--installed"]));
+        if !host_triple.is_empty() && !targets.contains(&host_triple) {
+            targets.insert(0, host_triple.clone());
+        }
+        Capabilities {
+            rustc_versions,
+            host_triple,
+            targets,
+            disk_free_bytes: disk_free_bytes(dir).unwrap_or(0),
+            job_types,
+        }
+    }
+
+    /// Whether these satisfy a job's `key = value` constraint: `target` an
+    /// installed target, `rustc` an installed version (or its prefix, e.g.
+    /// "1.78"), `host` the host triple
+    pub fn provide(&self, key: &str, value: &str) -> bool {
+        match key {
+            "target" => self.targets.iter().any(|target| target == value),
+            "rustc" => self
+                .rustc_versions
+                .iter()
+                .any(|version| version == value || version.strip_prefix(value).is_some_and(|rest| rest.starts_with('.'))),
+            "host" => self.host_triple == value,
+            _ => false,
+        }
+    }
+
+    /// Whether it runs jobs of `job_type`
+    pub fn runs(&self, job_type: &str) -> bool {
+        self.job_types.is_empty() || self.job_types.iter().any(|t| t == job_type)
+    }
+}
+
+impl From<WorkerCapabilities> for Capabilities {
+    fn from(capabilities: WorkerCapabilities) -> Self {
+        Capabilities {
+            rustc_versions: capabilities.rustc_versions,
+            host_triple: capabilities.host_triple,
+            targets: capabilities.targets,
+            disk_free_bytes: capabilities.disk_free_bytes,
+            job_types: capabilities.job_types,
+        }
+    }
+}
+
+impl From<Capabilities> for WorkerCapabilities {
+    fn from(capabilities: Capabilities) -> Self {
+        WorkerCapabilities {
+            rustc_versions: capabilities.rustc_versions,
+            host_triple: capabilities.host_triple,
+            targets: capabilities.targets,
+            disk_free_bytes: capabilities.disk_free_bytes,
+            job_types: capabilities.job_types,
+        }
+    }
+}
+
+/// Release and host triple from `<rustc> -vV`
+fn rustc_release(rustc: &mut Command) -> Option<(String, String)> {
+    let output = rustc.arg("-vV").output().ok().filter(|output| output.status.success())?;
+    parse_rustc_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// `rustc -vV` prints `release: 1.78.0` and `host: x86_64-...` among others
+fn parse_rustc_version(output: &str) -> Option<(String, String)> {
+    let field = |name: &str| {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .map(|value| value.trim().to_string())
+    };
+    Some((field("release:")?, field("host:").unwrap_or_default()))
+}
+
+/// Non-empty lines a command prints, or none if it can't be run
+fn command_lines(command: &mut Command) -> Vec<String> {
+    command
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Bytes available on the filesystem holding `dir`, per `df`
+fn disk_free_bytes(dir: &Path) -> Option<u64> {
+    let output = Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+    parse_df_available(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The second line of `df -Pk` output has the available KiB fourth
+fn parse_df_available(output: &str) -> Option<u64> {
+    let kib: u64 = output.lines().nth(1)?.split_whitespace().nth(3)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// This machine's name, for telling its processes apart from other hosts'
+pub fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn detect_libc() -> String {
+    if cfg!(target_env = "musl") {
+        return "musl".to_string();
+    }
+    if !cfg!(target_env = "gnu") {
+        return "unknown".to_string();
+    }
+
+    // First line looks like "ldd (Ubuntu GLIBC 2.35-0ubuntu3.1) 2.35"
+    Command::new("ldd")
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| {
+            let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+            let version = stdout.lines().next()?.split_whitespace().last()?.to_string();
+            Some(format!("glibc {}", version))
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Parse "glibc 2.35" into (2, 35)
+fn glibc_version(libc: &str) -> Option<(u32, u32)> {
+    let (major, minor) = libc.strip_prefix("glibc ")?.split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn platform(libc: &str) -> Platform {
+        Platform {
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            libc: libc.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_platform_mismatch() {
+        let local = platform("glibc 2.35");
+
+        assert_eq!(local.mismatch(&platform("glibc 2.35")), None);
+        assert_eq!(local.mismatch(&platform("glibc 2.31")), None);
+        assert!(local.mismatch(&platform("glibc 2.38")).is_some());
+        assert!(local.mismatch(&platform("musl")).is_some());
+        assert_eq!(local.mismatch(&platform("unknown")), None);
+
+        let mut arm = platform("glibc 2.35");
+        arm.arch = "aarch64".to_string();
+        assert!(local.mismatch(&arm).unwrap().contains("aarch64"));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let rustc = "rustc 1.78.0 (9b00956e5 2024-04-29)\nbinary: rustc\nhost: x86_64-unknown-linux-gnu\nrelease: 1.78.0\n";
+        assert_eq!(
+            parse_rustc_version(rustc),
+            Some(("1.78.0".to_string(), "x86_64-unknown-linux-gnu".to_string()))
+        );
+        let df = "Filesystem 1024-blocks Used Available Capacity Mounted on\n/dev/sda1 1000 400 600 40% /\n";
+        assert_eq!(parse_df_available(df), Some(600 * 1024));
+
+        let capabilities = Capabilities {
+            rustc_versions: vec!["1.78.0".to_string(), "1.80.1".to_string()],
+            host_triple: "x86_64-unknown-linux-gnu".to_string(),
+            targets: vec!["x86_64-unknown-linux-gnu".to_string(), "wasm32-unknown-unknown".to_string()],
+            disk_free_bytes: 0,
+            job_types: vec!["rust-compile".to_string()],
+        };
+        assert!(capabilities.provide("target", "wasm32-unknown-unknown"));
+        assert!(!capabilities.provide("target", "aarch64-apple-darwin"));
+        assert!(capabilities.provide("rustc", "1.80"));
+        assert!(capabilities.provide("rustc", "1.78.0"));
+        assert!(!capabilities.provide("rustc", "1.8"));
+        assert!(!capabilities.provide("os", "linux"));
+        assert!(capabilities.runs("rust-compile"));
+        assert!(!capabilities.runs("rust-test"));
+    }
+}
@@ -0,0 +1,219 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// How a unit ended up being compiled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnitMode {
+    Remote,
+    Local,
+    /// Remote compilation failed and the wrapper compiled locally
+    Fallback,
+}
+
+/// One rustc invocation seen by the wrapper
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitRecord {
+    pub crate_name: String,
+    /// Cargo package the unit belongs to
+    pub package: String,
+    /// Whether cargo was asked to build this package (e.g. via `-p`),
+    /// rather than building it as a dependency
+    pub primary: bool,
+    pub mode: UnitMode,
+    pub duration_ms: u64,
+    pub finished_at: i64,
+}
+
+/// Units compiled by one `cargo build` of one workspace. Every wrapper
+/// process of the build appends to the same file, keyed by the cargo pid,
+/// so package-scoped builds only ever see their own units.
+#[derive(Debug, Clone)]
+pub struct BuildSession {
+    path: PathBuf,
+}
+
+impl BuildSession {
+    /// Session of the cargo process that spawned this wrapper, for the
+    /// workspace containing the package being compiled
+    pub fn current() -> Option<Self> {
+        let manifest_dir = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR")?);
+        let workspace = workspace_root(&manifest_dir);
+        let dir = sessions_dir(&workspace);
+        Some(Self::open(dir, std::os::unix::process::parent_id()))
+    }
+
+    fn open(dir: PathBuf, cargo_pid: u32) -> Self {
+        BuildSession {
+            path: dir.join(format!("{}.jsonl", cargo_pid)),
+        }
+    }
+
+    /// Append a unit; the first unit of a session clears this workspace's
+    /// older sessions (other workspaces' sessions are left alone)
+    pub fn record(&self, unit: &UnitRecord) -> Result<()> {
+        let dir = self.path.parent().context("Session path has no parent")?;
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+
+        if !self.path.exists() {
+            for entry in fs::read_dir(dir)?.flatten() {
+                if entry.path() != self.path {
+                    let _ = fs::remove_file(entry.path());
+                }
+            }
+        }
+
+        // One short line per write, so concurrent appends don't interleave
+        let mut line = serde_json::to_vec(unit)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(&line))
+            .with_context(|| format!("Failed to write {:?}", self.path))
+    }
+
+    /// Most recent session recorded for `workspace`
+    pub fn latest(workspace: &Path) -> Option<Self> {
+        let dir = sessions_dir(&workspace_root(workspace));
+        let path = fs::read_dir(dir)
+            .ok()?
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "jsonl"))
+            .max_by_key(|entry| entry.metadata().and_then(|m| m.modified()).ok())?
+            .path();
+        Some(BuildSession { path })
+    }
+
+    /// Units recorded so far; unreadable lines are skipped
+    pub fn units(&self) -> Result<Vec<UnitRecord>> {
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read {:?}", self.path))?;
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+}
+
+/// Summary of a session for the build report
+#[derive(Debug, Clone, Default)]
+pub struct SessionReport {
+    pub units: usize,
+    pub remote: usize,
+    pub local: usize,
+    pub fallback: usize,
+    /// Packages cargo was asked to build, sorted
+    pub primary_packages: Vec<String>,
+}
+
+impl SessionReport {
+    pub fn from_units(units: &[UnitRecord]) -> Self {
+        let mut report = SessionReport {
+            units: units.len(),
+            ..Default::default()
+        };
+
+        for unit in units {
+            match unit.mode {
+                UnitMode::Remote => report.remote += 1,
+                UnitMode::Local => report.local += 1,
+                UnitMode::Fallback => report.fallback += 1,
+            }
+            if unit.primary && !report.primary_packages.contains(&unit.package) {
+                report.primary_packages.push(unit.package.clone());
+            }
+        }
+
+        report.primary_packages.sort();
+        report
+    }
+
+    /// Whether only some of the workspace's `members` were built
+    pub fn is_partial(&self, members: &[String]) -> bool {
+        members
+            .iter()
+            .any(|member| !self.primary_packages.contains(member))
+    }
+}
+
+/// Outermost directory above `dir` whose Cargo.toml declares a `[workspace]`,
+/// or `dir` itself for standalone packages
+pub fn workspace_root(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .filter(|ancestor| {
+            fs::read_to_string(ancestor.join("Cargo.toml"))
+                .map(|manifest| manifest.lines().any(|line| line.trim() == "[workspace]"))
+                .unwrap_or(false)
+        })
+        .last()
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+/// ~/.cache/cargo-distbuild/sessions/<hash of the workspace path>
+fn sessions_dir(workspace: &Path) -> PathBuf {
+    let key = crate::cas::Cas::hash_bytes(workspace.to_string_lossy().as_bytes());
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("cargo-distbuild")
+        .join("sessions")
+        .join(&key[..16])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn unit(package: &str, primary: bool, mode: UnitMode) -> UnitRecord {
+        UnitRecord {
+            crate_name: package.replace('-', "_"),
+            package: package.to_string(),
+            primary,
+            mode,
+            duration_ms: 10,
+            finished_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_session_scoped_to_cargo_process() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("sessions");
+
+        let first = BuildSession::open(dir.clone(), 100);
+        first.record(&unit("serde", false, UnitMode::Remote)).unwrap();
+        first.record(&unit("app-core", true, UnitMode::Remote)).unwrap();
+        assert_eq!(first.units().unwrap().len(), 2);
+
+        // `cargo build -p app-cli` starts a fresh session for the workspace
+        let second = BuildSession::open(dir, 200);
+        second.record(&unit("app-cli", true, UnitMode::Fallback)).unwrap();
+        assert!(first.units().is_err());
+
+        let report = SessionReport::from_units(&second.units().unwrap());
+        assert_eq!(report.units, 1);
+        assert_eq!(report.fallback, 1);
+        assert_eq!(report.primary_packages, vec!["app-cli"]);
+
+        let members = vec!["app-cli".to_string(), "app-core".to_string()];
+        assert!(report.is_partial(&members));
+        assert!(!report.is_partial(&members[..1]));
+    }
+
+    #[test]
+    fn test_workspace_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let member = temp_dir.path().join("crates").join("core");
+        fs::create_dir_all(&member).unwrap();
+        fs::write(temp_dir.path().join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\"]\n").unwrap();
+        fs::write(member.join("Cargo.toml"), "[package]\nname = \"core\"\n").unwrap();
+
+        assert_eq!(workspace_root(&member), temp_dir.path());
+    }
+}
@@ -1,49 +1,415 @@
+use crate::common::platform::{Capabilities, Platform};
+use crate::common::version::BuildVersion;
+use crate::proto::distbuild::{self as proto, JobErrorKind, JobStatus, WorkerState};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JobMetadata {
     pub job_id: String,
     pub input_hash: String,
     pub output_hash: Option<String>,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub error_kind: JobErrorKindEnum,
     pub job_type: String,
     pub status: JobStatusEnum,
     pub assigned_worker: Option<String>,
     pub submitted_at: i64,
+    pub started_at: Option<i64>,
     pub completed_at: Option<i64>,
     pub metadata: HashMap<String, String>,
+    pub preemptions: u32,
+    pub worker_platform: Option<Platform>,
+    /// Pending jobs with higher priority are dispatched first
+    #[serde(default)]
+    pub priority: i32,
+    /// Every status the job has been in, oldest first
+    #[serde(default)]
+    pub timeline: Vec<JobTransition>,
+    /// Latest liveness report from the worker running the job
+    #[serde(default)]
+    pub progress: Option<JobProgress>,
+    /// Times the job has been dispatched, retries included
+    #[serde(default)]
+    pub attempts: u32,
+    /// Each of those dispatches, oldest first
+    #[serde(default)]
+    pub attempt_history: Vec<JobAttempt>,
+    /// A job queued again after failing isn't dispatched before this
+    #[serde(default)]
+    pub retry_at: Option<i64>,
+    /// Nor one submitted to run later (say, a nightly cache warm-up)
+    #[serde(default)]
+    pub run_after: Option<i64>,
+    /// Jobs that must complete before this one is queued (it's `Blocked`
+    /// until then, and fails if one of them doesn't)
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Labels a worker must have (with these values) to run this job
+    #[serde(default)]
+    pub constraints: HashMap<String, String>,
+    /// Max run time once dispatched, instead of the scheduler's default
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// An identical job already in flight whose outcome this one takes on
+    /// (it's `Blocked` until then instead of running itself)
+    #[serde(default)]
+    pub attached_to: Option<String>,
+    /// What running it took, as its worker reported
+    #[serde(default)]
+    pub usage: Option<JobUsage>,
+    /// Tenant it belongs to, who alone (besides operators) sees it
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Build (cargo invocation) that submitted it, for grouping and
+    /// cancelling its jobs together
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// CAS blob of the log its latest run left
+    #[serde(default)]
+    pub log_hash: Option<String>,
+    /// Worker slots it takes up while dispatched, more than one for a
+    /// heavy crate
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Only workers in this pool run it; `None` runs on any
+    #[serde(default)]
+    pub pool: Option<String>,
+    /// A build is blocked waiting on it, so it's dispatched ahead of every
+    /// job that isn't, whatever their priorities
+    #[serde(default)]
+    pub boosted: bool,
+    /// Jobs submitted together to be dispatched all at once, only when
+    /// every one of them fits
+    #[serde(default)]
+    pub gang: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+fn default_weight() -> u32 {
+    1
+}
+
+/// Resources a job used on its worker
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct JobUsage {
+    pub exec_millis: u64,
+    /// Input and dependency blobs read
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    /// Of the inputs, pulled from CAS peers
+    pub transferred_bytes: u64,
+}
+
+/// What a worker's watchdog last said about a running job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProgress {
+    pub at: i64,
+    pub phase: String,
+    pub elapsed_secs: u64,
+    /// Compiler process, if one is running
+    pub pid: Option<u32>,
+    pub process_alive: bool,
+}
+
+/// A job entering a status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobTransition {
+    pub status: JobStatusEnum,
+    pub at: i64,
+    pub worker: Option<String>,
+}
+
+/// One dispatch of a job, from being assigned to a worker until it
+/// completed, failed, was cancelled or was queued again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobAttempt {
+    pub worker: String,
+    pub started_at: i64,
+    /// `None` while it's under way
+    pub ended_at: Option<i64>,
+    /// Status the job moved to as it ended (`Pending` when requeued)
+    pub outcome: Option<JobStatusEnum>,
+    pub error: Option<String>,
+}
+
+impl JobMetadata {
+    /// Whether a queued job may be dispatched at `now`: it isn't backing
+    /// off after a failure, or waiting for the time it was submitted for
+    pub fn is_due(&self, now: i64) -> bool {
+        self.retry_at.is_none_or(|at| at <= now) && self.run_after.is_none_or(|at| at <= now)
+    }
+
+    /// Move the job to `status`, recording the transition in its timeline.
+    /// Assigning it to a worker starts an attempt, and leaving the worker
+    /// ends it, with `error` unless it completed or was cancelled.
+    pub fn set_status(&mut self, status: JobStatusEnum, at: i64) {
+        self.status = status;
+        match status {
+            JobStatusEnum::Assigned => self.attempt_history.push(JobAttempt {
+                worker: self.assigned_worker.clone().unwrap_or_default(),
+                started_at: at,
+                ended_at: None,
+                outcome: None,
+                error: None,
+            }),
+            JobStatusEnum::Running => {}
+            _ => {
+                if let Some(attempt) = self.attempt_history.last_mut().filter(|a| a.ended_at.is_none()) {
+                    attempt.ended_at = Some(at);
+                    attempt.outcome = Some(status);
+                    attempt.error = match status {
+                        JobStatusEnum::Completed | JobStatusEnum::Cancelled => None,
+                        _ => self.error.clone(),
+                    };
+                }
+            }
+        }
+        self.timeline.push(JobTransition {
+            status,
+            at,
+            worker: self.assigned_worker.clone(),
+        });
+    }
+
+    /// What the job has cost, once it has started: its queue wait plus
+    /// what its worker reported
+    pub fn usage_info(&self) -> Option<proto::JobUsage> {
+        let started_at = self.started_at?;
+        let usage = self.usage.unwrap_or_default();
+        Some(proto::JobUsage {
+            queue_wait_secs: (started_at - self.submitted_at).max(0) as u64,
+            exec_millis: usage.exec_millis,
+            input_bytes: usage.input_bytes,
+            output_bytes: usage.output_bytes,
+            transferred_bytes: usage.transferred_bytes,
+        })
+    }
+
+    /// Whether a running job has shown no sign of life for `grace_secs`:
+    /// no progress report (the worker may be hung or gone), or a report
+    /// that its compiler process died. A long compile that keeps reporting
+    /// never counts as stalled, however long it runs.
+    pub fn is_stalled(&self, now: i64, grace_secs: i64) -> bool {
+        if self.status != JobStatusEnum::Running {
+            return false;
+        }
+        let last_alive = match &self.progress {
+            Some(progress) if progress.pid.is_some() && !progress.process_alive => return true,
+            Some(progress) => progress.at,
+            None => self.started_at.unwrap_or(self.submitted_at),
+        };
+        now - last_alive > grace_secs
+    }
+
+    /// When a dispatched job has run too long: `timeout_secs` (or the
+    /// scheduler's `default_timeout_secs`, 0 = no limit) after this attempt
+    /// was assigned or started
+    pub fn deadline(&self, default_timeout_secs: u64) -> Option<i64> {
+        let since = match self.status {
+            JobStatusEnum::Running => self.started_at,
+            JobStatusEnum::Assigned => None,
+            _ => return None,
+        };
+        let since = since.or(self.timeline.last().map(|t| t.at)).unwrap_or(self.submitted_at);
+        let timeout = self.timeout_secs.unwrap_or(default_timeout_secs);
+        (timeout > 0).then(|| since + timeout as i64)
+    }
+
+    /// Tenant the job is accounted to: the one it belongs to, else from
+    /// `tenant` or `user` metadata
+    pub fn tenant(&self) -> &str {
+        self.tenant
+            .as_ref()
+            .or_else(|| self.metadata.get("tenant"))
+            .or_else(|| self.metadata.get("user"))
+            .map(|s| s.as_str())
+            .filter(|s| !s.is_empty())
+            .unwrap_or("unknown")
+    }
+
+    /// Case-insensitive substring match on job ID, crate name and error text
+    pub fn matches_text(&self, needle: &str) -> bool {
+        let needle = needle.to_lowercase();
+        [
+            Some(self.job_id.as_str()),
+            self.metadata.get("crate_name").map(|s| s.as_str()),
+            self.error.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|text| text.to_lowercase().contains(&needle))
+    }
+}
+
+impl From<&JobAttempt> for proto::JobAttempt {
+    fn from(attempt: &JobAttempt) -> Self {
+        proto::JobAttempt {
+            worker: attempt.worker.clone(),
+            started_at: attempt.started_at,
+            ended_at: attempt.ended_at.unwrap_or(0),
+            outcome: attempt.outcome.unwrap_or(JobStatusEnum::Running).into(),
+            error: attempt.error.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl From<JobProgress> for proto::JobProgress {
+    fn from(progress: JobProgress) -> Self {
+        proto::JobProgress {
+            at: progress.at,
+            phase: progress.phase,
+            elapsed_secs: progress.elapsed_secs,
+            pid: progress.pid.unwrap_or(0),
+            process_alive: progress.process_alive,
+        }
+    }
+}
+
+impl From<proto::JobUsage> for JobUsage {
+    fn from(usage: proto::JobUsage) -> Self {
+        JobUsage {
+            exec_millis: usage.exec_millis,
+            input_bytes: usage.input_bytes,
+            output_bytes: usage.output_bytes,
+            transferred_bytes: usage.transferred_bytes,
+        }
+    }
+}
+
+/// Machine-readable cause of a job failure, for clients to act on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobErrorKindEnum {
+    #[default]
+    Unspecified,
+    /// The job's CAS namespace hit its quota
+    QuotaExceeded,
+    /// The build itself failed (e.g. a compile error)
+    Compile,
+}
+
+impl From<JobErrorKind> for JobErrorKindEnum {
+    fn from(kind: JobErrorKind) -> Self {
+        match kind {
+            JobErrorKind::Unspecified => JobErrorKindEnum::Unspecified,
+            JobErrorKind::QuotaExceeded => JobErrorKindEnum::QuotaExceeded,
+            JobErrorKind::Compile => JobErrorKindEnum::Compile,
+        }
+    }
+}
+
+impl From<JobErrorKindEnum> for JobErrorKind {
+    fn from(kind: JobErrorKindEnum) -> Self {
+        match kind {
+            JobErrorKindEnum::Unspecified => JobErrorKind::Unspecified,
+            JobErrorKindEnum::QuotaExceeded => JobErrorKind::QuotaExceeded,
+            JobErrorKindEnum::Compile => JobErrorKind::Compile,
+        }
+    }
+}
+
+/// Unlike statuses, an unknown error kind is just unspecified
+impl From<i32> for JobErrorKindEnum {
+    fn from(value: i32) -> Self {
+        JobErrorKind::try_from(value).map(Into::into).unwrap_or_default()
+    }
+}
+
+impl From<JobErrorKindEnum> for i32 {
+    fn from(kind: JobErrorKindEnum) -> Self {
+        JobErrorKind::from(kind) as i32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum JobStatusEnum {
     Pending,
     Assigned,
     Running,
     Completed,
     Failed,
+    Cancelled,
+    /// Waiting on dependencies to complete
+    Blocked,
+    /// Forwarded to another scheduler
+    QueuedRemote,
 }
 
-impl From<i32> for JobStatusEnum {
-    fn from(value: i32) -> Self {
-        match value {
-            0 => JobStatusEnum::Pending,
-            1 => JobStatusEnum::Assigned,
-            2 => JobStatusEnum::Running,
-            3 => JobStatusEnum::Completed,
-            4 => JobStatusEnum::Failed,
-            _ => JobStatusEnum::Failed,
+impl JobStatusEnum {
+    /// Whether the job has finished (successfully or not) and will not change again
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            JobStatusEnum::Completed | JobStatusEnum::Failed | JobStatusEnum::Cancelled
+        )
+    }
+}
+
+// Conversions go through the generated proto enum with exhaustive matches,
+// so adding a state on either side fails to compile until it's mapped here
+
+impl From<JobStatus> for JobStatusEnum {
+    fn from(status: JobStatus) -> Self {
+        match status {
+            JobStatus::Pending => JobStatusEnum::Pending,
+            JobStatus::Assigned => JobStatusEnum::Assigned,
+            JobStatus::Running => JobStatusEnum::Running,
+            JobStatus::Completed => JobStatusEnum::Completed,
+            JobStatus::Failed => JobStatusEnum::Failed,
+            JobStatus::Cancelled => JobStatusEnum::Cancelled,
+            JobStatus::Blocked => JobStatusEnum::Blocked,
+            JobStatus::QueuedRemote => JobStatusEnum::QueuedRemote,
         }
     }
 }
 
-impl From<JobStatusEnum> for i32 {
+impl From<JobStatusEnum> for JobStatus {
     fn from(status: JobStatusEnum) -> Self {
         match status {
-            JobStatusEnum::Pending => 0,
-            JobStatusEnum::Assigned => 1,
-            JobStatusEnum::Running => 2,
-            JobStatusEnum::Completed => 3,
-            JobStatusEnum::Failed => 4,
+            JobStatusEnum::Pending => JobStatus::Pending,
+            JobStatusEnum::Assigned => JobStatus::Assigned,
+            JobStatusEnum::Running => JobStatus::Running,
+            JobStatusEnum::Completed => JobStatus::Completed,
+            JobStatusEnum::Failed => JobStatus::Failed,
+            JobStatusEnum::Cancelled => JobStatus::Cancelled,
+            JobStatusEnum::Blocked => JobStatus::Blocked,
+            JobStatusEnum::QueuedRemote => JobStatus::QueuedRemote,
+        }
+    }
+}
+
+/// Wire values a newer peer may send that this build doesn't know are
+/// rejected rather than guessed at
+impl TryFrom<i32> for JobStatusEnum {
+    type Error = prost::UnknownEnumValue;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        JobStatus::try_from(value).map(JobStatusEnum::from)
+    }
+}
+
+impl From<JobStatusEnum> for i32 {
+    fn from(status: JobStatusEnum) -> Self {
+        JobStatus::from(status) as i32
+    }
+}
+
+impl std::str::FromStr for JobStatusEnum {
+    type Err = String;
+
+    /// Parse the display form, case-insensitively ("failed", "queued-remote")
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "pending" => Ok(JobStatusEnum::Pending),
+            "assigned" => Ok(JobStatusEnum::Assigned),
+            "running" => Ok(JobStatusEnum::Running),
+            "completed" => Ok(JobStatusEnum::Completed),
+            "failed" => Ok(JobStatusEnum::Failed),
+            "cancelled" => Ok(JobStatusEnum::Cancelled),
+            "blocked" => Ok(JobStatusEnum::Blocked),
+            "queued-remote" => Ok(JobStatusEnum::QueuedRemote),
+            _ => Err(format!("Unknown job status: {}", s)),
         }
     }
 }
@@ -56,6 +422,9 @@ impl std::fmt::Display for JobStatusEnum {
             JobStatusEnum::Running => write!(f, "RUNNING"),
             JobStatusEnum::Completed => write!(f, "COMPLETED"),
             JobStatusEnum::Failed => write!(f, "FAILED"),
+            JobStatusEnum::Cancelled => write!(f, "CANCELLED"),
+            JobStatusEnum::Blocked => write!(f, "BLOCKED"),
+            JobStatusEnum::QueuedRemote => write!(f, "QUEUED-REMOTE"),
         }
     }
 }
@@ -68,5 +437,103 @@ pub struct WorkerMetadata {
     pub active_jobs: u32,
     pub last_heartbeat: i64,
     pub labels: HashMap<String, String>,
+    /// Build the worker runs, if it reports one
+    pub version: Option<BuildVersion>,
+    /// Being decommissioned: gets no new jobs, and is removed once the ones
+    /// it has finish
+    #[serde(default)]
+    pub draining: bool,
+    /// Failing too many jobs: gets none until then, and afterwards is on
+    /// probation (one job at a time) until one succeeds
+    #[serde(default)]
+    pub quarantined_until: Option<i64>,
+    /// Only runs this tenant's jobs; `None` runs anyone's
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Toolchains, targets and job types it reported; `None` from workers
+    /// that predate reporting them, which are taken to run anything
+    #[serde(default)]
+    pub capabilities: Option<Capabilities>,
+    /// Group of like workers (e.g. `big-mem`) jobs can be sent to by name
+    #[serde(default)]
+    pub pool: Option<String>,
+    /// Where it stands in its lifecycle, as of the scheduler's last look
+    #[serde(default)]
+    pub state: WorkerStateEnum,
+    /// When it entered `state`
+    #[serde(default)]
+    pub state_since: i64,
+    /// Blobs it recently had locally, per its last heartbeat (jobs needing
+    /// them are preferably placed there)
+    #[serde(skip)]
+    pub cached_hashes: Arc<HashSet<String>>,
+}
+
+/// Where a worker stands: taking jobs, overdue with its heartbeats,
+/// given up on, or being decommissioned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WorkerStateEnum {
+    #[default]
+    Online,
+    Suspect,
+    Offline,
+    Draining,
+}
+
+impl From<WorkerState> for WorkerStateEnum {
+    fn from(state: WorkerState) -> Self {
+        match state {
+            WorkerState::Online => WorkerStateEnum::Online,
+            WorkerState::Suspect => WorkerStateEnum::Suspect,
+            WorkerState::Offline => WorkerStateEnum::Offline,
+            WorkerState::Draining => WorkerStateEnum::Draining,
+        }
+    }
+}
+
+impl From<WorkerStateEnum> for WorkerState {
+    fn from(state: WorkerStateEnum) -> Self {
+        match state {
+            WorkerStateEnum::Online => WorkerState::Online,
+            WorkerStateEnum::Suspect => WorkerState::Suspect,
+            WorkerStateEnum::Offline => WorkerState::Offline,
+            WorkerStateEnum::Draining => WorkerState::Draining,
+        }
+    }
+}
+
+impl From<i32> for WorkerStateEnum {
+    fn from(value: i32) -> Self {
+        WorkerState::try_from(value).map(Into::into).unwrap_or_default()
+    }
+}
+
+impl From<WorkerStateEnum> for i32 {
+    fn from(state: WorkerStateEnum) -> Self {
+        WorkerState::from(state) as i32
+    }
+}
+
+impl std::fmt::Display for WorkerStateEnum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkerStateEnum::Online => write!(f, "ONLINE"),
+            WorkerStateEnum::Suspect => write!(f, "SUSPECT"),
+            WorkerStateEnum::Offline => write!(f, "OFFLINE"),
+            WorkerStateEnum::Draining => write!(f, "DRAINING"),
+        }
+    }
+}
+
+impl WorkerMetadata {
+    /// Jobs it may have at once now: none while quarantined, one on
+    /// probation, otherwise its capacity
+    pub fn capacity_at(&self, now: i64) -> u32 {
+        match self.quarantined_until {
+            Some(until) if now < until => 0,
+            Some(_) => self.capacity.min(1),
+            None => self.capacity,
+        }
+    }
 }
 
@@ -0,0 +1,116 @@
+use crate::proto::distbuild::VersionInfo;
+use serde::{Deserialize, Serialize};
+
+/// Wire protocol revision. Bump it when a change means older binaries can
+/// no longer talk to newer ones (a field changing type, an RPC removed),
+/// so mismatched nodes are refused up front instead of failing mid-build.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Which build of cargo-distbuild a node runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildVersion {
+    pub version: String,
+    pub git_commit: String,
+    pub protocol: u32,
+}
+
+impl BuildVersion {
+    /// This binary's version
+    pub fn current() -> Self {
+        BuildVersion {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: env!("DISTBUILD_GIT_COMMIT").to_string(),
+            protocol: PROTOCOL_VERSION,
+        }
+    }
+
+    /// Why a node running `other` can't work with this one, if it can't
+    pub fn incompatibility(&self, other: &BuildVersion) -> Option<String> {
+        (self.protocol != other.protocol).then(|| {
+            format!(
+                "protocol v{} is incompatible with protocol v{} ({} vs {}); upgrade both to the same release",
+                other.protocol, self.protocol, other, self
+            )
+        })
+    }
+
+    /// Whether `other` is a different build, compatible or not
+    pub fn differs(&self, other: &BuildVersion) -> bool {
+        self.version != other.version || self.git_commit != other.git_commit
+    }
+}
+
+impl std::fmt::Display for BuildVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.version, self.git_commit)
+    }
+}
+
+impl From<VersionInfo> for BuildVersion {
+    fn from(info: VersionInfo) -> Self {
+        BuildVersion {
+            version: info.version,
+            git_commit: info.git_commit,
+            protocol: info.protocol_version,
+        }
+    }
+}
+
+impl From<BuildVersion> for VersionInfo {
+    fn from(version: BuildVersion) -> Self {
+        VersionInfo {
+            version: version.version,
+            git_commit: version.git_commit,
+            protocol_version: version.protocol,
+        }
+    }
+}
+
+/// Warnings about nodes (name, version if reported) running a different
+/// build than `reference`; incompatible ones are flagged as such
+pub fn fleet_warnings(reference: &BuildVersion, nodes: &[(String, Option<BuildVersion>)]) -> Vec<String> {
+    nodes
+        .iter()
+        .filter_map(|(name, version)| match version {
+            None => Some(format!("{} doesn't report its version (older than version reporting)", name)),
+            Some(version) => match reference.incompatibility(version) {
+                Some(reason) => Some(format!("{} is INCOMPATIBLE: {}", name, reason)),
+                None if reference.differs(version) => {
+                    Some(format!("{} runs {} but the scheduler runs {}", name, version, reference))
+                }
+                None => None,
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(version: &str, protocol: u32) -> BuildVersion {
+        BuildVersion {
+            version: version.to_string(),
+            git_commit: "abc123".to_string(),
+            protocol,
+        }
+    }
+
+    #[test]
+    fn test_fleet_warnings() {
+        let scheduler = build("0.2.0", 1);
+        let nodes = vec![
+            ("same".to_string(), Some(build("0.2.0", 1))),
+            ("older".to_string(), Some(build("0.1.9", 1))),
+            ("ancient".to_string(), Some(build("0.1.0", 0))),
+            ("silent".to_string(), None),
+        ];
+
+        let warnings = fleet_warnings(&scheduler, &nodes);
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].starts_with("older runs 0.1.9"));
+        assert!(warnings[1].starts_with("ancient is INCOMPATIBLE"));
+        assert!(warnings[2].starts_with("silent doesn't report"));
+        assert!(scheduler.incompatibility(&build("0.1.9", 1)).is_none());
+    }
+}
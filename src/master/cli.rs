@@ -1,7 +1,12 @@
+use crate::cas::verify::CorruptAction;
+use crate::common::config::{Role, StrategyKind};
+use crate::common::types::JobStatusEnum;
 use crate::common::Config;
-use crate::master::commands::CommandExecutor;
+use crate::master::commands::{AuditFilter, CommandExecutor, HistoryFilter, JobFilter};
+use crate::proto::distbuild::ClusterEventKind;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use std::path::Path;
 
 #[derive(Parser)]
 #[command(name = "cargo-distbuild")]
@@ -15,6 +20,10 @@ pub struct Cli {
 pub enum Commands {
     /// CAS operations
     Cas {
+        /// Operate on this project namespace instead of the configured one
+        #[arg(long, global = true)]
+        namespace: Option<String>,
+
         #[command(subcommand)]
         action: CasCommands,
     },
@@ -36,6 +45,16 @@ pub enum Commands {
         #[command(subcommand)]
         action: MasterCommands,
     },
+
+    /// Check CAS access, scheduler connectivity, clock skew and versions
+    Doctor,
+
+    /// Summarize the last wrapped `cargo build` of a workspace
+    BuildReport {
+        /// Workspace (or package) directory
+        #[arg(default_value = ".")]
+        workspace: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -54,6 +73,18 @@ pub enum CasCommands {
         output: String,
     },
     
+    /// Print a blob, listing tarballs and pretty-printing JSON
+    Cat {
+        /// Hash of the blob
+        hash: String,
+    },
+    
+    /// Show how a blob is stored and its recorded origin and access times
+    Inspect {
+        /// Hash of the blob
+        hash: String,
+    },
+    
     /// Check if a hash exists
     Exists {
         /// Hash to check
@@ -62,6 +93,85 @@ pub enum CasCommands {
     
     /// List all blobs in CAS
     List,
+    
+    /// Remove a blob (refuses if an in-flight job needs it)
+    Rm {
+        /// Hash of the blob
+        hash: String,
+        
+        /// Remove even if referenced by in-flight jobs or the scheduler is unreachable
+        #[arg(long)]
+        force: bool,
+    },
+    
+    /// Show blob count, sizes and largest blobs
+    Stats,
+    
+    /// List project namespaces
+    Namespaces,
+    
+    /// Re-hash all blobs and report corrupt ones
+    Verify {
+        /// Limit scrub throughput in MiB/s (0 = unlimited)
+        #[arg(long, default_value = "0")]
+        rate: u64,
+        
+        /// Delete corrupt blobs
+        #[arg(long, conflicts_with = "quarantine")]
+        delete: bool,
+        
+        /// Move corrupt blobs to <cas-root>/.quarantine
+        #[arg(long)]
+        quarantine: bool,
+    },
+    
+    /// Show which jobs reference a blob
+    Refs {
+        /// Hash of the blob
+        hash: String,
+    },
+    
+    /// Export blobs into a seed archive
+    Export {
+        /// Archive file to write (.tar.gz)
+        file: String,
+        
+        /// Only export blobs written within this window (e.g. 7d)
+        #[arg(long, value_parser = parse_duration_secs)]
+        max_age: Option<u64>,
+        
+        /// Only export hashes listed in this file (one per line)
+        #[arg(long)]
+        manifest: Option<String>,
+    },
+    
+    /// Import blobs from a seed archive
+    Import {
+        /// Archive file to read
+        file: String,
+    },
+    
+    /// Garbage-collect old blobs not pinned by in-flight jobs
+    Gc {
+        /// Only delete blobs older than this many seconds
+        #[arg(long, default_value = "604800")]
+        max_age: u64,
+        
+        /// Run even if the scheduler can't be reached for the pin list
+        #[arg(long)]
+        ignore_pins: bool,
+    },
+    
+    /// Pack small blobs into packfiles (default sizes from [cas.packing])
+    Compact {
+        /// Pack blobs up to this many KiB
+        #[arg(long)]
+        max_blob_kib: Option<u64>,
+        
+        /// Only pack blobs untouched for this many seconds
+        #[arg(long)]
+        min_age: Option<u64>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -71,10 +181,40 @@ pub enum SchedulerCommands {
         /// Address to bind to (default: from config)
         #[arg(long)]
         addr: Option<String>,
+        /// Serve reads only, following the primary scheduler that persists
+        /// to this state dir (default: from config)
+        #[arg(long)]
+        mirror_of: Option<String>,
     },
     
     /// Show scheduler status
     Status,
+    
+    /// Queue depth, job counts, throughput, queue latency and worker load
+    Stats {
+        /// Window for throughput and queue latency (e.g. 15m)
+        #[arg(long, default_value = "5m", value_parser = parse_duration_secs)]
+        window: u64,
+    },
+    
+    /// Replay a recorded job trace against scheduling strategies offline,
+    /// reporting makespan, utilization and queue latency for each
+    Simulate {
+        /// Scheduler journal, job archive or state dir holding them
+        trace: String,
+        
+        /// least-loaded, round-robin or random (repeatable; default: all)
+        #[arg(long = "strategy", value_parser = parse_strategy)]
+        strategies: Vec<StrategyKind>,
+        
+        /// Replay on this many identical workers instead of the traced ones
+        #[arg(long)]
+        workers: Option<usize>,
+        
+        /// Job slots of each worker given with --workers
+        #[arg(long, default_value = "4")]
+        capacity: u32,
+    },
 }
 
 #[derive(Subcommand)]
@@ -97,6 +237,42 @@ pub enum MasterCommands {
     SubmitJob {
         /// Input hash from CAS
         input_hash: String,
+        
+        /// Queued jobs with higher priority are dispatched first
+        #[arg(long, default_value = "0", allow_negative_numbers = true)]
+        priority: i32,
+        
+        /// Only run on workers with this label, as KEY=VALUE (repeatable)
+        #[arg(long = "constraint", value_parser = parse_label)]
+        constraints: Vec<(String, String)>,
+        
+        /// Fail (or retry) the job if it runs longer, e.g. 30m (default:
+        /// the scheduler's job_timeout_secs)
+        #[arg(long, value_parser = parse_duration_secs)]
+        timeout: Option<u64>,
+        
+        /// Queue it now but dispatch it no sooner than this: a time (RFC
+        /// 3339, e.g. 2026-01-31T02:00:00Z) or a delay (e.g. 8h)
+        #[arg(long, value_parser = parse_run_after)]
+        run_after: Option<i64>,
+        
+        /// Only run on workers in this pool (default: the wrapper's pool,
+        /// if set, else any worker)
+        #[arg(long)]
+        pool: Option<String>,
+    },
+    
+    /// Submit a build plan: a JSON list of jobs ({"name", "input_hash",
+    /// "priority", "depends_on": [names], "constraints": {labels},
+    /// "run_after": unix time, "pool"}) in dependency order
+    SubmitPlan {
+        /// Plan file
+        file: String,
+        
+        /// Dispatch the jobs all at once, only when every one fits (none
+        /// may depend on another)
+        #[arg(long)]
+        gang: bool,
     },
     
     /// Get job status
@@ -105,22 +281,296 @@ pub enum MasterCommands {
         job_id: String,
     },
     
+    /// Show a job's spec, timeline, worker, resource usage and log tail
+    InspectJob {
+        /// Job ID
+        job_id: String,
+    },
+    
+    /// Print the log of a job's latest run (kept after it finished)
+    JobLogs {
+        /// Job ID
+        job_id: String,
+    },
+    
+    /// Cancel a job that hasn't finished
+    CancelJob {
+        /// Job ID
+        job_id: String,
+    },
+    
     /// List jobs
     ListJobs {
         /// Maximum number of jobs to show
         #[arg(long, default_value = "10")]
         limit: u32,
+        
+        /// Only jobs submitted within this window (e.g. 2h)
+        #[arg(long, value_parser = parse_duration_secs)]
+        since: Option<u64>,
+        
+        /// Only jobs submitted longer ago than this (e.g. 30m)
+        #[arg(long, value_parser = parse_duration_secs)]
+        older_than: Option<u64>,
+        
+        /// Only jobs in this state (repeatable, e.g. --status failed)
+        #[arg(long)]
+        status: Vec<JobStatusEnum>,
+        
+        /// Only jobs assigned to this worker
+        #[arg(long)]
+        worker: Option<String>,
+        
+        /// Only jobs of this type (e.g. rust-compile)
+        #[arg(long)]
+        job_type: Option<String>,
+        
+        /// Only jobs of this tenant
+        #[arg(long)]
+        tenant: Option<String>,
+        
+        /// Only jobs of this build session
+        #[arg(long)]
+        session: Option<String>,
+        
+        /// Only jobs whose ID, crate name or error contains this text
+        #[arg(long)]
+        grep: Option<String>,
+        
+        /// Show the next page, from the cursor printed after the last one
+        #[arg(long)]
+        cursor: Option<String>,
+    },
+    
+    /// List builds (one per cargo invocation) and their progress
+    ListSessions {
+        /// Maximum number of sessions to show
+        #[arg(long, default_value = "10")]
+        limit: u32,
+        
+        /// Only sessions with unfinished jobs
+        #[arg(long)]
+        active: bool,
+    },
+    
+    /// Show a build session's status, timing and jobs
+    Session {
+        /// Session ID
+        session_id: String,
+    },
+    
+    /// Cancel every unfinished job of a build session
+    CancelSession {
+        /// Session ID
+        session_id: String,
     },
     
     /// List workers
     ListWorkers,
+    
+    /// Take a worker out of rotation: no new jobs, and it's removed once
+    /// the ones it has finish
+    DrainWorker {
+        /// Worker ID
+        worker_id: String,
+        
+        /// How long to wait for its jobs (e.g. 30m); it stays draining
+        /// if they haven't finished by then
+        #[arg(long, default_value = "10m", value_parser = parse_duration_secs)]
+        timeout: u64,
+    },
+    
+    /// Follow cluster events (workers joining and leaving, jobs submitted
+    /// and finishing) until interrupted
+    Events {
+        /// Only this kind, e.g. worker-offline or job-failed (repeatable)
+        #[arg(long = "kind", value_parser = parse_event_kind)]
+        kinds: Vec<ClusterEventKind>,
+    },
+    
+    /// Per-tenant queue wait and cluster share report
+    FairnessReport {
+        /// Time window to report on (e.g. 30m, 1h, 2d)
+        #[arg(long, default_value = "1h", value_parser = parse_duration_secs)]
+        window: u64,
+    },
+    
+    /// List infrastructure errors reported by wrappers
+    ClientErrors {
+        /// Maximum number of errors to show
+        #[arg(long, default_value = "20")]
+        limit: u32,
+    },
+    
+    /// Who submitted, cancelled, reprioritized or drained what, newest first
+    Audit {
+        /// Maximum number of actions to show
+        #[arg(long, default_value = "50")]
+        limit: u32,
+        
+        /// Only actions within this window (e.g. 24h)
+        #[arg(long, value_parser = parse_duration_secs)]
+        since: Option<u64>,
+        
+        /// Only this actor's (token identity, or address without auth)
+        #[arg(long)]
+        actor: Option<String>,
+        
+        /// Only this kind, e.g. cancel-job or drain-worker
+        #[arg(long)]
+        action: Option<String>,
+        
+        /// Only actions on this job, session or worker
+        #[arg(long)]
+        target: Option<String>,
+    },
+    
+    /// Search finished jobs, most recently finished first, including ones
+    /// the scheduler no longer keeps
+    JobHistory {
+        /// Maximum number of jobs to show
+        #[arg(long, default_value = "20")]
+        limit: u32,
+        
+        /// Only jobs finished within this window (e.g. 7d)
+        #[arg(long, value_parser = parse_duration_secs)]
+        since: Option<u64>,
+        
+        /// Only builds of this crate
+        #[arg(long = "crate")]
+        crate_name: Option<String>,
+        
+        /// Only jobs that ended in this state (repeatable, e.g. --status failed)
+        #[arg(long)]
+        status: Vec<JobStatusEnum>,
+        
+        /// Only jobs last run on this worker
+        #[arg(long)]
+        worker: Option<String>,
+        
+        /// Only jobs of this tenant
+        #[arg(long)]
+        tenant: Option<String>,
+    },
+    
+    /// Recommended worker count from queue depth and throughput
+    ScalingAdvice {
+        /// Window for arrival rate and job duration (e.g. 5m)
+        #[arg(long, default_value = "5m", value_parser = parse_duration_secs)]
+        window: u64,
+        
+        /// Fraction of worker slots to keep busy
+        #[arg(long, default_value = "0.8")]
+        target_utilization: f64,
+        
+        /// Keep refreshing every few seconds
+        #[arg(long)]
+        watch: bool,
+    },
+    
+    /// Save the scheduler's state and have it exit, for its supervisor to
+    /// restart it (e.g. on a new version); queued jobs carry over
+    SchedulerRestart {
+        /// Let running jobs finish first
+        #[arg(long)]
+        drain: bool,
+        
+        /// Give up on draining after this long (e.g. 10m)
+        #[arg(long, default_value = "10m", value_parser = parse_duration_secs)]
+        timeout: u64,
+    },
+    
+    /// Stop admitting and dispatching jobs and wait for running ones, so
+    /// the cluster can be upgraded or taken down safely
+    ClusterQuiesce {
+        /// Stop waiting for running jobs after this long (e.g. 10m)
+        #[arg(long, default_value = "10m", value_parser = parse_duration_secs)]
+        timeout: u64,
+    },
+    
+    /// Admit and dispatch jobs again after a quiesce
+    ClusterResume,
+}
+
+/// Parse a human duration like `45s`, `30m`, `1h` or `2d` into seconds
+/// (a bare number is taken as seconds)
+pub fn parse_duration_secs(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => s.split_at(idx),
+        None => (s, "s"),
+    };
+
+    let value: u64 = num
+        .parse()
+        .map_err(|_| format!("Invalid duration: {}", s))?;
+
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("Invalid duration unit in {} (use s, m, h or d)", s)),
+    };
+
+    Ok(value * multiplier)
+}
+
+/// Parse a cluster event kind like `job-failed`
+pub fn parse_event_kind(s: &str) -> Result<ClusterEventKind, String> {
+    let name = format!("CLUSTER_EVENT_KIND_{}", s.trim().to_ascii_uppercase().replace('-', "_"));
+    ClusterEventKind::from_str_name(&name)
+        .filter(|kind| *kind != ClusterEventKind::Unspecified)
+        .ok_or_else(|| {
+            format!(
+                "Unknown event kind: {} (use worker-online, worker-offline, job-submitted, \
+                job-completed, job-failed or job-cancelled)",
+                s
+            )
+        })
+}
+
+/// Parse when to run a job, as an RFC 3339 time or a delay from now, into
+/// a unix time
+fn parse_run_after(s: &str) -> Result<i64, String> {
+    if let Ok(at) = chrono::DateTime::parse_from_rfc3339(s.trim()) {
+        return Ok(at.timestamp());
+    }
+    let delay = parse_duration_secs(s).map_err(|_| format!("Invalid time or delay: {} (e.g. 2026-01-31T02:00:00Z or 8h)", s))?;
+    Ok(chrono::Utc::now().timestamp() + delay as i64)
+}
+
+/// Parse a scheduling strategy like `least-loaded`
+fn parse_strategy(s: &str) -> Result<StrategyKind, String> {
+    match s.trim() {
+        "least-loaded" => Ok(StrategyKind::LeastLoaded),
+        "round-robin" => Ok(StrategyKind::RoundRobin),
+        "random" => Ok(StrategyKind::Random),
+        _ => Err(format!("Unknown strategy: {} (use least-loaded, round-robin or random)", s)),
+    }
+}
+
+/// Parse a `KEY=VALUE` worker label
+fn parse_label(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("Invalid label {} (expected KEY=VALUE)", s)),
+    }
 }
 
 pub async fn run_cli(cli: Cli) -> Result<()> {
-    let config = Config::load_default()?;
+    let role = match &cli.command {
+        Some(Commands::Scheduler { action: SchedulerCommands::Run { .. } }) => Role::Scheduler,
+        Some(Commands::Worker { .. }) => Role::Worker,
+        _ => Role::Client,
+    };
+    let mut config = Config::load_default_for(role)?;
 
     match cli.command {
-        Some(Commands::Cas { action }) => {
+        Some(Commands::Cas { namespace, action }) => {
+            if namespace.is_some() {
+                config.cas.namespace = namespace;
+            }
             let executor = CommandExecutor::new(config)?;
             
             match action {
@@ -130,32 +580,98 @@ pub async fn run_cli(cli: Cli) -> Result<()> {
                 CasCommands::Get { hash, output } => {
                     executor.cas_get(&hash, &output).await?;
                 }
+                CasCommands::Cat { hash } => {
+                    executor.cas_cat(&hash).await?;
+                }
+                CasCommands::Inspect { hash } => {
+                    executor.cas_inspect(&hash).await?;
+                }
                 CasCommands::Exists { hash } => {
                     executor.cas_exists(&hash).await?;
                 }
                 CasCommands::List => {
                     executor.cas_list().await?;
                 }
+                CasCommands::Rm { hash, force } => {
+                    executor.cas_rm(&hash, force).await?;
+                }
+                CasCommands::Stats => {
+                    executor.cas_stats().await?;
+                }
+                CasCommands::Namespaces => {
+                    executor.cas_namespaces().await?;
+                }
+                CasCommands::Verify { rate, delete, quarantine } => {
+                    let action = if delete {
+                        CorruptAction::Delete
+                    } else if quarantine {
+                        CorruptAction::Quarantine
+                    } else {
+                        CorruptAction::Report
+                    };
+                    executor.cas_verify(rate, action).await?;
+                }
+                CasCommands::Refs { hash } => {
+                    executor.cas_refs(&hash).await?;
+                }
+                CasCommands::Export { file, max_age, manifest } => {
+                    executor.cas_export(&file, max_age, manifest.as_deref()).await?;
+                }
+                CasCommands::Import { file } => {
+                    executor.cas_import(&file).await?;
+                }
+                CasCommands::Gc { max_age, ignore_pins } => {
+                    executor.cas_gc(max_age, ignore_pins).await?;
+                }
+                CasCommands::Compact { max_blob_kib, min_age } => {
+                    executor.cas_compact(max_blob_kib, min_age).await?;
+                }
             }
         }
         
         Some(Commands::Scheduler { action }) => {
             match action {
-                SchedulerCommands::Run { addr } => {
-                    let scheduler_addr = addr.unwrap_or(config.scheduler.addr);
-                    crate::scheduler::run_scheduler(scheduler_addr).await?;
+                SchedulerCommands::Run { addr, mirror_of } => {
+                    crate::common::logging::init("scheduler", &config.logging.scheduler)?;
+                    let mut scheduler_config = config.scheduler;
+                    if let Some(addr) = addr {
+                        scheduler_config.addr = addr;
+                    }
+                    if mirror_of.is_some() {
+                        scheduler_config.mirror_of = mirror_of;
+                    }
+                    let cas = std::sync::Arc::new(crate::cas::Cas::for_service(&config.cas)?);
+                    if let Some(http_addr) = scheduler_config.http_addr.clone() {
+                        let cas = cas.clone();
+                        let auth = crate::common::auth::Authenticator::from_config(&scheduler_config);
+                        tokio::spawn(async move {
+                            if let Err(e) = crate::cas::http::serve(cas, &http_addr, auth).await {
+                                log::error!("❌ Blob HTTP server failed: {}", e);
+                            }
+                        });
+                    }
+                    crate::scheduler::run_scheduler_with_config(scheduler_config, Some(cas)).await?;
                 }
                 SchedulerCommands::Status => {
                     let executor = CommandExecutor::new(config)?;
                     executor.scheduler_status().await?;
                 }
+                SchedulerCommands::Stats { window } => {
+                    let executor = CommandExecutor::new(config)?;
+                    executor.scheduler_stats(window).await?;
+                }
+                SchedulerCommands::Simulate { trace, strategies, workers, capacity } => {
+                    let executor = CommandExecutor::new(config)?;
+                    executor.simulate(Path::new(&trace), &strategies, workers, capacity).await?;
+                }
             }
         }
         
         Some(Commands::Worker { action }) => {
             match action {
                 WorkerCommands::Run { id, port } => {
-                    let cas = std::sync::Arc::new(crate::cas::Cas::new(&config.cas.root)?);
+                    crate::common::logging::init("worker", &config.logging.worker)?;
+                    let cas = std::sync::Arc::new(crate::cas::Cas::for_service(&config.cas)?);
                     crate::worker::run_worker(id, port, config, cas).await?;
                 }
             }
@@ -165,21 +681,112 @@ pub async fn run_cli(cli: Cli) -> Result<()> {
             let executor = CommandExecutor::new(config)?;
             
             match action {
-                MasterCommands::SubmitJob { input_hash } => {
-                    executor.submit_job(&input_hash).await?;
+                MasterCommands::SubmitJob { input_hash, priority, constraints, timeout, run_after, pool } => {
+                    executor.submit_job(&input_hash, priority, constraints.into_iter().collect(), timeout, run_after, pool).await?;
+                }
+                MasterCommands::SubmitPlan { file, gang } => {
+                    executor.submit_plan(Path::new(&file), gang).await?;
                 }
                 MasterCommands::JobStatus { job_id } => {
                     executor.job_status(&job_id).await?;
                 }
-                MasterCommands::ListJobs { limit } => {
-                    executor.list_jobs(limit).await?;
+                MasterCommands::InspectJob { job_id } => {
+                    executor.inspect_job(&job_id).await?;
+                }
+                MasterCommands::JobLogs { job_id } => {
+                    executor.job_logs(&job_id).await?;
+                }
+                MasterCommands::CancelJob { job_id } => {
+                    executor.cancel_job(&job_id).await?;
+                }
+                MasterCommands::ListJobs { limit, since, older_than, status, worker, job_type, tenant, session, grep, cursor } => {
+                    executor
+                        .list_jobs(JobFilter {
+                            limit,
+                            since_secs: since,
+                            older_than_secs: older_than,
+                            statuses: status,
+                            worker,
+                            job_type,
+                            tenant,
+                            session,
+                            grep,
+                            cursor,
+                        })
+                        .await?;
+                }
+                MasterCommands::ListSessions { limit, active } => {
+                    executor.list_sessions(limit, active).await?;
+                }
+                MasterCommands::Session { session_id } => {
+                    executor.show_session(&session_id).await?;
+                }
+                MasterCommands::CancelSession { session_id } => {
+                    executor.cancel_session(&session_id).await?;
                 }
                 MasterCommands::ListWorkers => {
                     executor.list_workers().await?;
                 }
+                MasterCommands::DrainWorker { worker_id, timeout } => {
+                    executor.drain_worker(&worker_id, timeout).await?;
+                }
+                MasterCommands::Events { kinds } => {
+                    executor.events(&kinds).await?;
+                }
+                MasterCommands::FairnessReport { window } => {
+                    executor.fairness_report(window).await?;
+                }
+                MasterCommands::ClientErrors { limit } => {
+                    executor.client_errors(limit).await?;
+                }
+                MasterCommands::Audit { limit, since, actor, action, target } => {
+                    executor
+                        .audit(AuditFilter {
+                            limit,
+                            since_secs: since,
+                            actor,
+                            action,
+                            target,
+                        })
+                        .await?;
+                }
+                MasterCommands::JobHistory { limit, since, crate_name, status, worker, tenant } => {
+                    executor
+                        .job_history(HistoryFilter {
+                            limit,
+                            since_secs: since,
+                            crate_name,
+                            statuses: status,
+                            worker,
+                            tenant,
+                        })
+                        .await?;
+                }
+                MasterCommands::ScalingAdvice { window, target_utilization, watch } => {
+                    executor.scaling_advice(window, target_utilization, watch).await?;
+                }
+                MasterCommands::SchedulerRestart { drain, timeout } => {
+                    executor.quiesce(drain, timeout, true).await?;
+                }
+                MasterCommands::ClusterQuiesce { timeout } => {
+                    executor.quiesce(true, timeout, false).await?;
+                }
+                MasterCommands::ClusterResume => {
+                    executor.cluster_resume().await?;
+                }
             }
         }
         
+        Some(Commands::Doctor) => {
+            let executor = CommandExecutor::new(config)?;
+            executor.doctor().await?;
+        }
+
+        Some(Commands::BuildReport { workspace }) => {
+            let executor = CommandExecutor::new(config)?;
+            executor.build_report(&workspace).await?;
+        }
+
         None => {
             // No command provided - start interactive REPL
             crate::master::repl::run_repl().await?;
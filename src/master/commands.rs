@@ -1,22 +1,114 @@
+use crate::cas::archive::ExportFilter;
+use crate::cas::inspect::{inspect, BlobView, HEX_PREVIEW_BYTES};
+use crate::cas::metrics::CasMetrics;
+use crate::cas::replication::Replicator;
+use crate::cas::retention::RetentionPolicy;
+use crate::cas::verify::{CorruptAction, VerifyOptions};
 use crate::cas::Cas;
+use crate::common::auth::{AuthedSchedulerClient, ClientAuth};
+use crate::common::clock;
+use crate::common::error::{rejection, Rejection};
+use crate::common::session::{workspace_root, BuildSession, SessionReport};
+use crate::common::types::{JobStatusEnum, WorkerStateEnum};
+use crate::common::version::{fleet_warnings, BuildVersion};
+use crate::common::config::StrategyKind;
 use crate::common::Config;
-use crate::proto::distbuild::scheduler_client::SchedulerClient;
 use crate::proto::distbuild::*;
+use crate::scheduler::Trace;
 use anyhow::{Context, Result};
 use colored::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
 use uuid::Uuid;
 
+/// A job in a build plan file: `name` is how other entries' `depends_on`
+/// refer to it (anything else there is taken as an already submitted job ID)
+#[derive(Debug, Deserialize)]
+struct PlannedJob {
+    name: String,
+    input_hash: String,
+    #[serde(default)]
+    job_type: Option<String>,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    depends_on: Vec<String>,
+    #[serde(default)]
+    constraints: HashMap<String, String>,
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+    /// Unix time before which it isn't dispatched
+    #[serde(default)]
+    run_after: Option<i64>,
+    /// Worker pool it's sent to, instead of the wrapper's
+    #[serde(default)]
+    pool: Option<String>,
+}
+
+/// Which jobs `list_jobs` shows, one page at a time
+#[derive(Debug, Clone, Default)]
+pub struct JobFilter {
+    /// Page size (0 = everything)
+    pub limit: u32,
+    /// Only jobs submitted within this many seconds
+    pub since_secs: Option<u64>,
+    /// Only jobs submitted over this many seconds ago
+    pub older_than_secs: Option<u64>,
+    /// Only jobs in one of these states (empty = any)
+    pub statuses: Vec<JobStatusEnum>,
+    pub worker: Option<String>,
+    pub job_type: Option<String>,
+    pub tenant: Option<String>,
+    /// Only jobs of this build session
+    pub session: Option<String>,
+    /// Only jobs whose ID, crate name or error contains this text
+    pub grep: Option<String>,
+    /// Where the previous page left off
+    pub cursor: Option<String>,
+}
+
+/// Which audit records `audit` shows
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    /// Most recent records shown (0 = all)
+    pub limit: u32,
+    /// Only actions within this many seconds
+    pub since_secs: Option<u64>,
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    /// Only actions on this job, session or worker
+    pub target: Option<String>,
+}
+
+/// Which finished jobs `job_history` shows
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    /// Most recently finished shown (0 = all)
+    pub limit: u32,
+    /// Only jobs finished within this many seconds
+    pub since_secs: Option<u64>,
+    pub crate_name: Option<String>,
+    /// Only jobs that ended in one of these states (empty = any)
+    pub statuses: Vec<JobStatusEnum>,
+    /// Only jobs last run on this worker
+    pub worker: Option<String>,
+    pub tenant: Option<String>,
+}
+
 pub struct CommandExecutor {
     config: Config,
     cas: Cas,
+    auth: ClientAuth,
 }
 
 impl CommandExecutor {
     pub fn new(config: Config) -> Result<Self> {
-        let cas = Cas::new(&config.cas.root)?;
-        Ok(CommandExecutor { config, cas })
+        let cas = Cas::from_config(&config.cas)?;
+        let auth = ClientAuth::from_config(&config.scheduler)?;
+        Ok(CommandExecutor { config, cas, auth })
     }
 
     pub async fn cas_put(&self, file_path: &str) -> Result<()> {
@@ -35,20 +127,83 @@ impl CommandExecutor {
     }
 
     pub async fn cas_get(&self, hash: &str, output_path: &str) -> Result<()> {
-        let data = self.cas.get(hash)
+        let mut reader = self.cas.get_reader(hash)
             .with_context(|| format!("Hash not found in CAS: {}", hash))?;
 
-        fs::write(output_path, &data)
+        let mut file = fs::File::create(output_path)
+            .with_context(|| format!("Failed to create: {}", output_path))?;
+        let size = std::io::copy(&mut reader, &mut file)
             .with_context(|| format!("Failed to write to: {}", output_path))?;
 
         println!("{}", "✅ File retrieved from CAS".green());
         println!("   Hash: {}", hash.bright_cyan());
-        println!("   Size: {} bytes", data.len());
+        println!("   Size: {} bytes", size);
         println!("   Saved to: {}", output_path);
 
         Ok(())
     }
 
+    pub async fn cas_cat(&self, hash: &str) -> Result<()> {
+        let data = self.cas.get(hash)
+            .with_context(|| format!("Hash not found in CAS: {}", hash))?;
+        let view = inspect(&data);
+
+        println!("{} ({}, {})", hash.bright_cyan(), view.kind().bold(), format_bytes(data.len() as u64));
+        match view {
+            BlobView::Tar { entries, .. } => {
+                for (path, size) in &entries {
+                    println!("   {:>10}  {}", format_bytes(*size), path);
+                }
+                println!("   {} entries", entries.len());
+            }
+            BlobView::Json(pretty) => println!("{}", pretty),
+            BlobView::Text(text) => println!("{}", text),
+            BlobView::Binary { size, preview } => {
+                println!("{}", preview);
+                if size > HEX_PREVIEW_BYTES {
+                    println!("   ... {} bytes total", size);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn cas_inspect(&self, hash: &str) -> Result<()> {
+        if !self.cas.exists(hash) {
+            anyhow::bail!("Hash {} not found in CAS", hash);
+        }
+
+        println!("{}", hash.bright_cyan());
+        match self.cas.chunk_list(hash)? {
+            Some(chunks) => println!("   Storage: {} chunks", chunks.len()),
+            None => println!("   Storage: single blob"),
+        }
+
+        let Some(meta) = self.cas.blob_meta(hash) else {
+            println!("   {}", "No metadata recorded (enable `blob_metadata` under [cas])".yellow());
+            return Ok(());
+        };
+        let now = chrono::Utc::now().timestamp();
+        let at = |ts: i64| {
+            let when = chrono::DateTime::from_timestamp(ts, 0)
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_default();
+            format!("{} ({}s ago)", when, (now - ts).max(0))
+        };
+        println!("   Size: {}", format_bytes(meta.size));
+        println!("   Created: {}", at(meta.created_at));
+        println!("   Last access: {}", at(meta.last_access));
+        if let Some(job_id) = &meta.job_id {
+            println!("   Job: {}", job_id.bright_yellow());
+        }
+        if let Some(crate_name) = &meta.crate_name {
+            println!("   Crate: {}", crate_name);
+        }
+
+        Ok(())
+    }
+
     pub async fn cas_exists(&self, hash: &str) -> Result<()> {
         let exists = self.cas.exists(hash);
         
@@ -73,9 +228,374 @@ impl CommandExecutor {
         Ok(())
     }
 
-    pub async fn submit_job(&self, input_hash: &str) -> Result<()> {
-        let scheduler_addr = format!("http://{}", self.config.scheduler.addr);
-        let mut client = SchedulerClient::connect(scheduler_addr)
+    pub async fn cas_rm(&self, hash: &str, force: bool) -> Result<()> {
+        if !self.cas.exists(hash) {
+            anyhow::bail!("Hash {} not found in CAS", hash);
+        }
+
+        // Ask the scheduler whether any unfinished job still needs this blob
+        let refs = match self.auth.connect_any(&self.config.scheduler.urls()).await {
+            Ok(mut client) => {
+                let request = GetBlobRefsRequest {
+                    hash: hash.to_string(),
+                    namespace: self.cas.namespace_name().unwrap_or_default().to_string(),
+                };
+                Some(client.get_blob_refs(request).await?.into_inner())
+            }
+            Err(e) => {
+                if !force {
+                    anyhow::bail!(
+                        "Cannot check job references with the scheduler ({}); use --force to remove anyway",
+                        e
+                    );
+                }
+                None
+            }
+        };
+
+        if let Some(refs) = &refs {
+            if refs.pinned && !force {
+                let jobs: Vec<&str> = refs
+                    .refs
+                    .iter()
+                    // Unknown states count as in-flight
+                    .filter(|r| !JobStatusEnum::try_from(r.status).is_ok_and(|s| s.is_terminal()))
+                    .map(|r| r.job_id.as_str())
+                    .collect();
+                anyhow::bail!(
+                    "Blob is referenced by in-flight job(s): {}; use --force to remove anyway",
+                    jobs.join(", ")
+                );
+            }
+            let results = refs.actions.iter().filter(|action| action.output_hash == hash).count();
+            if results > 0 && !force {
+                anyhow::bail!(
+                    "Blob is the cached result of {} action(s); use --force to remove anyway",
+                    results
+                );
+            }
+        }
+
+        self.cas.remove(hash)?;
+
+        println!("{}", "🗑️  Blob removed from CAS".green());
+        println!("   Hash: {}", hash.bright_cyan());
+        if let Some(refs) = refs.filter(|r| !r.refs.is_empty()) {
+            println!("   {}", format!("Was referenced by {} job(s)", refs.refs.len()).yellow());
+        }
+
+        Ok(())
+    }
+
+    pub async fn cas_namespaces(&self) -> Result<()> {
+        let namespaces = self.cas.list_namespaces()?;
+
+        if namespaces.is_empty() {
+            println!("{}", "No CAS namespaces (all blobs are in the default space)".yellow());
+            return Ok(());
+        }
+
+        println!("{}", format!("📁 {} namespace(s):", namespaces.len()).bright_green());
+        for name in namespaces {
+            let blobs = self.cas.namespace(&name)?.list_all()?.len();
+            let marker = if self.cas.namespace_name() == Some(name.as_str()) { " *" } else { "" };
+            println!("  {}{} ({} blobs)", name.bright_cyan(), marker, blobs);
+        }
+
+        Ok(())
+    }
+
+    pub async fn cas_stats(&self) -> Result<()> {
+        let stats = self.cas.stats()?;
+
+        println!("{}", "📊 CAS Statistics".bold());
+        println!("   Blobs: {}", stats.blob_count);
+        println!("   Logical size: {} bytes", stats.logical_bytes);
+        println!("   Stored size: {} bytes", stats.stored_bytes);
+        println!("   Compression ratio: {:.2}x", stats.compression_ratio());
+        if self.cas.is_read_only() {
+            println!("   Mode: {}", "read-only".yellow());
+        }
+        if let Some(mirror) = self.cas.mirror() {
+            println!("   Mirror: {} (not included above)", mirror.root().display());
+        }
+
+        println!("\n   {}", "Size histogram:".bold());
+        let mut lower = 0;
+        for (bound, count) in &stats.histogram {
+            let label = match bound {
+                Some(bound) => format!("{} - {}", format_bytes(lower), format_bytes(*bound)),
+                None => format!(">= {}", format_bytes(lower)),
+            };
+            println!("     {:>22}: {}", label, count);
+            lower = bound.unwrap_or(lower);
+        }
+
+        if !stats.largest.is_empty() {
+            println!("\n   {}", "Largest blobs:".bold());
+            for (hash, size) in &stats.largest {
+                println!("     {}  {}", hash.bright_cyan(), format_bytes(*size));
+            }
+        }
+
+        // Traffic is counted in the long-running scheduler, not this process
+        match self.scheduler_cas_metrics().await {
+            Ok(metrics) => {
+                println!("\n   {}", "Scheduler CAS traffic (since start):".bold());
+                println!("     Puts: {} ({} already stored)", metrics.puts, metrics.deduplicated_puts);
+                println!(
+                    "     Gets: {} ({} hits, {} misses, {:.1}% hit rate)",
+                    metrics.gets,
+                    metrics.hits,
+                    metrics.misses,
+                    metrics.hit_rate() * 100.0
+                );
+                println!("     In: {}  Out: {}", format_bytes(metrics.bytes_in), format_bytes(metrics.bytes_out));
+            }
+            Err(e) => println!("\n   {}", format!("Scheduler CAS traffic unavailable: {}", e).dimmed()),
+        }
+
+        Ok(())
+    }
+
+    async fn scheduler_cas_metrics(&self) -> Result<CasMetrics> {
+        use crate::proto::distbuild::blob_store_client::BlobStoreClient;
+
+        let addr = format!("http://{}", self.config.scheduler.addr);
+        let fetch = async {
+            let mut client = BlobStoreClient::with_interceptor(self.auth.channel(addr).await?, self.auth.clone());
+            let resp = client.get_cas_metrics(GetCasMetricsRequest {}).await?;
+            Ok::<_, anyhow::Error>(resp.into_inner().into())
+        };
+        tokio::time::timeout(Duration::from_secs(2), fetch)
+            .await
+            .context("timed out")?
+    }
+
+    pub async fn build_report(&self, workspace: &str) -> Result<()> {
+        let dir = fs::canonicalize(workspace)
+            .with_context(|| format!("Failed to resolve {}", workspace))?;
+        let root = workspace_root(&dir);
+        let Some(session) = BuildSession::latest(&root) else {
+            println!("{}", format!("No wrapped builds recorded for {:?}", root).yellow());
+            return Ok(());
+        };
+        let report = SessionReport::from_units(&session.units()?);
+
+        println!("{}", "📋 Build Report".bold());
+        println!("   Workspace: {:?}", root);
+        match workspace_members(&root) {
+            Some(members) if report.is_partial(&members) => println!(
+                "   Scope: {} ({} of {} workspace packages: {})",
+                "partial".yellow(),
+                report.primary_packages.len(),
+                members.len(),
+                report.primary_packages.join(", ")
+            ),
+            Some(_) => println!("   Scope: {}", "full workspace".green()),
+            None => println!("   Scope: {}", report.primary_packages.join(", ")),
+        }
+        println!("   Units: {}", report.units);
+        println!("     Remote: {}", report.remote);
+        println!("     Local: {}", report.local);
+        if report.fallback > 0 {
+            println!("     {}", format!("Fell back to local: {}", report.fallback).yellow());
+        }
+
+        Ok(())
+    }
+
+    pub async fn cas_verify(&self, rate_mib_per_sec: u64, action: CorruptAction) -> Result<()> {
+        let options = VerifyOptions {
+            max_bytes_per_sec: (rate_mib_per_sec > 0).then(|| rate_mib_per_sec * 1024 * 1024),
+            action,
+        };
+
+        println!("{}", "🔍 Scrubbing CAS...".bold());
+        let report = self.cas.verify(&options)?;
+
+        println!("   Checked: {} blobs ({})", report.checked, format_bytes(report.bytes_checked));
+
+        if report.corrupt.is_empty() {
+            println!("{}", "✅ All blobs intact".green());
+        } else {
+            println!("{}", format!("❌ {} corrupt blob(s):", report.corrupt.len()).red());
+            for hash in &report.corrupt {
+                println!("     {}", hash.bright_cyan());
+            }
+            match action {
+                CorruptAction::Report => println!("   Re-run with --delete or --quarantine to remove them"),
+                CorruptAction::Delete => println!("   Corrupt blobs deleted"),
+                CorruptAction::Quarantine => {
+                    println!("   Corrupt blobs moved to {:?}", self.cas.quarantine_dir())
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn cas_refs(&self, hash: &str) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = GetBlobRefsRequest {
+            hash: hash.to_string(),
+            namespace: self.cas.namespace_name().unwrap_or_default().to_string(),
+        };
+        let response = client.get_blob_refs(request).await?;
+        let resp = response.into_inner();
+
+        println!("{}", "🔗 Blob References".bold());
+        println!("   Hash: {}", hash.bright_cyan());
+        if resp.pinned {
+            println!("   {}", "Pinned by an in-flight job".yellow());
+        }
+
+        if resp.refs.is_empty() && resp.actions.is_empty() {
+            println!("   {}", "Not referenced by any known job or cached action".green());
+        } else {
+            for blob_ref in resp.refs {
+                println!("  • {} ({}) [{}]",
+                    blob_ref.job_id.bright_yellow(),
+                    blob_ref.role,
+                    colored_status(blob_ref.status));
+            }
+            for action in resp.actions {
+                if action.input_hash == hash {
+                    println!("  • action input, cached result {}", action.output_hash.bright_cyan());
+                } else {
+                    println!("  • action result, of input {}", action.input_hash.bright_cyan());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn cas_export(&self, file: &str, max_age_secs: Option<u64>, manifest: Option<&str>) -> Result<()> {
+        let hashes = match manifest {
+            Some(manifest) => {
+                let content = fs::read_to_string(manifest)
+                    .with_context(|| format!("Failed to read manifest: {}", manifest))?;
+                Some(
+                    content
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(str::to_string)
+                        .collect(),
+                )
+            }
+            None => None,
+        };
+
+        let filter = ExportFilter {
+            max_age: max_age_secs.map(Duration::from_secs),
+            hashes,
+        };
+
+        let out = fs::File::create(file)
+            .with_context(|| format!("Failed to create archive: {}", file))?;
+        let exported = self.cas.export_archive(out, &filter)?;
+
+        println!("{}", "✅ Seed archive exported".green());
+        println!("   Blobs: {}", exported);
+        println!("   Archive: {}", file);
+
+        Ok(())
+    }
+
+    pub async fn cas_import(&self, file: &str) -> Result<()> {
+        let archive = fs::File::open(file)
+            .with_context(|| format!("Failed to open archive: {}", file))?;
+        let stats = self.cas.import_archive(archive)?;
+
+        println!("{}", "✅ Seed archive imported".green());
+        println!("   Imported: {}", stats.imported);
+        println!("   Already present: {}", stats.already_present);
+        if !stats.corrupt.is_empty() {
+            println!("   {}", format!("Rejected {} corrupt entries:", stats.corrupt.len()).red());
+            for name in &stats.corrupt {
+                println!("     {}", name);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn cas_gc(&self, max_age_secs: u64, ignore_pins: bool) -> Result<()> {
+        // Blobs used by in-flight jobs are pinned by the scheduler, and blobs
+        // referenced by jobs it still remembers are kept as well, unless a
+        // retention rule gives an output its own max age
+        let retention = RetentionPolicy::new(self.config.cas.retention.clone());
+        let (pinned, ages) = match self.auth.connect_any(&self.config.scheduler.urls()).await {
+            Ok(mut client) => {
+                let response = client.get_pinned_blobs(GetPinnedBlobsRequest {}).await?;
+                let resp = response.into_inner();
+                let ages = retention.ages(resp.outputs.iter().map(|output| {
+                    (output.hash.as_str(), output.job_type.as_str(), output.profile.as_str())
+                }));
+                let pinned: HashSet<String> = resp
+                    .hashes
+                    .into_iter()
+                    .chain(resp.referenced.into_iter().filter(|hash| !ages.contains_key(hash)))
+                    .collect();
+                (pinned, ages)
+            }
+            Err(e) if ignore_pins => {
+                println!("{} Scheduler unreachable ({}), ignoring pins", "⚠️".yellow(), e);
+                (HashSet::new(), HashMap::new())
+            }
+            Err(e) => {
+                anyhow::bail!(
+                    "Cannot fetch pinned blobs from scheduler ({}); refusing to GC. \
+                    Use --ignore-pins to override",
+                    e
+                );
+            }
+        };
+
+        let stats = self.cas.gc_with_ages(Duration::from_secs(max_age_secs), &ages, &pinned)?;
+
+        println!("{}", "🧹 CAS garbage collection complete".green());
+        println!("   Scanned: {}", stats.scanned);
+        println!("   Pinned/referenced (kept): {}", stats.pinned);
+        if !ages.is_empty() {
+            println!("   Under retention rules: {}", ages.len());
+        }
+        println!("   Deleted: {}", stats.deleted);
+        println!("   Freed: {} bytes", stats.bytes_freed);
+
+        Ok(())
+    }
+
+    pub async fn cas_compact(&self, max_blob_kib: Option<u64>, min_age_secs: Option<u64>) -> Result<()> {
+        let packing = &self.config.cas.packing;
+        let max_blob_size = max_blob_kib.unwrap_or(packing.max_blob_kib) * 1024;
+        let min_age = Duration::from_secs(min_age_secs.unwrap_or(packing.min_age_secs));
+
+        let stats = self.cas.compact(max_blob_size, min_age)?;
+
+        println!("{}", "📦 CAS compaction complete".green());
+        println!("   Packed blobs: {}", stats.packed);
+        println!("   Packs written: {}", stats.packs_written);
+        println!("   Bytes packed: {}", format_bytes(stats.bytes_packed));
+
+        Ok(())
+    }
+
+    pub async fn submit_job(
+        &self,
+        input_hash: &str,
+        priority: i32,
+        constraints: HashMap<String, String>,
+        timeout_secs: Option<u64>,
+        run_after: Option<i64>,
+        pool: Option<String>,
+    ) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
             .await
             .context("Failed to connect to scheduler")?;
 
@@ -90,16 +610,51 @@ impl CommandExecutor {
             job_id: job_id.clone(),
             input_hash: input_hash.to_string(),
             job_type: "transform".to_string(),
-            metadata: std::collections::HashMap::new(),
+            metadata: self
+                .cas
+                .namespace_name()
+                .map(|ns| ("namespace".to_string(), ns.to_string()))
+                .into_iter()
+                .collect(),
+            client_version: Some(BuildVersion::current().into()),
+            priority,
+            depends_on: Vec::new(),
+            constraints,
+            timeout_secs: timeout_secs.unwrap_or(0),
+            tenant: self.config.wrapper.tenant.clone().unwrap_or_default(),
+            session_id: String::new(),
+            input_size: 0,
+            run_after: run_after.unwrap_or(0),
+            pool: pool.or_else(|| self.config.wrapper.pool.clone()).unwrap_or_default(),
         };
 
-        let response = client.submit_job(request).await?;
-        let resp = response.into_inner();
+        let resp = match client.submit_job(request.clone()).await {
+            Ok(response) => response.into_inner(),
+            // The scheduler can't see our CAS: push the input and resubmit once
+            Err(status) if rejection(&status) == Some(Rejection::MissingInput) => {
+                println!("{}", "🔁 Scheduler is missing the input; pushing it".yellow());
+                let replicator = Replicator::new(&self.config.cas.replication, &self.config.scheduler.addr, "")
+                    .with_remote(&self.config.cas.remote)
+                    .with_auth(self.auth.clone());
+                replicator.push(&self.cas, input_hash).await;
+                client.submit_job(request).await.map_err(rejected)?.into_inner()
+            }
+            Err(status) => return Err(rejected(status)),
+        };
 
         if resp.success {
-            println!("{}", "✅ Job submitted successfully".green());
+            println!("{}", format!("✅ {}", resp.message).green());
             println!("   Job ID: {}", job_id.bright_yellow());
             println!("   Input: {}", input_hash.bright_cyan());
+            if !resp.output_hash.is_empty() {
+                println!("   Output: {}", resp.output_hash.bright_cyan());
+            }
+            if !resp.attached_to.is_empty() {
+                println!("   Sharing: {}", resp.attached_to.bright_yellow());
+            }
+            if let Some(at) = run_after.filter(|&at| at > chrono::Utc::now().timestamp()) {
+                println!("   Runs after: {}", format_time(at));
+            }
         } else {
             anyhow::bail!("Failed to submit job: {}", resp.message);
         }
@@ -107,9 +662,66 @@ impl CommandExecutor {
         Ok(())
     }
 
+    /// Submit every job of a JSON build plan in one request; as a `gang`,
+    /// they're only dispatched once there's room for all of them
+    pub async fn submit_plan(&self, path: &Path, gang: bool) -> Result<()> {
+        let data = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        let plan: Vec<PlannedJob> =
+            serde_json::from_str(&data).with_context(|| format!("Failed to parse plan {:?}", path))?;
+        if let Some(missing) = plan.iter().find(|job| !self.cas.exists(&job.input_hash)) {
+            anyhow::bail!("Input hash {} of {} not found in CAS", missing.input_hash, missing.name);
+        }
+
+        let ids: HashMap<&str, String> = plan
+            .iter()
+            .map(|job| (job.name.as_str(), Uuid::new_v4().to_string()))
+            .collect();
+        let metadata: HashMap<String, String> = self
+            .cas
+            .namespace_name()
+            .map(|ns| ("namespace".to_string(), ns.to_string()))
+            .into_iter()
+            .collect();
+        // The plan is one build, so its jobs share a session
+        let session_id = Uuid::new_v4().to_string();
+        let jobs = plan
+            .iter()
+            .map(|job| SubmitJobRequest {
+                job_id: ids[job.name.as_str()].clone(),
+                input_hash: job.input_hash.clone(),
+                job_type: job.job_type.clone().unwrap_or_else(|| "transform".to_string()),
+                metadata: metadata.clone(),
+                client_version: Some(BuildVersion::current().into()),
+                priority: job.priority,
+                depends_on: job
+                    .depends_on
+                    .iter()
+                    .map(|dep| ids.get(dep.as_str()).cloned().unwrap_or_else(|| dep.clone()))
+                    .collect(),
+                constraints: job.constraints.clone(),
+                timeout_secs: job.timeout_secs.unwrap_or(0),
+                tenant: self.config.wrapper.tenant.clone().unwrap_or_default(),
+                session_id: session_id.clone(),
+                input_size: 0,
+                run_after: job.run_after.unwrap_or(0),
+                pool: job.pool.clone().or_else(|| self.config.wrapper.pool.clone()).unwrap_or_default(),
+            })
+            .collect();
+
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+        let resp = client.submit_jobs(SubmitJobsRequest { jobs, gang }).await.map_err(rejected)?.into_inner();
+
+        println!("{}", format!("✅ Submitted {} jobs", resp.results.len()).green());
+        for (job, result) in plan.iter().zip(&resp.results) {
+            println!("   {} {} ({})", job.name, result.job_id.bright_yellow(), result.message);
+        }
+        Ok(())
+    }
+
     pub async fn job_status(&self, job_id: &str) -> Result<()> {
-        let scheduler_addr = format!("http://{}", self.config.scheduler.addr);
-        let mut client = SchedulerClient::connect(scheduler_addr)
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
             .await
             .context("Failed to connect to scheduler")?;
 
@@ -120,14 +732,7 @@ impl CommandExecutor {
         let response = client.get_job_status(request).await?;
         let resp = response.into_inner();
 
-        let status_str = match resp.status {
-            0 => "PENDING".yellow(),
-            1 => "ASSIGNED".cyan(),
-            2 => "RUNNING".blue(),
-            3 => "COMPLETED".green(),
-            4 => "FAILED".red(),
-            _ => "UNKNOWN".white(),
-        };
+        let status_str = colored_status(resp.status);
 
         println!("{}", "📊 Job Status".bold());
         println!("   Job ID: {}", job_id.bright_yellow());
@@ -144,13 +749,471 @@ impl CommandExecutor {
         if !resp.error.is_empty() {
             println!("   Error: {}", resp.error.red());
         }
+        if resp.attempt_history.len() > 1 {
+            println!("   Attempts: {} (see `job inspect`)", resp.attempt_history.len());
+        }
+        if resp.run_after > chrono::Utc::now().timestamp() {
+            println!("   Scheduled: not before {}", format_time(resp.run_after));
+        }
+        match (resp.expected_secs, resp.remaining_secs) {
+            (0, _) => {}
+            (expected, 0) => println!("   ETA: overdue (usually takes {}s)", expected),
+            (expected, remaining) => println!("   ETA: {}s remaining (usually takes {}s)", remaining, expected),
+        }
+
+        Ok(())
+    }
+
+    /// Multi-section view of a job: spec, timeline, worker, resources and log tail
+    pub async fn inspect_job(&self, job_id: &str) -> Result<()> {
+        let resp = self.fetch_job(job_id).await?;
+        let job = resp.job.clone().unwrap_or_default();
+
+        println!("{} {} [{}]", "🔍 Job".bold(), job.job_id.bright_yellow(), colored_status(job.status));
+
+        println!("\n{}", "Spec".bold().underline());
+        println!("   Type: {}", resp.job_type);
+        if !job.crate_name.is_empty() {
+            println!("   Crate: {}", job.crate_name);
+        }
+        println!("   Input: {}", job.input_hash.bright_cyan());
+        if !job.tenant.is_empty() {
+            println!("   Tenant: {}", job.tenant);
+        }
+        if !job.session_id.is_empty() {
+            println!("   Session: {}", job.session_id);
+        }
+        if job.priority != 0 {
+            println!("   Priority: {}", job.priority);
+        }
+        if job.boosted {
+            println!("   Boosted: {}", "a build is waiting on it".bright_green());
+        }
+        if !job.gang.is_empty() {
+            println!("   Gang: {}", job.gang);
+        }
+        if job.weight > 1 {
+            println!("   Weight: {} worker slots", job.weight);
+        }
+        if !job.pool.is_empty() {
+            println!("   Pool: {}", job.pool);
+        }
+        let mut metadata: Vec<_> = resp.metadata.iter().filter(|(k, _)| *k != "crate_name").collect();
+        metadata.sort();
+        for (key, value) in metadata {
+            println!("   {}: {}", key, truncate(value, 100));
+        }
+
+        println!("\n{}", "Timeline".bold().underline());
+        for transition in &resp.timeline {
+            let offset = transition.at - job.submitted_at;
+            let worker = if transition.worker.is_empty() {
+                String::new()
+            } else {
+                format!(" on {}", transition.worker)
+            };
+            println!("   +{:>5}s  {}{}", offset, colored_status(transition.status), worker);
+        }
+
+        println!("\n{}", "Worker".bold().underline());
+        match (&resp.worker, job.assigned_worker.as_str()) {
+            (Some(worker), _) => {
+                println!("   ID: {}", worker.worker_id.bright_green());
+                println!("   Address: {}", worker.address);
+                println!("   Load: {}/{}", worker.active_jobs, worker.capacity);
+                let mut labels: Vec<_> = worker.labels.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                labels.sort();
+                if !labels.is_empty() {
+                    println!("   Labels: {}", labels.join(", "));
+                }
+            }
+            (None, "") => println!("   {}", "Not assigned".yellow()),
+            (None, id) => println!("   {} {}", id, "(no longer registered)".yellow()),
+        }
+        if let Some(platform) = &resp.worker_platform {
+            println!("   Platform: {}/{} {}", platform.os, platform.arch, platform.libc);
+        }
+        if let Some(progress) = &resp.progress {
+            let process = match progress.pid {
+                0 => String::new(),
+                pid if progress.process_alive => format!(", pid {}", pid),
+                pid => format!(", pid {} {}", pid, "exited".red()),
+            };
+            println!(
+                "   Progress: {} for {}s{} (reported {}s ago)",
+                progress.phase,
+                progress.elapsed_secs,
+                process,
+                (chrono::Utc::now().timestamp() - progress.at).max(0)
+            );
+        }
+        if resp.stalled {
+            println!("   {}", "Stalled: no sign of life from the worker recently".red());
+        }
+        if !resp.depends_on.is_empty() {
+            println!("   Depends on: {}", resp.depends_on.join(", "));
+        }
+        if !resp.attached_to.is_empty() {
+            println!("   Shares the result of: {}", resp.attached_to.bright_yellow());
+        }
+        let mut constraints: Vec<_> = resp.constraints.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        constraints.sort();
+        if !constraints.is_empty() {
+            println!("   Requires: {}", constraints.join(", "));
+        }
+        if job.run_after > chrono::Utc::now().timestamp() {
+            println!("   {}", format!("Scheduled: not before {}", format_time(job.run_after)).yellow());
+        }
+        if resp.retry_at > 0 {
+            let wait = (resp.retry_at - chrono::Utc::now().timestamp()).max(0);
+            println!("   {}", format!("Retrying in {}s after a failure", wait).yellow());
+        }
+        if resp.deadline > 0 {
+            let left = resp.deadline - chrono::Utc::now().timestamp();
+            println!("   Times out in {}s", left.max(0));
+        }
+
+        if !resp.attempt_history.is_empty() {
+            println!("\n{}", "Attempts".bold().underline());
+            for (n, attempt) in resp.attempt_history.iter().enumerate() {
+                let offset = attempt.started_at - job.submitted_at;
+                let ended = match attempt.ended_at {
+                    0 => "under way".to_string(),
+                    ended_at => format!("{} after {}s", colored_status(attempt.outcome), ended_at - attempt.started_at),
+                };
+                println!("   #{} +{:>5}s  on {}: {}", n + 1, offset, attempt.worker, ended);
+                if !attempt.error.is_empty() {
+                    println!("          {}", truncate(&attempt.error, 100).red());
+                }
+            }
+        }
+
+        println!("\n{}", "Resources".bold().underline());
+        if resp.started_at > 0 {
+            println!("   Queue wait: {}s", resp.started_at - job.submitted_at);
+            if job.completed_at > 0 {
+                println!("   Run time: {}s", job.completed_at - resp.started_at);
+            }
+        }
+        // As the worker measured it, if it reported back
+        match job.usage.filter(|usage| usage.exec_millis > 0) {
+            Some(usage) => {
+                println!("   Worker time: {:.1}s", usage.exec_millis as f64 / 1000.0);
+                println!(
+                    "   Read: {} ({} from peers)",
+                    format_bytes(usage.input_bytes),
+                    format_bytes(usage.transferred_bytes)
+                );
+                println!("   Written: {}", format_bytes(usage.output_bytes));
+            }
+            None => println!("   Input size: {}", self.local_size(&job.input_hash)),
+        }
+        if !job.output_hash.is_empty() {
+            println!("   Output: {} ({})", &job.output_hash[..16].bright_cyan(), self.local_size(&job.output_hash));
+        }
+
+        println!("\n{}", "Log tail".bold().underline());
+        let log = self.fetch_log(job_id, LOG_TAIL_LINES as u32).await?.unwrap_or(job.error);
+        let lines: Vec<&str> = log.lines().collect();
+        if lines.is_empty() {
+            println!("   {}", "No output recorded".bright_black());
+        } else {
+            for line in &lines[lines.len().saturating_sub(LOG_TAIL_LINES)..] {
+                println!("   {}", line.red());
+            }
+        }
+
+        Ok(())
+    }
 
+    /// Full log of a job's latest run, or the error it failed with if no
+    /// log was kept
+    pub async fn job_logs(&self, job_id: &str) -> Result<()> {
+        let log = match self.fetch_log(job_id, 0).await? {
+            Some(log) => log,
+            None => self.fetch_job(job_id).await?.job.unwrap_or_default().error,
+        };
+        if log.is_empty() {
+            println!("{}", "No output recorded for this job".yellow());
+        } else {
+            println!("{}", log);
+        }
         Ok(())
     }
 
+    /// A job's log (its last `tail_lines`, 0 = all), from the scheduler or
+    /// else the local CAS; `None` if none was stored
+    async fn fetch_log(&self, job_id: &str, tail_lines: u32) -> Result<Option<String>> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = GetJobLogsRequest {
+            job_id: job_id.to_string(),
+            tail_lines,
+        };
+        let resp = client.get_job_logs(request).await?.into_inner();
+        if resp.log_hash.is_empty() {
+            return Ok(None);
+        }
+        if !resp.log.is_empty() {
+            return Ok(Some(resp.log));
+        }
+        let data = self.cas.get(&resp.log_hash).with_context(|| format!("Failed to read log {}", resp.log_hash))?;
+        let log = String::from_utf8_lossy(&data);
+        let lines: Vec<&str> = log.lines().collect();
+        let skip = if tail_lines > 0 { lines.len().saturating_sub(tail_lines as usize) } else { 0 };
+        Ok(Some(lines[skip..].join("\n")))
+    }
+
+    /// Submit a copy of a job under a new ID and return that ID
+    pub async fn retry_job(&self, job_id: &str) -> Result<String> {
+        let resp = self.fetch_job(job_id).await?;
+        let job = resp.job.unwrap_or_default();
+
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let new_id = Uuid::new_v4().to_string();
+        let mut metadata = resp.metadata;
+        metadata.insert("retry_of".to_string(), job_id.to_string());
+        let request = SubmitJobRequest {
+            job_id: new_id.clone(),
+            input_hash: job.input_hash,
+            job_type: resp.job_type,
+            metadata,
+            client_version: Some(BuildVersion::current().into()),
+            priority: job.priority,
+            depends_on: resp.depends_on,
+            constraints: resp.constraints,
+            timeout_secs: resp.timeout_secs,
+            tenant: job.tenant,
+            session_id: job.session_id,
+            input_size: 0,
+            run_after: 0,
+            pool: job.pool,
+        };
+
+        let resp = client.submit_job(request).await.map_err(rejected)?.into_inner();
+        if !resp.success {
+            anyhow::bail!("Failed to resubmit job: {}", resp.message);
+        }
+
+        println!("{}", "🔁 Job resubmitted".green());
+        println!("   Job ID: {}", new_id.bright_yellow());
+        println!("   Retry of: {}", job_id);
+        Ok(new_id)
+    }
+
+    pub async fn cancel_job(&self, job_id: &str) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = CancelJobRequest {
+            job_id: job_id.to_string(),
+        };
+        let resp = client.cancel_job(request).await?.into_inner();
+
+        if resp.cancelled {
+            println!("{} {}", "🚫 Cancelled".magenta(), job_id.bright_yellow());
+        } else {
+            println!("{} already finished [{}]", job_id.bright_yellow(), colored_status(resp.status));
+        }
+        Ok(())
+    }
+
+    /// List build sessions, most recently started first
+    pub async fn list_sessions(&self, limit: u32, active_only: bool) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = ListSessionsRequest { limit, active_only };
+        let resp = client.list_sessions(request).await?.into_inner();
+
+        println!("{}", format!("🏗️  Build sessions (showing {})", resp.sessions.len()).bold());
+        if resp.sessions.is_empty() {
+            println!("   {}", "No sessions".yellow());
+        }
+        for session in &resp.sessions {
+            print_session(session);
+        }
+        Ok(())
+    }
+
+    /// Show a build session's progress and its jobs
+    pub async fn show_session(&self, session_id: &str) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = GetSessionRequest {
+            session_id: session_id.to_string(),
+        };
+        let resp = client.get_session(request).await?.into_inner();
+
+        print_session(&resp.session.unwrap_or_default());
+        println!("    Jobs:");
+        for job in resp.jobs {
+            let name = if job.crate_name.is_empty() { job.job_id.clone() } else { job.crate_name.clone() };
+            println!("      {} {} [{}]", name, job.job_id.bright_yellow(), colored_status(job.status));
+            if !job.error.is_empty() {
+                println!("        Error: {}", job.error.red());
+            }
+        }
+        Ok(())
+    }
+
+    /// Cancel every unfinished job of a build session
+    pub async fn cancel_session(&self, session_id: &str) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = CancelSessionRequest {
+            session_id: session_id.to_string(),
+        };
+        let resp = client.cancel_session(request).await?.into_inner();
+
+        if resp.cancelled > 0 {
+            println!("{} {} jobs of {}", "🚫 Cancelled".magenta(), resp.cancelled, session_id.bright_yellow());
+        } else {
+            println!("{} has no unfinished jobs", session_id.bright_yellow());
+        }
+        Ok(())
+    }
+
+    /// Quiesce the scheduler (see `Quiesce`), optionally restarting it
+    pub async fn quiesce(&self, drain: bool, timeout_secs: u64, restart: bool) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        println!("{}", "⏸️  Stopping admissions...".bold());
+        if drain {
+            println!("   Waiting up to {}s for running jobs to finish", timeout_secs);
+        }
+        let request = QuiesceRequest {
+            drain,
+            timeout_secs: timeout_secs.min(u32::MAX as u64) as u32,
+            restart,
+        };
+        let resp = client.quiesce(request).await?.into_inner();
+
+        println!("   Running: {}", resp.running);
+        println!("   Queued: {}", resp.queued);
+        println!("   State saved: {}", if resp.persisted { "yes".green() } else { "no (no state_dir)".yellow() });
+        if resp.restarting {
+            println!("{} {}", "🔄".bold(), resp.message.green());
+        } else if resp.ready {
+            println!("{} {}", "✅".bold(), resp.message.green());
+            println!("   Run `master cluster-resume` to admit jobs again");
+        } else {
+            println!("{} {}", "⚠️ ".bold(), resp.message.yellow());
+            println!("   Admissions stay stopped; run `master cluster-resume` to undo");
+        }
+        Ok(())
+    }
+
+    pub async fn cluster_resume(&self) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let resp = client.resume(ResumeRequest {}).await?.into_inner();
+        if resp.resumed {
+            println!("{}", "▶️  Scheduler is admitting jobs again".green());
+        } else {
+            println!("Scheduler wasn't quiesced");
+        }
+        Ok(())
+    }
+
+    /// The blobs a job reads: its input (listed if it's a tarball) and dependencies
+    pub async fn job_inputs(&self, job_id: &str) -> Result<()> {
+        let resp = self.fetch_job(job_id).await?;
+        let job = resp.job.unwrap_or_default();
+
+        println!("{}", "📥 Inputs".bold());
+        println!("   Input: {} ({})", job.input_hash.bright_cyan(), self.local_size(&job.input_hash));
+        if let Ok(data) = self.cas.get(&job.input_hash) {
+            if let BlobView::Tar { entries, .. } = inspect(&data) {
+                for (path, size) in &entries {
+                    println!("   {:>10}  {}", format_bytes(*size), path);
+                }
+            }
+        }
+
+        let deps = resp.metadata.get("deps").map(String::as_str).unwrap_or_default();
+        for dep in deps.split(',').map(str::trim).filter(|h| !h.is_empty()) {
+            println!("   Dep: {} ({})", dep.bright_cyan(), self.local_size(dep));
+        }
+        Ok(())
+    }
+
+    async fn fetch_job(&self, job_id: &str) -> Result<InspectJobResponse> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = InspectJobRequest {
+            job_id: job_id.to_string(),
+        };
+        Ok(client.inspect_job(request).await?.into_inner())
+    }
+
+    /// Size of a blob in the local CAS, for display
+    fn local_size(&self, hash: &str) -> String {
+        match self.cas.get(hash) {
+            Ok(data) => format_bytes(data.len() as u64),
+            Err(_) => "not in local CAS".to_string(),
+        }
+    }
+
+    /// Print cluster events of `kinds` (all if empty) as they happen, until
+    /// interrupted
+    pub async fn events(&self, kinds: &[ClusterEventKind]) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = SubscribeEventsRequest {
+            kinds: kinds.iter().map(|kind| *kind as i32).collect(),
+        };
+        let mut events = client.subscribe_events(request).await?.into_inner();
+        println!("{}", "📡 Following cluster events (Ctrl-C to stop)".bold());
+
+        loop {
+            let event = tokio::select! {
+                event = events.message() => event?,
+                _ = tokio::signal::ctrl_c() => return Ok(()),
+            };
+            let Some(event) = event else {
+                println!("{}", "Scheduler closed the event stream".yellow());
+                return Ok(());
+            };
+
+            let at = chrono::DateTime::from_timestamp(event.at, 0)
+                .map(|t| t.with_timezone(&chrono::Local).format("%H:%M:%S").to_string())
+                .unwrap_or_default();
+            let line = match ClusterEventKind::try_from(event.kind).unwrap_or_default() {
+                ClusterEventKind::WorkerOnline => format!("🟢 Worker {} online at {}", event.worker_id, event.detail).green(),
+                ClusterEventKind::WorkerOffline => format!("🔴 Worker {} offline: {}", event.worker_id, event.detail).red(),
+                ClusterEventKind::WorkerQuarantined => format!("🚧 Worker {} quarantined: {}", event.worker_id, event.detail).yellow(),
+                ClusterEventKind::JobSubmitted => format!("📋 Job {} submitted: {}", event.job_id, event.detail).normal(),
+                ClusterEventKind::JobCompleted => format!("✅ Job {} completed: {}", event.job_id, event.detail).green(),
+                ClusterEventKind::JobFailed => format!("❌ Job {} failed: {}", event.job_id, event.detail).red(),
+                ClusterEventKind::JobCancelled => format!("🚫 Job {} cancelled", event.job_id).magenta(),
+                ClusterEventKind::Unspecified => format!("Unknown event {:?}", event).white(),
+            };
+            println!("{} {}", at.bright_black(), line);
+        }
+    }
+
     pub async fn list_workers(&self) -> Result<()> {
-        let scheduler_addr = format!("http://{}", self.config.scheduler.addr);
-        let mut client = SchedulerClient::connect(scheduler_addr)
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
             .await
             .context("Failed to connect to scheduler")?;
 
@@ -160,29 +1223,119 @@ impl CommandExecutor {
 
         println!("{}", format!("🔧 Registered Workers ({})", resp.workers.len()).bold());
         
+        let version_warnings = fleet_version_warnings(&mut client, &resp.workers).await;
         if resp.workers.is_empty() {
             println!("   {}", "No workers registered".yellow());
         } else {
+            let now = chrono::Utc::now().timestamp();
             for worker in resp.workers {
                 let capacity_str = format!("{}/{}", worker.active_jobs, worker.capacity);
-                println!("\n  • {}", worker.worker_id.bright_green());
+                let mut notes = Vec::new();
+                match WorkerStateEnum::from(worker.state) {
+                    WorkerStateEnum::Online => {}
+                    WorkerStateEnum::Suspect => notes.push(format!("(suspect for {}s)", (now - worker.state_since).max(0))),
+                    WorkerStateEnum::Offline => notes.push(format!("(offline since {})", format_time(worker.state_since))),
+                    WorkerStateEnum::Draining => notes.push("(draining)".to_string()),
+                }
+                match worker.quarantined_until {
+                    0 => {}
+                    until if until > now => notes.push(format!("(quarantined for {}s)", until - now)),
+                    _ => notes.push("(on probation)".to_string()),
+                }
+                if notes.is_empty() {
+                    println!("\n  • {}", worker.worker_id.bright_green());
+                } else {
+                    println!("\n  • {} {}", worker.worker_id.bright_green(), notes.join(" ").yellow());
+                }
                 println!("    Address: {}", worker.address);
+                if !worker.tenant.is_empty() {
+                    println!("    Tenant: {}", worker.tenant);
+                }
+                if !worker.pool.is_empty() {
+                    println!("    Pool: {}", worker.pool);
+                }
                 println!("    Load: {}", capacity_str);
+                if let Some(health) = &worker.health {
+                    println!(
+                        "    Health: {:.2} ({:.0}% of recent jobs failed, dispatch {}ms, {:.0}% of heartbeats on time)",
+                        health.score,
+                        health.failure_rate * 100.0,
+                        health.dispatch_latency_ms,
+                        health.heartbeat_stability * 100.0
+                    );
+                }
+                if let Some(capabilities) = &worker.capabilities {
+                    let or_unknown = |list: &[String]| if list.is_empty() { "unknown".to_string() } else { list.join(", ") };
+                    println!("    Rustc: {}", or_unknown(&capabilities.rustc_versions));
+                    println!("    Targets: {}", or_unknown(&capabilities.targets));
+                    if capabilities.disk_free_bytes > 0 {
+                        println!("    Disk free: {}", format_bytes(capabilities.disk_free_bytes));
+                    }
+                    if !capabilities.job_types.is_empty() {
+                        println!("    Job types: {}", capabilities.job_types.join(", "));
+                    }
+                }
+                println!("    Version: {}", worker.version.map(|v| BuildVersion::from(v).to_string()).unwrap_or_else(|| "unknown".to_string()));
                 println!("    Last heartbeat: {} seconds ago", 
                     chrono::Utc::now().timestamp() - worker.last_heartbeat);
             }
         }
 
+        if !version_warnings.is_empty() {
+            println!("\n{}", "⚠️  Mixed-version fleet:".yellow().bold());
+            for warning in &version_warnings {
+                println!("   {}", warning.yellow());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drain a worker (see `DrainWorker`), waiting up to `timeout_secs`
+    /// for its jobs
+    pub async fn drain_worker(&self, worker_id: &str, timeout_secs: u64) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        println!("{} {}", "🚧 Draining".bold(), worker_id.bright_green());
+        println!("   Waiting up to {}s for its jobs to finish", timeout_secs);
+        let request = DrainWorkerRequest {
+            worker_id: worker_id.to_string(),
+            timeout_secs: timeout_secs.min(u32::MAX as u64) as u32,
+        };
+        let resp = client.drain_worker(request).await?.into_inner();
+
+        if resp.removed {
+            println!("{} {}", "✅".bold(), resp.message.green());
+        } else {
+            println!("{} {}", "⚠️ ".bold(), resp.message.yellow());
+            if resp.remaining > 0 {
+                println!("   Run the command again to keep waiting");
+            }
+        }
         Ok(())
     }
 
-    pub async fn list_jobs(&self, limit: u32) -> Result<()> {
-        let scheduler_addr = format!("http://{}", self.config.scheduler.addr);
-        let mut client = SchedulerClient::connect(scheduler_addr)
+    pub async fn list_jobs(&self, filter: JobFilter) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
             .await
             .context("Failed to connect to scheduler")?;
 
-        let request = ListJobsRequest { limit };
+        let now = chrono::Utc::now().timestamp();
+        let request = ListJobsRequest {
+            limit: filter.limit,
+            within_secs: filter.since_secs.unwrap_or(0),
+            older_than_secs: filter.older_than_secs.unwrap_or(0),
+            status: filter.statuses.iter().map(|&s| s.into()).collect(),
+            worker: filter.worker.unwrap_or_default(),
+            job_type: filter.job_type.unwrap_or_default(),
+            tenant: filter.tenant.unwrap_or_default(),
+            session_id: filter.session.unwrap_or_default(),
+            grep: filter.grep.unwrap_or_default(),
+            cursor: filter.cursor.unwrap_or_default(),
+            ..Default::default()
+        };
         let response = client.list_jobs(request).await?;
         let resp = response.into_inner();
 
@@ -192,16 +1345,14 @@ impl CommandExecutor {
             println!("   {}", "No jobs".yellow());
         } else {
             for job in resp.jobs {
-                let status_str = match job.status {
-                    0 => "PENDING".yellow(),
-                    1 => "ASSIGNED".cyan(),
-                    2 => "RUNNING".blue(),
-                    3 => "COMPLETED".green(),
-                    4 => "FAILED".red(),
-                    _ => "UNKNOWN".white(),
-                };
+                let status_str = colored_status(job.status);
 
                 println!("\n  • {} [{}]", job.job_id.bright_yellow(), status_str);
+                if !job.crate_name.is_empty() {
+                    println!("    Crate: {}", job.crate_name);
+                }
+                // The scheduler's clock may be ahead of ours
+                println!("    Submitted: {}s ago", (now - job.submitted_at).max(0));
                 println!("    Input: {}", &job.input_hash[..16].bright_cyan());
                 
                 if !job.output_hash.is_empty() {
@@ -211,20 +1362,195 @@ impl CommandExecutor {
                 if !job.assigned_worker.is_empty() {
                     println!("    Worker: {}", job.assigned_worker);
                 }
+                
+                if !job.error.is_empty() {
+                    println!("    Error: {}", job.error.red());
+                }
+            }
+        }
+        if !resp.next_cursor.is_empty() {
+            println!("\n   More: --cursor {}", resp.next_cursor.bright_cyan());
+        }
+
+        Ok(())
+    }
+
+    pub async fn fairness_report(&self, window_secs: u64) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = GetFairnessReportRequest { window_secs };
+        let response = client.get_fairness_report(request).await?;
+        let resp = response.into_inner();
+
+        println!("{}", format!("⚖️  Fairness Report (last {}s)", resp.window_secs).bold());
+
+        if resp.tenants.is_empty() {
+            println!("   {}", "No jobs in window".yellow());
+        } else {
+            for tenant in resp.tenants {
+                println!("\n  • {}", tenant.tenant.bright_green());
+                println!("    Jobs: {}", tenant.jobs);
+                println!("    Queue wait p50/p90/p99: {}s / {}s / {}s",
+                    tenant.wait_p50_secs, tenant.wait_p90_secs, tenant.wait_p99_secs);
+                println!("    Cluster time: {}s ({:.1}%)",
+                    tenant.cluster_time_secs, tenant.cluster_share * 100.0);
+                println!("    Preemptions: {}", tenant.preemptions);
             }
         }
 
         Ok(())
     }
 
+    pub async fn scaling_advice(&self, window_secs: u64, target_utilization: f64, watch: bool) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        loop {
+            let request = GetScalingAdviceRequest {
+                window_secs,
+                target_utilization,
+            };
+            let response = client.get_scaling_advice(request).await?;
+            let resp = response.into_inner();
+
+            let desired = match resp.desired_workers.cmp(&resp.current_workers) {
+                std::cmp::Ordering::Greater => format!("{} (scale up)", resp.desired_workers).yellow(),
+                std::cmp::Ordering::Less => format!("{} (scale down)", resp.desired_workers).cyan(),
+                std::cmp::Ordering::Equal => format!("{} (steady)", resp.desired_workers).green(),
+            };
+
+            println!("{}", format!("📈 Scaling Advice (last {}s)", resp.window_secs).bold());
+            println!("   Workers: {} online, desired {}", resp.current_workers, desired);
+            println!("   Queue: {} pending, {} running", resp.queue_depth, resp.running_jobs);
+            println!("   Arrival rate: {:.3} jobs/s", resp.arrival_rate);
+            println!("   Avg job duration: {:.1}s", resp.avg_job_duration_secs);
+            println!("   Slots per worker: {:.1}", resp.slots_per_worker);
+            println!("   {}", resp.reason.dimmed());
+
+            if !watch {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            println!();
+        }
+    }
+
+    pub async fn client_errors(&self, limit: u32) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = ListClientErrorsRequest { limit };
+        let response = client.list_client_errors(request).await?;
+        let resp = response.into_inner();
+
+        println!("{}", format!("🩺 Client Errors (showing {})", resp.errors.len()).bold());
+
+        if resp.errors.is_empty() {
+            println!("   {}", "No client errors reported".green());
+        } else {
+            let now = chrono::Utc::now().timestamp();
+            for error in resp.errors {
+                println!("\n  • [{}] {}", error.kind.bright_yellow(), error.message.red());
+                println!("    Count: {} from {} client(s): {}",
+                    error.count, error.clients.len(), error.clients.join(", "));
+                println!("    Last seen: {} seconds ago", now - error.last_seen);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Who submitted, cancelled or drained what, newest first
+    pub async fn audit(&self, filter: AuditFilter) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = QueryAuditRequest {
+            within_secs: filter.since_secs.unwrap_or(0),
+            actor: filter.actor.unwrap_or_default(),
+            action: filter.action.unwrap_or_default(),
+            target: filter.target.unwrap_or_default(),
+            limit: filter.limit,
+            ..Default::default()
+        };
+        let resp = client.query_audit(request).await?.into_inner();
+
+        println!("{}", format!("📜 Audit trail (showing {})", resp.entries.len()).bold());
+        if resp.entries.is_empty() {
+            println!("   {}", "No matching actions".yellow());
+        }
+        for entry in resp.entries {
+            let at = chrono::DateTime::from_timestamp(entry.at, 0)
+                .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+            let actor = if entry.tenant.is_empty() { entry.actor } else { format!("{} ({})", entry.actor, entry.tenant) };
+            println!("  {} {} {} {}", at.bright_black(), actor.bright_green(), entry.action.cyan(), entry.target.bright_yellow());
+            if !entry.detail.is_empty() {
+                println!("      {}", entry.detail);
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn job_history(&self, filter: HistoryFilter) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = SearchJobHistoryRequest {
+            within_secs: filter.since_secs.unwrap_or(0),
+            crate_name: filter.crate_name.unwrap_or_default(),
+            status: filter.statuses.iter().map(|&s| s.into()).collect(),
+            worker: filter.worker.unwrap_or_default(),
+            tenant: filter.tenant.unwrap_or_default(),
+            limit: filter.limit,
+            ..Default::default()
+        };
+        let resp = client.search_job_history(request).await?.into_inner();
+
+        println!("{}", format!("🗂️  Job history (showing {})", resp.entries.len()).bold());
+        if resp.entries.is_empty() {
+            println!("   {}", "No matching jobs".yellow());
+        }
+        for entry in resp.entries {
+            let finished = chrono::DateTime::from_timestamp(entry.finished_at, 0)
+                .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+            println!("\n  • {} [{}] {}", entry.job_id.bright_yellow(), colored_status(entry.status), finished.bright_black());
+            if !entry.crate_name.is_empty() {
+                println!("    Crate: {}", entry.crate_name);
+            }
+            if !entry.worker.is_empty() {
+                println!("    Worker: {}", entry.worker);
+            }
+            if entry.exec_millis > 0 {
+                println!("    Ran for: {:.1}s", entry.exec_millis as f64 / 1000.0);
+            }
+            if !entry.session_id.is_empty() {
+                println!("    Session: {}", entry.session_id);
+            }
+            if !entry.error.is_empty() {
+                println!("    Error: {}", entry.error.red());
+            }
+        }
+        Ok(())
+    }
+
     pub async fn scheduler_status(&self) -> Result<()> {
         println!("{}", "📡 Scheduler Configuration".bold());
         println!("   Address: {}", self.config.scheduler.addr.bright_green());
+        if !self.config.scheduler.fallback_addrs.is_empty() {
+            println!("   Fallbacks: {}", self.config.scheduler.fallback_addrs.join(", "));
+        }
         println!("   CAS Root: {}", self.config.cas.root);
         
         // Try to connect
-        let scheduler_addr = format!("http://{}", self.config.scheduler.addr);
-        match SchedulerClient::connect(scheduler_addr).await {
+        match self.auth.connect_any(&self.config.scheduler.urls()).await {
             Ok(_) => println!("   Status: {}", "Online ✓".green()),
             Err(_) => println!("   Status: {}", "Offline ✗".red()),
         }
@@ -232,23 +1558,350 @@ impl CommandExecutor {
         Ok(())
     }
 
+    /// Replay the jobs recorded in `trace` with each of `strategies` (all
+    /// of them if none), on the traced workers or `workers` identical ones
+    pub async fn simulate(&self, trace: &Path, strategies: &[StrategyKind], workers: Option<usize>, capacity: u32) -> Result<()> {
+        let mut trace = Trace::load(trace)?;
+        if let Some(count) = workers {
+            trace = trace.with_workers(count, capacity);
+        }
+        if trace.workers() == 0 {
+            anyhow::bail!("No workers in the trace; give some with --workers");
+        }
+        let strategies = match strategies {
+            [] => &[StrategyKind::LeastLoaded, StrategyKind::RoundRobin, StrategyKind::Random][..],
+            strategies => strategies,
+        };
+
+        println!("{}", format!("🧪 Simulating {} jobs on {} workers", trace.jobs(), trace.workers()).bold());
+        for &strategy in strategies {
+            let report = trace.simulate(strategy);
+            println!("\n{}", format!("{:?}", report.strategy).bold().underline());
+            println!("   Makespan: {:.1}s", report.makespan_ms as f64 / 1000.0);
+            println!("   Utilization: {:.0}%", report.utilization * 100.0);
+            println!(
+                "   Queue latency: {:.1}s avg, {:.1}s p95",
+                report.avg_queue_latency_ms as f64 / 1000.0,
+                report.p95_queue_latency_ms as f64 / 1000.0
+            );
+            if report.unschedulable > 0 {
+                println!("   {}", format!("{} jobs no worker could take", report.unschedulable).yellow());
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn scheduler_stats(&self, window_secs: u64) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.config.scheduler.urls())
+            .await
+            .context("Failed to connect to scheduler")?;
+
+        let request = GetSchedulerStatsRequest { window_secs };
+        let resp = client.get_scheduler_stats(request).await?.into_inner();
+
+        println!("{}", format!("📊 Scheduler Stats (last {}s)", resp.window_secs).bold());
+        println!("   Queue depth: {}", resp.queue_depth);
+        let mut statuses: Vec<_> = resp.jobs_by_status.iter().collect();
+        statuses.sort();
+        let counts: Vec<String> = statuses.iter().map(|(status, count)| format!("{} {}", count, status)).collect();
+        println!("   Jobs: {} ({})", resp.total_jobs, counts.join(", "));
+        println!("   Throughput: {:.1} jobs/min", resp.jobs_per_minute);
+        println!("   Avg queue latency: {:.1}s", resp.avg_queue_latency_secs);
+
+        println!("\n{}", "Workers".bold().underline());
+        if resp.workers.is_empty() {
+            println!("   {}", "No workers registered".yellow());
+        }
+        for worker in &resp.workers {
+            let load = format!("{}/{} ({:.0}%)", worker.active_jobs, worker.capacity, worker.utilization * 100.0);
+            let load = if worker.online { load.normal() } else { format!("{} offline", load).red() };
+            println!("   {} {}", worker.worker_id.bright_yellow(), load);
+        }
+
+        println!("\n{}", "Cost by tenant".bold().underline());
+        if resp.costs.is_empty() {
+            println!("   {}", "No jobs finished in window".yellow());
+        }
+        for cost in &resp.costs {
+            let usage = cost.usage.unwrap_or_default();
+            println!(
+                "   {} {} jobs, {:.1}s on workers, {}s queued, read {} ({} from peers), wrote {}",
+                cost.tenant.bright_yellow(),
+                cost.jobs,
+                usage.exec_millis as f64 / 1000.0,
+                usage.queue_wait_secs,
+                format_bytes(usage.input_bytes),
+                format_bytes(usage.transferred_bytes),
+                format_bytes(usage.output_bytes)
+            );
+        }
+        Ok(())
+    }
+
+    /// Check the CAS, scheduler connectivity and clock skew, and flag mixed versions
+    pub async fn doctor(&self) -> Result<()> {
+        println!("{}", "🩺 cargo-distbuild doctor".bold());
+        let mut problems = 0;
+
+        let roots = std::iter::once(("root", self.cas.root().to_path_buf()))
+            .chain(self.cas.shard_roots().into_iter().map(|root| ("shard", root)));
+        for (kind, root) in roots {
+            let probe = root.join(format!(".doctor-{}", std::process::id()));
+            match fs::write(&probe, b"probe").and_then(|_| fs::remove_file(&probe)) {
+                Ok(()) => println!("   {} CAS {} {:?} is writable", "✓".green(), kind, root),
+                Err(e) => {
+                    problems += 1;
+                    println!("   {} CAS {} {:?} is not writable: {}", "✗".red(), kind, root, e);
+                }
+            }
+        }
+
+        match self.auth.connect_any(&self.config.scheduler.urls()).await {
+            Ok(mut client) => {
+                println!("   {} Scheduler {} is reachable", "✓".green(), self.config.scheduler.addr);
+
+                let sent_ms = chrono::Utc::now().timestamp_millis();
+                let resp = client.ping(PingRequest {}).await?.into_inner();
+                let received_ms = chrono::Utc::now().timestamp_millis();
+                let (skew_ms, uncertainty_ms) =
+                    clock::estimate_skew(sent_ms, resp.server_time_ms, received_ms);
+
+                let detail = format!(
+                    "Clock skew vs scheduler: {:+}ms (±{}ms)",
+                    skew_ms, uncertainty_ms
+                );
+                if skew_ms.abs() > clock::MAX_CLOCK_SKEW_MS + uncertainty_ms {
+                    problems += 1;
+                    println!("   {} {}", "⚠".yellow(), detail);
+                    println!("     Job times are recorded by the scheduler, but logs and");
+                    println!("     local timestamps won't line up. Check NTP on both hosts.");
+                } else {
+                    println!("   {} {}", "✓".green(), detail);
+                }
+
+                let ours = BuildVersion::current();
+                match client.get_capabilities(GetCapabilitiesRequest {}).await {
+                    Ok(resp) => {
+                        let theirs = resp.into_inner().version.map(BuildVersion::from);
+                        match theirs {
+                            Some(theirs) if ours.incompatibility(&theirs).is_some() => {
+                                problems += 1;
+                                println!("   {} {}", "✗".red(), ours.incompatibility(&theirs).unwrap_or_default());
+                            }
+                            Some(theirs) if ours.differs(&theirs) => {
+                                problems += 1;
+                                println!("   {} Scheduler runs {}, this CLI {}", "⚠".yellow(), theirs, ours);
+                            }
+                            Some(theirs) => println!("   {} Scheduler runs {}", "✓".green(), theirs),
+                            None => {}
+                        }
+                    }
+                    Err(_) => {
+                        problems += 1;
+                        println!("   {} Scheduler doesn't report its version (older than {})", "⚠".yellow(), ours);
+                    }
+                }
+
+                if let Ok(resp) = client.list_workers(ListWorkersRequest {}).await {
+                    let warnings = fleet_version_warnings(&mut client, &resp.into_inner().workers).await;
+                    problems += warnings.len();
+                    for warning in warnings {
+                        println!("   {} {}", "⚠".yellow(), warning);
+                    }
+                }
+            }
+            Err(e) => {
+                problems += 1;
+                println!(
+                    "   {} Scheduler {} is unreachable: {}",
+                    "✗".red(),
+                    self.config.scheduler.addr,
+                    e
+                );
+            }
+        }
+
+        if problems == 0 {
+            println!("{}", "✅ No problems found".green());
+        } else {
+            println!("{}", format!("❌ {} problem(s) found", problems).red());
+        }
+        Ok(())
+    }
+
     pub fn show_help(&self) {
         println!("{}", "Available Commands:".bold().underline());
         println!();
-        println!("  {}  {}", "cas put <file>".cyan(), "Store a file in CAS");
-        println!("  {}  {}", "cas get <hash> <out>".cyan(), "Retrieve a blob from CAS");
-        println!("  {}  {}", "cas exists <hash>".cyan(), "Check if a hash exists in CAS");
-        println!("  {}  {}", "cas list".cyan(), "List all hashes in CAS");
+        println!("  {}  Store a file in CAS", "cas put <file>".cyan());
+        println!("  {}  Retrieve a blob from CAS", "cas get <hash> <out>".cyan());
+        println!("  {}  Print a blob (tar listing, JSON, text or hex)", "cas cat <hash>".cyan());
+        println!("  {}  Show a blob's storage, origin and access times", "cas inspect <hash>".cyan());
+        println!("  {}  Check if a hash exists in CAS", "cas exists <hash>".cyan());
+        println!("  {}  List all hashes in CAS", "cas list".cyan());
+        println!("  {}  Remove a blob not needed by in-flight jobs", "cas rm <hash> [--force]".cyan());
+        println!("  {}  Show CAS size statistics", "cas stats".cyan());
+        println!("  {}  List CAS namespaces", "cas namespaces".cyan());
+        println!("  {}  Re-hash all blobs and report corrupt ones", "cas verify".cyan());
+        println!("  {}  Export all blobs into a seed archive", "cas export <file>".cyan());
+        println!("  {}  Import blobs from a seed archive", "cas import <file>".cyan());
+        println!("  {}  Show which jobs reference a blob", "cas refs <hash>".cyan());
+        println!("  {}  Delete old blobs not pinned by in-flight jobs", "cas gc [max-age-secs]".cyan());
+        println!("  {}  Pack small blobs into packfiles", "cas compact [max-blob-kib]".cyan());
         println!();
-        println!("  {}  {}", "job submit <hash>".cyan(), "Submit a job with input hash");
-        println!("  {}  {}", "job status <id>".cyan(), "Get status of a job");
-        println!("  {}  {}", "jobs list [limit]".cyan(), "List recent jobs");
+        println!("  {}  Submit a job with input hash", "job submit <hash> [priority]".cyan());
+        println!("  {}  Get status of a job", "job status <id>".cyan());
+        println!("  {}  Spec, timeline, worker, resources and log tail of a job", "inspect <id>".cyan());
+        println!("  {}  Act on the last inspected job", "logs|retry|cancel|inputs".cyan());
+        println!("  {}  List recent jobs, filtered", "jobs list [limit] [--since|--older-than|--status|--worker|--type|--tenant|--session|--grep|--cursor]".cyan());
+        println!("  {}  List builds and their progress", "sessions list [limit] [--active]".cyan());
+        println!("  {}  A build's jobs, or cancel all it has left", "sessions show|cancel <id>".cyan());
         println!();
-        println!("  {}  {}", "workers list".cyan(), "List registered workers");
-        println!("  {}  {}", "scheduler status".cyan(), "Show scheduler information");
+        println!("  {}  Per-tenant queue wait report (e.g. 1h)", "fairness [window]".cyan());
+        println!("  {}  Infrastructure errors reported by wrappers", "errors [limit]".cyan());
+        println!("  {}  Who submitted, cancelled or drained what", "audit [limit] [--since|--actor|--action|--target]".cyan());
+        println!("  {}  Search finished jobs, even forgotten ones", "history [limit] [--since|--crate|--status|--worker|--tenant]".cyan());
+        println!("  {}  Recommended worker count (e.g. 5m)", "scaling [window]".cyan());
         println!();
-        println!("  {}  {}", "help".cyan(), "Show this help message");
-        println!("  {}  {}", "exit/quit".cyan(), "Exit the shell");
+        println!("  {}  List registered workers", "workers list".cyan());
+        println!("  {}  Stop giving a worker jobs; remove it once idle", "workers drain <id>".cyan());
+        println!("  {}  Follow cluster events (e.g. job-failed) until Ctrl-C", "events [kind...]".cyan());
+        println!("  {}  Check CAS, scheduler connectivity, clock skew and versions", "doctor".cyan());
+        println!("  {}  Show scheduler information", "scheduler status".cyan());
+        println!("  {}  Queue, throughput, latency and worker load", "scheduler stats [window]".cyan());
+        println!();
+        println!("  {}  Show this help message", "help".cyan());
+        println!("  {}  Exit the shell", "exit/quit".cyan());
+    }
+}
+
+/// Lines of job output shown by `inspect`
+const LOG_TAIL_LINES: usize = 10;
+
+/// A submission error, naming the reason if the scheduler rejected the job
+fn rejected(status: tonic::Status) -> anyhow::Error {
+    match rejection(&status) {
+        Some(reason) => anyhow::anyhow!("Job rejected ({}): {}", reason.as_str(), status.message()),
+        None => status.into(),
+    }
+}
+
+/// Shorten `text` to at most `max` characters for one-line display
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Warnings about workers running a different build than the scheduler
+/// (none if the scheduler doesn't report its own)
+async fn fleet_version_warnings(
+    client: &mut AuthedSchedulerClient,
+    workers: &[WorkerInfo],
+) -> Vec<String> {
+    let scheduler = match client.get_capabilities(GetCapabilitiesRequest {}).await {
+        Ok(resp) => resp.into_inner().version.map(BuildVersion::from),
+        Err(_) => None,
+    };
+    let Some(scheduler) = scheduler else {
+        return Vec::new();
+    };
+
+    let nodes: Vec<_> = workers
+        .iter()
+        .map(|w| (format!("Worker {}", w.worker_id), w.version.clone().map(BuildVersion::from)))
+        .collect();
+    fleet_warnings(&scheduler, &nodes)
+}
+
+/// Format a byte count using binary units (e.g. 1.5 MiB)
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// A unix time as local time, with how far off it is (e.g. `2026-10-17
+/// 02:00:00 (in 6h)`)
+fn format_time(at: i64) -> String {
+    let local = chrono::DateTime::from_timestamp(at, 0)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| at.to_string());
+    let secs = at - chrono::Utc::now().timestamp();
+    let wait = match secs.abs() {
+        s if s >= 3600 => format!("{}h", s / 3600),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    };
+    if secs >= 0 {
+        format!("{} (in {})", local, wait)
+    } else {
+        format!("{} ({} ago)", local, wait)
     }
 }
 
+/// Render a wire job status for display
+/// A build session's status, job counts and timing
+fn print_session(session: &SessionInfo) {
+    println!("\n  • {} [{}]", session.session_id.bright_yellow(), colored_status(session.status));
+    if !session.tenant.is_empty() {
+        println!("    Tenant: {}", session.tenant);
+    }
+    println!(
+        "    Jobs: {} ({} pending, {} running, {} completed, {} failed, {} cancelled)",
+        session.total, session.pending, session.running, session.completed, session.failed, session.cancelled
+    );
+    let started = chrono::DateTime::from_timestamp(session.started_at, 0).map(|t| t.with_timezone(&chrono::Local));
+    if let Some(started) = started {
+        println!("    Started: {}", started.format("%Y-%m-%d %H:%M:%S"));
+    }
+    // The scheduler's clock may be ahead of ours
+    let end = if session.finished_at > 0 { session.finished_at } else { chrono::Utc::now().timestamp() };
+    println!("    Duration: {}s{}", (end - session.started_at).max(0), if session.finished_at > 0 { "" } else { " so far" });
+}
+
+fn colored_status(status: i32) -> ColoredString {
+    let Ok(status) = JobStatusEnum::try_from(status) else {
+        return format!("UNKNOWN({})", status).white();
+    };
+
+    let label = status.to_string();
+    match status {
+        JobStatusEnum::Pending => label.yellow(),
+        JobStatusEnum::Assigned => label.cyan(),
+        JobStatusEnum::Running => label.blue(),
+        JobStatusEnum::Completed => label.green(),
+        JobStatusEnum::Failed => label.red(),
+        JobStatusEnum::Cancelled => label.magenta(),
+        JobStatusEnum::Blocked => label.bright_black(),
+        JobStatusEnum::QueuedRemote => label.bright_blue(),
+    }
+}
+
+/// Package names of the workspace members, via `cargo metadata`
+fn workspace_members(root: &Path) -> Option<Vec<String>> {
+    let output = std::process::Command::new("cargo")
+        .args(["metadata", "--no-deps", "--format-version", "1", "--manifest-path"])
+        .arg(root.join("Cargo.toml"))
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let metadata: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+
+    Some(
+        metadata["packages"]
+            .as_array()?
+            .iter()
+            .filter_map(|package| package["name"].as_str().map(str::to_string))
+            .collect(),
+    )
+}
@@ -1,5 +1,9 @@
+use crate::cas::verify::CorruptAction;
+use crate::common::config::Role;
 use crate::common::Config;
-use crate::master::commands::CommandExecutor;
+use crate::common::types::JobStatusEnum;
+use crate::master::cli::{parse_duration_secs, parse_event_kind};
+use crate::master::commands::{AuditFilter, CommandExecutor, HistoryFilter, JobFilter};
 use anyhow::Result;
 use colored::*;
 use rustyline::error::ReadlineError;
@@ -9,10 +13,12 @@ pub async fn run_repl() -> Result<()> {
     println!("{}", "🚀 cargo-distbuild interactive shell".bright_green().bold());
     println!("Type 'help' for available commands, 'exit' to quit\n");
 
-    let config = Config::load_default()?;
+    let config = Config::load_default_for(Role::Client)?;
     let executor = CommandExecutor::new(config)?;
 
     let mut rl: DefaultEditor = DefaultEditor::new()?;
+    // Target of the `logs`, `retry`, `cancel` and `inputs` shortcuts
+    let mut last_job: Option<String> = None;
     
     // Load history if available
     let history_file = dirs::home_dir()
@@ -35,7 +41,7 @@ pub async fn run_repl() -> Result<()> {
 
                 let _ = rl.add_history_entry(line);
 
-                if let Err(e) = handle_command(&executor, line).await {
+                if let Err(e) = handle_command(&executor, line, &mut last_job).await {
                     eprintln!("{} {}", "Error:".red().bold(), e);
                 }
             }
@@ -63,7 +69,7 @@ pub async fn run_repl() -> Result<()> {
     Ok(())
 }
 
-async fn handle_command(executor: &CommandExecutor, line: &str) -> Result<()> {
+async fn handle_command(executor: &CommandExecutor, line: &str, last_job: &mut Option<String>) -> Result<()> {
     let parts: Vec<&str> = line.split_whitespace().collect();
     
     if parts.is_empty() {
@@ -80,7 +86,7 @@ async fn handle_command(executor: &CommandExecutor, line: &str) -> Result<()> {
         }
         "cas" => {
             if parts.len() < 2 {
-                eprintln!("Usage: cas <put|get|exists|list> [args...]");
+                eprintln!("Usage: cas <put|get|cat|exists|list|rm|stats|namespaces|verify|export|import|refs|gc|compact> [args...]");
                 return Ok(());
             }
             
@@ -99,6 +105,20 @@ async fn handle_command(executor: &CommandExecutor, line: &str) -> Result<()> {
                     }
                     executor.cas_get(parts[2], parts[3]).await?;
                 }
+                "cat" => {
+                    if parts.len() < 3 {
+                        eprintln!("Usage: cas cat <hash>");
+                        return Ok(());
+                    }
+                    executor.cas_cat(parts[2]).await?;
+                }
+                "inspect" => {
+                    if parts.len() < 3 {
+                        eprintln!("Usage: cas inspect <hash>");
+                        return Ok(());
+                    }
+                    executor.cas_inspect(parts[2]).await?;
+                }
                 "exists" => {
                     if parts.len() < 3 {
                         eprintln!("Usage: cas exists <hash>");
@@ -109,9 +129,64 @@ async fn handle_command(executor: &CommandExecutor, line: &str) -> Result<()> {
                 "list" => {
                     executor.cas_list().await?;
                 }
+                "rm" => {
+                    if parts.len() < 3 {
+                        eprintln!("Usage: cas rm <hash> [--force]");
+                        return Ok(());
+                    }
+                    let force = parts.get(3) == Some(&"--force");
+                    executor.cas_rm(parts[2], force).await?;
+                }
+                "stats" => {
+                    executor.cas_stats().await?;
+                }
+                "namespaces" => {
+                    executor.cas_namespaces().await?;
+                }
+                "verify" => {
+                    let action = match parts.get(2) {
+                        Some(&"--delete") => CorruptAction::Delete,
+                        Some(&"--quarantine") => CorruptAction::Quarantine,
+                        _ => CorruptAction::Report,
+                    };
+                    executor.cas_verify(0, action).await?;
+                }
+                "export" => {
+                    if parts.len() < 3 {
+                        eprintln!("Usage: cas export <file>");
+                        return Ok(());
+                    }
+                    executor.cas_export(parts[2], None, None).await?;
+                }
+                "import" => {
+                    if parts.len() < 3 {
+                        eprintln!("Usage: cas import <file>");
+                        return Ok(());
+                    }
+                    executor.cas_import(parts[2]).await?;
+                }
+                "refs" => {
+                    if parts.len() < 3 {
+                        eprintln!("Usage: cas refs <hash>");
+                        return Ok(());
+                    }
+                    executor.cas_refs(parts[2]).await?;
+                }
+                "gc" => {
+                    let max_age = if parts.len() >= 3 {
+                        parts[2].parse().unwrap_or(604800)
+                    } else {
+                        604800
+                    };
+                    executor.cas_gc(max_age, false).await?;
+                }
+                "compact" => {
+                    let max_blob_kib = parts.get(2).and_then(|kib| kib.parse().ok());
+                    executor.cas_compact(max_blob_kib, None).await?;
+                }
                 _ => {
                     eprintln!("Unknown cas subcommand: {}", parts[1]);
-                    eprintln!("Available: put, get, exists, list");
+                    eprintln!("Available: put, get, cat, exists, list, rm, stats, namespaces, verify, export, import, refs, gc, compact");
                 }
             }
         }
@@ -124,10 +199,18 @@ async fn handle_command(executor: &CommandExecutor, line: &str) -> Result<()> {
             match parts[1] {
                 "submit" => {
                     if parts.len() < 3 {
-                        eprintln!("Usage: job submit <input-hash>");
+                        eprintln!("Usage: job submit <input-hash> [priority]");
                         return Ok(());
                     }
-                    executor.submit_job(parts[2]).await?;
+                    let priority = match parts.get(3).map(|p| p.parse::<i32>()) {
+                        None => 0,
+                        Some(Ok(priority)) => priority,
+                        Some(Err(_)) => {
+                            eprintln!("Priority must be a number");
+                            return Ok(());
+                        }
+                    };
+                    executor.submit_job(parts[2], priority, Default::default(), None, None, None).await?;
                 }
                 "status" => {
                     if parts.len() < 3 {
@@ -144,18 +227,44 @@ async fn handle_command(executor: &CommandExecutor, line: &str) -> Result<()> {
         }
         "jobs" => {
             if parts.len() < 2 {
-                eprintln!("Usage: jobs list [limit]");
+                eprintln!("Usage: jobs list [limit] [--since 2h] [--older-than 30m] [--status failed] [--worker id] [--type t] [--tenant t] [--session s] [--grep text] [--cursor c]");
                 return Ok(());
             }
             
             match parts[1] {
                 "list" => {
-                    let limit = if parts.len() >= 3 {
-                        parts[2].parse().unwrap_or(10)
-                    } else {
-                        10
+                    let mut filter = JobFilter {
+                        limit: 10,
+                        ..Default::default()
                     };
-                    executor.list_jobs(limit).await?;
+
+                    let mut args = parts[2..].iter();
+                    while let Some(arg) = args.next() {
+                        let mut value = || args.next().map(|value| value.to_string());
+                        match *arg {
+                            "--since" => {
+                                let value = value().unwrap_or_default();
+                                filter.since_secs = Some(parse_duration_secs(&value).map_err(anyhow::Error::msg)?);
+                            }
+                            "--older-than" => {
+                                let value = value().unwrap_or_default();
+                                filter.older_than_secs = Some(parse_duration_secs(&value).map_err(anyhow::Error::msg)?);
+                            }
+                            "--status" => {
+                                let value = value().unwrap_or_default();
+                                filter.statuses.push(value.parse::<JobStatusEnum>().map_err(anyhow::Error::msg)?);
+                            }
+                            "--worker" => filter.worker = value(),
+                            "--type" => filter.job_type = value(),
+                            "--tenant" => filter.tenant = value(),
+                            "--session" => filter.session = value(),
+                            "--grep" => filter.grep = value(),
+                            "--cursor" => filter.cursor = value(),
+                            other => filter.limit = other.parse().unwrap_or(10),
+                        }
+                    }
+
+                    executor.list_jobs(filter).await?;
                 }
                 _ => {
                     eprintln!("Unknown jobs subcommand: {}", parts[1]);
@@ -163,9 +272,121 @@ async fn handle_command(executor: &CommandExecutor, line: &str) -> Result<()> {
                 }
             }
         }
+        "sessions" => {
+            match (parts.get(1).copied(), parts.get(2)) {
+                (Some("list"), _) => {
+                    let active = parts[2..].contains(&"--active");
+                    let limit = parts[2..].iter().find_map(|arg| arg.parse().ok()).unwrap_or(10);
+                    executor.list_sessions(limit, active).await?;
+                }
+                (Some("show"), Some(session_id)) => executor.show_session(session_id).await?,
+                (Some("cancel"), Some(session_id)) => executor.cancel_session(session_id).await?,
+                _ => eprintln!("Usage: sessions list [limit] [--active] | sessions show <id> | sessions cancel <id>"),
+            }
+        }
+        "inspect" => {
+            let Some(job_id) = parts.get(1).map(|id| id.to_string()).or_else(|| last_job.clone()) else {
+                eprintln!("Usage: inspect <job-id>");
+                return Ok(());
+            };
+            executor.inspect_job(&job_id).await?;
+            println!("\n{}", "Shortcuts: logs, retry, cancel, inputs".bright_black());
+            *last_job = Some(job_id);
+        }
+        "logs" | "retry" | "cancel" | "inputs" => {
+            let Some(job_id) = parts.get(1).map(|id| id.to_string()).or_else(|| last_job.clone()) else {
+                eprintln!("No job inspected yet; run 'inspect <job-id>' first");
+                return Ok(());
+            };
+            match parts[0] {
+                "logs" => executor.job_logs(&job_id).await?,
+                "retry" => *last_job = Some(executor.retry_job(&job_id).await?),
+                "cancel" => executor.cancel_job(&job_id).await?,
+                _ => executor.job_inputs(&job_id).await?,
+            }
+        }
+        "fairness" => {
+            let window = match parts.get(1) {
+                Some(w) => parse_duration_secs(w).map_err(anyhow::Error::msg)?,
+                None => 3600,
+            };
+            executor.fairness_report(window).await?;
+        }
+        "doctor" => {
+            executor.doctor().await?;
+        }
+        "scaling" => {
+            let window = match parts.get(1) {
+                Some(w) => parse_duration_secs(w).map_err(anyhow::Error::msg)?,
+                None => 300,
+            };
+            executor.scaling_advice(window, 0.8, false).await?;
+        }
+        "errors" => {
+            let limit = if parts.len() >= 2 {
+                parts[1].parse().unwrap_or(20)
+            } else {
+                20
+            };
+            executor.client_errors(limit).await?;
+        }
+        "audit" => {
+            let mut filter = AuditFilter {
+                limit: 50,
+                ..Default::default()
+            };
+            let mut args = parts[1..].iter();
+            while let Some(arg) = args.next() {
+                let mut value = || args.next().map(|value| value.to_string());
+                match *arg {
+                    "--since" => {
+                        let value = value().unwrap_or_default();
+                        filter.since_secs = Some(parse_duration_secs(&value).map_err(anyhow::Error::msg)?);
+                    }
+                    "--actor" => filter.actor = value(),
+                    "--action" => filter.action = value(),
+                    "--target" => filter.target = value(),
+                    other => filter.limit = other.parse().unwrap_or(50),
+                }
+            }
+            executor.audit(filter).await?;
+        }
+        "history" => {
+            let mut filter = HistoryFilter {
+                limit: 20,
+                ..Default::default()
+            };
+            let mut args = parts[1..].iter();
+            while let Some(arg) = args.next() {
+                let mut value = || args.next().map(|value| value.to_string());
+                match *arg {
+                    "--since" => {
+                        let value = value().unwrap_or_default();
+                        filter.since_secs = Some(parse_duration_secs(&value).map_err(anyhow::Error::msg)?);
+                    }
+                    "--crate" => filter.crate_name = value(),
+                    "--status" => {
+                        let value = value().unwrap_or_default();
+                        filter.statuses.push(value.parse::<JobStatusEnum>().map_err(anyhow::Error::msg)?);
+                    }
+                    "--worker" => filter.worker = value(),
+                    "--tenant" => filter.tenant = value(),
+                    other => filter.limit = other.parse().unwrap_or(20),
+                }
+            }
+            executor.job_history(filter).await?;
+        }
+        "events" => {
+            let kinds = parts[1..]
+                .iter()
+                .map(|kind| parse_event_kind(kind))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(anyhow::Error::msg)?;
+            executor.events(&kinds).await?;
+        }
         "workers" => {
             if parts.len() < 2 {
-                eprintln!("Usage: workers list");
+                eprintln!("Usage: workers list | workers drain <worker-id>");
                 return Ok(());
             }
             
@@ -173,15 +394,22 @@ async fn handle_command(executor: &CommandExecutor, line: &str) -> Result<()> {
                 "list" => {
                     executor.list_workers().await?;
                 }
+                "drain" => {
+                    let Some(worker_id) = parts.get(2) else {
+                        eprintln!("Usage: workers drain <worker-id>");
+                        return Ok(());
+                    };
+                    executor.drain_worker(worker_id, 600).await?;
+                }
                 _ => {
                     eprintln!("Unknown workers subcommand: {}", parts[1]);
-                    eprintln!("Available: list");
+                    eprintln!("Available: list, drain");
                 }
             }
         }
         "scheduler" => {
             if parts.len() < 2 {
-                eprintln!("Usage: scheduler status");
+                eprintln!("Usage: scheduler <status|stats> [window]");
                 return Ok(());
             }
             
@@ -189,9 +417,16 @@ async fn handle_command(executor: &CommandExecutor, line: &str) -> Result<()> {
                 "status" => {
                     executor.scheduler_status().await?;
                 }
+                "stats" => {
+                    let window = match parts.get(2) {
+                        Some(w) => parse_duration_secs(w).map_err(anyhow::Error::msg)?,
+                        None => 300,
+                    };
+                    executor.scheduler_stats(window).await?;
+                }
                 _ => {
                     eprintln!("Unknown scheduler subcommand: {}", parts[1]);
-                    eprintln!("Available: status");
+                    eprintln!("Available: status, stats");
                 }
             }
         }
@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Audited actions, one JSON object per line, never rewritten
+const AUDIT_FILE: &str = "audit.jsonl";
+/// Most recent records kept in memory without a state dir
+const MAX_RECENT: usize = 10_000;
+
+/// Someone submitting, cancelling or otherwise changing something
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct AuditRecord {
+    pub at: i64,
+    /// Identity of the token it came with, else the peer's address
+    pub actor: String,
+    /// Tenant the token acts as, if any
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// e.g. `submit-job`, `cancel-session`, `drain-worker`
+    pub action: String,
+    /// Job, session or worker acted on (empty for cluster-wide actions)
+    #[serde(default)]
+    pub target: String,
+    #[serde(default)]
+    pub detail: String,
+}
+
+/// Who did what and when. With a state dir every record is appended to
+/// `audit.jsonl` there, which queries read in full, so the trail outlives
+/// restarts and the jobs it mentions; otherwise only the most recent are
+/// kept, in memory.
+#[derive(Debug, Default)]
+pub(crate) struct AuditLog {
+    recent: VecDeque<AuditRecord>,
+    path: Option<PathBuf>,
+}
+
+impl AuditLog {
+    /// The trail in `dir` (a state dir, or a mirrored primary's)
+    pub fn open(dir: &Path) -> Self {
+        AuditLog {
+            recent: VecDeque::new(),
+            path: Some(dir.join(AUDIT_FILE)),
+        }
+    }
+
+    /// Add `record`; a failure to write it is logged, not fatal, so the
+    /// action it records isn't refused
+    pub fn record(&mut self, record: AuditRecord) {
+        if let Some(path) = &self.path {
+            if let Err(e) = append(path, &record) {
+                warn!("⚠️  Failed to write audit record to {:?}: {:#}", path, e);
+            }
+            return;
+        }
+        if self.recent.len() >= MAX_RECENT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(record);
+    }
+
+    /// Records matching `filter`, newest first, at most `limit` (0 = all)
+    pub fn query(&self, filter: impl Fn(&AuditRecord) -> bool, limit: usize) -> Result<Vec<AuditRecord>> {
+        let mut records: Vec<AuditRecord> = match &self.path {
+            Some(path) => match fs::read_to_string(path) {
+                // Skip a line torn by a crash mid-write
+                Ok(content) => content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+            },
+            None => self.recent.iter().cloned().collect(),
+        };
+        records.retain(|record| filter(record));
+        records.reverse();
+        if limit > 0 {
+            records.truncate(limit);
+        }
+        Ok(records)
+    }
+}
+
+fn append(path: &Path, record: &AuditRecord) -> Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(&line))
+        .with_context(|| format!("Failed to append to {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record(at: i64, actor: &str, action: &str) -> AuditRecord {
+        AuditRecord {
+            at,
+            actor: actor.to_string(),
+            tenant: None,
+            action: action.to_string(),
+            target: format!("job-{}", at),
+            detail: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_trail_survives_reopening() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut audit = AuditLog::open(temp_dir.path());
+        audit.record(record(1, "ci", "submit-job"));
+        audit.record(record(2, "alice", "cancel-job"));
+        audit.record(record(3, "ci", "cancel-job"));
+
+        let audit = AuditLog::open(temp_dir.path());
+        let all = audit.query(|_| true, 0).unwrap();
+        assert_eq!(all.iter().map(|r| r.at).collect::<Vec<_>>(), [3, 2, 1]);
+        let by_ci = audit.query(|r| r.actor == "ci", 1).unwrap();
+        assert_eq!(by_ci, [record(3, "ci", "cancel-job")]);
+    }
+
+    #[test]
+    fn test_memory_keeps_most_recent() {
+        let mut audit = AuditLog::default();
+        for at in 0..MAX_RECENT as i64 + 5 {
+            audit.record(record(at, "ci", "submit-job"));
+        }
+        let all = audit.query(|_| true, 0).unwrap();
+        assert_eq!(all.len(), MAX_RECENT);
+        assert_eq!(all.last().unwrap().at, 5);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::common::auth::{AuthedWorkerClient, ClientAuth};
+use crate::proto::distbuild::worker_client::WorkerClient;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tonic::transport::Channel;
+use tonic::{Code, Status};
+
+/// How long to wait for a worker to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Open connections are pinged this often, and dropped when a ping goes
+/// unanswered for `KEEP_ALIVE_TIMEOUT`, so a dead worker's connection is
+/// noticed before a dispatch has to wait on it
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Channels to workers kept open by address, so dispatches, aborts and
+/// stop notices reuse a connection (and its TLS handshake) rather than
+/// dialing every time. A channel carries concurrent calls and reconnects
+/// once its connection drops; one whose call failed in transport is
+/// discarded, so the next call dials afresh. Clones share the channels.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WorkerChannels {
+    auth: ClientAuth,
+    channels: Arc<Mutex<HashMap<String, Channel>>>,
+}
+
+impl WorkerChannels {
+    pub fn new(auth: ClientAuth) -> Self {
+        WorkerChannels {
+            auth,
+            channels: Arc::default(),
+        }
+    }
+
+    /// A client for the worker at `address` (host:port), on its open
+    /// channel if there is one; connects on first use
+    pub fn client(&self, address: &str) -> Result<AuthedWorkerClient> {
+        let mut channels = self.channels.lock().unwrap();
+        if let Some(channel) = channels.get(address) {
+            return Ok(WorkerClient::with_interceptor(channel.clone(), self.auth.clone()));
+        }
+        let channel = self
+            .auth
+            .endpoint(format!("http://{}", address))?
+            .connect_timeout(CONNECT_TIMEOUT)
+            .http2_keep_alive_interval(KEEP_ALIVE_INTERVAL)
+            .keep_alive_timeout(KEEP_ALIVE_TIMEOUT)
+            .keep_alive_while_idle(true)
+            .connect_lazy();
+        channels.insert(address.to_string(), channel.clone());
+        Ok(WorkerClient::with_interceptor(channel, self.auth.clone()))
+    }
+
+    /// Pass on the `result` of a call to `address`, discarding its channel
+    /// if the call never got through
+    #[allow(clippy::result_large_err)] // passes tonic's `Status` through as is
+    pub fn check<T>(&self, address: &str, result: Result<T, Status>) -> Result<T, Status> {
+        if let Err(status) = &result {
+            if matches!(status.code(), Code::Unavailable | Code::Unknown) {
+                self.channels.lock().unwrap().remove(address);
+            }
+        }
+        result
+    }
+
+    /// Close channels to anything but `addresses` (the registered workers)
+    pub fn retain<'a>(&self, addresses: impl IntoIterator<Item = &'a str>) {
+        let keep: HashSet<&str> = addresses.into_iter().collect();
+        self.channels.lock().unwrap().retain(|address, _| keep.contains(address.as_str()));
+    }
+
+    #[cfg(test)]
+    fn open(&self) -> usize {
+        self.channels.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::distbuild::AbortJobRequest;
+
+    #[tokio::test]
+    async fn test_channels_are_reused_until_they_fail() {
+        let channels = WorkerChannels::default();
+        channels.client("127.0.0.1:1").unwrap();
+        channels.client("127.0.0.1:1").unwrap();
+        channels.client("127.0.0.1:2").unwrap();
+        assert_eq!(channels.open(), 2);
+
+        // Nothing listens there, so the call fails in transport
+        let mut client = channels.client("127.0.0.1:1").unwrap();
+        let result = client.abort_job(AbortJobRequest { job_id: "job".to_string() }).await;
+        assert!(channels.check("127.0.0.1:1", result).is_err());
+        assert_eq!(channels.open(), 1);
+
+        // Other failures say nothing about the connection
+        let refused: Result<(), Status> = Err(Status::not_found("no such job"));
+        assert!(channels.check("127.0.0.1:2", refused).is_err());
+        assert_eq!(channels.open(), 1);
+
+        channels.retain(["127.0.0.1:3"]);
+        assert_eq!(channels.open(), 0);
+    }
+}
@@ -0,0 +1,242 @@
+use super::{SchedulerState, Scope};
+use crate::common::clock;
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::RwLock;
+
+/// How often the queue depth is sampled for the graph
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+/// Samples kept (an hour's worth)
+const MAX_SAMPLES: usize = 720;
+/// Most recent jobs listed
+const MAX_JOBS: usize = 100;
+/// Max size of a request line plus headers
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+
+const PAGE: &str = include_str!("dashboard.html");
+
+/// (time, pending jobs) samples, oldest first
+type History = Arc<Mutex<VecDeque<(i64, u32)>>>;
+
+/// Serve the dashboard on `addr`: `GET /` is the page, which refreshes
+/// itself from `GET /api/state`
+pub(crate) async fn serve(state: Arc<RwLock<SchedulerState>>, addr: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind dashboard to {}", addr))?;
+    info!("📊 Dashboard on http://{}", addr);
+    serve_listener(state, listener).await
+}
+
+async fn serve_listener(state: Arc<RwLock<SchedulerState>>, listener: TcpListener) -> Result<()> {
+    let history = History::default();
+    spawn_sampler(state.clone(), history.clone());
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let (state, history) = (state.clone(), history.clone());
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(&state, &history, stream).await {
+                warn!("⚠️  Dashboard request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// Record the queue depth every `SAMPLE_INTERVAL`
+fn spawn_sampler(state: Arc<RwLock<SchedulerState>>, history: History) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let depth = state.read().await.stats(&Scope::default(), 1, clock::now()).queue_depth;
+            let mut history = history.lock().unwrap();
+            if history.len() == MAX_SAMPLES {
+                history.pop_front();
+            }
+            history.push_back((clock::now(), depth));
+        }
+    });
+}
+
+async fn handle_connection(state: &RwLock<SchedulerState>, history: &History, stream: TcpStream) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+    let Some((method, path)) = read_request(&mut stream).await? else {
+        return respond(&mut stream, "400 Bad Request", "text/plain", b"bad request\n").await;
+    };
+    if method != "GET" {
+        return respond(&mut stream, "405 Method Not Allowed", "text/plain", b"").await;
+    }
+
+    match path.as_str() {
+        "/" => respond(&mut stream, "200 OK", "text/html; charset=utf-8", PAGE.as_bytes()).await,
+        "/api/state" => {
+            let body = snapshot(&*state.read().await, history, clock::now());
+            respond(&mut stream, "200 OK", "application/json", body.to_string().as_bytes()).await
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"not found\n").await,
+    }
+}
+
+/// What the page shows: summary stats, workers, recent jobs and the queue
+/// depth history
+fn snapshot(state: &SchedulerState, history: &History, now: i64) -> serde_json::Value {
+    let stats = state.stats(&Scope::default(), 300, now);
+
+    let mut workers: Vec<_> = state.workers.values().collect();
+    workers.sort_by(|a, b| a.worker_id.cmp(&b.worker_id));
+    let workers: Vec<_> = workers
+        .into_iter()
+        .map(|worker| {
+            json!({
+                "id": worker.worker_id,
+                "address": worker.address,
+                "active_jobs": worker.active_jobs,
+                "capacity": worker.capacity,
+                "online": state.liveness.is_online(worker, now),
+                "state": worker.state.to_string(),
+                "draining": worker.draining,
+                "quarantined_until": worker.quarantined_until,
+                "last_heartbeat": worker.last_heartbeat,
+                "health": state.health.report(&worker.worker_id, now).score,
+                "labels": worker.labels,
+            })
+        })
+        .collect();
+
+    let mut jobs: Vec<_> = state.jobs.values().collect();
+    jobs.sort_by_key(|job| std::cmp::Reverse(job.submitted_at));
+    let jobs: Vec<_> = jobs
+        .into_iter()
+        .take(MAX_JOBS)
+        .map(|job| {
+            json!({
+                "id": job.job_id,
+                "status": job.status.to_string(),
+                "crate_name": job.metadata.get("crate_name").cloned().unwrap_or_default(),
+                "worker": job.assigned_worker,
+                "submitted_at": job.submitted_at,
+                "started_at": job.started_at,
+                "completed_at": job.completed_at,
+                "error": job.error,
+            })
+        })
+        .collect();
+
+    let history: Vec<_> = history.lock().unwrap().iter().map(|&(at, depth)| json!([at, depth])).collect();
+
+    json!({
+        "now": now,
+        "queue_depth": stats.queue_depth,
+        "total_jobs": stats.total_jobs,
+        "jobs_by_status": stats.jobs_by_status,
+        "jobs_per_minute": stats.jobs_per_minute,
+        "avg_queue_latency_secs": stats.avg_queue_latency_secs,
+        "workers": workers,
+        "jobs": jobs,
+        "queue_history": history,
+    })
+}
+
+/// The request's method and path (query string dropped)
+async fn read_request(stream: &mut BufReader<TcpStream>) -> Result<Option<(String, String)>> {
+    let mut line = String::new();
+    let mut total = stream.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    let request = (method.to_string(), path.split('?').next().unwrap_or(path).to_string());
+
+    // Skip the headers; nothing here depends on them
+    loop {
+        let mut header = String::new();
+        let read = stream.read_line(&mut header).await?;
+        total += read;
+        if read == 0 || total > MAX_HEADER_BYTES {
+            return Ok(None);
+        }
+        if header.trim_end().is_empty() {
+            return Ok(Some(request));
+        }
+    }
+}
+
+async fn respond(stream: &mut BufReader<TcpStream>, status: &str, content_type: &str, body: &[u8]) -> Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nConnection: close\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    stream.get_mut().write_all(head.as_bytes()).await?;
+    stream.get_mut().write_all(body).await?;
+    stream.get_mut().shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::types::WorkerMetadata;
+    use tokio::io::AsyncReadExt;
+
+    async fn request(addr: std::net::SocketAddr, raw: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(raw.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_serve_dashboard() {
+        let mut state = SchedulerState::default();
+        state.workers.insert(
+            "w1".to_string(),
+            WorkerMetadata {
+                worker_id: "w1".to_string(),
+                address: "127.0.0.1:6001".to_string(),
+                capacity: 4,
+                active_jobs: 1,
+                last_heartbeat: clock::now(),
+                labels: Default::default(),
+                version: None,
+                draining: false,
+                quarantined_until: None,
+                tenant: None,
+                capabilities: None,
+                pool: None,
+                state: Default::default(),
+                state_since: 0,
+                cached_hashes: Arc::default(),
+            },
+        );
+        let state = Arc::new(RwLock::new(state));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_listener(state, listener));
+
+        let page = request(addr, "GET / HTTP/1.1\r\n\r\n").await;
+        assert!(page.starts_with("HTTP/1.1 200 OK"));
+        assert!(page.contains("text/html"));
+
+        let api = request(addr, "GET /api/state HTTP/1.1\r\n\r\n").await;
+        let body = api.split("\r\n\r\n").nth(1).unwrap();
+        let snapshot: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(snapshot["workers"][0]["id"], "w1");
+        assert_eq!(snapshot["workers"][0]["online"], true);
+        assert_eq!(snapshot["workers"][0]["health"], 1.0);
+        assert_eq!(snapshot["queue_depth"], 0);
+
+        let missing = request(addr, "GET /nope HTTP/1.1\r\n\r\n").await;
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found"));
+    }
+}
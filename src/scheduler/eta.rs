@@ -0,0 +1,190 @@
+use crate::common::config::WeightConfig;
+use crate::common::types::{JobMetadata, JobStatusEnum};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Most recent run times kept per crate and per job type
+const MAX_SAMPLES: usize = 10;
+
+/// How long jobs took on workers, by crate (and profile) and by job type,
+/// to estimate how long the next run of each will take: the median of its
+/// crate's recent runs, or of its job type's for a crate not built yet. The
+/// same runs size up how many worker slots a job should take.
+/// Saved in the state store, so estimates survive restarts and outlive
+/// the jobs they came from.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct DurationHistory {
+    /// Milliseconds, oldest first
+    by_crate: HashMap<String, VecDeque<u64>>,
+    by_job_type: HashMap<String, VecDeque<u64>>,
+}
+
+/// What to expect of a job still in progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Eta {
+    /// A run of it usually takes
+    pub expected_secs: u64,
+    /// Left of that, the whole of it if not started
+    pub remaining_secs: u64,
+}
+
+impl DurationHistory {
+    /// Note how long `job` ran, if it completed on a worker (rather than
+    /// from the cache)
+    pub fn record(&mut self, job: &JobMetadata) {
+        let Some(millis) = job.usage.as_ref().map(|usage| usage.exec_millis).filter(|&millis| millis > 0) else {
+            return;
+        };
+        if job.status != JobStatusEnum::Completed {
+            return;
+        }
+        let keys = [(&mut self.by_crate, crate_key(job)), (&mut self.by_job_type, Some(job.job_type.clone()))];
+        for (samples, key) in keys {
+            let Some(key) = key else {
+                continue;
+            };
+            let samples = samples.entry(key).or_default();
+            if samples.len() >= MAX_SAMPLES {
+                samples.pop_front();
+            }
+            samples.push_back(millis);
+        }
+    }
+
+    /// When `job`, queued or running, should finish, as of `now`; `None`
+    /// once it's done or with nothing to go by
+    pub fn eta(&self, job: &JobMetadata, now: i64) -> Option<Eta> {
+        if job.status.is_terminal() {
+            return None;
+        }
+        let samples = crate_key(job)
+            .and_then(|key| self.by_crate.get(&key))
+            .or_else(|| self.by_job_type.get(&job.job_type))?;
+        let expected_secs = median(samples).div_ceil(1000).max(1);
+
+        let elapsed = match (job.status, job.started_at) {
+            (JobStatusEnum::Running, Some(started_at)) => (now - started_at).max(0) as u64,
+            _ => 0,
+        };
+        Some(Eta {
+            expected_secs,
+            remaining_secs: expected_secs.saturating_sub(elapsed),
+        })
+    }
+
+    /// Worker slots `job` should take up (see `WeightConfig`): by how long
+    /// its crate's recent runs took, or else by `input_bytes`
+    pub fn weight(&self, job: &JobMetadata, input_bytes: u64, config: &WeightConfig) -> u32 {
+        let by_runs = crate_key(job)
+            .and_then(|key| self.by_crate.get(&key))
+            .filter(|_| config.slot_secs > 0)
+            .map(|samples| median(samples).div_ceil(config.slot_secs * 1000));
+        let by_input = (config.slot_bytes > 0).then(|| input_bytes.div_ceil(config.slot_bytes));
+        by_runs.or(by_input).unwrap_or(1).clamp(1, u64::from(config.max_weight.max(1))) as u32
+    }
+}
+
+fn median(samples: &VecDeque<u64>) -> u64 {
+    let mut sorted: Vec<u64> = samples.iter().copied().collect();
+    sorted.sort_unstable();
+    sorted[sorted.len() / 2]
+}
+
+/// Runs of the same crate in another profile take their own time
+fn crate_key(job: &JobMetadata) -> Option<String> {
+    let name = job.metadata.get("crate_name").filter(|name| !name.is_empty())?;
+    Some(match job.metadata.get("profile") {
+        Some(profile) => format!("{}@{}", name, profile),
+        None => name.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::types::JobUsage;
+
+    fn job(crate_name: &str, status: JobStatusEnum, exec_millis: u64) -> JobMetadata {
+        JobMetadata {
+            job_id: format!("{}-{}", crate_name, exec_millis),
+            input_hash: String::new(),
+            output_hash: None,
+            error: None,
+            error_kind: Default::default(),
+            job_type: "rust-compile".to_string(),
+            status,
+            assigned_worker: None,
+            submitted_at: 0,
+            started_at: Some(100),
+            completed_at: None,
+            metadata: HashMap::from([("crate_name".to_string(), crate_name.to_string())]),
+            preemptions: 0,
+            worker_platform: None,
+            priority: 0,
+            timeline: Vec::new(),
+            progress: None,
+            attempts: 1,
+            attempt_history: Vec::new(),
+            retry_at: None,
+            run_after: None,
+            depends_on: Vec::new(),
+            constraints: HashMap::new(),
+            timeout_secs: None,
+            attached_to: None,
+            usage: Some(JobUsage {
+                exec_millis,
+                ..Default::default()
+            }),
+            tenant: None,
+            session_id: None,
+            log_hash: None,
+            weight: 1,
+            pool: None,
+            boosted: false,
+            gang: None,
+        }
+    }
+
+    #[test]
+    fn test_eta_from_past_runs() {
+        let mut history = DurationHistory::default();
+        assert_eq!(history.eta(&job("serde", JobStatusEnum::Pending, 0), 100), None);
+
+        for millis in [8_000, 12_000, 30_000] {
+            history.record(&job("serde", JobStatusEnum::Completed, millis));
+        }
+        // Failed runs and cache hits say nothing about how long it takes
+        history.record(&job("serde", JobStatusEnum::Failed, 1_000));
+        history.record(&job("serde", JobStatusEnum::Completed, 0));
+        history.record(&job("tiny", JobStatusEnum::Completed, 200));
+
+        let eta = |crate_name: &str, status, now| history.eta(&job(crate_name, status, 0), now);
+        let expected = |expected_secs, remaining_secs| Some(Eta { expected_secs, remaining_secs });
+        assert_eq!(eta("serde", JobStatusEnum::Pending, 100), expected(12, 12));
+        assert_eq!(eta("serde", JobStatusEnum::Running, 105), expected(12, 7));
+        assert_eq!(eta("serde", JobStatusEnum::Running, 200), expected(12, 0));
+        assert_eq!(eta("tiny", JobStatusEnum::Running, 100), expected(1, 1));
+        // A crate never built goes by all compiles
+        assert_eq!(eta("tokio", JobStatusEnum::Pending, 100), expected(12, 12));
+        assert_eq!(eta("serde", JobStatusEnum::Completed, 100), None);
+    }
+
+    #[test]
+    fn test_weight_from_runs_or_input() {
+        let mut history = DurationHistory::default();
+        for millis in [100_000, 130_000, 400_000] {
+            history.record(&job("heavy", JobStatusEnum::Completed, millis));
+        }
+        let config = WeightConfig::default();
+        let weight = |crate_name: &str, input_bytes| history.weight(&job(crate_name, JobStatusEnum::Pending, 0), input_bytes, &config);
+        // Runs usually take over two minutes, however small the input
+        assert_eq!(weight("heavy", 0), 3);
+        // A crate not built yet goes by its input, up to max_weight
+        assert_eq!(weight("new", 0), 1);
+        assert_eq!(weight("new", 5 << 20), 2);
+        assert_eq!(weight("new", 1 << 30), 4);
+
+        let config = WeightConfig { max_weight: 1, ..config };
+        assert_eq!(history.weight(&job("heavy", JobStatusEnum::Pending, 0), 0, &config), 1);
+    }
+}
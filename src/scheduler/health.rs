@@ -0,0 +1,194 @@
+use crate::common::config::HealthConfig;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Heartbeat gaps kept to judge how regular a worker's heartbeats are
+const MAX_GAPS: usize = 20;
+/// Share of the running dispatch latency average each new dispatch makes up
+const LATENCY_WEIGHT: f64 = 0.2;
+/// Successes every worker is credited with, so one early failure doesn't
+/// mark a new worker unhealthy
+const PRIOR_SUCCESSES: f64 = 2.0;
+
+/// How each worker has been doing lately, beyond online or offline: its
+/// job outcomes, how long dispatches to it take and how regularly its
+/// heartbeats arrive, combined into a score by `report`. Kept in memory
+/// only; a restarted scheduler starts everyone afresh.
+#[derive(Debug, Default)]
+pub(crate) struct HealthTracker {
+    config: HealthConfig,
+    workers: HashMap<String, WorkerHealth>,
+}
+
+#[derive(Debug, Default)]
+struct WorkerHealth {
+    /// (when, failed) for recent job outcomes, oldest first
+    outcomes: VecDeque<(i64, bool)>,
+    /// Moving average of dispatch round trips
+    latency_ms: Option<f64>,
+    last_heartbeat: Option<i64>,
+    /// Seconds between recent heartbeats, oldest first
+    gaps: VecDeque<i64>,
+}
+
+/// A worker's health score (0 to 1, higher is better) and what went into it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct HealthReport {
+    pub score: f64,
+    /// Share of recent jobs failed
+    pub failure_rate: f64,
+    /// Typical dispatch round trip; 0 = none yet
+    pub dispatch_latency_ms: u64,
+    /// Share of recent heartbeats that came on time
+    pub heartbeat_stability: f64,
+}
+
+impl HealthTracker {
+    pub fn new(config: HealthConfig) -> Self {
+        HealthTracker {
+            config,
+            workers: HashMap::new(),
+        }
+    }
+
+    /// Note a job on `worker_id` finishing, `failed` or not
+    pub fn record_outcome(&mut self, worker_id: &str, failed: bool, now: i64) {
+        let window = self.config.window_secs as i64;
+        let outcomes = &mut self.workers.entry(worker_id.to_string()).or_default().outcomes;
+        outcomes.push_back((now, failed));
+        while outcomes.front().is_some_and(|&(at, _)| now - at > window) {
+            outcomes.pop_front();
+        }
+    }
+
+    /// Note a dispatch to `worker_id` being accepted after `latency`
+    pub fn record_dispatch(&mut self, worker_id: &str, latency: Duration) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let average = &mut self.workers.entry(worker_id.to_string()).or_default().latency_ms;
+        *average = Some(match *average {
+            Some(average) => average + LATENCY_WEIGHT * (latency_ms - average),
+            None => latency_ms,
+        });
+    }
+
+    pub fn record_heartbeat(&mut self, worker_id: &str, now: i64) {
+        let health = self.workers.entry(worker_id.to_string()).or_default();
+        if let Some(last) = health.last_heartbeat.replace(now) {
+            if health.gaps.len() >= MAX_GAPS {
+                health.gaps.pop_front();
+            }
+            health.gaps.push_back(now - last);
+        }
+    }
+
+    pub fn forget(&mut self, worker_id: &str) {
+        self.workers.remove(worker_id);
+    }
+
+    /// Whether a worker scoring `score` is only to get jobs nobody
+    /// healthier can take
+    pub fn is_unhealthy(&self, score: f64) -> bool {
+        score < self.config.unhealthy_below
+    }
+
+    /// How `worker_id` is doing as of `now`; perfectly, as far as anyone
+    /// knows, if nothing has been recorded for it
+    pub fn report(&self, worker_id: &str, now: i64) -> HealthReport {
+        let Some(health) = self.workers.get(worker_id) else {
+            return HealthReport {
+                score: 1.0,
+                failure_rate: 0.0,
+                dispatch_latency_ms: 0,
+                heartbeat_stability: 1.0,
+            };
+        };
+
+        let window = self.config.window_secs as i64;
+        let recent: Vec<bool> = health
+            .outcomes
+            .iter()
+            .filter(|&&(at, _)| now - at <= window)
+            .map(|&(_, failed)| failed)
+            .collect();
+        let failed = recent.iter().filter(|&&failed| failed).count();
+        let failure_rate = failed as f64 / (recent.len() as f64 + PRIOR_SUCCESSES);
+
+        let latency_ms = health.latency_ms.unwrap_or(0.0);
+        let slow_ms = self.config.slow_dispatch_ms as f64;
+        let latency_factor = if slow_ms > 0.0 && latency_ms > slow_ms { slow_ms / latency_ms } else { 1.0 };
+
+        let heartbeat_stability = health.stability(now);
+        HealthReport {
+            score: (1.0 - failure_rate) * latency_factor * heartbeat_stability,
+            failure_rate,
+            dispatch_latency_ms: latency_ms.round() as u64,
+            heartbeat_stability,
+        }
+    }
+}
+
+impl WorkerHealth {
+    /// Share of recent heartbeat gaps no longer than half again the usual
+    /// (median) one, counting the current silence if it's already longer
+    fn stability(&self, now: i64) -> f64 {
+        if self.gaps.is_empty() {
+            return 1.0;
+        }
+        let mut sorted: Vec<i64> = self.gaps.iter().copied().collect();
+        sorted.sort_unstable();
+        // Timestamps are whole seconds, so allow one either way
+        let late = sorted[sorted.len() / 2] * 3 / 2 + 1;
+
+        let silent = self.last_heartbeat.is_some_and(|last| now - last > late);
+        let total = self.gaps.len() + usize::from(silent);
+        let on_time = self.gaps.iter().filter(|&&gap| gap <= late).count();
+        on_time as f64 / total as f64
+    }
+}
+
+impl From<HealthReport> for crate::proto::distbuild::WorkerHealth {
+    fn from(report: HealthReport) -> Self {
+        crate::proto::distbuild::WorkerHealth {
+            score: report.score,
+            failure_rate: report.failure_rate,
+            dispatch_latency_ms: report.dispatch_latency_ms,
+            heartbeat_stability: report.heartbeat_stability,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_reflects_failures_latency_and_heartbeats() {
+        let mut health = HealthTracker::new(HealthConfig::default());
+        assert_eq!(health.report("w1", 0).score, 1.0);
+
+        // Failed 6 of its last 8 jobs; the ones before the window don't count
+        health.record_outcome("w1", true, 0);
+        for at in 1000..1008 {
+            health.record_outcome("w1", at < 1006, at);
+        }
+        let report = health.report("w1", 1010);
+        assert_eq!(report.failure_rate, 6.0 / 10.0);
+        assert!(health.is_unhealthy(report.score));
+
+        // Dispatches slower than slow_dispatch_ms count against it
+        health.record_dispatch("w2", Duration::from_millis(1000));
+        let report = health.report("w2", 0);
+        assert_eq!(report.dispatch_latency_ms, 1000);
+        assert_eq!(report.score, 0.5);
+
+        // One heartbeat of four arrived late, and it's silent again
+        for at in [0, 10, 20, 50, 60] {
+            health.record_heartbeat("w3", at);
+        }
+        assert_eq!(health.report("w3", 65).heartbeat_stability, 0.75);
+        assert_eq!(health.report("w3", 90).heartbeat_stability, 0.6);
+
+        health.forget("w1");
+        assert_eq!(health.report("w1", 1010).score, 1.0);
+    }
+}
@@ -0,0 +1,398 @@
+use crate::common::types::{JobMetadata, JobStatusEnum};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Finished jobs, one JSON object per line, appended as they finish
+const HISTORY_FILE: &str = "history.jsonl";
+/// Most records kept without a state dir
+const MAX_IN_MEMORY: usize = 100_000;
+
+/// How a job ended, kept after the job itself is forgotten
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct HistoryRecord {
+    pub job_id: String,
+    pub job_type: String,
+    #[serde(default)]
+    pub crate_name: Option<String>,
+    pub status: JobStatusEnum,
+    /// Worker it last ran on
+    #[serde(default)]
+    pub worker: Option<String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub session_id: Option<String>,
+    pub input_hash: String,
+    #[serde(default)]
+    pub output_hash: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub submitted_at: i64,
+    pub finished_at: i64,
+    /// Time it spent running on its worker; 0 = didn't run there
+    #[serde(default)]
+    pub exec_millis: u64,
+}
+
+impl HistoryRecord {
+    fn of(job: &JobMetadata, now: i64) -> Self {
+        HistoryRecord {
+            job_id: job.job_id.clone(),
+            job_type: job.job_type.clone(),
+            crate_name: job.metadata.get("crate_name").filter(|name| !name.is_empty()).cloned(),
+            status: job.status,
+            worker: job.assigned_worker.clone(),
+            tenant: job.tenant.clone(),
+            session_id: job.session_id.clone(),
+            input_hash: job.input_hash.clone(),
+            output_hash: job.output_hash.clone(),
+            error: job.error.clone(),
+            submitted_at: job.submitted_at,
+            finished_at: job.completed_at.unwrap_or(now),
+            exec_millis: job.usage.map_or(0, |usage| usage.exec_millis),
+        }
+    }
+}
+
+/// What `JobHistory::search` looks for; unset fields match anything
+#[derive(Debug, Clone)]
+pub(crate) struct HistoryQuery {
+    pub crate_name: Option<String>,
+    /// Any of these
+    pub statuses: Vec<JobStatusEnum>,
+    pub worker: Option<String>,
+    pub tenant: Option<String>,
+    /// Finished at or after
+    pub since: i64,
+    /// Finished before
+    pub until: i64,
+    /// 0 = all
+    pub limit: usize,
+}
+
+impl Default for HistoryQuery {
+    fn default() -> Self {
+        HistoryQuery {
+            crate_name: None,
+            statuses: Vec::new(),
+            worker: None,
+            tenant: None,
+            since: 0,
+            until: i64::MAX,
+            limit: 0,
+        }
+    }
+}
+
+/// Every job that finished in the last `history_retention_secs`, indexed
+/// by crate, worker and final status so searches don't scan the lot. With
+/// a state dir each record is also appended to `history.jsonl` there and
+/// loaded back at startup, so the history outlives restarts and the jobs'
+/// own retention; otherwise the most recent are kept in memory.
+#[derive(Debug, Default)]
+pub(crate) struct JobHistory {
+    /// Oldest first
+    records: VecDeque<HistoryRecord>,
+    /// Sequence number of `records[0]`; the indexes hold sequence numbers
+    first: u64,
+    by_crate: HashMap<String, VecDeque<u64>>,
+    by_worker: HashMap<String, VecDeque<u64>>,
+    by_status: HashMap<JobStatusEnum, VecDeque<u64>>,
+    /// Jobs in `records`, so one finishing again isn't recorded twice
+    recorded: HashSet<String>,
+    /// Appended to as jobs finish, unless following another scheduler's
+    path: Option<PathBuf>,
+    read_only: bool,
+    /// 0 = kept for good
+    retention_secs: i64,
+}
+
+impl JobHistory {
+    pub fn in_memory(retention_secs: u64) -> Self {
+        JobHistory {
+            retention_secs: retention_secs as i64,
+            ..Default::default()
+        }
+    }
+
+    /// The history in state dir `dir`, dropping records past retention
+    /// from the file as well
+    pub fn open(dir: &Path, retention_secs: u64, now: i64) -> Self {
+        let mut history = Self::load(dir, retention_secs, now);
+        history.compact();
+        history
+    }
+
+    /// The history a primary keeps in `dir`, for a mirror or standby to
+    /// search, without writing to it
+    pub fn follow(dir: &Path, retention_secs: u64, now: i64) -> Self {
+        JobHistory {
+            read_only: true,
+            ..Self::load(dir, retention_secs, now)
+        }
+    }
+
+    fn load(dir: &Path, retention_secs: u64, now: i64) -> Self {
+        let path = dir.join(HISTORY_FILE);
+        let mut history = JobHistory {
+            path: Some(path.clone()),
+            ..Self::in_memory(retention_secs)
+        };
+        match fs::read_to_string(&path) {
+            // Skip a line torn by a crash mid-write
+            Ok(content) => {
+                for record in content.lines().filter_map(|line| serde_json::from_str(line).ok()) {
+                    history.push(record);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("⚠️  Failed to read job history {:?}: {}", path, e),
+        }
+        history.prune(now);
+        history
+    }
+
+    /// Note `job` if it just finished; a failure to write it is logged,
+    /// not fatal
+    pub fn record(&mut self, job: &JobMetadata, now: i64) {
+        if !job.status.is_terminal() || self.recorded.contains(&job.job_id) {
+            return;
+        }
+        let record = HistoryRecord::of(job, now);
+        if let Some(path) = self.path.as_ref().filter(|_| !self.read_only) {
+            if let Err(e) = append(path, &record) {
+                warn!("⚠️  Failed to write job history to {:?}: {:#}", path, e);
+            }
+        }
+        self.push(record);
+        self.prune(now);
+    }
+
+    /// Records matching `query`, most recently finished first
+    pub fn search(&self, query: &HistoryQuery) -> Vec<&HistoryRecord> {
+        // Walk the narrowest index the query names
+        let candidates: Vec<u64> = if let Some(crate_name) = &query.crate_name {
+            self.by_crate.get(crate_name).map_or_else(Vec::new, |seqs| seqs.iter().copied().collect())
+        } else if let Some(worker) = &query.worker {
+            self.by_worker.get(worker).map_or_else(Vec::new, |seqs| seqs.iter().copied().collect())
+        } else if !query.statuses.is_empty() {
+            let mut seqs: Vec<u64> = query
+                .statuses
+                .iter()
+                .filter_map(|status| self.by_status.get(status))
+                .flatten()
+                .copied()
+                .collect();
+            seqs.sort_unstable();
+            seqs
+        } else {
+            (self.first..self.first + self.records.len() as u64).collect()
+        };
+
+        let limit = if query.limit > 0 { query.limit } else { usize::MAX };
+        candidates
+            .into_iter()
+            .rev()
+            .filter_map(|seq| self.records.get((seq - self.first) as usize))
+            .filter(|record| {
+                query.crate_name.as_ref().is_none_or(|name| record.crate_name.as_ref() == Some(name))
+                    && query.worker.as_ref().is_none_or(|worker| record.worker.as_ref() == Some(worker))
+                    && (query.statuses.is_empty() || query.statuses.contains(&record.status))
+                    && (query.tenant.is_none() || record.tenant == query.tenant)
+                    && record.finished_at >= query.since
+                    && record.finished_at < query.until
+            })
+            .take(limit)
+            .collect()
+    }
+
+    fn push(&mut self, record: HistoryRecord) {
+        let seq = self.first + self.records.len() as u64;
+        if let Some(crate_name) = &record.crate_name {
+            self.by_crate.entry(crate_name.clone()).or_default().push_back(seq);
+        }
+        if let Some(worker) = &record.worker {
+            self.by_worker.entry(worker.clone()).or_default().push_back(seq);
+        }
+        self.by_status.entry(record.status).or_default().push_back(seq);
+        self.recorded.insert(record.job_id.clone());
+        self.records.push_back(record);
+    }
+
+    /// Drop records past retention (or beyond what's kept in memory), and
+    /// their index entries
+    fn prune(&mut self, now: i64) {
+        let cutoff = if self.retention_secs > 0 { now - self.retention_secs } else { i64::MIN };
+        let cap = if self.path.is_some() { usize::MAX } else { MAX_IN_MEMORY };
+        let mut dropped = false;
+        while let Some(front) = self.records.front() {
+            if front.finished_at >= cutoff && self.records.len() <= cap {
+                break;
+            }
+            let front = self.records.pop_front().unwrap();
+            self.recorded.remove(&front.job_id);
+            self.first += 1;
+            dropped = true;
+        }
+        if !dropped {
+            return;
+        }
+        let first = self.first;
+        let trim = |seqs: &mut VecDeque<u64>| {
+            while seqs.front().is_some_and(|&seq| seq < first) {
+                seqs.pop_front();
+            }
+            !seqs.is_empty()
+        };
+        self.by_crate.retain(|_, seqs| trim(seqs));
+        self.by_worker.retain(|_, seqs| trim(seqs));
+        self.by_status.retain(|_, seqs| trim(seqs));
+    }
+
+    /// Rewrite the file with only the records still kept, if it holds more
+    fn compact(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let lines = match fs::read_to_string(path) {
+            Ok(content) => content.lines().count(),
+            Err(_) => return,
+        };
+        if lines <= self.records.len() {
+            return;
+        }
+        let result = (|| -> Result<()> {
+            let tmp = path.with_extension("jsonl.tmp");
+            let mut data = Vec::new();
+            for record in &self.records {
+                data.extend(serde_json::to_vec(record)?);
+                data.push(b'\n');
+            }
+            fs::write(&tmp, data).with_context(|| format!("Failed to write {:?}", tmp))?;
+            fs::rename(&tmp, path).with_context(|| format!("Failed to replace {:?}", path))
+        })();
+        match result {
+            Ok(()) => info!("🧹 Dropped {} expired job history record(s)", lines - self.records.len()),
+            Err(e) => warn!("⚠️  Failed to compact job history: {:#}", e),
+        }
+    }
+}
+
+fn append(path: &Path, record: &HistoryRecord) -> Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(&line))
+        .with_context(|| format!("Failed to append to {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn job(id: &str, crate_name: &str, status: JobStatusEnum, worker: &str, completed_at: i64) -> JobMetadata {
+        JobMetadata {
+            job_id: id.to_string(),
+            input_hash: String::new(),
+            output_hash: None,
+            error: None,
+            error_kind: Default::default(),
+            job_type: "rust-compile".to_string(),
+            status,
+            assigned_worker: Some(worker.to_string()),
+            submitted_at: completed_at - 10,
+            started_at: None,
+            completed_at: Some(completed_at),
+            metadata: HashMap::from([("crate_name".to_string(), crate_name.to_string())]),
+            preemptions: 0,
+            worker_platform: None,
+            priority: 0,
+            timeline: Vec::new(),
+            progress: None,
+            attempts: 1,
+            attempt_history: Vec::new(),
+            retry_at: None,
+            run_after: None,
+            depends_on: Vec::new(),
+            constraints: HashMap::new(),
+            timeout_secs: None,
+            attached_to: None,
+            usage: None,
+            tenant: None,
+            session_id: None,
+            log_hash: None,
+            weight: 1,
+            pool: None,
+            boosted: false,
+            gang: None,
+        }
+    }
+
+    fn ids(records: Vec<&HistoryRecord>) -> Vec<&str> {
+        records.into_iter().map(|record| record.job_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_search_by_crate_status_worker_and_time() {
+        let mut history = JobHistory::in_memory(0);
+        history.record(&job("s1", "serde", JobStatusEnum::Completed, "w1", 100), 100);
+        history.record(&job("t1", "tokio", JobStatusEnum::Failed, "w1", 110), 110);
+        history.record(&job("s2", "serde", JobStatusEnum::Failed, "w2", 120), 120);
+        history.record(&job("s3", "serde", JobStatusEnum::Completed, "w1", 130), 130);
+        // Still running, then journaled again once finished
+        history.record(&job("t2", "tokio", JobStatusEnum::Running, "w2", 140), 140);
+        history.record(&job("s3", "serde", JobStatusEnum::Completed, "w1", 130), 150);
+
+        let search = |query: HistoryQuery| ids(history.search(&query));
+        let serde = || HistoryQuery {
+            crate_name: Some("serde".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(serde()), ["s3", "s2", "s1"]);
+        assert_eq!(search(HistoryQuery { since: 110, until: 130, ..serde() }), ["s2"]);
+        assert_eq!(search(HistoryQuery { limit: 1, ..serde() }), ["s3"]);
+        let failed_on_w1 = HistoryQuery {
+            worker: Some("w1".to_string()),
+            statuses: vec![JobStatusEnum::Failed],
+            ..Default::default()
+        };
+        assert_eq!(search(failed_on_w1), ["t1"]);
+        let failed = HistoryQuery {
+            statuses: vec![JobStatusEnum::Failed, JobStatusEnum::Cancelled],
+            ..Default::default()
+        };
+        assert_eq!(search(failed), ["s2", "t1"]);
+        assert_eq!(search(HistoryQuery::default()).len(), 4);
+    }
+
+    #[test]
+    fn test_history_survives_restart_until_expired() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut history = JobHistory::open(temp_dir.path(), 100, 0);
+        history.record(&job("old", "serde", JobStatusEnum::Completed, "w1", 10), 10);
+        history.record(&job("new", "serde", JobStatusEnum::Failed, "w2", 90), 90);
+
+        let reopened = JobHistory::open(temp_dir.path(), 100, 50);
+        assert_eq!(ids(reopened.search(&HistoryQuery::default())), ["new", "old"]);
+
+        // Past retention, "old" is gone from memory and the file alike
+        let history = JobHistory::open(temp_dir.path(), 100, 150);
+        assert_eq!(ids(history.search(&HistoryQuery::default())), ["new"]);
+        let worker = HistoryQuery {
+            worker: Some("w1".to_string()),
+            ..Default::default()
+        };
+        assert!(history.search(&worker).is_empty());
+        let content = fs::read_to_string(temp_dir.path().join(HISTORY_FILE)).unwrap();
+        assert_eq!(content.lines().count(), 1);
+    }
+}
@@ -0,0 +1,218 @@
+use crate::common::types::{JobMetadata, WorkerMetadata};
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const JOURNAL_FILE: &str = "journal.jsonl";
+
+/// Once the journal outgrows this it's rotated to `journal.1.jsonl`
+/// (replacing the previous one) after the next save to the state store
+const JOURNAL_ROTATE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// A job or worker state transition, carrying the full record after it
+/// so replaying the journal reproduces the exact state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event {
+    Job { job: Box<JobMetadata> },
+    WorkerRegistered { worker: Box<WorkerMetadata> },
+    WorkerRemoved { worker_id: String, reason: String },
+    /// A finished job forgotten under the retention policy
+    JobRemoved { job_id: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    pub seq: u64,
+    pub at: i64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Jobs and workers changed by journaled events, so only their records
+/// need saving to the state store
+#[derive(Debug, Default)]
+pub(crate) struct Changes {
+    pub jobs: HashSet<String>,
+    pub workers: HashSet<String>,
+}
+
+impl Changes {
+    pub fn note(&mut self, event: &Event) {
+        match event {
+            Event::Job { job } => self.jobs.insert(job.job_id.clone()),
+            Event::JobRemoved { job_id } => self.jobs.insert(job_id.clone()),
+            Event::WorkerRegistered { worker } => self.workers.insert(worker.worker_id.clone()),
+            Event::WorkerRemoved { worker_id, .. } => self.workers.insert(worker_id.clone()),
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty() && self.workers.is_empty()
+    }
+
+    fn extend(&mut self, other: Changes) {
+        self.jobs.extend(other.jobs);
+        self.workers.extend(other.workers);
+    }
+}
+
+/// Append-only log of scheduler events, one JSON object per line
+#[derive(Debug)]
+pub(crate) struct Journal {
+    path: PathBuf,
+    file: fs::File,
+    seq: u64,
+    unsaved: Changes, // since the state store last saved
+}
+
+impl Journal {
+    /// Open the journal in `dir` for appending, continuing after `last_seq`.
+    /// A torn last line (from a crash mid-write) is cut off first, or the
+    /// next entry would be glued onto it and lost along with it.
+    pub fn open(dir: &Path, last_seq: u64) -> Result<Self> {
+        let path = dir.join(JOURNAL_FILE);
+        let mut file = fs::File::options()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open journal {:?}", path))?;
+        truncate_torn_tail(&mut file).with_context(|| format!("Failed to repair journal {:?}", path))?;
+        Ok(Journal {
+            path,
+            file,
+            seq: last_seq,
+            unsaved: Changes::default(),
+        })
+    }
+
+    /// Sequence number of the latest entry
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Records changed since the last `take_unsaved`
+    pub fn take_unsaved(&mut self) -> Changes {
+        std::mem::take(&mut self.unsaved)
+    }
+
+    /// Mark `changes` as not saved yet (again, if saving them failed)
+    pub fn keep_unsaved(&mut self, changes: Changes) {
+        self.unsaved.extend(changes);
+    }
+
+    /// Append `event`; failures are logged, never fatal to the scheduler
+    pub fn record(&mut self, at: i64, event: Event) {
+        self.seq += 1;
+        self.unsaved.note(&event);
+        let entry = Entry {
+            seq: self.seq,
+            at,
+            event,
+        };
+        let written = serde_json::to_vec(&entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                self.file.write_all(&line).map_err(Into::into)
+            });
+        if let Err(e) = written {
+            warn!("⚠️  Failed to write scheduler journal: {}", e);
+        }
+    }
+
+    /// Start a new journal file if this one is large; every entry in it
+    /// must already be saved to the state store
+    pub fn rotate_if_large(&mut self) -> Result<()> {
+        if self.file.metadata()?.len() < JOURNAL_ROTATE_BYTES {
+            return Ok(());
+        }
+        let rotated = self.path.with_extension("1.jsonl");
+        fs::rename(&self.path, &rotated)
+            .with_context(|| format!("Failed to rotate journal to {:?}", rotated))?;
+        let unsaved = self.take_unsaved();
+        *self = Journal::open(self.path.parent().unwrap_or(Path::new(".")), self.seq)?;
+        self.unsaved = unsaved;
+        Ok(())
+    }
+}
+
+/// Cut `file` back to just after its last newline
+fn truncate_torn_tail(file: &mut fs::File) -> Result<()> {
+    let len = file.metadata()?.len();
+    let mut end = len;
+    let mut block = [0u8; 4096];
+    while end > 0 {
+        let start = end.saturating_sub(block.len() as u64);
+        let block = &mut block[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(block)?;
+        if let Some(i) = block.iter().rposition(|&b| b == b'\n') {
+            end = start + i as u64 + 1;
+            break;
+        }
+        end = start;
+    }
+    if end < len {
+        warn!("⚠️  Dropping a torn {}-byte record at the end of the journal", len - end);
+        file.set_len(end)?;
+    }
+    Ok(())
+}
+
+/// Complete entries appended to the journal in `dir` from byte `offset`
+/// on, with the offset to continue from. A line still being written is
+/// left for the next call. `None` if the journal is now shorter than
+/// `offset`, i.e. it was rotated.
+pub(crate) fn read_from(dir: &Path, offset: u64) -> Result<Option<(Vec<Entry>, u64)>> {
+    let path = dir.join(JOURNAL_FILE);
+    let mut file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read journal {:?}", path)),
+    };
+    if file.metadata()?.len() < offset {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)
+        .with_context(|| format!("Failed to read journal {:?}", path))?;
+
+    let complete = data.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let mut entries = Vec::new();
+    for line in data[..complete].split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+        match serde_json::from_slice::<Entry>(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warn!("⚠️  Skipping unreadable journal line in {:?}: {}", path, e),
+        }
+    }
+    Ok(Some((entries, offset + complete as u64)))
+}
+
+/// Entries in the journal in `dir` after `after_seq`, oldest first. A torn
+/// last line (from a crash mid-write) is skipped.
+pub(crate) fn read_after(dir: &Path, after_seq: u64) -> Result<Vec<Entry>> {
+    let path = dir.join(JOURNAL_FILE);
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read journal {:?}", path)),
+    };
+
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read journal {:?}", path))?;
+        match serde_json::from_str::<Entry>(&line) {
+            Ok(entry) if entry.seq > after_seq => entries.push(entry),
+            Ok(_) => {}
+            Err(e) => warn!("⚠️  Skipping unreadable journal line {} in {:?}: {}", number + 1, path, e),
+        }
+    }
+    Ok(entries)
+}
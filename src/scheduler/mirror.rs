@@ -0,0 +1,146 @@
+use super::journal;
+use super::store::StateStore;
+use super::SchedulerState;
+use anyhow::Result;
+use log::info;
+use std::path::{Path, PathBuf};
+
+/// Keeps a copy of a primary scheduler's state current by following its
+/// state dir: its saved state once, then the journal as it grows
+#[derive(Debug)]
+pub(crate) struct Mirror {
+    dir: PathBuf,
+    store: StateStore,
+    offset: u64, // bytes of the journal consumed
+    seq: u64,    // last entry applied
+}
+
+impl Mirror {
+    /// Start following the primary whose state dir is `dir`
+    pub fn open(dir: &Path) -> Result<(Self, SchedulerState)> {
+        let mut mirror = Mirror {
+            dir: dir.to_path_buf(),
+            store: StateStore::open(dir)?,
+            offset: 0,
+            seq: 0,
+        };
+        let state = mirror.resync()?;
+        Ok((mirror, state))
+    }
+
+    /// Apply whatever the primary journaled since the last call
+    pub fn poll(&mut self, state: &mut SchedulerState) -> Result<()> {
+        let entries = match journal::read_from(&self.dir, self.offset)? {
+            Some((entries, offset)) => {
+                self.offset = offset;
+                entries
+            }
+            // Rotated: the new journal continues where the old one stopped
+            None => match journal::read_from(&self.dir, 0)? {
+                Some((entries, offset)) => {
+                    self.offset = offset;
+                    entries
+                }
+                None => return Ok(()),
+            },
+        };
+
+        let applied = self.seq;
+        let mut entries = entries.into_iter().filter(|entry| entry.seq > applied).peekable();
+        if entries.peek().is_some_and(|entry| entry.seq != applied + 1) {
+            // Missed entries (rotated twice between polls); start over
+            info!("🪞 Lost track of the primary's journal; reloading its state");
+            let liveness = state.liveness;
+            let history = std::mem::take(&mut state.history);
+            *state = self.resync()?;
+            state.liveness = liveness;
+            state.history = history;
+            return Ok(());
+        }
+        for entry in entries {
+            self.seq = entry.seq;
+            state.apply(entry.event);
+        }
+        Ok(())
+    }
+
+    /// The primary's saved state and the whole current journal
+    fn resync(&mut self) -> Result<SchedulerState> {
+        let (mut state, seq) = self.store.read_snapshot()?;
+        self.seq = seq;
+        self.offset = 0;
+        self.poll(&mut state)?;
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::types::{JobErrorKindEnum, JobMetadata, JobStatusEnum};
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn job(id: &str, status: JobStatusEnum) -> JobMetadata {
+        JobMetadata {
+            job_id: id.to_string(),
+            input_hash: format!("{}-input", id),
+            output_hash: None,
+            error: None,
+            error_kind: JobErrorKindEnum::Unspecified,
+            job_type: "rust-compile".to_string(),
+            status,
+            assigned_worker: None,
+            submitted_at: 100,
+            started_at: None,
+            completed_at: None,
+            metadata: HashMap::new(),
+            preemptions: 0,
+            worker_platform: None,
+            priority: 0,
+            timeline: Vec::new(),
+            progress: None,
+            attempts: 0,
+            attempt_history: Vec::new(),
+            retry_at: None,
+            run_after: None,
+            depends_on: Vec::new(),
+            constraints: HashMap::new(),
+            timeout_secs: None,
+            attached_to: None,
+            usage: None,
+            tenant: None,
+            session_id: None,
+            log_hash: None,
+            weight: 1,
+            pool: None,
+            boosted: false,
+            gang: None,
+        }
+    }
+
+    #[test]
+    fn test_mirror_follows_primary() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut store = StateStore::open(temp_dir.path()).unwrap();
+        let mut primary = store.load(0).unwrap();
+        primary.jobs.insert("a".to_string(), job("a", JobStatusEnum::Pending));
+        primary.journal_job("a");
+        assert!(store.save(&mut primary).unwrap());
+        primary.jobs.insert("b".to_string(), job("b", JobStatusEnum::Pending));
+        primary.journal_job("b");
+
+        let (mut mirror, mut state) = Mirror::open(temp_dir.path()).unwrap();
+        assert_eq!(state.jobs.len(), 2);
+
+        primary.jobs.get_mut("a").unwrap().set_status(JobStatusEnum::Running, 5);
+        primary.journal_job("a");
+        mirror.poll(&mut state).unwrap();
+        assert_eq!(state.jobs["a"].status, JobStatusEnum::Running);
+        assert!(state.blob_refs.contains_key("b-input"));
+
+        // Nothing new: nothing changes
+        mirror.poll(&mut state).unwrap();
+        assert_eq!(state.jobs.len(), 2);
+    }
+}
@@ -1,408 +1,3902 @@
-use crate::common::types::{JobMetadata, JobStatusEnum, WorkerMetadata};
+use crate::cas::service::BlobStoreService;
+use crate::cas::{is_valid_hash, Cas};
+use crate::common::auth::{self, Authenticator, Caller, ClientAuth};
+use crate::common::clock;
+use crate::common::platform;
+use crate::common::config::{OfflineAction, QuarantineConfig, RetryClass, RetryConfig, SchedulerConfig, StandbyConfig, WeightConfig};
+use crate::common::types::{JobErrorKindEnum, JobMetadata, JobProgress, JobStatusEnum, WorkerMetadata, WorkerStateEnum};
+use crate::common::version::BuildVersion;
+use crate::common::error::Rejection;
+use crate::common::DistbuildError;
 use crate::proto::distbuild::*;
 use crate::proto::distbuild::scheduler_server::{Scheduler, SchedulerServer};
-use anyhow::Result;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use anyhow::{Context, Result};
+use log::{debug, error, info, warn};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use audit::{AuditLog, AuditRecord};
+use channels::WorkerChannels;
+use eta::{DurationHistory, Eta};
+use health::{HealthReport, HealthTracker};
+use history::{HistoryQuery, JobHistory};
+use journal::{Event, Journal};
+use mirror::Mirror;
+use queue::JobIndex;
+use store::StateStore;
+use strategy::{Candidate, SchedulingStrategy};
+use webhooks::Webhooks;
+use futures::Stream;
+use std::pin::Pin;
+use tokio::sync::{broadcast, Notify, RwLock};
+use tonic::service::interceptor::InterceptedService;
 use tonic::{transport::Server, Request, Response, Status};
 
+mod audit;
+mod channels;
+mod dashboard;
+mod eta;
+mod health;
+mod history;
+mod journal;
+mod mirror;
+mod queue;
+mod simulate;
+mod store;
+mod strategy;
+mod webhooks;
+
+pub use simulate::Trace;
+
+/// Max client error reports accepted per client per minute
+const CLIENT_ERROR_RATE_LIMIT: u32 = 10;
+/// Max distinct client errors kept in memory
+const MAX_CLIENT_ERRORS: usize = 1000;
+/// Default window and target utilization for scaling advice
+const DEFAULT_SCALING_WINDOW_SECS: u64 = 300;
+const DEFAULT_TARGET_UTILIZATION: f64 = 0.8;
+/// A running job is stalled after this long without a progress report
+/// (workers send one every 30s)
+const PROGRESS_STALL_SECS: i64 = 90;
+/// How often changed state is written to the state dir
+const STATE_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// How often queued jobs are matched against free workers, besides
+/// submissions, registrations and freed slots triggering a pass
+const ASSIGN_INTERVAL: Duration = Duration::from_secs(1);
+/// How often dispatched jobs are checked against their deadline
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
+/// How often finished jobs are checked against the retention policy
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// How often a mirror checks the primary's journal for new entries
+const MIRROR_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often a standby pings the primary, and how long it waits for an
+/// answer
+const STANDBY_PING_INTERVAL: Duration = Duration::from_secs(2);
+/// Job changes buffered per watcher before it has to catch up from the
+/// job's current record
+const JOB_UPDATE_BUFFER: usize = 256;
+/// Cluster events buffered per subscriber; one that falls further behind
+/// misses some
+const EVENT_BUFFER: usize = 1024;
+/// How long clients are told to wait when the queue is full
+const QUEUE_FULL_RETRY_SECS: u64 = 5;
+/// How long each worker gets to acknowledge that the scheduler is stopping
+const STOP_NOTICE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone)]
 pub struct SchedulerService {
+    config: Arc<SchedulerConfig>,
     state: Arc<RwLock<SchedulerState>>,
+    cas: Option<Arc<Cas>>, // served to CAS replication peers when set
+    store: Arc<OnceLock<Mutex<StateStore>>>, // persists `state` when set
+    mirror: Arc<Mutex<Option<Mirror>>>, // read-only copy of another scheduler (until taking over) when set
+    quiesced: Arc<AtomicBool>, // no admissions or dispatches while set
+    shutdown: Arc<Notify>,
+    strategy: Arc<Mutex<Box<dyn SchedulingStrategy>>>,
+    auth: ClientAuth, // to connect to other schedulers with
+    workers: WorkerChannels, // open connections to workers
 }
 
 #[derive(Default)]
 struct SchedulerState {
     workers: HashMap<String, WorkerMetadata>,
     jobs: HashMap<String, JobMetadata>,
-    next_worker_index: usize, // For round-robin scheduling
+    index: JobIndex, // over `jobs`, refreshed by `journal_job`
+    liveness: Liveness,
+    failures: HashMap<String, FailureRecord>, // worker_id -> its recent job failures
+    health: HealthTracker, // scores workers for placement, from `[scheduler.health]`
+    dispatch_failures: HashMap<String, Vec<String>>, // job_id -> workers it couldn't be dispatched to
+    durations: DurationHistory, // how long past runs took, for ETAs; saved in the state store
+    weights: WeightConfig, // sizes up new jobs, from `[scheduler.weights]`
+    webhooks: Webhooks, // from `[[scheduler.webhooks]]`, told of every job change by `journal_job`
+    history: JobHistory, // every finished job, searchable after the job is forgotten
+    client_errors: HashMap<(String, String), ClientErrorRecord>, // keyed by (kind, message)
+    client_error_windows: HashMap<String, (i64, u32)>, // client_id -> (window start, count)
+    blob_refs: HashMap<String, HashMap<String, &'static str>>, // hash -> job_id -> role
+    journal: Option<Journal>, // records every job/worker change when persisting
+    audit: AuditLog, // who did what, on disk alongside the journal when persisting
+    job_updates: Option<broadcast::Sender<GetJobStatusResponse>>, // set once someone watches a job
+    events: Option<broadcast::Sender<ClusterEvent>>, // set once someone subscribes to events
 }
 
-impl SchedulerService {
-    pub fn new() -> Self {
-        SchedulerService {
-            state: Arc::new(RwLock::new(SchedulerState::default())),
+/// When a silent worker is offline and what's done about it, from
+/// `[scheduler]`; the one place heartbeat ages are judged
+#[derive(Debug, Clone, Copy)]
+struct Liveness {
+    /// Without a heartbeat for longer, a worker gets no new jobs
+    timeout_secs: i64,
+    /// Further seconds before `action` is taken
+    grace_secs: i64,
+    action: OfflineAction,
+    /// Seconds offline before `OfflineAction::Remove` forgets a worker
+    retention_secs: i64,
+}
+
+impl Liveness {
+    fn from_config(config: &SchedulerConfig) -> Self {
+        Liveness {
+            timeout_secs: config.worker_timeout_secs as i64,
+            grace_secs: config.offline_grace_secs as i64,
+            action: config.offline_action,
+            retention_secs: config.offline_retention_secs as i64,
         }
     }
 
-    pub async fn run(self, addr: String) -> Result<()> {
-        let addr = addr.parse()?;
-        println!("🚀 Scheduler listening on {}", addr);
+    fn is_online(&self, worker: &WorkerMetadata, now: i64) -> bool {
+        now - worker.last_heartbeat <= self.timeout_secs
+    }
 
-        Server::builder()
-            .add_service(SchedulerServer::new(self))
-            .serve(addr)
-            .await?;
+    /// Offline past the grace period, so `action` is due
+    fn is_overdue(&self, worker: &WorkerMetadata, now: i64) -> bool {
+        now - worker.last_heartbeat > self.timeout_secs + self.grace_secs
+    }
 
-        Ok(())
+    /// The state `worker` should be in as of `now`, and since when (`now`
+    /// if it only just got there)
+    fn state_of(&self, worker: &WorkerMetadata, now: i64) -> (WorkerStateEnum, i64) {
+        let silent_at = worker.last_heartbeat + self.timeout_secs;
+        if self.is_overdue(worker, now) {
+            (WorkerStateEnum::Offline, silent_at + self.grace_secs)
+        } else if !self.is_online(worker, now) {
+            (WorkerStateEnum::Suspect, silent_at)
+        } else if worker.draining {
+            (WorkerStateEnum::Draining, now)
+        } else {
+            (WorkerStateEnum::Online, now)
+        }
     }
+}
 
-    async fn assign_jobs_to_workers(&self) {
-        let now = chrono::Utc::now().timestamp();
-        let mut state = self.state.write().await;
-        
-        // Mark workers as offline if heartbeat is too old (10 seconds)
-        let offline_workers: Vec<String> = state
-            .workers
-            .iter()
-            .filter(|(_, worker)| now - worker.last_heartbeat > 10)
-            .map(|(id, _)| id.clone())
-            .collect();
-        
-        for worker_id in offline_workers {
-            state.workers.remove(&worker_id);
-            println!("⚠️  Worker {} marked offline (no heartbeat)", worker_id);
+impl Default for Liveness {
+    fn default() -> Self {
+        Liveness::from_config(&SchedulerConfig::default())
+    }
+}
+
+/// What a request may see and change, by the tenant its token acts as:
+/// everything for operators, otherwise the tenant's own jobs and workers,
+/// plus a look at shared workers. Others' jobs are as good as missing.
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    tenant: Option<String>,
+    /// Who's asking, as the audit trail names them: the token's identity,
+    /// else the peer's address
+    actor: String,
+}
+
+impl Scope {
+    fn of<T>(request: &Request<T>) -> Self {
+        let caller = Caller::of(request);
+        let peer = || request.remote_addr().map_or_else(|| "unknown".to_string(), |addr| addr.ip().to_string());
+        Scope {
+            actor: caller.identity.unwrap_or_else(peer),
+            tenant: caller.tenant,
         }
-        
-        // Find pending jobs
-        let pending_jobs: Vec<(String, String, String, String)> = state
-            .jobs
-            .iter()
-            .filter(|(_, job)| job.status == JobStatusEnum::Pending)
-            .map(|(id, job)| (id.clone(), job.input_hash.clone(), job.job_type.clone(), job.metadata.clone().into_iter().collect::<Vec<_>>().into_iter().map(|(k,v)| format!("{}={}", k, v)).collect::<Vec<_>>().join(",")))
-            .collect();
+    }
 
-        // Find available workers (healthy and with capacity)
-        let available_workers: Vec<(String, String)> = state
-            .workers
-            .iter()
-            .filter(|(_, worker)| worker.active_jobs < worker.capacity && now - worker.last_heartbeat < 10)
-            .map(|(id, worker)| (id.clone(), worker.address.clone()))
-            .collect();
+    fn sees_job(&self, job: &JobMetadata) -> bool {
+        self.tenant.is_none() || job.tenant == self.tenant
+    }
 
-        if pending_jobs.is_empty() || available_workers.is_empty() {
-            return;
+    fn sees_worker(&self, worker: &WorkerMetadata) -> bool {
+        worker.tenant.is_none() || self.owns_worker(worker)
+    }
+
+    fn owns_worker(&self, worker: &WorkerMetadata) -> bool {
+        self.tenant.is_none() || worker.tenant == self.tenant
+    }
+
+    /// Events about its jobs and workers, and shared workers
+    fn sees_event(&self, event: &ClusterEvent) -> bool {
+        match &self.tenant {
+            Some(tenant) => event.tenant == *tenant || (event.tenant.is_empty() && event.job_id.is_empty()),
+            None => true,
         }
+    }
 
-        // Collect assignments to make outside the lock
-        // Use round-robin scheduling for better load distribution
-        let mut assignments = Vec::new();
-        
-        let num_workers = available_workers.len();
-        if num_workers == 0 {
-            return;
+    /// Tenant a job or worker the caller submits belongs to, given the
+    /// `requested` one: a tenant can only ask for itself
+    #[allow(clippy::result_large_err)]
+    fn tenant_for(&self, requested: &str) -> Result<Option<String>, Status> {
+        match &self.tenant {
+            Some(tenant) if requested.is_empty() || requested == tenant => Ok(Some(tenant.clone())),
+            Some(tenant) => Err(Status::permission_denied(format!("Tenant {} can't act for tenant {}", tenant, requested))),
+            None => Ok(Some(requested.to_string()).filter(|t| !t.is_empty())),
         }
-        
-        for (idx, (job_id, input_hash, job_type, _metadata)) in pending_jobs.iter().enumerate() {
-            // Round-robin: pick worker based on counter, not always first!
-            let worker_idx = (state.next_worker_index + idx) % num_workers;
-            let (worker_id, worker_addr) = &available_workers[worker_idx];
-            
-            if let Some(job) = state.jobs.get_mut(job_id) {
-                job.status = JobStatusEnum::Assigned;
-                job.assigned_worker = Some(worker_id.clone());
-                
-                assignments.push((
-                    job_id.clone(),
-                    input_hash.clone(),
-                    job_type.clone(),
-                    worker_id.clone(),
-                    worker_addr.clone(),
-                ));
-            }
-            if let Some(worker) = state.workers.get_mut(worker_id) {
-                worker.active_jobs += 1;
-            }
+    }
+
+    /// Refuse cluster-wide operations to tenants
+    #[allow(clippy::result_large_err)]
+    fn require_operator(&self, what: &str) -> Result<(), Status> {
+        match &self.tenant {
+            Some(tenant) => Err(Status::permission_denied(format!("Tenant {} can't {}", tenant, what))),
+            None => Ok(()),
         }
-        
-        // Update the round-robin counter for next time
-        state.next_worker_index = (state.next_worker_index + pending_jobs.len()) % num_workers;
-        
-        // Drop lock before async operations
-        drop(state);
-        
-        // Execute jobs on workers
-        for (job_id, input_hash, job_type, worker_id, worker_addr) in assignments {
-            let self_clone = SchedulerService {
-                state: self.state.clone(),
-            };
-            
-            tokio::spawn(async move {
-                if let Err(e) = self_clone.dispatch_job_to_worker(
-                    &job_id,
-                    &input_hash,
-                    &job_type,
-                    &worker_id,
-                    &worker_addr,
-                ).await {
-                    eprintln!("❌ Failed to dispatch job {} to {}: {}", job_id, worker_id, e);
-                    
-                    // Mark job as failed
-                    let mut state = self_clone.state.write().await;
-                    if let Some(job) = state.jobs.get_mut(&job_id) {
-                        job.status = JobStatusEnum::Failed;
-                        job.completed_at = Some(chrono::Utc::now().timestamp());
-                    }
-                    if let Some(worker) = state.workers.get_mut(&worker_id) {
-                        worker.active_jobs = worker.active_jobs.saturating_sub(1);
-                    }
-                }
-            });
+    }
+}
+
+/// Job failures held against a worker (see `record_outcome`)
+#[derive(Default)]
+struct FailureRecord {
+    /// Since its last success
+    consecutive: u32,
+    /// When recent ones happened, oldest first
+    recent: VecDeque<i64>,
+    /// Quarantines since it was last fully back in rotation
+    strikes: u32,
+}
+
+/// Where a job's dependencies stand
+enum Readiness {
+    /// All completed
+    Ready,
+    /// Some still queued or running
+    Waiting,
+    /// This one failed or was cancelled
+    Failed(String),
+}
+
+/// Work a submission can take over instead of running
+enum Reuse {
+    /// An earlier completed job (see `cached_result`) and its output
+    Cached { job_id: String, output_hash: String },
+    /// An identical job still queued or running (see `in_flight`)
+    InFlight(String),
+}
+
+/// A deduplicated error reported by one or more wrappers
+struct ClientErrorRecord {
+    count: u32,
+    clients: HashSet<String>,
+    first_seen: i64,
+    last_seen: i64,
+}
+
+impl SchedulerState {
+    /// Journal the current record of `job_id` (after changing it)
+    fn journal_job(&mut self, job_id: &str) {
+        self.index.update(job_id, self.jobs.get(job_id));
+        if self.jobs.get(job_id).is_none_or(|job| job.status.is_terminal()) {
+            self.dispatch_failures.remove(job_id);
+        }
+        if let (Some(journal), Some(job)) = (self.journal.as_mut(), self.jobs.get(job_id)) {
+            journal.record(clock::now(), Event::Job { job: Box::new(job.clone()) });
+        }
+        self.announce_job(job_id);
+        if let Some(job) = self.jobs.get(job_id) {
+            self.webhooks.job_changed(job, clock::now());
+            self.history.record(job, clock::now());
         }
     }
-    
-    async fn dispatch_job_to_worker(
-        &self,
-        job_id: &str,
-        input_hash: &str,
-        job_type: &str,
-        worker_id: &str,
-        worker_addr: &str,
-    ) -> Result<()> {
-        use crate::proto::distbuild::worker_client::WorkerClient;
-        
-        println!("📤 Dispatching job {} to worker {} at {}", job_id, worker_id, worker_addr);
-        
-        // Update job status to RUNNING
-        {
-            let mut state = self.state.write().await;
-            if let Some(job) = state.jobs.get_mut(job_id) {
-                job.status = JobStatusEnum::Running;
-            }
+
+    /// Count how long `job_id` took towards the ETAs of later runs
+    fn record_duration(&mut self, job_id: &str) {
+        if let Some(job) = self.jobs.get(job_id) {
+            self.durations.record(job);
         }
-        
-        // Connect to worker and execute job
-        let worker_url = format!("http://{}", worker_addr);
-        let mut client = WorkerClient::connect(worker_url).await?;
-        
-        let request = ExecuteJobRequest {
-            job_id: job_id.to_string(),
-            input_hash: input_hash.to_string(),
-            job_type: job_type.to_string(),
-            metadata: std::collections::HashMap::new(),
-        };
-        
-        let _response = client.execute_job(request).await?;
-        
-        Ok(())
     }
-}
 
-#[tonic::async_trait]
-impl Scheduler for SchedulerService {
-    async fn register_worker(
-        &self,
-        request: Request<RegisterWorkerRequest>,
-    ) -> Result<Response<RegisterWorkerResponse>, Status> {
-        let req = request.into_inner();
-        let worker_id = req.worker_id.clone();
+    /// Send webhooks for the sessions idle long enough whose jobs have all
+    /// finished; a session with one still in the queue is sent once that
+    /// one finishes
+    fn announce_finished_sessions(&mut self, now: i64) {
+        let due = self.webhooks.due_sessions(now);
+        if due.is_empty() {
+            return;
+        }
+        let mut sessions = self.sessions(&Scope::default());
+        for session_id in due {
+            let Some(jobs) = sessions.remove(session_id.as_str()) else {
+                continue;
+            };
+            if jobs.iter().all(|job| job.status.is_terminal()) {
+                self.webhooks.session_finished(&session_info(&session_id, &jobs), now);
+            }
+        }
+    }
 
-        let worker = WorkerMetadata {
-            worker_id: worker_id.clone(),
-            address: req.address,
-            capacity: req.capacity,
-            active_jobs: 0,
-            last_heartbeat: chrono::Utc::now().timestamp(),
-            labels: req.labels,
+    /// Tell anyone watching jobs where `job_id` stands now, and event
+    /// subscribers if it just finished
+    fn announce_job(&self, job_id: &str) {
+        let Some(job) = self.jobs.get(job_id) else {
+            return;
         };
+        if let Some(updates) = self.job_updates.as_ref().filter(|updates| updates.receiver_count() > 0) {
+            let _ = updates.send(status_response(job, self.durations.eta(job, clock::now())));
+        }
+        let (kind, detail) = match job.status {
+            JobStatusEnum::Completed => (ClusterEventKind::JobCompleted, job.output_hash.clone()),
+            JobStatusEnum::Failed => (ClusterEventKind::JobFailed, job.error.clone()),
+            JobStatusEnum::Cancelled => (ClusterEventKind::JobCancelled, None),
+            _ => return,
+        };
+        let worker_id = job.assigned_worker.as_deref().unwrap_or_default();
+        self.emit(kind, job.tenant.as_deref(), worker_id, job_id, &detail.unwrap_or_default());
+    }
 
-        let mut state = self.state.write().await;
-        state.workers.insert(worker_id.clone(), worker);
+    /// Tell event subscribers that a job was submitted
+    fn announce_submitted(&self, job: &JobMetadata) {
+        let detail = match job.metadata.get("crate_name").filter(|name| !name.is_empty()) {
+            Some(crate_name) => format!("{} ({})", job.job_type, crate_name),
+            None => job.job_type.clone(),
+        };
+        self.emit(ClusterEventKind::JobSubmitted, job.tenant.as_deref(), "", &job.job_id, &detail);
+    }
 
-        println!("✅ Worker registered: {}", worker_id);
+    /// Send an event about a job or worker of `tenant` to everyone
+    /// subscribed to them
+    fn emit(&self, kind: ClusterEventKind, tenant: Option<&str>, worker_id: &str, job_id: &str, detail: &str) {
+        if let Some(events) = self.events.as_ref().filter(|events| events.receiver_count() > 0) {
+            let _ = events.send(ClusterEvent {
+                at: clock::now(),
+                kind: kind.into(),
+                worker_id: worker_id.to_string(),
+                job_id: job_id.to_string(),
+                detail: detail.to_string(),
+                tenant: tenant.unwrap_or_default().to_string(),
+            });
+        }
+    }
 
-        Ok(Response::new(RegisterWorkerResponse {
-            success: true,
-            message: format!("Worker {} registered successfully", worker_id),
-        }))
+    /// Cluster events from now on, as sent by `emit`
+    fn subscribe_events(&mut self) -> broadcast::Receiver<ClusterEvent> {
+        self.events
+            .get_or_insert_with(|| broadcast::channel(EVENT_BUFFER).0)
+            .subscribe()
     }
 
-    async fn heartbeat(
-        &self,
-        request: Request<HeartbeatRequest>,
-    ) -> Result<Response<HeartbeatResponse>, Status> {
-        let req = request.into_inner();
-        let worker_id = req.worker_id.clone();
+    /// Changes to any job from now on, as announced by `announce_job`
+    fn watch_jobs(&mut self) -> broadcast::Receiver<GetJobStatusResponse> {
+        self.job_updates
+            .get_or_insert_with(|| broadcast::channel(JOB_UPDATE_BUFFER).0)
+            .subscribe()
+    }
 
-        let mut state = self.state.write().await;
-        
-        if let Some(worker) = state.workers.get_mut(&worker_id) {
-            worker.last_heartbeat = chrono::Utc::now().timestamp();
-            worker.active_jobs = req.active_jobs;
-        } else {
-            return Err(Status::not_found(format!("Worker {} not found", worker_id)));
+    fn journal(&mut self, event: Event) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.record(clock::now(), event);
         }
+    }
 
-        Ok(Response::new(HeartbeatResponse {
-            success: true,
-            jobs_to_execute: vec![], // No longer used - scheduler calls ExecuteJob directly
-        }))
+    /// Drop a worker, queueing the jobs it was dispatched or running
+    /// again. Returns how many were requeued.
+    fn remove_worker(&mut self, worker_id: &str, reason: &str, now: i64) -> usize {
+        let Some(worker) = self.workers.remove(worker_id) else {
+            return 0;
+        };
+        self.health.forget(worker_id);
+        self.journal(Event::WorkerRemoved {
+            worker_id: worker_id.to_string(),
+            reason: reason.to_string(),
+        });
+        self.emit(ClusterEventKind::WorkerOffline, worker.tenant.as_deref(), worker_id, "", reason);
+        self.requeue_jobs_on(worker_id, reason, now)
     }
 
-    async fn submit_job(
-        &self,
-        request: Request<SubmitJobRequest>,
-    ) -> Result<Response<SubmitJobResponse>, Status> {
-        let req = request.into_inner();
-        let job_id = req.job_id.clone();
+    /// Queue the jobs dispatched to or running on `worker_id` again
+    /// (counted as preemptions). Returns how many there were.
+    fn requeue_jobs_on(&mut self, worker_id: &str, reason: &str, now: i64) -> usize {
+        let orphaned = self.jobs_on(worker_id);
+        for job_id in &orphaned {
+            if let Some(job) = self.jobs.get_mut(job_id) {
+                job.assigned_worker = None;
+                job.progress = None;
+                job.preemptions += 1;
+                job.error = Some(format!("Requeued from worker {} ({})", worker_id, reason));
+                job.set_status(JobStatusEnum::Pending, now);
+            }
+            self.journal_job(job_id);
+            warn!("🔁 Job {} requeued from worker {} ({})", job_id, worker_id, reason);
+        }
+        orphaned.len()
+    }
+
+    /// Count a job outcome on `worker_id`, towards its health score too: a
+    /// failure of a kind `policy` counts may quarantine it (straight away if
+    /// it was on probation), a success clears its record and ends any
+    /// probation
+    fn record_outcome(&mut self, worker_id: &str, failure: Option<RetryClass>, policy: &QuarantineConfig, now: i64) {
+        let Some(worker) = self.workers.get_mut(worker_id) else {
+            return;
+        };
+        let counted = failure.is_some_and(|class| policy.count_on.contains(&class));
+        if failure.is_none() || counted {
+            self.health.record_outcome(worker_id, counted, now);
+        }
+        let record = self.failures.entry(worker_id.to_string()).or_default();
+        let Some(class) = failure else {
+            record.consecutive = 0;
+            if worker.quarantined_until.take().is_some() {
+                *record = FailureRecord::default();
+                info!("✅ Worker {} passed probation; back in rotation", worker_id);
+                let worker = worker.clone();
+                self.journal(Event::WorkerRegistered { worker: Box::new(worker) });
+            }
+            return;
+        };
+        // Jobs it was given before being quarantined don't add to it
+        if !policy.count_on.contains(&class) || worker.quarantined_until.is_some_and(|until| now < until) {
+            return;
+        }
+
+        record.consecutive += 1;
+        record.recent.push_back(now);
+        while record.recent.front().is_some_and(|&at| now - at > policy.window_secs as i64) {
+            record.recent.pop_front();
+        }
+        let reason = if worker.quarantined_until.is_some() {
+            "failed its probation job".to_string()
+        } else if policy.consecutive_failures > 0 && record.consecutive >= policy.consecutive_failures {
+            format!("{} failures in a row", record.consecutive)
+        } else if policy.window_failures > 0 && record.recent.len() >= policy.window_failures as usize {
+            format!("{} failures in {}s", record.recent.len(), policy.window_secs)
+        } else {
+            return;
+        };
+
+        record.strikes += 1;
+        record.consecutive = 0;
+        record.recent.clear();
+        let secs = policy.duration_secs(record.strikes);
+        worker.quarantined_until = Some(now + secs as i64);
+        warn!("🚧 Worker {} quarantined for {}s: {}", worker_id, secs, reason);
+        let worker = worker.clone();
+        let detail = format!("{} (for {}s)", reason, secs);
+        self.emit(ClusterEventKind::WorkerQuarantined, worker.tenant.as_deref(), worker_id, "", &detail);
+        self.journal(Event::WorkerRegistered { worker: Box::new(worker) });
+    }
 
-        let job = JobMetadata {
+    /// Queue a submitted job, or hold it until its dependencies (which must
+    /// be known) complete; returns the status it starts in. With `reuse`
+    /// it's completed from the cache right away, or held until the job it
+    /// attaches to finishes instead. Jobs admitted with the same `gang` are
+    /// dispatched together.
+    fn admit(&mut self, req: SubmitJobRequest, now: i64, reuse: Option<Reuse>, gang: Option<String>) -> JobStatusEnum {
+        let job_id = req.job_id;
+        let mut job = JobMetadata {
             job_id: job_id.clone(),
             input_hash: req.input_hash,
             output_hash: None,
+            error: None,
+            error_kind: JobErrorKindEnum::Unspecified,
             job_type: req.job_type,
             status: JobStatusEnum::Pending,
             assigned_worker: None,
-            submitted_at: chrono::Utc::now().timestamp(),
+            submitted_at: now,
+            started_at: None,
             completed_at: None,
             metadata: req.metadata,
+            preemptions: 0,
+            worker_platform: None,
+            priority: req.priority,
+            timeline: Vec::new(),
+            progress: None,
+            attempts: 0,
+            attempt_history: Vec::new(),
+            retry_at: None,
+            run_after: Some(req.run_after).filter(|&at| at > now),
+            depends_on: req.depends_on,
+            constraints: req.constraints,
+            timeout_secs: Some(req.timeout_secs).filter(|&secs| secs > 0),
+            attached_to: None,
+            usage: None,
+            tenant: Some(req.tenant).filter(|tenant| !tenant.is_empty()),
+            session_id: Some(req.session_id).filter(|session| !session.is_empty()),
+            log_hash: None,
+            weight: 1,
+            pool: Some(req.pool).filter(|pool| !pool.is_empty()),
+            boosted: false,
+            gang,
         };
+        job.weight = self.durations.weight(&job, req.input_size, &self.weights);
+        match reuse {
+            Some(Reuse::Cached { job_id: source, output_hash }) => {
+                job.output_hash = Some(output_hash);
+                job.completed_at = Some(now);
+                job.metadata.insert("cached_from".to_string(), source);
+                job.set_status(JobStatusEnum::Completed, now);
+            }
+            Some(Reuse::InFlight(primary)) => {
+                // The shared job runs as soon as the most urgent of its submitters needs it
+                if let Some(running) = self.jobs.get_mut(&primary).filter(|p| p.priority < job.priority) {
+                    running.priority = job.priority;
+                    self.journal_job(&primary);
+                }
+                info!("🔗 Job {} attached to identical job {}", job_id, primary);
+                job.attached_to = Some(primary);
+                job.set_status(JobStatusEnum::Blocked, now);
+            }
+            None => match self.readiness(&job.depends_on) {
+                Readiness::Ready => job.set_status(JobStatusEnum::Pending, now),
+                Readiness::Waiting => job.set_status(JobStatusEnum::Blocked, now),
+                Readiness::Failed(dep) => {
+                    job.error = Some(format!("Dependency {} did not complete", dep));
+                    job.completed_at = Some(now);
+                    job.set_status(JobStatusEnum::Failed, now);
+                }
+            },
+        }
+        let status = job.status;
+        self.add_blob_ref(&job.input_hash, &job_id, "input");
+        if let Some(output) = &job.output_hash {
+            self.add_blob_ref(output, &job_id, "output");
+        }
+        self.announce_submitted(&job);
+        self.jobs.insert(job_id.clone(), job);
+        self.journal_job(&job_id);
 
-        let mut state = self.state.write().await;
-        state.jobs.insert(job_id.clone(), job);
+        info!("📋 Job submitted: {} [{}]", job_id, status);
+        status
+    }
 
-        println!("📋 Job submitted: {}", job_id);
+    /// `job_id`, unless `scope` doesn't see it
+    #[allow(clippy::result_large_err)]
+    fn job_in(&self, scope: &Scope, job_id: &str) -> Result<&JobMetadata, Status> {
+        self.jobs
+            .get(job_id)
+            .filter(|job| scope.sees_job(job))
+            .ok_or_else(|| Status::not_found(format!("Job {} not found", job_id)))
+    }
 
-        // Drop the lock before async work
-        drop(state);
+    #[allow(clippy::result_large_err)]
+    fn job_in_mut(&mut self, scope: &Scope, job_id: &str) -> Result<&mut JobMetadata, Status> {
+        self.jobs
+            .get_mut(job_id)
+            .filter(|job| scope.sees_job(job))
+            .ok_or_else(|| Status::not_found(format!("Job {} not found", job_id)))
+    }
 
-        // Try to assign jobs
-        self.assign_jobs_to_workers().await;
+    /// Jobs dispatched or running, by tenant
+    fn running_by_tenant(&self) -> HashMap<Option<String>, usize> {
+        let mut running: HashMap<Option<String>, usize> = HashMap::new();
+        let dispatched = self.index.with_status(JobStatusEnum::Assigned);
+        for id in dispatched.chain(self.index.with_status(JobStatusEnum::Running)) {
+            if let Some(job) = self.jobs.get(id) {
+                *running.entry(job.tenant.clone()).or_default() += 1;
+            }
+        }
+        running
+    }
 
-        Ok(Response::new(SubmitJobResponse {
-            success: true,
-            job_id,
-            message: "Job submitted successfully".to_string(),
-        }))
+    /// Worker slots taken up by the jobs dispatched to each worker or
+    /// running there, by their weights
+    fn weighted_load(&self) -> HashMap<&str, u32> {
+        let mut load: HashMap<&str, u32> = HashMap::new();
+        let dispatched = self.index.with_status(JobStatusEnum::Assigned);
+        for id in dispatched.chain(self.index.with_status(JobStatusEnum::Running)) {
+            if let Some((worker, job)) = self.jobs.get(id).and_then(|job| Some((job.assigned_worker.as_deref()?, job))) {
+                *load.entry(worker).or_default() += job.weight;
+            }
+        }
+        load
     }
 
-    async fn get_job_status(
-        &self,
-        request: Request<GetJobStatusRequest>,
-    ) -> Result<Response<GetJobStatusResponse>, Status> {
-        let req = request.into_inner();
-        let job_id = req.job_id;
+    /// Add to the audit trail that `scope`'s caller did `action` to `target`
+    fn audit(&mut self, scope: &Scope, action: &str, target: &str, detail: String) {
+        self.audit.record(AuditRecord {
+            at: clock::now(),
+            actor: scope.actor.clone(),
+            tenant: scope.tenant.clone(),
+            action: action.to_string(),
+            target: target.to_string(),
+            detail,
+        });
+    }
 
-        let state = self.state.read().await;
-        
-        if let Some(job) = state.jobs.get(&job_id) {
-            Ok(Response::new(GetJobStatusResponse {
-                job_id: job.job_id.clone(),
-                status: job.status.into(),
-                output_hash: job.output_hash.clone().unwrap_or_default(),
-                error: String::new(),
-                assigned_worker: job.assigned_worker.clone().unwrap_or_default(),
-            }))
-        } else {
-            Err(Status::not_found(format!("Job {} not found", job_id)))
+    /// Jobs visible in `scope` by the build session they're part of
+    fn sessions(&self, scope: &Scope) -> HashMap<&str, Vec<&JobMetadata>> {
+        let mut sessions: HashMap<&str, Vec<&JobMetadata>> = HashMap::new();
+        for job in self.jobs.values().filter(|job| scope.sees_job(job)) {
+            if let Some(session_id) = &job.session_id {
+                sessions.entry(session_id).or_default().push(job);
+            }
         }
+        sessions
     }
 
-    async fn list_workers(
-        &self,
-        _request: Request<ListWorkersRequest>,
-    ) -> Result<Response<ListWorkersResponse>, Status> {
-        let now = chrono::Utc::now().timestamp();
-        let mut state = self.state.write().await;
-        
-        // Remove offline workers (no heartbeat for 10+ seconds)
-        let offline_workers: Vec<String> = state
-            .workers
+    /// An earlier completed job of `tenant`'s, of `job_type` on
+    /// `input_hash`, and its output, which a new submission of the same can
+    /// reuse
+    fn cached_result(&self, tenant: Option<&str>, job_type: &str, input_hash: &str) -> Option<(String, String)> {
+        self.blob_refs
+            .get(input_hash)?
             .iter()
-            .filter(|(_, worker)| now - worker.last_heartbeat > 10)
-            .map(|(id, _)| id.clone())
-            .collect();
-        
-        for worker_id in &offline_workers {
-            state.workers.remove(worker_id);
-            println!("⚠️  Worker {} removed (offline for >10s)", worker_id);
-        }
-        
-        let workers = state
-            .workers
-            .values()
-            .map(|w| WorkerInfo {
-                worker_id: w.worker_id.clone(),
-                address: w.address.clone(),
-                capacity: w.capacity,
-                active_jobs: w.active_jobs,
-                last_heartbeat: w.last_heartbeat,
-                labels: w.labels.clone(),
-            })
-            .collect();
+            .filter(|(_, role)| **role == "input")
+            .filter_map(|(job_id, _)| self.jobs.get(job_id))
+            .filter(|job| job.status == JobStatusEnum::Completed && job.job_type == job_type)
+            .filter(|job| job.tenant.as_deref() == tenant)
+            .find_map(|job| Some((job.job_id.clone(), job.output_hash.clone()?)))
+    }
 
-        Ok(Response::new(ListWorkersResponse { workers }))
+    /// The oldest queued or running job of `tenant`'s, of `job_type` on
+    /// `input_hash`, that runs itself, which a new submission of the same
+    /// can share
+    fn in_flight(&self, tenant: Option<&str>, job_type: &str, input_hash: &str) -> Option<String> {
+        self.blob_refs
+            .get(input_hash)?
+            .iter()
+            .filter(|(_, role)| **role == "input")
+            .filter_map(|(job_id, _)| self.jobs.get(job_id))
+            .filter(|job| job.tenant.as_deref() == tenant)
+            .filter(|job| matches!(job.status, JobStatusEnum::Pending | JobStatusEnum::Assigned | JobStatusEnum::Running))
+            .filter(|job| job.job_type == job_type && job.depends_on.is_empty() && job.attached_to.is_none())
+            .min_by_key(|job| (job.submitted_at, job.job_id.clone()))
+            .map(|job| job.job_id.clone())
     }
 
-    async fn list_jobs(
-        &self,
-        request: Request<ListJobsRequest>,
-    ) -> Result<Response<ListJobsResponse>, Status> {
-        let req = request.into_inner();
-        let state = self.state.read().await;
-        
-        let mut jobs: Vec<JobInfo> = state
-            .jobs
-            .values()
-            .map(|j| JobInfo {
-                job_id: j.job_id.clone(),
-                status: j.status.into(),
-                input_hash: j.input_hash.clone(),
-                output_hash: j.output_hash.clone().unwrap_or_default(),
-                assigned_worker: j.assigned_worker.clone().unwrap_or_default(),
-                submitted_at: j.submitted_at,
-                completed_at: j.completed_at.unwrap_or(0),
+    /// Hand `job_id`'s outcome to the jobs attached to it: its output or
+    /// failure if it has one, returning those jobs. If it was cancelled, the
+    /// oldest attached job is queued to run itself and the rest attach to
+    /// that; returns whether one was.
+    fn settle_attached(&mut self, job_id: &str, now: i64) -> (Vec<String>, bool) {
+        let Some(primary) = self.jobs.get(job_id).filter(|job| job.status.is_terminal()).cloned() else {
+            return (Vec::new(), false);
+        };
+        let mut attached: Vec<(i64, String)> = self
+            .index
+            .with_status(JobStatusEnum::Blocked)
+            .filter_map(|id| self.jobs.get(id))
+            .filter(|job| job.attached_to.as_deref() == Some(job_id))
+            .map(|job| (job.submitted_at, job.job_id.clone()))
+            .collect();
+        attached.sort();
+        let attached: Vec<String> = attached.into_iter().map(|(_, id)| id).collect();
+
+        if primary.status == JobStatusEnum::Cancelled {
+            let Some((promoted, rest)) = attached.split_first() else {
+                return (Vec::new(), false);
+            };
+            for id in &attached {
+                if let Some(job) = self.jobs.get_mut(id) {
+                    if id == promoted {
+                        job.attached_to = None;
+                        job.set_status(JobStatusEnum::Pending, now);
+                    } else {
+                        job.attached_to = Some(promoted.clone());
+                    }
+                }
+                self.journal_job(id);
+            }
+            info!("🔗 Job {} cancelled; {} runs in its place for {} more", job_id, promoted, rest.len());
+            return (Vec::new(), true);
+        }
+
+        for id in &attached {
+            if let Some(job) = self.jobs.get_mut(id) {
+                job.output_hash = primary.output_hash.clone();
+                job.error = primary.error.clone();
+                job.error_kind = primary.error_kind;
+                job.worker_platform = primary.worker_platform.clone();
+                job.log_hash = primary.log_hash.clone();
+                job.completed_at = Some(now);
+                job.set_status(primary.status, now);
+            }
+            if let Some(output) = &primary.output_hash {
+                self.add_blob_ref(output, id, "output");
+            }
+            if let Some(log) = &primary.log_hash {
+                self.add_blob_ref(log, id, "log");
+            }
+            self.journal_job(id);
+        }
+        (attached, false)
+    }
+
+    /// Blobs `job` reads: its input, the `deps` it lists and its
+    /// dependencies' outputs
+    fn needed_blobs(&self, job: &JobMetadata) -> Vec<String> {
+        let mut hashes = vec![job.input_hash.clone()];
+        if let Some(deps) = job.metadata.get("deps") {
+            hashes.extend(deps.split(',').map(str::trim).filter(|h| !h.is_empty()).map(str::to_string));
+        }
+        hashes.extend(job.depends_on.iter().filter_map(|dep| self.jobs.get(dep)?.output_hash.clone()));
+        hashes
+    }
+
+    /// Index into `candidates` of the worker `strategy` picks for `job`,
+    /// among those that accept it and have room
+    fn pick_worker(&self, job: &JobMetadata, candidates: &[Candidate], strategy: &mut dyn SchedulingStrategy) -> Option<usize> {
+        let needed = self.needed_blobs(job);
+        let mut eligible: Vec<usize> = (0..candidates.len())
+            .filter(|&idx| candidates[idx].accepts(job) && candidates[idx].fits(job.weight))
+            .collect();
+        // Workers it couldn't be dispatched to only get it back if no
+        // other can take it
+        if let Some(failed) = self.dispatch_failures.get(&job.job_id) {
+            if eligible.iter().any(|&idx| !failed.contains(&candidates[idx].id)) {
+                eligible.retain(|&idx| !failed.contains(&candidates[idx].id));
+            }
+        }
+        // Unhealthy workers only get what no healthier one can take
+        if eligible.iter().any(|&idx| !self.health.is_unhealthy(candidates[idx].health)) {
+            eligible.retain(|&idx| !self.health.is_unhealthy(candidates[idx].health));
+        }
+        // Prefer the workers already holding most of what the job reads
+        let best = eligible.iter().map(|&idx| candidates[idx].locality(&needed)).max().unwrap_or(0);
+        if best > 0 {
+            eligible.retain(|&idx| candidates[idx].locality(&needed) == best);
+        }
+        strategy::pick_among(strategy, candidates, &eligible)
+    }
+
+    /// Pending members of each gang with some, in queue order, and the
+    /// gangs held back: with a member still waiting on dependencies or not
+    /// due yet, so dispatching the rest would leave the gang half-started
+    fn gangs(&self, pending: &[String], now: i64) -> (HashMap<String, Vec<String>>, HashSet<String>) {
+        let mut gangs: HashMap<String, Vec<String>> = HashMap::new();
+        let mut held = HashSet::new();
+        for job in pending.iter().filter_map(|job_id| self.jobs.get(job_id)) {
+            let Some(gang) = &job.gang else {
+                continue;
+            };
+            gangs.entry(gang.clone()).or_default().push(job.job_id.clone());
+            if !job.is_due(now) {
+                held.insert(gang.clone());
+            }
+        }
+        let blocked = self.index.with_status(JobStatusEnum::Blocked).filter_map(|job_id| self.jobs.get(job_id));
+        // One attached to an identical job runs as that one, so holds nothing up
+        held.extend(blocked.filter(|job| job.attached_to.is_none()).filter_map(|job| job.gang.clone()));
+        (gangs, held)
+    }
+
+    /// Whether any queued job is ready to be dispatched
+    fn has_runnable_jobs(&self, now: i64) -> bool {
+        self.index
+            .pending()
+            .filter_map(|job_id| self.jobs.get(job_id))
+            .any(|job| job.is_due(now))
+    }
+
+    /// Jobs dispatched to `worker_id` that it hasn't finished
+    fn jobs_on(&self, worker_id: &str) -> Vec<String> {
+        [JobStatusEnum::Assigned, JobStatusEnum::Running]
+            .into_iter()
+            .flat_map(|status| self.index.with_status(status))
+            .filter(|job_id| {
+                self.jobs.get(*job_id).is_some_and(|job| job.assigned_worker.as_deref() == Some(worker_id))
+            })
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Finished jobs the retention policy no longer keeps, longest finished
+    /// first: those finished over `retention_secs` ago, and the ones
+    /// beyond the newest `max_finished` (0 = no limit on either). Jobs a
+    /// blocked job still depends on are kept.
+    fn expired_jobs(&self, retention_secs: u64, max_finished: usize, now: i64) -> Vec<String> {
+        let needed: HashSet<&str> = self
+            .index
+            .with_status(JobStatusEnum::Blocked)
+            .filter_map(|job_id| self.jobs.get(job_id))
+            .flat_map(|job| job.depends_on.iter().map(String::as_str))
+            .collect();
+        let mut finished: Vec<(i64, &str)> = [JobStatusEnum::Completed, JobStatusEnum::Failed, JobStatusEnum::Cancelled]
+            .into_iter()
+            .flat_map(|status| self.index.with_status(status))
+            .filter(|job_id| !needed.contains(job_id))
+            .filter_map(|job_id| {
+                let job = self.jobs.get(job_id)?;
+                Some((job.completed_at.unwrap_or(job.submitted_at), job_id))
+            })
+            .collect();
+        finished.sort();
+
+        let mut expired = if max_finished > 0 {
+            finished.len().saturating_sub(max_finished)
+        } else {
+            0
+        };
+        if retention_secs > 0 {
+            let cutoff = now - retention_secs as i64;
+            expired = expired.max(finished.partition_point(|&(at, _)| at < cutoff));
+        }
+        finished[..expired].iter().map(|(_, job_id)| job_id.to_string()).collect()
+    }
+
+    /// Forget `job_id` for good (see `expired_jobs`), journaling that
+    fn forget_job(&mut self, job_id: &str) {
+        if self.remove_job(job_id).is_some() {
+            self.journal(Event::JobRemoved { job_id: job_id.to_string() });
+        }
+    }
+
+    /// Drop `job_id` from the jobs, their index and the blob references
+    fn remove_job(&mut self, job_id: &str) -> Option<JobMetadata> {
+        let job = self.jobs.remove(job_id)?;
+        self.index.update(job_id, None);
+        self.dispatch_failures.remove(job_id);
+        self.webhooks.forget(job_id);
+        for hash in std::iter::once(&job.input_hash).chain(&job.output_hash).chain(&job.log_hash) {
+            if let Some(refs) = self.blob_refs.get_mut(hash) {
+                refs.remove(job_id);
+                if refs.is_empty() {
+                    self.blob_refs.remove(hash);
+                }
+            }
+        }
+        Some(job)
+    }
+
+    /// Whether jobs depending on `depends_on` can run yet
+    fn readiness(&self, depends_on: &[String]) -> Readiness {
+        let mut ready = Readiness::Ready;
+        for dep in depends_on {
+            match self.jobs.get(dep).map(|job| job.status) {
+                Some(JobStatusEnum::Completed) => {}
+                Some(JobStatusEnum::Failed | JobStatusEnum::Cancelled) | None => return Readiness::Failed(dep.clone()),
+                Some(_) => ready = Readiness::Waiting,
+            }
+        }
+        ready
+    }
+
+    /// Follow up on `job_id` having finished: jobs attached to it share its
+    /// outcome (see `settle_attached`), jobs blocked on it whose
+    /// dependencies have now all completed are queued, and if it failed or
+    /// was cancelled, jobs blocked on it fail (and so on down the graph).
+    /// Returns whether any job was queued.
+    fn settle_dependents(&mut self, job_id: &str, now: i64) -> bool {
+        let mut queued = false;
+        let mut finished = vec![job_id.to_string()];
+        while let Some(done) = finished.pop() {
+            if !self.jobs.get(&done).is_some_and(|job| job.status.is_terminal()) {
+                continue;
+            }
+            let (shared, promoted) = self.settle_attached(&done, now);
+            finished.extend(shared);
+            queued |= promoted;
+            let blocked: Vec<(String, Vec<String>)> = self
+                .jobs
+                .values()
+                .filter(|job| job.status == JobStatusEnum::Blocked && job.depends_on.contains(&done))
+                .map(|job| (job.job_id.clone(), job.depends_on.clone()))
+                .collect();
+
+            for (dependent, depends_on) in blocked {
+                let readiness = self.readiness(&depends_on);
+                let Some(job) = self.jobs.get_mut(&dependent) else {
+                    continue;
+                };
+                match readiness {
+                    Readiness::Waiting => continue,
+                    Readiness::Ready => {
+                        job.set_status(JobStatusEnum::Pending, now);
+                        queued = true;
+                    }
+                    Readiness::Failed(dep) => {
+                        job.error = Some(format!("Dependency {} did not complete", dep));
+                        job.completed_at = Some(now);
+                        job.set_status(JobStatusEnum::Failed, now);
+                        info!("⏭️  Job {} skipped: dependency {} did not complete", dependent, dep);
+                        finished.push(dependent.clone());
+                    }
+                }
+                self.journal_job(&dependent);
+            }
+        }
+        queued
+    }
+
+    /// Move workers between lifecycle states by their heartbeats,
+    /// journaling each change. Going offline takes the configured offline
+    /// action, once; under `OfflineAction::Remove` workers offline past the
+    /// retention period are then forgotten. Returns how many jobs were left
+    /// to requeue.
+    fn update_worker_states(&mut self, now: i64) -> usize {
+        let liveness = self.liveness;
+        let changed: Vec<(String, WorkerStateEnum, i64)> = self
+            .workers
+            .values()
+            .map(|worker| (worker, liveness.state_of(worker, now)))
+            .filter(|(worker, (state, _))| *state != worker.state)
+            .map(|(worker, (state, since))| (worker.worker_id.clone(), state, since))
+            .collect();
+
+        let mut requeued = 0;
+        for (worker_id, state, since) in changed {
+            let Some(worker) = self.workers.get_mut(&worker_id) else {
+                continue;
+            };
+            let was = std::mem::replace(&mut worker.state, state);
+            worker.state_since = since;
+            let worker = worker.clone();
+            self.journal(Event::WorkerRegistered { worker: Box::new(worker.clone()) });
+            match state {
+                WorkerStateEnum::Suspect => {
+                    warn!("⚠️  Worker {} suspect (no heartbeat for >{}s)", worker_id, liveness.timeout_secs);
+                }
+                WorkerStateEnum::Offline => {
+                    let silent = liveness.timeout_secs + liveness.grace_secs;
+                    warn!("⚠️  Worker {} offline (no heartbeat for >{}s): {:?}", worker_id, silent, liveness.action);
+                    self.emit(ClusterEventKind::WorkerOffline, worker.tenant.as_deref(), &worker_id, "", "no heartbeat");
+                    if liveness.action != OfflineAction::MarkOffline {
+                        requeued += self.requeue_jobs_on(&worker_id, "no heartbeat", now);
+                    }
+                }
+                _ if was == WorkerStateEnum::Offline => {
+                    info!("✅ Worker {} back online", worker_id);
+                    self.emit(ClusterEventKind::WorkerOnline, worker.tenant.as_deref(), &worker_id, "", &worker.address);
+                }
+                _ => {}
+            }
+        }
+
+        if liveness.action == OfflineAction::Remove {
+            let expired: Vec<String> = self
+                .workers
+                .values()
+                .filter(|worker| worker.state == WorkerStateEnum::Offline && now - worker.state_since >= liveness.retention_secs)
+                .map(|worker| worker.worker_id.clone())
+                .collect();
+            for worker_id in expired {
+                self.workers.remove(&worker_id);
+                self.health.forget(&worker_id);
+                self.journal(Event::WorkerRemoved {
+                    worker_id: worker_id.clone(),
+                    reason: "offline".to_string(),
+                });
+                info!("🧹 Forgot worker {}, offline for over {}s", worker_id, liveness.retention_secs);
+            }
+        }
+        requeued
+    }
+
+    /// Dispatched jobs to reap at `now`, and why: past their deadline
+    /// (`default_timeout` unless they set their own) or with no sign of
+    /// life for `PROGRESS_STALL_SECS`
+    fn stuck_jobs(&self, now: i64, default_timeout: u64) -> Vec<(String, String)> {
+        self.jobs
+            .values()
+            .filter_map(|job| {
+                let reason = if job.deadline(default_timeout).is_some_and(|at| now > at) {
+                    format!("Timed out after {}s", job.timeout_secs.unwrap_or(default_timeout))
+                } else if job.is_stalled(now, PROGRESS_STALL_SECS) {
+                    format!("Stalled: no sign of life for over {}s", PROGRESS_STALL_SECS)
+                } else {
+                    return None;
+                };
+                Some((job.job_id.clone(), reason))
+            })
+            .collect()
+    }
+
+    /// Hashes that must survive CAS garbage collection: every blob a job
+    /// that is not yet terminal reads (see `needed_blobs`), and its output
+    /// and log if already stored
+    fn pinned_hashes(&self) -> HashSet<String> {
+        let mut pinned = HashSet::new();
+        for job in self.jobs.values().filter(|job| !job.status.is_terminal()) {
+            pinned.extend(self.needed_blobs(job));
+            pinned.extend(job.output_hash.iter().chain(&job.log_hash).cloned());
+        }
+        pinned
+    }
+
+    /// Record that `job_id` references `hash` as its input, output or log
+    fn add_blob_ref(&mut self, hash: &str, job_id: &str, role: &'static str) {
+        if hash.is_empty() {
+            return;
+        }
+        self.blob_refs
+            .entry(hash.to_string())
+            .or_default()
+            .insert(job_id.to_string(), role);
+    }
+
+    /// Summarize queue wait and execution time per tenant for jobs
+    /// submitted at or after `since`
+    fn fairness_report(&self, since: i64, now: i64) -> Vec<TenantUsage> {
+        let mut waits: HashMap<String, Vec<i64>> = HashMap::new();
+        let mut usage: HashMap<String, TenantUsage> = HashMap::new();
+
+        for job in self.jobs.values().filter(|job| job.submitted_at >= since) {
+            let tenant = job.tenant().to_string();
+            let entry = usage.entry(tenant.clone()).or_insert_with(|| TenantUsage {
+                tenant: tenant.clone(),
+                ..Default::default()
+            });
+            entry.jobs += 1;
+            entry.preemptions += job.preemptions;
+
+            // Jobs that haven't started yet are still waiting
+            let started = job.started_at.unwrap_or(now);
+            waits.entry(tenant).or_default().push(started - job.submitted_at);
+
+            if let Some(started_at) = job.started_at {
+                let finished = job.completed_at.unwrap_or(now);
+                entry.cluster_time_secs += (finished - started_at).max(0);
+            }
+        }
+
+        let total_time: i64 = usage.values().map(|u| u.cluster_time_secs).sum();
+
+        let mut tenants: Vec<TenantUsage> = usage
+            .into_values()
+            .map(|mut u| {
+                let mut tenant_waits = waits.remove(&u.tenant).unwrap_or_default();
+                tenant_waits.sort_unstable();
+                u.wait_p50_secs = percentile(&tenant_waits, 50);
+                u.wait_p90_secs = percentile(&tenant_waits, 90);
+                u.wait_p99_secs = percentile(&tenant_waits, 99);
+                if total_time > 0 {
+                    u.cluster_share = u.cluster_time_secs as f64 / total_time as f64;
+                }
+                u
+            })
+            .collect();
+
+        tenants.sort_by_key(|u| std::cmp::Reverse(u.cluster_time_secs));
+        tenants
+    }
+
+    /// Desired worker count from Little's law: jobs in the system = arrival
+    /// rate × average duration, plus enough slots to drain the current
+    /// queue within one window, scaled up to keep utilization at target
+    fn scaling_advice(&self, window_secs: u64, target_utilization: f64, now: i64) -> GetScalingAdviceResponse {
+        let since = now - window_secs as i64;
+
+        let online: Vec<&WorkerMetadata> = self
+            .workers
+            .values()
+            .filter(|worker| self.liveness.is_online(worker, now))
+            .collect();
+        let slots_per_worker = if online.is_empty() {
+            1.0
+        } else {
+            online.iter().map(|w| w.capacity as f64).sum::<f64>() / online.len() as f64
+        };
+
+        let queue_depth = self
+            .jobs
+            .values()
+            .filter(|job| job.status == JobStatusEnum::Pending)
+            .count() as u32;
+        let running_jobs = self
+            .jobs
+            .values()
+            .filter(|job| matches!(job.status, JobStatusEnum::Assigned | JobStatusEnum::Running))
+            .count() as u32;
+        let arrivals = self.jobs.values().filter(|job| job.submitted_at >= since).count();
+        let arrival_rate = arrivals as f64 / window_secs as f64;
+
+        let durations: Vec<i64> = self
+            .jobs
+            .values()
+            .filter(|job| job.completed_at.is_some_and(|t| t >= since))
+            .filter_map(|job| Some(job.completed_at? - job.started_at?))
+            .collect();
+        let avg_job_duration_secs = if durations.is_empty() {
+            0.0
+        } else {
+            durations.iter().sum::<i64>().max(0) as f64 / durations.len() as f64
+        };
+
+        let (busy_slots, reason) = if avg_job_duration_secs > 0.0 {
+            let steady = arrival_rate * avg_job_duration_secs;
+            let backlog = queue_depth as f64 * avg_job_duration_secs / window_secs as f64;
+            (
+                steady + backlog,
+                format!(
+                    "{:.2} jobs/s × {:.1}s avg = {:.1} busy slots, plus {:.1} to drain {} queued",
+                    arrival_rate, avg_job_duration_secs, steady, backlog, queue_depth
+                ),
+            )
+        } else {
+            // No completed jobs to measure yet: one slot per outstanding job
+            (
+                (queue_depth + running_jobs) as f64,
+                format!(
+                    "no job durations in window; sized for {} outstanding jobs",
+                    queue_depth + running_jobs
+                ),
+            )
+        };
+
+        let desired_slots = busy_slots / target_utilization;
+        let desired_workers = (desired_slots / slots_per_worker).ceil() as u32;
+
+        GetScalingAdviceResponse {
+            current_workers: online.len() as u32,
+            desired_workers,
+            queue_depth,
+            running_jobs,
+            arrival_rate,
+            avg_job_duration_secs,
+            slots_per_worker,
+            window_secs,
+            reason,
+        }
+    }
+
+    /// Job counts, throughput and queue latency over the last `window_secs`,
+    /// and each worker's load
+    fn stats(&self, scope: &Scope, window_secs: u64, now: i64) -> GetSchedulerStatsResponse {
+        let since = now - window_secs as i64;
+        let jobs = || self.jobs.values().filter(|job| scope.sees_job(job));
+
+        let mut jobs_by_status: HashMap<String, u32> = HashMap::new();
+        for job in jobs() {
+            *jobs_by_status.entry(job.status.to_string()).or_default() += 1;
+        }
+        let queue_depth = jobs_by_status.get(&JobStatusEnum::Pending.to_string()).copied().unwrap_or(0);
+
+        let finished = jobs()
+            .filter(|job| job.status != JobStatusEnum::Cancelled)
+            .filter(|job| job.completed_at.is_some_and(|at| at >= since))
+            .count();
+        let latencies: Vec<i64> = jobs()
+            .filter_map(|job| job.started_at.filter(|&at| at >= since).map(|at| at - job.submitted_at))
+            .collect();
+        let avg_queue_latency_secs = if latencies.is_empty() {
+            0.0
+        } else {
+            latencies.iter().sum::<i64>().max(0) as f64 / latencies.len() as f64
+        };
+
+        let mut workers: Vec<WorkerUtilization> = self
+            .workers
+            .values()
+            .filter(|worker| scope.sees_worker(worker))
+            .map(|worker| WorkerUtilization {
+                worker_id: worker.worker_id.clone(),
+                active_jobs: worker.active_jobs,
+                capacity: worker.capacity,
+                utilization: if worker.capacity > 0 {
+                    worker.active_jobs as f64 / worker.capacity as f64
+                } else {
+                    0.0
+                },
+                online: self.liveness.is_online(worker, now),
+            })
+            .collect();
+        workers.sort_by(|a, b| a.worker_id.cmp(&b.worker_id));
+
+        GetSchedulerStatsResponse {
+            queue_depth,
+            jobs_by_status,
+            total_jobs: jobs().count() as u32,
+            jobs_per_minute: finished as f64 * 60.0 / window_secs as f64,
+            avg_queue_latency_secs,
+            workers,
+            window_secs,
+            costs: self.costs(scope, since),
+        }
+    }
+
+    /// Resources used per tenant by jobs finished at or after `since`,
+    /// the most expensive (by run time) first
+    fn costs(&self, scope: &Scope, since: i64) -> Vec<TenantCost> {
+        let mut costs: HashMap<&str, TenantCost> = HashMap::new();
+        let finished = self.jobs.values().filter(|job| job.completed_at.is_some_and(|at| at >= since));
+        for job in finished.filter(|job| scope.sees_job(job)) {
+            let Some(usage) = job.usage_info() else {
+                continue;
+            };
+            let cost = costs.entry(job.tenant()).or_insert_with(|| TenantCost {
+                tenant: job.tenant().to_string(),
+                ..Default::default()
+            });
+            cost.jobs += 1;
+            let total = cost.usage.get_or_insert_with(Default::default);
+            total.queue_wait_secs += usage.queue_wait_secs;
+            total.exec_millis += usage.exec_millis;
+            total.input_bytes += usage.input_bytes;
+            total.output_bytes += usage.output_bytes;
+            total.transferred_bytes += usage.transferred_bytes;
+        }
+
+        let mut costs: Vec<TenantCost> = costs.into_values().collect();
+        costs.sort_by_key(|cost| std::cmp::Reverse(cost.usage.map_or(0, |usage| usage.exec_millis)));
+        costs
+    }
+
+    /// Record a client error, returning false if the client is over its rate limit
+    fn record_client_error(&mut self, client_id: &str, kind: &str, message: &str, now: i64) -> bool {
+        let window = self
+            .client_error_windows
+            .entry(client_id.to_string())
+            .or_insert((now, 0));
+        if now - window.0 >= 60 {
+            *window = (now, 0);
+        }
+        if window.1 >= CLIENT_ERROR_RATE_LIMIT {
+            return false;
+        }
+        window.1 += 1;
+
+        let key = (kind.to_string(), message.to_string());
+        if !self.client_errors.contains_key(&key) && self.client_errors.len() >= MAX_CLIENT_ERRORS {
+            // Evict the least recently seen error
+            if let Some(oldest) = self
+                .client_errors
+                .iter()
+                .min_by_key(|(_, record)| record.last_seen)
+                .map(|(key, _)| key.clone())
+            {
+                self.client_errors.remove(&oldest);
+            }
+        }
+
+        let record = self.client_errors.entry(key).or_insert_with(|| ClientErrorRecord {
+            count: 0,
+            clients: HashSet::new(),
+            first_seen: now,
+            last_seen: now,
+        });
+        record.count += 1;
+        record.clients.insert(client_id.to_string());
+        record.last_seen = now;
+
+        true
+    }
+}
+
+/// Nearest-rank percentile of an already sorted slice
+fn percentile(sorted: &[i64], p: usize) -> i64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+impl SchedulerService {
+    pub fn new(config: SchedulerConfig) -> Self {
+        SchedulerService {
+            strategy: Arc::new(Mutex::new(strategy::from_kind(config.strategy))),
+            state: Arc::new(RwLock::new(SchedulerState {
+                liveness: Liveness::from_config(&config),
+                health: HealthTracker::new(config.health.clone()),
+                weights: config.weights.clone(),
+                webhooks: Webhooks::new(config.webhooks.clone(), config.session_idle_secs),
+                history: JobHistory::in_memory(config.history_retention_secs),
+                index: JobIndex::aging(config.aging_secs, []),
+                ..Default::default()
+            })),
+            config: Arc::new(config),
+            cas: None,
+            store: Arc::default(),
+            mirror: Arc::default(),
+            quiesced: Arc::default(),
+            shutdown: Arc::default(),
+            auth: ClientAuth::default(),
+            workers: WorkerChannels::default(),
+        }
+    }
+
+    /// Keep jobs and workers in `dir` so they survive restarts, resuming
+    /// from whatever a previous run left there
+    pub fn with_state_dir(mut self, dir: &Path) -> Result<Self> {
+        let mut store = StateStore::open(dir)?;
+        store.acquire(&self.lease_holder(), clock::now(), false)?;
+        let state = self.restore(&mut store, dir)?;
+        self.state = Arc::new(RwLock::new(state));
+        self.store = Arc::new(OnceLock::from(Mutex::new(store)));
+        Ok(self)
+    }
+
+    /// Whatever the last primary on `dir` left, ready to carry on from
+    fn restore(&self, store: &mut StateStore, dir: &Path) -> Result<SchedulerState> {
+        let mut state = store.load(clock::now())?;
+        state.liveness = Liveness::from_config(&self.config);
+        state.health = HealthTracker::new(self.config.health.clone());
+        state.weights = self.config.weights.clone();
+        state.webhooks = Webhooks::new(self.config.webhooks.clone(), self.config.session_idle_secs);
+        state.index = JobIndex::aging(self.config.aging_secs, state.jobs.values());
+        state.audit = AuditLog::open(dir);
+        state.history = JobHistory::open(dir, self.config.history_retention_secs, clock::now());
+        if !state.jobs.is_empty() || !state.workers.is_empty() {
+            info!("♻️  Restored {} jobs and {} workers from {:?}", state.jobs.len(), state.workers.len(), dir);
+        }
+        Ok(state)
+    }
+
+    /// Who this scheduler is on the state dir's lease; the same across
+    /// restarts, so a primary coming back isn't locked out by itself
+    fn lease_holder(&self) -> String {
+        format!("{}/{}", platform::hostname(), self.config.addr)
+    }
+
+    /// Run as a read-only mirror of the primary scheduler persisting to
+    /// `dir`, serving listings and reports from a copy of its state so that
+    /// traffic never contends with the primary's scheduling. Anything that
+    /// would change state is refused. Worker heartbeats aren't journaled,
+    /// so listed workers keep the heartbeat and load they registered with.
+    pub fn with_mirror_of(mut self, dir: &Path) -> Result<Self> {
+        let (mirror, mut state) = Mirror::open(dir)?;
+        state.liveness = Liveness::from_config(&self.config);
+        state.audit = AuditLog::open(dir);
+        state.history = JobHistory::follow(dir, self.config.history_retention_secs, clock::now());
+        info!("🪞 Mirroring {} jobs and {} workers from {:?}", state.jobs.len(), state.workers.len(), dir);
+        self.state = Arc::new(RwLock::new(state));
+        self.mirror = Arc::new(Mutex::new(Some(mirror)));
+        Ok(self)
+    }
+
+    /// Also serve `cas` to replication peers on the scheduler address
+    pub fn with_cas(mut self, cas: Arc<Cas>) -> Self {
+        self.cas = Some(cas);
+        self
+    }
+
+    pub async fn run(mut self, addr: String) -> Result<()> {
+        let addr = addr.parse()?;
+        self.auth = ClientAuth::from_config(&self.config)?;
+        self.workers = WorkerChannels::new(self.auth.clone());
+        info!("🚀 Scheduler listening on {}", addr);
+
+        self.spawn_signal_handler();
+        if let Some(dashboard_addr) = self.config.dashboard_addr.clone() {
+            let state = self.state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = dashboard::serve(state, &dashboard_addr).await {
+                    error!("❌ Dashboard failed: {}", e);
+                }
+            });
+        }
+        if self.mirroring() {
+            self.spawn_mirror_follower();
+            if let Some(standby) = self.config.standby.clone() {
+                self.spawn_standby_watch(standby);
+            }
+        } else if self.store.get().is_some() {
+            self.start_persistent().await;
+        } else {
+            self.spawn_assignment_loop(ASSIGN_INTERVAL);
+            self.spawn_reaper();
+            self.spawn_sweeper();
+            if self.config.archive_jobs {
+                warn!("⚠️  archive_jobs needs a state_dir; forgotten jobs won't be archived");
+            }
+        }
+
+        let authenticator = Authenticator::from_config(&self.config);
+        let blob_store = self
+            .cas
+            .clone()
+            .map(|cas| InterceptedService::new(BlobStoreService::server(cas), authenticator.clone()));
+        if authenticator.enabled() {
+            info!("🔒 Scheduler requests need an auth token");
+        }
+        let mut server = Server::builder();
+        if let Some(tls) = &self.config.tls {
+            server = server.tls_config(auth::server_tls(tls)?)?;
+            info!("🔒 Serving over TLS, requiring client certificates");
+        }
+        let shutdown = self.shutdown.clone();
+        server
+            .add_service(SchedulerServer::with_interceptor(self, authenticator))
+            .add_optional_service(blob_store)
+            .serve_with_shutdown(addr, async move { shutdown.notified().await })
+            .await?;
+
+        Ok(())
+    }
+
+    /// On SIGTERM or SIGINT, shut down gracefully rather than dropping
+    /// everything
+    fn spawn_signal_handler(&self) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = shutdown_signal().await {
+                warn!("⚠️  Can't listen for shutdown signals: {}", e);
+                return;
+            }
+            scheduler.shut_down().await;
+        });
+    }
+
+    /// Stop admitting and dispatching jobs, wait up to
+    /// `shutdown_drain_secs` for running ones to report back (a second
+    /// signal stops waiting), save the state and tell the workers, then
+    /// stop serving. Jobs still running are picked up again after a
+    /// restart when there's a state dir, and lost otherwise.
+    async fn shut_down(&self) {
+        self.quiesced.store(true, Ordering::SeqCst);
+        if self.mirroring() {
+            info!("🛑 Shutting down");
+            self.shutdown.notify_one();
+            return;
+        }
+
+        let (running, queued) = self.job_counts().await;
+        info!("🛑 Shutting down: no longer admitting or dispatching jobs ({} running, {} queued)", running, queued);
+        let drain_secs = self.config.shutdown_drain_secs;
+        if running > 0 && drain_secs > 0 {
+            info!("🛑 Waiting up to {}s for running jobs (signal again to stop waiting)", drain_secs);
+            let deadline = std::time::Instant::now() + Duration::from_secs(drain_secs);
+            let drain = async {
+                while self.job_counts().await.0 > 0 && std::time::Instant::now() < deadline {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            };
+            tokio::select! {
+                _ = drain => {}
+                _ = shutdown_signal() => info!("🛑 Not waiting for running jobs"),
+            }
+        }
+
+        let (running, queued) = self.job_counts().await;
+        let persisted = self.persist_now().await.unwrap_or_else(|e| {
+            error!("❌ Failed to save scheduler state: {}", e);
+            false
+        });
+        self.notify_workers_stopping(persisted).await;
+        if persisted {
+            info!("💾 Saved {} running and {} queued job(s) for the next start", running, queued);
+        } else if running + queued > 0 {
+            warn!("⚠️  Losing {} running and {} queued job(s): there's no state_dir to save them to", running, queued);
+        }
+        self.shutdown.notify_one();
+    }
+
+    /// Tell online workers the scheduler is going away. When it's
+    /// `resuming` (its state is saved) they hold the results of running
+    /// jobs until it's back; otherwise they abort jobs it will have
+    /// forgotten.
+    async fn notify_workers_stopping(&self, resuming: bool) {
+        let now = clock::now();
+        let workers: Vec<(String, String)> = {
+            let state = self.state.read().await;
+            state
+                .workers
+                .values()
+                .filter(|worker| state.liveness.is_online(worker, now))
+                .map(|worker| (worker.worker_id.clone(), worker.address.clone()))
+                .collect()
+        };
+        let notices = workers.iter().map(|(worker_id, address)| async move {
+            match tokio::time::timeout(STOP_NOTICE_TIMEOUT, notify_stopping(&self.workers, address, resuming)).await {
+                Ok(Ok(0)) => {}
+                Ok(Ok(running)) => info!("🔌 {} still has {} job(s) running", worker_id, running),
+                Ok(Err(e)) => warn!("⚠️  Failed to tell {} the scheduler is stopping: {}", worker_id, e),
+                Err(_) => warn!("⚠️  Timed out telling {} the scheduler is stopping", worker_id),
+            }
+        });
+        futures::future::join_all(notices).await;
+    }
+
+    /// Persist, dispatch, reap and sweep, once restored from the state dir
+    async fn start_persistent(&self) {
+        self.spawn_state_flusher();
+        self.resume_queued_jobs().await;
+        self.spawn_assignment_loop(self.resume_grace());
+        self.spawn_reaper();
+        self.spawn_sweeper();
+    }
+
+    /// Save the state to the store whenever it changed. Holds the state
+    /// lock throughout, so the saved records and the journal position they
+    /// reflect agree, and the journal can rotate once it's covered. Steps
+    /// down if a standby has taken the state dir over.
+    fn spawn_state_flusher(&self) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            let Some(store) = scheduler.store.get() else {
+                return;
+            };
+            loop {
+                tokio::time::sleep(STATE_FLUSH_INTERVAL).await;
+                let mut state = scheduler.state.write().await;
+                match flush_state(&mut state, &mut store.lock().unwrap()) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        error!("❌ Another scheduler has taken over the state dir; stepping down");
+                        scheduler.quiesced.store(true, Ordering::SeqCst);
+                        scheduler.shutdown.notify_one();
+                        return;
+                    }
+                    Err(e) => warn!("⚠️  Failed to persist scheduler state: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Write the state to the store now; false if there's no store (or
+    /// it's been taken over)
+    async fn persist_now(&self) -> Result<bool> {
+        let Some(store) = self.store.get() else {
+            return Ok(false);
+        };
+        let mut state = self.state.write().await;
+        flush_state(&mut state, &mut store.lock().unwrap())
+    }
+
+    /// Assigned and running jobs, and pending ones
+    async fn job_counts(&self) -> (u32, u32) {
+        let state = self.state.read().await;
+        let count = |pred: &dyn Fn(&JobMetadata) -> bool| state.jobs.values().filter(|job| pred(job)).count() as u32;
+        (
+            count(&|job| matches!(job.status, JobStatusEnum::Assigned | JobStatusEnum::Running)),
+            count(&|job| job.status == JobStatusEnum::Pending),
+        )
+    }
+
+    /// Jobs assigned to or running on `worker_id`
+    async fn jobs_on(&self, worker_id: &str) -> u32 {
+        self.state.read().await.jobs_on(worker_id).len() as u32
+    }
+
+    /// Run an assignment pass every `ASSIGN_INTERVAL`, starting after
+    /// `first`, whenever jobs are waiting; catches capacity no event
+    /// announced and retries whose backoff ran out
+    fn spawn_assignment_loop(&self, first: Duration) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(first).await;
+            let mut interval = tokio::time::interval(ASSIGN_INTERVAL);
+            loop {
+                interval.tick().await;
+                if scheduler.state.read().await.has_runnable_jobs(clock::now()) {
+                    scheduler.assign_jobs_to_workers().await;
+                }
+            }
+        });
+    }
+
+    /// Reap stuck jobs, update worker states, send webhooks for build
+    /// sessions that have finished, and close channels to workers that are
+    /// gone, every `REAP_INTERVAL`
+    fn spawn_reaper(&self) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REAP_INTERVAL);
+            loop {
+                interval.tick().await;
+                scheduler.reap_stuck_jobs().await;
+                if !scheduler.mirroring() && scheduler.state.write().await.update_worker_states(clock::now()) > 0 {
+                    scheduler.assign_after(Duration::ZERO);
+                }
+                scheduler.state.write().await.announce_finished_sessions(clock::now());
+                let state = scheduler.state.read().await;
+                scheduler.workers.retain(state.workers.values().map(|worker| worker.address.as_str()));
+            }
+        });
+    }
+
+    /// Forget finished jobs under the retention policy every
+    /// `SWEEP_INTERVAL`, if there is one
+    fn spawn_sweeper(&self) {
+        if self.config.job_retention_secs == 0 && self.config.max_finished_jobs == 0 {
+            return;
+        }
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                scheduler.sweep_finished_jobs().await;
+            }
+        });
+    }
+
+    /// Forget the finished jobs the retention policy no longer keeps,
+    /// archiving them first if configured; none are forgotten if that fails
+    async fn sweep_finished_jobs(&self) {
+        let mut state = self.state.write().await;
+        let expired = state.expired_jobs(self.config.job_retention_secs, self.config.max_finished_jobs, clock::now());
+        if expired.is_empty() {
+            return;
+        }
+
+        let archive = self.store.get().filter(|_| self.config.archive_jobs);
+        if let Some(store) = archive {
+            let jobs: Vec<&JobMetadata> = expired.iter().filter_map(|job_id| state.jobs.get(job_id)).collect();
+            if let Err(e) = store.lock().unwrap().archive(&jobs) {
+                warn!("⚠️  Failed to archive finished jobs; keeping them: {}", e);
+                return;
+            }
+        }
+        for job_id in &expired {
+            state.forget_job(job_id);
+        }
+        info!("🧹 Forgot {} finished job(s){}", expired.len(), if archive.is_some() { " (archived)" } else { "" });
+    }
+
+    /// Fail or requeue dispatched jobs past their deadline, or stalled (no
+    /// sign of life for `PROGRESS_STALL_SECS`), freeing their worker's
+    /// slot and telling it to stop them
+    async fn reap_stuck_jobs(&self) {
+        let now = clock::now();
+        let default_timeout = self.config.job_timeout_secs;
+        let mut state = self.state.write().await;
+        let stuck = state.stuck_jobs(now, default_timeout);
+
+        for (job_id, reason) in stuck {
+            let Some(job) = state.jobs.get_mut(&job_id) else {
+                continue;
+            };
+            let worker_id = job.assigned_worker.clone();
+            let error = format!("{} on {}", reason, worker_id.as_deref().unwrap_or("no worker"));
+            let retry = fail_or_retry(job, &self.config.retry, RetryClass::Timeout, error, now);
+            warn!("⏰ Job {} {}: {}", job_id, if retry.is_some() { "requeued" } else { "failed" }, reason);
+            state.journal_job(&job_id);
+            state.settle_dependents(&job_id, now);
+            if let Some(worker_id) = &worker_id {
+                state.record_outcome(worker_id, Some(RetryClass::Timeout), &self.config.quarantine, now);
+            }
+
+            if let Some(worker) = worker_id.and_then(|id| state.workers.get_mut(&id)) {
+                worker.active_jobs = worker.active_jobs.saturating_sub(1);
+                let (job_id, worker_id, address) = (job_id.clone(), worker.worker_id.clone(), worker.address.clone());
+                let workers = self.workers.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = abort_on_worker(&workers, &job_id, &address).await {
+                        warn!("⚠️  Failed to abort job {} on {}: {}", job_id, worker_id, e);
+                    }
+                });
+            }
+            if let Some(delay) = retry {
+                self.assign_after(delay);
+            }
+        }
+    }
+
+    /// Following another scheduler's state rather than keeping its own
+    fn mirroring(&self) -> bool {
+        self.mirror.lock().unwrap().is_some()
+    }
+
+    /// Keep the mirrored state in step with the primary's journal, until
+    /// taking over from it
+    fn spawn_mirror_follower(&self) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(MIRROR_POLL_INTERVAL).await;
+                let mut state = scheduler.state.write().await;
+                let mut mirror = scheduler.mirror.lock().unwrap();
+                let Some(mirror) = mirror.as_mut() else {
+                    return;
+                };
+                if let Err(e) = mirror.poll(&mut state) {
+                    warn!("⚠️  Failed to follow primary scheduler: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Ping the primary every `STANDBY_PING_INTERVAL`, and take over once
+    /// it hasn't answered for `takeover_after_secs`
+    fn spawn_standby_watch(&self, standby: StandbyConfig) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            let primary = format!("http://{}", standby.primary);
+            let takeover_after = Duration::from_secs(standby.takeover_after_secs);
+            info!("🛟 Standing by for {}; taking over after {:?} without an answer", standby.primary, takeover_after);
+            let mut last_answer = Instant::now();
+            loop {
+                tokio::time::sleep(STANDBY_PING_INTERVAL).await;
+                let ping = async {
+                    let mut client = scheduler.auth.connect_scheduler(primary.clone()).await?;
+                    client.ping(PingRequest {}).await?;
+                    anyhow::Ok(())
+                };
+                match tokio::time::timeout(STANDBY_PING_INTERVAL, ping).await {
+                    Ok(Ok(())) => last_answer = Instant::now(),
+                    Ok(Err(e)) => debug!("Primary {} didn't answer: {}", standby.primary, e),
+                    Err(_) => debug!("Timed out pinging primary {}", standby.primary),
+                }
+                if last_answer.elapsed() < takeover_after {
+                    continue;
+                }
+
+                warn!("⚠️  Primary {} hasn't answered for {:?}; taking over", standby.primary, takeover_after);
+                match scheduler.take_over().await {
+                    Ok(()) => return,
+                    Err(e) => error!("❌ Failed to take over from the primary: {:#}", e),
+                }
+            }
+        });
+    }
+
+    /// Stop following the primary and become it: claim its state dir
+    /// (fencing it out should it come back), load everything it
+    /// journaled, and start scheduling as a restarted primary would.
+    /// Watchers and event subscribers carry on uninterrupted.
+    async fn take_over(&self) -> Result<()> {
+        let dir = self.config.state_dir.as_deref().context("A standby needs the primary's state_dir")?;
+        let dir = Path::new(dir);
+        {
+            let mut state = self.state.write().await;
+            let mut store = StateStore::open(dir)?;
+            store.acquire(&self.lease_holder(), clock::now(), true)?;
+            let mut restored = self.restore(&mut store, dir)?;
+            restored.job_updates = state.job_updates.take();
+            restored.events = state.events.take();
+            *state = restored;
+            *self.mirror.lock().unwrap() = None;
+            if self.store.set(Mutex::new(store)).is_err() {
+                anyhow::bail!("Already took over");
+            }
+        }
+        info!("👑 Took over as primary scheduler");
+        self.start_persistent().await;
+        Ok(())
+    }
+
+    /// Mirrors and standbys only serve reads; writes belong on the primary
+    #[allow(clippy::result_large_err)] // handlers return `Status` as is
+    fn check_writable(&self) -> Result<(), Status> {
+        if self.mirroring() {
+            let role = if self.config.standby.is_some() { "standby" } else { "read-only mirror" };
+            return Err(Status::failed_precondition(format!(
+                "This scheduler is a {}; send changes to the primary",
+                role
+            )));
+        }
+        Ok(())
+    }
+
+    /// Earlier work `req` can reuse: a result cache hit, as long as its
+    /// output is still in the CAS (when this scheduler has one to check),
+    /// or else an identical job in flight. Jobs reading other jobs' outputs
+    /// aren't described by their input hash alone, so never reuse any.
+    fn lookup_reuse(&self, state: &SchedulerState, req: &SubmitJobRequest) -> Option<Reuse> {
+        if !req.depends_on.is_empty() {
+            return None;
+        }
+        let tenant = Some(req.tenant.as_str()).filter(|tenant| !tenant.is_empty());
+        let cached = state
+            .cached_result(tenant, &req.job_type, &req.input_hash)
+            .filter(|(_, output)| self.cas.as_ref().is_none_or(|cas| cas.exists(output)));
+        match cached {
+            Some((job_id, output_hash)) => Some(Reuse::Cached { job_id, output_hash }),
+            None => state.in_flight(tenant, &req.job_type, &req.input_hash).map(Reuse::InFlight),
+        }
+    }
+
+    /// Action-cache entries in `namespace` of this scheduler's CAS using
+    /// blob `hash` (none without a CAS)
+    fn action_refs(&self, namespace: &str, hash: &str) -> Result<Vec<ActionRef>> {
+        let Some(cas) = self.cas.as_deref().filter(|_| is_valid_hash(hash)) else {
+            return Ok(Vec::new());
+        };
+        // Only existing namespaces: a lookup must not create them
+        let cas = if namespace.is_empty() {
+            cas.clone()
+        } else if cas.list_namespaces()?.iter().any(|name| name == namespace) {
+            cas.namespace(namespace)?
+        } else {
+            return Ok(Vec::new());
+        };
+        Ok(cas
+            .action_refs(hash)?
+            .into_iter()
+            .map(|(input_hash, output_hash)| ActionRef { input_hash, output_hash })
+            .collect())
+    }
+
+    /// Cancel `job_id` unless it has already finished, stopping it on its
+    /// worker if dispatched; whether it was cancelled
+    fn cancel(&self, state: &mut SchedulerState, job_id: &str) -> bool {
+        let Some(job) = state.jobs.get_mut(job_id).filter(|job| !job.status.is_terminal()) else {
+            return false;
+        };
+        let dispatched = matches!(job.status, JobStatusEnum::Assigned | JobStatusEnum::Running)
+            .then(|| job.assigned_worker.clone())
+            .flatten();
+        let now = clock::now();
+        job.set_status(JobStatusEnum::Cancelled, now);
+        job.completed_at = Some(now);
+        info!("🚫 Job cancelled: {}", job_id);
+        state.journal_job(job_id);
+        if state.settle_dependents(job_id, now) {
+            self.assign_after(Duration::ZERO);
+        }
+
+        // Stop it on the worker too; it reports back (as failed) once it
+        // has, which frees its slot
+        if let Some(worker) = dispatched.and_then(|id| state.workers.get(&id)) {
+            let (worker_id, address) = (worker.worker_id.clone(), worker.address.clone());
+            let (job_id, workers) = (job_id.to_string(), self.workers.clone());
+            tokio::spawn(async move {
+                if let Err(e) = abort_on_worker(&workers, &job_id, &address).await {
+                    warn!("⚠️  Failed to abort job {} on {}: {}", job_id, worker_id, e);
+                }
+            });
+        }
+        true
+    }
+
+    /// Refuse a job that `[scheduler.validation]` doesn't let in
+    #[allow(clippy::result_large_err)]
+    fn check_job(&self, req: &SubmitJobRequest) -> Result<(), Status> {
+        let policy = &self.config.validation;
+        let rejected = |reason, message: String| Err(DistbuildError::Rejected { reason, message }.into_status());
+        if req.input_hash.len() != 64 || !req.input_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return rejected(Rejection::InvalidHash, format!("input hash {:?} of job {} isn't a SHA-256 hash", req.input_hash, req.job_id));
+        }
+        if !policy.job_types.is_empty() && !policy.job_types.contains(&req.job_type) {
+            return rejected(
+                Rejection::UnknownJobType,
+                format!("job type {:?} of job {} isn't one of {}", req.job_type, req.job_id, policy.job_types.join(", ")),
+            );
+        }
+        if policy.max_input_bytes > 0 && req.input_size > policy.max_input_bytes {
+            return rejected(
+                Rejection::InputTooLarge,
+                format!("input of job {} is {} bytes, over the limit of {}", req.job_id, req.input_size, policy.max_input_bytes),
+            );
+        }
+        if policy.require_input && self.cas.as_ref().is_some_and(|cas| !cas.exists(&req.input_hash)) {
+            return rejected(Rejection::MissingInput, format!("input {} of job {} isn't in the CAS", req.input_hash, req.job_id));
+        }
+        Ok(())
+    }
+
+    /// Refuse a submission that would queue `incoming` more jobs beyond
+    /// `max_pending_jobs`, or more of a tenant's beyond its own limit
+    #[allow(clippy::result_large_err)]
+    fn check_queue_room(&self, state: &SchedulerState, incoming: &HashMap<Option<String>, usize>) -> Result<(), Status> {
+        let max = self.config.max_pending_jobs;
+        let pending = state.index.count(JobStatusEnum::Pending);
+        let total: usize = incoming.values().sum();
+        if max > 0 && pending + total > max {
+            debug!("Queue full ({} pending, limit {}); rejecting {} job(s)", pending, max, total);
+            return Err(DistbuildError::QueueFull { pending, retry_after_secs: QUEUE_FULL_RETRY_SECS }.into_status());
+        }
+
+        for (tenant, &count) in incoming {
+            let Some(name) = tenant else {
+                continue;
+            };
+            let max = self.config.tenants.get(name).map_or(0, |t| t.max_pending_jobs);
+            if max == 0 {
+                continue;
+            }
+            let pending = state
+                .index
+                .with_status(JobStatusEnum::Pending)
+                .filter(|id| state.jobs.get(*id).is_some_and(|job| job.tenant == *tenant))
+                .count();
+            if pending + count > max {
+                debug!("Tenant {}'s queue full ({} pending, limit {}); rejecting {} job(s)", name, pending, max, count);
+                return Err(DistbuildError::QueueFull { pending, retry_after_secs: QUEUE_FULL_RETRY_SECS }.into_status());
+            }
+        }
+        Ok(())
+    }
+
+    /// New jobs go to a writable scheduler that isn't quiesced
+    #[allow(clippy::result_large_err)]
+    fn check_admitting(&self) -> Result<(), Status> {
+        self.check_writable()?;
+        if self.quiesced.load(Ordering::SeqCst) {
+            return Err(Status::unavailable("Scheduler is quiesced for maintenance; not accepting jobs"));
+        }
+        Ok(())
+    }
+
+    /// After a restart, queued jobs are dispatched once workers have had
+    /// this long to check back in (just past the offline timeout)
+    fn resume_grace(&self) -> Duration {
+        Duration::from_secs(self.config.worker_timeout_secs + 1)
+    }
+
+    /// Announce jobs restored in the queue; the assignment loop picks them
+    /// up once workers have had `resume_grace` to check back in
+    async fn resume_queued_jobs(&self) {
+        let queued = self
+            .state
+            .read()
+            .await
+            .jobs
+            .values()
+            .filter(|job| job.status == JobStatusEnum::Pending)
+            .count();
+        if queued == 0 {
+            return;
+        }
+
+        info!("♻️  {} restored job(s) queued; dispatching in {:?}", queued, self.resume_grace());
+    }
+
+    /// Run an assignment pass as each of `run_after` comes due, for jobs
+    /// submitted to run later (heartbeats would get to them, but later)
+    fn wake_when_due(&self, run_after: impl IntoIterator<Item = i64>, now: i64) {
+        let due: BTreeSet<i64> = run_after.into_iter().filter(|&at| at > now).collect();
+        for at in due {
+            self.assign_after(Duration::from_secs((at - now) as u64));
+        }
+    }
+
+    /// Run an assignment pass after `delay`
+    fn assign_after(&self, delay: Duration) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            scheduler.assign_jobs_to_workers().await;
+        });
+    }
+
+    async fn assign_jobs_to_workers(&self) {
+        if self.quiesced.load(Ordering::SeqCst) {
+            return;
+        }
+        let now = clock::now();
+        let mut state = self.state.write().await;
+        
+        // Requeue the jobs of workers that stopped heartbeating
+        state.update_worker_states(now);
+        
+        // Find available workers (healthy and with capacity), in a stable
+        // order so strategies like round-robin see the same list each time
+        let load = state.weighted_load();
+        let mut candidates: Vec<Candidate> = state
+            .workers
+            .iter()
+            .map(|(id, worker)| (id, worker, worker.active_jobs.max(load.get(id.as_str()).copied().unwrap_or(0))))
+            .filter(|(_, worker, active)| !worker.draining && *active < worker.capacity_at(now))
+            .filter(|(_, worker, _)| state.liveness.is_online(worker, now))
+            .map(|(id, worker, active_jobs)| Candidate {
+                health: state.health.report(id, now).score,
+                id: id.clone(),
+                address: worker.address.clone(),
+                active_jobs,
+                capacity: worker.capacity_at(now),
+                labels: worker.labels.clone(),
+                cached_hashes: worker.cached_hashes.clone(),
+                tenant: worker.tenant.clone(),
+                capabilities: worker.capabilities.clone(),
+                pool: worker.pool.clone(),
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.id.cmp(&b.id));
+
+        if candidates.is_empty() {
+            return;
+        }
+
+        // Pending jobs, boosted ones, then highest priority (aged by
+        // waiting), then oldest first, as queued
+        let pending: Vec<String> = state.index.pending().map(str::to_string).collect();
+        let (gangs, held) = state.gangs(&pending, now);
+        let mut running = state.running_by_tenant();
+
+        // Collect assignments to make outside the lock; jobs left over once
+        // every worker is full (or no free one has the labels they need)
+        // wait for the next pass
+        let mut assignments = Vec::new();
+        let mut strategy = self.strategy.lock().unwrap();
+        for job_id in &pending {
+            if candidates.is_empty() {
+                break;
+            }
+            // Skip ones backing off after a failure or not due yet, and
+            // gang members already placed along with the first of them
+            let Some(job) = state.jobs.get(job_id).filter(|job| job.is_due(now) && job.status == JobStatusEnum::Pending) else {
+                continue;
+            };
+            // A gang goes all at once, its pending members placed together
+            let members = match &job.gang {
+                Some(gang) if held.contains(gang) => continue,
+                Some(gang) => gangs[gang].clone(),
+                None => vec![job_id.clone()],
+            };
+            // Skip ones whose tenant has all the jobs running it may
+            let limit = job.tenant.as_ref().and_then(|t| self.config.tenants.get(t)).map_or(0, |t| t.max_running_jobs);
+            if limit > 0 && running.get(&job.tenant).copied().unwrap_or(0) + members.len() > limit {
+                continue;
+            }
+
+            // Try a gang on a copy of the free slots, so one that doesn't
+            // fit whole takes none
+            let mut trial = job.gang.as_ref().map(|_| candidates.clone());
+            let free = trial.as_mut().unwrap_or(&mut candidates);
+            let mut picks = Vec::new();
+            for member in &members {
+                let Some(job) = state.jobs.get(member) else {
+                    break;
+                };
+                let Some(idx) = state.pick_worker(job, free, &mut **strategy) else {
+                    break;
+                };
+                let candidate = &mut free[idx];
+                candidate.active_jobs += job.weight.min(candidate.capacity);
+                picks.push((member.clone(), candidate.id.clone(), candidate.address.clone()));
+                if candidate.active_jobs >= candidate.capacity {
+                    free.remove(idx);
+                }
+            }
+            if picks.len() < members.len() {
+                continue;
+            }
+            if let Some(trial) = trial {
+                if members.len() > 1 {
+                    info!("👥 Dispatching gang of {} jobs with {}", members.len(), job_id);
+                }
+                candidates = trial;
+            }
+
+            for (member, worker_id, worker_addr) in picks {
+                if let Some(job) = state.jobs.get_mut(&member) {
+                    *running.entry(job.tenant.clone()).or_default() += 1;
+                    job.assigned_worker = Some(worker_id.clone());
+                    job.attempts += 1;
+                    job.retry_at = None;
+                    job.set_status(JobStatusEnum::Assigned, now);
+                    let (input_hash, job_type) = (job.input_hash.clone(), job.job_type.clone());
+                    state.journal_job(&member);
+
+                    assignments.push((
+                        member,
+                        input_hash,
+                        job_type,
+                        worker_id.clone(),
+                        worker_addr,
+                    ));
+                }
+                if let Some(worker) = state.workers.get_mut(&worker_id) {
+                    worker.active_jobs += 1;
+                }
+            }
+        }
+        drop(strategy);
+        
+        // Drop lock before async operations
+        drop(state);
+        
+        // Execute jobs on workers
+        for (job_id, input_hash, job_type, worker_id, worker_addr) in assignments {
+            let self_clone = self.clone();
+            
+            tokio::spawn(async move {
+                if let Err(e) = self_clone.dispatch_job_to_worker(
+                    &job_id,
+                    &input_hash,
+                    &job_type,
+                    &worker_id,
+                    &worker_addr,
+                ).await {
+                    error!("❌ Failed to dispatch job {} to {}: {}", job_id, worker_id, e);
+                    
+                    // Try another worker, or once it has failed to reach
+                    // enough of them retry or fail it, unless it was
+                    // cancelled meanwhile
+                    let mut guard = self_clone.state.write().await;
+                    let state = &mut *guard;
+                    if let Some(job) = state.jobs.get_mut(&job_id).filter(|job| !job.status.is_terminal()) {
+                        let error = format!("Dispatch to {} failed: {}", worker_id, e);
+                        let now = clock::now();
+                        let failed = state.dispatch_failures.entry(job_id.clone()).or_default();
+                        failed.push(worker_id.clone());
+                        let retry = if failed.len() < self_clone.config.retry.dispatch_attempts as usize {
+                            warn!("🔁 Job {} couldn't be dispatched to {}; reassigning", job_id, worker_id);
+                            job.error = Some(error);
+                            job.assigned_worker = None;
+                            job.progress = None;
+                            job.set_status(JobStatusEnum::Pending, now);
+                            Some(Duration::ZERO)
+                        } else {
+                            fail_or_retry(job, &self_clone.config.retry, RetryClass::Dispatch, error, now)
+                        };
+                        state.journal_job(&job_id);
+                        state.settle_dependents(&job_id, now);
+                        state.record_outcome(&worker_id, Some(RetryClass::Dispatch), &self_clone.config.quarantine, now);
+                        if let Some(delay) = retry {
+                            self_clone.assign_after(delay);
+                        }
+                    }
+                    if let Some(worker) = state.workers.get_mut(&worker_id) {
+                        worker.active_jobs = worker.active_jobs.saturating_sub(1);
+                    }
+                }
+            });
+        }
+    }
+    
+    async fn dispatch_job_to_worker(
+        &self,
+        job_id: &str,
+        input_hash: &str,
+        job_type: &str,
+        worker_id: &str,
+        worker_addr: &str,
+    ) -> Result<()> {
+        info!("📤 Dispatching job {} to worker {} at {}", job_id, worker_id, worker_addr);
+        
+        // Update job status to RUNNING
+        let metadata = {
+            let mut state = self.state.write().await;
+            match state.jobs.get_mut(job_id) {
+                Some(job) if job.status == JobStatusEnum::Assigned => {
+                    let now = clock::now();
+                    job.set_status(JobStatusEnum::Running, now);
+                    job.started_at = Some(now);
+                    let metadata = job.metadata.clone();
+                    state.journal_job(job_id);
+                    metadata
+                }
+                // Cancelled before it could be sent
+                _ => return Ok(()),
+            }
+        };
+        
+        // Execute the job over the worker's open channel
+        let mut client = self.workers.client(worker_addr)?;
+        
+        let request = ExecuteJobRequest {
+            job_id: job_id.to_string(),
+            input_hash: input_hash.to_string(),
+            job_type: job_type.to_string(),
+            metadata,
+        };
+        
+        let started = Instant::now();
+        self.workers.check(worker_addr, client.execute_job(request).await)?;
+        self.state.write().await.health.record_dispatch(worker_id, started.elapsed());
+        
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl Scheduler for SchedulerService {
+    async fn register_worker(
+        &self,
+        request: Request<RegisterWorkerRequest>,
+    ) -> Result<Response<RegisterWorkerResponse>, Status> {
+        self.check_writable()?;
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let worker_id = req.worker_id.clone();
+        let tenant = scope.tenant_for(&req.tenant)?;
+        let version = check_version(&format!("Worker {}", worker_id), req.version)
+            .map_err(Status::failed_precondition)?;
+        match &version {
+            Some(version) if BuildVersion::current().differs(version) => {
+                warn!("⚠️  Worker {} runs {} but the scheduler runs {}", worker_id, version, BuildVersion::current());
+            }
+            None => warn!("⚠️  Worker {} doesn't report its version", worker_id),
+            _ => {}
+        }
+
+        let worker = WorkerMetadata {
+            worker_id: worker_id.clone(),
+            address: req.address,
+            capacity: req.capacity,
+            active_jobs: 0,
+            last_heartbeat: clock::now(),
+            labels: req.labels,
+            version,
+            draining: false,
+            quarantined_until: None,
+            tenant,
+            capabilities: req.capabilities.map(Into::into),
+            pool: Some(req.pool).filter(|pool| !pool.is_empty()),
+            state: WorkerStateEnum::Online,
+            state_since: clock::now(),
+            cached_hashes: Arc::default(),
+        };
+
+        let mut state = self.state.write().await;
+        if state.workers.get(&worker_id).is_some_and(|w| !scope.owns_worker(w)) {
+            return Err(Status::permission_denied(format!("Worker {} belongs to another tenant", worker_id)));
+        }
+        // Restarting mid-drain or in quarantine doesn't put it back in rotation
+        let (draining, quarantined_until) = state
+            .workers
+            .get(&worker_id)
+            .map_or((false, None), |w| (w.draining, w.quarantined_until));
+        let lifecycle = if draining { WorkerStateEnum::Draining } else { WorkerStateEnum::Online };
+        let worker = WorkerMetadata { draining, quarantined_until, state: lifecycle, ..worker };
+        state.journal(Event::WorkerRegistered { worker: Box::new(worker.clone()) });
+        state.emit(ClusterEventKind::WorkerOnline, worker.tenant.as_deref(), &worker_id, "", &worker.address);
+        state.workers.insert(worker_id.clone(), worker);
+        drop(state);
+        self.assign_after(Duration::ZERO);
+
+        info!("✅ Worker registered: {}", worker_id);
+
+        Ok(Response::new(RegisterWorkerResponse {
+            success: true,
+            message: format!("Worker {} registered successfully", worker_id),
+            heartbeat_interval_secs: self.config.heartbeat_interval_secs.unwrap_or(0) as u32,
+        }))
+    }
+
+    async fn heartbeat(
+        &self,
+        request: Request<HeartbeatRequest>,
+    ) -> Result<Response<HeartbeatResponse>, Status> {
+        self.check_writable()?;
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let worker_id = req.worker_id.clone();
+
+        let mut state = self.state.write().await;
+        
+        let now = clock::now();
+        let worker = state.workers.get_mut(&worker_id).filter(|worker| scope.owns_worker(worker));
+        let has_room = if let Some(worker) = worker {
+            worker.last_heartbeat = now;
+            worker.active_jobs = req.active_jobs;
+            worker.cached_hashes = Arc::new(req.cached_hashes.into_iter().collect());
+            if let Some(version) = req.version {
+                worker.version = Some(version.into());
+            }
+            worker.active_jobs < worker.capacity_at(now)
+        } else {
+            return Err(Status::not_found(format!("Worker {} not found", worker_id)));
+        };
+        state.health.record_heartbeat(&worker_id, now);
+
+        // Live workers' heartbeats are how dead ones get noticed when no
+        // jobs are being submitted
+        let requeued = state.update_worker_states(now) > 0;
+        if requeued || (has_room && state.has_runnable_jobs(now)) {
+            self.assign_after(Duration::ZERO);
+        }
+
+        Ok(Response::new(HeartbeatResponse {
+            success: true,
+            jobs_to_execute: vec![], // No longer used - scheduler calls ExecuteJob directly
+            heartbeat_interval_secs: self.config.heartbeat_interval_secs.unwrap_or(0) as u32,
+        }))
+    }
+
+    async fn report_job_progress(
+        &self,
+        request: Request<ReportJobProgressRequest>,
+    ) -> Result<Response<ReportJobProgressResponse>, Status> {
+        self.check_writable()?;
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let now = clock::now();
+
+        let mut state = self.state.write().await;
+        // A progress report is as good as a heartbeat for the worker
+        if let Some(worker) = state.workers.get_mut(&req.worker_id).filter(|worker| scope.owns_worker(worker)) {
+            worker.last_heartbeat = worker.last_heartbeat.max(now);
+        }
+        let job = state.jobs.get_mut(&req.job_id).filter(|job| {
+            scope.sees_job(job)
+                && job.status == JobStatusEnum::Running
+                && job.assigned_worker.as_deref() == Some(req.worker_id.as_str())
+        });
+        let Some(job) = job else {
+            debug!("Progress for job {} from {} ignored: not running there", req.job_id, req.worker_id);
+            return Ok(Response::new(ReportJobProgressResponse { keep_running: false }));
+        };
+
+        let pid = Some(req.pid).filter(|pid| *pid != 0);
+        if pid.is_some() && !req.process_alive {
+            warn!("⚠️  Compiler for job {} on {} died after {}s", req.job_id, req.worker_id, req.elapsed_secs);
+        }
+        job.progress = Some(JobProgress {
+            at: now,
+            phase: req.phase,
+            elapsed_secs: req.elapsed_secs,
+            pid,
+            process_alive: req.process_alive,
+        });
+
+        Ok(Response::new(ReportJobProgressResponse { keep_running: true }))
+    }
+
+    async fn submit_job(
+        &self,
+        request: Request<SubmitJobRequest>,
+    ) -> Result<Response<SubmitJobResponse>, Status> {
+        self.check_admitting()?;
+        let scope = Scope::of(&request);
+        let mut req = request.into_inner();
+        let job_id = req.job_id.clone();
+        check_version("Client", req.client_version.clone()).map_err(Status::failed_precondition)?;
+        self.check_job(&req)?;
+        let tenant = scope.tenant_for(&req.tenant)?;
+        req.tenant = tenant.clone().unwrap_or_default();
+
+        let mut state = self.state.write().await;
+        if let Some(unknown) = req.depends_on.iter().find(|dep| state.job_in(&scope, dep).is_err()) {
+            return Err(Status::invalid_argument(format!("Dependency {} not found", unknown)));
+        }
+        let reuse = self.lookup_reuse(&state, &req);
+        // Taking over another job's result adds nothing to the queue
+        if reuse.is_none() {
+            self.check_queue_room(&state, &HashMap::from([(tenant, 1)]))?;
+        }
+        let (output_hash, attached_to) = reuse_summary(&reuse);
+        state.audit(&scope, "submit-job", &job_id, format!("{} on {}, priority {}", req.job_type, req.input_hash, req.priority));
+        let (now, run_after) = (clock::now(), req.run_after);
+        state.admit(req, now, reuse, None);
+
+        // Drop the lock before async work
+        drop(state);
+        self.wake_when_due([run_after], now);
+
+        // Try to assign jobs
+        self.assign_jobs_to_workers().await;
+
+        let message = if !output_hash.is_empty() {
+            "Job completed from the result cache".to_string()
+        } else if !attached_to.is_empty() {
+            format!("Job attached to identical job {}", attached_to)
+        } else {
+            "Job submitted successfully".to_string()
+        };
+        Ok(Response::new(SubmitJobResponse {
+            success: true,
+            job_id,
+            message,
+            output_hash,
+            attached_to,
+        }))
+    }
+
+    async fn submit_jobs(
+        &self,
+        request: Request<SubmitJobsRequest>,
+    ) -> Result<Response<SubmitJobsResponse>, Status> {
+        self.check_admitting()?;
+        let scope = Scope::of(&request);
+        let mut req = request.into_inner();
+
+        let mut state = self.state.write().await;
+        // Check the whole batch first so a bad entry doesn't leave half a plan queued
+        let mut batch = HashSet::new();
+        let mut incoming: HashMap<Option<String>, usize> = HashMap::new();
+        for job in &mut req.jobs {
+            check_version("Client", job.client_version.clone()).map_err(Status::failed_precondition)?;
+            self.check_job(job)?;
+            let tenant = scope.tenant_for(&job.tenant)?;
+            job.tenant = tenant.clone().unwrap_or_default();
+            *incoming.entry(tenant).or_default() += 1;
+            let known = |dep: &String| state.job_in(&scope, dep).is_ok() || batch.contains(dep.as_str());
+            if let Some(unknown) = job.depends_on.iter().find(|dep| !known(dep)) {
+                return Err(Status::invalid_argument(format!(
+                    "Dependency {} of job {} not found (it must be submitted before its dependents)",
+                    unknown, job.job_id
+                )));
+            }
+            if req.gang {
+                if let Some(dep) = job.depends_on.iter().find(|dep| batch.contains(dep.as_str())) {
+                    return Err(Status::invalid_argument(format!(
+                        "Job {} depends on {}, so they can't run as a gang",
+                        job.job_id, dep
+                    )));
+                }
+            }
+            if !batch.insert(job.job_id.as_str()) {
+                return Err(Status::invalid_argument(format!("Job {} appears twice", job.job_id)));
+            }
+        }
+        self.check_queue_room(&state, &incoming)?;
+
+        let now = clock::now();
+        let count = req.jobs.len();
+        let gang = req.gang.then(|| uuid::Uuid::new_v4().to_string());
+        let kind = if gang.is_some() { "gang" } else { "batch" };
+        self.wake_when_due(req.jobs.iter().map(|job| job.run_after), now);
+        let results = req
+            .jobs
+            .into_iter()
+            .map(|job| {
+                let job_id = job.job_id.clone();
+                let reuse = self.lookup_reuse(&state, &job);
+                let (output_hash, attached_to) = reuse_summary(&reuse);
+                let detail = format!("{} on {}, priority {}, {} of {}", job.job_type, job.input_hash, job.priority, kind, count);
+                state.audit(&scope, "submit-job", &job_id, detail);
+                let status = state.admit(job, now, reuse, gang.clone());
+                SubmitJobResponse {
+                    success: true,
+                    job_id,
+                    message: format!("Job submitted ({})", status),
+                    output_hash,
+                    attached_to,
+                }
+            })
+            .collect();
+        info!("📋 Submitted a {} of {} jobs", kind, count);
+        drop(state);
+
+        self.assign_jobs_to_workers().await;
+
+        Ok(Response::new(SubmitJobsResponse { results }))
+    }
+
+    async fn get_job_status(
+        &self,
+        request: Request<GetJobStatusRequest>,
+    ) -> Result<Response<GetJobStatusResponse>, Status> {
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+
+        let state = self.state.read().await;
+        let job = state.job_in(&scope, &req.job_id)?;
+        Ok(Response::new(status_response(job, state.durations.eta(job, clock::now()))))
+    }
+
+    type WatchJobStream = Pin<Box<dyn Stream<Item = Result<GetJobStatusResponse, Status>> + Send>>;
+
+    async fn watch_job(
+        &self,
+        request: Request<WatchJobRequest>,
+    ) -> Result<Response<Self::WatchJobStream>, Status> {
+        let scope = Scope::of(&request);
+        let job_id = request.into_inner().job_id;
+        let mut state = self.state.write().await;
+        let job = state.job_in(&scope, &job_id)?;
+        let current = status_response(job, state.durations.eta(job, clock::now()));
+        let updates = state.watch_jobs();
+        drop(state);
+
+        // The current status, then each different one until it's final
+        let watch = JobWatch {
+            job_id,
+            state: self.state.clone(),
+            updates,
+            next: Some(current),
+            last_status: None,
+        };
+        let stream = futures::stream::unfold(watch, |mut watch| async move {
+            let update = watch.next_update().await?;
+            Some((Ok(update), watch))
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    type SubscribeEventsStream = Pin<Box<dyn Stream<Item = Result<ClusterEvent, Status>> + Send>>;
+
+    async fn subscribe_events(
+        &self,
+        request: Request<SubscribeEventsRequest>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        let scope = Scope::of(&request);
+        let kinds: HashSet<i32> = request.into_inner().kinds.into_iter().collect();
+        let events = self.state.write().await.subscribe_events();
+
+        let stream = futures::stream::unfold(events, move |mut events| {
+            let (kinds, scope) = (kinds.clone(), scope.clone());
+            async move {
+                loop {
+                    match events.recv().await {
+                        Ok(event) if !scope.sees_event(&event) => continue,
+                        Ok(event) if kinds.is_empty() || kinds.contains(&event.kind) => return Some((Ok(event), events)),
+                        Ok(_) => continue,
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            debug!("Event subscriber fell behind; {} events dropped", missed);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn inspect_job(
+        &self,
+        request: Request<InspectJobRequest>,
+    ) -> Result<Response<InspectJobResponse>, Status> {
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let state = self.state.read().await;
+
+        let job = state.job_in(&scope, &req.job_id)?;
+        let worker = job
+            .assigned_worker
+            .as_ref()
+            .and_then(|id| state.workers.get(id))
+            .map(|worker| worker_info(worker, state.health.report(&worker.worker_id, clock::now())));
+
+        Ok(Response::new(InspectJobResponse {
+            job: Some(job_info(job)),
+            job_type: job.job_type.clone(),
+            metadata: job.metadata.clone(),
+            timeline: job
+                .timeline
+                .iter()
+                .map(|t| JobTransition {
+                    status: t.status.into(),
+                    at: t.at,
+                    worker: t.worker.clone().unwrap_or_default(),
+                })
+                .collect(),
+            started_at: job.started_at.unwrap_or(0),
+            worker,
+            worker_platform: job.worker_platform.clone().map(Into::into),
+            error_kind: job.error_kind.into(),
+            progress: job.progress.clone().map(Into::into),
+            stalled: job.is_stalled(clock::now(), PROGRESS_STALL_SECS),
+            attempts: job.attempts,
+            retry_at: job.retry_at.unwrap_or(0),
+            depends_on: job.depends_on.clone(),
+            constraints: job.constraints.clone(),
+            deadline: job.deadline(self.config.job_timeout_secs).unwrap_or(0),
+            timeout_secs: job.timeout_secs.unwrap_or(0),
+            attached_to: job.attached_to.clone().unwrap_or_default(),
+            attempt_history: job.attempt_history.iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn get_job_logs(
+        &self,
+        request: Request<GetJobLogsRequest>,
+    ) -> Result<Response<GetJobLogsResponse>, Status> {
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let (log_hash, namespace) = {
+            let state = self.state.read().await;
+            let job = state.job_in(&scope, &req.job_id)?;
+            (job.log_hash.clone().unwrap_or_default(), job.metadata.get("namespace").cloned())
+        };
+
+        // Read from the job's namespace, where its worker stored it
+        let mut log = String::new();
+        if let Some(cas) = self.cas.as_ref().filter(|_| !log_hash.is_empty()) {
+            let data = match namespace.filter(|ns| !ns.is_empty()) {
+                Some(ns) => cas.namespace(&ns).and_then(|cas| cas.get(&log_hash)),
+                None => cas.get(&log_hash),
+            };
+            match data {
+                Ok(data) => log = String::from_utf8_lossy(&data).into_owned(),
+                Err(e) => warn!("⚠️  Failed to read log {} of job {}: {:#}", log_hash, req.job_id, e),
+            }
+        }
+        if req.tail_lines > 0 {
+            let lines: Vec<&str> = log.lines().collect();
+            log = lines[lines.len().saturating_sub(req.tail_lines as usize)..].join("\n");
+        }
+
+        Ok(Response::new(GetJobLogsResponse { log_hash, log }))
+    }
+
+    async fn cancel_job(
+        &self,
+        request: Request<CancelJobRequest>,
+    ) -> Result<Response<CancelJobResponse>, Status> {
+        self.check_writable()?;
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let mut state = self.state.write().await;
+
+        let status = state.job_in(&scope, &req.job_id)?.status;
+        let cancelled = self.cancel(&mut state, &req.job_id);
+        if cancelled {
+            state.audit(&scope, "cancel-job", &req.job_id, format!("was {}", status));
+        }
+        let status = if cancelled { JobStatusEnum::Cancelled } else { status };
+
+        Ok(Response::new(CancelJobResponse {
+            cancelled,
+            status: status.into(),
+        }))
+    }
+
+    async fn list_sessions(
+        &self,
+        request: Request<ListSessionsRequest>,
+    ) -> Result<Response<ListSessionsResponse>, Status> {
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let state = self.state.read().await;
+
+        let mut sessions: Vec<SessionInfo> = state
+            .sessions(&scope)
+            .into_iter()
+            .map(|(session_id, jobs)| session_info(session_id, &jobs))
+            .filter(|session| !req.active_only || session.finished_at == 0)
+            .collect();
+        sessions.sort_by(|a, b| b.started_at.cmp(&a.started_at).then_with(|| a.session_id.cmp(&b.session_id)));
+        if req.limit > 0 {
+            sessions.truncate(req.limit as usize);
+        }
+
+        Ok(Response::new(ListSessionsResponse { sessions }))
+    }
+
+    async fn get_session(
+        &self,
+        request: Request<GetSessionRequest>,
+    ) -> Result<Response<GetSessionResponse>, Status> {
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let state = self.state.read().await;
+
+        let mut jobs = state.sessions(&scope).remove(req.session_id.as_str()).unwrap_or_default();
+        if jobs.is_empty() {
+            return Err(Status::not_found(format!("Session {} not found", req.session_id)));
+        }
+        jobs.sort_by(|a, b| (a.submitted_at, &a.job_id).cmp(&(b.submitted_at, &b.job_id)));
+
+        Ok(Response::new(GetSessionResponse {
+            session: Some(session_info(&req.session_id, &jobs)),
+            jobs: jobs.into_iter().map(job_info).collect(),
+        }))
+    }
+
+    async fn cancel_session(
+        &self,
+        request: Request<CancelSessionRequest>,
+    ) -> Result<Response<CancelSessionResponse>, Status> {
+        self.check_writable()?;
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let mut state = self.state.write().await;
+
+        let Some(jobs) = state.sessions(&scope).remove(req.session_id.as_str()) else {
+            return Err(Status::not_found(format!("Session {} not found", req.session_id)));
+        };
+        let unfinished: Vec<String> = jobs
+            .into_iter()
+            .filter(|job| !job.status.is_terminal())
+            .map(|job| job.job_id.clone())
+            .collect();
+        let mut cancelled = 0;
+        for job_id in &unfinished {
+            if self.cancel(&mut state, job_id) {
+                cancelled += 1;
+            }
+        }
+        if cancelled > 0 {
+            info!("🚫 Session {} cancelled ({} jobs)", req.session_id, cancelled);
+        }
+        state.audit(&scope, "cancel-session", &req.session_id, format!("{} job(s) cancelled", cancelled));
+
+        Ok(Response::new(CancelSessionResponse { cancelled }))
+    }
+
+    async fn update_job_priority(
+        &self,
+        request: Request<UpdateJobPriorityRequest>,
+    ) -> Result<Response<UpdateJobPriorityResponse>, Status> {
+        self.check_writable()?;
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let mut state = self.state.write().await;
+
+        let job = state.job_in_mut(&scope, &req.job_id)?;
+        let updated = job.status == JobStatusEnum::Pending;
+        if updated && job.priority != req.priority {
+            debug!("↕️  Job {} priority {} -> {}", req.job_id, job.priority, req.priority);
+            let detail = format!("{} -> {}", job.priority, req.priority);
+            job.priority = req.priority;
+            state.journal_job(&req.job_id);
+            state.audit(&scope, "update-priority", &req.job_id, detail);
+        }
+
+        Ok(Response::new(UpdateJobPriorityResponse { updated }))
+    }
+
+    async fn boost_job(
+        &self,
+        request: Request<BoostJobRequest>,
+    ) -> Result<Response<BoostJobResponse>, Status> {
+        self.check_writable()?;
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let mut state = self.state.write().await;
+
+        // Waiting on a job attached to an identical one is waiting on that one
+        let job = state.job_in_mut(&scope, &req.job_id)?;
+        let job_id = job.attached_to.clone().unwrap_or(req.job_id);
+        let Some(job) = state.jobs.get_mut(&job_id) else {
+            return Ok(Response::new(BoostJobResponse { boosted: false }));
+        };
+        let boosted = matches!(job.status, JobStatusEnum::Pending | JobStatusEnum::Blocked);
+        if boosted && !job.boosted {
+            debug!("🚀 Job {} boosted", job_id);
+            job.boosted = true;
+            state.journal_job(&job_id);
+            state.audit(&scope, "boost-job", &job_id, String::new());
+        }
+
+        Ok(Response::new(BoostJobResponse { boosted }))
+    }
+
+    async fn ping(
+        &self,
+        _request: Request<PingRequest>,
+    ) -> Result<Response<PingResponse>, Status> {
+        Ok(Response::new(PingResponse {
+            server_time_ms: clock::now_ms(),
+        }))
+    }
+
+    async fn get_capabilities(
+        &self,
+        _request: Request<GetCapabilitiesRequest>,
+    ) -> Result<Response<GetCapabilitiesResponse>, Status> {
+        Ok(Response::new(GetCapabilitiesResponse {
+            version: Some(BuildVersion::current().into()),
+        }))
+    }
+
+    async fn quiesce(
+        &self,
+        request: Request<QuiesceRequest>,
+    ) -> Result<Response<QuiesceResponse>, Status> {
+        self.check_writable()?;
+        let scope = Scope::of(&request);
+        scope.require_operator("quiesce the scheduler")?;
+        let req = request.into_inner();
+        let detail = format!("drain: {}, restart: {}", req.drain, req.restart);
+        self.state.write().await.audit(&scope, "quiesce", "", detail);
+        if !self.quiesced.swap(true, Ordering::SeqCst) {
+            info!("⏸️  Quiesced: no longer admitting or dispatching jobs");
+        }
+
+        // Drain: running jobs finish and report back as usual
+        let deadline = std::time::Instant::now() + Duration::from_secs(req.timeout_secs.into());
+        let (mut running, mut queued) = self.job_counts().await;
+        while req.drain && running > 0 && std::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            (running, queued) = self.job_counts().await;
+        }
+
+        let persisted = self
+            .persist_now()
+            .await
+            .map_err(|e| Status::internal(format!("Failed to save scheduler state: {}", e)))?;
+
+        // Without a state dir a restart loses whatever is still tracked
+        let ready = !req.drain || running == 0;
+        let restart_safe = persisted || (running == 0 && queued == 0);
+        let restarting = req.restart && ready && restart_safe;
+        let message = if req.restart && !restart_safe {
+            format!("Not restarting: {} job(s) would be lost without a state_dir", running + queued)
+        } else if !ready {
+            format!("Timed out with {} job(s) still running", running)
+        } else if restarting {
+            "Exiting for restart".to_string()
+        } else {
+            "Quiesced; ready for maintenance".to_string()
+        };
+        info!("⏸️  {} ({} running, {} queued)", message, running, queued);
+
+        if restarting {
+            self.notify_workers_stopping(persisted).await;
+            self.shutdown.notify_one();
+        }
+        Ok(Response::new(QuiesceResponse {
+            ready,
+            running,
+            queued,
+            persisted,
+            restarting,
+            message,
+        }))
+    }
+
+    async fn resume(
+        &self,
+        request: Request<ResumeRequest>,
+    ) -> Result<Response<ResumeResponse>, Status> {
+        self.check_writable()?;
+        let scope = Scope::of(&request);
+        scope.require_operator("resume the scheduler")?;
+        let resumed = self.quiesced.swap(false, Ordering::SeqCst);
+        if resumed {
+            self.state.write().await.audit(&scope, "resume", "", String::new());
+        }
+        if resumed {
+            info!("▶️  Resumed: admitting and dispatching jobs again");
+            self.assign_after(Duration::ZERO);
+        }
+        Ok(Response::new(ResumeResponse { resumed }))
+    }
+
+    async fn query_audit(
+        &self,
+        request: Request<QueryAuditRequest>,
+    ) -> Result<Response<QueryAuditResponse>, Status> {
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let now = clock::now();
+        let mut since = req.since;
+        if req.within_secs > 0 {
+            since = since.max(now - req.within_secs as i64);
+        }
+        let until = if req.until > 0 { req.until } else { i64::MAX };
+
+        // Tenants only see what was done under their own tenant
+        let state = self.state.read().await;
+        let records = state
+            .audit
+            .query(
+                |record| {
+                    (scope.tenant.is_none() || record.tenant == scope.tenant)
+                        && record.at >= since
+                        && record.at < until
+                        && (req.actor.is_empty() || record.actor == req.actor)
+                        && (req.action.is_empty() || record.action == req.action)
+                        && (req.target.is_empty() || record.target == req.target)
+                },
+                req.limit as usize,
+            )
+            .map_err(|e| Status::internal(format!("Failed to read the audit trail: {:#}", e)))?;
+
+        let entries = records
+            .into_iter()
+            .map(|record| AuditEntry {
+                at: record.at,
+                actor: record.actor,
+                tenant: record.tenant.unwrap_or_default(),
+                action: record.action,
+                target: record.target,
+                detail: record.detail,
+            })
+            .collect();
+        Ok(Response::new(QueryAuditResponse { entries }))
+    }
+
+    async fn search_job_history(
+        &self,
+        request: Request<SearchJobHistoryRequest>,
+    ) -> Result<Response<SearchJobHistoryResponse>, Status> {
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let now = clock::now();
+        let mut since = req.since;
+        if req.within_secs > 0 {
+            since = since.max(now - req.within_secs as i64);
+        }
+        let mut statuses = Vec::new();
+        for &status in &req.status {
+            statuses.push(JobStatusEnum::try_from(status).map_err(|_| Status::invalid_argument(format!("Unknown job status {}", status)))?);
+        }
+        let query = HistoryQuery {
+            crate_name: Some(req.crate_name).filter(|name| !name.is_empty()),
+            statuses,
+            worker: Some(req.worker).filter(|worker| !worker.is_empty()),
+            // Tenants only see their own jobs
+            tenant: scope.tenant_for(&req.tenant)?,
+            since,
+            until: if req.until > 0 { req.until } else { i64::MAX },
+            limit: req.limit as usize,
+        };
+
+        let state = self.state.read().await;
+        let entries = state
+            .history
+            .search(&query)
+            .into_iter()
+            .map(|record| JobHistoryEntry {
+                job_id: record.job_id.clone(),
+                job_type: record.job_type.clone(),
+                crate_name: record.crate_name.clone().unwrap_or_default(),
+                status: record.status.into(),
+                worker: record.worker.clone().unwrap_or_default(),
+                tenant: record.tenant.clone().unwrap_or_default(),
+                session_id: record.session_id.clone().unwrap_or_default(),
+                input_hash: record.input_hash.clone(),
+                output_hash: record.output_hash.clone().unwrap_or_default(),
+                error: record.error.clone().unwrap_or_default(),
+                submitted_at: record.submitted_at,
+                finished_at: record.finished_at,
+                exec_millis: record.exec_millis,
             })
             .collect();
+        Ok(Response::new(SearchJobHistoryResponse { entries }))
+    }
+
+    async fn list_workers(
+        &self,
+        request: Request<ListWorkersRequest>,
+    ) -> Result<Response<ListWorkersResponse>, Status> {
+        let scope = Scope::of(&request);
+        let now = clock::now();
+        let mut state = self.state.write().await;
+        
+        // Bring worker states up to date, requeueing offline workers' jobs;
+        // a mirror sees no heartbeats and leaves that to the primary's journal
+        if !self.mirroring() && state.update_worker_states(now) > 0 {
+            self.assign_after(Duration::ZERO);
+        }
+        
+        let workers = state
+            .workers
+            .values()
+            .filter(|worker| scope.sees_worker(worker))
+            .map(|worker| worker_info(worker, state.health.report(&worker.worker_id, now)))
+            .collect();
 
-        // Sort by submission time (newest first)
-        jobs.sort_by(|a, b| b.submitted_at.cmp(&a.submitted_at));
+        Ok(Response::new(ListWorkersResponse { workers }))
+    }
 
-        // Apply limit
-        if req.limit > 0 {
+    async fn drain_worker(
+        &self,
+        request: Request<DrainWorkerRequest>,
+    ) -> Result<Response<DrainWorkerResponse>, Status> {
+        self.check_writable()?;
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let worker_id = req.worker_id;
+
+        let mut state = self.state.write().await;
+        let worker = state
+            .workers
+            .get_mut(&worker_id)
+            .filter(|worker| scope.sees_worker(worker))
+            .ok_or_else(|| Status::not_found(format!("Worker {} not found", worker_id)))?;
+        if !scope.owns_worker(worker) {
+            return Err(Status::permission_denied(format!("Worker {} is shared; only operators can drain it", worker_id)));
+        }
+        if !worker.draining {
+            worker.draining = true;
+            if worker.state == WorkerStateEnum::Online {
+                worker.state = WorkerStateEnum::Draining;
+                worker.state_since = clock::now();
+            }
+            let worker = worker.clone();
+            state.journal(Event::WorkerRegistered { worker: Box::new(worker) });
+            info!("🚧 Draining worker {}: no new jobs", worker_id);
+        }
+        state.audit(&scope, "drain-worker", &worker_id, format!("timeout {}s", req.timeout_secs));
+        drop(state);
+
+        // Its jobs finish and report back as usual
+        let deadline = std::time::Instant::now() + Duration::from_secs(req.timeout_secs.into());
+        let mut remaining = self.jobs_on(&worker_id).await;
+        while remaining > 0 && std::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            remaining = self.jobs_on(&worker_id).await;
+        }
+
+        let mut state = self.state.write().await;
+        // Anything assigned since the last look is queued again elsewhere
+        let removed = remaining == 0 && state.workers.contains_key(&worker_id);
+        let message = if removed {
+            state.remove_worker(&worker_id, "drained", clock::now());
+            info!("🚧 Worker {} drained and removed", worker_id);
+            format!("Worker {} drained and removed", worker_id)
+        } else if remaining > 0 {
+            format!("Timed out with {} job(s) still on {}; it stays draining", remaining, worker_id)
+        } else {
+            format!("Worker {} went away while draining", worker_id)
+        };
+
+        Ok(Response::new(DrainWorkerResponse {
+            removed,
+            remaining,
+            message,
+        }))
+    }
+
+    async fn list_jobs(
+        &self,
+        request: Request<ListJobsRequest>,
+    ) -> Result<Response<ListJobsResponse>, Status> {
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let after = match req.cursor.as_str() {
+            "" => None,
+            cursor => Some(parse_cursor(cursor).ok_or_else(|| Status::invalid_argument(format!("Invalid cursor {:?}", cursor)))?),
+        };
+        let state = self.state.read().await;
+
+        // A relative window is measured on this clock, immune to client skew
+        let now = clock::now();
+        let mut since = req.since;
+        if req.within_secs > 0 {
+            since = since.max(now - req.within_secs as i64);
+        }
+        let mut until = if req.until > 0 { req.until } else { i64::MAX };
+        if req.older_than_secs > 0 {
+            until = until.min(now - req.older_than_secs as i64);
+        }
+
+        let mut jobs: Vec<JobInfo> = state
+            .jobs
+            .values()
+            .filter(|j| scope.sees_job(j))
+            .filter(|j| req.tenant.is_empty() || j.tenant.as_deref() == Some(req.tenant.as_str()))
+            .filter(|j| req.session_id.is_empty() || j.session_id.as_deref() == Some(req.session_id.as_str()))
+            .filter(|j| j.submitted_at >= since && j.submitted_at < until)
+            .filter(|j| req.status.is_empty() || req.status.contains(&j.status.into()))
+            .filter(|j| req.worker.is_empty() || j.assigned_worker.as_deref() == Some(req.worker.as_str()))
+            .filter(|j| req.job_type.is_empty() || j.job_type == req.job_type)
+            .filter(|j| req.grep.is_empty() || j.matches_text(&req.grep))
+            .filter(|j| after.as_ref().is_none_or(|(at, id)| page_order(j.submitted_at, &j.job_id) > page_order(*at, id)))
+            .map(job_info)
+            .collect();
+
+        // Newest first, in an order pages can pick up from
+        jobs.sort_by(|a, b| page_order(a.submitted_at, &a.job_id).cmp(&page_order(b.submitted_at, &b.job_id)));
+
+        // Apply limit, pointing at the rest
+        let mut next_cursor = String::new();
+        if req.limit > 0 && jobs.len() > req.limit as usize {
             jobs.truncate(req.limit as usize);
+            if let Some(last) = jobs.last() {
+                next_cursor = format!("{}:{}", last.submitted_at, last.job_id);
+            }
         }
 
-        Ok(Response::new(ListJobsResponse { jobs }))
+        Ok(Response::new(ListJobsResponse { jobs, next_cursor }))
     }
 
     async fn report_job_result(
         &self,
         request: Request<ReportJobResultRequest>,
     ) -> Result<Response<ReportJobResultResponse>, Status> {
+        self.check_writable()?;
+        let scope = Scope::of(&request);
         let req = request.into_inner();
         let job_id = req.job_id.clone();
 
         let mut state = self.state.write().await;
+        let job = state.job_in(&scope, &job_id)?;
+
+        // Only the worker the job is dispatched to now may settle it; a
+        // report from one it was requeued or reaped from, or a repeat of
+        // one already taken, is stale
+        let current = matches!(job.status, JobStatusEnum::Assigned | JobStatusEnum::Running | JobStatusEnum::Cancelled)
+            && job.assigned_worker.as_deref() == Some(req.worker_id.as_str());
+        if !current {
+            warn!(
+                "⚠️  Dropped stale result of job {} from worker {:?} (job is {}, on {:?})",
+                job_id, req.worker_id, job.status, job.assigned_worker
+            );
+            return Ok(Response::new(ReportJobResultResponse { acknowledged: false }));
+        }
         
-        // Get the assigned worker_id before mutable borrows
-        let worker_id = state.jobs.get(&job_id)
-            .and_then(|job| job.assigned_worker.clone());
+        let mut retry = None;
+        let mut failure = None;
+        let cancelled = match state.jobs.get_mut(&job_id) {
+            // The worker finished a job that was cancelled meanwhile; keep it
+            // cancelled, and its slot freed only the once
+            Some(job) if job.status == JobStatusEnum::Cancelled => {
+                job.assigned_worker = None;
+                true
+            }
+            Some(job) => {
+                let now = clock::now();
+                job.usage = req.usage.map(Into::into);
+                if !req.log_hash.is_empty() {
+                    job.log_hash = Some(req.log_hash.clone());
+                }
+                if req.success {
+                    let output_hash = req.output_hash.clone();
+                    job.set_status(JobStatusEnum::Completed, now);
+                    job.output_hash = Some(req.output_hash.clone());
+                    job.error = None;
+                    job.completed_at = Some(now);
+                    job.worker_platform = req.platform.clone().map(Into::into);
+                    
+                    info!("✅ Job completed: {} (output: {})", job_id, output_hash);
+                } else {
+                    job.error_kind = req.error_kind.into();
+                    let class = retry_class(job.error_kind);
+                    failure = Some(class);
+                    retry = fail_or_retry(job, &self.config.retry, class, req.error.clone(), now);
+                    
+                    match retry {
+                        Some(delay) => warn!("🔁 Job {} failed (attempt {}), retrying in {:?}: {}", job_id, job.attempts, delay, req.error),
+                        None => error!("❌ Job failed: {} (error: {})", job_id, req.error),
+                    }
+                }
+                false
+            }
+            None => return Err(Status::not_found(format!("Job {} not found", job_id))),
+        };
         
-        if let Some(job) = state.jobs.get_mut(&job_id) {
-            if req.success {
-                let output_hash = req.output_hash.clone();
-                job.status = JobStatusEnum::Completed;
-                job.output_hash = Some(req.output_hash);
-                job.completed_at = Some(chrono::Utc::now().timestamp());
-                
-                println!("✅ Job completed: {} (output: {})", job_id, output_hash);
-            } else {
-                let error = req.error.clone();
-                job.status = JobStatusEnum::Failed;
-                job.completed_at = Some(chrono::Utc::now().timestamp());
-                
-                println!("❌ Job failed: {} (error: {})", job_id, error);
+        if !cancelled {
+            state.journal_job(&job_id);
+            if state.settle_dependents(&job_id, clock::now()) {
+                self.assign_after(Duration::ZERO);
             }
-        } else {
-            return Err(Status::not_found(format!("Job {} not found", job_id)));
+        }
+        if req.success && !cancelled {
+            state.add_blob_ref(&req.output_hash, &job_id, "output");
+            state.record_duration(&job_id);
+        }
+        if !cancelled {
+            state.add_blob_ref(&req.log_hash, &job_id, "log");
         }
         
         // Decrease worker's active job count (after job borrow is released)
-        if let Some(worker_id) = worker_id {
-            if let Some(worker) = state.workers.get_mut(&worker_id) {
-                worker.active_jobs = worker.active_jobs.saturating_sub(1);
-            }
+        if !cancelled {
+            state.record_outcome(&req.worker_id, failure, &self.config.quarantine, clock::now());
+        }
+        if let Some(worker) = state.workers.get_mut(&req.worker_id) {
+            worker.active_jobs = worker.active_jobs.saturating_sub(1);
+        }
+        // The freed slot can take a job still waiting in the queue
+        if state.has_runnable_jobs(clock::now()) {
+            self.assign_after(Duration::ZERO);
+        }
+        if let Some(delay) = retry {
+            self.assign_after(delay);
         }
 
         Ok(Response::new(ReportJobResultResponse {
             acknowledged: true,
         }))
     }
+
+    async fn get_pinned_blobs(
+        &self,
+        request: Request<GetPinnedBlobsRequest>,
+    ) -> Result<Response<GetPinnedBlobsResponse>, Status> {
+        Scope::of(&request).require_operator("list every pinned blob")?;
+        let state = self.state.read().await;
+        let mut hashes: Vec<String> = state.pinned_hashes().into_iter().collect();
+        hashes.sort();
+
+        let mut referenced: Vec<String> = state.blob_refs.keys().cloned().collect();
+        referenced.sort();
+
+        let outputs = state
+            .jobs
+            .values()
+            .filter(|job| job.status == JobStatusEnum::Completed)
+            .filter_map(|job| {
+                Some(OutputBlob {
+                    hash: job.output_hash.clone()?,
+                    job_type: job.job_type.clone(),
+                    profile: job.metadata.get("profile").cloned().unwrap_or_default(),
+                    completed_at: job.completed_at.unwrap_or_default(),
+                })
+            })
+            .collect();
+
+        Ok(Response::new(GetPinnedBlobsResponse { hashes, referenced, outputs }))
+    }
+
+    async fn get_blob_refs(
+        &self,
+        request: Request<GetBlobRefsRequest>,
+    ) -> Result<Response<GetBlobRefsResponse>, Status> {
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let state = self.state.read().await;
+
+        let mut refs: Vec<BlobRef> = state
+            .blob_refs
+            .get(&req.hash)
+            .into_iter()
+            .flatten()
+            .filter_map(|(job_id, role)| {
+                state.job_in(&scope, job_id).ok().map(|job| BlobRef {
+                    job_id: job_id.clone(),
+                    role: role.to_string(),
+                    status: job.status.into(),
+                })
+            })
+            .collect();
+        refs.sort_by(|a, b| a.job_id.cmp(&b.job_id));
+
+        // Tenants only learn of pins through their own jobs
+        let pinned = (scope.tenant.is_none() || !refs.is_empty()) && state.pinned_hashes().contains(&req.hash);
+        drop(state);
+        let actions = self
+            .action_refs(&req.namespace, &req.hash)
+            .map_err(|e| Status::internal(format!("Failed to read the action cache: {:#}", e)))?;
+
+        Ok(Response::new(GetBlobRefsResponse { refs, pinned, actions }))
+    }
+
+    async fn get_fairness_report(
+        &self,
+        request: Request<GetFairnessReportRequest>,
+    ) -> Result<Response<GetFairnessReportResponse>, Status> {
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let now = clock::now();
+        let since = if req.window_secs > 0 {
+            now - req.window_secs as i64
+        } else {
+            i64::MIN
+        };
+
+        let state = self.state.read().await;
+        let mut tenants = state.fairness_report(since, now);
+        // A tenant sees its own share of the cluster, not the others'
+        if let Some(tenant) = &scope.tenant {
+            tenants.retain(|usage| usage.tenant == *tenant);
+        }
+
+        Ok(Response::new(GetFairnessReportResponse {
+            tenants,
+            window_secs: req.window_secs,
+        }))
+    }
+
+    async fn get_scaling_advice(
+        &self,
+        request: Request<GetScalingAdviceRequest>,
+    ) -> Result<Response<GetScalingAdviceResponse>, Status> {
+        Scope::of(&request).require_operator("get scaling advice")?;
+        let req = request.into_inner();
+        let window_secs = if req.window_secs > 0 {
+            req.window_secs
+        } else {
+            DEFAULT_SCALING_WINDOW_SECS
+        };
+        let target_utilization = if req.target_utilization > 0.0 {
+            req.target_utilization.min(1.0)
+        } else {
+            DEFAULT_TARGET_UTILIZATION
+        };
+
+        let now = clock::now();
+        let state = self.state.read().await;
+
+        Ok(Response::new(state.scaling_advice(window_secs, target_utilization, now)))
+    }
+
+    async fn get_scheduler_stats(
+        &self,
+        request: Request<GetSchedulerStatsRequest>,
+    ) -> Result<Response<GetSchedulerStatsResponse>, Status> {
+        let scope = Scope::of(&request);
+        let req = request.into_inner();
+        let window_secs = if req.window_secs > 0 {
+            req.window_secs
+        } else {
+            DEFAULT_SCALING_WINDOW_SECS
+        };
+
+        let state = self.state.read().await;
+        Ok(Response::new(state.stats(&scope, window_secs, clock::now())))
+    }
+
+    async fn report_client_error(
+        &self,
+        request: Request<ReportClientErrorRequest>,
+    ) -> Result<Response<ReportClientErrorResponse>, Status> {
+        self.check_writable()?;
+        let req = request.into_inner();
+        let now = clock::now();
+
+        let mut state = self.state.write().await;
+        let accepted = state.record_client_error(&req.client_id, &req.kind, &req.message, now);
+
+        if accepted {
+            warn!("⚠️  Client error from {} [{}]: {}", req.client_id, req.kind, req.message);
+        }
+
+        Ok(Response::new(ReportClientErrorResponse {
+            accepted,
+            rate_limited: !accepted,
+        }))
+    }
+
+    async fn list_client_errors(
+        &self,
+        request: Request<ListClientErrorsRequest>,
+    ) -> Result<Response<ListClientErrorsResponse>, Status> {
+        Scope::of(&request).require_operator("list client errors")?;
+        let req = request.into_inner();
+        let state = self.state.read().await;
+
+        let mut errors: Vec<ClientErrorInfo> = state
+            .client_errors
+            .iter()
+            .map(|((kind, message), record)| {
+                let mut clients: Vec<String> = record.clients.iter().cloned().collect();
+                clients.sort();
+                ClientErrorInfo {
+                    kind: kind.clone(),
+                    message: message.clone(),
+                    count: record.count,
+                    clients,
+                    first_seen: record.first_seen,
+                    last_seen: record.last_seen,
+                }
+            })
+            .collect();
+
+        // Most recently seen first
+        errors.sort_by_key(|e| std::cmp::Reverse(e.last_seen));
+
+        if req.limit > 0 {
+            errors.truncate(req.limit as usize);
+        }
+
+        Ok(Response::new(ListClientErrorsResponse { errors }))
+    }
+}
+
+/// Save what changed in `state` to `store`, rotating the journal once
+/// the store covers it
+fn flush_state(state: &mut SchedulerState, store: &mut StateStore) -> Result<bool> {
+    if !store.renew(clock::now())? {
+        // Leave the dir to whoever took it over
+        state.journal = None;
+        return Ok(false);
+    }
+    if store.save(state)? {
+        if let Some(journal) = state.journal.as_mut() {
+            journal.rotate_if_large()?;
+        }
+    }
+    Ok(true)
+}
+
+/// Fail `job` with `error`, or queue it again if `policy` retries `class`
+/// and it has attempts left. Returns the backoff before the retry.
+fn fail_or_retry(job: &mut JobMetadata, policy: &RetryConfig, class: RetryClass, error: String, now: i64) -> Option<Duration> {
+    job.error = Some(error);
+    if policy.retry_on.contains(&class) && job.attempts < policy.max_attempts {
+        let delay = policy.backoff_secs(job.attempts);
+        job.assigned_worker = None;
+        job.progress = None;
+        job.retry_at = Some(now + delay as i64);
+        job.set_status(JobStatusEnum::Pending, now);
+        return Some(Duration::from_secs(delay));
+    }
+    job.set_status(JobStatusEnum::Failed, now);
+    job.completed_at = Some(now);
+    None
+}
+
+fn retry_class(kind: JobErrorKindEnum) -> RetryClass {
+    match kind {
+        JobErrorKindEnum::Unspecified => RetryClass::Worker,
+        JobErrorKindEnum::QuotaExceeded => RetryClass::Quota,
+        JobErrorKindEnum::Compile => RetryClass::Compile,
+    }
+}
+
+/// Where a job falls in `ListJobs` pages: newest first, by ID within a
+/// second
+fn page_order(submitted_at: i64, job_id: &str) -> (std::cmp::Reverse<i64>, &str) {
+    (std::cmp::Reverse(submitted_at), job_id)
+}
+
+/// The last job of the previous page, from a `ListJobs` cursor
+/// (`<submitted_at>:<job_id>`)
+fn parse_cursor(cursor: &str) -> Option<(i64, String)> {
+    let (at, job_id) = cursor.split_once(':')?;
+    Some((at.parse().ok()?, job_id.to_string()))
+}
+
+/// One `WatchJob` call following a job
+struct JobWatch {
+    job_id: String,
+    state: Arc<RwLock<SchedulerState>>,
+    updates: broadcast::Receiver<GetJobStatusResponse>,
+    next: Option<GetJobStatusResponse>, // to send before waiting for changes
+    last_status: Option<i32>,
+}
+
+impl JobWatch {
+    /// The job's next status; `None` once the last one sent was final (or
+    /// the job is gone)
+    async fn next_update(&mut self) -> Option<GetJobStatusResponse> {
+        loop {
+            if self.last_status.and_then(|s| JobStatusEnum::try_from(s).ok()).is_some_and(|s| s.is_terminal()) {
+                return None;
+            }
+            let update = match self.next.take() {
+                Some(update) => update,
+                None => match self.updates.recv().await {
+                    Ok(update) if update.job_id == self.job_id => update,
+                    Ok(_) => continue,
+                    // Missed some changes; the job's record says where it is now
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        let state = self.state.read().await;
+                        let job = state.jobs.get(&self.job_id)?;
+                        status_response(job, state.durations.eta(job, clock::now()))
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+            };
+            if self.last_status != Some(update.status) {
+                self.last_status = Some(update.status);
+                return Some(update);
+            }
+        }
+    }
+}
+
+fn worker_info(worker: &WorkerMetadata, health: HealthReport) -> WorkerInfo {
+    WorkerInfo {
+        worker_id: worker.worker_id.clone(),
+        address: worker.address.clone(),
+        capacity: worker.capacity,
+        active_jobs: worker.active_jobs,
+        last_heartbeat: worker.last_heartbeat,
+        labels: worker.labels.clone(),
+        version: worker.version.clone().map(Into::into),
+        draining: worker.draining,
+        quarantined_until: worker.quarantined_until.unwrap_or_default(),
+        tenant: worker.tenant.clone().unwrap_or_default(),
+        capabilities: worker.capabilities.clone().map(Into::into),
+        health: Some(health.into()),
+        pool: worker.pool.clone().unwrap_or_default(),
+        state: worker.state.into(),
+        state_since: worker.state_since,
+    }
+}
+
+/// Where `job` stands, as reported to clients waiting on it, with when it
+/// should finish if there's an `eta`
+fn status_response(job: &JobMetadata, eta: Option<Eta>) -> GetJobStatusResponse {
+    let eta = eta.unwrap_or(Eta { expected_secs: 0, remaining_secs: 0 });
+    GetJobStatusResponse {
+        job_id: job.job_id.clone(),
+        status: job.status.into(),
+        output_hash: job.output_hash.clone().unwrap_or_default(),
+        error: job.error.clone().unwrap_or_default(),
+        assigned_worker: job.assigned_worker.clone().unwrap_or_default(),
+        worker_platform: job.worker_platform.clone().map(Into::into),
+        error_kind: job.error_kind.into(),
+        usage: job.usage_info(),
+        attempt_history: job.attempt_history.iter().map(Into::into).collect(),
+        expected_secs: eta.expected_secs,
+        remaining_secs: eta.remaining_secs,
+        run_after: job.run_after.unwrap_or(0),
+    }
+}
+
+fn job_info(job: &JobMetadata) -> JobInfo {
+    JobInfo {
+        job_id: job.job_id.clone(),
+        status: job.status.into(),
+        input_hash: job.input_hash.clone(),
+        output_hash: job.output_hash.clone().unwrap_or_default(),
+        assigned_worker: job.assigned_worker.clone().unwrap_or_default(),
+        submitted_at: job.submitted_at,
+        completed_at: job.completed_at.unwrap_or(0),
+        crate_name: job.metadata.get("crate_name").cloned().unwrap_or_default(),
+        error: job.error.clone().unwrap_or_default(),
+        priority: job.priority,
+        usage: job.usage_info(),
+        tenant: job.tenant.clone().unwrap_or_default(),
+        session_id: job.session_id.clone().unwrap_or_default(),
+        log_hash: job.log_hash.clone().unwrap_or_default(),
+        weight: job.weight,
+        run_after: job.run_after.unwrap_or(0),
+        pool: job.pool.clone().unwrap_or_default(),
+        boosted: job.boosted,
+        gang: job.gang.clone().unwrap_or_default(),
+    }
+}
+
+/// Aggregate status and timing of the build session made of `jobs`
+fn session_info(session_id: &str, jobs: &[&JobMetadata]) -> SessionInfo {
+    let mut session = SessionInfo {
+        session_id: session_id.to_string(),
+        tenant: jobs.iter().find_map(|job| job.tenant.clone()).unwrap_or_default(),
+        total: jobs.len() as u32,
+        started_at: jobs.iter().map(|job| job.submitted_at).min().unwrap_or(0),
+        ..Default::default()
+    };
+    for job in jobs {
+        match job.status {
+            JobStatusEnum::Pending | JobStatusEnum::Blocked => session.pending += 1,
+            JobStatusEnum::Assigned | JobStatusEnum::Running | JobStatusEnum::QueuedRemote => session.running += 1,
+            JobStatusEnum::Completed => session.completed += 1,
+            JobStatusEnum::Failed => session.failed += 1,
+            JobStatusEnum::Cancelled => session.cancelled += 1,
+        }
+    }
+
+    let status = if session.pending == session.total {
+        JobStatusEnum::Pending
+    } else if session.pending + session.running > 0 {
+        JobStatusEnum::Running
+    } else {
+        session.finished_at = jobs.iter().filter_map(|job| job.completed_at).max().unwrap_or(0);
+        if session.failed > 0 {
+            JobStatusEnum::Failed
+        } else if session.cancelled > 0 {
+            JobStatusEnum::Cancelled
+        } else {
+            JobStatusEnum::Completed
+        }
+    };
+    session.status = status.into();
+    session
+}
+
+/// The output hash and attached-to job a submission response reports for `reuse`
+fn reuse_summary(reuse: &Option<Reuse>) -> (String, String) {
+    match reuse {
+        Some(Reuse::Cached { output_hash, .. }) => (output_hash.clone(), String::new()),
+        Some(Reuse::InFlight(primary)) => (String::new(), primary.clone()),
+        None => (String::new(), String::new()),
+    }
+}
+
+/// Refuse a node whose protocol this scheduler can't speak, explaining why.
+/// Nodes that don't report a version are let in.
+fn check_version(who: &str, version: Option<VersionInfo>) -> Result<Option<BuildVersion>, String> {
+    let Some(version) = version.map(BuildVersion::from) else {
+        return Ok(None);
+    };
+
+    if let Some(reason) = BuildVersion::current().incompatibility(&version) {
+        warn!("⚠️  {} refused: {}", who, reason);
+        return Err(format!("{} refused by scheduler: {}", who, reason));
+    }
+    Ok(Some(version))
+}
+
+/// Tell the worker at `address` to stop running `job_id`
+async fn abort_on_worker(workers: &WorkerChannels, job_id: &str, address: &str) -> Result<()> {
+    let mut client = workers.client(address)?;
+    let request = AbortJobRequest { job_id: job_id.to_string() };
+    if workers.check(address, client.abort_job(request).await)?.into_inner().aborted {
+        info!("🛑 Job {} aborted on {}", job_id, address);
+    }
+    Ok(())
+}
+
+/// Tell the worker at `address` the scheduler is stopping; returns how
+/// many jobs it's still running
+async fn notify_stopping(workers: &WorkerChannels, address: &str, resuming: bool) -> Result<u32> {
+    let mut client = workers.client(address)?;
+    let request = SchedulerStoppingRequest { resuming };
+    let response = workers.check(address, client.scheduler_stopping(request).await)?;
+    Ok(response.into_inner().running)
+}
+
+/// Resolves on SIGINT (Ctrl-C), or SIGTERM on Unix
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
 }
 
 pub async fn run_scheduler(addr: String) -> Result<()> {
-    let service = SchedulerService::new();
+    let config = SchedulerConfig {
+        addr,
+        ..Default::default()
+    };
+    run_scheduler_with_config(config, None).await
+}
+
+pub async fn run_scheduler_with_config(config: SchedulerConfig, cas: Option<Arc<Cas>>) -> Result<()> {
+    let addr = config.addr.clone();
+    let state_dir = config.state_dir.clone();
+    let mirror_of = config.mirror_of.clone();
+    let standby = config.standby.is_some();
+    let mut service = SchedulerService::new(config);
+    match (mirror_of, state_dir) {
+        (Some(_), _) if standby => anyhow::bail!("A scheduler can't be both a mirror and a standby"),
+        (Some(dir), _) => service = service.with_mirror_of(Path::new(&dir))?,
+        // Following the primary's journal until taking over
+        (None, Some(dir)) if standby => service = service.with_mirror_of(Path::new(&dir))?,
+        (None, Some(dir)) => service = service.with_state_dir(Path::new(&dir))?,
+        (None, None) if standby => anyhow::bail!("A standby scheduler needs the primary's state_dir, on shared storage"),
+        (None, None) => {}
+    }
+    if let Some(cas) = cas {
+        service = service.with_cas(cas);
+    }
     service.run(addr).await
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::StrategyKind;
+    use crate::common::types::JobProgress;
+
+    fn job(id: &str, status: JobStatusEnum) -> JobMetadata {
+        JobMetadata {
+            job_id: id.to_string(),
+            input_hash: format!("{}-input", id),
+            output_hash: None,
+            error: None,
+            error_kind: JobErrorKindEnum::Unspecified,
+            job_type: "rust-compile".to_string(),
+            status,
+            assigned_worker: Some("w1".to_string()),
+            submitted_at: 100,
+            started_at: None,
+            completed_at: None,
+            metadata: HashMap::new(),
+            preemptions: 0,
+            worker_platform: None,
+            priority: 0,
+            timeline: Vec::new(),
+            progress: None,
+            attempts: 1,
+            attempt_history: Vec::new(),
+            retry_at: None,
+            run_after: None,
+            depends_on: Vec::new(),
+            constraints: HashMap::new(),
+            timeout_secs: None,
+            attached_to: None,
+            usage: None,
+            tenant: None,
+            session_id: None,
+            log_hash: None,
+            weight: 1,
+            pool: None,
+            boosted: false,
+            gang: None,
+        }
+    }
+
+    fn state_with(jobs: Vec<JobMetadata>) -> SchedulerState {
+        let mut state = SchedulerState::default();
+        for job in jobs {
+            state.jobs.insert(job.job_id.clone(), job);
+        }
+        state
+    }
+
+    #[test]
+    fn test_stuck_jobs() {
+        let running = |id: &str, timeout_secs: Option<u64>, progress_at: i64| {
+            let mut job = job(id, JobStatusEnum::Running);
+            job.started_at = Some(100);
+            job.timeout_secs = timeout_secs;
+            job.progress = Some(JobProgress {
+                at: progress_at,
+                phase: "compiling".to_string(),
+                elapsed_secs: 0,
+                pid: None,
+                process_alive: false,
+            });
+            job
+        };
+        let state = state_with(vec![
+            running("own-timeout", Some(60), 1_000),
+            running("default-timeout", None, 1_000),
+            running("stalled", Some(0), 100),
+            job("queued", JobStatusEnum::Pending),
+        ]);
+        let stuck = |now, default_timeout| {
+            let mut stuck = state.stuck_jobs(now, default_timeout);
+            stuck.sort();
+            stuck
+        };
+        let ids = |now, default_timeout| -> Vec<String> { stuck(now, default_timeout).into_iter().map(|(id, _)| id).collect() };
+
+        // Its own timeout, up to the second
+        assert!(ids(160, 0).is_empty());
+        assert_eq!(stuck(161, 0), [("own-timeout".to_string(), "Timed out after 60s".to_string())]);
+
+        // The scheduler's default for a job without one
+        assert!(ids(130, 30).is_empty());
+        assert_eq!(stuck(131, 30), [("default-timeout".to_string(), "Timed out after 30s".to_string())]);
+
+        // No progress for PROGRESS_STALL_SECS; the queued job is never reaped
+        assert_eq!(ids(100 + PROGRESS_STALL_SECS, 0), ["own-timeout"]);
+        let reaped = stuck(101 + PROGRESS_STALL_SECS, 0);
+        assert_eq!(reaped[1].0, "stalled");
+        assert_eq!(reaped[1].1, format!("Stalled: no sign of life for over {}s", PROGRESS_STALL_SECS));
+        assert_eq!(ids(2_000, 30), ["default-timeout", "own-timeout", "stalled"]);
+    }
+
+    #[test]
+    fn test_is_stalled() {
+        let progress = |at: i64, pid: Option<u32>, process_alive: bool| JobProgress {
+            at,
+            phase: "compiling".to_string(),
+            elapsed_secs: 0,
+            pid,
+            process_alive,
+        };
+
+        // Never reported progress: counted from when it started
+        let mut silent = job("silent", JobStatusEnum::Running);
+        silent.started_at = Some(200);
+        assert!(!silent.is_stalled(200 + PROGRESS_STALL_SECS, PROGRESS_STALL_SECS));
+        assert!(silent.is_stalled(201 + PROGRESS_STALL_SECS, PROGRESS_STALL_SECS));
+
+        // ...or submitted, if it has no start time
+        silent.started_at = None;
+        assert!(!silent.is_stalled(100 + PROGRESS_STALL_SECS, PROGRESS_STALL_SECS));
+        assert!(silent.is_stalled(101 + PROGRESS_STALL_SECS, PROGRESS_STALL_SECS));
+
+        // The latest report resets the clock, however long the job has run
+        let mut reporting = job("reporting", JobStatusEnum::Running);
+        reporting.started_at = Some(100);
+        reporting.progress = Some(progress(1_000, Some(42), true));
+        assert!(!reporting.is_stalled(1_000 + PROGRESS_STALL_SECS, PROGRESS_STALL_SECS));
+        assert!(reporting.is_stalled(1_001 + PROGRESS_STALL_SECS, PROGRESS_STALL_SECS));
+
+        // A dead compiler is stalled at once
+        reporting.progress = Some(progress(1_000, Some(42), false));
+        assert!(reporting.is_stalled(1_000, PROGRESS_STALL_SECS));
+
+        // Only running jobs stall
+        let mut assigned = job("assigned", JobStatusEnum::Assigned);
+        assigned.progress = Some(progress(100, Some(42), false));
+        assert!(!assigned.is_stalled(10_000, PROGRESS_STALL_SECS));
+    }
+
+    fn candidate(id: &str, active_jobs: u32, cached: &[&str]) -> Candidate {
+        Candidate {
+            id: id.to_string(),
+            address: format!("{}:6001", id),
+            active_jobs,
+            capacity: 4,
+            labels: HashMap::new(),
+            cached_hashes: Arc::new(cached.iter().map(|hash| hash.to_string()).collect()),
+            tenant: None,
+            capabilities: None,
+            health: 1.0,
+            pool: None,
+        }
+    }
+
+    #[test]
+    fn test_pick_worker_prefers_locality() {
+        let mut lib = job("lib", JobStatusEnum::Completed);
+        lib.output_hash = Some("lib-output".to_string());
+        let mut bin = job("bin", JobStatusEnum::Pending);
+        bin.depends_on = vec!["lib".to_string()];
+        let state = state_with(vec![lib, bin]);
+        let bin = &state.jobs["bin"];
+        let mut strategy = strategy::from_kind(StrategyKind::LeastLoaded);
+
+        // Equally loaded, the one already holding its input and its
+        // dependency's output wins over one with either, or neither
+        let candidates = vec![
+            candidate("cold", 1, &[]),
+            candidate("input-only", 1, &["bin-input"]),
+            candidate("warm", 1, &["bin-input", "lib-output"]),
+        ];
+        assert_eq!(state.pick_worker(bin, &candidates, strategy.as_mut()), Some(2));
+        // Load breaks ties between equally warm ones
+        let candidates = vec![candidate("busy", 3, &["bin-input"]), candidate("idle", 0, &["bin-input"])];
+        assert_eq!(state.pick_worker(bin, &candidates, strategy.as_mut()), Some(1));
+        // A warm worker without room doesn't get it
+        let candidates = vec![candidate("cold", 1, &[]), candidate("full", 4, &["bin-input", "lib-output"])];
+        assert_eq!(state.pick_worker(bin, &candidates, strategy.as_mut()), Some(0));
+    }
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[], 50), 0);
+        assert_eq!(percentile(&[], 99), 0);
+        for p in [0, 1, 50, 99, 100] {
+            assert_eq!(percentile(&[7], p), 7);
+        }
+        let sorted: Vec<i64> = (1..=10).collect();
+        assert_eq!(percentile(&sorted, 0), 1);
+        assert_eq!(percentile(&sorted, 50), 5);
+        assert_eq!(percentile(&sorted, 90), 9);
+        assert_eq!(percentile(&sorted, 99), 10);
+        assert_eq!(percentile(&sorted, 100), 10);
+    }
+
+    #[test]
+    fn test_fairness_report() {
+        let run = |id: &str, tenant: &str, submitted_at: i64, started_at: Option<i64>, completed_at: Option<i64>| {
+            let mut job = job(id, JobStatusEnum::Completed);
+            if id.starts_with('b') {
+                // Accounted by its `user` metadata rather than a tenant
+                job.metadata.insert("user".to_string(), tenant.to_string());
+            } else {
+                job.tenant = Some(tenant.to_string());
+            }
+            job.submitted_at = submitted_at;
+            job.started_at = started_at;
+            job.completed_at = completed_at;
+            job
+        };
+        let mut preempted = run("a2", "alpha", 200, Some(230), Some(290));
+        preempted.preemptions = 2;
+        let state = state_with(vec![
+            run("a1", "alpha", 100, Some(110), Some(170)),
+            preempted,
+            // Still queued: waiting up to now, no cluster time
+            run("a3", "alpha", 300, None, None),
+            // Before the window
+            run("a0", "alpha", 50, Some(50), Some(950)),
+            run("b1", "beta", 150, Some(150), Some(190)),
+            // Still running: counted up to now
+            run("b2", "beta", 960, Some(980), None),
+        ]);
+
+        let report = state.fairness_report(100, 1_000);
+        let tenants: Vec<&str> = report.iter().map(|u| u.tenant.as_str()).collect();
+        assert_eq!(tenants, ["alpha", "beta"]);
+        let (alpha, beta) = (&report[0], &report[1]);
+        assert_eq!((alpha.jobs, alpha.preemptions, alpha.cluster_time_secs), (3, 2, 120));
+        assert_eq!((alpha.wait_p50_secs, alpha.wait_p90_secs, alpha.wait_p99_secs), (30, 700, 700));
+        assert_eq!((beta.jobs, beta.preemptions, beta.cluster_time_secs), (2, 0, 60));
+        assert_eq!((beta.wait_p50_secs, beta.wait_p90_secs, beta.wait_p99_secs), (0, 20, 20));
+        assert!((alpha.cluster_share - 2.0 / 3.0).abs() < 1e-9);
+        assert!((beta.cluster_share - 1.0 / 3.0).abs() < 1e-9);
+
+        assert!(state.fairness_report(2_000, 2_000).is_empty());
+    }
+
+    #[test]
+    fn test_pinned_hashes() {
+        let mut lib = job("lib", JobStatusEnum::Completed);
+        lib.output_hash = Some("lib-output".to_string());
+        let mut bin = job("bin", JobStatusEnum::Blocked);
+        bin.depends_on = vec!["lib".to_string(), "proc-macro".to_string()];
+        bin.metadata.insert("deps".to_string(), "listed-dep, ,other-dep".to_string());
+        let mut retried = job("retried", JobStatusEnum::Pending);
+        retried.log_hash = Some("retried-log".to_string());
+        let mut old = job("old", JobStatusEnum::Failed);
+        old.log_hash = Some("old-log".to_string());
+        let state = state_with(vec![lib, bin, retried, old, job("proc-macro", JobStatusEnum::Running)]);
+
+        // The blocked job still needs its completed dependency's output
+        let mut pinned: Vec<String> = state.pinned_hashes().into_iter().collect();
+        pinned.sort();
+        assert_eq!(
+            pinned,
+            ["bin-input", "lib-output", "listed-dep", "other-dep", "proc-macro-input", "retried-input", "retried-log"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blob_refs() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cas = Arc::new(Cas::new(temp_dir.path()).unwrap());
+        let [input, output, other_input] = [b"src".as_slice(), b"rlib", b"other src"].map(|data| cas.put(data).unwrap());
+        let service = SchedulerService::new(SchedulerConfig::default()).with_cas(cas.clone());
+
+        let mut lib = job("lib", JobStatusEnum::Completed);
+        lib.input_hash = input.clone();
+        lib.output_hash = Some(output.clone());
+        {
+            let mut state = service.state.write().await;
+            state.add_blob_ref(&input, "lib", "input");
+            state.add_blob_ref(&output, "lib", "output");
+            state.jobs.insert("lib".to_string(), lib);
+        }
+        // Cached by a wrapper, or by a job the scheduler has since forgotten
+        cas.record_action(&input, &output).unwrap();
+        cas.record_action(&other_input, &output).unwrap();
+        cas.namespace("project-a").unwrap().record_action(&input, &other_input).unwrap();
+
+        let refs = |hash: &str, namespace: &str| {
+            let request = Request::new(GetBlobRefsRequest {
+                hash: hash.to_string(),
+                namespace: namespace.to_string(),
+            });
+            async { service.get_blob_refs(request).await.unwrap().into_inner() }
+        };
+        let actions = |resp: &GetBlobRefsResponse| -> Vec<(String, String)> {
+            resp.actions.iter().map(|action| (action.input_hash.clone(), action.output_hash.clone())).collect()
+        };
+
+        let resp = refs(&output, "").await;
+        let jobs: Vec<(&str, &str)> = resp.refs.iter().map(|r| (r.job_id.as_str(), r.role.as_str())).collect();
+        assert_eq!(jobs, [("lib", "output")]);
+        assert!(!resp.pinned);
+        let mut expected = vec![(input.clone(), output.clone()), (other_input.clone(), output.clone())];
+        expected.sort();
+        assert_eq!(actions(&resp), expected);
+
+        // Known to no job, but still the input of a cached action
+        let resp = refs(&other_input, "").await;
+        assert!(resp.refs.is_empty());
+        assert_eq!(actions(&resp), [(other_input.clone(), output.clone())]);
+
+        // Each namespace has its own action cache
+        assert_eq!(actions(&refs(&other_input, "project-a").await), [(input.clone(), other_input.clone())]);
+        assert!(refs(&output, "project-b").await.actions.is_empty());
+        assert!(!cas.list_namespaces().unwrap().contains(&"project-b".to_string()));
+    }
+}
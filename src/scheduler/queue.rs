@@ -0,0 +1,185 @@
+use crate::common::types::{JobMetadata, JobStatusEnum};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Jobs by status, and the pending ones in dispatch order, so scheduling
+/// passes don't scan every job ever submitted. Kept in step with the job
+/// map by calling `update` after each change to a job.
+#[derive(Debug, Default)]
+pub(crate) struct JobIndex {
+    by_status: HashMap<JobStatusEnum, HashSet<String>>,
+    /// Pending jobs, boosted, then highest (aged) priority, then oldest,
+    /// first
+    pending: BTreeSet<QueueKey>,
+    /// What each job was last filed under, to find it again
+    filed: HashMap<String, (JobStatusEnum, QueueKey)>,
+    /// Seconds of waiting worth a priority level; 0 = no aging
+    aging_secs: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct QueueKey {
+    boosted: Reverse<bool>,
+    /// Priority, scaled by `aging_secs` less the submission time when
+    /// aging: every job gains a level per `aging_secs` waited, so comparing
+    /// at any moment gives the same order and nothing needs refiling
+    rank: Reverse<i64>,
+    submitted_at: i64,
+    job_id: String,
+}
+
+impl JobIndex {
+    /// Index every job in `jobs` from scratch
+    pub fn build<'a>(jobs: impl IntoIterator<Item = &'a JobMetadata>) -> Self {
+        Self::aging(0, jobs)
+    }
+
+    /// Like `build`, but pending jobs gain a priority level for every
+    /// `aging_secs` they wait, so low-priority ones aren't starved
+    pub fn aging<'a>(aging_secs: u64, jobs: impl IntoIterator<Item = &'a JobMetadata>) -> Self {
+        let mut index = JobIndex {
+            aging_secs: aging_secs as i64,
+            ..Default::default()
+        };
+        for job in jobs {
+            index.update(&job.job_id, Some(job));
+        }
+        index
+    }
+
+    /// Refile `job_id` as it is now (`None` if it's gone)
+    pub fn update(&mut self, job_id: &str, job: Option<&JobMetadata>) {
+        if let Some((status, key)) = self.filed.remove(job_id) {
+            if let Some(ids) = self.by_status.get_mut(&status) {
+                ids.remove(job_id);
+            }
+            self.pending.remove(&key);
+        }
+        let Some(job) = job else {
+            return;
+        };
+
+        let rank = match self.aging_secs {
+            0 => job.priority as i64,
+            secs => job.priority as i64 * secs - job.submitted_at,
+        };
+        let key = QueueKey {
+            boosted: Reverse(job.boosted),
+            rank: Reverse(rank),
+            submitted_at: job.submitted_at,
+            job_id: job_id.to_string(),
+        };
+        self.by_status.entry(job.status).or_default().insert(job_id.to_string());
+        if job.status == JobStatusEnum::Pending {
+            self.pending.insert(key.clone());
+        }
+        self.filed.insert(job_id.to_string(), (job.status, key));
+    }
+
+    /// Pending jobs in the order they should be dispatched (backing-off
+    /// ones included)
+    pub fn pending(&self) -> impl Iterator<Item = &str> {
+        self.pending.iter().map(|key| key.job_id.as_str())
+    }
+
+    pub fn with_status(&self, status: JobStatusEnum) -> impl Iterator<Item = &str> {
+        self.by_status.get(&status).into_iter().flatten().map(|id| id.as_str())
+    }
+
+    pub fn count(&self, status: JobStatusEnum) -> usize {
+        self.by_status.get(&status).map_or(0, HashSet::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(id: &str, status: JobStatusEnum, priority: i32, submitted_at: i64) -> JobMetadata {
+        JobMetadata {
+            job_id: id.to_string(),
+            input_hash: String::new(),
+            output_hash: None,
+            error: None,
+            error_kind: Default::default(),
+            job_type: "rust-compile".to_string(),
+            status,
+            assigned_worker: None,
+            submitted_at,
+            started_at: None,
+            completed_at: None,
+            metadata: HashMap::new(),
+            preemptions: 0,
+            worker_platform: None,
+            priority,
+            timeline: Vec::new(),
+            progress: None,
+            attempts: 0,
+            attempt_history: Vec::new(),
+            retry_at: None,
+            run_after: None,
+            depends_on: Vec::new(),
+            constraints: HashMap::new(),
+            timeout_secs: None,
+            attached_to: None,
+            usage: None,
+            tenant: None,
+            session_id: None,
+            log_hash: None,
+            weight: 1,
+            pool: None,
+            boosted: false,
+            gang: None,
+        }
+    }
+
+    #[test]
+    fn test_pending_order_follows_changes() {
+        let mut jobs = vec![
+            job("old", JobStatusEnum::Pending, 0, 1),
+            job("new", JobStatusEnum::Pending, 0, 2),
+            job("urgent", JobStatusEnum::Pending, 5, 3),
+            job("done", JobStatusEnum::Completed, 9, 0),
+        ];
+        let mut index = JobIndex::build(&jobs);
+        assert_eq!(index.pending().collect::<Vec<_>>(), ["urgent", "old", "new"]);
+
+        // Bumped ahead of the others
+        jobs[1].priority = 10;
+        index.update("new", Some(&jobs[1]));
+        assert_eq!(index.pending().collect::<Vec<_>>(), ["new", "urgent", "old"]);
+
+        // Dispatched, so no longer queued
+        jobs[2].status = JobStatusEnum::Running;
+        index.update("urgent", Some(&jobs[2]));
+        assert_eq!(index.pending().collect::<Vec<_>>(), ["new", "old"]);
+        assert_eq!(index.with_status(JobStatusEnum::Running).collect::<Vec<_>>(), ["urgent"]);
+
+        // A build waiting on it puts it ahead of any priority
+        jobs[0].boosted = true;
+        index.update("old", Some(&jobs[0]));
+        assert_eq!(index.pending().collect::<Vec<_>>(), ["old", "new"]);
+
+        index.update("old", None);
+        assert_eq!(index.pending().collect::<Vec<_>>(), ["new"]);
+        assert_eq!(index.with_status(JobStatusEnum::Completed).collect::<Vec<_>>(), ["done"]);
+    }
+
+    #[test]
+    fn test_aging_lets_long_waiting_jobs_ahead() {
+        let jobs = vec![
+            job("ancient", JobStatusEnum::Pending, 0, 0),
+            job("stale", JobStatusEnum::Pending, 1, 500),
+            job("urgent", JobStatusEnum::Pending, 5, 1000),
+            job("fresh", JobStatusEnum::Pending, 5, 1001),
+        ];
+        assert_eq!(JobIndex::build(&jobs).pending().collect::<Vec<_>>(), ["urgent", "fresh", "stale", "ancient"]);
+
+        // At 300s a level, "ancient" has waited long enough to pass
+        // "stale" but not the five levels up to "urgent"; at 100s, both
+        let index = JobIndex::aging(300, &jobs);
+        assert_eq!(index.pending().collect::<Vec<_>>(), ["urgent", "fresh", "ancient", "stale"]);
+        let index = JobIndex::aging(100, &jobs);
+        assert_eq!(index.pending().collect::<Vec<_>>(), ["ancient", "stale", "urgent", "fresh"]);
+    }
+}
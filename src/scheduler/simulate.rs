@@ -0,0 +1,324 @@
+use super::journal::{Entry, Event};
+use super::strategy::{self, Candidate};
+use crate::common::config::StrategyKind;
+use crate::common::types::{JobMetadata, WorkerMetadata};
+use anyhow::{bail, Context, Result};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// Files in a state dir a trace is read from, oldest first
+const TRACE_FILES: [&str; 3] = ["jobs-archive.jsonl", "journal.1.jsonl", "journal.jsonl"];
+
+/// Jobs (with how long each ran) and workers recorded by a scheduler, to
+/// replay against scheduling strategies offline. Jobs arrive as they were
+/// submitted, wait for their dependencies and run for as long as they
+/// did; workers are there from the start. Jobs that never ran (cancelled
+/// while queued, or served from the cache) are left out.
+#[derive(Debug)]
+pub struct Trace {
+    jobs: Vec<TraceJob>,
+    workers: Vec<WorkerMetadata>,
+}
+
+#[derive(Debug)]
+struct TraceJob {
+    job: JobMetadata,
+    /// Milliseconds after the first submission in the trace
+    arrival: i64,
+    duration: i64,
+    /// Indices of the traced jobs it depends on
+    depends_on: Vec<usize>,
+}
+
+/// How a strategy fared on a trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationReport {
+    pub strategy: StrategyKind,
+    pub jobs: usize,
+    /// Left waiting for good: no worker accepts them, or something they
+    /// depend on
+    pub unschedulable: usize,
+    /// First submission to last job finishing
+    pub makespan_ms: u64,
+    /// Share of worker slots busy over the makespan
+    pub utilization: f64,
+    /// Jobs' waits from ready (submitted, dependencies done) to started
+    pub avg_queue_latency_ms: u64,
+    pub p95_queue_latency_ms: u64,
+}
+
+impl Trace {
+    /// Read a scheduler journal (`journal.jsonl`), job archive
+    /// (`jobs-archive.jsonl`) or a state dir holding them
+    pub fn load(path: &Path) -> Result<Self> {
+        let files: Vec<_> = if path.is_dir() {
+            TRACE_FILES.iter().map(|name| path.join(name)).filter(|file| file.exists()).collect()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        let mut jobs: HashMap<String, JobMetadata> = HashMap::new();
+        let mut workers: HashMap<String, WorkerMetadata> = HashMap::new();
+        for file in files {
+            let content = fs::read_to_string(&file).with_context(|| format!("Failed to read trace {:?}", file))?;
+            // Skip a line torn by a crash mid-write
+            for line in content.lines() {
+                if let Ok(entry) = serde_json::from_str::<Entry>(line) {
+                    match entry.event {
+                        Event::Job { job } => {
+                            jobs.insert(job.job_id.clone(), *job);
+                        }
+                        Event::WorkerRegistered { worker } => {
+                            workers.insert(worker.worker_id.clone(), *worker);
+                        }
+                        Event::WorkerRemoved { .. } | Event::JobRemoved { .. } => {}
+                    }
+                } else if let Ok(job) = serde_json::from_str::<JobMetadata>(line) {
+                    jobs.insert(job.job_id.clone(), job);
+                }
+            }
+        }
+
+        let mut ran: Vec<(JobMetadata, i64)> = jobs
+            .into_values()
+            .filter_map(|job| duration_ms(&job).map(|duration| (job, duration)))
+            .collect();
+        if ran.is_empty() {
+            bail!("No jobs that ran in trace {:?}", path);
+        }
+        ran.sort_by(|(a, _), (b, _)| (a.submitted_at, &a.job_id).cmp(&(b.submitted_at, &b.job_id)));
+        let first = ran[0].0.submitted_at;
+        let index: HashMap<String, usize> = ran.iter().enumerate().map(|(idx, (job, _))| (job.job_id.clone(), idx)).collect();
+        let jobs = ran
+            .into_iter()
+            .map(|(job, duration)| TraceJob {
+                arrival: (job.submitted_at - first) * 1000,
+                duration,
+                // Dependencies that never ran were done (cached) or gone
+                depends_on: job.depends_on.iter().filter_map(|dep| index.get(dep).copied()).collect(),
+                job,
+            })
+            .collect();
+
+        let mut workers: Vec<WorkerMetadata> = workers.into_values().collect();
+        workers.sort_by(|a, b| a.worker_id.cmp(&b.worker_id));
+        Ok(Trace { jobs, workers })
+    }
+
+    pub fn jobs(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Replay on `count` identical workers instead of the traced ones
+    pub fn with_workers(mut self, count: usize, capacity: u32) -> Self {
+        self.workers = (0..count)
+            .map(|n| WorkerMetadata {
+                worker_id: format!("sim-{}", n + 1),
+                address: String::new(),
+                capacity,
+                active_jobs: 0,
+                last_heartbeat: 0,
+                labels: HashMap::new(),
+                version: None,
+                draining: false,
+                quarantined_until: None,
+                tenant: None,
+                capabilities: None,
+                pool: None,
+                state: Default::default(),
+                state_since: 0,
+                cached_hashes: Default::default(),
+            })
+            .collect();
+        self
+    }
+
+    /// Replay the trace with `kind` placing jobs, as the scheduler would:
+    /// queued jobs by priority, then age, each to a worker that accepts it
+    /// with room for its weight
+    pub fn simulate(&self, kind: StrategyKind) -> SimulationReport {
+        let mut strategy = strategy::from_kind(kind);
+        let mut active = vec![0u32; self.workers.len()];
+        let mut finished: Vec<Option<i64>> = vec![None; self.jobs.len()];
+        let mut running: BinaryHeap<Reverse<(i64, usize, u32)>> = BinaryHeap::new(); // (end, worker, slots)
+        let mut pending: Vec<usize> = Vec::new();
+        let mut latencies: Vec<i64> = Vec::new();
+        let (mut arrived, mut now, mut busy) = (0, 0, 0);
+
+        loop {
+            while running.peek().is_some_and(|Reverse((end, _, _))| *end <= now) {
+                if let Some(Reverse((_, worker, slots))) = running.pop() {
+                    active[worker] -= slots;
+                }
+            }
+            while arrived < self.jobs.len() && self.jobs[arrived].arrival <= now {
+                pending.push(arrived);
+                arrived += 1;
+            }
+
+            pending.sort_by_key(|&idx| (Reverse(self.jobs[idx].job.priority), idx));
+            let mut slots: Vec<usize> = (0..self.workers.len()).filter(|&w| active[w] < self.workers[w].capacity).collect();
+            let mut candidates: Vec<Candidate> = slots.iter().map(|&w| candidate(&self.workers[w], active[w])).collect();
+            pending.retain(|&idx| {
+                let job = &self.jobs[idx];
+                let Some(ready) = job
+                    .depends_on
+                    .iter()
+                    .try_fold(job.arrival, |ready, &dep| finished[dep].filter(|&at| at <= now).map(|at| ready.max(at)))
+                else {
+                    return true;
+                };
+                let eligible: Vec<usize> = (0..candidates.len())
+                    .filter(|&c| candidates[c].accepts(&job.job) && candidates[c].fits(job.job.weight))
+                    .collect();
+                let Some(c) = strategy::pick_among(strategy.as_mut(), &candidates, &eligible) else {
+                    return true;
+                };
+
+                let (worker, weight) = (slots[c], job.job.weight.min(candidates[c].capacity));
+                active[worker] += weight;
+                candidates[c].active_jobs += weight;
+                if candidates[c].active_jobs >= candidates[c].capacity {
+                    candidates.remove(c);
+                    slots.remove(c);
+                }
+                finished[idx] = Some(now + job.duration);
+                running.push(Reverse((now + job.duration, worker, weight)));
+                latencies.push(now - ready);
+                busy += job.duration * i64::from(weight);
+                false
+            });
+
+            let next_end = running.peek().map(|Reverse((end, _, _))| *end);
+            let next_arrival = self.jobs.get(arrived).map(|job| job.arrival);
+            now = match (next_end, next_arrival) {
+                (Some(end), Some(arrival)) => end.min(arrival),
+                (Some(at), None) | (None, Some(at)) => at,
+                (None, None) => break,
+            };
+        }
+
+        let makespan = finished.iter().flatten().max().copied().unwrap_or(0);
+        let slots: u64 = self.workers.iter().map(|worker| u64::from(worker.capacity)).sum();
+        latencies.sort_unstable();
+        let percentile = |p: usize| latencies.get((latencies.len() * p).div_ceil(100).saturating_sub(1)).copied().unwrap_or(0);
+        SimulationReport {
+            strategy: kind,
+            jobs: self.jobs.len(),
+            unschedulable: pending.len(),
+            makespan_ms: makespan as u64,
+            utilization: match slots * makespan as u64 {
+                0 => 0.0,
+                capacity => busy as f64 / capacity as f64,
+            },
+            avg_queue_latency_ms: match latencies.len() {
+                0 => 0,
+                n => (latencies.iter().sum::<i64>() / n as i64) as u64,
+            },
+            p95_queue_latency_ms: percentile(95) as u64,
+        }
+    }
+}
+
+/// How long `job` ran: as its worker measured, else by its timestamps
+fn duration_ms(job: &JobMetadata) -> Option<i64> {
+    match (job.usage, job.started_at, job.completed_at) {
+        (Some(usage), _, _) if usage.exec_millis > 0 => Some(usage.exec_millis as i64),
+        (_, Some(started), Some(completed)) if completed >= started => Some((completed - started) * 1000),
+        _ => None,
+    }
+}
+
+fn candidate(worker: &WorkerMetadata, active_jobs: u32) -> Candidate {
+    Candidate {
+        id: worker.worker_id.clone(),
+        address: worker.address.clone(),
+        active_jobs,
+        capacity: worker.capacity,
+        labels: worker.labels.clone(),
+        cached_hashes: Default::default(),
+        tenant: worker.tenant.clone(),
+        capabilities: worker.capabilities.clone(),
+        health: 1.0,
+        pool: worker.pool.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::types::JobStatusEnum;
+    use crate::scheduler::journal::Journal;
+    use tempfile::TempDir;
+
+    fn job(id: &str, submitted_at: i64, secs: i64, depends_on: &[&str]) -> JobMetadata {
+        JobMetadata {
+            job_id: id.to_string(),
+            input_hash: String::new(),
+            output_hash: None,
+            error: None,
+            error_kind: Default::default(),
+            job_type: "rust-compile".to_string(),
+            status: JobStatusEnum::Completed,
+            assigned_worker: None,
+            submitted_at,
+            started_at: Some(submitted_at),
+            completed_at: Some(submitted_at + secs),
+            metadata: HashMap::new(),
+            preemptions: 0,
+            worker_platform: None,
+            priority: 0,
+            timeline: Vec::new(),
+            progress: None,
+            attempts: 1,
+            attempt_history: Vec::new(),
+            retry_at: None,
+            run_after: None,
+            depends_on: depends_on.iter().map(|dep| dep.to_string()).collect(),
+            constraints: HashMap::new(),
+            timeout_secs: None,
+            attached_to: None,
+            usage: None,
+            tenant: None,
+            session_id: None,
+            log_hash: None,
+            weight: 1,
+            pool: None,
+            boosted: false,
+            gang: None,
+        }
+    }
+
+    #[test]
+    fn test_replay_journal() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut journal = Journal::open(temp_dir.path(), 0).unwrap();
+        let mut arm = job("arm-only", 100, 5, &[]);
+        arm.constraints.insert("arch".to_string(), "aarch64".to_string());
+        let mut cached = job("cached", 100, 0, &[]);
+        cached.started_at = None;
+        for job in [job("a", 100, 10, &[]), job("b", 100, 10, &[]), job("c", 105, 10, &["a"]), arm, cached] {
+            journal.record(0, Event::Job { job: Box::new(job) });
+        }
+
+        let trace = Trace::load(temp_dir.path()).unwrap().with_workers(2, 1);
+        assert_eq!(trace.jobs(), 4);
+        let report = trace.simulate(StrategyKind::LeastLoaded);
+        // "c" waits for "a" to finish at 10s, then runs until 20s
+        assert_eq!(report.makespan_ms, 20_000);
+        assert_eq!(report.utilization, 30.0 / 40.0);
+        assert_eq!(report.unschedulable, 1);
+        assert_eq!(report.avg_queue_latency_ms, 0);
+
+        // With one slot, "b" waits 10s for "a", and "c" 10s more for "b"
+        let report = Trace::load(temp_dir.path()).unwrap().with_workers(1, 1).simulate(StrategyKind::RoundRobin);
+        assert_eq!(report.makespan_ms, 30_000);
+        assert_eq!(report.avg_queue_latency_ms, 20_000 / 3);
+        assert_eq!(report.p95_queue_latency_ms, 10_000);
+    }
+}
@@ -0,0 +1,637 @@
+use super::eta::DurationHistory;
+use super::journal::{self, Changes, Event, Journal};
+use super::queue::JobIndex;
+use super::SchedulerState;
+use crate::common::clock;
+use crate::common::types::{JobMetadata, JobStatusEnum, WorkerMetadata};
+use crate::proto::distbuild::ClusterEventKind;
+use anyhow::{bail, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// SQLite database holding the saved jobs, workers and run times
+const STATE_DB: &str = "scheduler-state.db";
+/// JSON snapshot earlier versions saved the state to, imported on open
+const LEGACY_STATE_FILE: &str = "scheduler-state.json";
+/// Jobs forgotten under the retention policy, one JSON object per line
+const ARCHIVE_FILE: &str = "jobs-archive.jsonl";
+/// Which scheduler writes to the state dir, renewed as it flushes
+const LEASE_FILE: &str = "lease.json";
+/// A lease not renewed for this long is free for another scheduler to take
+const LEASE_SECS: i64 = 10;
+/// How long a mirror reading the database waits out the primary's writes
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS jobs (id TEXT PRIMARY KEY, record TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS workers (id TEXT PRIMARY KEY, record TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value BLOB NOT NULL);
+";
+
+#[derive(Deserialize)]
+struct LegacySnapshot {
+    workers: HashMap<String, WorkerMetadata>,
+    jobs: HashMap<String, JobMetadata>,
+    #[serde(default)]
+    durations: DurationHistory,
+    #[serde(default)]
+    journal_seq: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Lease {
+    holder: String,
+    renewed_at: i64,
+}
+
+/// Scheduler state kept in an SQLite database, a row per job and worker,
+/// plus the journal of everything that happened since it was last saved.
+/// Saving only writes the records the journal says changed.
+#[derive(Debug)]
+pub(crate) struct StateStore {
+    dir: PathBuf,
+    db: Connection,
+    holder: Option<String>, // whose lease on the dir `renew` keeps up
+}
+
+impl StateStore {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create scheduler state dir {:?}", dir))?;
+        let path = dir.join(STATE_DB);
+        let db = Connection::open(&path).with_context(|| format!("Failed to open {:?}", path))?;
+        db.busy_timeout(BUSY_TIMEOUT)?;
+        // Lets a mirror read while the primary writes
+        db.pragma_update(None, "journal_mode", "WAL")?;
+        db.execute_batch(SCHEMA).with_context(|| format!("Failed to set up {:?}", path))?;
+
+        let mut store = StateStore {
+            dir: dir.to_path_buf(),
+            db,
+            holder: None,
+        };
+        store.import_legacy()?;
+        Ok(store)
+    }
+
+    /// The state the previous run left: what was last saved, with the
+    /// journal since replayed on top, made ready to resume at `now` (see
+    /// `SchedulerState::resume`). Empty on first start. From here on the
+    /// state records its changes in the journal.
+    pub fn load(&mut self, now: i64) -> Result<SchedulerState> {
+        let (mut state, mut seq) = self.read_snapshot()?;
+        let mut replayed = Changes::default();
+        for entry in journal::read_after(&self.dir, seq)? {
+            seq = seq.max(entry.seq);
+            replayed.note(&entry.event);
+            state.apply(entry.event);
+        }
+
+        let mut journal = Journal::open(&self.dir, seq)?;
+        journal.keep_unsaved(replayed);
+        state.journal = Some(journal);
+        state.resume(now);
+        Ok(state)
+    }
+
+    /// The state as last saved (empty if nothing was yet) and the last
+    /// journal entry it reflects
+    pub fn read_snapshot(&mut self) -> Result<(SchedulerState, u64)> {
+        // One read transaction, so the records and their journal position agree
+        let tx = self.db.transaction()?;
+        let durations = match read_meta(&tx, "durations")? {
+            Some(durations) => serde_json::from_slice(&durations).context("Corrupt saved run times")?,
+            None => DurationHistory::default(),
+        };
+        let mut state = SchedulerState {
+            jobs: read_records(&tx, "jobs")?,
+            workers: read_records(&tx, "workers")?,
+            durations,
+            ..Default::default()
+        };
+        let seq = read_journal_seq(&tx)?.unwrap_or(0);
+        tx.finish()?;
+
+        state.rebuild_blob_refs();
+        state.index = JobIndex::build(state.jobs.values());
+        Ok((state, seq))
+    }
+
+    /// Save the jobs and workers `state`'s journal changed since the last
+    /// save, along with the journal position; false if nothing changed
+    pub fn save(&mut self, state: &mut SchedulerState) -> Result<bool> {
+        let Some(journal) = state.journal.as_mut() else {
+            return Ok(false);
+        };
+        let changes = journal.take_unsaved();
+        if changes.is_empty() {
+            return Ok(false);
+        }
+        let seq = journal.seq();
+
+        match self.write_changes(state, &changes, seq) {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                if let Some(journal) = state.journal.as_mut() {
+                    journal.keep_unsaved(changes);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn write_changes(&mut self, state: &SchedulerState, changes: &Changes, seq: u64) -> Result<()> {
+        let tx = self.db.transaction()?;
+        write_records(&tx, "jobs", &changes.jobs, &state.jobs)?;
+        write_records(&tx, "workers", &changes.workers, &state.workers)?;
+        // Run times only change as jobs complete, so they're saved with them
+        write_meta(&tx, "durations", serde_json::to_vec(&state.durations)?)?;
+        write_meta(&tx, "journal_seq", seq as i64)?;
+        tx.commit().context("Failed to save scheduler state")
+    }
+
+    /// Move a JSON snapshot left by an earlier version into the database
+    /// (then set it aside), unless the database already has saved state
+    fn import_legacy(&mut self) -> Result<()> {
+        let path = self.dir.join(LEGACY_STATE_FILE);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+
+        let tx = self.db.transaction()?;
+        if read_journal_seq(&tx)?.is_none() {
+            let snapshot: LegacySnapshot = serde_json::from_slice(&data)
+                .with_context(|| format!("Corrupt scheduler state in {:?}", path))?;
+            write_records(&tx, "jobs", snapshot.jobs.keys(), &snapshot.jobs)?;
+            write_records(&tx, "workers", snapshot.workers.keys(), &snapshot.workers)?;
+            write_meta(&tx, "durations", serde_json::to_vec(&snapshot.durations)?)?;
+            write_meta(&tx, "journal_seq", snapshot.journal_seq as i64)?;
+        }
+        tx.commit()?;
+
+        let imported = path.with_extension("json.imported");
+        match fs::rename(&path, &imported) {
+            // Another process opening the dir got there first
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result.with_context(|| format!("Failed to move {:?} to {:?}", path, imported)),
+        }
+    }
+
+    /// Append `jobs` to the archive of forgotten jobs
+    pub fn archive(&self, jobs: &[&JobMetadata]) -> Result<()> {
+        let path = self.dir.join(ARCHIVE_FILE);
+        let mut lines = Vec::new();
+        for job in jobs {
+            serde_json::to_writer(&mut lines, job)?;
+            lines.push(b'\n');
+        }
+        let mut file = fs::File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open job archive {:?}", path))?;
+        file.write_all(&lines)
+            .with_context(|| format!("Failed to write job archive {:?}", path))
+    }
+
+    /// Claim the dir for `holder`, since two schedulers appending to one
+    /// journal would corrupt it. Refused while another scheduler holds it,
+    /// unless `force`d by a standby taking over from a primary that no
+    /// longer answers.
+    pub fn acquire(&mut self, holder: &str, now: i64, force: bool) -> Result<()> {
+        if let Some(lease) = self.read_lease()? {
+            if !force && lease.holder != holder && now - lease.renewed_at < LEASE_SECS {
+                bail!(
+                    "Scheduler state dir {:?} is in use by {} (a standby that took over?); run this one as its standby instead",
+                    self.dir,
+                    lease.holder
+                );
+            }
+        }
+        self.holder = Some(holder.to_string());
+        self.write_lease(holder, now)
+    }
+
+    /// Keep holding the dir `acquire` claimed; false once another
+    /// scheduler has taken it over
+    pub fn renew(&self, now: i64) -> Result<bool> {
+        let Some(holder) = &self.holder else {
+            return Ok(true);
+        };
+        if self.read_lease()?.is_some_and(|lease| lease.holder != *holder) {
+            return Ok(false);
+        }
+        self.write_lease(holder, now)?;
+        Ok(true)
+    }
+
+    fn read_lease(&self) -> Result<Option<Lease>> {
+        let path = self.dir.join(LEASE_FILE);
+        match fs::read(&path) {
+            Ok(data) => Ok(serde_json::from_slice(&data).ok()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        }
+    }
+
+    fn write_lease(&self, holder: &str, now: i64) -> Result<()> {
+        let path = self.dir.join(LEASE_FILE);
+        let tmp = path.with_extension("json.tmp");
+        let lease = Lease {
+            holder: holder.to_string(),
+            renewed_at: now,
+        };
+        fs::write(&tmp, serde_json::to_vec(&lease)?).with_context(|| format!("Failed to write {:?}", tmp))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to move {:?} to {:?}", tmp, path))
+    }
+
+}
+
+/// Every row of `table`, by id
+fn read_records<T: DeserializeOwned>(tx: &Transaction, table: &str) -> Result<HashMap<String, T>> {
+    let mut statement = tx.prepare(&format!("SELECT id, record FROM {}", table))?;
+    let mut rows = statement.query([])?;
+    let mut records = HashMap::new();
+    while let Some(row) = rows.next()? {
+        let id: String = row.get(0)?;
+        let record: String = row.get(1)?;
+        let record = serde_json::from_str(&record)
+            .with_context(|| format!("Corrupt saved record {} in {}", id, table))?;
+        records.insert(id, record);
+    }
+    Ok(records)
+}
+
+/// Write the rows of `table` for `ids` from `records`, deleting those no
+/// longer there
+fn write_records<'a, T: Serialize>(
+    tx: &Transaction,
+    table: &str,
+    ids: impl IntoIterator<Item = &'a String>,
+    records: &HashMap<String, T>,
+) -> Result<()> {
+    let mut upsert = tx.prepare(&format!("INSERT OR REPLACE INTO {} (id, record) VALUES (?1, ?2)", table))?;
+    let mut delete = tx.prepare(&format!("DELETE FROM {} WHERE id = ?1", table))?;
+    for id in ids {
+        match records.get(id) {
+            Some(record) => upsert.execute(params![id, serde_json::to_string(record)?])?,
+            None => delete.execute([id])?,
+        };
+    }
+    Ok(())
+}
+
+fn read_meta(tx: &Transaction, key: &str) -> Result<Option<Vec<u8>>> {
+    Ok(tx
+        .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| row.get(0))
+        .optional()?)
+}
+
+/// Last journal entry the saved state reflects; `None` before the first save
+fn read_journal_seq(tx: &Transaction) -> Result<Option<u64>> {
+    Ok(tx
+        .query_row("SELECT value FROM meta WHERE key = 'journal_seq'", [], |row| row.get::<_, i64>(0))
+        .optional()?
+        .map(|seq| seq as u64))
+}
+
+fn write_meta(tx: &Transaction, key: &str, value: impl rusqlite::ToSql) -> Result<()> {
+    tx.execute("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)", params![key, value])?;
+    Ok(())
+}
+
+impl SchedulerState {
+    /// Redo a journaled event
+    pub(super) fn apply(&mut self, event: Event) {
+        match event {
+            Event::Job { job } => {
+                self.add_blob_ref(&job.input_hash, &job.job_id, "input");
+                if let Some(output) = &job.output_hash {
+                    self.add_blob_ref(output, &job.job_id, "output");
+                }
+                if let Some(log) = &job.log_hash {
+                    self.add_blob_ref(log, &job.job_id, "log");
+                }
+                let previous = self.jobs.get(&job.job_id).map(|previous| previous.status);
+                if previous.is_none() {
+                    self.announce_submitted(&job);
+                }
+                let job_id = job.job_id.clone();
+                let completed = job.status == JobStatusEnum::Completed && previous != Some(JobStatusEnum::Completed);
+                self.jobs.insert(job_id.clone(), *job);
+                self.index.update(&job_id, self.jobs.get(&job_id));
+                if completed {
+                    self.record_duration(&job_id);
+                }
+                self.announce_job(&job_id);
+                if let Some(job) = self.jobs.get(&job_id) {
+                    self.history.record(job, clock::now());
+                }
+            }
+            Event::WorkerRegistered { worker } => {
+                self.emit(ClusterEventKind::WorkerOnline, worker.tenant.as_deref(), &worker.worker_id, "", &worker.address);
+                self.workers.insert(worker.worker_id.clone(), *worker);
+            }
+            Event::WorkerRemoved { worker_id, reason } => {
+                if let Some(worker) = self.workers.remove(&worker_id) {
+                    self.emit(ClusterEventKind::WorkerOffline, worker.tenant.as_deref(), &worker_id, "", &reason);
+                }
+            }
+            Event::JobRemoved { job_id } => {
+                self.remove_job(&job_id);
+            }
+        }
+    }
+
+    /// Make restored state consistent with a scheduler that just started:
+    /// dispatches that were in flight are lost, so those jobs queue again,
+    /// and workers get a fresh heartbeat window to check back in. Running
+    /// jobs stay running; their workers report the results as usual.
+    fn resume(&mut self, now: i64) {
+        let interrupted: Vec<String> = self
+            .jobs
+            .values()
+            .filter(|job| job.status == JobStatusEnum::Assigned)
+            .map(|job| job.job_id.clone())
+            .collect();
+        for job_id in interrupted {
+            if let Some(job) = self.jobs.get_mut(&job_id) {
+                job.assigned_worker = None;
+                job.error = Some("Dispatch interrupted by a scheduler restart".to_string());
+                job.set_status(JobStatusEnum::Pending, now);
+            }
+            self.journal_job(&job_id);
+        }
+        for worker in self.workers.values_mut() {
+            worker.last_heartbeat = now;
+        }
+    }
+
+    fn rebuild_blob_refs(&mut self) {
+        let refs: Vec<(String, String, &'static str)> = self
+            .jobs
+            .values()
+            .flat_map(|job| {
+                let input = Some((job.input_hash.clone(), job.job_id.clone(), "input"));
+                let output = job.output_hash.clone().map(|hash| (hash, job.job_id.clone(), "output"));
+                let log = job.log_hash.clone().map(|hash| (hash, job.job_id.clone(), "log"));
+                input.into_iter().chain(output).chain(log)
+            })
+            .collect();
+        for (hash, job_id, role) in refs {
+            self.add_blob_ref(&hash, &job_id, role);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::types::JobErrorKindEnum;
+    use tempfile::TempDir;
+
+    fn job(id: &str, status: JobStatusEnum) -> JobMetadata {
+        JobMetadata {
+            job_id: id.to_string(),
+            input_hash: format!("{}-input", id),
+            output_hash: None,
+            error: None,
+            error_kind: JobErrorKindEnum::Unspecified,
+            job_type: "rust-compile".to_string(),
+            status,
+            assigned_worker: Some("w1".to_string()),
+            submitted_at: 100,
+            started_at: None,
+            completed_at: None,
+            metadata: HashMap::new(),
+            preemptions: 0,
+            worker_platform: None,
+            priority: 0,
+            timeline: Vec::new(),
+            progress: None,
+            attempts: 0,
+            attempt_history: Vec::new(),
+            retry_at: None,
+            run_after: None,
+            depends_on: Vec::new(),
+            constraints: HashMap::new(),
+            timeout_secs: None,
+            attached_to: None,
+            usage: None,
+            tenant: None,
+            session_id: None,
+            log_hash: None,
+            weight: 1,
+            pool: None,
+            boosted: false,
+            gang: None,
+        }
+    }
+
+    #[test]
+    fn test_state_survives_restart() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut store = StateStore::open(temp_dir.path()).unwrap();
+        let mut state = store.load(0).unwrap();
+        assert!(state.jobs.is_empty());
+
+        for (id, status) in [("queued", JobStatusEnum::Pending), ("dispatching", JobStatusEnum::Assigned), ("forgotten", JobStatusEnum::Completed)] {
+            state.jobs.insert(id.to_string(), job(id, status));
+            state.journal_job(id);
+        }
+        assert!(store.save(&mut state).unwrap());
+        assert!(!store.save(&mut state).unwrap());
+        state.forget_job("forgotten");
+        assert!(store.save(&mut state).unwrap());
+
+        // Changes after the last save are recovered from the journal
+        state.jobs.insert("running".to_string(), job("running", JobStatusEnum::Running));
+        state.journal_job("running");
+        state.jobs.get_mut("queued").unwrap().priority = 5;
+        state.journal_job("queued");
+        drop(state);
+        let (saved, _) = StateStore::open(temp_dir.path()).unwrap().read_snapshot().unwrap();
+        let mut ids: Vec<&str> = saved.jobs.keys().map(String::as_str).collect();
+        ids.sort();
+        assert_eq!(ids, ["dispatching", "queued"]);
+
+        let mut reopened = StateStore::open(temp_dir.path()).unwrap();
+        let mut restored = reopened.load(200).unwrap();
+        assert_eq!(restored.jobs.len(), 3);
+        assert_eq!(restored.jobs["queued"].priority, 5);
+        assert_eq!(restored.jobs["dispatching"].status, JobStatusEnum::Pending);
+        assert_eq!(restored.jobs["dispatching"].assigned_worker, None);
+        assert_eq!(restored.jobs["running"].status, JobStatusEnum::Running);
+        assert!(restored.pinned_hashes().contains("running-input"));
+        assert!(restored.blob_refs.contains_key("queued-input"));
+
+        // What was replayed is saved on the next flush
+        assert!(reopened.save(&mut restored).unwrap());
+        let (saved, seq) = reopened.read_snapshot().unwrap();
+        assert_eq!(saved.jobs.len(), 3);
+        assert_eq!(saved.jobs["queued"].priority, 5);
+        assert_eq!(seq, restored.journal.as_ref().unwrap().seq());
+    }
+
+    #[test]
+    fn test_legacy_snapshot_imported() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = serde_json::json!({
+            "workers": { "w1": worker("w1") },
+            "jobs": { "queued": job("queued", JobStatusEnum::Pending) },
+            "journal_seq": 4,
+        });
+        fs::write(temp_dir.path().join(LEGACY_STATE_FILE), legacy.to_string()).unwrap();
+
+        let (state, seq) = StateStore::open(temp_dir.path()).unwrap().read_snapshot().unwrap();
+        assert_eq!(seq, 4);
+        assert_eq!(state.jobs["queued"].status, JobStatusEnum::Pending);
+        assert!(state.workers.contains_key("w1"));
+        assert!(!temp_dir.path().join(LEGACY_STATE_FILE).exists());
+
+        // Only once: the database is what's kept up to date from then on
+        let (state, _) = StateStore::open(temp_dir.path()).unwrap().read_snapshot().unwrap();
+        assert_eq!(state.jobs.len(), 1);
+    }
+
+    fn worker(id: &str) -> WorkerMetadata {
+        WorkerMetadata {
+            worker_id: id.to_string(),
+            address: format!("{}:6001", id),
+            capacity: 4,
+            active_jobs: 0,
+            last_heartbeat: 100,
+            labels: HashMap::new(),
+            version: None,
+            draining: false,
+            quarantined_until: None,
+            tenant: None,
+            capabilities: None,
+            pool: None,
+            state: Default::default(),
+            state_since: 0,
+            cached_hashes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_journal_replay() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut state = StateStore::open(temp_dir.path()).unwrap().load(0).unwrap();
+        for id in ["w1", "w2"] {
+            state.workers.insert(id.to_string(), worker(id));
+            state.journal(Event::WorkerRegistered { worker: Box::new(worker(id)) });
+        }
+        let mut queued = job("queued", JobStatusEnum::Pending);
+        queued.assigned_worker = None;
+        let mut done = job("done", JobStatusEnum::Completed);
+        done.output_hash = Some("done-output".to_string());
+        done.assigned_worker = Some("w2".to_string());
+        for job in [queued, job("running", JobStatusEnum::Running), done] {
+            let job_id = job.job_id.clone();
+            state.jobs.insert(job_id.clone(), job);
+            state.journal_job(&job_id);
+        }
+        // The worker running a job goes away, so the job is queued again
+        assert_eq!(state.remove_worker("w1", "drained", 150), 1);
+        drop(state);
+
+        // A crash mid-write leaves the last record half written
+        let mut journal = fs::File::options().append(true).open(temp_dir.path().join("journal.jsonl")).unwrap();
+        journal.write_all(br#"{"seq":8,"at":160,"event":"job","job":{"job_id":"torn","#).unwrap();
+        drop(journal);
+
+        // No snapshot was ever written: everything comes from the journal
+        let mut restored = StateStore::open(temp_dir.path()).unwrap().load(200).unwrap();
+        let workers: Vec<&str> = restored.workers.keys().map(String::as_str).collect();
+        assert_eq!(workers, ["w2"]);
+        assert_eq!(restored.workers["w2"].last_heartbeat, 200);
+        let mut jobs: Vec<&str> = restored.jobs.keys().map(String::as_str).collect();
+        jobs.sort();
+        assert_eq!(jobs, ["done", "queued", "running"]);
+        let running = &restored.jobs["running"];
+        assert_eq!((running.status, running.assigned_worker.as_deref()), (JobStatusEnum::Pending, None));
+        assert_eq!(running.preemptions, 1);
+        assert_eq!(restored.jobs["done"].output_hash.as_deref(), Some("done-output"));
+        assert_eq!(restored.index.with_status(JobStatusEnum::Pending).count(), 2);
+        assert!(restored.blob_refs.contains_key("done-output"));
+        // New entries carry on from the last complete one, on a line of
+        // their own, so they survive the next restart
+        assert_eq!(restored.journal.as_ref().unwrap().seq(), 7);
+        restored.jobs.get_mut("queued").unwrap().priority = 5;
+        restored.journal_job("queued");
+        drop(restored);
+
+        let restored = StateStore::open(temp_dir.path()).unwrap().load(300).unwrap();
+        assert_eq!(restored.jobs["queued"].priority, 5);
+        assert_eq!(restored.journal.as_ref().unwrap().seq(), 8);
+        let journal = fs::read_to_string(temp_dir.path().join("journal.jsonl")).unwrap();
+        assert!(!journal.contains("torn"));
+    }
+
+    #[test]
+    fn test_forgotten_jobs_stay_gone() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut store = StateStore::open(temp_dir.path()).unwrap();
+        let mut state = store.load(0).unwrap();
+
+        for (id, completed_at) in [("oldest", 10), ("old", 20), ("needed", 5), ("recent", 90)] {
+            let mut job = job(id, JobStatusEnum::Completed);
+            job.completed_at = Some(completed_at);
+            state.jobs.insert(id.to_string(), job);
+            state.journal_job(id);
+        }
+        let mut blocked = job("blocked", JobStatusEnum::Blocked);
+        blocked.depends_on = vec!["needed".to_string(), "running".to_string()];
+        state.jobs.insert("blocked".to_string(), blocked);
+        state.journal_job("blocked");
+
+        // Over 50s old, or beyond the newest 2; "needed" is kept either way
+        assert_eq!(state.expired_jobs(50, 0, 100), ["oldest", "old"]);
+        assert_eq!(state.expired_jobs(0, 2, 100), ["oldest"]);
+        assert!(state.expired_jobs(0, 0, 100).is_empty());
+
+        let expired = state.expired_jobs(50, 0, 100);
+        store.archive(&expired.iter().map(|id| &state.jobs[id]).collect::<Vec<_>>()).unwrap();
+        for job_id in &expired {
+            state.forget_job(job_id);
+        }
+        assert!(!state.blob_refs.contains_key("old-input"));
+        drop(state);
+
+        let archived = fs::read_to_string(temp_dir.path().join(ARCHIVE_FILE)).unwrap();
+        assert_eq!(archived.lines().count(), 2);
+        let restored = StateStore::open(temp_dir.path()).unwrap().load(100).unwrap();
+        let mut ids: Vec<&str> = restored.jobs.keys().map(String::as_str).collect();
+        ids.sort();
+        assert_eq!(ids, ["blocked", "needed", "recent"]);
+        assert_eq!(restored.expired_jobs(50, 0, 100), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_lease_fences_out_old_primary() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut primary = StateStore::open(temp_dir.path()).unwrap();
+        primary.acquire("a/10.0.0.1:5000", 100, false).unwrap();
+
+        // Restarting under the same name is fine; another scheduler has
+        // to wait for the lease to lapse, or force it as a standby
+        StateStore::open(temp_dir.path()).unwrap().acquire("a/10.0.0.1:5000", 101, false).unwrap();
+        let mut standby = StateStore::open(temp_dir.path()).unwrap();
+        assert!(standby.acquire("b/10.0.0.2:5000", 105, false).is_err());
+        standby.acquire("b/10.0.0.2:5000", 105, true).unwrap();
+
+        assert!(!primary.renew(106).unwrap());
+        assert!(standby.renew(106).unwrap());
+        assert!(primary.acquire("a/10.0.0.1:5000", 110, false).is_err());
+        primary.acquire("a/10.0.0.1:5000", 106 + LEASE_SECS, false).unwrap();
+    }
+}
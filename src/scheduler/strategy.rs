@@ -0,0 +1,220 @@
+use crate::common::config::StrategyKind;
+use crate::common::platform::Capabilities;
+use crate::common::types::JobMetadata;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+
+/// A worker with room for at least one more job
+#[derive(Debug, Clone)]
+pub(crate) struct Candidate {
+    pub id: String,
+    pub address: String,
+    /// Slots in use: the weights of its jobs, or as many as it says it's
+    /// running if that's more
+    pub active_jobs: u32,
+    pub capacity: u32,
+    pub labels: HashMap<String, String>,
+    pub cached_hashes: Arc<HashSet<String>>,
+    /// Only takes this tenant's jobs when set
+    pub tenant: Option<String>,
+    /// What it reported it can build with, if anything
+    pub capabilities: Option<Capabilities>,
+    /// Its health score, 0 to 1 (see `HealthTracker`)
+    pub health: f64,
+    pub pool: Option<String>,
+}
+
+impl Candidate {
+    /// Whether this worker has every label a job requires, as a label or
+    /// (for `target`, `rustc` and `host`) among its reported capabilities
+    pub fn satisfies(&self, constraints: &HashMap<String, String>) -> bool {
+        constraints.iter().all(|(key, value)| {
+            self.labels.get(key) == Some(value)
+                || self.capabilities.as_ref().is_some_and(|c| c.provide(key, value))
+        })
+    }
+
+    /// Whether this worker runs jobs of `job_type`, and has `target` (the
+    /// one a job compiles for, if not the host's) installed. Workers that
+    /// didn't report capabilities are taken to
+    pub fn runs(&self, job_type: &str, target: Option<&str>) -> bool {
+        self.capabilities.as_ref().is_none_or(|c| {
+            c.runs(job_type) && target.is_none_or(|target| c.provide("target", target))
+        })
+    }
+
+    /// Whether this worker may run `job` at all: has the labels and
+    /// capabilities it needs, serves its tenant and is in its pool, if
+    /// it's sent to one
+    pub fn accepts(&self, job: &JobMetadata) -> bool {
+        self.satisfies(&job.constraints)
+            && self.serves(job.tenant.as_deref())
+            && job.pool.as_ref().is_none_or(|pool| self.pool.as_ref() == Some(pool))
+            && self.runs(&job.job_type, job.metadata.get("target").map(String::as_str))
+    }
+
+    /// Whether this worker may run a job of `tenant`
+    pub fn serves(&self, tenant: Option<&str>) -> bool {
+        self.tenant.is_none() || self.tenant.as_deref() == tenant
+    }
+
+    /// Whether this worker has room for a job taking up `weight` slots; one
+    /// heavier than its whole capacity fits once the worker is idle
+    pub fn fits(&self, weight: u32) -> bool {
+        self.active_jobs + weight.min(self.capacity) <= self.capacity
+    }
+
+    /// How many of the blobs a job needs this worker already has
+    pub fn locality(&self, hashes: &[String]) -> usize {
+        hashes.iter().filter(|hash| self.cached_hashes.contains(*hash)).count()
+    }
+}
+
+/// Decides which worker each queued job is dispatched to
+pub(crate) trait SchedulingStrategy: Send + Sync {
+    /// Index into `candidates` (never empty) of the worker for the next job
+    fn pick(&mut self, candidates: &[Candidate]) -> usize;
+}
+
+/// Index into `candidates` of the worker `strategy` picks among the
+/// `eligible` ones; `None` if there are none
+pub(crate) fn pick_among(strategy: &mut dyn SchedulingStrategy, candidates: &[Candidate], eligible: &[usize]) -> Option<usize> {
+    match eligible.len() {
+        0 => None,
+        n if n == candidates.len() => Some(strategy.pick(candidates)),
+        _ => {
+            let subset: Vec<Candidate> = eligible.iter().map(|&idx| candidates[idx].clone()).collect();
+            Some(eligible[strategy.pick(&subset)])
+        }
+    }
+}
+
+/// The strategy configured as `[scheduler] strategy`
+pub(crate) fn from_kind(kind: StrategyKind) -> Box<dyn SchedulingStrategy> {
+    match kind {
+        StrategyKind::LeastLoaded => Box::new(LeastLoaded),
+        StrategyKind::RoundRobin => Box::new(RoundRobin::default()),
+        StrategyKind::Random => Box::new(Random::default()),
+    }
+}
+
+/// Health scores below this are taken as this, so a worker scored 0 still
+/// ranks by its load
+const MIN_HEALTH: f64 = 0.05;
+
+/// The worker using the smallest share of its capacity, that share
+/// weighed up the less healthy the worker is
+struct LeastLoaded;
+
+impl SchedulingStrategy for LeastLoaded {
+    fn pick(&mut self, candidates: &[Candidate]) -> usize {
+        let load = |c: &Candidate| c.active_jobs as f64 / c.capacity.max(1) as f64 / c.health.max(MIN_HEALTH);
+        candidates
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| load(a).total_cmp(&load(b)))
+            .map_or(0, |(idx, _)| idx)
+    }
+}
+
+/// Each worker in turn
+#[derive(Default)]
+struct RoundRobin {
+    next: usize,
+}
+
+impl SchedulingStrategy for RoundRobin {
+    fn pick(&mut self, candidates: &[Candidate]) -> usize {
+        let idx = self.next % candidates.len();
+        self.next = self.next.wrapping_add(1);
+        idx
+    }
+}
+
+/// Any worker, uniformly
+#[derive(Default)]
+struct Random {
+    seed: RandomState, // randomly keyed per process
+    count: u64,
+}
+
+impl SchedulingStrategy for Random {
+    fn pick(&mut self, candidates: &[Candidate]) -> usize {
+        let mut hasher = self.seed.build_hasher();
+        hasher.write_u64(self.count);
+        self.count += 1;
+        (hasher.finish() % candidates.len() as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: &str, active_jobs: u32, capacity: u32) -> Candidate {
+        Candidate {
+            id: id.to_string(),
+            address: format!("{}:6001", id),
+            active_jobs,
+            capacity,
+            labels: HashMap::from([("target".to_string(), "x86_64-unknown-linux-gnu".to_string())]),
+            cached_hashes: Arc::new(HashSet::from([format!("{}-input", id)])),
+            tenant: None,
+            capabilities: None,
+            health: 1.0,
+            pool: None,
+        }
+    }
+
+    #[test]
+    fn test_strategies() {
+        let candidates = vec![candidate("a", 3, 4), candidate("b", 1, 2), candidate("c", 1, 8)];
+        assert_eq!(from_kind(StrategyKind::LeastLoaded).pick(&candidates), 2);
+        let mut ailing = candidates.clone();
+        ailing[2].health = 0.1;
+        assert_eq!(from_kind(StrategyKind::LeastLoaded).pick(&ailing), 1);
+
+        let mut round_robin = from_kind(StrategyKind::RoundRobin);
+        let picks: Vec<usize> = (0..4).map(|_| round_robin.pick(&candidates)).collect();
+        assert_eq!(picks, vec![0, 1, 2, 0]);
+
+        let mut random = from_kind(StrategyKind::Random);
+        assert!((0..20).all(|_| random.pick(&candidates) < candidates.len()));
+    }
+
+    #[test]
+    fn test_constraints() {
+        let worker = candidate("a", 0, 4);
+        assert!(worker.satisfies(&HashMap::new()));
+        let target = |triple: &str| HashMap::from([("target".to_string(), triple.to_string())]);
+        assert!(worker.satisfies(&target("x86_64-unknown-linux-gnu")));
+        assert!(!worker.satisfies(&target("aarch64-apple-darwin")));
+        assert!(!worker.satisfies(&HashMap::from([("rustc".to_string(), "1.78".to_string())])));
+
+        let needed = vec!["a-input".to_string(), "b-input".to_string()];
+        assert_eq!(worker.locality(&needed), 1);
+        assert_eq!(candidate("c", 0, 4).locality(&needed), 0);
+    }
+
+    #[test]
+    fn test_capabilities_matching() {
+        let mut worker = candidate("a", 0, 4);
+        assert!(worker.runs("rust-test", Some("wasm32-unknown-unknown")));
+
+        worker.capabilities = Some(Capabilities {
+            rustc_versions: vec!["1.78.0".to_string()],
+            host_triple: "x86_64-unknown-linux-gnu".to_string(),
+            targets: vec!["x86_64-unknown-linux-gnu".to_string()],
+            disk_free_bytes: 0,
+            job_types: vec!["rust-compile".to_string()],
+        });
+        assert!(worker.satisfies(&HashMap::from([("rustc".to_string(), "1.78".to_string())])));
+        assert!(!worker.satisfies(&HashMap::from([("rustc".to_string(), "1.80".to_string())])));
+        assert!(worker.runs("rust-compile", None));
+        assert!(worker.runs("rust-compile", Some("x86_64-unknown-linux-gnu")));
+        assert!(!worker.runs("rust-compile", Some("wasm32-unknown-unknown")));
+        assert!(!worker.runs("rust-test", None));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::common::config::{WebhookConfig, WebhookEvent};
+use crate::common::types::{JobMetadata, JobStatusEnum};
+use crate::proto::distbuild::SessionInfo;
+use log::{debug, warn};
+use ring::hmac;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Longest a webhook endpoint gets to answer one delivery
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Wait before the second try of a delivery, doubled for each after
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Tells the `[[scheduler.webhooks]]` endpoints about jobs completing or
+/// failing, and build sessions finishing: once none of a session's jobs has
+/// been queued or running for `session_idle_secs`. Deliveries run in the
+/// background, so a slow endpoint never holds up scheduling.
+#[derive(Debug, Default)]
+pub(crate) struct Webhooks {
+    hooks: Vec<WebhookConfig>,
+    client: reqwest::Client,
+    idle_secs: i64,
+    /// Finished jobs already sent, so journaling one again doesn't repeat it
+    announced: HashSet<String>,
+    /// Sessions whose last job finished, and when; dropped again when one
+    /// of their jobs is queued or starts
+    idle_sessions: HashMap<String, i64>,
+}
+
+impl Webhooks {
+    pub fn new(hooks: Vec<WebhookConfig>, idle_secs: u64) -> Self {
+        Webhooks {
+            hooks,
+            idle_secs: idle_secs as i64,
+            ..Default::default()
+        }
+    }
+
+    /// Note `job` as it is now, sending its event if it just completed or
+    /// failed
+    pub fn job_changed(&mut self, job: &JobMetadata, now: i64) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        if !job.status.is_terminal() {
+            self.announced.remove(&job.job_id);
+            if let Some(session_id) = &job.session_id {
+                self.idle_sessions.remove(session_id);
+            }
+            return;
+        }
+        if !self.announced.insert(job.job_id.clone()) {
+            return;
+        }
+        if let Some(session_id) = job.session_id.as_ref().filter(|_| self.wanted(WebhookEvent::SessionFinished)) {
+            self.idle_sessions.insert(session_id.clone(), now);
+        }
+
+        let event = match job.status {
+            JobStatusEnum::Completed => WebhookEvent::JobCompleted,
+            JobStatusEnum::Failed => WebhookEvent::JobFailed,
+            _ => return,
+        };
+        self.send(event, now, json!({ "job": job_payload(job) }));
+    }
+
+    /// `job_id` is gone for good
+    pub fn forget(&mut self, job_id: &str) {
+        self.announced.remove(job_id);
+    }
+
+    /// Sessions idle for `session_idle_secs` as of `now`, no longer tracked;
+    /// the caller checks they're really finished before `session_finished`
+    pub fn due_sessions(&mut self, now: i64) -> Vec<String> {
+        let due: Vec<String> = self
+            .idle_sessions
+            .iter()
+            .filter(|&(_, &since)| now - since >= self.idle_secs)
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+        for session_id in &due {
+            self.idle_sessions.remove(session_id);
+        }
+        due
+    }
+
+    pub fn session_finished(&self, session: &SessionInfo, now: i64) {
+        let status = JobStatusEnum::try_from(session.status).map_or_else(|_| String::new(), |status| status.to_string());
+        let payload = json!({
+            "session": {
+                "session_id": session.session_id,
+                "tenant": session.tenant,
+                "status": status,
+                "total": session.total,
+                "completed": session.completed,
+                "failed": session.failed,
+                "cancelled": session.cancelled,
+                "started_at": session.started_at,
+                "finished_at": session.finished_at,
+            }
+        });
+        self.send(WebhookEvent::SessionFinished, now, payload);
+    }
+
+    fn wanted(&self, event: WebhookEvent) -> bool {
+        self.hooks.iter().any(|hook| hook.wants(event))
+    }
+
+    /// POST `event` with `payload` (plus the event and time) to every hook
+    /// that wants it
+    fn send(&self, event: WebhookEvent, now: i64, mut payload: serde_json::Value) {
+        payload["event"] = json!(event.as_str());
+        payload["at"] = json!(now);
+        let body = payload.to_string();
+        for hook in self.hooks.iter().filter(|hook| hook.wants(event)) {
+            tokio::spawn(deliver(self.client.clone(), hook.clone(), event, body.clone()));
+        }
+    }
+}
+
+fn job_payload(job: &JobMetadata) -> serde_json::Value {
+    json!({
+        "job_id": job.job_id,
+        "status": job.status.to_string(),
+        "job_type": job.job_type,
+        "crate_name": job.metadata.get("crate_name"),
+        "tenant": job.tenant,
+        "session_id": job.session_id,
+        "worker": job.assigned_worker,
+        "output_hash": job.output_hash,
+        "error": job.error,
+        "submitted_at": job.submitted_at,
+        "completed_at": job.completed_at,
+    })
+}
+
+/// Try `body` on `hook` until it answers with a 2xx, backing off between
+/// tries, up to its `max_attempts`. Every try carries the same delivery ID,
+/// so the receiver can tell retries apart from new events.
+async fn deliver(client: reqwest::Client, hook: WebhookConfig, event: WebhookEvent, body: String) {
+    let delivery = uuid::Uuid::new_v4().to_string();
+    let signature = hook.secret.as_deref().map(|secret| format!("sha256={}", sign(secret, body.as_bytes())));
+    let attempts = hook.max_attempts.max(1);
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=attempts {
+        let mut request = client
+            .post(&hook.url)
+            .timeout(DELIVERY_TIMEOUT)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Distbuild-Event", event.as_str())
+            .header("X-Distbuild-Delivery", &delivery)
+            .body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header("X-Distbuild-Signature", signature);
+        }
+        let problem = match request.send().await {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => format!("HTTP {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt == attempts {
+            warn!("⚠️  Gave up on {} webhook to {} after {} tries: {}", event.as_str(), hook.url, attempts, problem);
+            return;
+        }
+        debug!("{} webhook to {} failed ({}), retrying in {:?}", event.as_str(), hook.url, problem, backoff);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Hex HMAC-SHA256 of `body` under `secret`
+fn sign(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hex::encode(hmac::sign(&key, body).as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_is_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    fn job(id: &str, status: JobStatusEnum) -> JobMetadata {
+        JobMetadata {
+            job_id: id.to_string(),
+            input_hash: String::new(),
+            output_hash: None,
+            error: None,
+            error_kind: Default::default(),
+            job_type: "rust-compile".to_string(),
+            status,
+            assigned_worker: None,
+            submitted_at: 0,
+            started_at: None,
+            completed_at: None,
+            metadata: HashMap::new(),
+            preemptions: 0,
+            worker_platform: None,
+            priority: 0,
+            timeline: Vec::new(),
+            progress: None,
+            attempts: 0,
+            attempt_history: Vec::new(),
+            retry_at: None,
+            run_after: None,
+            depends_on: Vec::new(),
+            constraints: HashMap::new(),
+            timeout_secs: None,
+            attached_to: None,
+            usage: None,
+            tenant: None,
+            session_id: Some("build-1".to_string()),
+            log_hash: None,
+            weight: 1,
+            pool: None,
+            boosted: false,
+            gang: None,
+        }
+    }
+
+    #[test]
+    fn test_sessions_idle_until_due() {
+        let hooks = vec![WebhookConfig {
+            url: "http://127.0.0.1:9/hook".to_string(),
+            secret: None,
+            events: vec![WebhookEvent::SessionFinished],
+            max_attempts: 1,
+        }];
+        let mut webhooks = Webhooks::new(hooks, 30);
+
+        webhooks.job_changed(&job("a", JobStatusEnum::Completed), 100);
+        assert!(webhooks.due_sessions(120).is_empty());
+        // Another job of the session queued puts off the session's end
+        webhooks.job_changed(&job("b", JobStatusEnum::Pending), 125);
+        assert!(webhooks.due_sessions(140).is_empty());
+
+        webhooks.job_changed(&job("b", JobStatusEnum::Failed), 150);
+        // Journaled again, but already sent
+        webhooks.job_changed(&job("b", JobStatusEnum::Failed), 170);
+        assert!(webhooks.due_sessions(170).is_empty());
+        assert_eq!(webhooks.due_sessions(180), ["build-1"]);
+        assert!(webhooks.due_sessions(300).is_empty());
+    }
+}
@@ -1,45 +1,134 @@
+use crate::cas::replication::Replicator;
+use crate::cas::service::BlobStoreService;
 use crate::cas::Cas;
-use crate::common::Config;
+use crate::common::auth::{self, Authenticator, ClientAuth};
+use crate::common::config::TlsConfig;
+use crate::common::platform::{Capabilities, Platform};
+use crate::common::types::JobErrorKindEnum;
+use crate::common::version::BuildVersion;
+use crate::common::{Config, DistbuildError};
 use crate::proto::distbuild::*;
-use crate::proto::distbuild::scheduler_client::SchedulerClient;
 use crate::proto::distbuild::worker_server::{Worker, WorkerServer};
+use sandbox::SandboxPool;
 use anyhow::{Context, Result};
-use std::collections::HashMap;
+use log::{error, info, warn};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio::time::{interval, Duration};
+use tokio::time::{sleep, Duration, Instant};
+use tonic::service::interceptor::InterceptedService;
 use tonic::{transport::Server, Request, Response, Status};
 
+pub mod sandbox;
+
 pub struct WorkerService {
     worker_id: String,
     address: String,
     capacity: u32,
+    prefetch_parallelism: usize,
+    heartbeat_interval_secs: Arc<AtomicU64>, // may be adjusted by the scheduler
+    platform: Platform,
+    labels: HashMap<String, String>, // advertised for jobs' constraints
+    tenant: Option<String>, // only runs this tenant's jobs when set
+    pool: Option<String>, // jobs sent to this pool may run here
+    capabilities: Capabilities, // toolchains, targets and job types, detected at startup
+    replicator: Replicator,
+    sandboxes: Arc<SandboxPool>,
     cas: Arc<Cas>,
-    scheduler_addr: String,
+    scheduler_urls: Vec<String>, // primary first, then fallbacks
+    auth: ClientAuth,
+    tls: Option<TlsConfig>, // served with when set
+    authenticator: Authenticator, // checks the scheduler's and peers' tokens
     state: Arc<RwLock<WorkerState>>,
 }
 
 #[derive(Default)]
 struct WorkerState {
     active_jobs: HashMap<String, JobInfo>,
+    cached_hashes: VecDeque<String>, // most recent last, advertised for locality
+    scheduler_away: bool, // restarting with its state saved; results are held until it's back
+}
+
+impl WorkerState {
+    /// Note that `hash` is in the local CAS now
+    fn remember_blob(&mut self, hash: &str) {
+        self.cached_hashes.retain(|cached| cached != hash);
+        if self.cached_hashes.len() >= MAX_ADVERTISED_BLOBS {
+            self.cached_hashes.pop_front();
+        }
+        self.cached_hashes.push_back(hash.to_string());
+    }
 }
 
 #[derive(Debug, Clone)]
 struct JobInfo {
     job_id: String,
-    status: String,
+    phase: &'static str,
+    started: Instant,
+    /// Compiler process running the job, once there is one
+    pid: Option<u32>,
+    /// The scheduler no longer wants it; stops at the next phase
+    aborted: bool,
 }
 
+/// How often the watchdog reports each running job to the scheduler
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+/// Recently used blobs reported in heartbeats, for the scheduler to place
+/// jobs needing them here
+const MAX_ADVERTISED_BLOBS: usize = 512;
+/// How long a result is held for a restarting scheduler before giving up
+const RESULT_HOLD: Duration = Duration::from_secs(15 * 60);
+/// How often a held result is resent
+const RESULT_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// Most of a job's log kept (its end, where errors are)
+const MAX_LOG_BYTES: usize = 1024 * 1024;
+/// Bounds on a heartbeat interval the scheduler suggests, so a typo in its
+/// config can't make workers flood it or look dead
+const MIN_HEARTBEAT_INTERVAL_SECS: u64 = 1;
+const MAX_HEARTBEAT_INTERVAL_SECS: u64 = 60;
+
 impl WorkerService {
-    pub fn new(worker_id: String, address: String, config: Config, cas: Arc<Cas>) -> Self {
-        WorkerService {
+    pub fn new(worker_id: String, address: String, config: Config, cas: Arc<Cas>) -> Result<Self> {
+        let auth = ClientAuth::from_config(&config.scheduler)?;
+        let replicator = Replicator::new(&config.cas.replication, &config.scheduler.addr, &address)
+            .with_remote(&config.cas.remote)
+            .with_auth(auth.clone());
+        let sandbox_root = match &config.worker.sandbox_root {
+            Some(root) => std::path::PathBuf::from(root).join(&worker_id),
+            None => std::env::temp_dir().join("cargo-distbuild-sandboxes").join(&worker_id),
+        };
+        let sandboxes = SandboxPool::new(
+            sandbox_root.clone(),
+            config.worker.sandbox_pool_size,
+            config.worker.sandbox_paranoid_wipe,
+        )?;
+        let capabilities = Capabilities::detect(&sandbox_root, config.worker.job_types.clone());
+        let platform = Platform::detect();
+        let mut labels = platform.labels();
+        labels.extend(config.worker.labels.clone());
+        Ok(WorkerService {
             worker_id,
             address,
             capacity: config.worker.capacity,
+            prefetch_parallelism: config.cas.prefetch_parallelism,
+            heartbeat_interval_secs: Arc::new(AtomicU64::new(config.worker.heartbeat_interval_secs.max(1))),
+            platform,
+            labels,
+            tenant: config.worker.tenant.clone().filter(|t| !t.is_empty()),
+            pool: config.worker.pool.clone().filter(|p| !p.is_empty()),
+            capabilities,
+            replicator,
+            sandboxes,
             cas,
-            scheduler_addr: format!("http://{}", config.scheduler.addr),
+            scheduler_urls: config.scheduler.urls(),
+            auth,
+            tls: config.scheduler.tls.clone(),
+            authenticator: Authenticator::from_config(&config.scheduler),
             state: Arc::new(RwLock::new(WorkerState::default())),
-        }
+        })
     }
 
     /// Run the worker (gRPC server + heartbeat loop)
@@ -54,16 +143,29 @@ impl WorkerService {
         let heartbeat_worker = self.clone_for_heartbeat();
         tokio::spawn(async move {
             if let Err(e) = heartbeat_worker.heartbeat_loop().await {
-                eprintln!("❌ Heartbeat loop error: {}", e);
+                error!("❌ Heartbeat loop error: {}", e);
             }
         });
 
+        // Tell the scheduler which jobs are still making progress
+        let watchdog = self.clone_for_heartbeat();
+        tokio::spawn(async move { watchdog.watchdog_loop().await });
+
         // Start gRPC server
         let addr = address.parse()?;
-        println!("🔧 Worker {} listening on {}", worker_id, addr);
+        info!("🔧 Worker {} listening on {}", worker_id, addr);
 
-        Server::builder()
-            .add_service(WorkerServer::new(self))
+        // Peers fetch from and replicate into this worker's CAS
+        let blob_store = InterceptedService::new(BlobStoreService::server(self.cas.clone()), self.authenticator.clone());
+
+        let mut server = Server::builder();
+        if let Some(tls) = &self.tls {
+            server = server.tls_config(auth::server_tls(tls)?)?;
+        }
+        let authenticator = self.authenticator.clone();
+        server
+            .add_service(WorkerServer::with_interceptor(self, authenticator))
+            .add_service(blob_store)
             .serve(addr)
             .await?;
 
@@ -75,14 +177,26 @@ impl WorkerService {
             worker_id: self.worker_id.clone(),
             address: self.address.clone(),
             capacity: self.capacity,
+            prefetch_parallelism: self.prefetch_parallelism,
+            heartbeat_interval_secs: self.heartbeat_interval_secs.clone(),
+            platform: self.platform.clone(),
+            labels: self.labels.clone(),
+            tenant: self.tenant.clone(),
+            pool: self.pool.clone(),
+            capabilities: self.capabilities.clone(),
+            replicator: self.replicator.clone(),
+            sandboxes: self.sandboxes.clone(),
             cas: self.cas.clone(),
-            scheduler_addr: self.scheduler_addr.clone(),
+            scheduler_urls: self.scheduler_urls.clone(),
+            auth: self.auth.clone(),
+            tls: self.tls.clone(),
+            authenticator: self.authenticator.clone(),
             state: self.state.clone(),
         }
     }
 
     async fn register(&self) -> Result<()> {
-        let mut client = SchedulerClient::connect(self.scheduler_addr.clone())
+        let mut client = self.auth.connect_any(&self.scheduler_urls)
             .await
             .context("Failed to connect to scheduler")?;
 
@@ -90,35 +204,56 @@ impl WorkerService {
             worker_id: self.worker_id.clone(),
             address: self.address.clone(),
             capacity: self.capacity,
-            labels: HashMap::new(),
+            labels: self.labels.clone(),
+            version: Some(BuildVersion::current().into()),
+            tenant: self.tenant.clone().unwrap_or_default(),
+            capabilities: Some(self.capabilities.clone().into()),
+            pool: self.pool.clone().unwrap_or_default(),
         };
 
         let response = client.register_worker(request).await?;
         let resp = response.into_inner();
 
         if resp.success {
-            println!("✅ Registered with scheduler: {}", resp.message);
+            info!("✅ Registered with scheduler: {}", resp.message);
         } else {
             anyhow::bail!("Failed to register: {}", resp.message);
         }
+        self.adopt_heartbeat_interval(resp.heartbeat_interval_secs);
 
         Ok(())
     }
 
-    async fn heartbeat_loop(&self) -> Result<()> {
-        let mut interval = interval(Duration::from_secs(10));
+    /// Heartbeat every `suggested` seconds from now on, as the scheduler
+    /// asks (within bounds); 0 keeps the current interval
+    fn adopt_heartbeat_interval(&self, suggested: u32) {
+        if suggested == 0 {
+            return;
+        }
+        let secs = (suggested as u64).clamp(MIN_HEARTBEAT_INTERVAL_SECS, MAX_HEARTBEAT_INTERVAL_SECS);
+        if secs != suggested as u64 {
+            warn!("⚠️  Scheduler suggested a {}s heartbeat interval; using {}s", suggested, secs);
+        }
+        let previous = self.heartbeat_interval_secs.swap(secs, Ordering::Relaxed);
+        if previous != secs {
+            info!("💓 Heartbeat interval set to {}s by scheduler", secs);
+        }
+    }
 
+    async fn heartbeat_loop(&self) -> Result<()> {
         loop {
-            interval.tick().await;
-
             if let Err(e) = self.send_heartbeat().await {
-                eprintln!("❌ Heartbeat failed: {}", e);
+                error!("❌ Heartbeat failed: {}", e);
             }
+
+            // Re-read every time: the scheduler may have suggested a new interval
+            let secs = self.heartbeat_interval_secs.load(Ordering::Relaxed);
+            sleep(Duration::from_secs(secs)).await;
         }
     }
 
     async fn send_heartbeat(&self) -> Result<()> {
-        let mut client = SchedulerClient::connect(self.scheduler_addr.clone()).await?;
+        let mut client = self.auth.connect_any(&self.scheduler_urls).await?;
 
         let state = self.state.read().await;
         let active_jobs = state.active_jobs.len() as u32;
@@ -128,20 +263,31 @@ impl WorkerService {
             worker_id: self.worker_id.clone(),
             active_jobs,
             available_slots,
+            version: Some(BuildVersion::current().into()),
+            cached_hashes: state.cached_hashes.iter().cloned().collect(),
         };
 
+        drop(state);
         let response = client.heartbeat(request).await?;
         let resp = response.into_inner();
 
+        let mut state = self.state.write().await;
+        if std::mem::take(&mut state.scheduler_away) {
+            info!("🔌 Scheduler is back");
+        }
+        drop(state);
+
+        self.adopt_heartbeat_interval(resp.heartbeat_interval_secs);
+
         if !resp.jobs_to_execute.is_empty() {
-            println!("📋 Received {} jobs to execute", resp.jobs_to_execute.len());
+            info!("📋 Received {} jobs to execute", resp.jobs_to_execute.len());
             
             // Execute jobs asynchronously
             for job_id in resp.jobs_to_execute {
                 let worker = self.clone_for_heartbeat();
                 tokio::spawn(async move {
                     if let Err(e) = worker.execute_job_by_id(&job_id).await {
-                        eprintln!("❌ Job {} execution failed: {}", job_id, e);
+                        error!("❌ Job {} execution failed: {}", job_id, e);
                     }
                 });
             }
@@ -150,40 +296,253 @@ impl WorkerService {
         Ok(())
     }
 
+    async fn watchdog_loop(&self) {
+        loop {
+            sleep(PROGRESS_INTERVAL).await;
+            let jobs: Vec<JobInfo> = self.state.read().await.active_jobs.values().cloned().collect();
+            if jobs.is_empty() {
+                continue;
+            }
+            if let Err(e) = self.report_progress(jobs).await {
+                warn!("⚠️  Failed to report job progress: {}", e);
+            }
+        }
+    }
+
+    async fn report_progress(&self, jobs: Vec<JobInfo>) -> Result<()> {
+        let mut client = self.auth.connect_any(&self.scheduler_urls).await?;
+        for job in jobs {
+            let request = ReportJobProgressRequest {
+                worker_id: self.worker_id.clone(),
+                job_id: job.job_id.clone(),
+                phase: job.phase.to_string(),
+                elapsed_secs: job.started.elapsed().as_secs(),
+                pid: job.pid.unwrap_or(0),
+                process_alive: job.pid.is_none_or(process_alive),
+            };
+            let resp = client.report_job_progress(request).await?.into_inner();
+            if !resp.keep_running {
+                warn!("⚠️  Scheduler no longer expects job {} from this worker; aborting it", job.job_id);
+                self.abort(&job.job_id).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record what a running job is doing, for the watchdog. Each phase
+    /// is also where an aborted job stops.
+    async fn set_phase(&self, job_id: &str, phase: &'static str) -> Result<()> {
+        if let Some(job) = self.state.write().await.active_jobs.get_mut(job_id) {
+            if job.aborted {
+                anyhow::bail!("Job {} was aborted before {}", job_id, phase);
+            }
+            job.phase = phase;
+        }
+        Ok(())
+    }
+
+    /// Stop `job_id` at its next phase, killing its compiler if it has one
+    /// running; false if it isn't running here
+    async fn abort(&self, job_id: &str) -> bool {
+        let mut state = self.state.write().await;
+        let Some(job) = state.active_jobs.get_mut(job_id) else {
+            return false;
+        };
+        job.aborted = true;
+        if let Some(pid) = job.pid.take() {
+            if let Err(e) = Command::new("kill").arg("-9").arg(pid.to_string()).status() {
+                warn!("⚠️  Failed to kill compiler {} of job {}: {}", pid, job_id, e);
+            }
+        }
+        info!("🛑 Aborting job {}", job_id);
+        true
+    }
+
     async fn execute_job_by_id(&self, _job_id: &str) -> Result<()> {
         // This path is no longer used - jobs come via gRPC ExecuteJob RPC
         Ok(())
     }
     
-    async fn report_completion(&self, job_id: &str, success: bool, output_hash: String, error: String) -> Result<()> {
-        let mut client = SchedulerClient::connect(self.scheduler_addr.clone()).await?;
-        
-        let request = ReportJobResultRequest {
-            job_id: job_id.to_string(),
-            success,
+    /// Run a dispatched job to the end and report its result
+    async fn run_job(&self, req: ExecuteJobRequest) -> ExecuteJobResponse {
+        let job_id = req.job_id.clone();
+
+        // Add to active jobs
+        {
+            let mut state = self.state.write().await;
+            state.active_jobs.insert(
+                job_id.clone(),
+                JobInfo {
+                    job_id: job_id.clone(),
+                    phase: "starting",
+                    started: Instant::now(),
+                    pid: None,
+                    aborted: false,
+                },
+            );
+        }
+
+        // Execute the job
+        let started = Instant::now();
+        let mut usage = JobUsage::default();
+        let mut log = String::new();
+        let result = self
+            .execute_job_impl(&req.job_id, &req.input_hash, &req.job_type, &req.metadata, &mut usage, &mut log)
+            .await;
+        usage.exec_millis = started.elapsed().as_millis() as u64;
+
+        // Remove from active jobs
+        {
+            let mut state = self.state.write().await;
+            state.active_jobs.remove(&job_id);
+        }
+
+        let (output_hash, error, error_kind) = match &result {
+            Ok(output_hash) => (output_hash.clone(), String::new(), JobErrorKindEnum::Unspecified),
+            Err(e) => (String::new(), format!("{:?}", e), job_error_kind(e)),
+        };
+        if !error.is_empty() {
+            let _ = writeln!(log, "error: {}", error);
+        }
+        let log_hash = self.store_log(&job_id, &req.metadata, &log);
+
+        // Report result to scheduler
+        let _ = self
+            .report_completion(ReportJobResultRequest {
+                job_id,
+                success: result.is_ok(),
+                output_hash: output_hash.clone(),
+                error: error.clone(),
+                platform: Some(self.platform.clone().into()),
+                error_kind: error_kind.into(),
+                usage: Some(usage),
+                log_hash,
+                worker_id: self.worker_id.clone(),
+            })
+            .await;
+        ExecuteJobResponse {
+            success: result.is_ok(),
             output_hash,
             error,
+            stdout: String::new(),
+            stderr: String::new(),
+        }
+    }
+
+    /// Keep a job's log in the CAS (its namespace, like its output) for
+    /// `GetJobLogs`, trimmed to its last `MAX_LOG_BYTES`; its hash, or
+    /// empty if it couldn't be stored
+    fn store_log(&self, job_id: &str, metadata: &HashMap<String, String>, log: &str) -> String {
+        let start = log.len().saturating_sub(MAX_LOG_BYTES);
+        let start = (start..log.len()).find(|&i| log.is_char_boundary(i)).unwrap_or(log.len());
+        let stored = match metadata.get("namespace").filter(|ns| !ns.is_empty()) {
+            Some(ns) => self.cas.namespace(ns).and_then(|cas| cas.put(&log.as_bytes()[start..])),
+            None => self.cas.put(&log.as_bytes()[start..]),
         };
-        
-        client.report_job_result(request).await?;
-        Ok(())
+        stored.unwrap_or_else(|e| {
+            warn!("⚠️  Failed to store log of job {}: {:#}", job_id, e);
+            String::new()
+        })
+    }
+
+    /// Report a finished job. While the scheduler is away restarting the
+    /// result is held and resent until it's back, for up to `RESULT_HOLD`.
+    async fn report_completion(&self, request: ReportJobResultRequest) -> Result<()> {
+        let job_id = &request.job_id;
+        let held_since = Instant::now();
+        loop {
+            let result = async {
+                let mut client = self.auth.connect_any(&self.scheduler_urls).await?;
+                client.report_job_result(request.clone()).await?;
+                anyhow::Ok(())
+            };
+            let e = match result.await {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            if !self.state.read().await.scheduler_away || held_since.elapsed() > RESULT_HOLD {
+                warn!("⚠️  Failed to report result of job {}: {}", job_id, e);
+                return Err(e);
+            }
+            sleep(RESULT_RETRY_INTERVAL).await;
+        }
     }
 
+    /// Run the job, tallying what it reads and writes in `usage`
     async fn execute_job_impl(
         &self,
         job_id: &str,
         input_hash: &str,
         job_type: &str,
+        metadata: &HashMap<String, String>,
+        usage: &mut JobUsage,
+        log: &mut String,
     ) -> Result<String> {
-        println!("🔨 Worker {} executing job: {}", self.worker_id, job_id);
-        println!("   Job type: {}", job_type);
-        println!("   Input hash: {}", input_hash);
+        info!("🔨 Worker {} executing job: {}", self.worker_id, job_id);
+        info!("   Job type: {}", job_type);
+        info!("   Input hash: {}", input_hash);
+        let _ = writeln!(log, "worker {} ({}), job {} ({})", self.worker_id, self.platform, job_id, job_type);
+        let _ = writeln!(log, "input {}", input_hash);
+
+        // Fetch input and dependency blobs (comma-separated `deps` metadata)
+        // from CAS in parallel
+        self.set_phase(job_id, "fetching inputs").await?;
+        let mut hashes = vec![input_hash.to_string()];
+        if let Some(deps) = metadata.get("deps") {
+            hashes.extend(deps.split(',').map(str::trim).filter(|h| !h.is_empty()).map(str::to_string));
+        }
 
-        // Fetch input from CAS
-        let input_data = self.cas.get(input_hash)
-            .context("Failed to get input from CAS")?;
+        // Jobs from namespaced projects read and write their own CAS space
+        let cas = match metadata.get("namespace").filter(|ns| !ns.is_empty()) {
+            Some(ns) => Arc::new(self.cas.namespace(ns)?),
+            None => self.cas.clone(),
+        };
+        let output_cas = cas.clone();
+        let parallelism = self.prefetch_parallelism;
+        let fetch_cas = cas.clone();
+        let results = tokio::task::spawn_blocking(move || {
+            fetch_cas.get_many(&hashes, parallelism).into_iter().collect::<Vec<_>>()
+        })
+        .await?;
+
+        // Anything missing locally may be fetched over HTTP or from a
+        // replication peer
+        let mut blobs = HashMap::new();
+        for (hash, result) in results {
+            let data = match result {
+                Ok(data) => data,
+                Err(e) => {
+                    if !self.replicator.pull(&cas, &hash).await? {
+                        return Err(e.context("Failed to get input from CAS"));
+                    }
+                    let data = cas.get(&hash).context("Failed to get input from CAS")?;
+                    usage.transferred_bytes += data.len() as u64;
+                    data
+                }
+            };
+            usage.input_bytes += data.len() as u64;
+            blobs.insert(hash, data);
+        }
+        {
+            let mut state = self.state.write().await;
+            blobs.keys().for_each(|hash| state.remember_blob(hash));
+        }
 
-        println!("   Read {} bytes from CAS", input_data.len());
+        let input_data = blobs.remove(input_hash).unwrap_or_default();
+
+        info!("   Read {} bytes from CAS ({} dependencies)", input_data.len(), blobs.len());
+        let _ = writeln!(log, "read {} bytes ({} dependencies)", input_data.len(), blobs.len());
+
+        // Stage inputs in a clean sandbox; it's wiped and recycled when dropped
+        let sandbox = self.sandboxes.acquire().context("Failed to acquire sandbox")?;
+        std::fs::write(sandbox.path().join("input.tar"), &input_data)
+            .context("Failed to stage input in sandbox")?;
+        for (hash, data) in &blobs {
+            std::fs::write(sandbox.path().join(hash), data)
+                .context("Failed to stage dependency in sandbox")?;
+        }
+
+        self.set_phase(job_id, "compiling").await?;
 
         // Check if this looks like Rust source code (basic validation)
         let input_str = String::from_utf8_lossy(&input_data);
@@ -192,24 +551,47 @@ impl WorkerService {
         // Real implementation will extract .rs files and run rustc
         if !input_str.contains("fn ") && !input_str.contains("pub ") && !input_str.contains("use ") {
             // Doesn't look like Rust code
-            anyhow::bail!(
+            return Err(DistbuildError::CompileFailed(format!(
                 "Input doesn't appear to be valid Rust source code. \
                 Expected Rust syntax (fn, pub, use, etc.) but found: {}",
                 &input_str.chars().take(100).collect::<String>()
-            );
+            ))
+            .into());
         }
 
         // Dummy transformation: append " + compiled by worker"
         // In real implementation, this would be: rustc <args> -> .rlib output
         let output = format!("{} + compiled by worker {}", input_str, self.worker_id);
-        let output_bytes = output.as_bytes();
+        let output = match metadata.get("artifacts").filter(|a| !a.is_empty()) {
+            Some(artifacts) => archive_artifacts(artifacts.split(','), output.as_bytes())?,
+            None => output.into_bytes(),
+        };
+        let output_bytes = &output[..];
+        usage.output_bytes = output_bytes.len() as u64;
 
         // Write output to CAS
-        let output_hash = self.cas.put(output_bytes)
+        self.set_phase(job_id, "storing output").await?;
+        let output_hash = output_cas.put(output_bytes)
             .context("Failed to put output to CAS")?;
+        if let Err(e) = output_cas.record_origin(&output_hash, Some(job_id), metadata.get("crate_name").map(String::as_str)) {
+            warn!("⚠️  Failed to record origin of {}: {}", output_hash, e);
+        }
+        if let Err(e) = output_cas.record_action(input_hash, &output_hash) {
+            warn!("⚠️  Failed to cache result of {}: {}", input_hash, e);
+        }
+
+        // Get the output near the workers that will need it next
+        let replicator = self.replicator.clone();
+        let replicated = (*output_cas).clone();
+        let replicated_hash = output_hash.clone();
+        tokio::spawn(async move {
+            replicator.push(&replicated, &replicated_hash).await;
+        });
 
-        println!("   Output hash: {}", output_hash);
-        println!("✅ Job completed successfully");
+        info!("   Output hash: {}", output_hash);
+        let _ = writeln!(log, "output {} ({} bytes)", output_hash, output_bytes.len());
+        self.state.write().await.remember_blob(&output_hash);
+        info!("✅ Job completed successfully");
 
         Ok(output_hash)
     }
@@ -221,56 +603,45 @@ impl Worker for WorkerService {
         &self,
         request: Request<ExecuteJobRequest>,
     ) -> Result<Response<ExecuteJobResponse>, Status> {
+        // In its own task, so the job outlives the dispatch connection
+        // (e.g. across a scheduler restart) and reports its result itself
+        let worker = self.clone_for_heartbeat();
         let req = request.into_inner();
-        let job_id = req.job_id.clone();
-
-        // Add to active jobs
-        {
-            let mut state = self.state.write().await;
-            state.active_jobs.insert(
-                job_id.clone(),
-                JobInfo {
-                    job_id: job_id.clone(),
-                    status: "running".to_string(),
-                },
-            );
-        }
+        tokio::spawn(async move { worker.run_job(req).await })
+            .await
+            .map_err(|e| Status::internal(format!("Job task failed: {}", e)))
+            .map(Response::new)
+    }
 
-        // Execute the job
-        let result = self
-            .execute_job_impl(&req.job_id, &req.input_hash, &req.job_type)
-            .await;
+    async fn abort_job(
+        &self,
+        request: Request<AbortJobRequest>,
+    ) -> Result<Response<AbortJobResponse>, Status> {
+        let req = request.into_inner();
+        let aborted = self.abort(&req.job_id).await;
+        Ok(Response::new(AbortJobResponse { aborted }))
+    }
 
-        // Remove from active jobs
-        {
+    async fn scheduler_stopping(
+        &self,
+        request: Request<SchedulerStoppingRequest>,
+    ) -> Result<Response<SchedulerStoppingResponse>, Status> {
+        let req = request.into_inner();
+        let job_ids: Vec<String> = {
             let mut state = self.state.write().await;
-            state.active_jobs.remove(&job_id);
-        }
-
-        // Report result to scheduler
-        match &result {
-            Ok(output_hash) => {
-                let _ = self.report_completion(&job_id, true, output_hash.clone(), String::new()).await;
-                Ok(Response::new(ExecuteJobResponse {
-                    success: true,
-                    output_hash: output_hash.clone(),
-                    error: String::new(),
-                    stdout: String::new(),
-                    stderr: String::new(),
-                }))
-            }
-            Err(e) => {
-                let error_msg = format!("{:?}", e);
-                let _ = self.report_completion(&job_id, false, String::new(), error_msg.clone()).await;
-                Ok(Response::new(ExecuteJobResponse {
-                    success: false,
-                    output_hash: String::new(),
-                    error: error_msg,
-                    stdout: String::new(),
-                    stderr: String::new(),
-                }))
+            state.scheduler_away = req.resuming;
+            state.active_jobs.keys().cloned().collect()
+        };
+        if req.resuming {
+            info!("🔌 Scheduler restarting; holding results of {} running job(s) until it's back", job_ids.len());
+        } else {
+            // It won't know these jobs when it comes back
+            warn!("🔌 Scheduler stopping without saving state; aborting {} running job(s)", job_ids.len());
+            for job_id in &job_ids {
+                self.abort(job_id).await;
             }
         }
+        Ok(Response::new(SchedulerStoppingResponse { running: job_ids.len() as u32 }))
     }
 
     async fn get_status(
@@ -289,9 +660,89 @@ impl Worker for WorkerService {
     }
 }
 
+/// A tar of one artifact per requested crate type (the wrapper unpacks it
+/// into the output directory), for invocations producing several at once
+fn archive_artifacts<'a>(names: impl Iterator<Item = &'a str>, data: &[u8]) -> Result<Vec<u8>> {
+    let mut archive = tar::Builder::new(Vec::new());
+    for name in names {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.append_data(&mut header, name, data)
+            .with_context(|| format!("Failed to archive artifact {}", name))?;
+    }
+    archive.into_inner().context("Failed to archive artifacts")
+}
+
+/// Whether process `pid` still exists (assumed so where we can't tell)
+fn process_alive(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        std::path::Path::new(&format!("/proc/{}", pid)).exists()
+    } else {
+        true
+    }
+}
+
+/// Classify a job failure so the submitting client can act on it
+fn job_error_kind(error: &anyhow::Error) -> JobErrorKindEnum {
+    error
+        .chain()
+        .find_map(|cause| match cause.downcast_ref::<DistbuildError>() {
+            Some(DistbuildError::QuotaExceeded { .. }) => Some(JobErrorKindEnum::QuotaExceeded),
+            Some(DistbuildError::CompileFailed(_)) => Some(JobErrorKindEnum::Compile),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
 pub async fn run_worker(worker_id: String, port: u16, config: Config, cas: Arc<Cas>) -> Result<()> {
     let address = format!("127.0.0.1:{}", port);
-    let service = WorkerService::new(worker_id, address, config, cas);
+    let service = WorkerService::new(worker_id, address, config, cas)?;
     service.run().await
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::{SchedulerConfig, WorkerConfig};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_heartbeat_interval_from_scheduler() {
+        let scheduler_config = SchedulerConfig {
+            addr: "127.0.0.1:15050".to_string(),
+            heartbeat_interval_secs: Some(3600),
+            ..Default::default()
+        };
+        tokio::spawn(crate::scheduler::run_scheduler_with_config(scheduler_config.clone(), None));
+        sleep(Duration::from_millis(500)).await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = Config {
+            scheduler: scheduler_config,
+            worker: WorkerConfig {
+                sandbox_root: Some(temp_dir.path().join("sandboxes").display().to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let cas = Arc::new(Cas::new(temp_dir.path().join("cas")).unwrap());
+        let worker = WorkerService::new("worker-1".to_string(), "127.0.0.1:16057".to_string(), config, cas).unwrap();
+        let interval = || worker.heartbeat_interval_secs.load(Ordering::Relaxed);
+        assert_eq!(interval(), 10);
+
+        // An hour is more than a worker may wait between heartbeats
+        worker.register().await.unwrap();
+        assert_eq!(interval(), MAX_HEARTBEAT_INTERVAL_SECS);
+
+        worker.adopt_heartbeat_interval(2);
+        assert_eq!(interval(), 2);
+        worker.adopt_heartbeat_interval(0);
+        assert_eq!(interval(), 2);
+
+        worker.send_heartbeat().await.unwrap();
+        assert_eq!(interval(), MAX_HEARTBEAT_INTERVAL_SECS);
+    }
+}
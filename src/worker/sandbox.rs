@@ -0,0 +1,180 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Pre-created job directories recycled between jobs, so tiny jobs don't
+/// pay for creating and deleting a directory tree each time
+#[derive(Debug)]
+pub struct SandboxPool {
+    root: PathBuf,
+    idle: Mutex<Vec<PathBuf>>,
+    max_idle: usize,
+    /// Delete and recreate each sandbox on release instead of emptying it
+    paranoid: bool,
+    next_id: AtomicU64,
+}
+
+impl SandboxPool {
+    /// Create a pool under `root` with `size` ready sandboxes. Anything left
+    /// under `root` by a previous run is wiped first.
+    pub fn new<P: AsRef<Path>>(root: P, size: usize, paranoid: bool) -> Result<Arc<Self>> {
+        let root = root.as_ref().to_path_buf();
+        if root.exists() {
+            fs::remove_dir_all(&root)
+                .with_context(|| format!("Failed to clear sandbox root {:?}", root))?;
+        }
+        fs::create_dir_all(&root)
+            .with_context(|| format!("Failed to create sandbox root {:?}", root))?;
+
+        let pool = Arc::new(SandboxPool {
+            root,
+            idle: Mutex::new(Vec::new()),
+            max_idle: size,
+            paranoid,
+            next_id: AtomicU64::new(0),
+        });
+
+        let ready = (0..size)
+            .map(|_| pool.create())
+            .collect::<Result<Vec<_>>>()?;
+        *pool.idle.lock().unwrap() = ready;
+
+        Ok(pool)
+    }
+
+    /// Take a clean sandbox; it returns to the pool when dropped
+    pub fn acquire(self: &Arc<Self>) -> Result<Sandbox> {
+        loop {
+            let next = self.idle.lock().unwrap().pop();
+            let path = match next {
+                Some(path) => path,
+                None => self.create()?,
+            };
+
+            // Never hand out a directory something else has written into
+            if is_empty(&path) {
+                return Ok(Sandbox {
+                    path,
+                    pool: self.clone(),
+                });
+            }
+            let _ = fs::remove_dir_all(&path);
+        }
+    }
+
+    /// Number of sandboxes waiting to be reused
+    pub fn idle_count(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+
+    fn create(&self) -> Result<PathBuf> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let path = self.root.join(format!("sandbox-{}", id));
+        fs::create_dir(&path)
+            .with_context(|| format!("Failed to create sandbox {:?}", path))?;
+        Ok(path)
+    }
+
+    /// Clean a used sandbox and keep it if it verifies empty and the pool has room
+    fn release(&self, path: PathBuf) {
+        let cleaned = if self.paranoid {
+            fs::remove_dir_all(&path).and_then(|_| fs::create_dir(&path))
+        } else {
+            empty_dir(&path)
+        };
+
+        let mut idle = self.idle.lock().unwrap();
+        if cleaned.is_ok() && is_empty(&path) && idle.len() < self.max_idle {
+            idle.push(path);
+        } else {
+            drop(idle);
+            let _ = fs::remove_dir_all(&path);
+        }
+    }
+}
+
+/// A job's working directory, borrowed from a `SandboxPool`
+#[derive(Debug)]
+pub struct Sandbox {
+    path: PathBuf,
+    pool: Arc<SandboxPool>,
+}
+
+impl Sandbox {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        self.pool.release(std::mem::take(&mut self.path));
+    }
+}
+
+fn empty_dir(path: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+fn is_empty(path: &Path) -> bool {
+    fs::read_dir(path)
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sandboxes_are_recycled_clean() {
+        let temp_dir = TempDir::new().unwrap();
+        let pool = SandboxPool::new(temp_dir.path().join("sandboxes"), 2, false).unwrap();
+        assert_eq!(pool.idle_count(), 2);
+
+        let first_path = {
+            let sandbox = pool.acquire().unwrap();
+            fs::create_dir_all(sandbox.path().join("target/debug")).unwrap();
+            fs::write(sandbox.path().join("input.tar"), b"sources").unwrap();
+            assert_eq!(pool.idle_count(), 1);
+            sandbox.path().to_path_buf()
+        };
+
+        // Same directory comes back, emptied
+        assert_eq!(pool.idle_count(), 2);
+        let reused = pool.acquire().unwrap();
+        assert_eq!(reused.path(), first_path);
+        assert!(is_empty(reused.path()));
+
+        // Beyond the pool size, extra sandboxes are created and discarded
+        let extra: Vec<_> = (0..2).map(|_| pool.acquire().unwrap()).collect();
+        drop(extra);
+        drop(reused);
+        assert_eq!(pool.idle_count(), 2);
+    }
+
+    #[test]
+    fn test_paranoid_wipe_recreates_sandbox() {
+        let temp_dir = TempDir::new().unwrap();
+        let pool = SandboxPool::new(temp_dir.path().join("sandboxes"), 1, true).unwrap();
+
+        {
+            let sandbox = pool.acquire().unwrap();
+            fs::write(sandbox.path().join(".hidden"), b"secret").unwrap();
+        }
+
+        let sandbox = pool.acquire().unwrap();
+        assert!(is_empty(sandbox.path()));
+    }
+}
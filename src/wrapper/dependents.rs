@@ -0,0 +1,112 @@
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Reverse dependency graph of a workspace, from its Cargo.lock
+#[derive(Debug, Default)]
+pub struct Dependents {
+    /// package -> packages depending on it directly
+    reverse: HashMap<String, HashSet<String>>,
+}
+
+impl Dependents {
+    /// Graph from the Cargo.lock nearest to `dir` (or one of its ancestors)
+    pub fn for_dir(dir: &Path) -> Result<Self> {
+        let lockfile = find_lockfile(dir).context("No Cargo.lock found")?;
+        let content = fs::read_to_string(&lockfile)
+            .with_context(|| format!("Failed to read {:?}", lockfile))?;
+        Self::parse(&content)
+    }
+
+    /// Parse a Cargo.lock. Versions are ignored: every version of a package
+    /// counts as the same node, which only ever overestimates dependents.
+    pub fn parse(lockfile: &str) -> Result<Self> {
+        let lock: toml::Value = toml::from_str(lockfile).context("Failed to parse Cargo.lock")?;
+        let mut reverse: HashMap<String, HashSet<String>> = HashMap::new();
+
+        let packages = lock.get("package").and_then(|p| p.as_array()).cloned().unwrap_or_default();
+        for package in &packages {
+            let Some(name) = package.get("name").and_then(|n| n.as_str()) else {
+                continue;
+            };
+            let dependencies = package.get("dependencies").and_then(|d| d.as_array());
+            // Entries are "name", "name version" or "name version (source)"
+            for dependency in dependencies.into_iter().flatten().filter_map(|d| d.as_str()) {
+                if let Some(dependency) = dependency.split_whitespace().next() {
+                    reverse.entry(dependency.to_string()).or_default().insert(name.to_string());
+                }
+            }
+        }
+
+        Ok(Dependents { reverse })
+    }
+
+    /// Every package that depends on `package`, directly or transitively
+    pub fn transitive(&self, package: &str) -> HashSet<String> {
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([package.to_string()]);
+
+        while let Some(next) = queue.pop_front() {
+            for dependent in self.reverse.get(&next).into_iter().flatten() {
+                if dependent != package && seen.insert(dependent.clone()) {
+                    queue.push_back(dependent.clone());
+                }
+            }
+        }
+        seen
+    }
+
+    /// How many of `package`'s dependents haven't been compiled yet; these
+    /// are what's (eventually) stuck behind it in cargo's queue
+    pub fn pending(&self, package: &str, finished: &HashSet<String>) -> usize {
+        self.transitive(package)
+            .iter()
+            .filter(|dependent| !finished.contains(*dependent))
+            .count()
+    }
+}
+
+fn find_lockfile(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|ancestor| ancestor.join("Cargo.lock"))
+        .find(|lockfile| lockfile.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCKFILE: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde", "tool"]
+
+[[package]]
+name = "tool"
+version = "0.1.0"
+dependencies = ["serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)"]
+
+[[package]]
+name = "serde"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    #[test]
+    fn test_pending_dependents() {
+        let dependents = Dependents::parse(LOCKFILE).unwrap();
+        assert_eq!(
+            dependents.transitive("serde"),
+            HashSet::from(["app".to_string(), "tool".to_string()])
+        );
+        assert!(dependents.transitive("app").is_empty());
+
+        let finished = HashSet::from(["tool".to_string()]);
+        assert_eq!(dependents.pending("serde", &finished), 1);
+        assert_eq!(dependents.pending("unknown", &finished), 0);
+    }
+}
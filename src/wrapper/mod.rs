@@ -4,9 +4,17 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+pub mod dependents;
 pub mod rustc_parser;
+pub mod timings;
+pub mod writeback;
 
+use crate::common::config::PushPolicy;
+use crate::common::platform::{hostname, Platform};
+use crate::common::session::{BuildSession, UnitMode, UnitRecord};
+use dependents::Dependents;
 use rustc_parser::RustcArgs;
+use timings::CrateTimings;
 
 /// Find config.toml by searching up from current directory
 fn find_config_file() -> Option<PathBuf> {
@@ -65,9 +73,31 @@ pub async fn run_wrapper() -> Result<()> {
         }
     };
 
-    // For now, if it's not a library compilation, run locally
+    let started = std::time::Instant::now();
+    let crate_name = rustc_args.crate_name.clone().unwrap_or_default();
+
+    // For now, anything but library crate types (bins, proc macros) runs locally
     if !rustc_args.is_lib {
-        return run_local_rustc(rustc_args_slice);
+        run_local_rustc(rustc_args_slice)?;
+        record_unit(&crate_name, UnitMode::Local, started);
+        return Ok(());
+    }
+
+    // Crates that historically compile faster than the remote round trip
+    // are built locally (going remote now and then to re-check)
+    let wrapper_config = load_config().map(|c| c.wrapper).unwrap_or_default();
+    let mut timings = CrateTimings::load_default();
+    if timings.should_compile_locally(
+        &crate_name,
+        wrapper_config.local_threshold_ms,
+        wrapper_config.reevaluate_secs,
+        chrono::Utc::now().timestamp(),
+    ) {
+        eprintln!("⚡ [cargo-distbuild] {} is tiny, compiling locally", crate_name);
+        run_local_rustc_timed(rustc_args_slice, &crate_name, &mut timings)?;
+        push_local_result(&rustc_args, wrapper_config.push).await;
+        record_unit(&crate_name, UnitMode::Local, started);
+        return Ok(());
     }
 
     eprintln!("🚀 [cargo-distbuild] Intercepted rustc call for crate: {:?}", rustc_args.crate_name);
@@ -77,16 +107,127 @@ pub async fn run_wrapper() -> Result<()> {
     match compile_distributed(&rustc_args).await {
         Ok(_) => {
             eprintln!("✅ [cargo-distbuild] Distributed compilation successful");
+            timings.record_remote(&crate_name, started.elapsed(), chrono::Utc::now().timestamp());
+            save_timings(&timings);
+            record_unit(&crate_name, UnitMode::Remote, started);
             Ok(())
         }
         Err(e) => {
             eprintln!("⚠️  [cargo-distbuild] Distributed compilation failed: {}", e);
+            report_client_error(&e).await;
             eprintln!("   Falling back to local compilation");
-            run_local_rustc(rustc_args_slice)
+            run_local_rustc_timed(rustc_args_slice, &crate_name, &mut timings)?;
+            push_local_result(&rustc_args, wrapper_config.push).await;
+            record_unit(&crate_name, UnitMode::Fallback, started);
+            Ok(())
         }
     }
 }
 
+/// Add the unit to this cargo invocation's build session (best effort), so
+/// `cargo-distbuild build-report` reflects what this build, and only this
+/// build, compiled
+fn record_unit(crate_name: &str, mode: UnitMode, started: std::time::Instant) {
+    let Some(session) = BuildSession::current() else {
+        return;
+    };
+
+    let unit = UnitRecord {
+        crate_name: crate_name.to_string(),
+        package: env::var("CARGO_PKG_NAME").unwrap_or_else(|_| crate_name.to_string()),
+        primary: env::var_os("CARGO_PRIMARY_PACKAGE").is_some(),
+        mode,
+        duration_ms: started.elapsed().as_millis() as u64,
+        finished_at: chrono::Utc::now().timestamp(),
+    };
+    if let Err(e) = session.record(&unit) {
+        eprintln!("⚠️  [cargo-distbuild] Failed to record build session: {}", e);
+    }
+}
+
+/// Load config from the cargo-distbuild directory, not current directory
+fn load_config() -> Result<crate::common::Config> {
+    use crate::common::config::Role;
+    use crate::common::Config;
+
+    // Find the config by looking in parent directories
+    match find_config_file() {
+        Some(config_path) => Config::load_for(&config_path, Role::Wrapper),
+        None => Config::load_default_for(Role::Wrapper), // Fallback to default
+    }
+}
+
+/// Identify this client in error reports as user@host
+fn client_id() -> String {
+    let user = env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    format!("{}@{}", user, hostname())
+}
+
+/// Build session the scheduler groups this cargo invocation's jobs under:
+/// DISTBUILD_SESSION if set (e.g. to a CI job's id), else the host, cargo's
+/// pid and when cargo started, so a reused pid doesn't join an old build
+fn session_id() -> String {
+    if let Some(id) = env::var("DISTBUILD_SESSION").ok().filter(|id| !id.is_empty()) {
+        return id;
+    }
+    let cargo_pid = std::os::unix::process::parent_id();
+    // Field 22 of /proc/<pid>/stat, counted after the parenthesized name
+    let started = fs::read_to_string(format!("/proc/{}/stat", cargo_pid))
+        .ok()
+        .and_then(|stat| Some(stat.rsplit_once(')')?.1.split_whitespace().nth(19)?.to_string()));
+    match started {
+        Some(started) => format!("{}-{}-{}", hostname(), cargo_pid, started),
+        None => format!("{}-{}", hostname(), cargo_pid),
+    }
+}
+
+/// Bucket a distributed compilation failure into a coarse kind for reporting
+fn classify_error(error: &anyhow::Error) -> &'static str {
+    let message = format!("{:#}", error);
+
+    if message.contains("Failed to connect to scheduler") {
+        "scheduler-connect"
+    } else if message.contains("quota exceeded") {
+        "quota"
+    } else if message.contains("queue stayed full") {
+        "queue-full"
+    } else if message.contains("CAS") {
+        "cas"
+    } else if message.contains("timeout") {
+        "timeout"
+    } else if message.contains("Job failed") {
+        "job-failed"
+    } else {
+        "other"
+    }
+}
+
+/// Best-effort report of a distributed compilation failure to the scheduler,
+/// so client-side problems are visible cluster-wide (never fails the build)
+async fn report_client_error(error: &anyhow::Error) {
+    use crate::common::auth::ClientAuth;
+    use crate::proto::distbuild::ReportClientErrorRequest;
+    use tokio::time::{timeout, Duration};
+
+    let Ok(config) = load_config() else {
+        return;
+    };
+
+    let report = async {
+        let mut client = ClientAuth::from_config(&config.scheduler)?.connect_any(&config.scheduler.urls()).await?;
+        let request = ReportClientErrorRequest {
+            client_id: client_id(),
+            kind: classify_error(error).to_string(),
+            message: format!("{:#}", error),
+            job_id: String::new(),
+        };
+        client.report_client_error(request).await?;
+        Ok::<_, anyhow::Error>(())
+    };
+
+    let _ = timeout(Duration::from_secs(2), report).await;
+}
+
 /// Check if we should skip distributed compilation for this invocation
 fn should_run_locally(args: &[String]) -> bool {
     // Run locally for:
@@ -123,79 +264,435 @@ fn run_local_rustc(args: &[String]) -> Result<()> {
     Ok(())
 }
 
+/// Run rustc locally and record how long the crate took to compile
+fn run_local_rustc_timed(args: &[String], crate_name: &str, timings: &mut CrateTimings) -> Result<()> {
+    let started = std::time::Instant::now();
+    run_local_rustc(args)?;
+
+    timings.record_local(crate_name, started.elapsed(), chrono::Utc::now().timestamp());
+    save_timings(timings);
+
+    Ok(())
+}
+
+fn save_timings(timings: &CrateTimings) {
+    if let Err(e) = timings.save() {
+        eprintln!("⚠️  [cargo-distbuild] Failed to save crate timings: {}", e);
+    }
+}
+
+/// Offer a crate compiled here to the shared action cache, if the push
+/// policy trusts this machine to populate it (best effort)
+async fn push_local_result(rustc_args: &RustcArgs, push: PushPolicy) {
+    if !push.allows_push() {
+        return;
+    }
+    if let Err(e) = store_local_result(rustc_args).await {
+        eprintln!("⚠️  [cargo-distbuild] Failed to push {} to the cache: {:#}", rustc_args.crate_name.as_deref().unwrap_or("crate"), e);
+    }
+}
+
+/// Store the artifacts just compiled here as the result of the crate's
+/// input, in the same form a worker would return them, and upload both
+/// with the action-cache entry to the scheduler's CAS and peers
+async fn store_local_result(rustc_args: &RustcArgs) -> Result<()> {
+    use crate::cas::replication::Replicator;
+    use crate::common::auth::ClientAuth;
+
+    let Some(output_path) = &rustc_args.output_path else {
+        return Ok(());
+    };
+    let config = load_config()?;
+    let cas = crate::cas::Cas::from_config(&config.cas)?;
+    let (input_hash, _) = create_source_tarball(rustc_args, &cas)?;
+
+    let names = rustc_args.artifact_names();
+    let output_hash = if names.len() > 1 {
+        let out_dir = if output_path.is_dir() {
+            output_path.as_path()
+        } else {
+            output_path.parent().unwrap_or(Path::new("."))
+        };
+        let mut archive = tar::Builder::new(cas.writer()?);
+        for name in &names {
+            archive.append_path_with_name(out_dir.join(name), name)
+                .with_context(|| format!("Failed to archive artifact {}", name))?;
+        }
+        archive.into_inner()?.finish()?
+    } else if output_path.is_file() {
+        let file = fs::File::open(output_path).with_context(|| format!("Failed to open {:?}", output_path))?;
+        cas.put_stream(file)?
+    } else {
+        return Ok(());
+    };
+
+    cas.record_action(&input_hash, &output_hash)?;
+
+    let auth = ClientAuth::from_config(&config.scheduler)?;
+    let replicator = Replicator::new(&config.cas.replication, &config.scheduler.addr, "")
+        .with_remote(&config.cas.remote)
+        .with_auth(auth);
+    let peers = replicator.push_action(&cas, &input_hash, &output_hash).await;
+    eprintln!("📤 [cargo-distbuild] Pushed {} to the cache ({} peer(s))", rustc_args.crate_name.as_deref().unwrap_or("crate"), peers);
+    Ok(())
+}
+
 /// Compile on the distributed system
 async fn compile_distributed(rustc_args: &RustcArgs) -> Result<()> {
+    use crate::cas::replication::Replicator;
     use crate::cas::Cas;
-    use crate::common::Config;
-    use crate::proto::distbuild::scheduler_client::SchedulerClient;
+    use crate::common::auth::ClientAuth;
+    use crate::common::error::{rejection, Rejection};
     use crate::proto::distbuild::*;
-    use std::path::PathBuf;
     
-    // Load config from the cargo-distbuild directory, not current directory
-    // Find the config by looking in parent directories
-    let config = match find_config_file() {
-        Some(config_path) => Config::load(&config_path)?,
-        None => Config::load_default()?, // Fallback to default
-    };
+    let config = load_config()?;
     
-    let cas = Cas::new(&config.cas.root)?;
+    let cas = Cas::from_config(&config.cas)?;
     
     eprintln!("📦 [cargo-distbuild] Packaging source files for CAS...");
     
-    // Create a tarball of the crate source
-    let tarball = create_source_tarball(rustc_args)?;
-    
-    // Upload to CAS
-    let input_hash = cas.put(&tarball)?;
+    // Stream a tarball of the crate source into CAS
+    let (input_hash, input_size) = create_source_tarball(rustc_args, &cas)?;
     eprintln!("   Input hash: {}", &input_hash[..16]);
+
+    // Exactly this compile was done before (by a worker, or a trusted client)
+    if let Some(output_hash) = cas.action_result(&input_hash) {
+        eprintln!("♻️  [cargo-distbuild] Reusing cached output {}", &output_hash[..16]);
+        return write_output(rustc_args, &cas, &output_hash);
+    }
+
+    // Workers that don't share our CAS root get the input pushed to them;
+    // the wrapper has no blob store of its own they could pull it from
+    let auth = ClientAuth::from_config(&config.scheduler)?;
+    let replicator = Replicator::new(&config.cas.replication, &config.scheduler.addr, "")
+        .with_remote(&config.cas.remote)
+        .with_auth(auth.clone());
+    replicator.push(&cas, &input_hash).await;
     
     // Connect to scheduler
-    let scheduler_addr = format!("http://{}", config.scheduler.addr);
-    let mut client = SchedulerClient::connect(scheduler_addr)
+    let mut client = auth.connect_any(&config.scheduler.urls())
         .await
         .context("Failed to connect to scheduler")?;
     
     // Submit job
     let job_id = uuid::Uuid::new_v4().to_string();
+    let _ = cas.record_origin(&input_hash, Some(&job_id), rustc_args.crate_name.as_deref());
+    let mut metadata = std::collections::HashMap::from([
+        ("crate_name".to_string(), rustc_args.crate_name.clone().unwrap_or_default()),
+        ("rustc_args".to_string(), rustc_args.original_args.join(" ")),
+        ("user".to_string(), env::var("USER").unwrap_or_default()),
+    ]);
+    if let Some(namespace) = &config.cas.namespace {
+        metadata.insert("namespace".to_string(), namespace.clone());
+    }
+    if let Some(profile) = rustc_args.profile() {
+        metadata.insert("profile".to_string(), profile);
+    }
+    // Only workers with the target installed get the job
+    if let Some(target) = &rustc_args.target {
+        metadata.insert("target".to_string(), target.clone());
+    }
+    // One invocation building several crate types returns them all, as an
+    // archive the writeback unpacks into the output directory
+    metadata.insert("crate_types".to_string(), rustc_args.crate_types.join(","));
+    let artifacts = rustc_args.artifact_names();
+    if artifacts.len() > 1 {
+        metadata.insert("artifacts".to_string(), artifacts.join(","));
+    }
+    // Crates more of the build is waiting on go first
+    let base_priority = env::var("DISTBUILD_PRIORITY")
+        .ok()
+        .and_then(|p| p.trim().parse().ok())
+        .unwrap_or(config.wrapper.priority);
+    let mut priority = PriorityReporter::new(rustc_args, base_priority);
     let request = SubmitJobRequest {
         job_id: job_id.clone(),
         input_hash: input_hash.clone(),
         job_type: "rust-compile".to_string(),
-        metadata: std::collections::HashMap::from([
-            ("crate_name".to_string(), rustc_args.crate_name.clone().unwrap_or_default()),
-            ("rustc_args".to_string(), rustc_args.original_args.join(" ")),
-        ]),
+        metadata,
+        client_version: Some(crate::common::version::BuildVersion::current().into()),
+        priority: priority.as_mut().map_or(base_priority, |p| p.submitted()),
+        depends_on: Vec::new(),
+        constraints: config.wrapper.constraints.clone(),
+        timeout_secs: 0,
+        tenant: config.wrapper.tenant.clone().unwrap_or_default(),
+        session_id: session_id(),
+        input_size,
+        run_after: 0,
+        pool: env::var("DISTBUILD_POOL")
+            .ok()
+            .or_else(|| config.wrapper.pool.clone())
+            .unwrap_or_default(),
     };
     
     eprintln!("📤 [cargo-distbuild] Submitting job to scheduler...");
-    client.submit_job(request).await?;
+    if let Err(e) = submit_with_backoff(&mut client, request.clone()).await {
+        // The scheduler can't see the input (the push may have failed):
+        // push it again and resubmit once; other rejections build locally
+        if e.downcast_ref::<tonic::Status>().and_then(rejection) != Some(Rejection::MissingInput) {
+            return Err(e);
+        }
+        eprintln!("🔁 [cargo-distbuild] Scheduler is missing the input; pushing it again");
+        replicator.push(&cas, &input_hash).await;
+        submit_with_backoff(&mut client, request).await?;
+    }
     
-    // Poll for completion
     eprintln!("⏳ [cargo-distbuild] Waiting for compilation...");
-    let output_hash = poll_for_completion(&mut client, &job_id).await?;
+    let (output_hash, worker_platform) = wait_for_completion(&mut client, &job_id, priority.as_mut()).await?;
     
-    // Download output from CAS
-    eprintln!("📥 [cargo-distbuild] Downloading output...");
-    let output_data = cas.get(&output_hash)?;
+    // Remote artifacts linked against a different platform fail with opaque
+    // linker errors later, so say so up front
+    if let Some(remote) = worker_platform {
+        let local = Platform::detect();
+        if let Some(reason) = local.mismatch(&remote) {
+            eprintln!("⚠️  [cargo-distbuild] Output for {} was {}", rustc_args.crate_name.as_deref().unwrap_or("crate"), reason);
+            eprintln!("   Linking may fail here. Run workers on a matching platform ({}),", local);
+            eprintln!("   or build locally by unsetting RUSTC_WORKSPACE_WRAPPER.");
+        }
+    }
     
-    // Write to output location
+    // Stream output from CAS to the output location (all artifacts or none)
+    if rustc_args.output_path.is_some() {
+        eprintln!("📥 [cargo-distbuild] Downloading output...");
+        if !cas.exists(&output_hash) && !replicator.pull(&cas, &output_hash).await? {
+            anyhow::bail!("Output {} not found in CAS or on any peer", output_hash);
+        }
+    }
+    write_output(rustc_args, &cas, &output_hash)
+}
+
+/// How long a wrapper keeps resubmitting to a full queue
+const QUEUE_FULL_PATIENCE: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Submit the job, waiting out a full scheduler queue for a while before
+/// giving up (and building locally)
+async fn submit_with_backoff(
+    client: &mut crate::common::auth::AuthedSchedulerClient,
+    request: crate::proto::distbuild::SubmitJobRequest,
+) -> Result<()> {
+    use crate::common::error::retry_after;
+    use tokio::time::{sleep, Duration, Instant};
+
+    let deadline = Instant::now() + QUEUE_FULL_PATIENCE;
+    loop {
+        let status = match client.submit_job(request.clone()).await {
+            Ok(_) => return Ok(()),
+            Err(status) => status,
+        };
+        // Spread out the retries of a whole build's worth of wrappers
+        let wait = match retry_after(&status) {
+            Some(wait) => wait + Duration::from_millis(u64::from(std::process::id()) % 1000),
+            None => return Err(status.into()),
+        };
+        if Instant::now() + wait > deadline {
+            return Err(anyhow::Error::from(status).context("Scheduler queue stayed full"));
+        }
+        eprintln!("⏸️  [cargo-distbuild] Scheduler queue is full, retrying in {:.1}s", wait.as_secs_f64());
+        sleep(wait).await;
+    }
+}
+
+/// Write output blob `output_hash` to the crate's output location
+fn write_output(rustc_args: &RustcArgs, cas: &crate::cas::Cas, output_hash: &str) -> Result<()> {
     if let Some(output_path) = &rustc_args.output_path {
-        let size = output_data.len();
-        fs::write(output_path, output_data)?;
-        eprintln!("   Wrote {} bytes to {:?}", size, output_path);
+        let reader = cas.get_reader(output_hash)?;
+        for (path, size) in writeback::write_blob_atomically(output_path, reader)? {
+            eprintln!("   Wrote {} bytes to {:?}", size, path);
+        }
     }
-    
     Ok(())
 }
 
+/// Keeps a job's priority at the number of this build's crates still
+/// waiting on it (directly or not), so the scheduler favours whatever most
+/// of the build is blocked behind
+struct PriorityReporter {
+    package: String,
+    dependents: Dependents,
+    base: i32,
+    reported: Option<i32>,
+}
+
+impl PriorityReporter {
+    /// `None` when the workspace's Cargo.lock can't be found or read
+    fn new(rustc_args: &RustcArgs, base: i32) -> Option<Self> {
+        let package = env::var("CARGO_PKG_NAME").ok()?;
+        // The output dir sits in the workspace's target dir, unlike the
+        // sources of registry dependencies
+        let dir = rustc_args
+            .output_path
+            .clone()
+            .or_else(|| env::current_dir().ok())?;
+        let dependents = Dependents::for_dir(&dir).ok()?;
+        Some(PriorityReporter {
+            package,
+            dependents,
+            base,
+            reported: None,
+        })
+    }
+
+    /// The priority to submit the job with
+    fn submitted(&mut self) -> i32 {
+        let priority = self.current();
+        self.reported = Some(priority);
+        priority
+    }
+
+    /// `base` plus the number of this build's crates still waiting on ours
+    fn current(&self) -> i32 {
+        let finished: std::collections::HashSet<String> = BuildSession::current()
+            .and_then(|session| session.units().ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|unit| unit.package)
+            .collect();
+        let pending = self.dependents.pending(&self.package, &finished).min(i32::MAX as usize) as i32;
+        self.base.saturating_add(pending)
+    }
+
+    /// Send the current priority if it changed (best effort)
+    async fn report(
+        &mut self,
+        client: &mut crate::common::auth::AuthedSchedulerClient,
+        job_id: &str,
+    ) {
+        use crate::proto::distbuild::UpdateJobPriorityRequest;
+
+        let priority = self.current();
+        if self.reported == Some(priority) {
+            return;
+        }
+
+        let request = UpdateJobPriorityRequest {
+            job_id: job_id.to_string(),
+            priority,
+        };
+        if client.update_job_priority(request).await.is_ok() {
+            self.reported = Some(priority);
+        }
+    }
+}
+
+/// Tell the scheduler our build is blocked on `job_id`, still queued after
+/// a while, so it's dispatched ahead of batch work. Returns whether that's
+/// done with: the scheduler heard, or is too old to know of boosts.
+async fn boost(client: &mut crate::common::auth::AuthedSchedulerClient, job_id: &str) -> bool {
+    use crate::proto::distbuild::BoostJobRequest;
+
+    let request = BoostJobRequest {
+        job_id: job_id.to_string(),
+    };
+    match client.boost_job(request).await {
+        Ok(_) => true,
+        Err(status) => status.code() == tonic::Code::Unimplemented,
+    }
+}
+
+/// Wait until the job finishes: follow it as the scheduler reports each
+/// change, or poll a scheduler that can't
+async fn wait_for_completion(
+    client: &mut crate::common::auth::AuthedSchedulerClient,
+    job_id: &str,
+    mut priority: Option<&mut PriorityReporter>,
+) -> Result<(String, Option<Platform>)> {
+    use crate::common::types::JobStatusEnum;
+    use crate::proto::distbuild::*;
+    use tokio::time::{timeout, Duration, Instant};
+
+    let request = WatchJobRequest {
+        job_id: job_id.to_string(),
+    };
+    let mut updates = match client.watch_job(request).await {
+        Ok(response) => response.into_inner(),
+        Err(status) if status.code() == tonic::Code::Unimplemented => {
+            return poll_for_completion(client, job_id, priority).await;
+        }
+        Err(status) => return Err(status.into()),
+    };
+
+    let started = Instant::now();
+    let mut current = JobStatusEnum::Pending;
+    let mut boosted = false;
+    // The last update's ETA, counted down from when it came
+    let mut eta = (0, 0, Instant::now());
+    loop {
+        match timeout(Duration::from_secs(5), updates.message()).await {
+            Ok(update) => {
+                let Some(status) = update? else {
+                    anyhow::bail!("Scheduler stopped reporting on job {}", job_id);
+                };
+                if let Some(finished) = job_outcome(&status) {
+                    return finished;
+                }
+                current = JobStatusEnum::try_from(status.status)
+                    .map_err(|e| anyhow::anyhow!("Scheduler reported {}", e))?;
+                eta = (status.expected_secs, status.remaining_secs, Instant::now());
+            }
+            Err(_) => {
+                let waited = started.elapsed().as_secs();
+                if waited >= 60 {
+                    anyhow::bail!("Job timeout after 60 seconds");
+                }
+                let (expected_secs, remaining_secs, at) = eta;
+                let since = if current == JobStatusEnum::Running { at.elapsed().as_secs() } else { 0 };
+                eprintln!("   Still waiting... ({}/60s) [{}]{}", waited, current, eta_note(expected_secs, remaining_secs, since));
+                if current == JobStatusEnum::Pending {
+                    if let Some(priority) = priority.as_deref_mut() {
+                        priority.report(client, job_id).await;
+                    }
+                }
+                if !boosted && matches!(current, JobStatusEnum::Pending | JobStatusEnum::Blocked) {
+                    boosted = boost(client, job_id).await;
+                }
+            }
+        }
+    }
+}
+
+/// The job's output and the platform it was built on once it completed,
+/// an error once it failed (or its status makes no sense), or `None` while
+/// it's still in progress
+fn job_outcome(status: &crate::proto::distbuild::GetJobStatusResponse) -> Option<Result<(String, Option<Platform>)>> {
+    use crate::common::types::{JobErrorKindEnum, JobStatusEnum};
+
+    let job_status = match JobStatusEnum::try_from(status.status) {
+        Ok(job_status) => job_status,
+        Err(e) => return Some(Err(anyhow::anyhow!("Scheduler reported {}", e))),
+    };
+    let outcome = match job_status {
+        JobStatusEnum::Completed if status.output_hash.is_empty() => {
+            Err(anyhow::anyhow!("Job completed but no output hash"))
+        }
+        JobStatusEnum::Completed => Ok((
+            status.output_hash.clone(),
+            status.worker_platform.clone().map(Platform::from),
+        )),
+        JobStatusEnum::Failed if JobErrorKindEnum::from(status.error_kind) == JobErrorKindEnum::QuotaExceeded => {
+            Err(anyhow::anyhow!(
+                "Job failed: CAS quota exceeded. Run `cargo-distbuild cas gc` or ask \
+                for a larger namespace quota ({})",
+                status.error
+            ))
+        }
+        JobStatusEnum::Failed => Err(anyhow::anyhow!("Job failed: {}", status.error)),
+        JobStatusEnum::Cancelled => Err(anyhow::anyhow!("Job was cancelled")),
+        _ => return None,
+    };
+    Some(outcome)
+}
+
 /// Poll scheduler until job completes
 async fn poll_for_completion(
-    client: &mut crate::proto::distbuild::scheduler_client::SchedulerClient<tonic::transport::Channel>,
+    client: &mut crate::common::auth::AuthedSchedulerClient,
     job_id: &str,
-) -> Result<String> {
+    mut priority: Option<&mut PriorityReporter>,
+) -> Result<(String, Option<Platform>)> {
+    use crate::common::types::JobStatusEnum;
     use crate::proto::distbuild::*;
     use tokio::time::{sleep, Duration};
     
+    let mut boosted = false;
     for attempt in 0..60 {  // Poll for up to 60 seconds
         sleep(Duration::from_secs(1)).await;
         
@@ -205,34 +702,55 @@ async fn poll_for_completion(
         
         let response = client.get_job_status(request).await?;
         let status = response.into_inner();
+        if let Some(finished) = job_outcome(&status) {
+            return finished;
+        }
         
-        match status.status {
-            3 => {  // COMPLETED
-                if status.output_hash.is_empty() {
-                    anyhow::bail!("Job completed but no output hash");
+        let job_status = JobStatusEnum::try_from(status.status)
+            .map_err(|e| anyhow::anyhow!("Scheduler reported {}", e))?;
+
+        match job_status {
+            JobStatusEnum::Pending => {
+                if attempt % 5 == 0 {
+                    let note = eta_note(status.expected_secs, status.remaining_secs, 0);
+                    eprintln!("   Still waiting... ({}/60s) [{}]{}", attempt, job_status, note);
+                    if let Some(priority) = priority.as_deref_mut() {
+                        priority.report(client, job_id).await;
+                    }
                 }
-                return Ok(status.output_hash);
-            }
-            4 => {  // FAILED
-                anyhow::bail!("Job failed: {}", status.error);
             }
             _ => {
                 if attempt % 5 == 0 {
-                    eprintln!("   Still waiting... ({}/60s)", attempt);
+                    let note = eta_note(status.expected_secs, status.remaining_secs, 0);
+                    eprintln!("   Still waiting... ({}/60s) [{}]{}", attempt, job_status, note);
                 }
             }
         }
+        if attempt > 0 && attempt % 5 == 0 && !boosted && matches!(job_status, JobStatusEnum::Pending | JobStatusEnum::Blocked) {
+            boosted = boost(client, job_id).await;
+        }
     }
     
     anyhow::bail!("Job timeout after 60 seconds")
 }
 
-/// Create a tarball of source files for the crate
-fn create_source_tarball(rustc_args: &RustcArgs) -> Result<Vec<u8>> {
+/// ", expected 12s remaining" for a job the scheduler estimated would take
+/// `expected_secs`, `remaining_secs` of them left as of `since` seconds ago;
+/// nothing without an estimate
+fn eta_note(expected_secs: u64, remaining_secs: u64, since: u64) -> String {
+    match remaining_secs.saturating_sub(since) {
+        _ if expected_secs == 0 => String::new(),
+        0 => format!(", taking longer than the usual {}s", expected_secs),
+        remaining => format!(", expected {}s remaining", remaining),
+    }
+}
+
+/// Write a tarball of source files for the crate into CAS, returning its
+/// hash and size
+fn create_source_tarball(rustc_args: &RustcArgs, cas: &crate::cas::Cas) -> Result<(String, u64)> {
     use tar::Builder;
     
-    let mut buffer = Vec::new();
-    let mut tar = Builder::new(&mut buffer);
+    let mut tar = Builder::new(cas.writer()?);
     
     // Add all input .rs files
     for input_file in &rustc_args.input_files {
@@ -241,12 +759,13 @@ fn create_source_tarball(rustc_args: &RustcArgs) -> Result<Vec<u8>> {
                 .and_then(|n| n.to_str())
                 .unwrap_or("input.rs");
             
-            let data = fs::read(input_file)?;
+            let file = fs::File::open(input_file)
+                .with_context(|| format!("Failed to open {:?}", input_file))?;
             let mut header = tar::Header::new_gnu();
-            header.set_size(data.len() as u64);
+            header.set_size(file.metadata()?.len());
             header.set_mode(0o644);
             header.set_cksum();
-            tar.append_data(&mut header, file_name, &data[..])?;
+            tar.append_data(&mut header, file_name, file)?;
         }
     }
     
@@ -254,6 +773,7 @@ fn create_source_tarball(rustc_args: &RustcArgs) -> Result<Vec<u8>> {
     let metadata = serde_json::json!({
         "crate_name": rustc_args.crate_name,
         "is_lib": rustc_args.is_lib,
+        "crate_types": rustc_args.crate_types,
         "rustc_args": rustc_args.original_args,
     });
     let metadata_json = serde_json::to_vec_pretty(&metadata)?;
@@ -263,9 +783,8 @@ fn create_source_tarball(rustc_args: &RustcArgs) -> Result<Vec<u8>> {
     header.set_cksum();
     tar.append_data(&mut header, "metadata.json", &metadata_json[..])?;
     
-    tar.finish()?;
-    drop(tar);
-    
-    Ok(buffer)
+    let writer = tar.into_inner()?;
+    let size = writer.size();
+    Ok((writer.finish()?, size))
 }
 
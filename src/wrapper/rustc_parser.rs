@@ -1,11 +1,22 @@
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
 use std::path::PathBuf;
 use anyhow::Result;
 
+/// Crate types workers can build; anything else (bin, proc-macro) stays local
+const REMOTE_CRATE_TYPES: &[&str] = &["lib", "rlib", "dylib", "cdylib", "staticlib"];
+
 /// Parsed rustc arguments
 #[derive(Debug, Clone)]
 pub struct RustcArgs {
     pub crate_name: Option<String>,
+    /// Every requested `--crate-type`, in order (flags may repeat or hold a list)
+    pub crate_types: Vec<String>,
+    /// Only library crate types were requested, so it can be built remotely
     pub is_lib: bool,
+    /// `-C extra-filename`, the hash cargo appends to artifact names
+    pub extra_filename: String,
+    /// `--target`, when cross-compiling
+    pub target: Option<String>,
     pub input_files: Vec<PathBuf>,
     pub output_path: Option<PathBuf>,
     pub original_args: Vec<String>,
@@ -15,7 +26,9 @@ impl RustcArgs {
     /// Parse rustc command-line arguments
     pub fn parse(args: &[String]) -> Result<Self> {
         let mut crate_name = None;
-        let mut is_lib = false;
+        let mut crate_types = Vec::new();
+        let mut extra_filename = String::new();
+        let mut target = None;
         let mut input_files = Vec::new();
         let mut output_path = None;
         
@@ -32,7 +45,19 @@ impl RustcArgs {
                 }
                 "--crate-type" => {
                     if i + 1 < args.len() {
-                        is_lib = args[i + 1] == "lib" || args[i + 1] == "rlib";
+                        crate_types.extend(split_list(&args[i + 1]));
+                        i += 1;
+                    }
+                }
+                "--target" => {
+                    if i + 1 < args.len() {
+                        target = Some(args[i + 1].clone());
+                        i += 1;
+                    }
+                }
+                "-C" => {
+                    if let Some(value) = args.get(i + 1).and_then(|a| a.strip_prefix("extra-filename=")) {
+                        extra_filename = value.to_string();
                         i += 1;
                     }
                 }
@@ -43,8 +68,14 @@ impl RustcArgs {
                     }
                 }
                 _ => {
-                    // Check if it's a .rs file (input)
-                    if arg.ends_with(".rs") {
+                    if let Some(types) = arg.strip_prefix("--crate-type=") {
+                        crate_types.extend(split_list(types));
+                    } else if let Some(value) = arg.strip_prefix("-Cextra-filename=") {
+                        extra_filename = value.to_string();
+                    } else if let Some(value) = arg.strip_prefix("--target=") {
+                        target = Some(value.to_string());
+                    } else if arg.ends_with(".rs") {
+                        // Check if it's a .rs file (input)
                         input_files.push(PathBuf::from(arg));
                     }
                 }
@@ -53,13 +84,97 @@ impl RustcArgs {
             i += 1;
         }
         
+        let is_lib = !crate_types.is_empty()
+            && crate_types.iter().all(|t| REMOTE_CRATE_TYPES.contains(&t.as_str()));
         Ok(RustcArgs {
             crate_name,
+            crate_types,
             is_lib,
+            extra_filename,
+            target,
             input_files,
             output_path,
             original_args: args.to_vec(),
         })
     }
+
+    /// File names of the artifacts rustc writes to the output directory for
+    /// the requested crate types (on this platform), without duplicates
+    pub fn artifact_names(&self) -> Vec<String> {
+        let name = format!("{}{}", self.crate_name.as_deref().unwrap_or("crate"), self.extra_filename);
+        let mut names: Vec<String> = Vec::new();
+        for crate_type in &self.crate_types {
+            let file = match crate_type.as_str() {
+                "lib" | "rlib" => format!("lib{}.rlib", name),
+                "dylib" | "cdylib" => format!("{}{}{}", DLL_PREFIX, name, DLL_SUFFIX),
+                "staticlib" if cfg!(windows) => format!("{}.lib", name),
+                "staticlib" => format!("lib{}.a", name),
+                _ => continue,
+            };
+            if !names.contains(&file) {
+                names.push(file);
+            }
+        }
+        names
+    }
+
+    /// Cargo profile the unit is built with, from its output directory
+    /// (`target/[<triple>/]<profile>/deps`)
+    pub fn profile(&self) -> Option<String> {
+        let output = self.output_path.as_ref()?;
+        let components: Vec<_> = output.components().collect();
+        let deps = components.iter().rposition(|c| c.as_os_str() == "deps")?;
+        let profile = components.get(deps.checked_sub(1)?)?;
+        Some(profile.as_os_str().to_string_lossy().into_owned())
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = String> + '_ {
+    value.split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_from_out_dir() {
+        let args = |out_dir: &str| {
+            let args: Vec<String> = ["--crate-name", "foo", "--out-dir", out_dir]
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+            RustcArgs::parse(&args).unwrap()
+        };
+
+        assert_eq!(args("/ws/target/release/deps").profile().as_deref(), Some("release"));
+        assert_eq!(
+            args("/ws/target/x86_64-unknown-linux-musl/debug/deps").profile().as_deref(),
+            Some("debug")
+        );
+        assert_eq!(args("/tmp/out").profile(), None);
+    }
+
+    #[test]
+    fn test_multiple_crate_types() {
+        let args: Vec<String> = ["--crate-name", "foo", "--crate-type", "lib,cdylib", "--crate-type=staticlib", "-C", "extra-filename=-abc"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let args = RustcArgs::parse(&args).unwrap();
+        assert_eq!(args.crate_types, vec!["lib", "cdylib", "staticlib"]);
+        assert!(args.is_lib);
+
+        let names = args.artifact_names();
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[0], "libfoo-abc.rlib");
+        assert_eq!(names[1], format!("{}foo-abc{}", DLL_PREFIX, DLL_SUFFIX));
+
+        assert_eq!(args.target, None);
+
+        let bin = RustcArgs::parse(&["--crate-type".to_string(), "lib,bin".to_string()]).unwrap();
+        assert!(!bin.is_lib);
+        let cross = RustcArgs::parse(&["--target=wasm32-unknown-unknown".to_string()]).unwrap();
+        assert_eq!(cross.target.as_deref(), Some("wasm32-unknown-unknown"));
+    }
+}
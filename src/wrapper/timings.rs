@@ -0,0 +1,200 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Historical compile durations per crate, shared by all wrapper
+/// invocations through a small JSON file
+#[derive(Debug, Default)]
+pub struct CrateTimings {
+    path: PathBuf,
+    entries: HashMap<String, CrateTiming>,
+    /// Crates this process recorded samples for, merged into the file on save
+    updated: HashSet<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CrateTiming {
+    /// Smoothed local compile duration in milliseconds
+    #[serde(default)]
+    local_ms: Option<u64>,
+    /// Smoothed remote wall time (the whole round trip) in milliseconds
+    #[serde(default)]
+    remote_ms: Option<u64>,
+    /// Unix timestamp the crate last went remote, or was first timed; a
+    /// tiny crate goes remote again once this is old, to be re-measured
+    #[serde(default, alias = "updated_at")]
+    checked_at: i64,
+}
+
+impl CrateTimings {
+    /// Load timings from the default location (~/.cache/cargo-distbuild)
+    pub fn load_default() -> Self {
+        let path = dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("cargo-distbuild")
+            .join("crate-timings.json");
+        Self::load(path)
+    }
+
+    /// Load timings from `path`; a missing or corrupt file yields empty history
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let entries = read_entries(&path);
+        CrateTimings {
+            path,
+            entries,
+            updated: HashSet::new(),
+        }
+    }
+
+    /// Whether the crate compiles in under `threshold_ms` (locally if it
+    /// ever did, else remotely) and was measured remotely in the last
+    /// `reevaluate_secs`
+    pub fn should_compile_locally(
+        &self,
+        crate_name: &str,
+        threshold_ms: u64,
+        reevaluate_secs: u64,
+        now: i64,
+    ) -> bool {
+        let Some(timing) = self.entries.get(crate_name) else {
+            return false;
+        };
+        if now - timing.checked_at >= reevaluate_secs as i64 {
+            return false;
+        }
+        timing.local_ms.or(timing.remote_ms).is_some_and(|ms| ms < threshold_ms)
+    }
+
+    /// Record a local compile duration (exponentially smoothed with history)
+    pub fn record_local(&mut self, crate_name: &str, duration: Duration, now: i64) {
+        let timing = self.timing(crate_name, now);
+        timing.local_ms = Some(smoothed(timing.local_ms, duration));
+    }
+
+    /// Record a remote compile's wall time, which re-checks the crate
+    pub fn record_remote(&mut self, crate_name: &str, duration: Duration, now: i64) {
+        let timing = self.timing(crate_name, now);
+        timing.remote_ms = Some(smoothed(timing.remote_ms, duration));
+        timing.checked_at = now;
+    }
+
+    fn timing(&mut self, crate_name: &str, now: i64) -> &mut CrateTiming {
+        self.updated.insert(crate_name.to_string());
+        self.entries.entry(crate_name.to_string()).or_insert_with(|| CrateTiming {
+            checked_at: now,
+            ..Default::default()
+        })
+    }
+
+    /// Write the crates recorded here back to disk. Wrappers run in
+    /// parallel, so this merges into the file's current contents under a
+    /// lock, then replaces it atomically.
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+
+        let lock_path = self.path.with_extension("lock");
+        let lock = fs::File::create(&lock_path)
+            .with_context(|| format!("Failed to open {:?}", lock_path))?;
+        lock.lock().with_context(|| format!("Failed to lock {:?}", lock_path))?;
+
+        let mut entries = read_entries(&self.path);
+        for crate_name in &self.updated {
+            entries.insert(crate_name.clone(), self.entries[crate_name].clone());
+        }
+
+        let tmp_path = self.path.with_extension(format!("tmp.{}", std::process::id()));
+        fs::write(&tmp_path, serde_json::to_vec(&entries)?)
+            .with_context(|| format!("Failed to write {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to rename {:?} to {:?}", tmp_path, self.path))?;
+
+        Ok(())
+    }
+}
+
+fn read_entries(path: &Path) -> HashMap<String, CrateTiming> {
+    fs::read(path)
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+/// `sample` averaged with the previous smoothed value, if any
+fn smoothed(previous: Option<u64>, sample: Duration) -> u64 {
+    let sample = sample.as_millis() as u64;
+    match previous {
+        Some(previous) => (previous + sample) / 2,
+        None => sample,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_tiny_crate_detection() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("timings.json");
+
+        let mut timings = CrateTimings::load(&path);
+        assert!(!timings.should_compile_locally("tiny", 100, 3600, 1000));
+
+        timings.record_local("tiny", Duration::from_millis(40), 1000);
+        timings.record_local("huge", Duration::from_millis(5000), 1000);
+        timings.save().unwrap();
+
+        let timings = CrateTimings::load(&path);
+        assert!(timings.should_compile_locally("tiny", 100, 3600, 1000));
+        assert!(!timings.should_compile_locally("huge", 100, 3600, 1000));
+
+        // Stale samples are re-evaluated
+        assert!(!timings.should_compile_locally("tiny", 100, 3600, 1000 + 3600));
+    }
+
+    #[test]
+    fn test_remote_timings() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("timings.json");
+
+        // A crate that only ever went remote is learned as tiny from that
+        let mut timings = CrateTimings::load(&path);
+        timings.record_remote("tiny", Duration::from_millis(60), 1000);
+        timings.record_remote("huge", Duration::from_millis(5000), 1000);
+        assert!(timings.should_compile_locally("tiny", 100, 3600, 1000));
+        assert!(!timings.should_compile_locally("huge", 100, 3600, 1000));
+
+        // Compiling it locally doesn't postpone the next remote re-check
+        timings.record_local("tiny", Duration::from_millis(20), 4000);
+        assert!(timings.should_compile_locally("tiny", 100, 3600, 4000));
+        assert!(!timings.should_compile_locally("tiny", 100, 3600, 1000 + 3600));
+        timings.record_remote("tiny", Duration::from_millis(80), 4600);
+        assert!(timings.should_compile_locally("tiny", 100, 3600, 4600));
+    }
+
+    #[test]
+    fn test_parallel_saves_merge() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("timings.json");
+
+        // Two wrappers load the same history and each time a different crate
+        let mut first = CrateTimings::load(&path);
+        let mut second = CrateTimings::load(&path);
+        first.record_local("a", Duration::from_millis(10), 1000);
+        second.record_local("b", Duration::from_millis(20), 1000);
+        first.save().unwrap();
+        second.save().unwrap();
+
+        let timings = CrateTimings::load(&path);
+        assert!(timings.should_compile_locally("a", 100, 3600, 1000));
+        assert!(timings.should_compile_locally("b", 100, 3600, 1000));
+    }
+}
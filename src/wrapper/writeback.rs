@@ -0,0 +1,238 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Stream a job output blob to disk as a set of artifacts, all or nothing.
+/// A tar archive carries several artifacts (entry paths relative to the
+/// output directory); anything else is a single artifact for `output_path`.
+/// Returns each written path with its size.
+pub fn write_blob_atomically<R: Read>(output_path: &Path, reader: R) -> Result<Vec<(PathBuf, u64)>> {
+    // Peek far enough to see the tar magic, then stream the rest
+    let mut head = Vec::with_capacity(TAR_HEADER_LEN);
+    let mut reader = reader;
+    (&mut reader).take(TAR_HEADER_LEN as u64).read_to_end(&mut head)?;
+    let is_archive = is_tar(&head);
+    let mut reader = io::Cursor::new(head).chain(reader);
+
+    let suffix = staging_suffix();
+    let mut staged = Vec::new();
+    let result = (|| -> Result<()> {
+        if !is_archive {
+            let tmp = with_suffix(output_path, &format!("{}.tmp", suffix));
+            staged.push((output_path.to_path_buf(), tmp.clone()));
+            stage(&tmp, &mut reader)
+                .with_context(|| format!("Failed to stage artifact {:?}", output_path))?;
+            return Ok(());
+        }
+
+        let out_dir = if output_path.is_dir() {
+            output_path
+        } else {
+            output_path.parent().unwrap_or(Path::new("."))
+        };
+
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries().context("Failed to read artifact archive")? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_path_buf();
+
+            // Never let a remote archive write outside the output directory
+            if !name.components().all(|c| matches!(c, Component::Normal(_))) {
+                anyhow::bail!("Refusing artifact with unsafe path {:?}", name);
+            }
+
+            let path = out_dir.join(name);
+            let tmp = with_suffix(&path, &format!("{}.tmp", suffix));
+            staged.push((path.clone(), tmp.clone()));
+            stage(&tmp, &mut entry)
+                .with_context(|| format!("Failed to stage artifact {:?}", path))?;
+        }
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        for (_, tmp) in &staged {
+            let _ = fs::remove_file(tmp);
+        }
+        return Err(e);
+    }
+
+    let sizes = staged
+        .iter()
+        .map(|(path, tmp)| (path.clone(), fs::metadata(tmp).map(|m| m.len()).unwrap_or(0)))
+        .collect();
+    commit_staged(&staged, &suffix)?;
+    Ok(sizes)
+}
+
+const TAR_HEADER_LEN: usize = 512;
+
+/// Whether the data looks like a (ustar/GNU) tar archive
+fn is_tar(data: &[u8]) -> bool {
+    data.len() >= TAR_HEADER_LEN && &data[257..262] == b"ustar"
+}
+
+/// Write all artifacts as a set: either every file is replaced, or none is.
+///
+/// Contents are staged in temp files next to their destination, existing files
+/// are moved aside, and the staged files are renamed into place. Any failure
+/// restores the previous files, so Cargo never sees a partial artifact set.
+pub fn write_artifacts_atomically(artifacts: &[(PathBuf, Vec<u8>)]) -> Result<()> {
+    let suffix = staging_suffix();
+    let staged: Vec<(PathBuf, PathBuf)> = artifacts
+        .iter()
+        .map(|(path, _)| (path.clone(), with_suffix(path, &format!("{}.tmp", suffix))))
+        .collect();
+
+    // Stage every artifact first
+    for ((path, data), (_, tmp)) in artifacts.iter().zip(&staged) {
+        if let Err(e) = fs::write(tmp, data) {
+            for (_, tmp) in &staged {
+                let _ = fs::remove_file(tmp);
+            }
+            return Err(e).with_context(|| format!("Failed to stage artifact {:?}", path));
+        }
+    }
+
+    commit_staged(&staged, &suffix)
+}
+
+fn staging_suffix() -> String {
+    format!("distbuild.{}", std::process::id())
+}
+
+/// Copy `reader` into a new staging file
+fn stage<R: Read>(tmp: &Path, reader: &mut R) -> Result<()> {
+    let mut file = fs::File::create(tmp)?;
+    io::copy(reader, &mut file)?;
+    Ok(())
+}
+
+/// Swap staged `(destination, temp)` files into place, keeping backups of
+/// what was there; on any failure restore the previous files
+fn commit_staged(staged: &[(PathBuf, PathBuf)], suffix: &str) -> Result<()> {
+    let mut committed: Vec<(&Path, Option<PathBuf>)> = Vec::new();
+    for (path, tmp) in staged {
+        let result = (|| -> Result<Option<PathBuf>> {
+            let backup = if path.exists() {
+                let backup = with_suffix(path, &format!("{}.bak", suffix));
+                fs::rename(path, &backup)?;
+                Some(backup)
+            } else {
+                None
+            };
+
+            if let Err(e) = fs::rename(tmp, path) {
+                if let Some(backup) = &backup {
+                    let _ = fs::rename(backup, path);
+                }
+                return Err(e.into());
+            }
+
+            Ok(backup)
+        })();
+
+        match result {
+            Ok(backup) => committed.push((path, backup)),
+            Err(e) => {
+                rollback(&committed);
+                for (_, tmp) in staged {
+                    let _ = fs::remove_file(tmp);
+                }
+                return Err(e).with_context(|| format!("Failed to write artifact {:?}", path));
+            }
+        }
+    }
+
+    for (_, backup) in committed {
+        if let Some(backup) = backup {
+            let _ = fs::remove_file(backup);
+        }
+    }
+
+    Ok(())
+}
+
+/// Restore the previous state of already-committed artifacts
+fn rollback(committed: &[(&Path, Option<PathBuf>)]) {
+    for (path, backup) in committed.iter().rev() {
+        match backup {
+            Some(backup) => {
+                let _ = fs::rename(backup, path);
+            }
+            None => {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_writeback_is_all_or_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let rlib = temp_dir.path().join("libfoo.rlib");
+        let rmeta = temp_dir.path().join("libfoo.rmeta");
+        fs::write(&rlib, b"old rlib").unwrap();
+
+        // Second artifact can't be written (missing directory): nothing changes
+        let artifacts = vec![
+            (rlib.clone(), b"new rlib".to_vec()),
+            (temp_dir.path().join("missing").join("libfoo.d"), b"deps".to_vec()),
+        ];
+        assert!(write_artifacts_atomically(&artifacts).is_err());
+        assert_eq!(fs::read(&rlib).unwrap(), b"old rlib");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+        // A good set replaces everything
+        let artifacts = vec![
+            (rlib.clone(), b"new rlib".to_vec()),
+            (rmeta.clone(), b"new rmeta".to_vec()),
+        ];
+        write_artifacts_atomically(&artifacts).unwrap();
+        assert_eq!(fs::read(&rlib).unwrap(), b"new rlib");
+        assert_eq!(fs::read(&rmeta).unwrap(), b"new rmeta");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_write_blob_streams_tar_artifacts() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let mut buffer = Vec::new();
+        {
+            let mut tar = tar::Builder::new(&mut buffer);
+            for (name, data) in [("libfoo.rlib", &b"rlib"[..]), ("libfoo.so", &b"so"[..])] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                tar.append_data(&mut header, name, data).unwrap();
+            }
+            tar.finish().unwrap();
+        }
+
+        let written = write_blob_atomically(temp_dir.path(), &buffer[..]).unwrap();
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[1], (temp_dir.path().join("libfoo.so"), 2));
+        assert_eq!(fs::read(temp_dir.path().join("libfoo.rlib")).unwrap(), b"rlib");
+
+        // Plain blobs are a single artifact
+        let out = temp_dir.path().join("out");
+        write_blob_atomically(&out, &b"rlib"[..]).unwrap();
+        assert_eq!(fs::read(&out).unwrap(), b"rlib");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 3);
+    }
+}
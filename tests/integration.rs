@@ -1,4 +1,7 @@
 use cargo_distbuild::cas::Cas;
+use cargo_distbuild::cas::replication::Replicator;
+use cargo_distbuild::cas::service::BlobStoreService;
+use cargo_distbuild::common::config::{ReplicationConfig, ReplicationMode};
 use cargo_distbuild::common::Config;
 use cargo_distbuild::proto::distbuild::scheduler_client::SchedulerClient;
 use cargo_distbuild::proto::distbuild::*;
@@ -93,6 +96,7 @@ async fn test_scheduler_worker_registration() {
         address: "127.0.0.1:16001".to_string(),
         capacity: 4,
         labels: std::collections::HashMap::new(),
+        ..Default::default()
     };
 
     let response = client.register_worker(request).await.unwrap();
@@ -145,6 +149,7 @@ async fn test_job_submission_and_status() {
         input_hash: input_hash.clone(),
         job_type: "test-transform".to_string(),
         metadata: std::collections::HashMap::new(),
+        ..Default::default()
     };
 
     let submit_response = client.submit_job(submit_request).await.unwrap();
@@ -165,7 +170,10 @@ async fn test_job_submission_and_status() {
     assert_eq!(status_resp.status, 0);
 
     // List jobs
-    let list_request = ListJobsRequest { limit: 10 };
+    let list_request = ListJobsRequest {
+        limit: 10,
+        ..Default::default()
+    };
     let list_response = client.list_jobs(list_request).await.unwrap();
     let list_resp = list_response.into_inner();
 
@@ -231,6 +239,7 @@ async fn test_end_to_end_workflow() {
         input_hash: input_hash.clone(),
         job_type: "transform".to_string(),
         metadata: std::collections::HashMap::new(),
+        ..Default::default()
     };
 
     let response = client.submit_job(submit_request).await.unwrap();
@@ -306,3 +315,3123 @@ async fn test_worker_heartbeat() {
     let now = chrono::Utc::now().timestamp();
     assert!(now - worker.last_heartbeat < 30);
 }
+
+#[tokio::test]
+async fn test_client_error_reporting() {
+    let addr = "127.0.0.1:15004".to_string();
+
+    // Start scheduler
+    let scheduler_addr = addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(scheduler_addr)
+            .await
+            .unwrap();
+    });
+
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+
+    // The same error reported repeatedly is deduplicated, and the client is
+    // rate-limited after 10 reports per minute
+    let mut accepted = 0;
+    for _ in 0..12 {
+        let request = ReportClientErrorRequest {
+            client_id: "dev@laptop".to_string(),
+            kind: "cas".to_string(),
+            message: "Hash abc not found in CAS".to_string(),
+            job_id: String::new(),
+        };
+        let resp = client.report_client_error(request).await.unwrap().into_inner();
+        if resp.accepted {
+            accepted += 1;
+        } else {
+            assert!(resp.rate_limited);
+        }
+    }
+    assert_eq!(accepted, 10);
+
+    let list_resp = client
+        .list_client_errors(ListClientErrorsRequest { limit: 0 })
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(list_resp.errors.len(), 1);
+    assert_eq!(list_resp.errors[0].count, 10);
+    assert_eq!(list_resp.errors[0].clients, vec!["dev@laptop".to_string()]);
+}
+
+#[tokio::test]
+async fn test_scaling_advice() {
+    let addr = "127.0.0.1:15005".to_string();
+
+    // Start scheduler
+    let scheduler_addr = addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(scheduler_addr)
+            .await
+            .unwrap();
+    });
+
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+
+    // Idle cluster: nothing to do
+    let resp = client
+        .get_scaling_advice(GetScalingAdviceRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(resp.desired_workers, 0);
+    assert_eq!(resp.window_secs, 300);
+
+    // Queued jobs with no workers and no duration history ask for capacity
+    for i in 0..3 {
+        let request = SubmitJobRequest {
+            job_id: format!("scaling-job-{}", i),
+            input_hash: format!("{:064}", i), // identical jobs would share one
+            job_type: "test".to_string(),
+            metadata: std::collections::HashMap::new(),
+            ..Default::default()
+        };
+        client.submit_job(request).await.unwrap();
+    }
+
+    let resp = client
+        .get_scaling_advice(GetScalingAdviceRequest {
+            window_secs: 60,
+            target_utilization: 1.0,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(resp.current_workers, 0);
+    assert_eq!(resp.queue_depth, 3);
+    assert_eq!(resp.desired_workers, 3);
+    assert!(resp.arrival_rate > 0.0);
+}
+
+#[tokio::test]
+async fn test_list_jobs_filters() {
+    let addr = "127.0.0.1:15006".to_string();
+
+    // Start scheduler
+    let scheduler_addr = addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(scheduler_addr)
+            .await
+            .unwrap();
+    });
+
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+
+    accepting_worker(&mut client, "filters", 16046, 3).await;
+    for (i, (job_id, crate_name)) in [("filter-1", "serde"), ("filter-2", "tokio"), ("filter-3", "serde_json")].into_iter().enumerate() {
+        let request = SubmitJobRequest {
+            job_id: job_id.to_string(),
+            input_hash: format!("{:064}", i),
+            job_type: "test".to_string(),
+            metadata: std::collections::HashMap::from([
+                ("crate_name".to_string(), crate_name.to_string()),
+            ]),
+            ..Default::default()
+        };
+        client.submit_job(request).await.unwrap();
+    }
+    client
+        .report_job_result(ReportJobResultRequest {
+            job_id: "filter-2".to_string(),
+            worker_id: "filters".to_string(),
+            success: false,
+            error: "linker `cc` not found".to_string(),
+            // Not retried, unlike other worker failures
+            error_kind: JobErrorKind::Compile as i32,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let list = |request: ListJobsRequest| {
+        let mut client = client.clone();
+        async move {
+            let mut ids: Vec<String> = client
+                .list_jobs(request)
+                .await
+                .unwrap()
+                .into_inner()
+                .jobs
+                .into_iter()
+                .map(|job| job.job_id)
+                .collect();
+            ids.sort();
+            ids
+        }
+    };
+
+    let by_crate = list(ListJobsRequest {
+        grep: "SERDE".to_string(),
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(by_crate, vec!["filter-1", "filter-3"]);
+
+    let failed = list(ListJobsRequest {
+        status: vec![JobStatus::Failed as i32],
+        grep: "linker".to_string(),
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(failed, vec!["filter-2"]);
+
+    let future = list(ListJobsRequest {
+        since: chrono::Utc::now().timestamp() + 3600,
+        ..Default::default()
+    })
+    .await;
+    assert!(future.is_empty());
+
+    // Relative windows are measured on the scheduler's clock
+    let recent = list(ListJobsRequest {
+        within_secs: 3600,
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(recent.len(), 3);
+}
+
+#[tokio::test]
+async fn test_cas_replication() {
+    // Two nodes with their own CAS roots, each serving it to peers
+    let dir_a = TempDir::new().unwrap();
+    let dir_b = TempDir::new().unwrap();
+    let cas_a = Arc::new(Cas::new(dir_a.path()).unwrap());
+    let cas_b = Arc::new(Cas::new(dir_b.path()).unwrap());
+
+    for (cas, addr) in [(cas_a.clone(), "127.0.0.1:16010"), (cas_b.clone(), "127.0.0.1:16011")] {
+        tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(BlobStoreService::server(cas))
+                .serve(addr.parse().unwrap())
+                .await
+                .unwrap();
+        });
+    }
+
+    sleep(Duration::from_secs(1)).await;
+
+    // A pushes a new output to B
+    let config = ReplicationConfig {
+        mode: ReplicationMode::Both,
+        peers: vec!["127.0.0.1:16010".to_string(), "127.0.0.1:16011".to_string()],
+        discover_workers: false,
+    };
+    let replicator_a = Replicator::new(&config, "127.0.0.1:15999", "127.0.0.1:16010");
+    let hash = cas_a.put(b"libfoo.rlib contents").unwrap();
+    assert_eq!(replicator_a.push(&cas_a, &hash).await, 1);
+    assert!(cas_b.exists(&hash));
+
+    // Pushing again is a no-op since B already has it
+    assert_eq!(replicator_a.push(&cas_a, &hash).await, 0);
+
+    // A third node without the blob pulls it from a peer
+    let dir_c = TempDir::new().unwrap();
+    let cas_c = Cas::new(dir_c.path()).unwrap();
+    let replicator_c = Replicator::new(&config, "127.0.0.1:15999", "127.0.0.1:16012");
+    assert!(replicator_c.pull(&cas_c, &hash).await.unwrap());
+    assert_eq!(cas_c.get(&hash).unwrap(), b"libfoo.rlib contents");
+    assert!(!replicator_c.pull(&cas_c, &"0".repeat(64)).await.unwrap());
+
+    // A compile done on A reaches B with its action-cache entry
+    let input = cas_a.put(b"libbar source tarball").unwrap();
+    let output = cas_a.put(b"libbar.rlib contents").unwrap();
+    cas_a.record_action(&input, &output).unwrap();
+    assert_eq!(replicator_a.push_action(&cas_a, &input, &output).await, 1);
+    assert_eq!(cas_b.action_result(&input), Some(output.clone()));
+    assert_eq!(cas_b.get(&output).unwrap(), b"libbar.rlib contents");
+}
+
+#[tokio::test]
+async fn test_inspect_and_cancel_job() {
+    let scheduler_addr = "127.0.0.1:15007".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "inspect-me".to_string(),
+            input_hash: "ab".repeat(32),
+            job_type: "rust-compile".to_string(),
+            metadata: [("crate_name".to_string(), "demo".to_string())].into(),
+            priority: 3,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let submitted = client
+        .inspect_job(InspectJobRequest { job_id: "inspect-me".to_string() })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(submitted.job.unwrap().priority, 3);
+
+    let reprioritize = |priority| UpdateJobPriorityRequest {
+        job_id: "inspect-me".to_string(),
+        priority,
+    };
+    assert!(client.update_job_priority(reprioritize(7)).await.unwrap().into_inner().updated);
+
+    let cancel = client
+        .cancel_job(CancelJobRequest { job_id: "inspect-me".to_string() })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(cancel.cancelled);
+
+    // Cancelling again is a no-op
+    let again = client
+        .cancel_job(CancelJobRequest { job_id: "inspect-me".to_string() })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!again.cancelled);
+    assert_eq!(again.status, JobStatus::Cancelled as i32);
+    // Only queued jobs can be re-prioritized
+    assert!(!client.update_job_priority(reprioritize(9)).await.unwrap().into_inner().updated);
+    // Progress for a job that isn't running on the reporter is refused
+    let progress = client
+        .report_job_progress(ReportJobProgressRequest {
+            worker_id: "w1".to_string(),
+            job_id: "inspect-me".to_string(),
+            phase: "compiling".to_string(),
+            elapsed_secs: 30,
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!progress.keep_running);
+
+    let inspected = client
+        .inspect_job(InspectJobRequest { job_id: "inspect-me".to_string() })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(inspected.job_type, "rust-compile");
+    let job = inspected.job.unwrap();
+    assert_eq!(job.crate_name, "demo");
+    assert_eq!(job.priority, 7);
+    let timeline: Vec<i32> = inspected.timeline.iter().map(|t| t.status).collect();
+    assert_eq!(timeline, vec![JobStatus::Pending as i32, JobStatus::Cancelled as i32]);
+    assert!(inspected.progress.is_none());
+    assert!(!inspected.stalled);
+
+    assert!(client
+        .inspect_job(InspectJobRequest { job_id: "missing".to_string() })
+        .await
+        .is_err());
+}
+
+/// Worker that takes every job it's sent, recording which it's told to
+/// abort
+#[derive(Clone, Default)]
+struct AbortRecorder {
+    aborted: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[tonic::async_trait]
+impl worker_server::Worker for AbortRecorder {
+    async fn execute_job(
+        &self,
+        _request: tonic::Request<ExecuteJobRequest>,
+    ) -> Result<tonic::Response<ExecuteJobResponse>, tonic::Status> {
+        Ok(tonic::Response::new(ExecuteJobResponse { success: true, ..Default::default() }))
+    }
+
+    async fn get_status(
+        &self,
+        _request: tonic::Request<GetStatusRequest>,
+    ) -> Result<tonic::Response<GetStatusResponse>, tonic::Status> {
+        Ok(tonic::Response::new(GetStatusResponse::default()))
+    }
+
+    async fn abort_job(
+        &self,
+        request: tonic::Request<AbortJobRequest>,
+    ) -> Result<tonic::Response<AbortJobResponse>, tonic::Status> {
+        self.aborted.lock().unwrap().push(request.into_inner().job_id);
+        Ok(tonic::Response::new(AbortJobResponse { aborted: true }))
+    }
+
+    async fn scheduler_stopping(
+        &self,
+        _request: tonic::Request<SchedulerStoppingRequest>,
+    ) -> Result<tonic::Response<SchedulerStoppingResponse>, tonic::Status> {
+        Ok(tonic::Response::new(SchedulerStoppingResponse { running: 0 }))
+    }
+}
+
+#[tokio::test]
+async fn test_cancel_running_job() {
+    let scheduler_addr = "127.0.0.1:15048".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    let recorder = AbortRecorder::default();
+    let server = worker_server::WorkerServer::new(recorder.clone());
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(server)
+            .serve("127.0.0.1:16056".parse().unwrap())
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "one-slot".to_string(),
+            address: "127.0.0.1:16056".to_string(),
+            capacity: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    for (job_id, input) in [("doomed", "c1"), ("next", "c2")] {
+        client
+            .submit_job(SubmitJobRequest {
+                job_id: job_id.to_string(),
+                input_hash: input.repeat(32),
+                job_type: "rust-compile".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    sleep(Duration::from_millis(500)).await;
+    let status = |job_id: &str| {
+        let mut client = client.clone();
+        let job_id = job_id.to_string();
+        async move { client.get_job_status(GetJobStatusRequest { job_id }).await.unwrap().into_inner() }
+    };
+    assert_eq!(status("doomed").await.status, JobStatus::Running as i32);
+    assert_eq!(status("next").await.status, JobStatus::Pending as i32);
+
+    // The worker is told to stop it
+    let cancel = client
+        .clone()
+        .cancel_job(CancelJobRequest { job_id: "doomed".to_string() })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(cancel.cancelled);
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(*recorder.aborted.lock().unwrap(), ["doomed"]);
+
+    // It finishing anyway doesn't undo the cancel, but frees its slot
+    let resp = client
+        .clone()
+        .report_job_result(ReportJobResultRequest {
+            job_id: "doomed".to_string(),
+            worker_id: "one-slot".to_string(),
+            success: true,
+            output_hash: "c3".repeat(32),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(resp.acknowledged);
+    let doomed = status("doomed").await;
+    assert_eq!(doomed.status, JobStatus::Cancelled as i32);
+    assert!(doomed.output_hash.is_empty());
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(status("next").await.status, JobStatus::Running as i32);
+}
+
+#[tokio::test]
+async fn test_quiesce_and_resume() {
+    let scheduler_addr = "127.0.0.1:15008".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    let submit = |job_id: &str| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: "cd".repeat(32),
+        job_type: "rust-compile".to_string(),
+        ..Default::default()
+    };
+    client.submit_job(submit("before")).await.unwrap();
+
+    let quiesced = client
+        .quiesce(QuiesceRequest { drain: true, timeout_secs: 5, restart: false })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(quiesced.ready);
+    assert_eq!(quiesced.queued, 1);
+    assert!(!quiesced.persisted);
+    assert!(!quiesced.restarting);
+
+    // Nothing is admitted meanwhile; a restart would lose the queued job
+    let refused = client.submit_job(submit("during")).await.unwrap_err();
+    assert_eq!(refused.code(), tonic::Code::Unavailable);
+    let restart = client
+        .quiesce(QuiesceRequest { drain: false, timeout_secs: 0, restart: true })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!restart.restarting);
+
+    assert!(client.resume(ResumeRequest {}).await.unwrap().into_inner().resumed);
+    assert!(!client.resume(ResumeRequest {}).await.unwrap().into_inner().resumed);
+    client.submit_job(submit("after")).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_job_dependencies() {
+    let scheduler_addr = "127.0.0.1:15009".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    let submit = |job_id: &str, depends_on: &[&str]| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: "ef".repeat(32),
+        job_type: "rust-compile".to_string(),
+        depends_on: depends_on.iter().map(|dep| dep.to_string()).collect(),
+        ..Default::default()
+    };
+    async fn status(client: &mut SchedulerClient<tonic::transport::Channel>, job_id: &str) -> i32 {
+        let request = InspectJobRequest { job_id: job_id.to_string() };
+        client.inspect_job(request).await.unwrap().into_inner().job.unwrap().status
+    }
+
+    accepting_worker(&mut client, "deps", 16047, 1).await;
+    client.submit_job(submit("lib", &[])).await.unwrap();
+    client.submit_job(submit("bin", &["lib"])).await.unwrap();
+    client.submit_job(submit("test", &["lib", "bin"])).await.unwrap();
+    let unknown = client.submit_job(submit("orphan", &["missing"])).await.unwrap_err();
+    assert_eq!(unknown.code(), tonic::Code::InvalidArgument);
+    assert_eq!(status(&mut client, "bin").await, JobStatus::Blocked as i32);
+
+    client
+        .report_job_result(ReportJobResultRequest {
+            job_id: "lib".to_string(),
+            worker_id: "deps".to_string(),
+            success: true,
+            output_hash: "01".repeat(32),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    // Unblocked, and given the slot lib freed
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(status(&mut client, "bin").await, JobStatus::Running as i32);
+    assert_eq!(status(&mut client, "test").await, JobStatus::Blocked as i32);
+
+    // A dependency that doesn't complete takes its dependents down with it
+    client.cancel_job(CancelJobRequest { job_id: "bin".to_string() }).await.unwrap();
+    assert_eq!(status(&mut client, "test").await, JobStatus::Failed as i32);
+    client.submit_job(submit("late", &["bin"])).await.unwrap();
+    assert_eq!(status(&mut client, "late").await, JobStatus::Failed as i32);
+
+    // A batch referring to a job later in it is refused as a whole
+    let backwards = SubmitJobsRequest { jobs: vec![submit("b2", &["b1"]), submit("b1", &[])], gang: false };
+    let refused = client.submit_jobs(backwards).await.unwrap_err();
+    assert_eq!(refused.code(), tonic::Code::InvalidArgument);
+    assert!(client.inspect_job(InspectJobRequest { job_id: "b1".to_string() }).await.is_err());
+
+    let plan = SubmitJobsRequest { jobs: vec![submit("b1", &["lib"]), submit("b2", &["b1"])], gang: false };
+    let results = client.submit_jobs(plan).await.unwrap().into_inner().results;
+    let ids: Vec<&str> = results.iter().map(|r| r.job_id.as_str()).collect();
+    assert_eq!(ids, vec!["b1", "b2"]);
+    assert_eq!(status(&mut client, "b1").await, JobStatus::Pending as i32);
+    assert_eq!(status(&mut client, "b2").await, JobStatus::Blocked as i32);
+}
+
+#[tokio::test]
+async fn test_submit_jobs_batch() {
+    let scheduler_addr = "127.0.0.1:15049".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    let submit = |job_id: &str, input: &str, depends_on: &[&str]| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: input.repeat(32),
+        job_type: "rust-compile".to_string(),
+        depends_on: depends_on.iter().map(|dep| dep.to_string()).collect(),
+        ..Default::default()
+    };
+    let inspect = |job_id: &str| {
+        let mut client = client.clone();
+        let request = InspectJobRequest { job_id: job_id.to_string() };
+        async move { client.inspect_job(request).await }
+    };
+
+    // One bad entry and none of the batch is queued, not even those before it
+    let mut bad = submit("bad", "d2", &[]);
+    bad.input_hash = "not-a-hash".to_string();
+    let batch = SubmitJobsRequest {
+        jobs: vec![submit("good-1", "d1", &[]), bad, submit("good-2", "d3", &["good-1"])],
+        gang: false,
+    };
+    let refused = client.clone().submit_jobs(batch).await.unwrap_err();
+    assert_eq!(refused.code(), tonic::Code::InvalidArgument);
+    for job_id in ["good-1", "bad", "good-2"] {
+        assert_eq!(inspect(job_id).await.unwrap_err().code(), tonic::Code::NotFound);
+    }
+
+    // Jobs can wait on ones earlier in the same batch
+    let plan = SubmitJobsRequest {
+        jobs: vec![submit("lib", "d4", &[]), submit("bin", "d5", &["lib"]), submit("test", "d6", &["lib", "bin"])],
+        gang: false,
+    };
+    let results = client.clone().submit_jobs(plan).await.unwrap().into_inner().results;
+    let ids: Vec<&str> = results.iter().map(|r| r.job_id.as_str()).collect();
+    assert_eq!(ids, ["lib", "bin", "test"]);
+    assert!(results.iter().all(|r| r.success));
+    let lib = inspect("lib").await.unwrap().into_inner();
+    assert_eq!(lib.job.unwrap().status, JobStatus::Pending as i32);
+    let test = inspect("test").await.unwrap().into_inner();
+    assert_eq!(test.job.unwrap().status, JobStatus::Blocked as i32);
+    assert_eq!(test.depends_on, ["lib", "bin"]);
+}
+
+#[tokio::test]
+async fn test_queued_job_waits_for_worker() {
+    let scheduler_addr = "127.0.0.1:15010".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "early".to_string(),
+            input_hash: "12".repeat(32),
+            job_type: "rust-compile".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let inspect = || InspectJobRequest { job_id: "early".to_string() };
+    assert_eq!(client.inspect_job(inspect()).await.unwrap().into_inner().attempts, 0);
+
+    // A worker showing up later gets the job without another submission
+    // (nothing listens there, so the dispatch itself fails)
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "late-worker".to_string(),
+            address: "127.0.0.1:16010".to_string(),
+            capacity: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(1500)).await;
+    assert!(client.inspect_job(inspect()).await.unwrap().into_inner().attempts >= 1);
+}
+
+#[tokio::test]
+async fn test_result_cache() {
+    let scheduler_addr = "127.0.0.1:15011".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    let submit = |job_id: &str, job_type: &str| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: "34".repeat(32),
+        job_type: job_type.to_string(),
+        ..Default::default()
+    };
+
+    accepting_worker(&mut client, "cacher", 16048, 1).await;
+    let first = client.submit_job(submit("first", "rust-compile")).await.unwrap().into_inner();
+    assert!(first.output_hash.is_empty());
+    client
+        .report_job_result(ReportJobResultRequest {
+            job_id: "first".to_string(),
+            worker_id: "cacher".to_string(),
+            success: true,
+            output_hash: "56".repeat(32),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // The same input and job type completes at once with the earlier output
+    let second = client.submit_job(submit("second", "rust-compile")).await.unwrap().into_inner();
+    assert_eq!(second.output_hash, "56".repeat(32));
+    let status = client
+        .get_job_status(GetJobStatusRequest { job_id: "second".to_string() })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(status.status, JobStatus::Completed as i32);
+    assert_eq!(status.output_hash, "56".repeat(32));
+
+    // Another job type on the same input still runs
+    let other = client.submit_job(submit("other", "transform")).await.unwrap().into_inner();
+    assert!(other.output_hash.is_empty());
+}
+
+#[tokio::test]
+async fn test_identical_submissions_share_a_job() {
+    let scheduler_addr = "127.0.0.1:15012".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    let submit = |job_id: &str| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: "78".repeat(32),
+        job_type: "rust-compile".to_string(),
+        ..Default::default()
+    };
+    async fn status(client: &mut SchedulerClient<tonic::transport::Channel>, job_id: &str) -> GetJobStatusResponse {
+        let request = GetJobStatusRequest { job_id: job_id.to_string() };
+        client.get_job_status(request).await.unwrap().into_inner()
+    }
+
+    client.submit_job(submit("alice")).await.unwrap();
+    let bob = client.submit_job(submit("bob")).await.unwrap().into_inner();
+    let carol = client.submit_job(submit("carol")).await.unwrap().into_inner();
+    assert_eq!(bob.attached_to, "alice");
+    assert_eq!(carol.attached_to, "alice");
+    assert_eq!(status(&mut client, "bob").await.status, JobStatus::Blocked as i32);
+
+    // Cancelling the job everyone shares hands it to the next submitter
+    client.cancel_job(CancelJobRequest { job_id: "alice".to_string() }).await.unwrap();
+    assert_eq!(status(&mut client, "bob").await.status, JobStatus::Pending as i32);
+    let inspect = InspectJobRequest { job_id: "carol".to_string() };
+    assert_eq!(client.inspect_job(inspect).await.unwrap().into_inner().attached_to, "bob");
+
+    // Its one result reaches every waiter
+    accepting_worker(&mut client, "sharer", 16049, 1).await;
+    client
+        .report_job_result(ReportJobResultRequest {
+            job_id: "bob".to_string(),
+            worker_id: "sharer".to_string(),
+            success: true,
+            output_hash: "9a".repeat(32),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let carol = status(&mut client, "carol").await;
+    assert_eq!(carol.status, JobStatus::Completed as i32);
+    assert_eq!(carol.output_hash, "9a".repeat(32));
+}
+
+#[tokio::test]
+async fn test_watch_job() {
+    let scheduler_addr = "127.0.0.1:15013".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "watched".to_string(),
+            input_hash: "bc".repeat(32),
+            job_type: "rust-compile".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let missing = client.watch_job(WatchJobRequest { job_id: "missing".to_string() }).await;
+    assert_eq!(missing.unwrap_err().code(), tonic::Code::NotFound);
+
+    let mut updates = client
+        .watch_job(WatchJobRequest { job_id: "watched".to_string() })
+        .await
+        .unwrap()
+        .into_inner();
+    let first = updates.message().await.unwrap().unwrap();
+    assert_eq!(first.status, JobStatus::Pending as i32);
+
+    accepting_worker(&mut client, "watched-worker", 16050, 1).await;
+    client
+        .report_job_result(ReportJobResultRequest {
+            job_id: "watched".to_string(),
+            worker_id: "watched-worker".to_string(),
+            success: true,
+            output_hash: "de".repeat(32),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    // Dispatched on the way
+    let done = loop {
+        let update = updates.message().await.unwrap().unwrap();
+        if ![JobStatus::Assigned as i32, JobStatus::Running as i32].contains(&update.status) {
+            break update;
+        }
+    };
+    assert_eq!(done.status, JobStatus::Completed as i32);
+    assert_eq!(done.output_hash, "de".repeat(32));
+    // The stream ends once the job has finished
+    assert!(updates.message().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_subscribe_events() {
+    let scheduler_addr = "127.0.0.1:15014".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    let mut events = client
+        .subscribe_events(SubscribeEventsRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    let kinds = [ClusterEventKind::JobSubmitted as i32, ClusterEventKind::JobCancelled as i32];
+    let mut job_events = client
+        .subscribe_events(SubscribeEventsRequest { kinds: kinds.to_vec() })
+        .await
+        .unwrap()
+        .into_inner();
+
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "events-worker".to_string(),
+            address: "127.0.0.1:16014".to_string(),
+            capacity: 0,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "events-job".to_string(),
+            input_hash: "f0".repeat(32),
+            job_type: "rust-compile".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    client.cancel_job(CancelJobRequest { job_id: "events-job".to_string() }).await.unwrap();
+
+    let mut seen = Vec::new();
+    for _ in 0..3 {
+        let event = events.message().await.unwrap().unwrap();
+        seen.push((event.kind, event.worker_id, event.job_id));
+    }
+    assert_eq!(
+        seen,
+        vec![
+            (ClusterEventKind::WorkerOnline as i32, "events-worker".to_string(), String::new()),
+            (ClusterEventKind::JobSubmitted as i32, String::new(), "events-job".to_string()),
+            (ClusterEventKind::JobCancelled as i32, String::new(), "events-job".to_string()),
+        ]
+    );
+    // A filtered subscription skips the worker
+    let first = job_events.message().await.unwrap().unwrap();
+    assert_eq!(first.kind, ClusterEventKind::JobSubmitted as i32);
+}
+
+#[tokio::test]
+async fn test_scheduler_stats() {
+    let scheduler_addr = "127.0.0.1:15015".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    accepting_worker(&mut client, "stats-worker", 16015, 1).await;
+    for i in 0..3 {
+        // Only the first runs; the others wait for a worker with a GPU
+        let constraints = if i == 0 { Default::default() } else { [("gpu".to_string(), "yes".to_string())].into() };
+        client
+            .submit_job(SubmitJobRequest {
+                job_id: format!("stats-job-{}", i),
+                input_hash: format!("{:064}", i),
+                job_type: "rust-compile".to_string(),
+                constraints,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    client
+        .report_job_result(ReportJobResultRequest {
+            job_id: "stats-job-0".to_string(),
+            worker_id: "stats-worker".to_string(),
+            success: true,
+            output_hash: "ab".repeat(32),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let stats = client
+        .get_scheduler_stats(GetSchedulerStatsRequest { window_secs: 60 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(stats.total_jobs, 3);
+    assert_eq!(stats.queue_depth, 2);
+    assert_eq!(stats.jobs_by_status["PENDING"], 2);
+    assert_eq!(stats.jobs_by_status["COMPLETED"], 1);
+    assert_eq!(stats.jobs_per_minute, 1.0);
+    assert_eq!(stats.workers.len(), 1);
+    assert!(stats.workers[0].online);
+}
+
+#[tokio::test]
+async fn test_drain_worker() {
+    let scheduler_addr = "127.0.0.1:15016".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    for id in ["drain-idle", "drain-other"] {
+        client
+            .register_worker(RegisterWorkerRequest {
+                worker_id: id.to_string(),
+                address: "127.0.0.1:16016".to_string(),
+                capacity: 0,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    // Nothing running on it, so it goes straight away
+    let resp = client
+        .drain_worker(DrainWorkerRequest {
+            worker_id: "drain-idle".to_string(),
+            timeout_secs: 5,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(resp.removed);
+    assert_eq!(resp.remaining, 0);
+
+    let workers = client.list_workers(ListWorkersRequest {}).await.unwrap().into_inner().workers;
+    assert_eq!(workers.len(), 1);
+    assert_eq!(workers[0].worker_id, "drain-other");
+    assert!(!workers[0].draining);
+
+    let missing = client
+        .drain_worker(DrainWorkerRequest {
+            worker_id: "drain-idle".to_string(),
+            timeout_secs: 5,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(missing.code(), tonic::Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_auth_token() {
+    use cargo_distbuild::common::auth::ClientAuth;
+    use cargo_distbuild::common::config::SchedulerConfig;
+
+    let scheduler_addr = "127.0.0.1:15017".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        auth_token: Some("shared-secret".to_string()),
+        auth_tokens: [("ci".to_string(), "ci-secret".to_string())].into(),
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+    let url = format!("http://{}", scheduler_addr);
+
+    let mut anonymous = SchedulerClient::connect(url.clone()).await.unwrap();
+    let err = anonymous.list_workers(ListWorkersRequest {}).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+    let mut wrong = ClientAuth::new(Some("guess")).connect_scheduler(url.clone()).await.unwrap();
+    let err = wrong.list_workers(ListWorkersRequest {}).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+    for token in ["shared-secret", "ci-secret"] {
+        let mut client = ClientAuth::new(Some(token)).connect_scheduler(url.clone()).await.unwrap();
+        client
+            .register_worker(RegisterWorkerRequest {
+                worker_id: format!("auth-worker-{}", token),
+                address: "127.0.0.1:16017".to_string(),
+                capacity: 0,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    let mut client = ClientAuth::new(Some("ci-secret")).connect_scheduler(url).await.unwrap();
+    let workers = client.list_workers(ListWorkersRequest {}).await.unwrap().into_inner().workers;
+    assert_eq!(workers.len(), 2);
+}
+
+#[tokio::test]
+async fn test_blob_store_auth() {
+    use cargo_distbuild::common::auth::ClientAuth;
+    use cargo_distbuild::common::config::SchedulerConfig;
+    use cargo_distbuild::proto::distbuild::blob_store_client::BlobStoreClient;
+
+    let temp_dir = TempDir::new().unwrap();
+    let cas = Arc::new(Cas::new(temp_dir.path()).unwrap());
+    let scheduler_addr = "127.0.0.1:15046".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        auth_token: Some("shared-secret".to_string()),
+        ..Default::default()
+    };
+    let scheduler_cas = cas.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, Some(scheduler_cas)).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+    let url = format!("http://{}", scheduler_addr);
+    let upload = || PutBlobRequest {
+        data: b"uploaded without a token".to_vec(),
+        ..Default::default()
+    };
+
+    // The blob store shares the scheduler's port, and its token
+    let mut anonymous = BlobStoreClient::connect(url.clone()).await.unwrap();
+    let err = anonymous.put_blob(upload()).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    let err = anonymous
+        .has_blob(HasBlobRequest {
+            hash: "0".repeat(64),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+    let auth = ClientAuth::new(Some("shared-secret"));
+    let mut client = BlobStoreClient::with_interceptor(auth.channel(url).await.unwrap(), auth);
+    let hash = client.put_blob(upload()).await.unwrap().into_inner().hash;
+    assert!(cas.exists(&hash));
+}
+
+#[tokio::test]
+async fn test_mutual_tls() {
+    use cargo_distbuild::common::auth::ClientAuth;
+    use cargo_distbuild::common::config::{SchedulerConfig, TlsConfig};
+
+    // Long-lived test CA and a node certificate for localhost/127.0.0.1;
+    // rogue.pem is the same but signed by another CA
+    let fixture = |name: &str| format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let tls = TlsConfig {
+        ca_cert: fixture("ca.pem"),
+        cert: fixture("node.pem"),
+        key: fixture("node-key.pem"),
+        domain: None,
+    };
+    let scheduler_addr = "127.0.0.1:15018".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        tls: Some(tls.clone()),
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+    let url = format!("http://{}", scheduler_addr);
+
+    // Plaintext gets nowhere
+    if let Ok(mut plain) = SchedulerClient::connect(url.clone()).await {
+        assert!(plain.list_workers(ListWorkersRequest {}).await.is_err());
+    }
+
+    // Trusting the CA isn't enough: a client needs a certificate it signed
+    let ca = std::fs::read(fixture("ca.pem")).unwrap();
+    let no_cert = tonic::transport::ClientTlsConfig::new().ca_certificate(tonic::transport::Certificate::from_pem(ca));
+    let endpoint = tonic::transport::Endpoint::from_shared(format!("https://{}", scheduler_addr))
+        .unwrap()
+        .tls_config(no_cert)
+        .unwrap();
+    if let Ok(channel) = endpoint.connect().await {
+        assert!(SchedulerClient::new(channel).list_workers(ListWorkersRequest {}).await.is_err());
+    }
+    let rogue = TlsConfig {
+        cert: fixture("rogue.pem"),
+        key: fixture("rogue-key.pem"),
+        ..tls.clone()
+    };
+    let rogue = ClientAuth::new(None).with_tls(&rogue).unwrap();
+    if let Ok(mut client) = rogue.connect_scheduler(url.clone()).await {
+        assert!(client.list_workers(ListWorkersRequest {}).await.is_err());
+    }
+
+    let auth = ClientAuth::new(None).with_tls(&tls).unwrap();
+    let mut client = auth.connect_scheduler(url).await.unwrap();
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "tls-worker".to_string(),
+            address: "127.0.0.1:16018".to_string(),
+            capacity: 0,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let workers = client.list_workers(ListWorkersRequest {}).await.unwrap().into_inner().workers;
+    assert_eq!(workers[0].worker_id, "tls-worker");
+}
+
+#[tokio::test]
+async fn test_queue_limit() {
+    use cargo_distbuild::common::config::SchedulerConfig;
+    use cargo_distbuild::common::error::retry_after;
+
+    let scheduler_addr = "127.0.0.1:15019".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        max_pending_jobs: 2,
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    let submit = |i: u32| SubmitJobRequest {
+        job_id: format!("queued-job-{}", i),
+        input_hash: format!("{:064}", i),
+        job_type: "rust-compile".to_string(),
+        ..Default::default()
+    };
+    // No workers, so everything stays queued
+    client.submit_job(submit(0)).await.unwrap();
+    client.submit_job(submit(1)).await.unwrap();
+
+    let rejected = client.submit_job(submit(2)).await.unwrap_err();
+    assert_eq!(rejected.code(), tonic::Code::ResourceExhausted);
+    assert!(retry_after(&rejected).is_some());
+
+    // Sharing a queued job's result doesn't grow the queue
+    let resp = client
+        .submit_job(SubmitJobRequest {
+            job_id: "same-as-queued".to_string(),
+            ..submit(0)
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(resp.attached_to, "queued-job-0");
+
+    let batch = client
+        .submit_jobs(SubmitJobsRequest {
+            jobs: vec![submit(3)],
+            gang: false,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(batch.code(), tonic::Code::ResourceExhausted);
+}
+
+#[tokio::test]
+async fn test_offline_policy() {
+    use cargo_distbuild::common::config::{OfflineAction, SchedulerConfig};
+
+    let scheduler_addr = "127.0.0.1:15020".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        worker_timeout_secs: 1,
+        offline_action: OfflineAction::MarkOffline,
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "silent-worker".to_string(),
+            address: "127.0.0.1:16020".to_string(),
+            capacity: 0,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(3)).await;
+
+    // Kept, but shown offline
+    let workers = client.list_workers(ListWorkersRequest {}).await.unwrap().into_inner().workers;
+    assert_eq!(workers.len(), 1);
+    assert_eq!(workers[0].state, WorkerState::Offline as i32);
+    let stats = client
+        .get_scheduler_stats(GetSchedulerStatsRequest { window_secs: 60 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!stats.workers[0].online);
+
+    // And back once it heartbeats again
+    client
+        .heartbeat(HeartbeatRequest {
+            worker_id: "silent-worker".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let stats = client
+        .get_scheduler_stats(GetSchedulerStatsRequest { window_secs: 60 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(stats.workers[0].online);
+}
+
+#[tokio::test]
+async fn test_stale_results_dropped() {
+    use cargo_distbuild::common::config::SchedulerConfig;
+
+    let scheduler_addr = "127.0.0.1:15047".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        worker_timeout_secs: 1,
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    accepting_worker(&mut client, "vanishing", 16054, 1).await;
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "stale".to_string(),
+            input_hash: "5e".repeat(32),
+            job_type: "rust-compile".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let inspect = |client: &SchedulerClient<tonic::transport::Channel>| {
+        let mut client = client.clone();
+        async move { client.inspect_job(InspectJobRequest { job_id: "stale".to_string() }).await.unwrap().into_inner() }
+    };
+    assert_eq!(inspect(&client).await.job.unwrap().assigned_worker, "vanishing");
+
+    // It stops heartbeating, so the job is requeued and goes to another
+    // worker, kept alive meanwhile
+    for _ in 0..20 {
+        if inspect(&client).await.job.unwrap().status == JobStatus::Pending as i32 {
+            break;
+        }
+        sleep(Duration::from_millis(500)).await;
+    }
+    accepting_worker(&mut client, "replacement", 16055, 1).await;
+    let mut heartbeats = client.clone();
+    tokio::spawn(async move {
+        loop {
+            let request = HeartbeatRequest {
+                worker_id: "replacement".to_string(),
+                ..Default::default()
+            };
+            let _ = heartbeats.heartbeat(request).await;
+            sleep(Duration::from_millis(300)).await;
+        }
+    });
+    let requeued = inspect(&client).await;
+    assert_eq!(requeued.job.unwrap().assigned_worker, "replacement");
+    assert_eq!(requeued.attempts, 2);
+
+    // The first worker's late failure changes nothing
+    let report = |worker_id: &str, success: bool| ReportJobResultRequest {
+        job_id: "stale".to_string(),
+        worker_id: worker_id.to_string(),
+        success,
+        output_hash: if success { "5f".repeat(32) } else { String::new() },
+        error: if success { String::new() } else { "worker went away".to_string() },
+        ..Default::default()
+    };
+    let resp = client.report_job_result(report("vanishing", false)).await.unwrap().into_inner();
+    assert!(!resp.acknowledged);
+    let running = inspect(&client).await;
+    assert_eq!(running.job.unwrap().status, JobStatus::Running as i32);
+    assert_eq!(running.attempts, 2);
+
+    // The current one's is taken, once
+    let resp = client.report_job_result(report("replacement", true)).await.unwrap().into_inner();
+    assert!(resp.acknowledged);
+    let resp = client.report_job_result(report("replacement", false)).await.unwrap().into_inner();
+    assert!(!resp.acknowledged);
+    let done = inspect(&client).await;
+    let job = done.job.unwrap();
+    assert_eq!((job.status, job.output_hash.as_str()), (JobStatus::Completed as i32, "5f".repeat(32).as_str()));
+    assert_eq!(done.attempts, 2);
+}
+
+#[tokio::test]
+async fn test_quarantine_failing_worker() {
+    use cargo_distbuild::common::config::{QuarantineConfig, RetryConfig, SchedulerConfig};
+
+    let scheduler_addr = "127.0.0.1:15021".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        retry: RetryConfig {
+            max_attempts: 1,
+            dispatch_attempts: 1,
+            ..Default::default()
+        },
+        quarantine: QuarantineConfig {
+            consecutive_failures: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    // Nothing listens there, so every dispatch fails
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "broken-worker".to_string(),
+            address: "127.0.0.1:1".to_string(),
+            capacity: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let submit = |i: u32| SubmitJobRequest {
+        job_id: format!("quarantine-job-{}", i),
+        input_hash: format!("{:064}", 100 + i),
+        job_type: "rust-compile".to_string(),
+        ..Default::default()
+    };
+    for i in 0..2 {
+        client.submit_job(submit(i)).await.unwrap();
+        sleep(Duration::from_millis(500)).await;
+    }
+
+    let workers = client.list_workers(ListWorkersRequest {}).await.unwrap().into_inner().workers;
+    assert!(workers[0].quarantined_until > chrono::Utc::now().timestamp());
+
+    // Quarantined, so nothing more goes there
+    client.submit_job(submit(2)).await.unwrap();
+    sleep(Duration::from_millis(500)).await;
+    let status = client
+        .get_job_status(GetJobStatusRequest {
+            job_id: "quarantine-job-2".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(status.status, JobStatus::Pending as i32);
+}
+
+#[tokio::test]
+async fn test_list_jobs_pages() {
+    let scheduler_addr = "127.0.0.1:15022".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    for i in 0..5 {
+        client
+            .submit_job(SubmitJobRequest {
+                job_id: format!("page-job-{}", i),
+                input_hash: format!("{:064}", 200 + i),
+                job_type: if i % 2 == 0 { "rust-compile" } else { "rust-test" }.to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    // Pages don't overlap and together cover every job
+    let mut seen = Vec::new();
+    let mut cursor = String::new();
+    loop {
+        let page = client
+            .list_jobs(ListJobsRequest {
+                limit: 2,
+                cursor: cursor.clone(),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(page.jobs.len() <= 2);
+        seen.extend(page.jobs.into_iter().map(|job| job.job_id));
+        if page.next_cursor.is_empty() {
+            break;
+        }
+        cursor = page.next_cursor;
+    }
+    seen.sort();
+    assert_eq!(seen, (0..5).map(|i| format!("page-job-{}", i)).collect::<Vec<_>>());
+
+    let tests = client
+        .list_jobs(ListJobsRequest {
+            job_type: "rust-test".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(tests.jobs.len(), 2);
+    assert!(tests.next_cursor.is_empty());
+
+    let none = client
+        .list_jobs(ListJobsRequest {
+            older_than_secs: 3600,
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(none.jobs.is_empty());
+
+    let bad = client
+        .list_jobs(ListJobsRequest {
+            cursor: "not-a-cursor".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(bad.code(), tonic::Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_job_usage() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.scheduler.addr = "127.0.0.1:15023".to_string();
+    config.cas.root = temp_dir.path().to_str().unwrap().to_string();
+
+    let scheduler_addr = config.scheduler.addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(scheduler_addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let worker_config = config.clone();
+    let cas = Arc::new(Cas::new(&worker_config.cas.root).unwrap());
+    let worker_cas = cas.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::worker::run_worker("test-worker-usage".to_string(), 16023, worker_config, worker_cas)
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(2)).await;
+
+    let input = b"pub fn answer() -> u32 { 42 }";
+    let input_hash = cas.put(input).unwrap();
+    let mut client = SchedulerClient::connect(format!("http://{}", config.scheduler.addr))
+        .await
+        .unwrap();
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "usage-job".to_string(),
+            input_hash,
+            job_type: "rust-compile".to_string(),
+            metadata: [("tenant".to_string(), "team-a".to_string())].into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let mut status = GetJobStatusResponse::default();
+    for _ in 0..20 {
+        status = client
+            .get_job_status(GetJobStatusRequest {
+                job_id: "usage-job".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        if status.status == JobStatus::Completed as i32 {
+            break;
+        }
+        sleep(Duration::from_millis(500)).await;
+    }
+    assert_eq!(status.status, JobStatus::Completed as i32);
+    let usage = status.usage.unwrap();
+    assert_eq!(usage.input_bytes, input.len() as u64);
+    assert!(usage.output_bytes > usage.input_bytes);
+    assert_eq!(usage.transferred_bytes, 0);
+
+    let stats = client
+        .get_scheduler_stats(GetSchedulerStatsRequest { window_secs: 60 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(stats.costs.len(), 1);
+    assert_eq!(stats.costs[0].tenant, "team-a");
+    assert_eq!(stats.costs[0].jobs, 1);
+    assert_eq!(stats.costs[0].usage.unwrap().output_bytes, usage.output_bytes);
+}
+
+/// Worker that runs nothing, recording what stop notices it gets
+#[derive(Clone, Default)]
+struct StopNoticeRecorder {
+    notices: Arc<std::sync::Mutex<Vec<bool>>>,
+}
+
+#[tonic::async_trait]
+impl worker_server::Worker for StopNoticeRecorder {
+    async fn execute_job(
+        &self,
+        _request: tonic::Request<ExecuteJobRequest>,
+    ) -> Result<tonic::Response<ExecuteJobResponse>, tonic::Status> {
+        Err(tonic::Status::unimplemented("not running jobs"))
+    }
+
+    async fn get_status(
+        &self,
+        _request: tonic::Request<GetStatusRequest>,
+    ) -> Result<tonic::Response<GetStatusResponse>, tonic::Status> {
+        Ok(tonic::Response::new(GetStatusResponse::default()))
+    }
+
+    async fn abort_job(
+        &self,
+        _request: tonic::Request<AbortJobRequest>,
+    ) -> Result<tonic::Response<AbortJobResponse>, tonic::Status> {
+        Ok(tonic::Response::new(AbortJobResponse { aborted: false }))
+    }
+
+    async fn scheduler_stopping(
+        &self,
+        request: tonic::Request<SchedulerStoppingRequest>,
+    ) -> Result<tonic::Response<SchedulerStoppingResponse>, tonic::Status> {
+        self.notices.lock().unwrap().push(request.into_inner().resuming);
+        Ok(tonic::Response::new(SchedulerStoppingResponse { running: 0 }))
+    }
+}
+
+#[tokio::test]
+async fn test_restart_notifies_workers() {
+    let state_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.scheduler.addr = "127.0.0.1:15024".to_string();
+    config.scheduler.state_dir = Some(state_dir.path().to_str().unwrap().to_string());
+    let scheduler = tokio::spawn(cargo_distbuild::scheduler::run_scheduler_with_config(config.scheduler.clone(), None));
+
+    let recorder = StopNoticeRecorder::default();
+    let server = worker_server::WorkerServer::new(recorder.clone());
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(server)
+            .serve("127.0.0.1:16024".parse().unwrap())
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", config.scheduler.addr))
+        .await
+        .unwrap();
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "stop-notice-worker".to_string(),
+            address: "127.0.0.1:16024".to_string(),
+            capacity: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let restart = client
+        .quiesce(QuiesceRequest { drain: true, timeout_secs: 5, restart: true })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(restart.persisted);
+    assert!(restart.restarting);
+
+    // The scheduler exits once workers know to hold results for it
+    tokio::time::timeout(Duration::from_secs(5), scheduler).await.unwrap().unwrap().unwrap();
+    assert_eq!(*recorder.notices.lock().unwrap(), [true]);
+}
+
+#[tokio::test]
+async fn test_tenant_isolation() {
+    use cargo_distbuild::common::auth::ClientAuth;
+    use cargo_distbuild::common::config::{SchedulerConfig, TenantConfig};
+
+    let scheduler_addr = "127.0.0.1:15025".to_string();
+    let tenant = |token: &str, max_pending_jobs| TenantConfig {
+        token: Some(token.to_string()),
+        max_pending_jobs,
+        ..Default::default()
+    };
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        auth_token: Some("operator-secret".to_string()),
+        tenants: [
+            ("team-a".to_string(), tenant("a-secret", 1)),
+            ("team-b".to_string(), tenant("b-secret", 0)),
+        ]
+        .into(),
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+    let url = format!("http://{}", scheduler_addr);
+    let mut operator = ClientAuth::new(Some("operator-secret")).connect_scheduler(url.clone()).await.unwrap();
+    let mut team_a = ClientAuth::new(Some("a-secret")).connect_scheduler(url.clone()).await.unwrap();
+    let mut team_b = ClientAuth::new(Some("b-secret")).connect_scheduler(url).await.unwrap();
+
+    let submit = |job_id: &str, tenant: &str| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: if job_id == "a-job-2" { "cd".repeat(32) } else { "ab".repeat(32) },
+        job_type: "rust-compile".to_string(),
+        tenant: tenant.to_string(),
+        ..Default::default()
+    };
+    team_a.submit_job(submit("a-job", "")).await.unwrap();
+    // Within its own quota, and not reusing another tenant's identical job
+    let full = team_a.submit_job(submit("a-job-2", "")).await.unwrap_err();
+    assert_eq!(full.code(), tonic::Code::ResourceExhausted);
+    let b_job = team_b.submit_job(submit("b-job", "")).await.unwrap().into_inner();
+    assert!(b_job.attached_to.is_empty());
+    let impersonating = team_b.submit_job(submit("b-job-2", "team-a")).await.unwrap_err();
+    assert_eq!(impersonating.code(), tonic::Code::PermissionDenied);
+
+    // Each tenant only sees its own jobs; operators see all
+    let status = team_b.get_job_status(GetJobStatusRequest { job_id: "a-job".to_string() }).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    let cancel = team_b.cancel_job(CancelJobRequest { job_id: "a-job".to_string() }).await.unwrap_err();
+    assert_eq!(cancel.code(), tonic::Code::NotFound);
+    let listed = |jobs: Vec<JobInfo>| jobs.into_iter().map(|job| (job.job_id, job.tenant)).collect::<Vec<_>>();
+    let b_jobs = team_b.list_jobs(ListJobsRequest::default()).await.unwrap().into_inner().jobs;
+    assert_eq!(listed(b_jobs), [("b-job".to_string(), "team-b".to_string())]);
+    let a_jobs = operator
+        .list_jobs(ListJobsRequest { tenant: "team-a".to_string(), ..Default::default() })
+        .await
+        .unwrap()
+        .into_inner()
+        .jobs;
+    assert_eq!(listed(a_jobs), [("a-job".to_string(), "team-a".to_string())]);
+    let stats = team_b.get_scheduler_stats(GetSchedulerStatsRequest::default()).await.unwrap().into_inner();
+    assert_eq!(stats.total_jobs, 1);
+
+    // Workers: a tenant's own and shared ones are visible, others' aren't
+    let register = |worker_id: &str| RegisterWorkerRequest {
+        worker_id: worker_id.to_string(),
+        address: "127.0.0.1:16025".to_string(),
+        capacity: 0,
+        ..Default::default()
+    };
+    team_a.register_worker(register("a-worker")).await.unwrap();
+    operator.register_worker(register("shared-worker")).await.unwrap();
+    let hijack = team_b.register_worker(register("a-worker")).await.unwrap_err();
+    assert_eq!(hijack.code(), tonic::Code::PermissionDenied);
+    let mut b_workers: Vec<(String, String)> = team_b
+        .list_workers(ListWorkersRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .workers
+        .into_iter()
+        .map(|worker| (worker.worker_id, worker.tenant))
+        .collect();
+    b_workers.sort();
+    assert_eq!(b_workers, [("shared-worker".to_string(), String::new())]);
+
+    let quiesce = team_b
+        .quiesce(QuiesceRequest { drain: false, timeout_secs: 0, restart: false })
+        .await
+        .unwrap_err();
+    assert_eq!(quiesce.code(), tonic::Code::PermissionDenied);
+}
+
+#[tokio::test]
+async fn test_build_sessions() {
+    let scheduler_addr = "127.0.0.1:15026".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr)).await.unwrap();
+
+    // No workers, so everything stays queued
+    for (job_id, session_id, input) in [("a1", "build-1", "aa"), ("a2", "build-1", "bb"), ("b1", "build-2", "cc")] {
+        let request = SubmitJobRequest {
+            job_id: job_id.to_string(),
+            input_hash: input.repeat(32),
+            job_type: "rust-compile".to_string(),
+            session_id: session_id.to_string(),
+            ..Default::default()
+        };
+        client.submit_job(request).await.unwrap();
+    }
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "loose".to_string(),
+            input_hash: "dd".repeat(32),
+            job_type: "rust-compile".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let sessions = client.list_sessions(ListSessionsRequest::default()).await.unwrap().into_inner().sessions;
+    let mut totals: Vec<(String, u32)> = sessions.iter().map(|s| (s.session_id.clone(), s.total)).collect();
+    totals.sort();
+    assert_eq!(totals, [("build-1".to_string(), 2), ("build-2".to_string(), 1)]);
+
+    let session = client
+        .get_session(GetSessionRequest { session_id: "build-1".to_string() })
+        .await
+        .unwrap()
+        .into_inner();
+    let info = session.session.unwrap();
+    assert_eq!(info.status, JobStatus::Pending as i32);
+    assert_eq!(info.pending, 2);
+    assert_eq!(info.finished_at, 0);
+    assert_eq!(session.jobs.iter().map(|job| job.session_id.as_str()).collect::<Vec<_>>(), ["build-1", "build-1"]);
+
+    // Aborting the build cancels everything it has queued, and nothing else
+    let cancelled = client
+        .cancel_session(CancelSessionRequest { session_id: "build-1".to_string() })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(cancelled.cancelled, 2);
+    let info = client
+        .get_session(GetSessionRequest { session_id: "build-1".to_string() })
+        .await
+        .unwrap()
+        .into_inner()
+        .session
+        .unwrap();
+    assert_eq!(info.status, JobStatus::Cancelled as i32);
+    assert!(info.finished_at > 0);
+    let loose = client.get_job_status(GetJobStatusRequest { job_id: "loose".to_string() }).await.unwrap().into_inner();
+    assert_eq!(loose.status, JobStatus::Pending as i32);
+
+    let active = client
+        .list_sessions(ListSessionsRequest { active_only: true, ..Default::default() })
+        .await
+        .unwrap()
+        .into_inner()
+        .sessions;
+    assert_eq!(active.iter().map(|s| s.session_id.as_str()).collect::<Vec<_>>(), ["build-2"]);
+    let jobs = client
+        .list_jobs(ListJobsRequest { session_id: "build-2".to_string(), ..Default::default() })
+        .await
+        .unwrap()
+        .into_inner()
+        .jobs;
+    assert_eq!(jobs.iter().map(|job| job.job_id.as_str()).collect::<Vec<_>>(), ["b1"]);
+
+    let missing = client.get_session(GetSessionRequest { session_id: "nope".to_string() }).await.unwrap_err();
+    assert_eq!(missing.code(), tonic::Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_job_logs() {
+    use cargo_distbuild::common::config::SchedulerConfig;
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.scheduler.addr = "127.0.0.1:15027".to_string();
+    config.cas.root = temp_dir.path().to_str().unwrap().to_string();
+    let cas = Arc::new(Cas::new(&config.cas.root).unwrap());
+
+    let scheduler_config = SchedulerConfig {
+        addr: config.scheduler.addr.clone(),
+        ..Default::default()
+    };
+    let scheduler_cas = cas.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(scheduler_config, Some(scheduler_cas))
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let worker_config = config.clone();
+    let worker_cas = cas.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::worker::run_worker("test-worker-logs".to_string(), 16027, worker_config, worker_cas)
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(2)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", config.scheduler.addr))
+        .await
+        .unwrap();
+    let inputs = [("good-job", &b"pub fn answer() -> u32 { 42 }"[..]), ("bad-job", b"not rust at all")];
+    for (job_id, input) in inputs {
+        client
+            .submit_job(SubmitJobRequest {
+                job_id: job_id.to_string(),
+                input_hash: cas.put(input).unwrap(),
+                job_type: "rust-compile".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    for _ in 0..20 {
+        let jobs = client.list_jobs(ListJobsRequest::default()).await.unwrap().into_inner().jobs;
+        if jobs.iter().all(|job| job.status == JobStatus::Completed as i32 || job.status == JobStatus::Failed as i32) {
+            break;
+        }
+        sleep(Duration::from_millis(500)).await;
+    }
+
+    let logs = |job_id: &str, tail_lines| GetJobLogsRequest {
+        job_id: job_id.to_string(),
+        tail_lines,
+    };
+    let good = client.get_job_logs(logs("good-job", 0)).await.unwrap().into_inner();
+    assert!(!good.log_hash.is_empty());
+    assert!(good.log.contains("test-worker-logs"), "{}", good.log);
+    assert!(good.log.contains("output "), "{}", good.log);
+
+    // A failed compile's output outlives the job, and is kept from GC
+    let bad = client.get_job_logs(logs("bad-job", 0)).await.unwrap().into_inner();
+    assert!(bad.log.contains("valid Rust source code"), "{}", bad.log);
+    let tail = client.get_job_logs(logs("bad-job", 1)).await.unwrap().into_inner();
+    assert_eq!(tail.log, bad.log.lines().last().unwrap());
+    let pinned = client.get_pinned_blobs(GetPinnedBlobsRequest {}).await.unwrap().into_inner();
+    assert!(pinned.referenced.contains(&bad.log_hash));
+
+    let missing = client.get_job_logs(logs("no-such-job", 0)).await.unwrap_err();
+    assert_eq!(missing.code(), tonic::Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_audit_trail() {
+    use cargo_distbuild::common::auth::ClientAuth;
+    use cargo_distbuild::common::config::{SchedulerConfig, TenantConfig};
+
+    let state_dir = TempDir::new().unwrap();
+    let scheduler_addr = "127.0.0.1:15028".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        state_dir: Some(state_dir.path().to_str().unwrap().to_string()),
+        auth_tokens: [
+            ("alice".to_string(), "alice-token".to_string()),
+            ("ci".to_string(), "ci-token".to_string()),
+        ]
+        .into(),
+        tenants: [(
+            "team-a".to_string(),
+            TenantConfig {
+                token: Some("a-token".to_string()),
+                ..Default::default()
+            },
+        )]
+        .into(),
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+    let url = format!("http://{}", scheduler_addr);
+    let mut alice = ClientAuth::new(Some("alice-token")).connect_scheduler(url.clone()).await.unwrap();
+    let mut ci = ClientAuth::new(Some("ci-token")).connect_scheduler(url.clone()).await.unwrap();
+    let mut team_a = ClientAuth::new(Some("a-token")).connect_scheduler(url).await.unwrap();
+
+    let submit = |job_id: &str, input: &str| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: input.repeat(32),
+        job_type: "rust-compile".to_string(),
+        ..Default::default()
+    };
+    ci.submit_job(submit("ci-job", "aa")).await.unwrap();
+    alice.cancel_job(CancelJobRequest { job_id: "ci-job".to_string() }).await.unwrap();
+    team_a.submit_job(submit("a-job", "bb")).await.unwrap();
+
+    let query = |actor: &str| QueryAuditRequest {
+        actor: actor.to_string(),
+        within_secs: 3600,
+        ..Default::default()
+    };
+    let entries = alice.query_audit(query("")).await.unwrap().into_inner().entries;
+    let trail: Vec<(&str, &str, &str)> = entries
+        .iter()
+        .map(|entry| (entry.actor.as_str(), entry.action.as_str(), entry.target.as_str()))
+        .collect();
+    assert_eq!(
+        trail,
+        [("team-a", "submit-job", "a-job"), ("alice", "cancel-job", "ci-job"), ("ci", "submit-job", "ci-job")]
+    );
+    assert_eq!(entries[0].tenant, "team-a");
+
+    let by_ci = alice.query_audit(query("ci")).await.unwrap().into_inner().entries;
+    assert_eq!(by_ci.len(), 1);
+    // A tenant only sees what was done under its name
+    let own = team_a.query_audit(query("")).await.unwrap().into_inner().entries;
+    assert_eq!(own.iter().map(|entry| entry.target.as_str()).collect::<Vec<_>>(), ["a-job"]);
+    assert!(state_dir.path().join("audit.jsonl").exists());
+}
+
+#[tokio::test]
+async fn test_standby_takes_over() {
+    use cargo_distbuild::common::auth::ClientAuth;
+    use cargo_distbuild::common::config::{SchedulerConfig, StandbyConfig};
+
+    let state_dir = TempDir::new().unwrap();
+    let primary_addr = "127.0.0.1:15029".to_string();
+    let standby_addr = "127.0.0.1:15030".to_string();
+    let primary_config = SchedulerConfig {
+        addr: primary_addr.clone(),
+        state_dir: Some(state_dir.path().to_str().unwrap().to_string()),
+        ..Default::default()
+    };
+    let standby_config = SchedulerConfig {
+        addr: standby_addr.clone(),
+        standby: Some(StandbyConfig {
+            primary: primary_addr.clone(),
+            takeover_after_secs: 2,
+        }),
+        ..primary_config.clone()
+    };
+    let primary = tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(primary_config, None).await.unwrap();
+    });
+    sleep(Duration::from_millis(500)).await;
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(standby_config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let urls = [format!("http://{}", primary_addr), format!("http://{}", standby_addr)];
+    let auth = ClientAuth::default();
+    let submit = |job_id: &str, input: &str| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: input.repeat(32),
+        job_type: "rust-compile".to_string(),
+        ..Default::default()
+    };
+    let mut client = auth.connect_any(&urls).await.unwrap();
+    client.submit_job(submit("before", "aa")).await.unwrap();
+
+    // The standby follows along but refuses changes while the primary is up
+    let mut standby = auth.connect_scheduler(urls[1].clone()).await.unwrap();
+    let refused = standby.submit_job(submit("too-early", "bb")).await.unwrap_err();
+    assert_eq!(refused.code(), tonic::Code::FailedPrecondition);
+    sleep(Duration::from_millis(1500)).await;
+    let status = |job_id: &str| GetJobStatusRequest { job_id: job_id.to_string() };
+    standby.get_job_status(status("before")).await.unwrap();
+
+    primary.abort();
+    let mut took_over = false;
+    for _ in 0..20 {
+        sleep(Duration::from_millis(500)).await;
+        if standby.submit_job(submit("after", "cc")).await.is_ok() {
+            took_over = true;
+            break;
+        }
+    }
+    assert!(took_over, "standby never took over");
+
+    // Clients configured with both addresses carry on against the standby,
+    // which kept everything the primary had
+    let mut client = auth.connect_any(&urls).await.unwrap();
+    client.submit_job(submit("later", "dd")).await.unwrap();
+    let before = client.get_job_status(status("before")).await.unwrap().into_inner();
+    assert_eq!(before.status, JobStatus::Pending as i32);
+    let lease = std::fs::read_to_string(state_dir.path().join("lease.json")).unwrap();
+    assert!(lease.contains(&standby_addr));
+}
+
+#[tokio::test]
+async fn test_attempt_history() {
+    use cargo_distbuild::common::config::{RetryConfig, SchedulerConfig};
+
+    let scheduler_addr = "127.0.0.1:15031".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        retry: RetryConfig {
+            max_attempts: 2,
+            initial_backoff_secs: 1,
+            dispatch_attempts: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    // Nothing listens there, so every dispatch fails
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "unreachable-worker".to_string(),
+            address: "127.0.0.1:1".to_string(),
+            capacity: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "retried-job".to_string(),
+            input_hash: "ee".repeat(32),
+            job_type: "rust-compile".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(3)).await;
+
+    let status = client
+        .get_job_status(GetJobStatusRequest {
+            job_id: "retried-job".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(status.status, JobStatus::Failed as i32);
+    let outcomes: Vec<i32> = status.attempt_history.iter().map(|attempt| attempt.outcome).collect();
+    assert_eq!(outcomes, [JobStatus::Pending as i32, JobStatus::Failed as i32]);
+    for attempt in &status.attempt_history {
+        assert_eq!(attempt.worker, "unreachable-worker");
+        assert!(attempt.ended_at >= attempt.started_at);
+        assert!(!attempt.error.is_empty());
+    }
+
+    let inspected = client
+        .inspect_job(InspectJobRequest {
+            job_id: "retried-job".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(inspected.attempt_history, status.attempt_history);
+}
+
+#[tokio::test]
+async fn test_worker_capabilities() {
+    let scheduler_addr = "127.0.0.1:15032".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    let capabilities = |targets: &[&str]| WorkerCapabilities {
+        rustc_versions: vec!["1.78.0".to_string()],
+        host_triple: "x86_64-unknown-linux-gnu".to_string(),
+        targets: targets.iter().map(|t| t.to_string()).collect(),
+        disk_free_bytes: 1 << 30,
+        job_types: vec!["rust-compile".to_string()],
+    };
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "host-only".to_string(),
+            address: "127.0.0.1:16032".to_string(),
+            capacity: 1,
+            capabilities: Some(capabilities(&["x86_64-unknown-linux-gnu"])),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let workers = client.list_workers(ListWorkersRequest {}).await.unwrap().into_inner().workers;
+    assert_eq!(workers[0].capabilities, Some(capabilities(&["x86_64-unknown-linux-gnu"])));
+
+    // Cross-compiling for a target it hasn't installed, and a job type it
+    // doesn't run, so neither job goes to it
+    let submit = |job_id: &str, job_type: &str, target: &str| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: format!("{:0>64}", job_id.len()),
+        job_type: job_type.to_string(),
+        metadata: std::collections::HashMap::from([("target".to_string(), target.to_string())]),
+        ..Default::default()
+    };
+    client.submit_job(submit("wasm", "rust-compile", "wasm32-unknown-unknown")).await.unwrap();
+    client.submit_job(submit("test", "rust-test", "x86_64-unknown-linux-gnu")).await.unwrap();
+    sleep(Duration::from_millis(1500)).await;
+    let attempts = |job_id: &str| InspectJobRequest { job_id: job_id.to_string() };
+    assert_eq!(client.inspect_job(attempts("wasm")).await.unwrap().into_inner().attempts, 0);
+    assert_eq!(client.inspect_job(attempts("test")).await.unwrap().into_inner().attempts, 0);
+
+    // One that has it installed takes the cross-compile
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "cross".to_string(),
+            address: "127.0.0.1:16033".to_string(),
+            capacity: 1,
+            capabilities: Some(capabilities(&["x86_64-unknown-linux-gnu", "wasm32-unknown-unknown"])),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(1500)).await;
+    let wasm = client.inspect_job(attempts("wasm")).await.unwrap().into_inner();
+    assert!(wasm.attempts >= 1);
+    assert!(wasm.attempt_history.iter().all(|attempt| attempt.worker == "cross"));
+    assert_eq!(client.inspect_job(attempts("test")).await.unwrap().into_inner().attempts, 0);
+}
+
+#[tokio::test]
+async fn test_worker_health() {
+    use cargo_distbuild::common::config::{RetryConfig, SchedulerConfig};
+
+    let scheduler_addr = "127.0.0.1:15033".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        retry: RetryConfig {
+            max_attempts: 1,
+            dispatch_attempts: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    // Nothing listens at either address, so every dispatch fails
+    let register = |worker_id: &str, port: u16| RegisterWorkerRequest {
+        worker_id: worker_id.to_string(),
+        address: format!("127.0.0.1:{}", port),
+        capacity: 4,
+        ..Default::default()
+    };
+    let submit = |n: usize| SubmitJobRequest {
+        job_id: format!("job-{}", n),
+        input_hash: format!("{:0>64}", n),
+        job_type: "rust-compile".to_string(),
+        ..Default::default()
+    };
+    client.register_worker(register("flaky", 1)).await.unwrap();
+    for n in 0..3 {
+        client.submit_job(submit(n)).await.unwrap();
+    }
+    sleep(Duration::from_millis(1500)).await;
+
+    let workers = client.list_workers(ListWorkersRequest {}).await.unwrap().into_inner().workers;
+    let health = workers[0].health.unwrap();
+    assert_eq!(health.failure_rate, 3.0 / 5.0);
+    assert!(health.score < 0.5);
+
+    // A worker with a clean record is preferred while it has room
+    client.register_worker(register("fresh", 2)).await.unwrap();
+    client.submit_job(submit(3)).await.unwrap();
+    sleep(Duration::from_millis(1500)).await;
+    let status = client
+        .get_job_status(GetJobStatusRequest {
+            job_id: "job-3".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let workers: Vec<&str> = status.attempt_history.iter().map(|attempt| attempt.worker.as_str()).collect();
+    assert_eq!(workers, ["fresh"]);
+}
+
+#[tokio::test]
+async fn test_job_eta() {
+    let scheduler_addr = "127.0.0.1:15034".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    let submit = |job_id: &str, crate_name: &str| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: format!("{:0>64}", job_id.len()),
+        job_type: "rust-compile".to_string(),
+        metadata: [("crate_name".to_string(), crate_name.to_string())].into(),
+        ..Default::default()
+    };
+    let status = |job_id: &str| GetJobStatusRequest { job_id: job_id.to_string() };
+
+    accepting_worker(&mut client, "eta-worker", 16051, 1).await;
+    client.submit_job(submit("first", "serde")).await.unwrap();
+    let first = client.get_job_status(status("first")).await.unwrap().into_inner();
+    assert_eq!((first.expected_secs, first.remaining_secs), (0, 0));
+    client
+        .report_job_result(ReportJobResultRequest {
+            job_id: "first".to_string(),
+            worker_id: "eta-worker".to_string(),
+            success: true,
+            output_hash: "02".repeat(32),
+            usage: Some(JobUsage {
+                exec_millis: 8_500,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // The next build of the crate is expected to take as long, once
+    // another job has taken the only slot
+    client.submit_job(submit("hog", "tokio")).await.unwrap();
+    client.submit_job(submit("second", "serde")).await.unwrap();
+    let second = client.get_job_status(status("second")).await.unwrap().into_inner();
+    assert_eq!(second.status, JobStatus::Pending as i32);
+    assert_eq!((second.expected_secs, second.remaining_secs), (9, 9));
+    let first = client.get_job_status(status("first")).await.unwrap().into_inner();
+    assert_eq!(first.expected_secs, 0);
+}
+
+#[tokio::test]
+async fn test_simulate_recorded_trace() {
+    use cargo_distbuild::common::config::StrategyKind;
+    use cargo_distbuild::scheduler::Trace;
+
+    let state_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.scheduler.addr = "127.0.0.1:15035".to_string();
+    config.scheduler.state_dir = Some(state_dir.path().to_str().unwrap().to_string());
+    tokio::spawn(cargo_distbuild::scheduler::run_scheduler_with_config(config.scheduler.clone(), None));
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", config.scheduler.addr))
+        .await
+        .unwrap();
+    accepting_worker(&mut client, "replayed", 16052, 3).await;
+    let runs = [("short", 2_000), ("long", 6_000), ("never-ran", 0)];
+    for (job_id, _) in runs {
+        client
+            .submit_job(SubmitJobRequest {
+                job_id: job_id.to_string(),
+                input_hash: format!("{:0>64}", job_id.len()),
+                job_type: "rust-compile".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    for (job_id, exec_millis) in runs.into_iter().filter(|&(_, exec_millis)| exec_millis > 0) {
+        client
+            .report_job_result(ReportJobResultRequest {
+                job_id: job_id.to_string(),
+                worker_id: "replayed".to_string(),
+                success: true,
+                output_hash: "03".repeat(32),
+                usage: Some(JobUsage { exec_millis, ..Default::default() }),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    // Replayed from the scheduler's journal, on one worker or two
+    let trace = Trace::load(state_dir.path()).unwrap();
+    assert_eq!((trace.jobs(), trace.workers()), (2, 1));
+    let serial = Trace::load(state_dir.path()).unwrap().with_workers(1, 1).simulate(StrategyKind::RoundRobin);
+    assert_eq!(serial.makespan_ms, 8_000);
+    let parallel = trace.with_workers(2, 1).simulate(StrategyKind::LeastLoaded);
+    assert_eq!(parallel.makespan_ms, 6_000);
+    assert_eq!(parallel.avg_queue_latency_ms, 0);
+}
+
+#[tokio::test]
+async fn test_dispatch_failure_reassigns() {
+    use cargo_distbuild::common::config::{RetryConfig, SchedulerConfig};
+
+    let scheduler_addr = "127.0.0.1:15036".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        retry: RetryConfig {
+            max_attempts: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    // Nothing listens at either address, so every dispatch fails
+    for (worker_id, port) in [("dead-1", 1), ("dead-2", 2)] {
+        client
+            .register_worker(RegisterWorkerRequest {
+                worker_id: worker_id.to_string(),
+                address: format!("127.0.0.1:{}", port),
+                capacity: 1,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "reassigned-job".to_string(),
+            input_hash: "ab".repeat(32),
+            job_type: "rust-compile".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(1500)).await;
+
+    // Handed straight to the other worker, then failed on the third
+    // dispatch without backing off, though only one attempt is allowed
+    let status = client
+        .get_job_status(GetJobStatusRequest {
+            job_id: "reassigned-job".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(status.status, JobStatus::Failed as i32);
+    let outcomes: Vec<i32> = status.attempt_history.iter().map(|attempt| attempt.outcome).collect();
+    assert_eq!(outcomes, [JobStatus::Pending as i32, JobStatus::Pending as i32, JobStatus::Failed as i32]);
+    assert_ne!(status.attempt_history[0].worker, status.attempt_history[1].worker);
+}
+
+#[tokio::test]
+async fn test_submit_validation() {
+    use cargo_distbuild::common::config::{SchedulerConfig, ValidationConfig};
+    use cargo_distbuild::common::error::{rejection, Rejection};
+
+    let temp_dir = TempDir::new().unwrap();
+    let cas = Arc::new(Cas::new(temp_dir.path()).unwrap());
+    let input_hash = cas.put(b"fn main() {}").unwrap();
+    let scheduler_addr = "127.0.0.1:15037".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        validation: ValidationConfig {
+            job_types: vec!["rust-compile".to_string()],
+            max_input_bytes: 1024,
+            require_input: true,
+        },
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, Some(cas)).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    let submit = |input_hash: &str, job_type: &str, input_size: u64| SubmitJobRequest {
+        job_id: format!("{}-{}", job_type, input_size),
+        input_hash: input_hash.to_string(),
+        job_type: job_type.to_string(),
+        input_size,
+        ..Default::default()
+    };
+    let cases = [
+        (submit("not-a-hash", "rust-compile", 12), Rejection::InvalidHash),
+        (submit(&input_hash, "transform", 12), Rejection::UnknownJobType),
+        (submit(&input_hash, "rust-compile", 4096), Rejection::InputTooLarge),
+        (submit(&"ab".repeat(32), "rust-compile", 12), Rejection::MissingInput),
+    ];
+    for (request, reason) in cases {
+        let status = client.submit_job(request).await.unwrap_err();
+        assert_eq!(rejection(&status), Some(reason), "{}", status.message());
+    }
+    // A bad job anywhere in a batch rejects all of it
+    let status = client
+        .submit_jobs(SubmitJobsRequest {
+            jobs: vec![submit(&input_hash, "rust-compile", 12), submit(&input_hash, "transform", 12)],
+            gang: false,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(rejection(&status), Some(Rejection::UnknownJobType));
+
+    client.submit_job(submit(&input_hash, "rust-compile", 12)).await.unwrap();
+}
+
+/// Worker that takes every job it's sent and never finishes one
+#[derive(Clone, Default)]
+struct AcceptingWorker;
+
+#[tonic::async_trait]
+impl worker_server::Worker for AcceptingWorker {
+    async fn execute_job(
+        &self,
+        _request: tonic::Request<ExecuteJobRequest>,
+    ) -> Result<tonic::Response<ExecuteJobResponse>, tonic::Status> {
+        Ok(tonic::Response::new(ExecuteJobResponse { success: true, ..Default::default() }))
+    }
+
+    async fn get_status(
+        &self,
+        _request: tonic::Request<GetStatusRequest>,
+    ) -> Result<tonic::Response<GetStatusResponse>, tonic::Status> {
+        Ok(tonic::Response::new(GetStatusResponse::default()))
+    }
+
+    async fn abort_job(
+        &self,
+        _request: tonic::Request<AbortJobRequest>,
+    ) -> Result<tonic::Response<AbortJobResponse>, tonic::Status> {
+        Ok(tonic::Response::new(AbortJobResponse { aborted: true }))
+    }
+
+    async fn scheduler_stopping(
+        &self,
+        _request: tonic::Request<SchedulerStoppingRequest>,
+    ) -> Result<tonic::Response<SchedulerStoppingResponse>, tonic::Status> {
+        Ok(tonic::Response::new(SchedulerStoppingResponse { running: 0 }))
+    }
+}
+
+/// Serve an `AcceptingWorker` on `port` and register it as `worker_id`,
+/// so jobs are dispatched to it and their results can be reported from it
+async fn accepting_worker(client: &mut SchedulerClient<tonic::transport::Channel>, worker_id: &str, port: u16, capacity: u32) {
+    let address = format!("127.0.0.1:{}", port);
+    let serve_addr = address.parse().unwrap();
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(worker_server::WorkerServer::new(AcceptingWorker))
+            .serve(serve_addr)
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_millis(200)).await;
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: worker_id.to_string(),
+            address,
+            capacity,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    // Jobs already queued are dispatched once it's registered
+    sleep(Duration::from_millis(300)).await;
+}
+
+#[tokio::test]
+async fn test_weighted_jobs() {
+    use cargo_distbuild::common::config::{SchedulerConfig, WeightConfig};
+
+    let scheduler_addr = "127.0.0.1:15038".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        weights: WeightConfig {
+            slot_bytes: 1000,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(worker_server::WorkerServer::new(AcceptingWorker))
+            .serve("127.0.0.1:16038".parse().unwrap())
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    // Queued before the worker shows up, heaviest first
+    let submit = |job_id: &str, priority: i32, input_size: u64| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: format!("{:0>64}", priority),
+        job_type: "rust-compile".to_string(),
+        priority,
+        input_size,
+        ..Default::default()
+    };
+    client.submit_job(submit("heavy", 3, 2500)).await.unwrap();
+    client.submit_job(submit("light", 2, 100)).await.unwrap();
+    client.submit_job(submit("medium", 1, 1500)).await.unwrap();
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "four-slots".to_string(),
+            address: "127.0.0.1:16038".to_string(),
+            capacity: 4,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(1500)).await;
+
+    // 3 slots and 1 fill the worker; the 2-slot job waits for room
+    let job = |job_id: &str| InspectJobRequest { job_id: job_id.to_string() };
+    let heavy = client.inspect_job(job("heavy")).await.unwrap().into_inner().job.unwrap();
+    assert_eq!((heavy.weight, heavy.status), (3, JobStatus::Running as i32));
+    let light = client.inspect_job(job("light")).await.unwrap().into_inner().job.unwrap();
+    assert_eq!((light.weight, light.status), (1, JobStatus::Running as i32));
+    let medium = client.inspect_job(job("medium")).await.unwrap().into_inner().job.unwrap();
+    assert_eq!((medium.weight, medium.status), (2, JobStatus::Pending as i32));
+
+    // Once the heavy one is done it fits
+    client
+        .report_job_result(ReportJobResultRequest {
+            job_id: "heavy".to_string(),
+            worker_id: "four-slots".to_string(),
+            success: true,
+            output_hash: "04".repeat(32),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(500)).await;
+    let medium = client.inspect_job(job("medium")).await.unwrap().into_inner().job.unwrap();
+    assert_eq!(medium.status, JobStatus::Running as i32);
+}
+
+#[tokio::test]
+async fn test_scheduled_job() {
+    let scheduler_addr = "127.0.0.1:15039".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(worker_server::WorkerServer::new(AcceptingWorker))
+            .serve("127.0.0.1:16039".parse().unwrap())
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "night-shift".to_string(),
+            address: "127.0.0.1:16039".to_string(),
+            capacity: 2,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let run_after = chrono::Utc::now().timestamp() + 2;
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "cache-warmup".to_string(),
+            input_hash: "5a".repeat(32),
+            job_type: "rust-compile".to_string(),
+            run_after,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let status = || GetJobStatusRequest {
+        job_id: "cache-warmup".to_string(),
+    };
+
+    // Queued with a free worker, but not due yet
+    sleep(Duration::from_millis(500)).await;
+    let waiting = client.get_job_status(status()).await.unwrap().into_inner();
+    assert_eq!((waiting.status, waiting.run_after), (JobStatus::Pending as i32, run_after));
+
+    sleep(Duration::from_secs(3)).await;
+    let due = client.get_job_status(status()).await.unwrap().into_inner();
+    assert_eq!(due.status, JobStatus::Running as i32);
+}
+
+/// Read one HTTP request off `stream`: its head and body
+async fn read_http_request(stream: &mut tokio::net::TcpStream) -> (String, String) {
+    use tokio::io::AsyncReadExt;
+
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut chunk).await.unwrap();
+        assert!(n > 0, "connection closed mid-request");
+        data.extend_from_slice(&chunk[..n]);
+        let text = String::from_utf8_lossy(&data).to_string();
+        let Some((head, body)) = text.split_once("\r\n\r\n") else {
+            continue;
+        };
+        let length = head
+            .lines()
+            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|n| n.trim().parse::<usize>().unwrap()))
+            .unwrap_or(0);
+        if body.len() >= length {
+            return (head.to_string(), body.to_string());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_webhooks() {
+    use cargo_distbuild::common::config::{SchedulerConfig, WebhookConfig};
+    use tokio::io::AsyncWriteExt;
+
+    // Fails the first delivery, then accepts every one
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:17040").await.unwrap();
+    let (requests_tx, mut requests) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        for answer in std::iter::once("500 Internal Server Error").chain(std::iter::repeat("200 OK")) {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let request = read_http_request(&mut stream).await;
+            let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", answer);
+            stream.write_all(response.as_bytes()).await.unwrap();
+            requests_tx.send(request).unwrap();
+        }
+    });
+
+    let scheduler_addr = "127.0.0.1:15040".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        webhooks: vec![WebhookConfig {
+            url: "http://127.0.0.1:17040/hooks".to_string(),
+            secret: Some("hook-secret".to_string()),
+            events: Vec::new(),
+            max_attempts: 3,
+        }],
+        session_idle_secs: 1,
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    accepting_worker(&mut client, "hooked-worker", 16053, 1).await;
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "hooked".to_string(),
+            input_hash: "6b".repeat(32),
+            job_type: "rust-compile".to_string(),
+            session_id: "nightly-7".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    client
+        .report_job_result(ReportJobResultRequest {
+            job_id: "hooked".to_string(),
+            worker_id: "hooked-worker".to_string(),
+            success: true,
+            output_hash: "6c".repeat(32),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    async fn next(requests: &mut tokio::sync::mpsc::UnboundedReceiver<(String, String)>) -> (String, String) {
+        let request = tokio::time::timeout(Duration::from_secs(10), requests.recv());
+        request.await.expect("no webhook delivered").unwrap()
+    }
+    let header = |head: &str, name: &str| {
+        head.lines()
+            .find_map(|line| line.split_once(": ").filter(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.to_string()))
+            .unwrap_or_default()
+    };
+
+    // Refused once, so sent again as the same delivery
+    let (failed_head, failed_body) = next(&mut requests).await;
+    let (head, body) = next(&mut requests).await;
+    assert_eq!(header(&head, "x-distbuild-event"), "job_completed");
+    assert_eq!(header(&head, "x-distbuild-delivery"), header(&failed_head, "x-distbuild-delivery"));
+    assert_eq!(body, failed_body);
+    let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(payload["event"], "job_completed");
+    assert_eq!(payload["job"]["job_id"], "hooked");
+    assert_eq!(payload["job"]["output_hash"], "6c".repeat(32));
+
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, b"hook-secret");
+    let signature = header(&head, "x-distbuild-signature");
+    let signature = hex::decode(signature.strip_prefix("sha256=").unwrap()).unwrap();
+    assert!(ring::hmac::verify(&key, body.as_bytes(), &signature).is_ok());
+
+    // Once the session has sat idle, and the reaper has been round
+    let (head, body) = next(&mut requests).await;
+    assert_eq!(header(&head, "x-distbuild-event"), "session_finished");
+    let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(payload["session"]["session_id"], "nightly-7");
+    assert_eq!(payload["session"]["status"], "COMPLETED");
+    assert_eq!(payload["session"]["completed"], 1);
+}
+
+#[tokio::test]
+async fn test_worker_pools() {
+    let scheduler_addr = "127.0.0.1:15041".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(worker_server::WorkerServer::new(AcceptingWorker))
+            .serve("127.0.0.1:16041".parse().unwrap())
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    for (worker_id, pool) in [("linux-1", "linux-x64"), ("mem-1", "big-mem")] {
+        client
+            .register_worker(RegisterWorkerRequest {
+                worker_id: worker_id.to_string(),
+                address: "127.0.0.1:16041".to_string(),
+                capacity: 4,
+                pool: pool.to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    for (job_id, pool, input) in [("huge-link", "big-mem", "7d"), ("ios-build", "mac-arm", "7e")] {
+        client
+            .submit_job(SubmitJobRequest {
+                job_id: job_id.to_string(),
+                input_hash: input.repeat(32),
+                job_type: "rust-compile".to_string(),
+                pool: pool.to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    sleep(Duration::from_millis(1000)).await;
+
+    let status = |job_id: &str| GetJobStatusRequest {
+        job_id: job_id.to_string(),
+    };
+    // Only the pool's worker gets it, however the strategy would spread jobs
+    let routed = client.get_job_status(status("huge-link")).await.unwrap().into_inner();
+    assert_eq!((routed.status, routed.assigned_worker.as_str()), (JobStatus::Running as i32, "mem-1"));
+    // No worker in the pool, so it waits for one
+    let waiting = client.get_job_status(status("ios-build")).await.unwrap().into_inner();
+    assert_eq!(waiting.status, JobStatus::Pending as i32);
+
+    let workers = client.list_workers(ListWorkersRequest {}).await.unwrap().into_inner().workers;
+    let mem = workers.iter().find(|worker| worker.worker_id == "mem-1").unwrap();
+    assert_eq!(mem.pool, "big-mem");
+}
+
+#[tokio::test]
+async fn test_worker_lifecycle() {
+    use cargo_distbuild::common::config::SchedulerConfig;
+
+    let scheduler_addr = "127.0.0.1:15042".to_string();
+    let config = SchedulerConfig {
+        addr: scheduler_addr.clone(),
+        worker_timeout_secs: 1,
+        offline_grace_secs: 2,
+        offline_retention_secs: 2,
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler_with_config(config, None).await.unwrap();
+    });
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(worker_server::WorkerServer::new(AcceptingWorker))
+            .serve("127.0.0.1:16042".parse().unwrap())
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "fading".to_string(),
+            address: "127.0.0.1:16042".to_string(),
+            capacity: 2,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    client
+        .submit_job(SubmitJobRequest {
+            job_id: "stranded".to_string(),
+            input_hash: "8f".repeat(32),
+            job_type: "rust-compile".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(500)).await;
+
+    let mut states = Vec::new();
+    for _ in 0..8 {
+        let workers = client.list_workers(ListWorkersRequest {}).await.unwrap().into_inner().workers;
+        let state = workers.first().map(|worker| worker.state);
+        if states.last() != Some(&state) {
+            states.push(state);
+        }
+        if state == Some(WorkerState::Offline as i32) {
+            // Still listed, but its job has been queued again
+            let job = client
+                .get_job_status(GetJobStatusRequest {
+                    job_id: "stranded".to_string(),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(job.status, JobStatus::Pending as i32);
+        }
+        sleep(Duration::from_secs(1)).await;
+    }
+    // Silent past the timeout, then the grace period, then forgotten once
+    // offline for the retention period
+    let expected = [WorkerState::Online, WorkerState::Suspect, WorkerState::Offline].map(|state| Some(state as i32));
+    assert_eq!(states, [&expected[..], &[None]].concat());
+}
+
+#[tokio::test]
+async fn test_job_history() {
+    let scheduler_addr = "127.0.0.1:15043".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(worker_server::WorkerServer::new(AcceptingWorker))
+            .serve("127.0.0.1:16043".parse().unwrap())
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "builder".to_string(),
+            address: "127.0.0.1:16043".to_string(),
+            capacity: 4,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    for (job_id, crate_name, input) in [("serde-1", "serde", "8a"), ("tokio-1", "tokio", "8b"), ("serde-2", "serde", "8c")] {
+        client
+            .submit_job(SubmitJobRequest {
+                job_id: job_id.to_string(),
+                input_hash: input.repeat(32),
+                job_type: "rust-compile".to_string(),
+                metadata: std::collections::HashMap::from([("crate_name".to_string(), crate_name.to_string())]),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    sleep(Duration::from_millis(500)).await;
+    for (job_id, success) in [("serde-1", true), ("tokio-1", false), ("serde-2", true)] {
+        client
+            .report_job_result(ReportJobResultRequest {
+                job_id: job_id.to_string(),
+                worker_id: "builder".to_string(),
+                success,
+                output_hash: if success { "02".repeat(32) } else { String::new() },
+                error: if success { String::new() } else { "mismatched types".to_string() },
+                error_kind: JobErrorKind::Compile as i32,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    let search = |request: SearchJobHistoryRequest| {
+        let mut client = client.clone();
+        async move {
+            let entries = client.search_job_history(request).await.unwrap().into_inner().entries;
+            entries.into_iter().map(|entry| entry.job_id).collect::<Vec<_>>()
+        }
+    };
+    let serde = search(SearchJobHistoryRequest {
+        crate_name: "serde".to_string(),
+        ..Default::default()
+    });
+    assert_eq!(serde.await, ["serde-2", "serde-1"]);
+    let failed = search(SearchJobHistoryRequest {
+        status: vec![JobStatus::Failed as i32],
+        worker: "builder".to_string(),
+        ..Default::default()
+    });
+    assert_eq!(failed.await, ["tokio-1"]);
+    let latest = search(SearchJobHistoryRequest {
+        within_secs: 60,
+        limit: 1,
+        ..Default::default()
+    });
+    assert_eq!(latest.await, ["serde-2"]);
+
+    let entries = client
+        .search_job_history(SearchJobHistoryRequest {
+            crate_name: "tokio".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .entries;
+    assert_eq!((entries[0].worker.as_str(), entries[0].error.as_str()), ("builder", "mismatched types"));
+}
+
+#[tokio::test]
+async fn test_boost_job() {
+    let scheduler_addr = "127.0.0.1:15044".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(worker_server::WorkerServer::new(AcceptingWorker))
+            .serve("127.0.0.1:16044".parse().unwrap())
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    for (job_id, priority, input) in [("batch", 10, "9a"), ("needed", 0, "9b"), ("needed-too", 0, "9b")] {
+        client
+            .submit_job(SubmitJobRequest {
+                job_id: job_id.to_string(),
+                input_hash: input.repeat(32),
+                job_type: "rust-compile".to_string(),
+                priority,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    // The build waits on the duplicate, so the job it's attached to is boosted
+    let boost = |job_id: &str| BoostJobRequest {
+        job_id: job_id.to_string(),
+    };
+    assert!(client.boost_job(boost("needed-too")).await.unwrap().into_inner().boosted);
+    let pending = client
+        .list_jobs(ListJobsRequest {
+            status: vec![JobStatus::Pending as i32],
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .jobs;
+    let needed = pending.iter().find(|job| job.job_id == "needed").unwrap();
+    assert!(needed.boosted);
+
+    // One slot: it goes ahead of the higher-priority batch job
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "only".to_string(),
+            address: "127.0.0.1:16044".to_string(),
+            capacity: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(1000)).await;
+    let status = |job_id: &str| GetJobStatusRequest {
+        job_id: job_id.to_string(),
+    };
+    let needed = client.get_job_status(status("needed")).await.unwrap().into_inner();
+    assert_eq!(needed.status, JobStatus::Running as i32);
+    let batch = client.get_job_status(status("batch")).await.unwrap().into_inner();
+    assert_eq!(batch.status, JobStatus::Pending as i32);
+
+    // Already left the queue
+    assert!(!client.boost_job(boost("needed")).await.unwrap().into_inner().boosted);
+}
+
+#[tokio::test]
+async fn test_gang_scheduling() {
+    let scheduler_addr = "127.0.0.1:15045".to_string();
+    let addr = scheduler_addr.clone();
+    tokio::spawn(async move {
+        cargo_distbuild::scheduler::run_scheduler(addr).await.unwrap();
+    });
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(worker_server::WorkerServer::new(AcceptingWorker))
+            .serve("127.0.0.1:16045".parse().unwrap())
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let mut client = SchedulerClient::connect(format!("http://{}", scheduler_addr))
+        .await
+        .unwrap();
+    client
+        .register_worker(RegisterWorkerRequest {
+            worker_id: "pair".to_string(),
+            address: "127.0.0.1:16045".to_string(),
+            capacity: 2,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let submit = |job_id: &str, input: &str, depends_on: &[&str]| SubmitJobRequest {
+        job_id: job_id.to_string(),
+        input_hash: input.repeat(32),
+        job_type: "rust-compile".to_string(),
+        depends_on: depends_on.iter().map(|dep| dep.to_string()).collect(),
+        ..Default::default()
+    };
+    client.submit_job(submit("solo", "a1", &[])).await.unwrap();
+
+    // Members can't wait on one another
+    let chained = client
+        .submit_jobs(SubmitJobsRequest {
+            jobs: vec![submit("lib", "a2", &[]), submit("bin", "a3", &["lib"])],
+            gang: true,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(chained.code(), tonic::Code::InvalidArgument);
+
+    // One slot left: neither member starts rather than one holding it
+    client
+        .submit_jobs(SubmitJobsRequest {
+            jobs: vec![submit("crate", "a4", &[]), submit("proc-macro", "a5", &[])],
+            gang: true,
+        })
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(500)).await;
+    let status = |job_id: &str| {
+        let mut client = client.clone();
+        let job_id = job_id.to_string();
+        async move { client.get_job_status(GetJobStatusRequest { job_id }).await.unwrap().into_inner().status }
+    };
+    assert_eq!(status("solo").await, JobStatus::Running as i32);
+    assert_eq!(status("crate").await, JobStatus::Pending as i32);
+    assert_eq!(status("proc-macro").await, JobStatus::Pending as i32);
+
+    // Both slots free: they go together
+    client
+        .clone()
+        .report_job_result(ReportJobResultRequest {
+            job_id: "solo".to_string(),
+            worker_id: "pair".to_string(),
+            success: true,
+            output_hash: "03".repeat(32),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(status("crate").await, JobStatus::Running as i32);
+    assert_eq!(status("proc-macro").await, JobStatus::Running as i32);
+}